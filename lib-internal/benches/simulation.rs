@@ -0,0 +1,101 @@
+#[macro_use]
+extern crate criterion;
+extern crate city_internal;
+
+use std::sync::mpsc;
+
+use criterion::Criterion;
+
+use city_internal::entities::player;
+use city_internal::space;
+use city_internal::sulphate;
+use city_internal::sulphate::server;
+use city_internal::sulphate::testing::TestServer;
+use city_internal::units;
+
+// how many events come due together each moment
+const EVENTS_PER_MOMENT: usize = 1000;
+
+// comes due once a moment forever, doing nothing else
+struct Again;
+
+impl sulphate::Event for Again {
+    fn invoke(
+        self: Self,
+        _space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        _matter: &mut sulphate::EntityHeap,
+    ) {
+        time.enqueue_relative(Again, units::moments(1));
+    }
+}
+
+fn event_throughput(c: &mut Criterion) {
+    let (mut server, ()) = TestServer::new(|_space, time, _matter| {
+        for _ in 0..EVENTS_PER_MOMENT {
+            time.enqueue_relative(Again, units::moments(1));
+        }
+    });
+    let id = format!("event queue, {} events per moment", EVENTS_PER_MOMENT);
+    c.bench_function(&id, |b| b.iter(|| {
+        server.advance_by(units::moments(1));
+    }));
+}
+
+fn interruption_latency(c: &mut Criterion) {
+    let (upd, _clock, handle, ()) =
+        server::start_server(|_space, _time, _matter| ());
+    // a snapshot of an empty world, so that this is mostly the channels
+    c.bench_function("interruption round trip", |b| b.iter(|| {
+        let (reply, recv) = mpsc::channel();
+        let request = server::Interruption::RequestSnapshot { reply };
+        upd.send(request).expect("server disconnected");
+        recv.recv().expect("server disconnected")
+    }));
+    upd.send(server::Interruption::KillServer).expect("server disconnected");
+    handle.join().expect("server panicked");
+}
+
+// far enough apart that nothing starts out touching anything else
+const SPACING: i32 = 40;
+
+fn position(x: i32, y: i32) -> units::Position {
+    let x = (x * SPACING).into();
+    let y = (y * SPACING).into();
+    units::Position::default() + units::Displacement { x, y }
+}
+
+// `count` players standing still in a square
+fn crowd(count: usize) -> (TestServer, mpsc::Receiver<player::Update>) {
+    TestServer::new(move |space, time, matter| {
+        let (update, updates) = mpsc::channel();
+        let side = (count as f64).sqrt().ceil() as i32;
+        for n in 0..count as i32 {
+            let at = position(n % side, n / side);
+            let appearance = player::Appearance::default();
+            let update = update.clone();
+            player::Player::new(space, time, matter, at, appearance, update);
+        }
+        updates
+    })
+}
+
+fn collision_queries(c: &mut Criterion) {
+    let counts = vec![1_000, 10_000, 100_000];
+    c.bench_function_over_inputs("collision query", |b, &count| {
+        let (server, _updates) = crowd(count);
+        let side = (count as f64).sqrt() as i32;
+        let center = position(side / 2, side / 2);
+        let circle = units::Circle { center, radius: 200.into() };
+        let now = server.now();
+        b.iter(|| server.space().query_circle(now, circle));
+    }, counts);
+}
+
+criterion_group!(
+    benches,
+    event_throughput,
+    interruption_latency,
+    collision_queries
+);
+criterion_main!(benches);
@@ -0,0 +1,494 @@
+use std::fmt;
+use std::io;
+use std::io::BufRead;
+use std::net;
+use std::path;
+use std::sync::mpsc;
+use std::thread;
+
+use blueprints;
+use config;
+use entities::item;
+use entities::player;
+use logging;
+use sulphate;
+use sulphate::metrics;
+use sulphate::server;
+use units;
+
+// commands are typed by people, so numbers are written normally here,
+// unlike the bits that `net` and `replay` use
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Command {
+    SpawnPlayer {
+        position: units::Position,
+    },
+    SpawnItem {
+        kind: item::Kind,
+        position: units::Position,
+    },
+    SpawnBlueprint {
+        blueprint: blueprints::Name,
+        position: units::Position,
+    },
+    Despawn {
+        id: sulphate::EntityId,
+    },
+    Pause,
+    Resume,
+    TimeScale {
+        factor: units::Scalar,
+    },
+    Save(path::PathBuf),
+    /// Reads gameplay settings again, from `config::config_path` unless
+    /// another path is given.
+    Reload(path::PathBuf),
+    /// Changes how much `module` and the modules inside it log, or every
+    /// module without a level of its own if no module is given.
+    Log {
+        module: Option<String>,
+        level: logging::Level,
+    },
+    List,
+    Inspect {
+        id: sulphate::EntityId,
+    },
+    Metrics,
+    /// Hashes everything about the world that a save would keep, for
+    /// telling whether two servers, or a server and a replay, agree.
+    Checksum,
+    Kill,
+}
+
+pub enum Response {
+    Done,
+    Spawned(sulphate::EntityId),
+    Entities(Vec<sulphate::EntitySummary>),
+    Entity(Option<sulphate::EntityInfo>),
+    Metrics(metrics::ServerMetrics),
+    Checksum(u64),
+}
+
+fn number(words: &[&str], n: usize) -> Result<f64, String> {
+    let word = words.get(n).ok_or("missing number")?;
+    word.parse().map_err(|_| format!("{} is not a number", word))
+}
+
+fn level(words: &[&str], n: usize) -> Result<logging::Level, String> {
+    let word = words.get(n).ok_or("missing level")?;
+    logging::parse_level(word).ok_or_else(|| format!("unknown level {}", word))
+}
+
+fn position(words: &[&str], n: usize) -> Result<units::Position, String> {
+    let x = number(words, n)?.into();
+    let y = number(words, n + 1)?.into();
+    let offset = units::Displacement { x, y };
+    Ok(units::Position::default() + offset)
+}
+
+fn entity_id(words: &[&str], n: usize) -> Result<sulphate::EntityId, String> {
+    let word = words.get(n).ok_or("missing entity id")?;
+    word.parse().map_err(|_| format!("{} is not an entity id", word))
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command = match words.get(0).cloned().unwrap_or("") {
+            "spawn" if words.get(1) == Some(&"player") => {
+                Command::SpawnPlayer { position: position(&words, 2)? }
+            },
+            "spawn" if words.get(1) == Some(&"item") => {
+                let name = words.get(2).ok_or("missing item")?;
+                let kind = item::Kind::from_name(name)
+                    .ok_or_else(|| format!("unknown item {}", name))?;
+                Command::SpawnItem { kind, position: position(&words, 3)? }
+            },
+            "spawn" if words.get(1) == Some(&"blueprint") => {
+                let name = words.get(2).ok_or("missing blueprint")?;
+                let blueprint = blueprints::Name::new(name)
+                    .ok_or_else(|| format!("{} isn't a blueprint", name))?;
+                let position = position(&words, 3)?;
+                Command::SpawnBlueprint { blueprint, position }
+            },
+            "spawn" => {
+                return Err(
+                    "can only spawn player, item or blueprint".to_string()
+                );
+            },
+            "despawn" => Command::Despawn { id: entity_id(&words, 1)? },
+            "pause" => Command::Pause,
+            "resume" => Command::Resume,
+            "timescale" => {
+                let factor = number(&words, 1)?.into();
+                Command::TimeScale { factor }
+            },
+            "save" => {
+                let path = words.get(1).ok_or("missing path")?;
+                Command::Save(path::PathBuf::from(*path))
+            },
+            "reload" => {
+                let path = words.get(1)
+                                .map(path::PathBuf::from)
+                                .unwrap_or_else(config::config_path);
+                Command::Reload(path)
+            },
+            "log" if words.len() > 2 => {
+                let module = Some(words[1].to_string());
+                Command::Log { module, level: level(&words, 2)? }
+            },
+            "log" => Command::Log { module: None, level: level(&words, 1)? },
+            "list" => Command::List,
+            "inspect" => Command::Inspect { id: entity_id(&words, 1)? },
+            "metrics" => Command::Metrics,
+            "checksum" => Command::Checksum,
+            "kill" => Command::Kill,
+            "" => return Err("no command".to_string()),
+            other => return Err(format!("unknown command {}", other)),
+        };
+        Ok(command)
+    }
+}
+
+impl fmt::Display for Response {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn entity(
+            f: &mut fmt::Formatter,
+            entity: &sulphate::EntitySummary,
+        ) -> fmt::Result {
+            let kind = match entity.kind {
+                Some(kind) => format!("{:?}", kind),
+                None => "unknown".to_string(),
+            };
+            write!(
+                f, "{} {} at ({}, {}) moving ({}, {})",
+                entity.id, kind,
+                entity.position.x, entity.position.y,
+                entity.velocity.x, entity.velocity.y,
+            )
+        }
+
+        fn details(
+            f: &mut fmt::Formatter,
+            details: &sulphate::Details,
+        ) -> fmt::Result {
+            use sulphate::Details::*;
+            match *details {
+                Player { ref appearance, ref carrying } => {
+                    write!(f, "\n  named {}", appearance.name)?;
+                    if !carrying.is_empty() {
+                        let names: Vec<_> =
+                            carrying.iter().map(|kind| kind.name()).collect();
+                        write!(f, "\n  carrying {}", names.join(", "))?;
+                    }
+                    Ok(())
+                },
+                Citizen { plans } => write!(
+                    f, "\n  home ({}, {}) work ({}, {})",
+                    plans.home.x, plans.home.y,
+                    plans.work.x, plans.work.y,
+                ),
+                Building { blueprint, blackout, burning, flow } => {
+                    write!(f, "\n  {}", blueprint.name())?;
+                    if blackout {
+                        write!(f, ", blacked out")?;
+                    }
+                    if burning {
+                        write!(f, ", on fire")?;
+                    }
+                    if let Some(flow) = flow {
+                        write!(f, "\n  makes {:?} uses {:?}",
+                               flow.makes, flow.uses)?;
+                    }
+                    Ok(())
+                },
+                Vehicle { ref stops } => {
+                    write!(f, "\n  stops")?;
+                    for &(x, y) in stops {
+                        write!(f, " ({}, {})", x, y)?;
+                    }
+                    Ok(())
+                },
+                Item { kind } => write!(f, "\n  {}", kind.name()),
+                Nothing => Ok(()),
+            }
+        }
+
+        match *self {
+            Response::Done => write!(f, "ok"),
+            Response::Spawned(id) => write!(f, "spawned {}", id),
+            Response::Entities(ref entities) => {
+                write!(f, "{} entities", entities.len())?;
+                for each in entities {
+                    write!(f, "\n  ")?;
+                    entity(f, each)?;
+                }
+                Ok(())
+            },
+            Response::Entity(Some(ref info)) => {
+                entity(f, &info.summary)?;
+                details(f, &info.details)
+            },
+            Response::Entity(None) => write!(f, "no such entity"),
+            Response::Metrics(ref metrics) => write!(
+                f,
+                "{} cycles, {} events, {} interruptions, {} dropped, \
+                 {} behind",
+                metrics.cycles, metrics.events, metrics.interruptions,
+                metrics.dropped.total(), metrics.drift,
+            ),
+            Response::Checksum(checksum) => {
+                write!(f, "checksum {:016x}", checksum)
+            },
+        }
+    }
+}
+
+/// Turns commands into interruptions, and waits for the server to answer
+/// the ones that have an answer.
+pub struct Console {
+    upd: mpsc::Sender<server::Interruption>,
+    // players spawned from the console have nobody watching them
+    devices: Vec<mpsc::Receiver<player::Update>>,
+}
+
+fn ask<T>(
+    upd: &mpsc::Sender<server::Interruption>,
+    request: server::Interruption,
+    answer: mpsc::Receiver<T>,
+) -> Result<T, String> {
+    let gone = || "server disconnected".to_string();
+    upd.send(request).map_err(|_| gone())?;
+    answer.recv().map_err(|_| gone())
+}
+
+impl Console {
+    pub fn new(upd: mpsc::Sender<server::Interruption>) -> Console {
+        let devices = Vec::new();
+        Console { upd, devices }
+    }
+
+    fn tell(
+        self: &Self,
+        interruption: server::Interruption,
+    ) -> Result<Response, String> {
+        self.upd.send(interruption)
+                .map(|_| Response::Done)
+                .map_err(|_| "server disconnected".to_string())
+    }
+
+    // whatever is spawned is given a device in case it is a player, which
+    // is only kept if it is used
+    fn spawn(
+        self: &mut Self,
+        blueprint: blueprints::Name,
+        position: units::Position,
+    ) -> Result<Response, String> {
+        use sulphate::server::Interruption as I;
+        let (reply, answer) = mpsc::channel();
+        let (update, device) = mpsc::channel();
+        let update = Some(update);
+        let request = I::Spawn { blueprint, position, update, reply };
+        let id = ask(&self.upd, request, answer)??;
+        if device.try_recv().is_ok() {
+            self.devices.push(device);
+        }
+        Ok(Response::Spawned(id))
+    }
+
+    pub fn execute(
+        self: &mut Self,
+        command: Command,
+    ) -> Result<Response, String> {
+        for device in &self.devices {
+            for _ in device.try_iter() {}
+        }
+
+        use sulphate::server::Interruption as I;
+        match command {
+            Command::SpawnPlayer { position } => {
+                self.spawn(blueprints::player(), position)
+            },
+            Command::SpawnItem { kind, position } => {
+                self.spawn(kind.into(), position)
+            },
+            Command::SpawnBlueprint { blueprint, position } => {
+                self.spawn(blueprint, position)
+            },
+            Command::Despawn { id } => self.tell(I::Despawn { id }),
+            Command::Pause => self.tell(I::PauseWorld { paused: true }),
+            Command::Resume => self.tell(I::PauseWorld { paused: false }),
+            Command::TimeScale { factor } => {
+                self.tell(I::SetTimeScale { factor })
+            },
+            Command::Save(path) => self.tell(I::SaveTo(path)),
+            Command::Reload(path) => self.tell(I::ReloadConfig(path)),
+            Command::Log { module, level } => {
+                // the filters belong to the whole process, not the server
+                logging::set_level(module.as_ref().map(|m| &m[..]), level);
+                Ok(Response::Done)
+            },
+            Command::List => {
+                let (reply, answer) = mpsc::channel();
+                let request = I::ListEntities { reply };
+                Ok(Response::Entities(ask(&self.upd, request, answer)?))
+            },
+            Command::Inspect { id } => {
+                let (reply, answer) = mpsc::channel();
+                let request = I::Inspect { id, reply };
+                Ok(Response::Entity(ask(&self.upd, request, answer)?))
+            },
+            Command::Metrics => {
+                let (reply, answer) = mpsc::channel();
+                let request = I::QueryMetrics { reply };
+                Ok(Response::Metrics(ask(&self.upd, request, answer)?))
+            },
+            Command::Checksum => {
+                let (reply, answer) = mpsc::channel();
+                let request = I::QueryChecksum { reply };
+                Ok(Response::Checksum(ask(&self.upd, request, answer)?))
+            },
+            Command::Kill => self.tell(I::KillServer),
+        }
+    }
+
+    /// Reads commands a line at a time, writing a response after each one,
+    /// until the input ends or the server is killed.
+    pub fn run(
+        self: &mut Self,
+        input: &mut io::BufRead,
+        output: &mut io::Write,
+    ) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            let command = Command::parse(&line);
+            let kill = command == Ok(Command::Kill);
+            match command.and_then(|command| self.execute(command)) {
+                Ok(response) => writeln!(output, "{}", response)?,
+                Err(e) => writeln!(output, "error: {}", e)?,
+            }
+            output.flush()?;
+            if kill {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs a console on this process's standard input and output.
+pub fn serve_stdin(
+    upd: mpsc::Sender<server::Interruption>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let stdout = io::stdout();
+        let result =
+            Console::new(upd).run(&mut stdin.lock(), &mut stdout.lock());
+        if let Err(e) = result {
+            warn!("Admin console closed: {}", e);
+        }
+    })
+}
+
+/// Accepts admin connections on `addr`, each with its own console.
+pub fn listen<A: net::ToSocketAddrs>(
+    addr: A,
+    upd: mpsc::Sender<server::Interruption>,
+) -> io::Result<thread::JoinHandle<()>> {
+    let listener = net::TcpListener::bind(addr)?;
+    let handle = thread::spawn(move || {
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    warn!("Failed to accept admin connection: {}", e);
+                    continue;
+                },
+            };
+            let upd = upd.clone();
+            thread::spawn(move || {
+                let result = stream.try_clone().and_then(|writer| {
+                    let mut reader = io::BufReader::new(stream);
+                    let mut writer = io::BufWriter::new(writer);
+                    Console::new(upd).run(&mut reader, &mut writer)
+                });
+                if let Err(e) = result {
+                    info!("Admin connection closed: {}", e);
+                }
+            });
+        }
+    });
+    Ok(handle)
+}
+
+#[cfg(test)]
+mod test_parse {
+    use units;
+
+    use super::*;
+
+    #[test]
+    fn spawn_player() {
+        let command = Command::parse("spawn player 10 -20").unwrap();
+        let x = 10.into();
+        let y = (-20).into();
+        let position =
+            units::Position::default() + units::Displacement { x, y };
+        assert_eq!(command, Command::SpawnPlayer { position });
+    }
+
+    #[test]
+    fn spawn_item() {
+        let command = Command::parse("spawn item brick 4 8").unwrap();
+        let x = 4.into();
+        let y = 8.into();
+        let position =
+            units::Position::default() + units::Displacement { x, y };
+        let kind = item::Kind::Brick;
+        assert_eq!(command, Command::SpawnItem { kind, position });
+        assert!(Command::parse("spawn item anvil 4 8").is_err());
+    }
+
+    #[test]
+    fn spawn_blueprint() {
+        let command = Command::parse("spawn blueprint miner 4 8").unwrap();
+        let x = 4.into();
+        let y = 8.into();
+        let position =
+            units::Position::default() + units::Displacement { x, y };
+        let blueprint = blueprints::Name::new("miner").unwrap();
+        assert_eq!(command, Command::SpawnBlueprint { blueprint, position });
+        assert!(Command::parse("spawn blueprint 9 4 8").is_err());
+    }
+
+    #[test]
+    fn bad_commands() {
+        assert!(Command::parse("").is_err());
+        assert!(Command::parse("spawn tree 1 2").is_err());
+        assert!(Command::parse("timescale fast").is_err());
+        assert!(Command::parse("despawn").is_err());
+    }
+
+    #[test]
+    fn reload_defaults_to_the_usual_file() {
+        let command = Command::parse("reload").unwrap();
+        assert_eq!(command, Command::Reload(config::config_path()));
+        let command = Command::parse("reload balance.txt").unwrap();
+        assert_eq!(command, Command::Reload("balance.txt".into()));
+    }
+
+    #[test]
+    fn log_levels_for_everything_or_one_module() {
+        let command = Command::parse("log debug").unwrap();
+        let level = logging::Level::Debug;
+        assert_eq!(command, Command::Log { module: None, level });
+        let command = Command::parse("log city_internal::net trace").unwrap();
+        let module = Some("city_internal::net".to_string());
+        let level = logging::Level::Trace;
+        assert_eq!(command, Command::Log { module, level });
+        assert!(Command::parse("log loudly").is_err());
+        assert!(Command::parse("log").is_err());
+    }
+}
@@ -0,0 +1,789 @@
+//! Blueprints, which say what goes into making each thing that can be put
+//! in the world, by name, so that spawning, building, maps and mods all
+//! make things the same way and new ones can be written down as data.
+//!
+//! The blueprints built in are read from `assets/blueprints.txt`, which
+//! has one for each kind of building, named as the config names them, one
+//! for each kind of item, and one named `player`. A `blueprints.txt` in the
+//! mods directory adds more, or goes over those there are, written the same
+//! way, one blueprint to each block of lines:
+//!
+//!     # anything after a hash is ignored
+//!     blueprint cottage
+//!     building house
+//!     flow 0 0 0 1 1 0
+//!     cost 40
+//!
+//!     blueprint miner
+//!     player
+//!     color 200 150 0
+//!     carrying brick brick
+//!
+//!     blueprint gold item coin
+//!
+//! Each block starts with the blueprint's name, which starts with a letter
+//! and has no spaces, and goes on with the components it is made of, one to
+//! a line. The first says what it makes: a building of the given kind, an
+//! item of the given kind, or a player, and can go on the same line as the
+//! name. The rest set how what it makes starts out, and anything left out
+//! is as it usually is. Buildings can have a flow, written like the
+//! config's without the building, and a cost, while players can have a
+//! color and up to eight items to carry. A block naming a blueprint there
+//! already is takes its place altogether.
+//!
+//! What a building does is still down to its kind, so a blueprint can make
+//! a house that costs more or uses less, but not a new sort of building.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path;
+use std::str;
+use std::sync::mpsc;
+
+use config;
+use economy;
+use economy::Resources;
+use entities::building;
+use entities::item;
+use entities::player;
+use space;
+use sulphate;
+use units;
+
+/// The most bytes that a blueprint's name can have.
+pub const MAX_NAME_LENGTH: usize = 24;
+
+/// What a blueprint is called, kept inline so that it can be sent around
+/// and copied as freely as the kinds of building were.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Name {
+    length: u8,
+    bytes: [u8; MAX_NAME_LENGTH],
+}
+
+impl Name {
+    /// `name`, unless it is too long, has spaces in it, or doesn't start
+    /// with a letter.
+    pub fn new(name: &str) -> Option<Name> {
+        let starts = name.chars().next().map_or(false, char::is_alphabetic);
+        let spaced = name.chars().any(char::is_whitespace);
+        if !starts || spaced || name.len() > MAX_NAME_LENGTH {
+            return None;
+        }
+        let mut bytes = [0u8; MAX_NAME_LENGTH];
+        bytes[..name.len()].copy_from_slice(name.as_bytes());
+        Some(Name { length: name.len() as u8, bytes })
+    }
+
+    pub fn as_str(self: &Self) -> &str {
+        str::from_utf8(&self.bytes[..self.length as usize])
+            .expect("Blueprint name isn't UTF-8")
+    }
+}
+
+impl fmt::Display for Name {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl fmt::Debug for Name {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+/// The blueprint built in for each kind of building.
+impl From<building::Blueprint> for Name {
+    fn from(blueprint: building::Blueprint) -> Name {
+        Name::new(&blueprint.name().replace(' ', "_"))
+            .expect("Kind of building has no name for its blueprint")
+    }
+}
+
+/// The blueprint built in for each kind of item.
+impl From<item::Kind> for Name {
+    fn from(kind: item::Kind) -> Name {
+        Name::new(kind.name()).expect("Kind of item has no blueprint name")
+    }
+}
+
+/// The blueprint that players are made from, unless they are spawned from
+/// another.
+pub fn player() -> Name {
+    Name::new("player").expect("Player blueprint has no name")
+}
+
+/// The blueprint written as `word`, which is either its name, or the tag
+/// of a kind of building, as replays and messages wrote them before there
+/// were blueprints.
+pub fn read_name(word: &str) -> Option<Name> {
+    match word.parse::<u8>() {
+        Ok(tag) => building::Blueprint::from_tag(tag).map(Name::from),
+        Err(_) => Name::new(word),
+    }
+}
+
+/// What a blueprint makes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Body {
+    Building(building::Blueprint),
+    Item(item::Kind),
+    Player,
+}
+
+/// One of the parts a blueprint is made of, each written on a line of its
+/// own.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Component {
+    /// What the blueprint makes, which it has exactly one of.
+    Makes(Body),
+    /// What a building makes and uses up, in place of its kind's flow.
+    Flow(economy::Flow),
+    /// What a player pays to put a building up, in place of its kind's.
+    Cost(i64),
+    /// How a player looks, in place of the usual color.
+    Color(player::Color),
+    /// What a player starts out carrying.
+    Carrying(Vec<item::Kind>),
+}
+
+impl Component {
+    /// The word that starts the component's line.
+    pub fn word(self: &Self) -> &'static str {
+        match *self {
+            Component::Makes(Body::Building(_)) => "building",
+            Component::Makes(Body::Item(_)) => "item",
+            Component::Makes(Body::Player) => "player",
+            Component::Flow(_) => "flow",
+            Component::Cost(_) => "cost",
+            Component::Color(_) => "color",
+            Component::Carrying(_) => "carrying",
+        }
+    }
+}
+
+/// A named blueprint, and the components it is made of. Anything that a
+/// component isn't given for starts out as it usually does.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Prefab {
+    pub name: Name,
+    // what it makes always comes first
+    components: Vec<Component>,
+}
+
+impl Prefab {
+    /// A blueprint that makes `body` as it usually is.
+    pub fn new(name: Name, body: Body) -> Prefab {
+        Prefab { name, components: vec![Component::Makes(body)] }
+    }
+
+    /// Adds `component` to the blueprint, unless it has one of that sort
+    /// already, or it doesn't go with what the blueprint makes.
+    pub fn add(self: &mut Self, component: Component) -> Result<(), String> {
+        let word = component.word();
+        let only = match component {
+            Component::Makes(_) => {
+                let made = self.components[0].word();
+                return Err(format!("{} when it makes a {} already",
+                                   word, made));
+            },
+            Component::Flow(_) | Component::Cost(_) => "buildings",
+            Component::Color(_) | Component::Carrying(_) => "players",
+        };
+        let fits = match (self.body(), only) {
+            (Body::Building(_), "buildings") => true,
+            (Body::Player, "players") => true,
+            _ => false,
+        };
+        if !fits {
+            return Err(format!("only {} have a {}", only, word));
+        }
+        if self.components.iter().any(|each| each.word() == word) {
+            return Err(format!("more than one {}", word));
+        }
+        self.components.push(component);
+        Ok(())
+    }
+
+    pub fn components(self: &Self) -> &[Component] {
+        &self.components
+    }
+
+    /// What the blueprint makes.
+    pub fn body(self: &Self) -> Body {
+        match self.components[0] {
+            Component::Makes(body) => body,
+            _ => panic!("Blueprint doesn't start with what it makes"),
+        }
+    }
+
+    /// The flow a building starts on, if it isn't its kind's.
+    pub fn flow(self: &Self) -> Option<economy::Flow> {
+        self.components.iter().filter_map(|each| match *each {
+            Component::Flow(flow) => Some(flow),
+            _ => None,
+        }).next()
+    }
+
+    /// What a building costs, if it isn't what its kind costs.
+    pub fn cost(self: &Self) -> Option<i64> {
+        self.components.iter().filter_map(|each| match *each {
+            Component::Cost(cost) => Some(cost),
+            _ => None,
+        }).next()
+    }
+
+    /// How a player looks, if it isn't the usual.
+    pub fn color(self: &Self) -> Option<player::Color> {
+        self.components.iter().filter_map(|each| match *each {
+            Component::Color(color) => Some(color),
+            _ => None,
+        }).next()
+    }
+
+    /// What a player starts out carrying.
+    pub fn carrying(self: &Self) -> &[item::Kind] {
+        self.components.iter().filter_map(|each| match *each {
+            Component::Carrying(ref items) => Some(&items[..]),
+            _ => None,
+        }).next().unwrap_or(&[])
+    }
+}
+
+/// Why a blueprints file couldn't be read, and which line of it was to
+/// blame.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BlueprintError {
+    /// Counted from 1, or None if it wasn't down to any one line.
+    pub line: Option<usize>,
+    pub reason: String,
+}
+
+impl fmt::Display for BlueprintError {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.reason),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+impl From<io::Error> for BlueprintError {
+    fn from(err: io::Error) -> BlueprintError {
+        BlueprintError { line: None, reason: err.to_string() }
+    }
+}
+
+/// Every blueprint there is, in the order they were first given.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Registry {
+    prefabs: Vec<Prefab>,
+}
+
+// the blueprints built in, as shipped in the assets
+const BUILT_IN: &str = include_str!("../../assets/blueprints.txt");
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry { prefabs: Vec::new() }
+            .with(BUILT_IN)
+            .expect("Built in blueprints couldn't be read")
+    }
+}
+
+fn whole(words: &[&str], n: usize) -> Result<i64, String> {
+    let word = words.get(n).ok_or("missing number")?;
+    word.parse().map_err(|_| format!("{} is not a whole number", word))
+}
+
+fn resources(words: &[&str], n: usize) -> Result<Resources, String> {
+    let power = whole(words, n)?;
+    let water = whole(words, n + 1)?;
+    let money = whole(words, n + 2)?;
+    Ok(Resources::new(power, water, money))
+}
+
+fn byte(words: &[&str], n: usize) -> Result<u8, String> {
+    let word = words.get(n).ok_or("missing number")?;
+    word.parse().map_err(|_| format!("{} is not from 0 to 255", word))
+}
+
+fn item_kind(word: Option<&&str>) -> Result<item::Kind, String> {
+    let word = word.ok_or("missing item")?;
+    item::Kind::from_name(word).ok_or_else(|| format!("unknown item {}", word))
+}
+
+fn makes_nothing(name: Name, line: usize) -> BlueprintError {
+    let reason = format!("{} doesn't say what it makes", name);
+    BlueprintError { line: Some(line), reason }
+}
+
+// the component written on a line as `words`
+fn component(words: &[&str]) -> Result<Component, String> {
+    let (component, expected) = match words[0] {
+        "building" => {
+            let word = words.get(1).ok_or("missing building")?;
+            let kind = building::Blueprint::all()
+                .iter()
+                .cloned()
+                .find(|kind| Name::from(*kind).as_str() == *word)
+                .ok_or_else(|| format!("unknown building {}", word))?;
+            (Component::Makes(Body::Building(kind)), 2)
+        },
+        "item" => (Component::Makes(Body::Item(item_kind(words.get(1))?)), 2),
+        "player" => (Component::Makes(Body::Player), 1),
+        "flow" => {
+            let makes = resources(words, 1)?;
+            let uses = resources(words, 4)?;
+            (Component::Flow(economy::Flow { makes, uses }), 7)
+        },
+        "cost" => {
+            let cost = whole(words, 1)?;
+            if cost < 0 {
+                return Err(format!("{} can't be less than 0", words[1]));
+            }
+            (Component::Cost(cost), 2)
+        },
+        "color" => {
+            let color = [byte(words, 1)?, byte(words, 2)?, byte(words, 3)?];
+            (Component::Color(color), 4)
+        },
+        "carrying" => {
+            if words.len() > 1 + item::CAPACITY {
+                return Err(format!("can't carry more than {} items",
+                                   item::CAPACITY));
+            }
+            let items = words[1..]
+                .iter()
+                .map(|word| item_kind(Some(word)))
+                .collect::<Result<_, _>>()?;
+            (Component::Carrying(items), words.len())
+        },
+        other => return Err(format!("unknown component {}", other)),
+    };
+    if words.len() > expected {
+        return Err(format!("unexpected {}", words[expected]));
+    }
+    Ok(component)
+}
+
+impl Registry {
+    /// The blueprints built in, with whatever `text` adds to them.
+    pub fn parse(text: &str) -> Result<Registry, BlueprintError> {
+        Registry::default().with(text)
+    }
+
+    /// These blueprints, with whatever `text` adds to them or puts in
+    /// their place, for going over one file with another.
+    pub fn with(self: Self, text: &str) -> Result<Registry, BlueprintError> {
+        let mut registry = self;
+        let mut making: Option<Prefab> = None;
+        // a blueprint that has been named but not yet said what it makes,
+        // and the line it was named on
+        let mut named: Option<(Name, usize)> = None;
+        for (i, line) in text.lines().enumerate() {
+            let number = Some(i + 1);
+            let error = |reason| BlueprintError { line: number, reason };
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let mut given = &words[..];
+            if words.get(0) == Some(&"blueprint") {
+                if let Some((name, line)) = named.take() {
+                    return Err(makes_nothing(name, line));
+                }
+                if let Some(prefab) = making.take() {
+                    registry.insert(prefab);
+                }
+                let word = words.get(1)
+                                .ok_or_else(|| error("missing name".into()))?;
+                let name = Name::new(word).ok_or_else(|| {
+                    error(format!("{} can't be a blueprint's name", word))
+                })?;
+                named = Some((name, i + 1));
+                // what it makes can go on the same line as its name
+                given = &words[2..];
+            }
+            if given.is_empty() {
+                continue;
+            }
+            let component = component(given).map_err(&error)?;
+            if let Some(ref mut prefab) = making {
+                prefab.add(component).map_err(&error)?;
+                continue;
+            }
+            let name = match named.take() {
+                Some((name, _)) => name,
+                None => {
+                    let reason = given[0].to_string();
+                    return Err(error(reason + " before any blueprint"));
+                },
+            };
+            match component {
+                Component::Makes(body) => {
+                    making = Some(Prefab::new(name, body));
+                },
+                other => {
+                    let reason = other.word().to_string();
+                    return Err(error(reason + " before what it makes"));
+                },
+            }
+        }
+        if let Some((name, line)) = named {
+            return Err(makes_nothing(name, line));
+        }
+        if let Some(prefab) = making {
+            registry.insert(prefab);
+        }
+        Ok(registry)
+    }
+
+    // puts `prefab` in place of the blueprint with its name, or after the
+    // rest if there isn't one
+    fn insert(self: &mut Self, prefab: Prefab) {
+        let found = self.prefabs
+                        .iter()
+                        .position(|each| each.name == prefab.name);
+        match found {
+            Some(n) => self.prefabs[n] = prefab,
+            None => self.prefabs.push(prefab),
+        }
+    }
+
+    pub fn get(self: &Self, name: Name) -> Option<&Prefab> {
+        self.prefabs.iter().find(|prefab| prefab.name == name)
+    }
+
+    /// Every blueprint that makes a building, along with the kind it
+    /// makes, in the order players go through them.
+    pub fn buildings(self: &Self) -> Vec<(Name, building::Blueprint)> {
+        self.prefabs
+            .iter()
+            .filter_map(|prefab| match prefab.body() {
+                Body::Building(kind) => Some((prefab.name, kind)),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// The name of every blueprint that `text` writes, in the order it writes
+/// them.
+pub fn defined(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.get(0) {
+                Some(&"blueprint") => words.get(1).map(|&name| name.into()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Reads the blueprints file at `path`, going over the built in blueprints.
+pub fn load(path: &path::Path) -> Result<Registry, BlueprintError> {
+    let mut text = String::new();
+    fs::File::open(path)?.read_to_string(&mut text)?;
+    Registry::parse(&text)
+}
+
+const REGISTRY: sulphate::EntityId = 0;
+
+/// The blueprints the world is making things from, which are the built in
+/// ones until something else is set.
+pub fn get(matter: &sulphate::EntityHeap) -> Registry {
+    matter.get::<Registry>(REGISTRY).cloned().unwrap_or_default()
+}
+
+/// The blueprint named `name`, as the world has it.
+pub fn find(matter: &sulphate::EntityHeap, name: Name) -> Option<Prefab> {
+    match matter.get::<Registry>(REGISTRY) {
+        Some(registry) => registry.get(name).cloned(),
+        None => Registry::default().get(name).cloned(),
+    }
+}
+
+/// Has the world make things from `registry` from now on. Buildings that
+/// are already standing take up the flows of their blueprints straight
+/// away, while costs change for whatever is built next.
+pub fn set(matter: &mut sulphate::EntityHeap, registry: Registry) {
+    // overwritten in place, for the same reason as the config
+    if matter.get::<Registry>(REGISTRY).is_some() {
+        *matter.get_mut::<Registry>(REGISTRY)
+               .expect("Blueprints went missing") = registry;
+    } else {
+        matter.insert(REGISTRY, registry);
+    }
+    let config = config::get(matter);
+    config::set(matter, config);
+}
+
+// which blueprint a building was made from, kept as a component of it
+struct Made {
+    name: Name,
+}
+
+/// The blueprint that building `id` was made from, which is the one built
+/// in for its kind if it was made before there were blueprints.
+pub fn made_as(
+    matter: &sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) -> Option<Name> {
+    let kind = matter.get::<building::Building>(id)?.blueprint();
+    let made = matter.component::<Made>(id).map(|made| made.name);
+    Some(made.unwrap_or_else(|| kind.into()))
+}
+
+/// What building `id` makes and uses up under `config`, going by the
+/// blueprint it was made from.
+pub fn flow(
+    matter: &sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    config: &config::Config,
+) -> Option<economy::Flow> {
+    let kind = matter.get::<building::Building>(id)?.blueprint();
+    let made = matter.component::<Made>(id)
+                     .and_then(|made| find(matter, made.name))
+                     .and_then(|prefab| prefab.flow());
+    Some(made.unwrap_or_else(|| config.flow(kind)))
+}
+
+/// The kind of building that the blueprint named `name` puts up, or None
+/// if it isn't a building's.
+pub fn kind(
+    matter: &sulphate::EntityHeap,
+    name: Name,
+) -> Option<building::Blueprint> {
+    match find(matter, name)?.body() {
+        Body::Building(kind) => Some(kind),
+        _ => None,
+    }
+}
+
+/// What a player pays to put up the blueprint named `name`, or None if it
+/// isn't a building's.
+pub fn cost(matter: &sulphate::EntityHeap, name: Name) -> Option<i64> {
+    let prefab = find(matter, name)?;
+    match prefab.body() {
+        Body::Building(kind) => {
+            let cost = prefab.cost();
+            Some(cost.unwrap_or_else(|| config::get(matter).cost(kind)))
+        },
+        _ => None,
+    }
+}
+
+// marks building `id` as made from `name`, and starts it on the flow of
+// that blueprint
+fn mark(
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    name: Name,
+) {
+    matter.insert_component(id, Made { name });
+    let flow = flow(matter, id, &config::get(matter));
+    if let (Some(flow), Some(current)) =
+        (flow, matter.component_mut::<economy::Flow>(id))
+    {
+        *current = flow;
+    }
+}
+
+/// Builds from the blueprint named `name` on the tile under `position`,
+/// the same way as `building::place`, giving None if it isn't a building's
+/// blueprint or there wasn't room.
+pub fn place(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    name: Name,
+    position: units::Position,
+) -> Option<sulphate::EntityId> {
+    let kind = kind(matter, name)?;
+    let id = building::place(space, time, matter, kind, position)?;
+    mark(matter, id, name);
+    // the grid was shared out going by the kind's flow
+    economy::resupply(space, time, matter);
+    Some(id)
+}
+
+/// Marks building `id`, just put back from a save, as made from `name`,
+/// which it stays marked as even if there is no blueprint by that name, so
+/// that it goes back to it once there is.
+pub fn restore(
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    name: Name,
+) {
+    mark(matter, id, name);
+}
+
+/// Makes whatever the blueprint named `name` makes at `position`, giving
+/// the new entity, or why it couldn't be made. Players are sent their
+/// updates through `update`, which they can't be made without.
+pub fn spawn(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    name: Name,
+    position: units::Position,
+    update: Option<mpsc::Sender<player::Update>>,
+) -> Result<sulphate::EntityId, String> {
+    let prefab = find(matter, name)
+        .ok_or_else(|| format!("no blueprint named {}", name))?;
+    match prefab.body() {
+        Body::Building(_) => {
+            place(space, time, matter, name, position)
+                .ok_or_else(|| format!("no room for a {}", name))
+        },
+        Body::Item(kind) => {
+            Ok(item::Item::new(space, time, matter, kind, position).id())
+        },
+        Body::Player => {
+            let update = update.ok_or("players need someone to update")?;
+            let mut appearance = player::Appearance::default();
+            if let Some(color) = prefab.color() {
+                appearance.color = color;
+            }
+            let id = player::Player::new(
+                space, time, matter, position, appearance, update,
+            ).id();
+            if let Some(inventory) =
+                matter.component_mut::<item::Inventory>(id)
+            {
+                *inventory = item::Inventory::new(prefab.carrying().to_vec());
+            }
+            Ok(id)
+        },
+    }
+}
+
+#[cfg(test)]
+mod test_blueprints {
+    use super::*;
+
+    #[test]
+    fn names_are_one_word() {
+        assert_eq!(Name::new("cottage").unwrap().as_str(), "cottage");
+        assert!(Name::new("").is_none());
+        assert!(Name::new("big house").is_none());
+        assert!(Name::new("2nd_house").is_none());
+        assert!(Name::new(&"a".repeat(MAX_NAME_LENGTH + 1)).is_none());
+        let plant: Name = building::Blueprint::PowerPlant.into();
+        assert_eq!(plant.as_str(), "power_plant");
+        // old replays wrote tags
+        let tag = building::Blueprint::PowerPlant.tag().to_string();
+        assert_eq!(read_name(&tag), Some(plant));
+        assert_eq!(read_name("power_plant"), Some(plant));
+    }
+
+    #[test]
+    fn files_say_what_is_wrong_with_them() {
+        let text = "blueprint cottage # cosy\n\
+                    building house\n\
+                    flow 0 0 0 1 1 0\n\
+                    cost 40\n\
+                    \n\
+                    blueprint miner player\n\
+                    color 200 150 0\n\
+                    carrying brick brick\n";
+        let registry = Registry::parse(text).unwrap();
+        let cottage = registry.get(Name::new("cottage").unwrap()).unwrap();
+        let house = building::Blueprint::House;
+        assert_eq!(cottage.body(), Body::Building(house));
+        assert_eq!(cottage.cost(), Some(40));
+        assert_eq!(cottage.components().len(), 3);
+        let miner = registry.get(Name::new("miner").unwrap()).unwrap();
+        assert_eq!(miner.color(), Some([200, 150, 0]));
+        assert_eq!(miner.carrying(), &[item::Kind::Brick; 2]);
+        // new buildings come after the built in ones
+        let buildings = registry.buildings();
+        assert_eq!(buildings.len(), building::Blueprint::all().len() + 1);
+        assert_eq!(buildings.last().unwrap().1, building::Blueprint::House);
+
+        let error = |text: &str| {
+            Registry::parse(text).unwrap_err().to_string()
+        };
+        assert_eq!(error("cost 40"), "line 1: cost before any blueprint");
+        assert_eq!(error("blueprint keep building castle"),
+                   "line 1: unknown building castle");
+        assert_eq!(error("blueprint gold item coin\ncost 5"),
+                   "line 2: only buildings have a cost");
+        assert_eq!(error("blueprint x player\ncolor 300 0 0"),
+                   "line 2: 300 is not from 0 to 255");
+        assert_eq!(error("blueprint x\ncost 5\nbuilding house"),
+                   "line 2: cost before what it makes");
+        assert_eq!(error("blueprint x player\nitem apple"),
+                   "line 2: item when it makes a player already");
+        assert_eq!(error("blueprint x player\ncolor 1 2 3\ncolor 1 2 3"),
+                   "line 3: more than one color");
+        assert_eq!(error("blueprint x\n\nblueprint y player"),
+                   "line 1: x doesn't say what it makes");
+    }
+
+    #[test]
+    fn everything_has_a_blueprint_built_in() {
+        let registry = Registry::default();
+        for &kind in building::Blueprint::all().iter() {
+            let prefab = registry.get(kind.into()).unwrap();
+            assert_eq!(prefab.body(), Body::Building(kind));
+        }
+        for &kind in item::Kind::all().iter() {
+            let prefab = registry.get(kind.into()).unwrap();
+            assert_eq!(prefab.body(), Body::Item(kind));
+        }
+        let prefab = registry.get(player()).unwrap();
+        assert_eq!(prefab.body(), Body::Player);
+        assert_eq!(defined(BUILT_IN).len(), registry.prefabs.len());
+    }
+
+    #[test]
+    fn blueprints_go_into_what_they_make() {
+        let mut space = space::CollisionSpace::new();
+        let mut time = sulphate::EventQueue::new(Default::default());
+        let mut matter = sulphate::EntityHeap::new();
+        let text = "blueprint cottage\n\
+                    building house\n\
+                    flow 0 0 0 1 1 0\n\
+                    blueprint miner player\n\
+                    carrying brick\n";
+        set(&mut matter, Registry::parse(text).unwrap());
+        let cottage = Name::new("cottage").unwrap();
+        let flow = economy::Flow {
+            makes: Resources::new(0, 0, 0),
+            uses: Resources::new(1, 1, 0),
+        };
+
+        let position = units::Position::default();
+        let id = spawn(
+            &mut space, &mut time, &mut matter, cottage, position, None,
+        ).unwrap();
+        assert_eq!(made_as(&matter, id), Some(cottage));
+        assert_eq!(matter.component::<economy::Flow>(id), Some(&flow));
+        let house = config::get(&matter).cost(building::Blueprint::House);
+        assert_eq!(cost(&matter, cottage), Some(house));
+
+        let miner = Name::new("miner").unwrap();
+        let offset = units::Displacement {
+            x: (space::TILE_SIZE * 4.0).into(),
+            y: 0.into(),
+        };
+        let position = position + offset;
+        let spawned = spawn(
+            &mut space, &mut time, &mut matter, miner, position, None,
+        );
+        assert!(spawned.is_err());
+        let (update, _updates) = mpsc::channel();
+        let id = spawn(
+            &mut space, &mut time, &mut matter, miner, position,
+            Some(update),
+        ).unwrap();
+        let inventory = matter.component::<item::Inventory>(id);
+        assert_eq!(inventory.unwrap().items(), &[item::Kind::Brick]);
+        assert!(matter.get::<player::Player>(id).is_some());
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::VecDeque;
+
+use sulphate;
+use units;
+use world::calendar;
+
+/// How many game days of books are kept, which is a season's worth.
+pub const DAYS: usize = calendar::DAYS_PER_SEASON as usize;
+
+/// What money comes in for, or goes out on.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Category {
+    /// Collected from the people living and working on zoned tiles.
+    Taxes,
+    /// What buildings make and spend each economy tick.
+    Running,
+    /// Putting buildings and roads up, and what comes back when they are
+    /// taken down again.
+    Construction,
+    /// Keeping the roads up.
+    Upkeep,
+}
+
+impl Category {
+    pub fn all() -> [Category; 4] {
+        [
+            Category::Taxes,
+            Category::Running,
+            Category::Construction,
+            Category::Upkeep,
+        ]
+    }
+
+    pub fn name(self: Self) -> &'static str {
+        match self {
+            Category::Taxes => "taxes",
+            Category::Running => "running",
+            Category::Construction => "construction",
+            Category::Upkeep => "road upkeep",
+        }
+    }
+
+    fn index(self: Self) -> usize {
+        Category::all()
+            .iter()
+            .position(|&each| each == self)
+            .expect("Category missing from Category::all")
+    }
+}
+
+// what came in and went out on one game day, in the order of
+// `Category::all`
+#[derive(Clone, Copy)]
+struct Day {
+    day: i64,
+    income: [i64; 4],
+    expense: [i64; 4],
+}
+
+/// The city's books for each of the last few game days, kept in the heap
+/// as the only entity of its type, like the ledger. It isn't saved, so a
+/// city picked back up from a save starts its books afresh.
+pub struct Budget {
+    days: VecDeque<Day>,
+}
+
+const BUDGET: sulphate::EntityId = 0;
+
+/// Books `amount` under `category` for the day it is at `now`, as income
+/// if it is positive, or spending if it is negative.
+pub fn record(
+    now: units::Time,
+    matter: &mut sulphate::EntityHeap,
+    category: Category,
+    amount: i64,
+) {
+    if amount == 0 {
+        return;
+    }
+    if matter.get::<Budget>(BUDGET).is_none() {
+        matter.insert(BUDGET, Budget { days: VecDeque::new() });
+    }
+    let budget = matter.get_mut::<Budget>(BUDGET).expect("budget vanished");
+    let today = calendar::date(now).day;
+    if budget.days.back().map_or(true, |last| last.day != today) {
+        let day = Day { day: today, income: [0; 4], expense: [0; 4] };
+        budget.days.push_back(day);
+        if budget.days.len() > DAYS {
+            budget.days.pop_front();
+        }
+    }
+    let day = budget.days.back_mut().expect("today vanished");
+    if amount > 0 {
+        day.income[category.index()] += amount;
+    } else {
+        day.expense[category.index()] -= amount;
+    }
+}
+
+/// What came in for, and went out on, one category.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Line {
+    pub category: Category,
+    pub income: i64,
+    pub expense: i64,
+}
+
+/// The books added up over some number of game days, up to and including
+/// today.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Report {
+    pub days: usize,
+    /// A line for every category, in the order of `Category::all`.
+    pub lines: Vec<Line>,
+}
+
+impl Report {
+    /// What came in, less what went out, over every category.
+    pub fn net(self: &Self) -> i64 {
+        self.lines.iter().map(|line| line.income - line.expense).sum()
+    }
+}
+
+/// The books over the last `days` game days as of `now`, or as many as
+/// are kept if that is fewer.
+pub fn report(
+    now: units::Time,
+    matter: &sulphate::EntityHeap,
+    days: usize,
+) -> Report {
+    let days = days.min(DAYS);
+    let today = calendar::date(now).day;
+    let mut lines: Vec<Line> = Category::all()
+        .iter()
+        .map(|&category| Line { category, income: 0, expense: 0 })
+        .collect();
+    if let Some(budget) = matter.get::<Budget>(BUDGET) {
+        let recent = budget.days
+                           .iter()
+                           .filter(|day| today - day.day < days as i64);
+        for day in recent {
+            for (i, line) in lines.iter_mut().enumerate() {
+                line.income += day.income[i];
+                line.expense += day.expense[i];
+            }
+        }
+    }
+    Report { days, lines }
+}
+
+#[cfg(test)]
+mod test_budget {
+    use super::*;
+
+    fn day(n: i32) -> units::Time {
+        units::Time::default() + calendar::hours(calendar::HOURS_PER_DAY * n)
+    }
+
+    #[test]
+    fn books_are_kept_by_the_day() {
+        let mut matter = sulphate::EntityHeap::new();
+        record(day(0), &mut matter, Category::Taxes, 30);
+        record(day(1), &mut matter, Category::Taxes, 20);
+        record(day(1), &mut matter, Category::Construction, -50);
+        record(day(1), &mut matter, Category::Construction, 10);
+
+        let today = report(day(1), &matter, 1);
+        assert_eq!(today.lines[0], Line {
+            category: Category::Taxes,
+            income: 20,
+            expense: 0,
+        });
+        assert_eq!(today.lines[2].expense, 50);
+        assert_eq!(today.lines[2].income, 10);
+        assert_eq!(today.net(), 20 - 50 + 10);
+        assert_eq!(report(day(1), &matter, 2).lines[0].income, 50);
+
+        // no more than a season back is ever added up
+        let later = day(DAYS as i32 + 1);
+        record(later, &mut matter, Category::Upkeep, -1);
+        let all = report(later, &matter, DAYS * 2);
+        assert_eq!(all.days, DAYS);
+        assert_eq!(all.lines[0].income, 0);
+        assert_eq!(all.lines[3].expense, 1);
+    }
+}
@@ -0,0 +1,595 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path;
+
+use blueprints;
+use economy;
+use economy::Resources;
+use entities::building;
+use space::zones;
+use sulphate;
+use units;
+use world::calendar;
+
+// settings are written by people, so each line is the name of a setting
+// followed by its value, written normally:
+//
+//     # anything after a hash is ignored
+//     player_speed 150
+//     player_acceleration 900
+//     player_friction 1200
+//     sprint_stamina 5
+//     citizen_speed 60
+//     vehicle_speed 96
+//     economy_tick 1
+//     growth_tick 2
+//     tax_tick 24
+//     fire_tick 24
+//     fire_chance 1
+//     fire_spread 1
+//     traffic_tick 1
+//     road_capacity 8
+//     flow power_plant 12 0 0 0 0 3
+//     cost power_plant 200
+//     road_cost 10
+//     road_upkeep 1
+//     pipe_cost 5
+//     tax residential 2
+//     freeze_distance 0
+//
+// speeds are in pixels per second, acceleration and friction in pixels per
+// second per second, stamina in seconds of sprinting, with 0 for no limit,
+// and ticks in game hours. a flow is the power, water and money that a
+// building makes, then what it uses up, every economy tick. a cost is the
+// money a player pays to put a building up, and road and pipe costs are
+// for each stretch laid. upkeep is the money each tile of road costs to
+// keep up every economy tick. a tax is the money each person living or
+// working on a tile of that zone pays every tax tick. the fire chance is
+// the percent chance of each building catching fire every fire tick, and
+// a fire spreads once it has burned for the fire spread, in game hours,
+// unless a fire engine gets there first. the road capacity is how many
+// vehicles a stretch of road carries before it slows them down, counting
+// those lately gone along it, which halves every traffic tick. the freeze
+// distance is how far from every player, in pixels, the chunks of a big
+// city stop growing and catching fire until someone comes near, with 0 for
+// never freezing any. anything left out keeps its usual value
+
+pub fn config_path() -> path::PathBuf {
+    path::PathBuf::from("config.txt")
+}
+
+/// Why a config file couldn't be read, and which line of it was to blame.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ConfigError {
+    /// Counted from 1, or None if it wasn't down to any one line.
+    pub line: Option<usize>,
+    pub reason: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.reason),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> ConfigError {
+        ConfigError { line: None, reason: err.to_string() }
+    }
+}
+
+/// The numbers that gameplay is balanced by, kept in the heap as the only
+/// entity of its type, like the ledger, so that they can be changed while
+/// the world runs.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Config {
+    /// How fast a player walking at full speed goes.
+    pub player_speed: units::Speed,
+    /// How quickly a player gets up to speed, or turns.
+    pub player_acceleration: units::Scalar,
+    /// How quickly a player comes to a stop once it stops walking.
+    pub player_friction: units::Scalar,
+    /// How long a rested player can sprint for, or 0 for as long as it
+    /// likes.
+    pub sprint_stamina: units::Duration,
+    pub citizen_speed: units::Speed,
+    pub vehicle_speed: units::Speed,
+    /// How much game time passes between economy ticks.
+    pub economy_tick: units::Duration,
+    /// How much game time passes between buildings growing on zones.
+    pub growth_tick: units::Duration,
+    /// How much game time passes between taxes being collected.
+    pub tax_tick: units::Duration,
+    /// How much game time passes between buildings having the chance to
+    /// catch fire.
+    pub fire_tick: units::Duration,
+    /// The percent chance of each building catching fire on each fire tick.
+    pub fire_chance: i64,
+    /// How long a fire burns before the building burns down and the fire
+    /// catches the buildings beside it.
+    pub fire_spread: units::Duration,
+    /// How much game time passes between traffic easing off the roads.
+    pub traffic_tick: units::Duration,
+    /// How many vehicles lately gone along a stretch of road it carries at
+    /// full speed.
+    pub road_capacity: i64,
+    /// How much money a player pays for each tile of road it lays.
+    pub road_cost: i64,
+    /// How much money each tile of road costs to keep up, every economy
+    /// tick.
+    pub road_upkeep: i64,
+    /// How much money a player pays for each tile of pipe it lays.
+    pub pipe_cost: i64,
+    /// How far from every player a chunk has to be for the systems that
+    /// tick over the whole city to leave it be, or 0 to never freeze one.
+    pub freeze_distance: units::Distance,
+    // in the order of `Blueprint::all`
+    flows: [economy::Flow; 6],
+    costs: [i64; 6],
+    // in the order of `Zone::all`
+    taxes: [i64; 3],
+}
+
+fn usual_flow(blueprint: building::Blueprint) -> economy::Flow {
+    use entities::building::Blueprint::*;
+    let (makes, uses) = match blueprint {
+        // the money is what the people living there pay in taxes
+        House => (Resources::new(0, 0, 2), Resources::new(2, 1, 0)),
+        Shop => (Resources::new(0, 0, 5), Resources::new(3, 1, 0)),
+        PowerPlant => (Resources::new(12, 0, 0), Resources::new(0, 0, 3)),
+        WaterTower => (Resources::new(0, 8, 0), Resources::new(0, 0, 2)),
+        Factory => (Resources::new(0, 0, 6), Resources::new(4, 2, 0)),
+        FireStation => (Resources::new(0, 0, 0), Resources::new(2, 2, 4)),
+    };
+    economy::Flow { makes, uses }
+}
+
+fn usual_cost(blueprint: building::Blueprint) -> i64 {
+    use entities::building::Blueprint::*;
+    match blueprint {
+        House => 50,
+        Shop => 80,
+        PowerPlant => 200,
+        WaterTower => 120,
+        Factory => 150,
+        FireStation => 160,
+    }
+}
+
+/// What each person living or working on a tile of `zone` pays every tax
+/// tick, unless the config says otherwise.
+pub fn usual_tax(zone: zones::Zone) -> i64 {
+    match zone {
+        zones::Zone::Residential => 2,
+        zones::Zone::Commercial => 3,
+        zones::Zone::Industrial => 2,
+    }
+}
+
+fn zone_index(zone: zones::Zone) -> usize {
+    zones::Zone::all()
+        .iter()
+        .position(|&each| each == zone)
+        .expect("Zone missing from Zone::all")
+}
+
+fn index(blueprint: building::Blueprint) -> usize {
+    building::Blueprint::all()
+        .iter()
+        .position(|&each| each == blueprint)
+        .expect("Blueprint missing from Blueprint::all")
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        let all = building::Blueprint::all();
+        let mut flows = [usual_flow(all[0]); 6];
+        for (flow, &blueprint) in flows.iter_mut().zip(all.iter()) {
+            *flow = usual_flow(blueprint);
+        }
+        let mut costs = [0; 6];
+        for (cost, &blueprint) in costs.iter_mut().zip(all.iter()) {
+            *cost = usual_cost(blueprint);
+        }
+        let mut taxes = [0; 3];
+        for (tax, &zone) in taxes.iter_mut().zip(zones::Zone::all().iter()) {
+            *tax = usual_tax(zone);
+        }
+        Config {
+            player_speed: 150.into(),
+            player_acceleration: 900.into(),
+            player_friction: 1200.into(),
+            sprint_stamina: 5.into(),
+            citizen_speed: 60.into(),
+            vehicle_speed: 96.into(),
+            economy_tick: calendar::hours(1),
+            growth_tick: calendar::hours(2),
+            tax_tick: calendar::hours(24),
+            fire_tick: calendar::hours(24),
+            fire_chance: 1,
+            fire_spread: calendar::hours(1),
+            traffic_tick: calendar::hours(1),
+            road_capacity: 8,
+            road_cost: 10,
+            road_upkeep: 1,
+            pipe_cost: 5,
+            freeze_distance: 0.into(),
+            flows,
+            costs,
+            taxes,
+        }
+    }
+}
+
+fn number(words: &[&str], n: usize) -> Result<f64, String> {
+    let word = words.get(n).ok_or("missing number")?;
+    word.parse().map_err(|_| format!("{} is not a number", word))
+}
+
+fn whole(words: &[&str], n: usize) -> Result<i64, String> {
+    let word = words.get(n).ok_or("missing number")?;
+    word.parse().map_err(|_| format!("{} is not a whole number", word))
+}
+
+fn positive(words: &[&str], n: usize) -> Result<units::Scalar, String> {
+    let value: units::Scalar = number(words, n)?.into();
+    if value <= 0 {
+        return Err(format!("{} has to be more than 0", words[n]));
+    }
+    Ok(value)
+}
+
+fn non_negative(words: &[&str], n: usize) -> Result<units::Scalar, String> {
+    let value: units::Scalar = number(words, n)?.into();
+    if value < 0 {
+        return Err(format!("{} can't be less than 0", words[n]));
+    }
+    Ok(value)
+}
+
+fn non_negative_whole(words: &[&str], n: usize) -> Result<i64, String> {
+    let value = whole(words, n)?;
+    if value < 0 {
+        return Err(format!("{} can't be less than 0", words[n]));
+    }
+    Ok(value)
+}
+
+fn zone(word: Option<&&str>) -> Result<zones::Zone, String> {
+    let word = word.ok_or("missing zone")?;
+    zones::Zone::all()
+        .iter()
+        .cloned()
+        .find(|zone| zone.name() == *word)
+        .ok_or_else(|| format!("unknown zone {}", word))
+}
+
+fn resources(words: &[&str], n: usize) -> Result<Resources, String> {
+    let power = whole(words, n)?;
+    let water = whole(words, n + 1)?;
+    let money = whole(words, n + 2)?;
+    Ok(Resources::new(power, water, money))
+}
+
+fn blueprint(word: Option<&&str>) -> Result<building::Blueprint, String> {
+    let word = word.ok_or("missing building")?;
+    building::Blueprint::all()
+        .iter()
+        .cloned()
+        .find(|blueprint| blueprint.name().replace(' ', "_") == *word)
+        .ok_or_else(|| format!("unknown building {}", word))
+}
+
+impl Config {
+    /// What a building of the given kind makes and uses up every tick.
+    pub fn flow(self: &Self, blueprint: building::Blueprint) -> economy::Flow {
+        self.flows[index(blueprint)]
+    }
+
+    /// How much money a player pays to put up a building of the given
+    /// kind.
+    pub fn cost(self: &Self, blueprint: building::Blueprint) -> i64 {
+        self.costs[index(blueprint)]
+    }
+
+    /// How much money each person living or working on a tile of `zone`
+    /// pays every tax tick.
+    pub fn tax(self: &Self, zone: zones::Zone) -> i64 {
+        self.taxes[zone_index(zone)]
+    }
+
+    /// The usual settings, with whatever `text` sets in place of them.
+    pub fn parse(text: &str) -> Result<Config, ConfigError> {
+        Config::default().with(text)
+    }
+
+    /// These settings, with whatever `text` sets in place of them, for
+    /// going over one config with another.
+    pub fn with(self: Self, text: &str) -> Result<Config, ConfigError> {
+        let mut config = self;
+        for (i, line) in text.lines().enumerate() {
+            config.parse_line(line).map_err(|reason| ConfigError {
+                line: Some(i + 1),
+                reason,
+            })?;
+        }
+        Ok(config)
+    }
+
+    fn parse_line(self: &mut Self, line: &str) -> Result<(), String> {
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let expected = match words.get(0).cloned() {
+            None => return Ok(()),
+            Some("player_speed") => {
+                self.player_speed = positive(&words, 1)?;
+                2
+            },
+            Some("player_acceleration") => {
+                self.player_acceleration = positive(&words, 1)?;
+                2
+            },
+            Some("player_friction") => {
+                self.player_friction = positive(&words, 1)?;
+                2
+            },
+            Some("sprint_stamina") => {
+                self.sprint_stamina = non_negative(&words, 1)?;
+                2
+            },
+            Some("citizen_speed") => {
+                self.citizen_speed = positive(&words, 1)?;
+                2
+            },
+            Some("vehicle_speed") => {
+                self.vehicle_speed = positive(&words, 1)?;
+                2
+            },
+            Some("economy_tick") => {
+                self.economy_tick = calendar::hours(1) * positive(&words, 1)?;
+                2
+            },
+            Some("growth_tick") => {
+                self.growth_tick = calendar::hours(1) * positive(&words, 1)?;
+                2
+            },
+            Some("tax_tick") => {
+                self.tax_tick = calendar::hours(1) * positive(&words, 1)?;
+                2
+            },
+            Some("fire_tick") => {
+                self.fire_tick = calendar::hours(1) * positive(&words, 1)?;
+                2
+            },
+            Some("fire_chance") => {
+                self.fire_chance = non_negative_whole(&words, 1)?;
+                2
+            },
+            Some("fire_spread") => {
+                self.fire_spread = calendar::hours(1) * positive(&words, 1)?;
+                2
+            },
+            Some("traffic_tick") => {
+                self.traffic_tick = calendar::hours(1) * positive(&words, 1)?;
+                2
+            },
+            Some("road_capacity") => {
+                self.road_capacity = whole(&words, 1)?;
+                if self.road_capacity < 1 {
+                    return Err(format!("{} has to be more than 0", words[1]));
+                }
+                2
+            },
+            Some("flow") => {
+                let blueprint = blueprint(words.get(1))?;
+                let makes = resources(&words, 2)?;
+                let uses = resources(&words, 5)?;
+                self.flows[index(blueprint)] = economy::Flow { makes, uses };
+                8
+            },
+            Some("cost") => {
+                let blueprint = blueprint(words.get(1))?;
+                self.costs[index(blueprint)] = non_negative_whole(&words, 2)?;
+                3
+            },
+            Some("road_cost") => {
+                self.road_cost = non_negative_whole(&words, 1)?;
+                2
+            },
+            Some("road_upkeep") => {
+                self.road_upkeep = non_negative_whole(&words, 1)?;
+                2
+            },
+            Some("pipe_cost") => {
+                self.pipe_cost = non_negative_whole(&words, 1)?;
+                2
+            },
+            Some("freeze_distance") => {
+                self.freeze_distance = non_negative(&words, 1)?;
+                2
+            },
+            Some("tax") => {
+                let zone = zone(words.get(1))?;
+                self.taxes[zone_index(zone)] = non_negative_whole(&words, 2)?;
+                3
+            },
+            Some(other) => return Err(format!("unknown setting {}", other)),
+        };
+        if words.len() > expected {
+            return Err(format!("unexpected {}", words[expected]));
+        }
+        Ok(())
+    }
+}
+
+/// The name of every setting `text` sets, along with the building or zone
+/// it is for if it is for one, as in `cost shop`, in the order it sets
+/// them.
+pub fn settings(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let name = words.get(0)?;
+            match *name {
+                "flow" | "cost" | "tax" => {
+                    let of = words.get(1).cloned().unwrap_or("");
+                    Some(format!("{} {}", name, of))
+                },
+                _ => Some(name.to_string()),
+            }
+        })
+        .collect()
+}
+
+/// Reads the config file at `path`.
+pub fn load(path: &path::Path) -> Result<Config, ConfigError> {
+    let mut text = String::new();
+    fs::File::open(path)?.read_to_string(&mut text)?;
+    Config::parse(&text)
+}
+
+const CONFIG: sulphate::EntityId = 0;
+
+/// The settings the world is running with, which are the usual ones until
+/// something else is set.
+pub fn get(matter: &sulphate::EntityHeap) -> Config {
+    matter.get::<Config>(CONFIG).cloned().unwrap_or_default()
+}
+
+/// Has the world run with `config` from now on. Speeds and flows change
+/// straight away, unless a building's blueprint gives it a flow of its
+/// own, and ticks once the tick that is already due comes.
+pub fn set(matter: &mut sulphate::EntityHeap, config: Config) {
+    // overwritten in place, since taking it out would take the components
+    // of whatever else has the same id with it
+    if matter.get::<Config>(CONFIG).is_some() {
+        *matter.get_mut::<Config>(CONFIG).expect("Config went missing") =
+            config;
+    } else {
+        matter.insert(CONFIG, config);
+    }
+
+    let ids: Vec<sulphate::EntityId> = matter
+        .query::<(&economy::Flow,)>()
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    for id in ids {
+        let made = match blueprints::flow(matter, id, &config) {
+            Some(made) => made,
+            None => continue,
+        };
+        if let Some(flow) = matter.component_mut::<economy::Flow>(id) {
+            *flow = made;
+        }
+    }
+}
+
+/// How long between economy ticks, as the world is set up now.
+pub fn economy_tick(matter: &sulphate::EntityHeap) -> units::Duration {
+    get(matter).economy_tick
+}
+
+/// How long between growth ticks, as the world is set up now.
+pub fn growth_tick(matter: &sulphate::EntityHeap) -> units::Duration {
+    get(matter).growth_tick
+}
+
+/// How long between tax ticks, as the world is set up now.
+pub fn tax_tick(matter: &sulphate::EntityHeap) -> units::Duration {
+    get(matter).tax_tick
+}
+
+/// How long between fire ticks, as the world is set up now.
+pub fn fire_tick(matter: &sulphate::EntityHeap) -> units::Duration {
+    get(matter).fire_tick
+}
+
+/// How long between traffic ticks, as the world is set up now.
+pub fn traffic_tick(matter: &sulphate::EntityHeap) -> units::Duration {
+    get(matter).traffic_tick
+}
+
+#[cfg(test)]
+mod test_config {
+    use super::*;
+
+    #[test]
+    fn settings_left_out_stay_usual() {
+        let config = Config::parse("\
+            # faster walking\n\
+            player_speed 200\n\
+            player_acceleration 600\n\
+            economy_tick 0.5\n\
+            flow power_plant 20 0 0 0 0 5\n\
+            cost shop 90\n\
+            road_upkeep 3\n\
+            pipe_cost 8\n\
+            fire_chance 3\n\
+            fire_spread 0.5\n\
+            road_capacity 3\n\
+            tax commercial 5\n\
+            freeze_distance 800\n\
+        ").unwrap();
+        let usual = Config::default();
+        assert_eq!(config.player_speed, units::Scalar::from(200));
+        assert_eq!(config.citizen_speed, usual.citizen_speed);
+        assert_eq!(config.player_acceleration, units::Scalar::from(600));
+        assert_eq!(config.player_friction, usual.player_friction);
+        assert_eq!(config.economy_tick, calendar::hours(1) / 2);
+        let plant = config.flow(building::Blueprint::PowerPlant);
+        assert_eq!(plant.makes, Resources::new(20, 0, 0));
+        assert_eq!(plant.uses, Resources::new(0, 0, 5));
+        let house = building::Blueprint::House;
+        assert_eq!(config.flow(house), usual.flow(house));
+        assert_eq!(config.cost(building::Blueprint::Shop), 90);
+        assert_eq!(config.cost(house), usual.cost(house));
+        assert_eq!(config.road_cost, usual.road_cost);
+        assert_eq!(config.road_upkeep, 3);
+        assert_eq!(config.pipe_cost, 8);
+        assert_eq!(config.fire_chance, 3);
+        assert_eq!(config.fire_spread, calendar::hours(1) / 2);
+        assert_eq!(config.fire_tick, usual.fire_tick);
+        assert_eq!(config.road_capacity, 3);
+        assert_eq!(config.traffic_tick, usual.traffic_tick);
+        assert_eq!(config.freeze_distance, units::Scalar::from(800));
+        assert_eq!(config.tax(zones::Zone::Commercial), 5);
+        let residential = zones::Zone::Residential;
+        assert_eq!(config.tax(residential), usual.tax(residential));
+    }
+
+    #[test]
+    fn mistakes_say_where_they_are() {
+        let error = |text: &str| Config::parse(text).unwrap_err();
+        assert_eq!(error("player_speed 1\nplayer_speed fast").to_string(),
+                   "line 2: fast is not a number");
+        assert_eq!(error("growth_tick 0").reason, "0 has to be more than 0");
+        assert_eq!(error("sprint_stamina -1").reason,
+                   "-1 can't be less than 0");
+        assert!(Config::parse("sprint_stamina 0").is_ok());
+        assert_eq!(error("flow castle 1 1 1 1 1 1").reason,
+                   "unknown building castle");
+        assert_eq!(error("vehicle_speed 5 6").reason, "unexpected 6");
+        assert_eq!(error("road_cost -5").reason, "-5 can't be less than 0");
+        assert_eq!(error("road_capacity 0").reason,
+                   "0 has to be more than 0");
+        assert_eq!(error("tax suburban 1").reason, "unknown zone suburban");
+        assert_eq!(error("gravity 10").reason, "unknown setting gravity");
+    }
+
+    #[test]
+    fn configs_go_over_each_other() {
+        let first = Config::parse("player_speed 200\ncost shop 90").unwrap();
+        let second = first.with("cost shop 70 # cheaper\n").unwrap();
+        assert_eq!(second.player_speed, units::Scalar::from(200));
+        assert_eq!(second.cost(building::Blueprint::Shop), 70);
+        assert_eq!(settings("# nothing\nroad_cost 3\nflow house 1 1 1 1 1"),
+                   vec!["road_cost".to_string(), "flow house".to_string()]);
+    }
+}
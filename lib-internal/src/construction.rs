@@ -0,0 +1,1085 @@
+use std::cmp;
+use std::collections;
+
+use blueprints;
+use budget;
+use config;
+use economy;
+use entities::building;
+use entities::player;
+use growth;
+use space;
+use space::zones;
+use sulphate;
+use units;
+
+// how many changes back a player can undo, after which the oldest are
+// forgotten
+const DEPTH: usize = 32;
+
+/// Something that a player built, kept so that it can be taken back, along
+/// with what was paid for it.
+#[derive(Clone)]
+enum Change {
+    Built {
+        id: sulphate::EntityId,
+        blueprint: blueprints::Name,
+        position: units::Position,
+        paid: i64,
+    },
+    // only the stretches of road that weren't there already
+    Road {
+        from: units::Position,
+        to: units::Position,
+        laid: Vec<(space::TileId, space::TileId)>,
+        paid: i64,
+    },
+    // the same for pipes
+    Pipe {
+        from: units::Position,
+        to: units::Position,
+        laid: Vec<(space::TileId, space::TileId)>,
+        paid: i64,
+    },
+    // how each tile was zoned before, and which already had buildings
+    Zoned {
+        from: units::Position,
+        to: units::Position,
+        zone: Option<zones::Zone>,
+        before: Vec<(space::TileId, Option<zones::Zone>)>,
+        built: Vec<space::TileId>,
+    },
+    // everything a batch did, in the order it did it
+    Batch(Vec<Change>),
+}
+
+/// One thing for a batch to do, the same way as the function of the same
+/// name would do it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Step {
+    Place {
+        blueprint: blueprints::Name,
+        position: units::Position,
+    },
+    Road {
+        from: units::Position,
+        to: units::Position,
+    },
+    Pipe {
+        from: units::Position,
+        to: units::Position,
+    },
+    Zone {
+        from: units::Position,
+        to: units::Position,
+        zone: Option<zones::Zone>,
+    },
+}
+
+/// What came of a step in a batch that went ahead.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Done {
+    Placed(sulphate::EntityId),
+    Laid,
+    Zoned(usize),
+}
+
+/// What a player has built and can undo, and what it has undone and can
+/// do again, kept as a component of the player. It isn't saved, so a
+/// player picked back up from a save starts out with nothing to undo.
+#[derive(Default)]
+pub struct History {
+    done: Vec<Change>,
+    undone: Vec<Change>,
+}
+
+impl History {
+    /// How many changes there are to undo, and to redo.
+    pub fn depth(self: &Self) -> (usize, usize) {
+        (self.done.len(), self.undone.len())
+    }
+}
+
+// the player's history, if there is such a player
+fn history(
+    matter: &mut sulphate::EntityHeap,
+    player: sulphate::EntityId,
+) -> Option<&mut History> {
+    matter.get::<player::Player>(player)?;
+    if matter.component::<History>(player).is_none() {
+        matter.insert_component(player, History::default());
+    }
+    matter.component_mut::<History>(player)
+}
+
+// something new done, which leaves nothing to redo
+fn push(
+    matter: &mut sulphate::EntityHeap,
+    player: sulphate::EntityId,
+    change: Change,
+) {
+    if let Some(history) = history(matter, player) {
+        history.undone.clear();
+        history.done.push(change);
+        if history.done.len() > DEPTH {
+            history.done.remove(0);
+        }
+    }
+}
+
+/// Builds from the blueprint named `blueprint` on the tile under
+/// `position`, as `blueprints::place` does, paid for by player `by` and
+/// put down to it, or for free if `by` is None.
+/// Nothing is built for a player that doesn't exist, or that the city
+/// can't afford.
+pub fn place(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    by: Option<sulphate::EntityId>,
+    blueprint: blueprints::Name,
+    position: units::Position,
+) -> Option<sulphate::EntityId> {
+    let player = match by {
+        Some(player) => player,
+        None => {
+            return blueprints::place(space, time, matter, blueprint, position);
+        },
+    };
+    history(matter, player)?;
+    let change = do_place(space, time, matter, true, blueprint, position)?;
+    let id = match change {
+        Change::Built { id, .. } => id,
+        _ => unreachable!(),
+    };
+    push(matter, player, change);
+    Some(id)
+}
+
+// builds as `place` does, for nothing unless it `pays`
+fn do_place(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    pays: bool,
+    blueprint: blueprints::Name,
+    position: units::Position,
+) -> Option<Change> {
+    let cost = blueprints::cost(matter, blueprint)?;
+    let paid = if pays { cost } else { 0 };
+    if !affordable(matter, paid) {
+        return None;
+    }
+    let id = blueprints::place(space, time, matter, blueprint, position)?;
+    let category = budget::Category::Construction;
+    economy::charge(time, matter, category, paid);
+    let position = building::snap(position);
+    Some(Change::Built { id, blueprint, position, paid })
+}
+
+/// Lays road between the tiles under `from` and `to`, as
+/// `CollisionSpace::build_road` does, paid for by player `by` for each
+/// stretch that wasn't already there. Nothing is laid if the city can't
+/// afford all of it.
+pub fn lay_road(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    by: Option<sulphate::EntityId>,
+    from: units::Position,
+    to: units::Position,
+) -> bool {
+    let player = match by {
+        Some(player) => player,
+        None => {
+            let built = space.build_road(from, to);
+            if built {
+                economy::resupply(space, time, matter);
+            }
+            return built;
+        },
+    };
+    if history(matter, player).is_none() {
+        return false;
+    }
+    match do_lay_road(space, time, matter, true, from, to) {
+        Some(Some(change)) => push(matter, player, change),
+        // nothing new, so nothing to undo
+        Some(None) => (),
+        None => return false,
+    }
+    true
+}
+
+fn do_lay_road(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    pays: bool,
+    from: units::Position,
+    to: units::Position,
+) -> Option<Option<Change>> {
+    let laid = {
+        let terrain = space.terrain();
+        let (a, b) = (terrain.tile_id(from), terrain.tile_id(to));
+        space.roads().unlaid(a, b)?
+    };
+    let each = if pays { config::get(matter).road_cost } else { 0 };
+    let paid = each * laid.len() as i64;
+    if !affordable(matter, paid) || !space.build_road(from, to) {
+        return None;
+    }
+    economy::resupply(space, time, matter);
+    if laid.is_empty() {
+        return Some(None);
+    }
+    let category = budget::Category::Construction;
+    economy::charge(time, matter, category, paid);
+    Some(Some(Change::Road { from, to, laid, paid }))
+}
+
+/// Lays pipe between the tiles under `from` and `to`, as
+/// `CollisionSpace::lay_pipe` does, paid for by player `by` the same way
+/// as roads are.
+pub fn lay_pipe(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    by: Option<sulphate::EntityId>,
+    from: units::Position,
+    to: units::Position,
+) -> bool {
+    let player = match by {
+        Some(player) => player,
+        None => {
+            let laid = space.lay_pipe(from, to);
+            if laid {
+                economy::resupply(space, time, matter);
+            }
+            return laid;
+        },
+    };
+    if history(matter, player).is_none() {
+        return false;
+    }
+    match do_lay_pipe(space, time, matter, true, from, to) {
+        Some(Some(change)) => push(matter, player, change),
+        Some(None) => (),
+        None => return false,
+    }
+    true
+}
+
+fn do_lay_pipe(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    pays: bool,
+    from: units::Position,
+    to: units::Position,
+) -> Option<Option<Change>> {
+    let laid = {
+        let terrain = space.terrain();
+        let (a, b) = (terrain.tile_id(from), terrain.tile_id(to));
+        space.pipes().unlaid(a, b)?
+    };
+    let each = if pays { config::get(matter).pipe_cost } else { 0 };
+    let paid = each * laid.len() as i64;
+    if !affordable(matter, paid) || !space.lay_pipe(from, to) {
+        return None;
+    }
+    economy::resupply(space, time, matter);
+    if laid.is_empty() {
+        return Some(None);
+    }
+    let category = budget::Category::Construction;
+    economy::charge(time, matter, category, paid);
+    Some(Some(Change::Pipe { from, to, laid, paid }))
+}
+
+/// Paints `zone` on the tiles between `from` and `to`, as `growth::zone`
+/// does, put down to player `by`. Zoning is free.
+pub fn zone(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    by: Option<sulphate::EntityId>,
+    from: units::Position,
+    to: units::Position,
+    zone: Option<zones::Zone>,
+) -> usize {
+    let player = match by {
+        Some(player) => player,
+        None => return growth::zone(space, time, matter, from, to, zone),
+    };
+    if history(matter, player).is_none() {
+        return 0;
+    }
+    match do_zone(space, time, matter, from, to, zone) {
+        Some((painted, change)) => {
+            push(matter, player, change);
+            painted
+        },
+        None => 0,
+    }
+}
+
+fn do_zone(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    from: units::Position,
+    to: units::Position,
+    zone: Option<zones::Zone>,
+) -> Option<(usize, Change)> {
+    let (before, built) = {
+        let terrain = space.terrain();
+        let (a, b) = (terrain.tile_id(from), terrain.tile_id(to));
+        let tiles = zones::rectangle(a, b)?;
+        let before = tiles.iter()
+                          .map(|&id| (id, space.zones().zone(id)))
+                          .collect();
+        let built = tiles.into_iter()
+                         .filter(|&id| is_built(terrain, id))
+                         .collect();
+        (before, built)
+    };
+    let painted = growth::zone(space, time, matter, from, to, zone);
+    Some((painted, Change::Zoned { from, to, zone, before, built }))
+}
+
+/// Does each of `steps` in turn, as the functions above would, paid for by
+/// player `by` and put down to it as one change that is undone all at
+/// once, or for free if `by` is None. Either every step goes ahead or none
+/// of them do, so nothing is done if the city can't afford all of it, or
+/// if anything is in the way of any one of them. Gives what came of each
+/// step, or None if nothing was done.
+pub fn batch(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    by: Option<sulphate::EntityId>,
+    steps: &[Step],
+) -> Option<Vec<Done>> {
+    if let Some(player) = by {
+        history(matter, player)?;
+    }
+    let pays = by.is_some();
+    if pays && !affordable(matter, cost(space, matter, steps)?) {
+        return None;
+    }
+    if !feasible(space, matter, time.now(), steps) {
+        return None;
+    }
+    let mut done = Vec::new();
+    let mut changes = Vec::new();
+    for &step in steps {
+        match do_step(space, time, matter, pays, step) {
+            Some((outcome, change)) => {
+                done.push(outcome);
+                changes.extend(change);
+            },
+            None => {
+                // only if the world isn't as `feasible` thought, but what
+                // the steps before did is still taken back, last first
+                let mut taken = Change::Batch(changes);
+                if !revert(space, time, matter, &mut taken) {
+                    warn!("Couldn't take back a batch that didn't go ahead");
+                }
+                return None;
+            },
+        }
+    }
+    if let Some(player) = by {
+        if !changes.is_empty() {
+            push(matter, player, Change::Batch(changes));
+        }
+    }
+    Some(done)
+}
+
+// what doing all of `steps` would cost, paying only once for road or pipe
+// that more than one of them lays, or None if one of them can't be laid
+fn cost(
+    space: &space::CollisionSpace,
+    matter: &sulphate::EntityHeap,
+    steps: &[Step],
+) -> Option<i64> {
+    let config = config::get(matter);
+    let terrain = space.terrain();
+    // either way along a stretch is the same stretch
+    let stretch = |(a, b): (space::TileId, space::TileId)| {
+        (cmp::min(a, b), cmp::max(a, b))
+    };
+    let mut roads = collections::BTreeSet::new();
+    let mut pipes = collections::BTreeSet::new();
+    let mut total = 0;
+    for &step in steps {
+        match step {
+            Step::Place { blueprint, .. } => {
+                total += blueprints::cost(matter, blueprint)?;
+            },
+            Step::Road { from, to } => {
+                let (a, b) = (terrain.tile_id(from), terrain.tile_id(to));
+                let laid = space.roads().unlaid(a, b)?;
+                roads.extend(laid.into_iter().map(&stretch));
+            },
+            Step::Pipe { from, to } => {
+                let (a, b) = (terrain.tile_id(from), terrain.tile_id(to));
+                let laid = space.pipes().unlaid(a, b)?;
+                pipes.extend(laid.into_iter().map(&stretch));
+            },
+            Step::Zone { .. } => (),
+        }
+    }
+    total += config.road_cost * roads.len() as i64;
+    total += config.pipe_cost * pipes.len() as i64;
+    Some(total)
+}
+
+// whether every one of `steps` could be done, each after those before it,
+// going through them against a copy of the roads and pipes, so that a batch
+// that can't be finished is never started
+fn feasible(
+    space: &space::CollisionSpace,
+    matter: &sulphate::EntityHeap,
+    now: units::Time,
+    steps: &[Step],
+) -> bool {
+    let terrain = space.terrain();
+    let mut roads = space.roads().clone();
+    let mut pipes = space.pipes().clone();
+    // the tiles that buildings earlier in the batch go up on
+    let mut built = collections::BTreeSet::new();
+    for &step in steps {
+        let fits = match step {
+            Step::Place { blueprint, position } => {
+                let position = building::snap(position);
+                let tile = terrain.tile_id(position);
+                match blueprints::kind(matter, blueprint) {
+                    Some(kind) => {
+                        let reached = !roads.access(tile).is_empty();
+                        let fits = !built.contains(&tile)
+                            && !space.blocked(now, position, kind.radius())
+                            && !roads.has_road(tile)
+                            && (reached || !kind.needs_road());
+                        built.insert(tile);
+                        fits
+                    },
+                    None => false,
+                }
+            },
+            Step::Road { from, to } => {
+                let (a, b) = (terrain.tile_id(from), terrain.tile_id(to));
+                let clear = space::roads::run(a, b).map_or(false, |tiles| {
+                    tiles.iter().all(|id| {
+                        !terrain.tile(*id).solid() && !built.contains(id)
+                    })
+                });
+                clear && roads.add_segment(a, b)
+            },
+            Step::Pipe { from, to } => {
+                let (a, b) = (terrain.tile_id(from), terrain.tile_id(to));
+                let clear = space::roads::run(a, b).map_or(false, |tiles| {
+                    tiles.iter().all(|&id| {
+                        let tile = terrain.tile(id);
+                        tile == space::Tile::Open
+                            || tile == space::Tile::Building
+                    })
+                });
+                clear && pipes.add_segment(a, b)
+            },
+            Step::Zone { from, to, .. } => {
+                let (a, b) = (terrain.tile_id(from), terrain.tile_id(to));
+                zones::rectangle(a, b).is_some()
+            },
+        };
+        if !fits {
+            return false;
+        }
+    }
+    true
+}
+
+// the steps that would do `changes` again
+fn steps_of(changes: &[Change]) -> Vec<Step> {
+    let mut steps = Vec::new();
+    for change in changes {
+        match *change {
+            Change::Built { blueprint, position, .. } => {
+                steps.push(Step::Place { blueprint, position });
+            },
+            Change::Road { from, to, .. } => {
+                steps.push(Step::Road { from, to });
+            },
+            Change::Pipe { from, to, .. } => {
+                steps.push(Step::Pipe { from, to });
+            },
+            Change::Zoned { from, to, zone, .. } => {
+                steps.push(Step::Zone { from, to, zone });
+            },
+            Change::Batch(ref changes) => steps.extend(steps_of(changes)),
+        }
+    }
+    steps
+}
+
+// does one step of a batch, giving what came of it and what there is to
+// undo, or None if it couldn't be done
+fn do_step(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    pays: bool,
+    step: Step,
+) -> Option<(Done, Option<Change>)> {
+    match step {
+        Step::Place { blueprint, position } => {
+            let change =
+                do_place(space, time, matter, pays, blueprint, position)?;
+            let id = match change {
+                Change::Built { id, .. } => id,
+                _ => unreachable!(),
+            };
+            Some((Done::Placed(id), Some(change)))
+        },
+        Step::Road { from, to } => {
+            let change = do_lay_road(space, time, matter, pays, from, to)?;
+            Some((Done::Laid, change))
+        },
+        Step::Pipe { from, to } => {
+            let change = do_lay_pipe(space, time, matter, pays, from, to)?;
+            Some((Done::Laid, change))
+        },
+        Step::Zone { from, to, zone } => {
+            let (painted, change) =
+                do_zone(space, time, matter, from, to, zone)?;
+            Some((Done::Zoned(painted), Some(change)))
+        },
+    }
+}
+
+// whether the city has `cost` to spend, though anything free can be had
+// even by a city that owes money
+fn affordable(matter: &sulphate::EntityHeap, cost: i64) -> bool {
+    cost == 0 || economy::balances(matter).money >= cost
+}
+
+fn is_built(terrain: &space::Terrain, id: space::TileId) -> bool {
+    terrain.tile(id) == space::Tile::Building
+}
+
+// the building standing on tile `id`, if there is one
+fn building_on(
+    space: &space::CollisionSpace,
+    matter: &sulphate::EntityHeap,
+    now: units::Time,
+    id: space::TileId,
+) -> Option<building::Blueprint> {
+    let building = building::standing_on(space, now, id)?;
+    matter.get::<building::Building>(building)
+          .map(building::Building::blueprint)
+}
+
+// whether digging up `laid` would cut off a building that needs a road,
+// leaving aside any that had no road to get to anyway
+fn strands_anything(
+    space: &space::CollisionSpace,
+    matter: &sulphate::EntityHeap,
+    now: units::Time,
+    laid: &[(space::TileId, space::TileId)],
+) -> bool {
+    let mut roads = space.roads().clone();
+    for &(a, b) in laid {
+        roads.remove_segment(a, b);
+    }
+    laid.iter()
+        .flat_map(|&(a, b)| vec![a, b])
+        .flat_map(|(x, y)| {
+            vec![(x, y - 1), (x, y + 1), (x - 1, y), (x + 1, y)]
+        })
+        .any(|id| {
+            let needs_road = building_on(space, matter, now, id)
+                .map_or(false, building::Blueprint::needs_road);
+            let reached = !space.roads().access(id).is_empty();
+            needs_road && reached && roads.access(id).is_empty()
+        })
+}
+
+// takes `change` back, giving whether nothing had been built over it since
+fn revert(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    change: &mut Change,
+) -> bool {
+    match *change {
+        Change::Built { id, blueprint, paid, .. } => {
+            if blueprints::made_as(matter, id) != Some(blueprint) {
+                return false;
+            }
+            building::Building::remove(space, time, matter, id);
+            let category = budget::Category::Construction;
+            economy::refund(time, matter, category, paid);
+        },
+        Change::Road { ref laid, paid, .. } => {
+            if strands_anything(space, matter, time.now(), laid) {
+                return false;
+            }
+            space.dig_up_road(laid);
+            economy::resupply(space, time, matter);
+            let category = budget::Category::Construction;
+            economy::refund(time, matter, category, paid);
+        },
+        // nothing needs a pipe to stand, only to have water
+        Change::Pipe { ref laid, paid, .. } => {
+            space.dig_up_pipe(laid);
+            economy::resupply(space, time, matter);
+            let category = budget::Category::Construction;
+            economy::refund(time, matter, category, paid);
+        },
+        Change::Zoned { zone, ref before, ref built, .. } => {
+            let built_over = before.iter().any(|&(id, _)| {
+                is_built(space.terrain(), id) && !built.contains(&id)
+            });
+            if built_over {
+                return false;
+            }
+            // tiles zoned again since then are left to whoever zoned them
+            for &(id, was) in before {
+                if space.zones().zone(id) == zone {
+                    let center = space.terrain().center(id);
+                    space.paint_zone(center, center, was);
+                }
+            }
+        },
+        // last first, and all or nothing, so whatever was taken back
+        // before one that can't be is done again
+        Change::Batch(ref mut changes) => {
+            let mut taken = 0;
+            for change in changes.iter_mut().rev() {
+                if !revert(space, time, matter, change) {
+                    break;
+                }
+                taken += 1;
+            }
+            if taken < changes.len() {
+                let start = changes.len() - taken;
+                for change in &mut changes[start..] {
+                    match reapply(space, time, matter, change) {
+                        Some(again) => *change = again,
+                        None => warn!("Couldn't put back part of a batch"),
+                    }
+                }
+                return false;
+            }
+        },
+    }
+    true
+}
+
+// does `change` again, giving how it went this time, or None if something
+// is in the way now, or there isn't the money
+fn reapply(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    change: &Change,
+) -> Option<Change> {
+    match *change {
+        Change::Built { blueprint, position, .. } => {
+            do_place(space, time, matter, true, blueprint, position)
+        },
+        Change::Road { from, to, .. } => {
+            // road that somebody else has laid since is theirs to undo
+            let laid = do_lay_road(space, time, matter, true, from, to)?;
+            let nothing = Change::Road { from, to, laid: Vec::new(), paid: 0 };
+            Some(laid.unwrap_or(nothing))
+        },
+        Change::Pipe { from, to, .. } => {
+            let laid = do_lay_pipe(space, time, matter, true, from, to)?;
+            let nothing = Change::Pipe { from, to, laid: Vec::new(), paid: 0 };
+            Some(laid.unwrap_or(nothing))
+        },
+        Change::Zoned { from, to, zone, .. } => {
+            do_zone(space, time, matter, from, to, zone)
+                .map(|(_, change)| change)
+        },
+        // all or nothing, the same as the first time
+        Change::Batch(ref changes) => {
+            let steps = steps_of(changes);
+            let total = cost(space, matter, &steps)?;
+            if !affordable(matter, total)
+                || !feasible(space, matter, time.now(), &steps)
+            {
+                return None;
+            }
+            let mut again = Vec::new();
+            for change in changes {
+                match reapply(space, time, matter, change) {
+                    Some(change) => again.push(change),
+                    None => {
+                        let mut taken = Change::Batch(again);
+                        if !revert(space, time, matter, &mut taken) {
+                            warn!("Couldn't take back part of a batch");
+                        }
+                        return None;
+                    },
+                }
+            }
+            Some(Change::Batch(again))
+        },
+    }
+}
+
+/// Takes back the last thing player `id` built, refunding what it paid,
+/// unless something has been built that depends on it since. Gives whether
+/// anything was undone.
+pub fn undo(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) -> bool {
+    let mut change = match history(matter, id).and_then(|h| h.done.pop()) {
+        Some(change) => change,
+        None => return false,
+    };
+    let reverted = revert(space, time, matter, &mut change);
+    if let Some(history) = history(matter, id) {
+        if reverted {
+            history.undone.push(change);
+        } else {
+            history.done.push(change);
+        }
+    }
+    reverted
+}
+
+/// Builds again the last thing player `id` undid, paying for it again,
+/// unless something is in the way of it now or the city can't afford it.
+/// Gives whether anything was redone.
+pub fn redo(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) -> bool {
+    let change = match history(matter, id).and_then(|h| h.undone.pop()) {
+        Some(change) => change,
+        None => return false,
+    };
+    let redone = reapply(space, time, matter, &change);
+    if let Some(history) = history(matter, id) {
+        match redone {
+            Some(ref redone) => history.done.push(redone.clone()),
+            None => history.undone.push(change),
+        }
+    }
+    redone.is_some()
+}
+
+#[cfg(test)]
+mod test_construction {
+    use std::sync::mpsc;
+
+    use sulphate::server::Interruption;
+    use sulphate::testing::TestServer;
+
+    use super::*;
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    // a world with one player in it, standing well out of the way
+    fn with_player() -> (TestServer, sulphate::EntityId) {
+        TestServer::new(|space, time, matter| {
+            let (send, _) = mpsc::channel();
+            let position = at(0, 600);
+            let appearance = Default::default();
+            player::Player::new(
+                space, time, matter, position, appearance, send,
+            ).id()
+        })
+    }
+
+    fn money(server: &TestServer) -> i64 {
+        economy::balances(server.matter()).money
+    }
+
+    fn zone_at(server: &TestServer, x: i32, y: i32) -> Option<zones::Zone> {
+        let tile = server.space().terrain().tile_id(at(x, y));
+        server.space().zones().zone(tile)
+    }
+
+    #[test]
+    fn undone_buildings_are_refunded() {
+        let (mut server, id) = with_player();
+        let start = money(&server);
+        let blueprint = building::Blueprint::House;
+        let (reply, built) = mpsc::channel();
+        server.send(Interruption::PlaceBuilding {
+            blueprint: blueprint.into(),
+            position: at(100, 100),
+            by: Some(id),
+            reply,
+        });
+        let house = built.recv().unwrap().expect("no room for the house");
+        let cost = config::Config::default().cost(blueprint);
+        assert_eq!(money(&server), start - cost);
+
+        server.send(Interruption::Undo { id });
+        assert!(server.matter().get::<building::Building>(house).is_none());
+        assert_eq!(money(&server), start);
+        let history = server.matter().component::<History>(id).unwrap();
+        assert_eq!(history.depth(), (0, 1));
+
+        server.send(Interruption::Redo { id });
+        assert_eq!(money(&server), start - cost);
+        let history = server.matter().component::<History>(id).unwrap();
+        assert_eq!(history.depth(), (1, 0));
+    }
+
+    #[test]
+    fn nothing_is_built_without_the_money() {
+        let (mut server, id) = TestServer::new(|space, time, matter| {
+            economy::open(time, matter).set_money(55);
+            let (send, _) = mpsc::channel();
+            let position = at(0, 600);
+            let appearance = Default::default();
+            player::Player::new(
+                space, time, matter, position, appearance, send,
+            ).id()
+        });
+        let (reply, built) = mpsc::channel();
+        server.send(Interruption::PlaceBuilding {
+            blueprint: building::Blueprint::PowerPlant.into(),
+            position: at(100, 100),
+            by: Some(id),
+            reply: reply.clone(),
+        });
+        assert_eq!(built.recv().unwrap(), None);
+        assert_eq!(money(&server), 55);
+
+        // six stretches of road is more than what is left after a house
+        server.send(Interruption::PlaceBuilding {
+            blueprint: building::Blueprint::House.into(),
+            position: at(100, 100),
+            by: Some(id),
+            reply,
+        });
+        assert!(built.recv().unwrap().is_some());
+        let (reply, laid) = mpsc::channel();
+        server.send(Interruption::BuildRoad {
+            from: at(0, 10),
+            to: at(200, 10),
+            by: Some(id),
+            reply,
+        });
+        assert!(!laid.recv().unwrap());
+        assert_eq!(money(&server), 5);
+        let tile = server.space().terrain().tile_id(at(0, 10));
+        assert!(!server.space().roads().has_road(tile));
+    }
+
+    #[test]
+    fn roads_that_buildings_need_stay() {
+        let (mut server, id) = with_player();
+        let start = money(&server);
+        let (reply, _) = mpsc::channel();
+        // along the row of tiles from y = 0 to 32, six stretches long
+        server.send(Interruption::BuildRoad {
+            from: at(0, 10),
+            to: at(200, 10),
+            by: Some(id),
+            reply,
+        });
+        let road_cost = config::Config::default().road_cost;
+        assert_eq!(money(&server), start - 6 * road_cost);
+
+        // somebody else's shop, that only has this road to get to
+        let (reply, built) = mpsc::channel();
+        server.send(Interruption::PlaceBuilding {
+            blueprint: building::Blueprint::Shop.into(),
+            position: at(100, 40),
+            by: None,
+            reply,
+        });
+        assert!(built.recv().unwrap().is_some());
+
+        server.send(Interruption::Undo { id });
+        let tile = server.space().terrain().tile_id(at(100, 10));
+        assert!(server.space().roads().has_road(tile));
+        assert_eq!(money(&server), start - 6 * road_cost);
+        let history = server.matter().component::<History>(id).unwrap();
+        assert_eq!(history.depth(), (1, 0));
+    }
+
+    #[test]
+    fn undone_pipes_are_dug_up() {
+        let (mut server, id) = with_player();
+        let start = money(&server);
+        let (reply, laid) = mpsc::channel();
+        // down the column of tiles from x = 0 to 32, three stretches long
+        server.send(Interruption::LayPipe {
+            from: at(10, 0),
+            to: at(10, 100),
+            by: Some(id),
+            reply,
+        });
+        assert!(laid.recv().unwrap());
+        let pipe_cost = config::Config::default().pipe_cost;
+        assert_eq!(money(&server), start - 3 * pipe_cost);
+
+        server.send(Interruption::Undo { id });
+        assert!(server.space().pipes().is_empty());
+        assert_eq!(money(&server), start);
+        server.send(Interruption::Redo { id });
+        assert!(server.space().pipes().linked((0, 2), (0, 3)));
+        assert_eq!(money(&server), start - 3 * pipe_cost);
+    }
+
+    #[test]
+    fn undone_zoning_goes_back_to_how_it_was() {
+        let (mut server, id) = with_player();
+        let paint = |from, to, zone| {
+            let (reply, _) = mpsc::channel();
+            Interruption::PaintZone { from, to, zone, by: Some(id), reply }
+        };
+        let residential = Some(zones::Zone::Residential);
+        let commercial = Some(zones::Zone::Commercial);
+        server.send(paint(at(10, 40), at(80, 70), residential));
+        server.send(paint(at(40, 40), at(120, 40), commercial));
+        assert_eq!(zone_at(&server, 10, 40), residential);
+        assert_eq!(zone_at(&server, 40, 40), commercial);
+        assert_eq!(zone_at(&server, 120, 40), commercial);
+
+        server.send(Interruption::Undo { id });
+        assert_eq!(zone_at(&server, 40, 40), residential);
+        assert_eq!(zone_at(&server, 120, 40), None);
+
+        server.send(Interruption::Undo { id });
+        assert_eq!(zone_at(&server, 10, 40), None);
+        assert_eq!(zone_at(&server, 40, 40), None);
+        let history = server.matter().component::<History>(id).unwrap();
+        assert_eq!(history.depth(), (0, 2));
+    }
+
+    // a road along the row of tiles from y = 0 to 32, six stretches long,
+    // and a house beside it
+    fn road_and_house(
+        id: sulphate::EntityId,
+    ) -> (Vec<Interruption>, mpsc::Receiver<Option<sulphate::EntityId>>) {
+        let (reply, _) = mpsc::channel();
+        let road = Interruption::BuildRoad {
+            from: at(0, 10),
+            to: at(200, 10),
+            by: Some(id),
+            reply,
+        };
+        let (reply, built) = mpsc::channel();
+        let house = Interruption::PlaceBuilding {
+            blueprint: building::Blueprint::House.into(),
+            position: at(100, 40),
+            by: Some(id),
+            reply,
+        };
+        (vec![road, house], built)
+    }
+
+    #[test]
+    fn batches_go_ahead_all_or_nothing() {
+        let config = config::Config::default();
+        // a stretch of road short of the road and the house together
+        let short = config.cost(building::Blueprint::House)
+                  + 5 * config.road_cost;
+        let (mut server, id) = TestServer::new(move |space, time, matter| {
+            economy::open(time, matter).set_money(short);
+            let (send, _) = mpsc::channel();
+            let position = at(0, 600);
+            let appearance = Default::default();
+            player::Player::new(
+                space, time, matter, position, appearance, send,
+            ).id()
+        });
+        let road = server.space().terrain().tile_id(at(100, 10));
+        let (batch, built) = road_and_house(id);
+        server.send(Interruption::Batch(batch));
+        assert_eq!(built.recv().unwrap(), None);
+        assert!(!server.space().roads().has_road(road));
+        assert_eq!(money(&server), short);
+    }
+
+    #[test]
+    fn batches_that_cannot_all_fit_are_never_started() {
+        let (mut server, id) = with_player();
+        let start = money(&server);
+        let road = server.space().terrain().tile_id(at(100, 10));
+        // a second house where the first one went has no room
+        let (mut batch, built) = road_and_house(id);
+        let (reply, second) = mpsc::channel();
+        batch.push(Interruption::PlaceBuilding {
+            blueprint: building::Blueprint::House.into(),
+            position: at(100, 40),
+            by: Some(id),
+            reply,
+        });
+        server.send(Interruption::Batch(batch));
+        assert_eq!(built.recv().unwrap(), None);
+        assert_eq!(second.recv().unwrap(), None);
+        assert!(!server.space().roads().has_road(road));
+        assert_eq!(money(&server), start);
+        let history = server.matter().component::<History>(id).unwrap();
+        assert_eq!(history.depth(), (0, 0));
+    }
+
+    #[test]
+    fn batches_are_undone_all_at_once() {
+        let (mut server, id) = with_player();
+        let start = money(&server);
+        let road = server.space().terrain().tile_id(at(100, 10));
+        let (batch, built) = road_and_house(id);
+        server.send(Interruption::Batch(batch));
+        let house = built.recv().unwrap().expect("no room for the house");
+        let config = config::Config::default();
+        let cost = config.cost(building::Blueprint::House)
+                 + 6 * config.road_cost;
+        assert_eq!(money(&server), start - cost);
+        let history = server.matter().component::<History>(id).unwrap();
+        assert_eq!(history.depth(), (1, 0));
+
+        // the house goes first, so the road isn't left holding it up
+        server.send(Interruption::Undo { id });
+        assert!(server.matter().get::<building::Building>(house).is_none());
+        assert!(!server.space().roads().has_road(road));
+        assert_eq!(money(&server), start);
+
+        server.send(Interruption::Redo { id });
+        assert!(server.space().roads().has_road(road));
+        assert_eq!(money(&server), start - cost);
+    }
+
+    #[test]
+    fn road_drags_are_undone_all_at_once() {
+        let (mut server, id) = with_player();
+        let start = money(&server);
+        let (reply, paved) = mpsc::channel();
+        // along the row of tiles from y = 0 to 32, six stretches long, and
+        // then down the column from x = 192 to 224, three more
+        let (from, to) = (at(0, 10), at(200, 100));
+        server.send(Interruption::road_drag(from, to, Some(id), &reply));
+        assert_eq!(paved.try_iter().collect::<Vec<_>>(), vec![true, true]);
+        let road_cost = config::Config::default().road_cost;
+        assert_eq!(money(&server), start - 9 * road_cost);
+        let corner = server.space().terrain().tile_id(at(200, 10));
+        assert!(server.space().roads().has_road(corner));
+        let history = server.matter().component::<History>(id).unwrap();
+        assert_eq!(history.depth(), (1, 0));
+
+        server.send(Interruption::Undo { id });
+        assert!(server.space().roads().is_empty());
+        assert_eq!(money(&server), start);
+    }
+}
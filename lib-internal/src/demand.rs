@@ -0,0 +1,221 @@
+use std::any;
+
+use config;
+use entities::building;
+use entities::citizen;
+use space;
+use space::zones;
+use sulphate;
+use units;
+use utilities;
+
+// the commute, in tiles across and along the grid, past which a citizen
+// gets nothing out of how close they live to work
+const LONG_COMMUTE: i64 = 40;
+
+// what happiness counts as neither drawing people in nor driving them off,
+// out of 100
+const CONTENT: i64 = 50;
+
+// people who would move into a city with no jobs in it yet, so that an
+// empty one gets going at all
+const SETTLERS: i64 = 16;
+
+// how far demand for any zone goes either way
+const MOST_DEMAND: i64 = 100;
+
+/// How happy citizen `id` is as of `now`, out of 100, or None if there is
+/// no such citizen. It is the average of how short their commute is, how
+/// much of what it needs their home gets, and how low the residential tax
+/// is next to the usual one, each out of 100. A citizen whose home isn't a
+/// building gets no utilities at all.
+pub fn satisfaction(
+    space: &space::CollisionSpace,
+    matter: &sulphate::EntityHeap,
+    now: units::Time,
+    id: sulphate::EntityId,
+) -> Option<i64> {
+    let plans = matter.get::<citizen::Citizen>(id)?.plans();
+    let terrain = space.terrain();
+    let home = terrain.tile_id(plans.home);
+    let work = terrain.tile_id(plans.work);
+    let tiles = (home.0 - work.0).abs() + (home.1 - work.1).abs();
+    let commute = 100 - 100 * tiles.min(LONG_COMMUTE) / LONG_COMMUTE;
+
+    let all = utilities::Utility::all();
+    let coverage = building::standing_on(space, now, home)
+        .and_then(|id| matter.get::<building::Building>(id))
+        .map(building::Building::coverage);
+    let covered = all.iter().filter(|&&utility| {
+        coverage.map_or(false, |coverage| utility.covered(coverage))
+    }).count() as i64;
+    let utilities = 100 * covered / all.len() as i64;
+
+    let residential = zones::Zone::Residential;
+    let tax = config::get(matter).tax(residential);
+    let usual = config::usual_tax(residential);
+    let taxes = if tax <= usual { 100 } else { 100 * usual / tax };
+
+    Some((commute + utilities + taxes) / 3)
+}
+
+/// How much the city wants more of each zone, between -100 and 100, where
+/// anything above 0 means it wants buildings of it to grow.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Demand {
+    /// The average satisfaction of every citizen, or None if nobody lives
+    /// in the city.
+    pub happiness: Option<i64>,
+    pub residential: i64,
+    pub commercial: i64,
+    pub industrial: i64,
+}
+
+impl Demand {
+    /// How much the city wants more of `zone`.
+    pub fn of(self: &Self, zone: zones::Zone) -> i64 {
+        match zone {
+            zones::Zone::Residential => self.residential,
+            zones::Zone::Commercial => self.commercial,
+            zones::Zone::Industrial => self.industrial,
+        }
+    }
+
+    /// Uses up `amount` of the demand for `zone`, as a building of that
+    /// many people growing on it does.
+    pub fn take(self: &mut Self, zone: zones::Zone, amount: i64) {
+        let demand = match zone {
+            zones::Zone::Residential => &mut self.residential,
+            zones::Zone::Commercial => &mut self.commercial,
+            zones::Zone::Industrial => &mut self.industrial,
+        };
+        *demand = (*demand - amount).max(-MOST_DEMAND);
+    }
+}
+
+fn clamp(demand: i64) -> i64 {
+    demand.max(-MOST_DEMAND).min(MOST_DEMAND)
+}
+
+/// How much the city wants more of each zone as of `now`. People move in
+/// while there are more jobs than homes, and more so the happier the
+/// citizens already here are, while half of the people living in the city
+/// want jobs in shops and half want jobs in factories, counting the people
+/// every building on a zoned tile has room for.
+pub fn demand(
+    space: &space::CollisionSpace,
+    matter: &sulphate::EntityHeap,
+    now: units::Time,
+) -> Demand {
+    let ty = any::TypeId::of::<citizen::Citizen>();
+    let (mut total, mut citizens) = (0, 0);
+    for body in space.snapshot(now) {
+        if body.uid.ty != ty {
+            continue;
+        }
+        if let Some(each) = satisfaction(space, matter, now, body.uid.id) {
+            total += each;
+            citizens += 1;
+        }
+    }
+    let happiness = if citizens > 0 {
+        Some(total / citizens)
+    } else {
+        None
+    };
+
+    let (mut residents, mut shops, mut factories) = (0, 0, 0);
+    for (tile, zone) in space.zones().tiles() {
+        let blueprint = building::standing_on(space, now, tile)
+            .and_then(|id| matter.get::<building::Building>(id))
+            .map(building::Building::blueprint);
+        let people = match blueprint {
+            Some(blueprint) => blueprint.occupants(),
+            None => continue,
+        };
+        match zone {
+            zones::Zone::Residential => residents += people,
+            zones::Zone::Commercial => shops += people,
+            zones::Zone::Industrial => factories += people,
+        }
+    }
+
+    let mood = happiness.unwrap_or(CONTENT) - CONTENT;
+    let jobs = shops + factories;
+    Demand {
+        happiness,
+        residential: clamp(SETTLERS + jobs - residents + mood / 2),
+        commercial: clamp(residents / 2 - shops),
+        industrial: clamp(residents / 2 - factories),
+    }
+}
+
+#[cfg(test)]
+mod test_demand {
+    use entities::building::Blueprint;
+    use units;
+
+    use super::*;
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    #[test]
+    fn citizens_are_happier_close_to_work_with_utilities() {
+        let mut space = space::CollisionSpace::new();
+        let mut time = sulphate::EventQueue::new(Default::default());
+        let mut matter = sulphate::EntityHeap::new();
+        let now = time.now();
+        let (near, far) = {
+            let mut settle = |home, work| {
+                let plans = citizen::Plans { home, work };
+                let citizen = citizen::Citizen::new(
+                    &mut space, &mut time, &mut matter, plans,
+                );
+                citizen.id()
+            };
+            (settle(at(0, 0), at(32, 0)), settle(at(0, 100), at(3200, 100)))
+        };
+        // a tile away, and a hundred tiles, without a building at home
+        let near = satisfaction(&space, &matter, now, near).unwrap();
+        let far = satisfaction(&space, &matter, now, far).unwrap();
+        assert_eq!(near, (98 + 0 + 100) / 3);
+        assert_eq!(far, (0 + 0 + 100) / 3);
+        let demand = demand(&space, &matter, now);
+        assert_eq!(demand.happiness, Some((near + far) / 2));
+    }
+
+    #[test]
+    fn homes_want_jobs_and_jobs_want_homes() {
+        let mut space = space::CollisionSpace::new();
+        let mut time = sulphate::EventQueue::new(Default::default());
+        let mut matter = sulphate::EntityHeap::new();
+        let now = time.now();
+        let empty = demand(&space, &matter, now);
+        assert_eq!(empty.happiness, None);
+        assert_eq!(empty.of(zones::Zone::Residential), SETTLERS);
+        assert_eq!(empty.of(zones::Zone::Commercial), 0);
+
+        let residential = Some(zones::Zone::Residential);
+        space.paint_zone(at(0, 0), at(100, 0), residential);
+        {
+            let mut place = |x| {
+                building::place(
+                    &mut space, &mut time, &mut matter, Blueprint::House,
+                    at(x, 0),
+                ).expect("no room to build");
+            };
+            for &x in &[0, 40, 80] {
+                place(x);
+            }
+        }
+        let mut demand = demand(&space, &matter, now);
+        let residents = 3 * Blueprint::House.occupants();
+        assert_eq!(demand.of(zones::Zone::Residential), SETTLERS - residents);
+        assert_eq!(demand.of(zones::Zone::Industrial), residents / 2);
+        demand.take(zones::Zone::Industrial, Blueprint::Factory.occupants());
+        assert_eq!(demand.of(zones::Zone::Industrial), 0);
+    }
+}
@@ -0,0 +1,491 @@
+use std::collections::BTreeMap;
+
+use budget;
+use config;
+use entities::building;
+use space;
+use sulphate;
+use sulphate::tick::TickScheduler;
+use units;
+use utilities;
+
+/// An amount of each of the things that a city runs on.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Resources {
+    pub power: i64,
+    pub water: i64,
+    pub money: i64,
+}
+
+impl Resources {
+    pub fn new(power: i64, water: i64, money: i64) -> Resources {
+        Resources { power, water, money }
+    }
+
+    fn plus(self: Self, other: Resources) -> Resources {
+        Resources {
+            power: self.power + other.power,
+            water: self.water + other.water,
+            money: self.money + other.money,
+        }
+    }
+
+}
+
+/// What a building makes and uses up every tick, as a component of the
+/// building.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Flow {
+    pub makes: Resources,
+    pub uses: Resources,
+}
+
+/// How the city's books stood after the last tick.
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct Balances {
+    pub money: i64,
+    /// The power and water made over the last tick, and the money taken in.
+    pub supply: Resources,
+    /// What buildings asked for over the last tick, whether they got it or
+    /// not.
+    pub demand: Resources,
+    /// How many buildings went without.
+    pub blackouts: u64,
+}
+
+/// The city-wide books, kept in the heap as the only entity of its type.
+pub struct Ledger {
+    balances: Balances,
+}
+
+// there is only ever one ledger, and ids only clash within a type
+const LEDGER: sulphate::EntityId = 0;
+
+pub fn starting_money() -> i64 {
+    1000
+}
+
+fn opening_balances() -> Balances {
+    Balances { money: starting_money(), ..Default::default() }
+}
+
+impl Ledger {
+    pub fn balances(self: &Self) -> Balances {
+        self.balances
+    }
+
+    /// For putting back a saved city, whose power and water get worked out
+    /// again on the next tick.
+    pub fn set_money(self: &mut Self, money: i64) {
+        self.balances.money = money;
+    }
+}
+
+/// The ledger, opening it and starting the economy ticking if nothing has
+/// needed it yet.
+pub fn open<'a>(
+    time: &mut sulphate::EventQueue,
+    matter: &'a mut sulphate::EntityHeap,
+) -> &'a mut Ledger {
+    if matter.get::<Ledger>(LEDGER).is_none() {
+        let balances = opening_balances();
+        matter.insert(LEDGER, Ledger { balances });
+        time.every_tunable(config::economy_tick, matter, tick);
+        time.every_tunable(config::tax_tick, matter, collect_taxes);
+    }
+    matter.get_mut::<Ledger>(LEDGER).expect("Ledger wasn't opened")
+}
+
+/// Takes `money` out of the city's books, for something a player has
+/// paid for, which the city can go into debt over.
+pub fn charge(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    category: budget::Category,
+    money: i64,
+) {
+    open(time, matter).balances.money -= money;
+    budget::record(time.now(), matter, category, -money);
+}
+
+/// Puts `money` back in the city's books, for something that was paid for
+/// and then taken back.
+pub fn refund(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    category: budget::Category,
+    money: i64,
+) {
+    open(time, matter).balances.money += money;
+    budget::record(time.now(), matter, category, money);
+}
+
+/// The books as of the last tick, or as they would start out if the city
+/// has no economy yet.
+pub fn balances(matter: &sulphate::EntityHeap) -> Balances {
+    matter.get::<Ledger>(LEDGER)
+          .map_or_else(opening_balances, Ledger::balances)
+}
+
+// a building's flow, and which network of each utility it is on, as
+// numbered by `utilities::membership`
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Hookup {
+    id: sulphate::EntityId,
+    flow: Flow,
+    power: usize,
+    water: usize,
+}
+
+// power and water can't be kept from one tick to the next, so each network
+// shares out what its own buildings make first come first served, and
+// whoever misses out goes without, along with which it missed. money all
+// comes out of the one purse. buildings that need neither power nor water
+// go first, since they might be what makes them
+fn settle(
+    money: i64,
+    hookups: &[Hookup],
+) -> (Balances, Vec<(sulphate::EntityId, building::Coverage)>) {
+    let mut money = money;
+    let mut power: BTreeMap<usize, i64> = BTreeMap::new();
+    let mut water: BTreeMap<usize, i64> = BTreeMap::new();
+    let mut supply = Resources::default();
+    let mut demand = Resources::default();
+    let mut going_without = Vec::new();
+    let (first, rest): (Vec<&Hookup>, Vec<&Hookup>) = hookups
+        .iter()
+        .partition(|hookup| {
+            hookup.flow.uses.power == 0 && hookup.flow.uses.water == 0
+        });
+    for hookup in first.into_iter().chain(rest) {
+        let Flow { makes, uses } = hookup.flow;
+        demand = demand.plus(uses);
+        let coverage = building::Coverage {
+            power: *power.get(&hookup.power).unwrap_or(&0) >= uses.power,
+            water: *water.get(&hookup.water).unwrap_or(&0) >= uses.water,
+        };
+        if coverage.power && coverage.water && money >= uses.money {
+            *power.entry(hookup.power).or_insert(0) +=
+                makes.power - uses.power;
+            *water.entry(hookup.water).or_insert(0) +=
+                makes.water - uses.water;
+            money += makes.money - uses.money;
+            supply = supply.plus(makes);
+        } else {
+            going_without.push((hookup.id, coverage));
+        }
+    }
+    let blackouts = going_without.len() as u64;
+    let balances = Balances { money, supply, demand, blackouts };
+    (balances, going_without)
+}
+
+// power goes along the roads and water along the pipes, so each building
+// is hooked up to a network of each before they are all settled together.
+// the networks are found at once, but settling them takes turns, in id
+// order, since they all pay out of the one purse
+fn distribute(
+    space: &space::CollisionSpace,
+    now: units::Time,
+    matter: &mut sulphate::EntityHeap,
+    money: i64,
+) -> (Balances, Vec<(sulphate::EntityId, building::Coverage)>) {
+    let grids = utilities::every_network(space, matter, now);
+    let power = utilities::membership(&grids[0]);
+    let water = utilities::membership(&grids[1]);
+    let hookups: Vec<Hookup> = matter
+        .query::<(&Flow,)>()
+        .into_iter()
+        .filter_map(|(id, (flow,))| -> Option<_> {
+            let power = *power.get(&id)?;
+            let water = *water.get(&id)?;
+            Some(Hookup { id, flow: *flow, power, water })
+        })
+        .collect();
+    settle(money, &hookups)
+}
+
+// a building that goes without makes nothing, and pays no taxes, until it
+// is supplied again
+fn black_out(
+    matter: &mut sulphate::EntityHeap,
+    going_without: &[(sulphate::EntityId, building::Coverage)],
+) {
+    let ids: Vec<sulphate::EntityId> = matter
+        .query::<(&Flow,)>()
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    for id in ids {
+        let missed = going_without.iter().find(|&&(each, _)| each == id);
+        let coverage = missed.map_or_else(
+            building::Coverage::full,
+            |&(_, coverage)| coverage,
+        );
+        if let Some(building) = matter.get_mut::<building::Building>(id) {
+            building.set_blackout(missed.is_some());
+            building.set_coverage(coverage);
+        }
+    }
+}
+
+/// Shares power and water out again over the grid as it is joined up now,
+/// for when a road or building comes or goes between ticks. Nothing is
+/// paid or made; that waits for the tick.
+pub fn resupply(
+    space: &space::CollisionSpace,
+    time: &sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+) {
+    let money = balances(matter).money;
+    let (_, going_without) = distribute(space, time.now(), matter, money);
+    black_out(matter, &going_without);
+}
+
+fn tick(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+) -> bool {
+    let money = balances(matter).money;
+    let now = time.now();
+    let (mut balances, going_without) =
+        distribute(space, now, matter, money);
+    // roads are kept up whether or not there is the money for it, which can
+    // leave the city owing
+    let road_tiles = space.roads().tile_count() as i64;
+    let upkeep = config::get(matter).road_upkeep * road_tiles;
+    let earned = balances.supply.money;
+    let spent = money + earned - balances.money;
+    balances.money -= upkeep;
+    balances.demand.money += upkeep;
+    budget::record(now, matter, budget::Category::Running, earned);
+    budget::record(now, matter, budget::Category::Running, -spent);
+    budget::record(now, matter, budget::Category::Upkeep, -upkeep);
+    black_out(matter, &going_without);
+    if let Some(ledger) = matter.get_mut::<Ledger>(LEDGER) {
+        ledger.balances = balances;
+    }
+    true
+}
+
+// buildings on zoned tiles pay tax for each person living or working in
+// them, which is nobody while they go without power or water
+fn collect_taxes(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+) -> bool {
+    let config = config::get(matter);
+    let now = time.now();
+    let mut taxes = 0;
+    for (tile, zone) in space.zones().tiles() {
+        let id = match building::standing_on(space, now, tile) {
+            Some(id) => id,
+            None => continue,
+        };
+        match matter.get::<building::Building>(id) {
+            Some(building) if !building.blackout() => {
+                taxes += config.tax(zone) * building.blueprint().occupants();
+            },
+            _ => (),
+        }
+    }
+    open(time, matter).balances.money += taxes;
+    budget::record(now, matter, budget::Category::Taxes, taxes);
+    true
+}
+
+#[cfg(test)]
+mod test_economy {
+    use std::sync::mpsc;
+    use std::time;
+
+    use entities::building::Blueprint;
+    use sulphate::server;
+    use units;
+
+    use super::*;
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    #[test]
+    fn power_goes_to_whoever_asks_first() {
+        let config = config::Config::default();
+        let plant = (3, config.flow(Blueprint::PowerPlant));
+        let tower = (4, config.flow(Blueprint::WaterTower));
+        // houses before the plant in id order, to show that it goes first
+        let mut flows: Vec<_> = (5..13).map(|id| {
+            (id, config.flow(Blueprint::House))
+        }).collect();
+        flows.push(plant);
+        flows.push(tower);
+        // all on the one network
+        let hookups: Vec<Hookup> = flows
+            .iter()
+            .map(|&(id, flow)| Hookup { id, flow, power: 0, water: 0 })
+            .collect();
+
+        let (balances, going_without) = settle(100, &hookups);
+        // 12 power lasts six houses, and the last two go without
+        let dark = building::Coverage { power: false, water: true };
+        assert_eq!(going_without, vec![(11, dark), (12, dark)]);
+        assert_eq!(balances.blackouts, 2);
+        assert_eq!(balances.supply, Resources::new(12, 8, 12));
+        assert_eq!(balances.demand, Resources::new(16, 8, 5));
+        assert_eq!(balances.money, 100 - 5 + 12);
+
+        // a plant that can't be paid for makes nothing
+        let (balances, going_without) = settle(0, &hookups);
+        assert_eq!(going_without.len(), flows.len());
+        assert_eq!(balances.money, 0);
+    }
+
+    #[test]
+    fn water_stays_in_its_own_pipes() {
+        let config = config::Config::default();
+        let hookup = |id, blueprint, water| Hookup {
+            id,
+            flow: config.flow(blueprint),
+            power: 0,
+            water,
+        };
+        // one power network, but the last house is on pipes of its own
+        let hookups = [
+            hookup(1, Blueprint::PowerPlant, 0),
+            hookup(2, Blueprint::WaterTower, 0),
+            hookup(3, Blueprint::House, 0),
+            hookup(4, Blueprint::House, 1),
+        ];
+        let (balances, going_without) = settle(100, &hookups);
+        let dry = building::Coverage { power: true, water: false };
+        assert_eq!(going_without, vec![(4, dry)]);
+        assert_eq!(balances.supply.water, 8);
+        assert_eq!(balances.demand.water, 2);
+    }
+
+    fn query_ledger(upd: &mpsc::Sender<server::Interruption>) -> Balances {
+        let (reply, recv) = mpsc::channel();
+        upd.send(server::Interruption::QueryLedger { reply }).unwrap();
+        recv.recv_timeout(time::Duration::from_secs(1))
+            .expect("server didn't reply")
+    }
+
+    #[test]
+    fn houses_go_dark_without_power() {
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, mut clock, handle, ()) =
+            server::start_server_with_clock(|space, time, matter| {
+                // along the row of tiles from y = 32 to 64, beside all
+                // three buildings, and a pipe under them
+                space.build_road(at(0, 40), at(200, 40));
+                space.lay_pipe(at(0, 0), at(200, 0));
+                building::place(
+                    space, time, matter, Blueprint::House, at(0, 0),
+                );
+                building::place(
+                    space, time, matter, Blueprint::WaterTower, at(100, 0),
+                );
+            }, clock);
+
+        // just past each tick, so that it has certainly happened
+        let step = config::Config::default().economy_tick
+            + units::moments(1);
+        let upkeep = config::Config::default().road_upkeep * 7;
+        clock.advance(step);
+        let balances = query_ledger(&upd);
+        assert_eq!(balances.blackouts, 1);
+        assert_eq!(balances.money, starting_money() - 2 - upkeep);
+
+        let (reply, _) = mpsc::channel();
+        let blueprint = Blueprint::PowerPlant;
+        let position = at(200, 0);
+        let place = server::Interruption::PlaceBuilding {
+            blueprint: blueprint.into(),
+            position,
+            by: None,
+            reply,
+        };
+        upd.send(place).unwrap();
+        clock.advance(step);
+        let balances = query_ledger(&upd);
+        assert_eq!(balances.blackouts, 0);
+        let spent = 2 + 5 - 2 + 2 * upkeep;
+        assert_eq!(balances.money, starting_money() - spent);
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn zoned_buildings_pay_taxes() {
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, mut clock, handle, ()) =
+            server::start_server_with_clock(|space, time, matter| {
+                let residential = Some(space::zones::Zone::Residential);
+                space.paint_zone(at(0, 0), at(0, 0), residential);
+                space.build_road(at(0, 40), at(200, 40));
+                space.lay_pipe(at(0, 0), at(200, 0));
+                building::place(
+                    space, time, matter, Blueprint::House, at(0, 0),
+                );
+                // and one that isn't zoned, which pays nothing
+                building::place(
+                    space, time, matter, Blueprint::House, at(0, 100),
+                );
+                building::place(
+                    space, time, matter, Blueprint::PowerPlant, at(100, 0),
+                );
+                building::place(
+                    space, time, matter, Blueprint::WaterTower, at(200, 0),
+                );
+            }, clock);
+
+        let config = config::Config::default();
+        clock.advance(config.tax_tick + units::moments(1));
+        let (reply, recv) = mpsc::channel();
+        upd.send(server::Interruption::QueryBudget { days: 1, reply })
+           .unwrap();
+        let report = recv.recv_timeout(time::Duration::from_secs(1))
+                         .expect("server didn't reply");
+        let taxes = report.lines[0];
+        assert_eq!(taxes.category, budget::Category::Taxes);
+        let residential = space::zones::Zone::Residential;
+        let each = config.tax(residential) * Blueprint::House.occupants();
+        assert_eq!(taxes.income, each);
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn roads_cost_upkeep_every_tick() {
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, mut clock, handle, ()) =
+            server::start_server_with_clock(|space, time, matter| {
+                // seven tiles along the row from y = 0 to 32
+                space.build_road(at(0, 10), at(200, 10));
+                open(time, matter);
+            }, clock);
+
+        let step = config::Config::default().economy_tick
+            + units::moments(1);
+        let upkeep = config::Config::default().road_upkeep * 7;
+        clock.advance(step);
+        let balances = query_ledger(&upd);
+        assert_eq!(balances.money, starting_money() - upkeep);
+        assert_eq!(balances.demand.money, upkeep);
+        clock.advance(step);
+        let balances = query_ledger(&upd);
+        assert_eq!(balances.money, starting_money() - 2 * upkeep);
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+}
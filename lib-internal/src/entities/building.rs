@@ -0,0 +1,448 @@
+use std::any;
+
+use config;
+use economy;
+use entities;
+use fire;
+use scripting;
+use space;
+use space::sounds;
+use sulphate;
+use sulphate::Scheduler;
+use units;
+
+/// Something built on a tile of the city, which takes up the whole tile
+/// for as long as it stands.
+pub struct Building {
+    blueprint: Blueprint,
+    // whether it went without power, water or upkeep on the last tick
+    blackout: bool,
+    coverage: Coverage,
+    // when it burns down, if it is on fire
+    fire: Option<sulphate::EventHandle>,
+}
+
+/// Which of the things carried to it over a grid a building got the last
+/// time they were shared out, counting whatever it needs none of as got.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Coverage {
+    pub power: bool,
+    pub water: bool,
+}
+
+impl Coverage {
+    pub fn full() -> Coverage {
+        Coverage { power: true, water: true }
+    }
+}
+
+/// The kinds of building that can be put up.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Blueprint {
+    House,
+    Shop,
+    PowerPlant,
+    WaterTower,
+    Factory,
+    FireStation,
+}
+
+impl Blueprint {
+    pub fn all() -> [Blueprint; 6] {
+        [
+            Blueprint::House,
+            Blueprint::Shop,
+            Blueprint::PowerPlant,
+            Blueprint::WaterTower,
+            Blueprint::Factory,
+            Blueprint::FireStation,
+        ]
+    }
+
+    pub fn name(self: Self) -> &'static str {
+        match self {
+            Blueprint::House => "house",
+            Blueprint::Shop => "shop",
+            Blueprint::PowerPlant => "power plant",
+            Blueprint::WaterTower => "water tower",
+            Blueprint::Factory => "factory",
+            Blueprint::FireStation => "fire station",
+        }
+    }
+
+    // kept apart from the order of `all`, since it is written to saves
+    pub fn tag(self: Self) -> u8 {
+        match self {
+            Blueprint::House => 0,
+            Blueprint::Shop => 1,
+            Blueprint::PowerPlant => 2,
+            Blueprint::WaterTower => 3,
+            Blueprint::Factory => 4,
+            Blueprint::FireStation => 5,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Blueprint> {
+        match tag {
+            0 => Some(Blueprint::House),
+            1 => Some(Blueprint::Shop),
+            2 => Some(Blueprint::PowerPlant),
+            3 => Some(Blueprint::WaterTower),
+            4 => Some(Blueprint::Factory),
+            5 => Some(Blueprint::FireStation),
+            _ => None,
+        }
+    }
+
+    /// Whether it has to be built beside a road, to have things delivered,
+    /// or to send fire engines out.
+    pub fn needs_road(self: Self) -> bool {
+        match self {
+            Blueprint::Shop | Blueprint::Factory => true,
+            Blueprint::FireStation => true,
+            _ => false,
+        }
+    }
+
+    /// How many people live or work in it, and pay tax for it, while it
+    /// stands on a tile zoned for them.
+    pub fn occupants(self: Self) -> i64 {
+        match self {
+            Blueprint::House => 4,
+            Blueprint::Shop => 3,
+            Blueprint::Factory => 6,
+            _ => 0,
+        }
+    }
+
+    /// How much of its tile it covers, which has to fit inside the tile.
+    pub fn radius(self: Self) -> units::Distance {
+        match self {
+            Blueprint::House => 12.into(),
+            Blueprint::Shop => 15.into(),
+            Blueprint::PowerPlant => 15.into(),
+            Blueprint::WaterTower => 10.into(),
+            Blueprint::Factory => 15.into(),
+            Blueprint::FireStation => 14.into(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone)]
+pub struct Image {
+    pub blueprint: Blueprint,
+    pub blackout: bool,
+    pub coverage: Coverage,
+    pub burning: bool,
+}
+
+impl entities::Display for Building {
+    fn image(self: &Self) -> Option<entities::Image> {
+        let blueprint = self.blueprint;
+        let blackout = self.blackout;
+        let coverage = self.coverage;
+        let burning = self.burning();
+        let image = Image { blueprint, blackout, coverage, burning };
+        Some(entities::Image::Building(image))
+    }
+}
+
+impl Building {
+    pub fn blueprint(self: &Self) -> Blueprint {
+        self.blueprint
+    }
+
+    pub fn blackout(self: &Self) -> bool {
+        self.blackout
+    }
+
+    /// Set by the economy each tick, and whenever the grid changes.
+    pub fn set_blackout(self: &mut Self, blackout: bool) {
+        self.blackout = blackout;
+    }
+
+    pub fn coverage(self: &Self) -> Coverage {
+        self.coverage
+    }
+
+    /// Set along with `set_blackout`.
+    pub fn set_coverage(self: &mut Self, coverage: Coverage) {
+        self.coverage = coverage;
+    }
+
+    pub fn burning(self: &Self) -> bool {
+        self.fire.is_some()
+    }
+
+    /// The event that burns it down, if it is on fire.
+    pub fn spreading(self: &Self) -> Option<&sulphate::EventHandle> {
+        self.fire.as_ref()
+    }
+
+    /// Set by `fire::ignite`, with the event that burns it down.
+    pub fn catch_fire(self: &mut Self, spreading: sulphate::EventHandle) {
+        self.fire = Some(spreading);
+    }
+
+    /// Stops it burning, giving back the event that would have burnt it
+    /// down, if it was on fire.
+    pub fn put_out(self: &mut Self) -> Option<sulphate::EventHandle> {
+        self.fire.take()
+    }
+
+    /// Builds on the tile at `position`, without checking that there is
+    /// room; `place` is for that.
+    pub fn new<'a>(
+        space: &'a mut space::CollisionSpace,
+        time: &'a mut sulphate::EventQueue,
+        matter: &'a mut sulphate::EntityHeap,
+        blueprint: Blueprint,
+        position: units::Position,
+    ) -> space::Entry<'a, Building> {
+        let id = matter.add(Building::standing(blueprint));
+        Building::stand(space, time, matter, id, blueprint, position)
+    }
+
+    pub fn restore<'a>(
+        space: &'a mut space::CollisionSpace,
+        time: &'a mut sulphate::EventQueue,
+        matter: &'a mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+        blueprint: Blueprint,
+        position: units::Position,
+    ) -> space::Entry<'a, Building> {
+        matter.insert(id, Building::standing(blueprint));
+        Building::stand(space, time, matter, id, blueprint, position)
+    }
+
+    fn standing(blueprint: Blueprint) -> Building {
+        Building {
+            blueprint,
+            blackout: false,
+            coverage: Coverage::full(),
+            fire: None,
+        }
+    }
+
+    // the tile is made solid so that paths go around it, the body is there
+    // so that it gets seen, the flow is what the economy ticks over, and
+    // anything standing can catch fire
+    fn stand<'a>(
+        space: &'a mut space::CollisionSpace,
+        time: &'a mut sulphate::EventQueue,
+        matter: &'a mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+        blueprint: Blueprint,
+        position: units::Position,
+    ) -> space::Entry<'a, Building> {
+        let flow = config::get(matter).flow(blueprint);
+        matter.insert_component(id, flow);
+        economy::open(time, matter);
+        fire::start(time, matter);
+        let tile = space.terrain().tile_id(position);
+        space.set_tile(time, tile, space::Tile::Building);
+        let mut this = space.entry::<Building>(time, matter, id);
+        this.body = Some(space::Body::new_frozen(position));
+        this
+    }
+
+    pub fn remove(
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+    ) -> Option<Building> {
+        let fire = matter.get_mut::<Building>(id).and_then(Building::put_out);
+        if let Some(fire) = fire {
+            time.cancel(&fire);
+        }
+        let ty = any::TypeId::of::<Building>();
+        let uid = sulphate::EntityUId { id, ty };
+        if let Some(position) = space.position(uid, time.now()) {
+            let tile = space.terrain().tile_id(position);
+            space.set_tile(time, tile, space::Tile::Open);
+            sounds::play(time, sounds::Sound::Demolition, position);
+        }
+        {
+            let mut this: space::Entry<Building> =
+                space.entry(time, matter, id);
+            this.body = None;
+        }
+        let removed = matter.remove(id);
+        economy::resupply(space, time, matter);
+        removed
+    }
+}
+
+impl entities::Interact for Building {
+    fn on_interact(
+        _space: &mut space::CollisionSpace,
+        _time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+        _player: sulphate::EntityId,
+    ) -> Option<entities::Interaction> {
+        matter.get::<Building>(id).map(|building| {
+            entities::Interaction::Menu {
+                blueprint: building.blueprint,
+                blackout: building.blackout,
+            }
+        })
+    }
+}
+
+impl space::Collide for Building {
+    // buildings don't move, whatever runs into them
+    fn collide(
+        _this: space::Entry<Building>,
+        _other: space::Image,
+    ) {}
+
+    fn release(
+        _this: space::Entry<Building>,
+        _other: space::Image,
+    ) {}
+
+    fn disappear(
+        _this: space::Entry<Building>,
+        _other: space::Image,
+    ) {}
+}
+
+/// The middle of the tile that `position` is on, which is where a building
+/// put there would stand.
+pub fn snap(position: units::Position) -> units::Position {
+    space::tile_center(position)
+}
+
+/// Builds on the tile under `position`, unless the terrain, a road or some
+/// body is in the way, or it needs a road and there isn't one beside it,
+/// returning the new building if it could be built. The grid is shared
+/// out again straight away, rather than waiting for the next tick.
+pub fn place(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    blueprint: Blueprint,
+    position: units::Position,
+) -> Option<sulphate::EntityId> {
+    let position = snap(position);
+    if space.blocked(time.now(), position, blueprint.radius()) {
+        return None;
+    }
+    {
+        let tile = space.terrain().tile_id(position);
+        let roads = space.roads();
+        if roads.has_road(tile) {
+            return None;
+        }
+        if blueprint.needs_road() && roads.access(tile).is_empty() {
+            return None;
+        }
+    }
+    let id = Building::new(space, time, matter, blueprint, position).id();
+    sounds::play(time, sounds::Sound::Construction, position);
+    economy::resupply(space, time, matter);
+    let tile = space.terrain().tile_id(position);
+    scripting::building_placed(time, matter, id, blueprint, tile);
+    Some(id)
+}
+
+/// The building standing on tile `id`, if there is one.
+pub fn standing_on(
+    space: &space::CollisionSpace,
+    now: units::Time,
+    id: space::TileId,
+) -> Option<sulphate::EntityId> {
+    if space.terrain().tile(id) != space::Tile::Building {
+        return None;
+    }
+    let center = space.terrain().center(id);
+    let circle = units::Circle { center, radius: 1.into() };
+    let ty = any::TypeId::of::<Building>();
+    space.query_circle(now, circle)
+         .into_iter()
+         .find(|uid| uid.ty == ty)
+         .map(|uid| uid.id)
+}
+
+#[cfg(test)]
+mod test_building {
+    use std::sync::mpsc;
+
+    use space;
+    use sulphate;
+    use sulphate::server;
+    use units;
+
+    use super::*;
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    fn place_at(
+        upd: &mpsc::Sender<server::Interruption>,
+        blueprint: Blueprint,
+        position: units::Position,
+    ) -> Option<sulphate::EntityId> {
+        let (reply, recv) = mpsc::channel();
+        let place = server::Interruption::PlaceBuilding {
+            blueprint: blueprint.into(),
+            position,
+            by: None,
+            reply,
+        };
+        upd.send(place).unwrap();
+        recv.recv().expect("server didn't reply to placement")
+    }
+
+    #[test]
+    fn buildings_need_room() {
+        let (upd, _clock, handle, ()) =
+            server::start_server(|space, time, _matter| {
+                space.set_tile(time, (5, 0), space::Tile::Water);
+            });
+
+        // anywhere on the tile stands in the middle of it
+        let id = place_at(&upd, Blueprint::House, at(100, 10))
+            .expect("couldn't build on an open tile");
+        let (reply, recv) = mpsc::channel();
+        upd.send(server::Interruption::Inspect { id, reply }).unwrap();
+        let info = recv.recv()
+                       .expect("server didn't reply")
+                       .expect("building went missing");
+        assert!(info.summary.position == at(112, 16));
+        match info.details {
+            sulphate::Details::Building { blueprint, .. } => {
+                assert_eq!(blueprint, Blueprint::House);
+            },
+            _ => panic!("building inspected as something else"),
+        }
+
+        assert!(place_at(&upd, Blueprint::House, at(120, 20)).is_none());
+        assert!(place_at(&upd, Blueprint::House, at(170, 10)).is_none());
+        assert!(place_at(&upd, Blueprint::House, at(200, 10)).is_some());
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn shops_need_a_road() {
+        let (upd, _clock, handle, ()) =
+            server::start_server(|space, _time, _matter| {
+                space.build_road(at(0, 40), at(100, 40));
+            });
+
+        // the road runs along the row of tiles from y = 32 to 64
+        assert!(place_at(&upd, Blueprint::Shop, at(40, 120)).is_none());
+        assert!(place_at(&upd, Blueprint::Shop, at(40, 50)).is_none());
+        assert!(place_at(&upd, Blueprint::Shop, at(40, 80)).is_some());
+        assert!(place_at(&upd, Blueprint::House, at(40, 120)).is_some());
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+}
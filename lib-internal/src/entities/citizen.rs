@@ -0,0 +1,511 @@
+use std::any;
+use std::cmp;
+use std::collections;
+
+use rand;
+use rand::Rng;
+use rand::SeedableRng;
+
+use config;
+use entities;
+use space;
+use sulphate;
+use sulphate::Scheduler;
+use units;
+use world::calendar;
+
+/// Someone who lives in the city, walking to work every morning and back
+/// home every evening.
+pub struct Citizen {
+    plans: Plans,
+    // waypoints left on the way to wherever it is going, the next one last
+    route: Vec<units::Position>,
+    // the next check on how far along the route it has got
+    walking: Option<sulphate::EventHandle>,
+}
+
+/// Where a citizen spends its day.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Plans {
+    pub home: units::Position,
+    pub work: units::Position,
+}
+
+#[derive(PartialEq, Eq, Clone)]
+pub struct Image;
+
+impl entities::Display for Citizen {
+    fn image(self: &Self) -> Option<entities::Image> {
+        Some(entities::Image::Citizen(Image))
+    }
+}
+
+// the hours that citizens set off at
+const LEAVE_HOME: i32 = 8;
+const LEAVE_WORK: i32 = 17;
+
+// close enough to a waypoint to count as being there
+fn arrival_distance() -> f64 {
+    4.0
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Trip {
+    ToWork,
+    ToHome,
+}
+
+fn uid(id: sulphate::EntityId) -> sulphate::EntityUId {
+    let ty = any::TypeId::of::<Citizen>();
+    sulphate::EntityUId { id, ty }
+}
+
+// whichever trip comes next in the day after `now`
+fn next_trip(now: units::Time) -> (Trip, units::Time) {
+    let to_work = calendar::next_time_of_day(now, calendar::hours(LEAVE_HOME));
+    let to_home = calendar::next_time_of_day(now, calendar::hours(LEAVE_WORK));
+    if to_work < to_home {
+        (Trip::ToWork, to_work)
+    } else {
+        (Trip::ToHome, to_home)
+    }
+}
+
+/// Citizens due to set off at the same time, who look for their ways there
+/// all at once, kept in the heap as the only entity of its type, like the
+/// ledger.
+pub struct Departures {
+    due: collections::BTreeMap<units::Time, Vec<(sulphate::EntityId, Trip)>>,
+}
+
+const DEPARTURES: sulphate::EntityId = 0;
+
+// joins whoever else is setting off at the same time, which is everyone
+// who has been in the city since the last trip
+fn plan_day(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) {
+    let (trip, at) = next_trip(time.now());
+    if matter.get::<Departures>(DEPARTURES).is_none() {
+        let due = collections::BTreeMap::new();
+        matter.insert(DEPARTURES, Departures { due });
+    }
+    let departures = matter.get_mut::<Departures>(DEPARTURES)
+                           .expect("Departures weren't started");
+    if !departures.due.contains_key(&at) {
+        time.enqueue_absolute(DepartEvent { at }, at);
+    }
+    departures.due.entry(at).or_insert_with(Vec::new).push((id, trip));
+}
+
+struct DepartEvent {
+    at: units::Time,
+}
+
+// finding the way is what takes the time, so every route is searched for
+// at once, and then each citizen sets off along its own in id order
+impl sulphate::Event for DepartEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        let mut due = matter
+            .get_mut::<Departures>(DEPARTURES)
+            .and_then(|departures| departures.due.remove(&self.at))
+            .unwrap_or_else(Vec::new);
+        due.sort_by_key(|&(id, _)| id);
+        let now = time.now();
+        let mut leaving = Vec::new();
+        let mut trips = Vec::new();
+        for (id, trip) in due {
+            let destination = match matter.get::<Citizen>(id) {
+                Some(citizen) => match trip {
+                    Trip::ToWork => citizen.plans.work,
+                    Trip::ToHome => citizen.plans.home,
+                },
+                // moved out since this was planned
+                None => continue,
+            };
+            plan_day(time, matter, id);
+            if let Some(from) = space.position(uid(id), now) {
+                leaving.push(id);
+                trips.push((from, destination));
+            }
+        }
+        let routes = space.find_paths(&trips);
+        for (id, route) in leaving.into_iter().zip(routes) {
+            set_off(space, time, matter, id, route);
+        }
+    }
+}
+
+struct WalkEvent {
+    id: sulphate::EntityId,
+}
+
+impl sulphate::Event for WalkEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        walk(space, time, matter, self.id);
+    }
+}
+
+fn set_off(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    route: Option<Vec<units::Position>>,
+) {
+    let mut route = route.unwrap_or_else(|| {
+        warn!("Citizen {} has no way to get where it is going", id);
+        Vec::new()
+    });
+    route.reverse();
+    if let Some(citizen) = matter.get_mut::<Citizen>(id) {
+        citizen.route = route;
+        if let Some(old) = citizen.walking.take() {
+            time.cancel(&old);
+        }
+    }
+    walk(space, time, matter, id);
+}
+
+fn separation(from: units::Position, to: units::Position) -> [f64; 2] {
+    let offset = to - from;
+    [offset.x.into(), offset.y.into()]
+}
+
+fn distance(offset: [f64; 2]) -> f64 {
+    (offset[0] * offset[0] + offset[1] * offset[1]).sqrt()
+}
+
+// heads for the next waypoint, or stops if there is none, and checks back
+// once it should have got there, since it may have been pushed off course
+fn walk(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) {
+    let now = time.now();
+    let position = match space.position(uid(id), now) {
+        Some(position) => position,
+        None => return,
+    };
+    let next = match matter.get_mut::<Citizen>(id) {
+        Some(citizen) => {
+            while citizen.route.last().map_or(false, |&next| {
+                distance(separation(position, next)) <= arrival_distance()
+            }) {
+                citizen.route.pop();
+            }
+            citizen.route.last().cloned()
+        },
+        None => return,
+    };
+
+    let speed: f64 = config::get(matter).citizen_speed.into();
+    let (velocity, delay) = match next {
+        Some(next) => {
+            let offset = separation(position, next);
+            let length = distance(offset);
+            let velocity = units::Velocity {
+                x: (offset[0] / length * speed).into(),
+                y: (offset[1] / length * speed).into(),
+            };
+            let delay: units::Duration = (length / speed).into();
+            (velocity, Some(delay))
+        },
+        None => (units::Velocity::default(), None),
+    };
+
+    {
+        let mut this: space::Entry<Citizen> = space.entry(time, matter, id);
+        if let Some(body) = this.body.as_mut() {
+            body.bounce(velocity, now);
+        }
+    }
+
+    if let Some(delay) = delay {
+        let handle = time.schedule_relative(WalkEvent { id }, delay);
+        if let Some(citizen) = matter.get_mut::<Citizen>(id) {
+            citizen.walking = Some(handle);
+        }
+    }
+}
+
+impl Citizen {
+    pub fn plans(self: &Self) -> Plans {
+        self.plans
+    }
+
+    /// A citizen at home, who sets off for work when the day starts.
+    pub fn new<'a>(
+        space: &'a mut space::CollisionSpace,
+        time: &'a mut sulphate::EventQueue,
+        matter: &'a mut sulphate::EntityHeap,
+        plans: Plans,
+    ) -> space::Entry<'a, Citizen> {
+        let citizen = Citizen { plans, route: Vec::new(), walking: None };
+        let id = matter.add(citizen);
+        plan_day(time, matter, id);
+        let mut this = space.entry::<Citizen>(time, matter, id);
+        this.body = Some(space::Body::new_frozen(plans.home));
+        this
+    }
+
+    /// Puts a citizen back as it was saved, going along `body` with
+    /// `route` left to go until `due`, when it looks at where it has got
+    /// to, or standing still until its next trip if it wasn't on its way.
+    pub fn restore<'a>(
+        space: &'a mut space::CollisionSpace,
+        time: &'a mut sulphate::EventQueue,
+        matter: &'a mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+        body: space::Body,
+        plans: Plans,
+        route: Vec<units::Position>,
+        due: Option<units::Time>,
+    ) -> space::Entry<'a, Citizen> {
+        let walking = due.map(|due| {
+            let due = cmp::max(due, time.now());
+            time.schedule_absolute(WalkEvent { id }, due)
+        });
+        let citizen = Citizen { plans, route, walking };
+        matter.insert(id, citizen);
+        plan_day(time, matter, id);
+        let mut this = space.entry::<Citizen>(time, matter, id);
+        this.body = Some(body);
+        this
+    }
+
+    /// The waypoints it has left to go, the next one last.
+    pub fn route(self: &Self) -> &[units::Position] {
+        &self.route
+    }
+
+    /// When it next looks at where it has got to, if it is on its way.
+    pub fn due(self: &Self) -> Option<units::Time> {
+        self.walking.as_ref().and_then(sulphate::EventHandle::due)
+    }
+
+    // its daily trips check that it is still around before doing anything
+    pub fn remove(
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+    ) -> Option<Citizen> {
+        let walking = matter.get_mut::<Citizen>(id)
+                            .and_then(|citizen| citizen.walking.take());
+        if let Some(walking) = walking {
+            time.cancel(&walking);
+        }
+        {
+            let mut this: space::Entry<Citizen> =
+                space.entry(time, matter, id);
+            this.body = None;
+        }
+        matter.remove(id)
+    }
+}
+
+impl entities::Interact for Citizen {
+    // they only ever talk about where they are off to
+    fn on_interact(
+        _space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+        _player: sulphate::EntityId,
+    ) -> Option<entities::Interaction> {
+        let citizen = matter.get::<Citizen>(id)?;
+        let line = if citizen.route.is_empty() {
+            "Lovely day for it."
+        } else {
+            // the trip after this one
+            match next_trip(time.now()).0 {
+                Trip::ToHome => "Can't stop, I'll be late for work!",
+                Trip::ToWork => "Off home, it's been a long day.",
+            }
+        };
+        let line = line.to_string();
+        Some(entities::Interaction::Talk { line })
+    }
+}
+
+impl space::Collide for Citizen {
+    // it waits where it is until its next check, when it tries again,
+    // unless it only walked over something
+    fn collide(
+        mut this: space::Entry<Citizen>,
+        other: space::Image,
+    ) {
+        if !other.inner_image.is_solid() {
+            return;
+        }
+        let now = this.now();
+
+        let body = this.body.as_mut().expect("Collided without a body");
+        body.bounce(units::Velocity::default(), now);
+    }
+
+    fn release(
+        _this: space::Entry<Citizen>,
+        _other: space::Image,
+    ) {}
+
+    fn disappear(
+        _this: space::Entry<Citizen>,
+        _other: space::Image,
+    ) {}
+}
+
+// how far out from the middle of the city, in tiles, citizens live and work
+const CITY_TILES: i64 = 20;
+
+// tries before giving up on finding a free tile
+const SPOT_ATTEMPTS: usize = 64;
+
+fn open_spot(
+    terrain: &space::Terrain,
+    taken: &mut collections::HashSet<space::TileId>,
+    rng: &mut rand::XorShiftRng,
+) -> Option<units::Position> {
+    for _ in 0..SPOT_ATTEMPTS {
+        let id = (
+            rng.gen_range(-CITY_TILES, CITY_TILES),
+            rng.gen_range(-CITY_TILES, CITY_TILES),
+        );
+        if !terrain.tile(id).solid() && taken.insert(id) {
+            return Some(terrain.center(id));
+        }
+    }
+    None
+}
+
+/// Moves `count` citizens into the city, each with a home and a workplace
+/// of its own, the same ones every time for the same `seed`.
+pub fn populate(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    count: usize,
+    seed: u32,
+) -> Vec<sulphate::EntityId> {
+    // xorshift can't start from all zeroes
+    let mut rng = rand::XorShiftRng::from_seed([seed, 1, 2, 3]);
+    let mut taken = collections::HashSet::new();
+    let mut ids = Vec::new();
+    for _ in 0..count {
+        let plans = {
+            let terrain = space.terrain();
+            let home = open_spot(terrain, &mut taken, &mut rng);
+            let work = open_spot(terrain, &mut taken, &mut rng);
+            match (home, work) {
+                (Some(home), Some(work)) => Plans { home, work },
+                _ => {
+                    warn!("No room left in the city for more citizens");
+                    break;
+                },
+            }
+        };
+        ids.push(Citizen::new(space, time, matter, plans).id());
+    }
+    ids
+}
+
+#[cfg(test)]
+mod test_citizen {
+    use std::sync::mpsc;
+    use std::time;
+
+    use sulphate::Event;
+    use sulphate::server;
+    use units;
+
+    use super::*;
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    #[test]
+    fn trips_follow_the_clock() {
+        let midnight = units::Time::default();
+        assert!(next_trip(midnight) ==
+                (Trip::ToWork, midnight + calendar::hours(LEAVE_HOME)));
+        let noon = midnight + calendar::hours(12);
+        assert!(next_trip(noon) ==
+                (Trip::ToHome, midnight + calendar::hours(LEAVE_WORK)));
+        let evening = midnight + calendar::hours(20);
+        let tomorrow = midnight + calendar::hours(calendar::HOURS_PER_DAY);
+        assert!(next_trip(evening) ==
+                (Trip::ToWork, tomorrow + calendar::hours(LEAVE_HOME)));
+    }
+
+    #[test]
+    fn everyone_sets_off_together() {
+        let mut space = space::CollisionSpace::new();
+        let mut time = sulphate::EventQueue::new(Default::default());
+        let mut matter = sulphate::EntityHeap::new();
+        let ids: Vec<_> = (0..3).map(|i| {
+            let plans = Plans { home: at(0, 40 * i), work: at(200, 40 * i) };
+            Citizen::new(&mut space, &mut time, &mut matter, plans).id()
+        }).collect();
+        let due = |matter: &sulphate::EntityHeap| -> Vec<_> {
+            let departures = matter.get::<Departures>(DEPARTURES).unwrap();
+            departures.due.iter().map(|(&at, due)| (at, due.len())).collect()
+        };
+        let morning = units::Time::default() + calendar::hours(LEAVE_HOME);
+        assert!(due(&matter) == vec![(morning, 3)]);
+
+        // as the queue would be once the morning came
+        let mut time = sulphate::EventQueue::new(morning);
+        let depart = DepartEvent { at: morning };
+        depart.invoke(&mut space, &mut time, &mut matter);
+        for &id in &ids {
+            let citizen = matter.get::<Citizen>(id).unwrap();
+            assert!(!citizen.route.is_empty());
+        }
+        // and they all come home together too
+        let evening = units::Time::default() + calendar::hours(LEAVE_WORK);
+        assert!(due(&matter) == vec![(evening, 3)]);
+    }
+
+    #[test]
+    fn citizen_walks_to_work() {
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let work = at(200, 40);
+        let (upd, mut clock, handle, id) =
+            server::start_server_with_clock(move |space, time, matter| {
+                let plans = Plans { home: at(0, 0), work };
+                Citizen::new(space, time, matter, plans).id()
+            }, clock);
+
+        // it should be well on its way within a minute of leaving
+        clock.advance(calendar::hours(LEAVE_HOME) + units::moments(16 * 60));
+        let wait = time::Duration::from_secs(1);
+        let (reply, recv) = mpsc::channel();
+        upd.send(server::Interruption::Inspect { id, reply }).unwrap();
+        let info = recv.recv_timeout(wait)
+                       .expect("server didn't reply")
+                       .expect("citizen went missing");
+        let left: [f64; 2] = separation(info.summary.position, work);
+        assert!(distance(left) <= arrival_distance());
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+}
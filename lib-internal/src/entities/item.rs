@@ -0,0 +1,220 @@
+use entities;
+use space;
+use sulphate;
+use units;
+
+/// Something lying about in the world, that players can pick up, carry
+/// around, and put down again somewhere else.
+pub struct Item {
+    kind: Kind,
+}
+
+/// The kinds of thing that can be picked up.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Apple,
+    Brick,
+    Coin,
+}
+
+impl Kind {
+    pub fn all() -> [Kind; 3] {
+        [Kind::Apple, Kind::Brick, Kind::Coin]
+    }
+
+    pub fn name(self: Self) -> &'static str {
+        match self {
+            Kind::Apple => "apple",
+            Kind::Brick => "brick",
+            Kind::Coin => "coin",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Kind> {
+        Kind::all().iter().cloned().find(|kind| kind.name() == name)
+    }
+
+    // kept apart from the order of `all`, since it is written to saves
+    pub fn tag(self: Self) -> u8 {
+        match self {
+            Kind::Apple => 0,
+            Kind::Brick => 1,
+            Kind::Coin => 2,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Kind> {
+        match tag {
+            0 => Some(Kind::Apple),
+            1 => Some(Kind::Brick),
+            2 => Some(Kind::Coin),
+            _ => None,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone)]
+pub struct Image {
+    pub kind: Kind,
+}
+
+impl entities::Display for Item {
+    fn image(self: &Self) -> Option<entities::Image> {
+        let kind = self.kind;
+        Some(entities::Image::Item(Image { kind }))
+    }
+}
+
+/// How far out from its middle an item reaches.
+pub fn radius() -> units::Distance {
+    4.into()
+}
+
+/// The most items that a player can carry at once.
+pub const CAPACITY: usize = 8;
+
+/// What a player is carrying, kept as a component of the player, in the
+/// order it was picked up.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Inventory {
+    items: Vec<Kind>,
+}
+
+impl Inventory {
+    /// Carrying `items`, or as many of them as there is room for.
+    pub fn new(mut items: Vec<Kind>) -> Inventory {
+        items.truncate(CAPACITY);
+        Inventory { items }
+    }
+
+    pub fn items(self: &Self) -> &[Kind] {
+        &self.items
+    }
+
+    /// Puts `kind` in with everything else, giving whether there was room.
+    pub fn add(self: &mut Self, kind: Kind) -> bool {
+        if self.items.len() >= CAPACITY {
+            return false;
+        }
+        self.items.push(kind);
+        true
+    }
+
+    /// Takes out whatever is in `slot`, moving everything after it along.
+    pub fn take(self: &mut Self, slot: usize) -> Option<Kind> {
+        if slot < self.items.len() {
+            Some(self.items.remove(slot))
+        } else {
+            None
+        }
+    }
+}
+
+impl Item {
+    pub fn kind(self: &Self) -> Kind {
+        self.kind
+    }
+
+    /// An item of `kind` lying at `position`.
+    pub fn new<'a>(
+        space: &'a mut space::CollisionSpace,
+        time: &'a mut sulphate::EventQueue,
+        matter: &'a mut sulphate::EntityHeap,
+        kind: Kind,
+        position: units::Position,
+    ) -> space::Entry<'a, Item> {
+        let id = matter.add(Item { kind });
+        let mut this = space.entry::<Item>(time, matter, id);
+        this.body = Some(space::Body::new_frozen(position));
+        this
+    }
+
+    pub fn restore<'a>(
+        space: &'a mut space::CollisionSpace,
+        time: &'a mut sulphate::EventQueue,
+        matter: &'a mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+        kind: Kind,
+        position: units::Position,
+    ) -> space::Entry<'a, Item> {
+        matter.insert(id, Item { kind });
+        let mut this = space.entry::<Item>(time, matter, id);
+        this.body = Some(space::Body::new_frozen(position));
+        this
+    }
+
+    pub fn remove(
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+    ) -> Option<Item> {
+        {
+            let mut this: space::Entry<Item> = space.entry(time, matter, id);
+            this.body = None;
+        }
+        matter.remove(id)
+    }
+}
+
+impl entities::Interact for Item {
+    // picked up by whoever interacts with it, as long as they have room
+    fn on_interact(
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+        player: sulphate::EntityId,
+    ) -> Option<entities::Interaction> {
+        let kind = matter.get::<Item>(id)?.kind;
+        if !matter.component_mut::<Inventory>(player)?.add(kind) {
+            return None;
+        }
+        Item::remove(space, time, matter, id);
+        entities::player::inventory_changed(time, matter, player);
+        Some(entities::Interaction::PickedUp { kind })
+    }
+}
+
+impl space::Collide for Item {
+    // items lie where they are, and everything else walks over them
+    fn collide(
+        _this: space::Entry<Item>,
+        _other: space::Image,
+    ) {}
+
+    fn release(
+        _this: space::Entry<Item>,
+        _other: space::Image,
+    ) {}
+
+    fn disappear(
+        _this: space::Entry<Item>,
+        _other: space::Image,
+    ) {}
+}
+
+#[cfg(test)]
+mod test_item {
+    use super::*;
+
+    #[test]
+    fn tags_and_names_come_back() {
+        for &kind in &Kind::all() {
+            assert_eq!(Kind::from_tag(kind.tag()), Some(kind));
+            assert_eq!(Kind::from_name(kind.name()), Some(kind));
+        }
+        assert_eq!(Kind::from_name("anvil"), None);
+    }
+
+    #[test]
+    fn inventories_fill_up() {
+        let mut inventory = Inventory::new(vec![Kind::Coin; CAPACITY + 2]);
+        assert_eq!(inventory.items().len(), CAPACITY);
+        assert!(!inventory.add(Kind::Apple));
+        assert_eq!(inventory.take(CAPACITY), None);
+        assert_eq!(inventory.take(0), Some(Kind::Coin));
+        assert!(inventory.add(Kind::Apple));
+        assert_eq!(inventory.items().last(), Some(&Kind::Apple));
+    }
+}
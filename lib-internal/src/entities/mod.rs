@@ -1,35 +1,155 @@
 use std::any;
 
+use space;
 use sulphate;
 use units;
 
+pub use self::building::Building;
+pub use self::citizen::Citizen;
+pub use self::item::Item;
 pub use self::player::Player;
+pub use self::vehicle::Vehicle;
 
+pub mod building;
+pub mod citizen;
+pub mod item;
 pub mod player;
+pub mod vehicle;
 
 #[derive(PartialEq, Clone)]
 pub enum Image {
     Player(player::Image),
+    Citizen(citizen::Image),
+    Building(building::Image),
+    Vehicle(vehicle::Image),
+    Item(item::Image),
 }
 
 impl Image {
     pub fn radius(self: &Self) -> units::Distance {
         match *self {
             Image::Player(_) => 10.into(),
+            Image::Citizen(_) => 8.into(),
+            Image::Building(ref image) => image.blueprint.radius(),
+            Image::Vehicle(_) => vehicle::radius(),
+            Image::Item(_) => item::radius(),
+        }
+    }
+
+    /// Whether things that meet it are stopped by it, rather than going
+    /// straight over it the way they do with items.
+    pub fn is_solid(self: &Self) -> bool {
+        match *self {
+            Image::Item(_) => false,
+            _ => true,
         }
     }
 }
 
+/// Removes whichever entity has the given id, returning false if there was
+/// none.
+pub fn despawn(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) -> bool {
+    if matter.get::<Player>(id).is_some() {
+        Player::remove(space, time, matter, id).is_some()
+    } else if matter.get::<Citizen>(id).is_some() {
+        Citizen::remove(space, time, matter, id).is_some()
+    } else if matter.get::<Building>(id).is_some() {
+        Building::remove(space, time, matter, id).is_some()
+    } else if matter.get::<Vehicle>(id).is_some() {
+        Vehicle::remove(space, time, matter, id).is_some()
+    } else if matter.get::<Item>(id).is_some() {
+        Item::remove(space, time, matter, id).is_some()
+    } else {
+        false
+    }
+}
+
 pub trait Display {
     fn image(self: &Self) -> Option<Image>;
 }
 
+/// What came of a player interacting with something, for its device to
+/// show.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Interaction {
+    /// A building's menu, showing what it is and how it is doing.
+    Menu {
+        blueprint: building::Blueprint,
+        blackout: bool,
+    },
+    /// Got into a vehicle, riding it until interacting again, and
+    /// steering it if nobody else already was.
+    Boarded {
+        vehicle: sulphate::EntityId,
+        driving: bool,
+    },
+    /// Got back out of whatever it was riding.
+    Alighted,
+    /// Something that a citizen said back.
+    Talk {
+        line: String,
+    },
+    /// Picked an item up, which is carried now.
+    PickedUp {
+        kind: item::Kind,
+    },
+}
+
+/// Something that a player can walk up to and interact with.
+pub trait Interact {
+    /// Called when player `player` interacts with entity `id`, giving what
+    /// the player should be shown, if anything.
+    fn on_interact(
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+        player: sulphate::EntityId,
+    ) -> Option<Interaction>;
+}
+
+/// Has player `player` interact with whichever entity `uid` is, or does
+/// nothing if it isn't something that can be interacted with.
+pub fn interact_with(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    uid: sulphate::EntityUId,
+    player: sulphate::EntityId,
+) -> Option<Interaction> {
+    let id = uid.id;
+    if uid.ty == any::TypeId::of::<Citizen>() {
+        Citizen::on_interact(space, time, matter, id, player)
+    } else if uid.ty == any::TypeId::of::<Building>() {
+        Building::on_interact(space, time, matter, id, player)
+    } else if uid.ty == any::TypeId::of::<Vehicle>() {
+        Vehicle::on_interact(space, time, matter, id, player)
+    } else if uid.ty == any::TypeId::of::<Item>() {
+        Item::on_interact(space, time, matter, id, player)
+    } else {
+        None
+    }
+}
+
 pub fn image_of(
     matter: &sulphate::EntityHeap,
     uid: sulphate::EntityUId,
 ) -> Option<Image> {
     if uid.ty == any::TypeId::of::<Player>() {
         matter.get::<Player>(uid.id).and_then(Display::image)
+    } else if uid.ty == any::TypeId::of::<Citizen>() {
+        matter.get::<Citizen>(uid.id).and_then(Display::image)
+    } else if uid.ty == any::TypeId::of::<Building>() {
+        matter.get::<Building>(uid.id).and_then(Display::image)
+    } else if uid.ty == any::TypeId::of::<Vehicle>() {
+        matter.get::<Vehicle>(uid.id).and_then(Display::image)
+    } else if uid.ty == any::TypeId::of::<Item>() {
+        matter.get::<Item>(uid.id).and_then(Display::image)
     } else {
         panic!("Tried to get image of unknown entity");
     }
@@ -1,28 +1,198 @@
+use std::any;
+use std::cmp;
+use std::ops;
 use std::sync::mpsc;
 
+use config;
 use entities;
+use entities::item;
+use entities::vehicle;
 use space;
 use sulphate;
+use sulphate::Scheduler;
 use units;
 
 pub struct Player {
     // stimulus from the game world
     update: mpsc::Sender<Update>,
+    appearance: Appearance,
+    // speeding up or slowing down, until it goes as its controls asked
+    walking: Option<Walking>,
+    // what its controls last asked for, to go back to whenever its stamina
+    // changes how fast it can go
+    asked: Control,
+    stamina: Stamina,
+    // the next time that its stamina runs out, or comes back
+    tiring: Option<sulphate::EventHandle>,
+    // in a vehicle, being carried along instead of walking
+    riding: Option<Riding>,
+}
+
+struct Riding {
+    vehicle: sulphate::EntityId,
+    // the next time it catches up with where the vehicle has got to
+    next: sulphate::EventHandle,
+}
+
+struct Walking {
+    motion: Motion,
+    // the straight stretch that its body was last sent along, to tell
+    // whether anything has knocked it off course since
+    chord: space::Body,
+    next: sulphate::EventHandle,
+}
+
+/// The most characters that a player's name can have.
+pub const MAX_NAME_LENGTH: usize = 24;
+
+/// Red, green and blue.
+pub type Color = [u8; 3];
+
+// what players are given if they don't pick, first choice first
+const PALETTE: [Color; 6] = [
+    [255, 0, 0],
+    [0, 160, 255],
+    [0, 200, 80],
+    [255, 140, 0],
+    [180, 60, 220],
+    [240, 220, 0],
+];
+
+/// How a player looks to everyone else.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct Appearance {
+    /// Always one word, like the names in chat.
+    pub name: String,
+    pub color: Color,
+}
+
+impl Appearance {
+    /// Cleans `name` up into one word of at most `MAX_NAME_LENGTH`.
+    pub fn new(name: &str, color: Color) -> Appearance {
+        let name = name.split_whitespace()
+                       .collect::<Vec<_>>()
+                       .join("_")
+                       .chars()
+                       .filter(|c| !c.is_control())
+                       .take(MAX_NAME_LENGTH)
+                       .collect::<String>();
+        let name = if name.is_empty() { "someone".to_string() } else { name };
+        Appearance { name, color }
+    }
+
+    /// Picks a color from the name, so that different names tend to look
+    /// different without anyone having to choose.
+    pub fn named(name: &str) -> Appearance {
+        let sum = name.bytes().fold(0usize, |sum, b| sum + b as usize);
+        Appearance::new(name, PALETTE[sum % PALETTE.len()])
+    }
+}
+
+impl Default for Appearance {
+    fn default() -> Appearance {
+        Appearance::new("player", PALETTE[0])
+    }
 }
 
 #[derive(PartialEq, Eq, Clone)]
-pub struct Image;
+pub struct Image {
+    pub appearance: Appearance,
+}
 
 impl entities::Display for Player {
     fn image(self: &Self) -> Option<entities::Image> {
-        let img = Image;
-        Some(entities::Image::Player(img))
+        let appearance = self.appearance.clone();
+        Some(entities::Image::Player(Image { appearance }))
+    }
+}
+
+/// How fast a player goes in whichever direction it is going.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Pace {
+    Walk,
+    /// The usual pace, which the speed in the config is for.
+    Run,
+    /// Faster, but only for as long as the player's stamina lasts.
+    Sprint,
+}
+
+impl Pace {
+    /// A speed or velocity at the usual pace, made as much faster or
+    /// slower as this pace is. Worked out as a fraction rather than as a
+    /// factor, so that a whole speed comes out exact at a sprint too.
+    pub fn scale<T>(self: Self, usual: T) -> T
+        where T: ops::Mul<i64, Output = T> + ops::Div<i64, Output = T>
+    {
+        match self {
+            Pace::Walk => usual / 2,
+            Pace::Run => usual,
+            Pace::Sprint => usual * 8 / 5,
+        }
+    }
+
+    // kept apart from the order of the variants, since it is sent over the
+    // network and written to replays
+    pub fn tag(self: Self) -> u8 {
+        match self {
+            Pace::Run => 0,
+            Pace::Walk => 1,
+            Pace::Sprint => 2,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Pace> {
+        match tag {
+            0 => Some(Pace::Run),
+            1 => Some(Pace::Walk),
+            2 => Some(Pace::Sprint),
+            _ => None,
+        }
     }
 }
 
+impl Default for Pace {
+    fn default() -> Pace {
+        Pace::Run
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
 pub enum Control {
     Move {
-        velocity: units::Velocity
+        /// At the usual pace, whatever pace is asked for.
+        velocity: units::Velocity,
+        pace: Pace,
+    }
+}
+
+// how long a player could keep sprinting for, as of `since`, which drains
+// while it sprints and comes back at half that rate otherwise
+#[derive(Clone, Copy)]
+struct Stamina {
+    left: units::Duration,
+    since: units::Time,
+    sprinting: bool,
+    // run all the way out, so it can't sprint again until fully rested
+    winded: bool,
+}
+
+impl Stamina {
+    fn rested(now: units::Time, config: &config::Config) -> Stamina {
+        let left = config.sprint_stamina;
+        Stamina { left, since: now, sprinting: false, winded: false }
+    }
+
+    fn left(
+        self: &Self,
+        now: units::Time,
+        full: units::Duration,
+    ) -> units::Duration {
+        let passed = now - self.since;
+        if self.sprinting {
+            cmp::max(self.left - passed, units::Duration::default())
+        } else {
+            cmp::min(self.left + passed / 2, full)
+        }
     }
 }
 
@@ -39,10 +209,121 @@ pub enum UpdateData {
     Vision {
         before: Option<space::Image>,
         after: Option<space::Image>,
+        // whether this is the player seeing itself
+        own: bool,
+    },
+    Cast {
+        from: units::Position,
+        target: units::Position,
+    },
+    Interacted {
+        outcome: entities::Interaction,
+    },
+    /// Everything the player is carrying, whenever that changes.
+    Inventory {
+        items: Vec<item::Kind>,
     },
 }
 
+/// How a player's velocity changes on the way to the one its controls
+/// asked for: straight towards it at a steady rate, so that where the
+/// player is follows a parabola until it gets there, and a straight line
+/// after.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Motion {
+    pub position: units::Position,
+    pub velocity: units::Velocity,
+    pub since: units::Time,
+    pub target: units::Velocity,
+    /// How fast the velocity changes, in pixels per second per second.
+    pub rate: units::Scalar,
+}
+
+impl Motion {
+    /// Heads for `target` from `position` and `velocity` at `since`,
+    /// speeding up or turning at the acceleration that `config` sets, and
+    /// coming to a stop at its friction.
+    pub fn walking(
+        position: units::Position,
+        velocity: units::Velocity,
+        since: units::Time,
+        target: units::Velocity,
+        config: &config::Config,
+    ) -> Motion {
+        let rate = if target == units::Velocity::default() {
+            config.player_friction
+        } else {
+            config.player_acceleration
+        };
+        Motion { position, velocity, since, target, rate }
+    }
+
+    /// Heads for the same velocity at the same rate, but from `position`
+    /// and `velocity` at `since`.
+    pub fn restarted(
+        self: &Self,
+        position: units::Position,
+        velocity: units::Velocity,
+        since: units::Time,
+    ) -> Motion {
+        Motion { position, velocity, since, ..*self }
+    }
+
+    /// When the velocity gets to the target.
+    pub fn arrival(self: &Self) -> units::Time {
+        if self.rate <= 0 {
+            return self.since;
+        }
+        let change = (self.target - self.velocity).magnitude();
+        self.since + change / self.rate
+    }
+
+    pub fn velocity(self: &Self, now: units::Time) -> units::Velocity {
+        let arrival = self.arrival();
+        if now >= arrival {
+            return self.target;
+        }
+        let part = (now - self.since) / (arrival - self.since);
+        self.velocity.lerp(self.target, part)
+    }
+
+    pub fn position(self: &Self, now: units::Time) -> units::Position {
+        let arrival = self.arrival();
+        let turned = cmp::min(now, arrival);
+        // the velocity changes steadily, so its average is halfway
+        let average = (self.velocity + self.velocity(turned)) / 2;
+        let curved = self.position + average * (turned - self.since);
+        curved + self.target * (now - turned)
+    }
+}
+
+// how often a player's body is sent along the next stretch of its motion,
+// which the rest of the space sees as straight lines meeting the curve
+fn steer_step() -> units::Duration {
+    units::moments(1)
+}
+
 impl Control {
+    fn standing() -> Control {
+        let velocity = units::Velocity::default();
+        Control::Move { velocity, pace: Pace::Run }
+    }
+
+    /// Walk at full speed in the given direction, or slower if it is shorter
+    /// than a unit vector. Diagonal directions are no faster than straight
+    /// ones.
+    pub fn walk(direction: units::Vector, pace: Pace) -> Control {
+        let squared = direction.squared();
+        // stay in fixed point so that this is the same on every machine
+        let unit = if squared > 1 {
+            direction.normalized()
+        } else {
+            direction
+        };
+        let velocity = unit * config::Config::default().player_speed;
+        Control::Move { velocity, pace }
+    }
+
     pub fn apply(
         space: &mut space::CollisionSpace,
         time: &mut sulphate::EventQueue,
@@ -51,19 +332,49 @@ impl Control {
         data: Control,
     ) {
         use self::Control::*;
+        let config = config::get(matter);
+        let pace = allowed_pace(time, matter, id, &data, &config);
+        // carried along by whatever it is riding, until it gets out, when
+        // it goes however it was last asked to, unless it is the one
+        // driving, when the vehicle goes that way instead
+        let riding = matter.get::<Player>(id)
+                           .and_then(|p| p.riding.as_ref())
+                           .map(|riding| riding.vehicle);
+        if let Some(ridden) = riding {
+            if vehicle::driver(matter, ridden) == Some(id) {
+                let Move { velocity, .. } = data;
+                let usual = config::Config::default().player_speed;
+                let velocity = velocity * (config.vehicle_speed / usual);
+                vehicle::steer(space, time, matter, ridden, velocity);
+                follow(space, time, matter, id, ridden);
+            }
+            return;
+        }
         match data {
-            Move { velocity } => {
-                let mut this: space::Entry<Player> =
-                    space.entry(time, matter, id);
-                let now = this.now();
+            Move { velocity, .. } => {
+                // walking is asked for at the usual speed, and goes at
+                // whatever speed the world is set to
+                let usual = config::Config::default().player_speed;
+                let speed = config.player_speed / usual;
+                let target = pace.scale(velocity * speed);
+                let now = time.now();
 
-                // do this borrow before the mutable borrow that follows
-                let contact_images = this.get_contact_images();
-
-                if let Some(body) = this.body.as_mut() {
+                let motion = {
+                    let this: space::Entry<Player> =
+                        space.entry(time, matter, id);
+                    let body = match this.body {
+                        Some(ref body) => body.clone(),
+                        None => {
+                            warn!("Player has no location!");
+                            return;
+                        },
+                    };
                     let pos = body.position(now);
-                    for image in contact_images {
-                        let rel_vel = velocity - image.body.velocity();
+                    let solid = this.get_contact_images()
+                                    .into_iter()
+                                    .filter(|i| i.inner_image.is_solid());
+                    for image in solid {
+                        let rel_vel = target - image.body.velocity();
                         let obst_dir = image.body.position(now) - pos;
                         let dir = units::Vector::inner(rel_vel, obst_dir);
 
@@ -72,22 +383,473 @@ impl Control {
                         }
                     }
 
-                    body.bounce(velocity, now);
+                    // carrying on from partway through speeding up, unless
+                    // something has pushed it off course since
+                    let walking = this.get().and_then(|p| p.walking.as_ref());
+                    let velocity = match walking {
+                        Some(walking) if walking.chord == body => {
+                            walking.motion.velocity(now)
+                        },
+                        _ => body.velocity(),
+                    };
+                    Motion::walking(pos, velocity, now, target, &config)
+                };
+                steer(space, time, matter, id, motion);
+            }
+        }
+    }
+}
+
+// the pace the player can go at, out of the one that `asked` asks for, as
+// far as its stamina is concerned, and when that will next change
+fn allowed_pace(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    asked: &Control,
+    config: &config::Config,
+) -> Pace {
+    let now = time.now();
+    let full = config.sprint_stamina;
+    let player = match matter.get_mut::<Player>(id) {
+        Some(player) => player,
+        None => return Pace::Run,
+    };
+    player.asked = asked.clone();
+    if let Some(old) = player.tiring.take() {
+        time.cancel(&old);
+    }
+
+    let wanted = match *asked {
+        Control::Move { pace, .. } => pace,
+    };
+    // no stamina to speak of means sprinting for as long as anyone likes
+    if full <= 0 {
+        return wanted;
+    }
+    let stamina = player.stamina;
+    let left = stamina.left(now, full);
+    let winded = stamina.winded;
+    let sprinting = wanted == Pace::Sprint && !winded && left > 0;
+    player.stamina = Stamina { left, since: now, sprinting, winded };
+
+    let next = if sprinting {
+        Some(now + left)
+    } else if winded {
+        Some(now + (full - left) * 2)
+    } else {
+        None
+    };
+    if let Some(next) = next {
+        let event = TiringEvent { id };
+        player.tiring = Some(time.schedule_absolute(event, next));
+    }
+
+    if sprinting {
+        Pace::Sprint
+    } else if wanted == Pace::Sprint {
+        Pace::Run
+    } else {
+        wanted
+    }
+}
+
+// a player running out of stamina, or getting it all back, and going
+// whatever pace it can go at now
+struct TiringEvent {
+    id: sulphate::EntityId,
+}
+
+impl sulphate::Event for TiringEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        let now = time.now();
+        let full = config::get(matter).sprint_stamina;
+        let asked = match matter.get_mut::<Player>(self.id) {
+            Some(player) => {
+                player.tiring = None;
+                let stamina = player.stamina;
+                let left = stamina.left(now, full);
+                // out of breath if it was sprinting, and rested again if
+                // it was out of breath
+                let winded = stamina.sprinting;
+                player.stamina =
+                    Stamina { left, since: now, sprinting: false, winded };
+                player.asked.clone()
+            },
+            None => return,
+        };
+        Control::apply(space, time, matter, self.id, asked);
+    }
+}
+
+// sends the player's body along the next stretch of `motion`, checking
+// back at the end of it until the motion carries on in a straight line
+fn steer(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    motion: Motion,
+) {
+    let old = matter.get_mut::<Player>(id).and_then(|p| p.walking.take());
+    if let Some(old) = old {
+        time.cancel(&old.next);
+    }
+
+    let now = time.now();
+    let end = cmp::min(now + steer_step(), motion.arrival());
+    let chord = {
+        let mut this: space::Entry<Player> = space.entry(time, matter, id);
+        match this.body.as_mut() {
+            Some(body) => {
+                if end > now {
+                    body.bounce_to(motion.position(end), now, end);
                 } else {
-                    println!("Player has no location!");
+                    body.bounce(motion.target, now);
                 }
+            },
+            None => return,
+        }
+        this.body.clone()
+    };
+
+    if let Some(chord) = chord {
+        if end > now {
+            let next = time.schedule_absolute(SteerEvent { id }, end);
+            if let Some(player) = matter.get_mut::<Player>(id) {
+                player.walking = Some(Walking { motion, chord, next });
+            }
+        }
+    }
+}
+
+struct SteerEvent {
+    id: sulphate::EntityId,
+}
+
+impl sulphate::Event for SteerEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        let id = self.id;
+        let walking = match matter.get_mut::<Player>(id) {
+            Some(player) => match player.walking.take() {
+                Some(walking) => walking,
+                None => return,
+            },
+            None => return,
+        };
+        let now = time.now();
+        let body = space.entry::<Player>(time, matter, id).body.clone();
+        let motion = match body {
+            Some(ref body) if *body == walking.chord => walking.motion,
+            // knocked off course, so it picks up from wherever it was sent
+            Some(ref body) => walking.motion.restarted(
+                body.position(now), body.velocity(), now,
+            ),
+            None => return,
+        };
+        steer(space, time, matter, id, motion);
+    }
+}
+
+pub fn cast(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    target: units::Position,
+) {
+    let this: space::Entry<Player> = space.entry(time, matter, id);
+    let when = this.now();
+
+    if let Some(from) = this.body.as_ref().map(|body| body.position(when)) {
+        let what = UpdateData::Cast { from, target };
+        let update = Update { when, what };
+        if let Some(player) = this.get() {
+            player.send(update);
+        }
+    } else {
+        warn!("Player cast without a location!");
+    }
+}
+
+// how far from the middle of a player something can be, and still be
+// interacted with
+fn reach() -> units::Distance {
+    30.into()
+}
+
+fn uid(id: sulphate::EntityId) -> sulphate::EntityUId {
+    let ty = any::TypeId::of::<Player>();
+    sulphate::EntityUId { id, ty }
+}
+
+// whatever else has its middle nearest to the middle of player `id`, out
+// of everything within reach, leaving out other players, who might be
+// riding in the very thing that was meant
+fn nearest(
+    space: &space::CollisionSpace,
+    id: sulphate::EntityId,
+    now: units::Time,
+) -> Option<sulphate::EntityUId> {
+    let this = uid(id);
+    let from = space.position(this, now)?;
+    let mut closest = None;
+    for other in space.query_around(now, this, reach()) {
+        if other.ty == this.ty {
+            continue;
+        }
+        let at = match space.position(other, now) {
+            Some(at) => at,
+            None => continue,
+        };
+        let squared = (at - from).squared();
+        if closest.map_or(true, |(_, best)| squared < best) {
+            closest = Some((other, squared));
+        }
+    }
+    closest.map(|(other, _)| other)
+}
+
+/// Has the player interact with whatever is nearest within reach, or get
+/// out of whatever it is riding, telling its device what came of it.
+pub fn interact(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) {
+    let now = time.now();
+    let riding = match matter.get::<Player>(id) {
+        Some(player) => player.riding.is_some(),
+        None => return,
+    };
+    let outcome = if riding {
+        alight(space, time, matter, id);
+        Some(entities::Interaction::Alighted)
+    } else {
+        match nearest(space, id, now) {
+            Some(other) => {
+                entities::interact_with(space, time, matter, other, id)
+            },
+            None => None,
+        }
+    };
+    if let Some(outcome) = outcome {
+        let what = UpdateData::Interacted { outcome };
+        if let Some(player) = matter.get::<Player>(id) {
+            player.send(Update { when: now, what });
+        }
+    }
+}
+
+/// Tells player `id`'s device what it is carrying, after that changes.
+pub fn inventory_changed(
+    time: &sulphate::EventQueue,
+    matter: &sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) {
+    let items = match matter.component::<item::Inventory>(id) {
+        Some(inventory) => inventory.items().to_vec(),
+        None => return,
+    };
+    if let Some(player) = matter.get::<Player>(id) {
+        let what = UpdateData::Inventory { items };
+        player.send(Update { when: time.now(), what });
+    }
+}
+
+/// Has player `id` put down whatever it is carrying in `slot`, to lie
+/// wherever the player is standing.
+pub fn drop_item(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    slot: usize,
+) {
+    let position = match space.position(uid(id), time.now()) {
+        Some(position) => position,
+        None => return,
+    };
+    let kind = matter.component_mut::<item::Inventory>(id)
+                     .and_then(|inventory| inventory.take(slot));
+    if let Some(kind) = kind {
+        item::Item::new(space, time, matter, kind, position);
+        inventory_changed(time, matter, id);
+    }
+}
+
+/// Puts player `id` in `vehicle`, to be carried along with it until it
+/// interacts again, giving whether there was such a player.
+pub fn board(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    vehicle: sulphate::EntityId,
+) -> bool {
+    let old = match matter.get_mut::<Player>(id) {
+        Some(player) => {
+            if let Some(walking) = player.walking.take() {
+                time.cancel(&walking.next);
             }
+            player.riding.take()
+        },
+        None => return false,
+    };
+    if let Some(old) = old {
+        time.cancel(&old.next);
+        let_go(time, matter, id, old.vehicle);
+    }
+    // the first one in drives, and anyone after that is a passenger
+    vehicle::take_the_wheel(time, matter, vehicle, id);
+    ride(space, time, matter, id, vehicle);
+    true
+}
+
+// catches up with `vehicle` straight away, rather than a moment later
+fn follow(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    vehicle: sulphate::EntityId,
+) {
+    let riding = matter.get_mut::<Player>(id)
+                       .and_then(|player| player.riding.take());
+    if let Some(riding) = riding {
+        time.cancel(&riding.next);
+        ride(space, time, matter, id, vehicle);
+    }
+}
+
+// puts the player where the vehicle is, going the way it is going, and
+// checks back a moment later in case it has turned or stopped since
+fn ride(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    vehicle: sulphate::EntityId,
+) {
+    let now = time.now();
+    let carrier = space.entry::<entities::Vehicle>(time, matter, vehicle)
+                       .body
+                       .clone();
+    let carrier = match carrier {
+        Some(carrier) => carrier,
+        // gone, leaving the player standing wherever it had got to
+        None => {
+            alight(space, time, matter, id);
+            return;
+        },
+    };
+    let at = carrier.position(now);
+    let velocity = carrier.velocity();
+    {
+        let mut this: space::Entry<Player> = space.entry(time, matter, id);
+        let along = match this.body {
+            Some(ref body) => {
+                body.position(now) == at && body.velocity() == velocity
+            },
+            None => return,
+        };
+        // left alone when it is already on its way, so that the space
+        // isn't told about a change that there wasn't
+        if !along {
+            this.body = Some(space::Body::new(at, velocity, now));
+        }
+    }
+
+    let next = time.schedule_relative(RideEvent { id }, steer_step());
+    if let Some(player) = matter.get_mut::<Player>(id) {
+        player.riding = Some(Riding { vehicle, next });
+    }
+}
+
+// gets out of whatever it is riding, stopping where it is before going
+// however its controls last asked
+fn alight(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) {
+    let (asked, ridden) = match matter.get_mut::<Player>(id) {
+        Some(player) => {
+            let ridden = player.riding.take().map(|riding| {
+                time.cancel(&riding.next);
+                riding.vehicle
+            });
+            (player.asked.clone(), ridden)
+        },
+        None => return,
+    };
+    if let Some(ridden) = ridden {
+        let_go(time, matter, id, ridden);
+    }
+    {
+        let mut this: space::Entry<Player> = space.entry(time, matter, id);
+        let now = this.now();
+        if let Some(body) = this.body.as_mut() {
+            body.freeze(now);
+        }
+    }
+    Control::apply(space, time, matter, id, asked);
+}
+
+// gives `vehicle` back to itself if player `id` was the one driving it
+fn let_go(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    vehicle: sulphate::EntityId,
+) {
+    if vehicle::driver(matter, vehicle) == Some(id) {
+        vehicle::hand_back(time, matter, vehicle);
+    }
+}
+
+struct RideEvent {
+    id: sulphate::EntityId,
+}
+
+impl sulphate::Event for RideEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        let riding = matter.get_mut::<Player>(self.id)
+                           .and_then(|player| player.riding.take());
+        if let Some(riding) = riding {
+            ride(space, time, matter, self.id, riding.vehicle);
         }
     }
 }
 
 impl Player {
+    pub fn appearance(self: &Self) -> &Appearance {
+        &self.appearance
+    }
+
     fn send(
         self: &Self,
         update: Update,
     ) {
         if let Err(_) = self.update.send(update) {
-            println!("Player failed to send update to device");
+            warn!("Player failed to send update to device");
         }
     }
 
@@ -96,10 +858,21 @@ impl Player {
         time: &'a mut sulphate::EventQueue,
         matter: &'a mut sulphate::EntityHeap,
         position: units::Position,
+        appearance: Appearance,
         update: mpsc::Sender<Update>,
     ) -> space::Entry<'a, Player> {
-        let player = Player { update };
+        let stamina = Stamina::rested(time.now(), &config::get(matter));
+        let player = Player {
+            update,
+            appearance,
+            walking: None,
+            asked: Control::standing(),
+            stamina,
+            tiring: None,
+            riding: None,
+        };
         let id = matter.add(player);
+        matter.insert_component(id, item::Inventory::default());
         let mut this = space.entry::<Player>(time, matter, id);
 
         this.body = Some(space::Body::new_frozen(position));
@@ -111,6 +884,77 @@ impl Player {
 
         this
     }
+
+    pub fn restore<'a>(
+        space: &'a mut space::CollisionSpace,
+        time: &'a mut sulphate::EventQueue,
+        matter: &'a mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+        body: space::Body,
+        appearance: Appearance,
+        inventory: item::Inventory,
+        update: mpsc::Sender<Update>,
+    ) -> space::Entry<'a, Player> {
+        // whatever it was speeding up to isn't saved, so it keeps going as
+        // its body was, and neither is how tired it was, or what it was
+        // riding in
+        let stamina = Stamina::rested(time.now(), &config::get(matter));
+        let player = Player {
+            update,
+            appearance,
+            walking: None,
+            asked: Control::standing(),
+            stamina,
+            tiring: None,
+            riding: None,
+        };
+        matter.insert(id, player);
+        let items = inventory.items().to_vec();
+        matter.insert_component(id, inventory);
+        let mut this = space.entry::<Player>(time, matter, id);
+
+        let when = this.now();
+        let position = body.position(when);
+        this.body = Some(body);
+
+        let what = UpdateData::Created { id, position };
+        let update = Update { when, what };
+        this.get().unwrap().send(update);
+        let what = UpdateData::Inventory { items };
+        this.get().unwrap().send(Update { when, what });
+
+        this
+    }
+
+    // calls off everything still scheduled for this player, so that
+    // nothing happens to an id that may have gone to something else
+    pub fn remove(
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+    ) -> Option<Player> {
+        if let Some(player) = matter.get_mut::<Player>(id) {
+            if let Some(walking) = player.walking.take() {
+                time.cancel(&walking.next);
+            }
+            if let Some(tiring) = player.tiring.take() {
+                time.cancel(&tiring);
+            }
+        }
+        let riding = matter.get_mut::<Player>(id)
+                           .and_then(|player| player.riding.take());
+        if let Some(riding) = riding {
+            time.cancel(&riding.next);
+            let_go(time, matter, id, riding.vehicle);
+        }
+        {
+            // releases contacts and tells everyone else it's gone
+            let mut this: space::Entry<Player> = space.entry(time, matter, id);
+            this.body = None;
+        }
+        matter.remove(id)
+    }
 }
 
 impl space::Eyes for Player {
@@ -122,24 +966,28 @@ impl space::Eyes for Player {
         if let Some(player) = this.get() {
             let before = before_ref.cloned();
             let after = after_ref.cloned();
+            let own = after.is_some() && this.image() == after;
             let when = this.now();
-            let what = UpdateData::Vision { before, after };
+            let what = UpdateData::Vision { before, after, own };
             let update = Update { when, what };
             player.send(update);
         }
     }
 }
 
+// players shove each other about as much as they bounce
+fn material() -> space::Material {
+    let mass = 1.into();
+    let restitution = units::Scalar::from(1) / 2;
+    space::Material { mass, restitution }
+}
+
 impl space::Collide for Player {
+    // the space already sent this off whichever way the hit pushed it
     fn collide(
-        mut this: space::Entry<Player>,
+        _this: space::Entry<Player>,
         _other: space::Image,
-    ) {
-        let now = this.now();
-
-        let body = this.body.as_mut().expect("Collided without a body");
-        body.bounce(units::Velocity::default(), now);
-    }
+    ) {}
 
     fn release(
         _this: space::Entry<Player>,
@@ -150,5 +998,491 @@ impl space::Collide for Player {
         _this: space::Entry<Player>,
         _other: space::Image,
     ) {}
+
+    // riders go wherever they are carried, rather than being knocked about
+    fn material(self: &Self) -> Option<space::Material> {
+        if self.riding.is_some() {
+            None
+        } else {
+            Some(material())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_appearance {
+    use super::*;
+
+    #[test]
+    fn names_are_one_short_word() {
+        let appearance = Appearance::new(" two\twords ", [1, 2, 3]);
+        assert_eq!(appearance.name, "two_words");
+        assert_eq!(Appearance::new("\n", [1, 2, 3]).name, "someone");
+        let long: String = (0..MAX_NAME_LENGTH * 2).map(|_| 'a').collect();
+        assert_eq!(Appearance::new(&long, [1, 2, 3]).name.len(),
+                   MAX_NAME_LENGTH);
+        assert!(Appearance::named("ab") == Appearance::named("ab"));
+        let (ab, ac) = (Appearance::named("ab"), Appearance::named("ac"));
+        assert!(ab.color != ac.color);
+    }
+}
+
+#[cfg(test)]
+mod test_push {
+    use std::sync::mpsc;
+
+    use sulphate::testing::TestServer;
+    use units;
+
+    use super::*;
+
+    fn speed_of(server: &TestServer, id: sulphate::EntityId) -> i32 {
+        server.space()
+              .snapshot(server.now())
+              .into_iter()
+              .find(|body| body.uid.id == id)
+              .expect("player missing from snapshot")
+              .velocity
+              .x
+              .into()
+    }
+
+    #[test]
+    fn walking_into_someone_shoves_them() {
+        let (mut server, ids) = TestServer::new(|space, time, matter| {
+            let mut ids = Vec::new();
+            for &x in [0, 100].iter() {
+                let (send, _) = mpsc::channel();
+                let x = x.into();
+                let offset = units::Displacement { x, y: 0.into() };
+                let position = units::Position::default() + offset;
+                let appearance = Default::default();
+                ids.push(Player::new(
+                    space, time, matter, position, appearance, send,
+                ).id());
+            }
+            let velocity = units::Velocity { x: 100.into(), y: 0.into() };
+            let control = Control::Move { velocity, pace: Pace::Run };
+            Control::apply(space, time, matter, ids[0], control);
+            ids
+        });
+
+        // looking straight after the hit, before the one walking has had
+        // long to speed back up
+        for _ in 0..2 * units::MOMENT_RATE {
+            server.advance_by(units::moments(1));
+            if speed_of(&server, ids[1]) != 0 {
+                break;
+            }
+        }
+        // half as bouncy as can be, so the one walking keeps a quarter
+        assert!((speed_of(&server, ids[1]) - 75).abs() <= 1);
+        assert!(speed_of(&server, ids[0]) < 75);
+    }
+}
+
+#[cfg(test)]
+mod test_motion {
+    use std::sync::mpsc;
+
+    use sulphate::server::Interruption;
+    use sulphate::testing::TestServer;
+    use units;
+
+    use super::*;
+
+    fn x_of(position: units::Position) -> f64 {
+        (position - units::Position::default()).x.into()
+    }
+
+    #[test]
+    fn speeding_up_follows_a_parabola() {
+        let start = units::Time::default();
+        let motion = Motion {
+            position: units::Position::default(),
+            velocity: units::Velocity::default(),
+            since: start,
+            target: units::Velocity { x: 100.into(), y: 0.into() },
+            rate: 400.into(),
+        };
+        let second = units::moments(units::MOMENT_RATE as i32);
+        let taken: f64 = (motion.arrival() - start).into();
+        assert!((taken - 0.25).abs() < 0.001);
+
+        let halfway = start + second / 8;
+        let velocity: f64 = motion.velocity(halfway).x.into();
+        assert!((velocity - 50.0).abs() < 0.01);
+        assert!((x_of(motion.position(halfway)) - 3.125).abs() < 0.01);
+        assert!((x_of(motion.position(motion.arrival())) - 12.5).abs() < 0.01);
+        assert!((x_of(motion.position(start + second)) - 87.5).abs() < 0.01);
+        assert_eq!(motion.velocity(start + second), motion.target);
+    }
+
+    fn body_of(
+        server: &TestServer,
+        id: sulphate::EntityId,
+    ) -> (f64, units::Velocity) {
+        let body = server.space()
+                         .snapshot(server.now())
+                         .into_iter()
+                         .find(|body| body.uid.id == id)
+                         .expect("player missing from snapshot");
+        (x_of(body.position), body.velocity)
+    }
+
+    #[test]
+    fn players_speed_up_and_slow_down() {
+        let (mut server, id) = TestServer::new(|space, time, matter| {
+            let (send, _) = mpsc::channel();
+            let position = units::Position::default();
+            let appearance = Default::default();
+            Player::new(space, time, matter, position, appearance, send).id()
+        });
+        let full = config::Config::default().player_speed;
+        let direction = units::Vector { x: 1.into(), y: 0.into() };
+        let control = Control::walk(direction, Pace::Run);
+        server.send(Interruption::PlayerUpdate { id, control });
+
+        server.advance_by(units::moments(1));
+        let (_, velocity) = body_of(&server, id);
+        assert!(velocity.x > 0 && velocity.x < full);
+
+        // up to speed in a sixth of a second, having lost half of that
+        // sixth's worth of distance to getting there
+        server.advance_by(units::moments(15));
+        let (x, velocity) = body_of(&server, id);
+        assert_eq!(velocity, direction * full);
+        assert!((x - (150.0 - 12.5)).abs() < 0.1, "got to {}", x);
+
+        let control = Control::walk(Default::default(), Pace::Run);
+        server.send(Interruption::PlayerUpdate { id, control });
+        server.advance_by(units::moments(1));
+        let (_, velocity) = body_of(&server, id);
+        assert!(velocity.x > 0 && velocity.x < full);
+
+        // and stopped in an eighth, going half as far as it would have
+        server.advance_by(units::moments(15));
+        let (stopped, velocity) = body_of(&server, id);
+        assert_eq!(velocity, units::Velocity::default());
+        assert!((stopped - (x + 9.375)).abs() < 0.1, "stopped at {}", stopped);
+    }
+
+    #[test]
+    fn sprinting_lasts_as_long_as_stamina() {
+        let (mut server, id) = TestServer::new(|space, time, matter| {
+            let (send, _) = mpsc::channel();
+            let position = units::Position::default();
+            let appearance = Default::default();
+            Player::new(space, time, matter, position, appearance, send).id()
+        });
+        let second = units::moments(units::MOMENT_RATE as i32);
+        let direction = units::Vector { x: 1.into(), y: 0.into() };
+        let control = Control::walk(direction, Pace::Sprint);
+        server.send(Interruption::PlayerUpdate { id, control });
+        server.advance_by(second);
+        let (_, velocity) = body_of(&server, id);
+        assert_eq!(velocity.x, 240);
+
+        // five seconds of stamina by default, then back to running until
+        // it has all come back, at half the rate it went
+        server.advance_by(second * 5);
+        let (_, velocity) = body_of(&server, id);
+        assert_eq!(velocity.x, 150);
+        server.advance_by(second * 17 / 2);
+        let (_, velocity) = body_of(&server, id);
+        assert_eq!(velocity.x, 150);
+        server.advance_by(second);
+        let (_, velocity) = body_of(&server, id);
+        assert_eq!(velocity.x, 240);
+
+        let control = Control::walk(direction, Pace::Walk);
+        server.send(Interruption::PlayerUpdate { id, control });
+        server.advance_by(second);
+        let (_, velocity) = body_of(&server, id);
+        assert_eq!(velocity.x, 75);
+    }
+
+    #[test]
+    fn removed_players_leave_nothing_scheduled() {
+        let (mut server, id) = TestServer::new(|space, time, matter| {
+            let (send, _) = mpsc::channel();
+            let position = units::Position::default();
+            let appearance = Default::default();
+            Player::new(space, time, matter, position, appearance, send).id()
+        });
+        let direction = units::Vector { x: 1.into(), y: 0.into() };
+        let control = Control::walk(direction, Pace::Sprint);
+        server.send(Interruption::PlayerUpdate { id, control });
+        server.advance_by(units::moments(1));
+
+        let (walking, tiring) = {
+            let player = server.matter()
+                               .get::<Player>(id)
+                               .expect("player missing");
+            let walking = player.walking
+                                .as_ref()
+                                .map(|walking| walking.next.clone());
+            (walking.expect("not walking"), player.tiring.clone().unwrap())
+        };
+        assert!(walking.pending() && tiring.pending());
+
+        server.send(Interruption::RemovePlayer { id });
+        assert!(!walking.pending());
+        assert!(!tiring.pending());
+    }
+}
+
+#[cfg(test)]
+mod test_walk {
+    use units;
+
+    use super::*;
+
+    fn walk_speed_of(x: i32, y: i32) -> units::Speed {
+        let direction = units::Vector { x: x.into(), y: y.into() };
+        match Control::walk(direction, Pace::Run) {
+            Control::Move { velocity, .. } => velocity.magnitude(),
+        }
+    }
+
+    #[test]
+    fn diagonal_no_faster() {
+        let straight = walk_speed_of(1, 0);
+        let diagonal = walk_speed_of(1, -1);
+        assert!(
+            (straight - diagonal).squared() < 1,
+            "straight speed {} but diagonal speed {}", straight, diagonal
+        );
+    }
+
+    #[test]
+    fn standing_still() {
+        match Control::walk(Default::default(), Pace::Run) {
+            Control::Move { velocity, .. } =>
+                assert_eq!(velocity, units::Velocity::default()),
+        }
+    }
 }
 
+#[cfg(test)]
+mod test_interact {
+    use std::any;
+    use std::sync::mpsc;
+
+    use entities::building;
+    use entities::item;
+    use entities::vehicle;
+    use entities::Interaction;
+    use sulphate::persist;
+    use sulphate::server::Interruption;
+    use sulphate::testing::TestServer;
+    use units;
+
+    use super::*;
+
+    // what the player was told came of interacting, leaving out the rest
+    fn outcomes(updates: &mpsc::Receiver<Update>) -> Vec<Interaction> {
+        updates.try_iter()
+               .filter_map(|update| match update.what {
+                   UpdateData::Interacted { outcome } => Some(outcome),
+                   _ => None,
+               })
+               .collect()
+    }
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    #[test]
+    fn the_nearest_thing_in_reach_is_used() {
+        let (mut server, (id, updates)) =
+            TestServer::new(|space, time, matter| {
+                let (send, updates) = mpsc::channel();
+                let appearance = Default::default();
+                let position = at(0, 0);
+                let id = Player::new(
+                    space, time, matter, position, appearance, send,
+                ).id();
+                let shop = building::Blueprint::Shop;
+                building::Building::new(space, time, matter, shop, at(40, 0));
+                let house = building::Blueprint::House;
+                building::Building::new(
+                    space, time, matter, house, at(-36, 0),
+                );
+                (id, updates)
+            });
+        server.send(Interruption::PlayerInteract { id });
+        let menu = Interaction::Menu {
+            blueprint: building::Blueprint::House,
+            blackout: false,
+        };
+        assert_eq!(outcomes(&updates), vec![menu]);
+    }
+
+    // the bus, then each player and what it is told
+    type Stop = (
+        sulphate::EntityId,
+        Vec<sulphate::EntityId>,
+        Vec<mpsc::Receiver<Update>>,
+    );
+
+    // a bus waiting at the first of its two stops, with a player as far
+    // below it as each of `offsets`
+    fn bus_stop(offsets: Vec<i32>) -> (TestServer, Stop) {
+        TestServer::new(move |space, time, matter| {
+            let from = space.terrain().center((0, 0));
+            let to = space.terrain().center((6, 0));
+            assert!(space.build_road(from, to));
+            let stops = vec![(0, 0), (6, 0)];
+            let bus = vehicle::Vehicle::new(space, time, matter, stops).id();
+
+            let mut ids = Vec::new();
+            let mut receivers = Vec::new();
+            for below in offsets {
+                let (send, updates) = mpsc::channel();
+                let appearance = Default::default();
+                let position = from + units::Displacement {
+                    x: 0.into(),
+                    y: below.into(),
+                };
+                ids.push(Player::new(
+                    space, time, matter, position, appearance, send,
+                ).id());
+                receivers.push(updates);
+            }
+            (bus, ids, receivers)
+        })
+    }
+
+    fn bus_at(
+        server: &TestServer,
+        bus: sulphate::EntityId,
+    ) -> units::Position {
+        let ty = any::TypeId::of::<vehicle::Vehicle>();
+        let bus_uid = sulphate::EntityUId { id: bus, ty };
+        server.space().position(bus_uid, server.now()).unwrap()
+    }
+
+    fn steer(
+        server: &mut TestServer,
+        id: sulphate::EntityId,
+        x: i32,
+        y: i32,
+    ) {
+        let direction = units::Vector { x: x.into(), y: y.into() };
+        let control = Control::walk(direction, Pace::Run);
+        server.send(Interruption::PlayerUpdate { id, control });
+    }
+
+    #[test]
+    fn drivers_steer_vehicles_until_they_get_out() {
+        let (mut server, (bus, ids, updates)) = bus_stop(vec![24]);
+        let id = ids[0];
+        let start = bus_at(&server, bus);
+        server.send(Interruption::PlayerInteract { id });
+        assert_eq!(
+            outcomes(&updates[0]),
+            vec![Interaction::Boarded { vehicle: bus, driving: true }],
+        );
+
+        // past where it would have waited, since that is where it was
+        // steered
+        steer(&mut server, id, 1, 0);
+        server.advance_by(units::moments(16));
+        let driven_to = bus_at(&server, bus);
+        assert!(driven_to.x > start.x + units::Scalar::from(50));
+        assert!(driven_to.y == start.y);
+        let player_at = server.space().position(uid(id), server.now());
+        assert!(player_at == Some(driven_to));
+
+        // left where it stopped once its driver gets out, and back to
+        // driving itself, back along the road to its first stop
+        steer(&mut server, id, 0, 0);
+        server.send(Interruption::PlayerInteract { id });
+        assert_eq!(outcomes(&updates[0]), vec![Interaction::Alighted]);
+        let stopped_at = bus_at(&server, bus);
+        server.advance_by(units::moments(16));
+        assert!(bus_at(&server, bus) != stopped_at);
+        let left_at = server.space().position(uid(id), server.now());
+        assert!(left_at == Some(stopped_at));
+    }
+
+    #[test]
+    fn passengers_go_wherever_the_driver_does() {
+        let (mut server, (bus, ids, updates)) = bus_stop(vec![24, -24]);
+        let (driver, passenger) = (ids[0], ids[1]);
+        server.send(Interruption::PlayerInteract { id: driver });
+        server.send(Interruption::PlayerInteract { id: passenger });
+        assert_eq!(
+            outcomes(&updates[1]),
+            vec![Interaction::Boarded { vehicle: bus, driving: false }],
+        );
+
+        // one steering east and the other asking to go north, which
+        // nothing comes of
+        steer(&mut server, driver, 1, 0);
+        steer(&mut server, passenger, 0, -1);
+        server.advance_by(units::moments(16));
+        let now = server.now();
+        let driven_to = bus_at(&server, bus);
+        let origin = units::Position::default();
+        assert!(driven_to.x > origin.x + units::Scalar::from(50));
+        let passenger_at = server.space().position(uid(passenger), now);
+        assert!(passenger_at == Some(driven_to));
+    }
+
+    // every inventory the player was told it had, leaving out the rest
+    fn inventories(updates: &mpsc::Receiver<Update>) -> Vec<Vec<item::Kind>> {
+        updates.try_iter()
+               .filter_map(|update| match update.what {
+                   UpdateData::Inventory { items } => Some(items),
+                   _ => None,
+               })
+               .collect()
+    }
+
+    fn items_at(server: &TestServer) -> Vec<units::Position> {
+        server.world()
+              .entities(server.now())
+              .into_iter()
+              .filter(|entity| entity.kind == Some(persist::EntityKind::Item))
+              .map(|entity| entity.position)
+              .collect()
+    }
+
+    #[test]
+    fn items_are_picked_up_and_put_down_again() {
+        let (mut server, (id, updates)) =
+            TestServer::new(|space, time, matter| {
+                let (send, updates) = mpsc::channel();
+                let appearance = Default::default();
+                let id = Player::new(
+                    space, time, matter, at(0, 0), appearance, send,
+                ).id();
+                let brick = item::Kind::Brick;
+                item::Item::new(space, time, matter, brick, at(16, 0));
+                (id, updates)
+            });
+        server.send(Interruption::PlayerInteract { id });
+        let carrying = vec![vec![item::Kind::Brick]];
+        assert_eq!(inventories(&updates), carrying);
+        assert!(items_at(&server).is_empty());
+
+        // walking off with it, then leaving it behind
+        steer(&mut server, id, 0, 1);
+        server.advance_by(units::moments(16));
+        server.send(Interruption::PlayerDrop { id, slot: 0 });
+        let now = server.now();
+        let dropped_at = server.space().position(uid(id), now).unwrap();
+        assert!(items_at(&server) == vec![dropped_at]);
+        assert!(dropped_at.y > units::Position::default().y);
+        let empty: Vec<Vec<item::Kind>> = vec![vec![]];
+        assert_eq!(inventories(&updates), empty);
+
+        // and nothing to drop the second time
+        server.send(Interruption::PlayerDrop { id, slot: 0 });
+        assert_eq!(items_at(&server).len(), 1);
+    }
+}
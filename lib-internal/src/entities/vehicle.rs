@@ -0,0 +1,762 @@
+use std::any;
+use std::cmp;
+use std::f64::consts;
+
+use config;
+use entities;
+use fire;
+use space;
+use space::sounds;
+use sulphate;
+use sulphate::Scheduler;
+use sulphate::tick::TickScheduler;
+use units;
+
+/// Something that drives around the roads, from one stop to the next and
+/// back to the first once it has been to them all.
+pub struct Vehicle {
+    stops: Vec<space::TileId>,
+    // which of the stops it is on its way to
+    stop: usize,
+    // road tiles left on the way there, the next one last
+    route: Vec<space::TileId>,
+    heading: Heading,
+    // the next time it looks at where it has got to
+    driving: Option<sulphate::EventHandle>,
+    // the player steering it instead, until they get out
+    driver: Option<sulphate::EntityId>,
+    // the burning building it is a fire engine on its way to, if it is one
+    responding: Option<sulphate::EntityId>,
+    // the stretch of road it was last counted going along, so that it is
+    // counted once however many times it looks at where it has got to
+    passing: Option<(space::TileId, space::TileId)>,
+    // the vehicle it last stopped to give way to
+    waiting_for: Option<sulphate::EntityId>,
+}
+
+/// Which way along the grid a vehicle is facing.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Heading {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Heading {
+    /// Whichever way `velocity` points most, or None if it isn't moving.
+    pub fn of(velocity: units::Velocity) -> Option<Heading> {
+        let x: f64 = velocity.x.into();
+        let y: f64 = velocity.y.into();
+        if x == 0.0 && y == 0.0 {
+            None
+        } else if x.abs() >= y.abs() {
+            Some(if x > 0.0 { Heading::East } else { Heading::West })
+        } else {
+            Some(if y > 0.0 { Heading::South } else { Heading::North })
+        }
+    }
+
+    /// Clockwise from east in radians, as it looks with y going down the
+    /// screen.
+    pub fn angle(self: Self) -> f64 {
+        match self {
+            Heading::East => 0.0,
+            Heading::South => consts::FRAC_PI_2,
+            Heading::West => consts::PI,
+            Heading::North => -consts::FRAC_PI_2,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone)]
+pub struct Image {
+    pub heading: Heading,
+}
+
+impl entities::Display for Vehicle {
+    fn image(self: &Self) -> Option<entities::Image> {
+        let heading = self.heading;
+        Some(entities::Image::Vehicle(Image { heading }))
+    }
+}
+
+/// How far out from the middle of its tile a vehicle reaches.
+pub fn radius() -> units::Distance {
+    9.into()
+}
+
+// close enough to the middle of a tile to count as being there
+fn arrival_distance() -> f64 {
+    4.0
+}
+
+// how long it waits at each stop, and for the way to clear when it has
+// given way
+fn dwell() -> units::Duration {
+    units::moments(32)
+}
+
+fn give_way() -> units::Duration {
+    units::moments(8)
+}
+
+/// Marks that traffic has started easing off the roads, kept in the heap as
+/// the only entity of its type, like the ledger.
+pub struct Easing;
+
+const EASING: sulphate::EntityId = 0;
+
+// the first vehicle out starts the traffic tick, which halves how busy
+// every stretch of road counts as
+fn start_easing(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+) {
+    if matter.get::<Easing>(EASING).is_none() {
+        matter.insert(EASING, Easing);
+        time.every_tunable(config::traffic_tick, matter, ease);
+    }
+}
+
+fn ease(
+    space: &mut space::CollisionSpace,
+    _time: &mut sulphate::EventQueue,
+    _matter: &mut sulphate::EntityHeap,
+) -> bool {
+    space.traffic_mut().ease();
+    true
+}
+
+fn uid(id: sulphate::EntityId) -> sulphate::EntityUId {
+    let ty = any::TypeId::of::<Vehicle>();
+    sulphate::EntityUId { id, ty }
+}
+
+struct DriveEvent {
+    id: sulphate::EntityId,
+}
+
+impl sulphate::Event for DriveEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        drive(space, time, matter, self.id);
+    }
+}
+
+fn separation(from: units::Position, to: units::Position) -> [f64; 2] {
+    let offset = to - from;
+    [offset.x.into(), offset.y.into()]
+}
+
+fn distance(offset: [f64; 2]) -> f64 {
+    (offset[0] * offset[0] + offset[1] * offset[1]).sqrt()
+}
+
+enum Plan {
+    Head(space::TileId),
+    Wait(units::Duration),
+    // a fire engine at the last of its stops, where the fire is
+    PutOut,
+}
+
+// moves on to the next tile of the route, and once there are none left,
+// either waits at the stop it has reached or finds the quickest way to the
+// stop it should be at through the traffic, given each stretch of road's
+// `capacity`
+fn next_move(
+    space: &space::CollisionSpace,
+    vehicle: &mut Vehicle,
+    id: sulphate::EntityId,
+    position: units::Position,
+    capacity: i64,
+) -> Plan {
+    let terrain = space.terrain();
+    while vehicle.route.last().map_or(false, |&next| {
+        let center = terrain.center(next);
+        distance(separation(position, center)) <= arrival_distance()
+    }) {
+        vehicle.route.pop();
+    }
+    if let Some(&next) = vehicle.route.last() {
+        return Plan::Head(next);
+    }
+
+    let here = terrain.tile_id(position);
+    let arrived = here == vehicle.stops[vehicle.stop];
+    let last = vehicle.stop + 1 == vehicle.stops.len();
+    if arrived && last && vehicle.responding.is_some() {
+        return Plan::PutOut;
+    }
+    if arrived {
+        vehicle.stop = (vehicle.stop + 1) % vehicle.stops.len();
+    }
+    let goal = vehicle.stops[vehicle.stop];
+    match space.quickest_route(here, goal, capacity) {
+        Some(mut route) => {
+            route.reverse();
+            vehicle.route = route;
+        },
+        None => {
+            warn!("Vehicle {} has no road to its next stop", id);
+            return Plan::Wait(dwell());
+        },
+    }
+    match vehicle.route.last() {
+        Some(&next) if !arrived => Plan::Head(next),
+        _ => Plan::Wait(dwell()),
+    }
+}
+
+// the vehicle in the way at `next`, if there is one. one that is itself
+// waiting for `id` to move is only given way to if it has the lower id, so
+// that two can't wait on each other forever
+fn in_the_way(
+    space: &space::CollisionSpace,
+    matter: &sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    next: space::TileId,
+    now: units::Time,
+) -> Option<sulphate::EntityId> {
+    let center = space.terrain().center(next);
+    let ty = any::TypeId::of::<Vehicle>();
+    let circle = units::Circle { center, radius: radius() };
+    space.query_circle(now, circle)
+         .into_iter()
+         .filter(|other| other.ty == ty && other.id != id)
+         .find(|other| {
+             let waiting = matter.get::<Vehicle>(other.id)
+                                 .map_or(false, |vehicle| {
+                                     vehicle.waiting_for == Some(id)
+                                 });
+             !waiting || other.id < id
+         })
+         .map(|other| other.id)
+}
+
+// heads for the next tile on the way, and checks back once it should be
+// there, which is also when it next looks out for other vehicles
+fn drive(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) {
+    // left to whoever has the wheel
+    if driver(matter, id).is_some() {
+        return;
+    }
+    // and a fire engine goes once there is no fire left to go to
+    let called_off = matter
+        .get::<Vehicle>(id)
+        .and_then(|vehicle| vehicle.responding)
+        .map_or(false, |target| !fire::is_burning(matter, target));
+    if called_off {
+        Vehicle::remove(space, time, matter, id);
+        return;
+    }
+    let now = time.now();
+    let position = match space.position(uid(id), now) {
+        Some(position) => position,
+        None => return,
+    };
+    let config = config::get(matter);
+    let plan = match matter.get_mut::<Vehicle>(id) {
+        Some(vehicle) => {
+            if let Some(old) = vehicle.driving.take() {
+                time.cancel(&old);
+            }
+            next_move(space, vehicle, id, position, config.road_capacity)
+        },
+        None => return,
+    };
+
+    let blocked = match plan {
+        Plan::Head(next) => in_the_way(space, matter, id, next, now),
+        _ => None,
+    };
+    if let Some(vehicle) = matter.get_mut::<Vehicle>(id) {
+        vehicle.waiting_for = blocked;
+    }
+    let plan = match plan {
+        Plan::PutOut => {
+            let target = matter.get::<Vehicle>(id)
+                               .and_then(|vehicle| vehicle.responding);
+            if let Some(target) = target {
+                fire::put_out(time, matter, target);
+            }
+            Vehicle::remove(space, time, matter, id);
+            return;
+        },
+        Plan::Head(_) if blocked.is_some() => Plan::Wait(give_way()),
+        plan => plan,
+    };
+    let (velocity, delay) = match plan {
+        Plan::Head(next) => {
+            let here = space.terrain().tile_id(position);
+            let slowness = if space.roads().linked(here, next) {
+                count_passing(space, matter, id, here, next);
+                let capacity = config.road_capacity;
+                space.traffic().slowness(here, next, capacity)
+            } else {
+                100
+            };
+            let target = space.terrain().center(next);
+            let offset = separation(position, target);
+            let length = distance(offset);
+            let speed: f64 = config.vehicle_speed.into();
+            let speed = speed * 100.0 / slowness as f64;
+            let velocity = units::Velocity {
+                x: (offset[0] / length * speed).into(),
+                y: (offset[1] / length * speed).into(),
+            };
+            let delay: units::Duration = (length / speed).into();
+            (velocity, delay)
+        },
+        Plan::Wait(delay) => (units::Velocity::default(), delay),
+        // gone already
+        Plan::PutOut => return,
+    };
+
+    {
+        let mut this: space::Entry<Vehicle> = space.entry(time, matter, id);
+        if let Some(heading) = Heading::of(velocity) {
+            if let Some(vehicle) = this.get_mut() {
+                vehicle.heading = heading;
+            }
+        }
+        if let Some(body) = this.body.as_mut() {
+            body.bounce(velocity, now);
+        }
+    }
+
+    let handle = time.schedule_relative(DriveEvent { id }, delay);
+    if let Some(vehicle) = matter.get_mut::<Vehicle>(id) {
+        vehicle.driving = Some(handle);
+    }
+}
+
+// adds vehicle `id` to the traffic between `here` and `next`, unless it
+// was already counted there
+fn count_passing(
+    space: &mut space::CollisionSpace,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    here: space::TileId,
+    next: space::TileId,
+) {
+    let counted = match matter.get_mut::<Vehicle>(id) {
+        Some(vehicle) => {
+            let counted = vehicle.passing == Some((here, next));
+            vehicle.passing = Some((here, next));
+            counted
+        },
+        None => return,
+    };
+    if !counted {
+        space.traffic_mut().pass(here, next);
+    }
+}
+
+/// Whoever is driving vehicle `id`, if anyone is.
+pub fn driver(
+    matter: &sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) -> Option<sulphate::EntityId> {
+    matter.get::<Vehicle>(id).and_then(|vehicle| vehicle.driver)
+}
+
+/// Hands vehicle `id` over to `player`, giving whether it could be, which
+/// it can't if it has gone or if someone else is already driving it.
+pub fn take_the_wheel(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    player: sulphate::EntityId,
+) -> bool {
+    if driver(matter, id).map_or(false, |other| other != player) {
+        return false;
+    }
+    match matter.get_mut::<Vehicle>(id) {
+        Some(vehicle) => {
+            if let Some(old) = vehicle.driving.take() {
+                time.cancel(&old);
+            }
+            vehicle.driver = Some(player);
+            true
+        },
+        None => false,
+    }
+}
+
+/// Sends vehicle `id` off at `velocity` for its driver, no faster than
+/// vehicles go, facing whichever way that is.
+pub fn steer(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    velocity: units::Velocity,
+) {
+    let speed = config::get(matter).vehicle_speed;
+    let velocity = if velocity.squared() > speed * speed {
+        velocity.normalized() * speed
+    } else {
+        velocity
+    };
+    let now = time.now();
+    let mut this: space::Entry<Vehicle> = space.entry(time, matter, id);
+    if let Some(heading) = Heading::of(velocity) {
+        if let Some(vehicle) = this.get_mut() {
+            vehicle.heading = heading;
+        }
+    }
+    if let Some(body) = this.body.as_mut() {
+        body.bounce(velocity, now);
+    }
+}
+
+/// Lets vehicle `id` drive itself again once its driver has got out,
+/// finding its way back to its stops from wherever it was left.
+pub fn hand_back(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) {
+    if let Some(vehicle) = matter.get_mut::<Vehicle>(id) {
+        vehicle.driver = None;
+        vehicle.route.clear();
+        let soon = units::moments(0);
+        let handle = time.schedule_relative(DriveEvent { id }, soon);
+        vehicle.driving = Some(handle);
+    }
+}
+
+impl Vehicle {
+    pub fn stops(self: &Self) -> &[space::TileId] {
+        &self.stops
+    }
+
+    /// The building it is a fire engine on its way to, if it is one.
+    pub fn responding(self: &Self) -> Option<sulphate::EntityId> {
+        self.responding
+    }
+
+    /// A vehicle waiting at the first of `stops`, which mustn't be empty,
+    /// about to set off for the next.
+    pub fn new<'a>(
+        space: &'a mut space::CollisionSpace,
+        time: &'a mut sulphate::EventQueue,
+        matter: &'a mut sulphate::EntityHeap,
+        stops: Vec<space::TileId>,
+    ) -> space::Entry<'a, Vehicle> {
+        let position = space.terrain().center(stops[0]);
+        let id = matter.add(Vehicle::on_the_way(stops));
+        Vehicle::start(space, time, matter, id, position)
+    }
+
+    /// A fire engine leaving the road tile `from` by its station for the
+    /// road tile `to` by building `target`, which it puts out on getting
+    /// there before it goes. It goes early if the fire goes out some other
+    /// way first.
+    pub fn dispatch<'a>(
+        space: &'a mut space::CollisionSpace,
+        time: &'a mut sulphate::EventQueue,
+        matter: &'a mut sulphate::EntityHeap,
+        from: space::TileId,
+        to: space::TileId,
+        target: sulphate::EntityId,
+    ) -> space::Entry<'a, Vehicle> {
+        let position = space.terrain().center(from);
+        let mut vehicle = Vehicle::on_the_way(vec![from, to]);
+        vehicle.responding = Some(target);
+        let id = matter.add(vehicle);
+        sounds::play(time, sounds::Sound::Siren, position);
+        Vehicle::start(space, time, matter, id, position)
+    }
+
+    /// Puts a vehicle back as it was saved, going along `body` on its way
+    /// to stop number `route.0` with the tiles `route.1` left to go, until
+    /// `due`, when it looks at where it has got to. Without a time it was
+    /// due, it works out where to go straight away.
+    pub fn restore<'a>(
+        space: &'a mut space::CollisionSpace,
+        time: &'a mut sulphate::EventQueue,
+        matter: &'a mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+        body: space::Body,
+        stops: Vec<space::TileId>,
+        route: (u64, Vec<space::TileId>),
+        due: Option<units::Time>,
+    ) -> space::Entry<'a, Vehicle> {
+        let (stop, tiles) = route;
+        let mut vehicle = Vehicle::on_the_way(stops);
+        if (stop as usize) < vehicle.stops.len() {
+            vehicle.stop = stop as usize;
+            vehicle.route = tiles;
+        }
+        if let Some(heading) = Heading::of(body.velocity()) {
+            vehicle.heading = heading;
+        }
+        matter.insert(id, vehicle);
+        let due = match due {
+            Some(due) => due,
+            None => {
+                let position = body.position(time.now());
+                return Vehicle::start(space, time, matter, id, position);
+            },
+        };
+        start_easing(time, matter);
+        let due = cmp::max(due, time.now());
+        let handle = time.schedule_absolute(DriveEvent { id }, due);
+        if let Some(vehicle) = matter.get_mut::<Vehicle>(id) {
+            vehicle.driving = Some(handle);
+        }
+        let mut this = space.entry::<Vehicle>(time, matter, id);
+        this.body = Some(body);
+        this
+    }
+
+    /// Which of its stops it is on its way to, and the road tiles left on
+    /// the way there, the next one last.
+    pub fn route(self: &Self) -> (usize, &[space::TileId]) {
+        (self.stop, &self.route)
+    }
+
+    /// When it next looks at where it has got to, if it is driving itself.
+    pub fn due(self: &Self) -> Option<units::Time> {
+        self.driving.as_ref().and_then(sulphate::EventHandle::due)
+    }
+
+    fn on_the_way(stops: Vec<space::TileId>) -> Vehicle {
+        Vehicle {
+            stops,
+            stop: 0,
+            route: Vec::new(),
+            heading: Heading::East,
+            driving: None,
+            driver: None,
+            responding: None,
+            passing: None,
+            waiting_for: None,
+        }
+    }
+
+    // it can only work out where to go once it has a body
+    fn start<'a>(
+        space: &'a mut space::CollisionSpace,
+        time: &'a mut sulphate::EventQueue,
+        matter: &'a mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+        position: units::Position,
+    ) -> space::Entry<'a, Vehicle> {
+        start_easing(time, matter);
+        // straight after it is in the space, so that it stops there first
+        let soon = units::moments(0);
+        let handle = time.schedule_relative(DriveEvent { id }, soon);
+        if let Some(vehicle) = matter.get_mut::<Vehicle>(id) {
+            vehicle.driving = Some(handle);
+        }
+        let mut this = space.entry::<Vehicle>(time, matter, id);
+        this.body = Some(space::Body::new_frozen(position));
+        this
+    }
+
+    pub fn remove(
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+    ) -> Option<Vehicle> {
+        let driving = matter.get_mut::<Vehicle>(id)
+                            .and_then(|vehicle| vehicle.driving.take());
+        if let Some(driving) = driving {
+            time.cancel(&driving);
+        }
+        {
+            let mut this: space::Entry<Vehicle> =
+                space.entry(time, matter, id);
+            this.body = None;
+        }
+        matter.remove(id)
+    }
+}
+
+impl entities::Interact for Vehicle {
+    // anyone can get on, wherever it is, and rides along until they get
+    // back off, the first one on taking the wheel
+    fn on_interact(
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+        player: sulphate::EntityId,
+    ) -> Option<entities::Interaction> {
+        if entities::player::board(space, time, matter, player, id) {
+            let driving = driver(matter, id) == Some(player);
+            Some(entities::Interaction::Boarded { vehicle: id, driving })
+        } else {
+            None
+        }
+    }
+}
+
+impl space::Collide for Vehicle {
+    // it stops until its next check, when it gives way or carries on,
+    // unless someone is driving it, who keeps it going or not themselves,
+    // or it only went over something
+    fn collide(
+        mut this: space::Entry<Vehicle>,
+        other: space::Image,
+    ) {
+        if this.get().map_or(false, |vehicle| vehicle.driver.is_some()) {
+            return;
+        }
+        if !other.inner_image.is_solid() {
+            return;
+        }
+        let now = this.now();
+
+        let body = this.body.as_mut().expect("Collided without a body");
+        body.bounce(units::Velocity::default(), now);
+    }
+
+    fn release(
+        _this: space::Entry<Vehicle>,
+        _other: space::Image,
+    ) {}
+
+    fn disappear(
+        _this: space::Entry<Vehicle>,
+        _other: space::Image,
+    ) {}
+}
+
+#[cfg(test)]
+mod test_vehicle {
+    use std::sync::mpsc;
+    use std::time;
+
+    use sulphate::server;
+    use sulphate::testing::TestServer;
+    use units;
+
+    use super::*;
+
+    fn inspect(
+        upd: &mpsc::Sender<server::Interruption>,
+        id: sulphate::EntityId,
+    ) -> sulphate::EntitySummary {
+        let wait = time::Duration::from_secs(1);
+        let (reply, recv) = mpsc::channel();
+        upd.send(server::Interruption::Inspect { id, reply }).unwrap();
+        recv.recv_timeout(wait)
+            .expect("server didn't reply")
+            .expect("vehicle went missing")
+            .summary
+    }
+
+    fn road(
+        space: &mut space::CollisionSpace,
+        from: space::TileId,
+        to: space::TileId,
+    ) {
+        let from = space.terrain().center(from);
+        let to = space.terrain().center(to);
+        assert!(space.build_road(from, to));
+    }
+
+    #[test]
+    fn headings_follow_velocity() {
+        let velocity = |x: i32, y: i32| units::Velocity {
+            x: x.into(),
+            y: y.into(),
+        };
+        assert_eq!(Heading::of(velocity(5, 1)), Some(Heading::East));
+        assert_eq!(Heading::of(velocity(-1, -5)), Some(Heading::North));
+        assert_eq!(Heading::of(velocity(0, 0)), None);
+    }
+
+    #[test]
+    fn vehicles_drive_to_their_stops() {
+        let clock = server::ManualClock::new(Default::default());
+        let (upd, mut clock, handle, (id, end)) =
+            server::start_server_with_clock(|space, time, matter| {
+                road(space, (0, 0), (6, 0));
+                let end = space.terrain().center((6, 0));
+                let stops = vec![(0, 0), (6, 0)];
+                let id = Vehicle::new(space, time, matter, stops).id();
+                (id, end)
+            }, clock);
+
+        // it waits at the first stop and then waits again at the second,
+        // having driven the six tiles in between in two seconds
+        clock.advance(units::moments(16 * 5));
+        let summary = inspect(&upd, id);
+        let left = separation(summary.position, end);
+        assert!(distance(left) <= arrival_distance());
+        assert!(summary.velocity == units::Velocity::default());
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn vehicles_give_way_to_those_ahead() {
+        let clock = server::ManualClock::new(Default::default());
+        let (upd, mut clock, handle, (first, second, wait)) =
+            server::start_server_with_clock(|space, time, matter| {
+                road(space, (0, 0), (6, 0));
+                // parked for good in the middle of the road
+                let first =
+                    Vehicle::new(space, time, matter, vec![(3, 0)]).id();
+                let stops = vec![(0, 0), (6, 0)];
+                let second = Vehicle::new(space, time, matter, stops).id();
+                let wait = space.terrain().center((2, 0));
+                (first, second, wait)
+            }, clock);
+
+        clock.advance(units::moments(16 * 10));
+        let summary = inspect(&upd, second);
+        let left = separation(summary.position, wait);
+        assert!(distance(left) <= arrival_distance());
+        assert!(summary.velocity == units::Velocity::default());
+        let parked = inspect(&upd, first);
+        assert!(parked.velocity == units::Velocity::default());
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn traffic_slows_vehicles_down() {
+        let (mut server, (id, end)) = TestServer::new(|space, time, matter| {
+            road(space, (0, 0), (6, 0));
+            // twice as busy as the road takes, all the way along
+            let busy = 2 * config::Config::default().road_capacity;
+            for x in 0..6 {
+                for _ in 0..busy {
+                    space.traffic_mut().pass((x, 0), (x + 1, 0));
+                }
+            }
+            let end = space.terrain().center((6, 0));
+            let stops = vec![(0, 0), (6, 0)];
+            (Vehicle::new(space, time, matter, stops).id(), end)
+        });
+
+        // it gets to the end in four seconds on an empty road, but only
+        // three quarters of the way after five on this one
+        server.advance_by(units::moments(16 * 5));
+        let position = server.space()
+                             .position(uid(id), server.now())
+                             .expect("vehicle went missing");
+        assert!(distance(separation(position, end)) > 32.0);
+        let busy = 2 * config::Config::default().road_capacity;
+        let traffic = server.space().traffic();
+        assert_eq!(traffic.load((0, 0), (1, 0)), busy + 1);
+    }
+}
@@ -0,0 +1,310 @@
+use std::any;
+
+use rand::Rng;
+
+use config;
+use economy;
+use entities::building;
+use entities::vehicle;
+use space;
+use space::TileId;
+use space::chunks;
+use space::roads;
+use sulphate;
+use sulphate::Scheduler;
+use sulphate::rng;
+use sulphate::tick::TickScheduler;
+use units;
+
+/// Marks that buildings have started catching fire, kept in the heap as
+/// the only entity of its type, like the ledger, along with the fire ticks
+/// that frozen chunks have missed.
+pub struct Fires {
+    frozen: chunks::Frozen,
+}
+
+const FIRES: sulphate::EntityId = 0;
+
+/// Starts buildings catching fire every so often, if they aren't already.
+pub fn start(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+) {
+    if matter.get::<Fires>(FIRES).is_none() {
+        matter.insert(FIRES, Fires { frozen: chunks::Frozen::new() });
+        time.every_tunable(config::fire_tick, matter, tick);
+    }
+}
+
+/// Whether building `id` is on fire.
+pub fn is_burning(
+    matter: &sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) -> bool {
+    matter.get::<building::Building>(id)
+          .map_or(false, building::Building::burning)
+}
+
+struct SpreadEvent {
+    id: sulphate::EntityId,
+}
+
+impl sulphate::Event for SpreadEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        burn_down(space, time, matter, self.id);
+    }
+}
+
+fn tile_of(
+    space: &space::CollisionSpace,
+    now: units::Time,
+    id: sulphate::EntityId,
+) -> Option<TileId> {
+    let ty = any::TypeId::of::<building::Building>();
+    let uid = sulphate::EntityUId { id, ty };
+    let position = space.position(uid, now)?;
+    Some(space.terrain().tile_id(position))
+}
+
+// every building standing, in id order
+fn buildings(matter: &mut sulphate::EntityHeap) -> Vec<sulphate::EntityId> {
+    matter.query::<(&economy::Flow,)>()
+          .into_iter()
+          .map(|(id, _)| id)
+          .collect()
+}
+
+// each building draws from its own stream, so that whether one catches
+// fire doesn't depend on how many others there are. buildings in frozen
+// chunks are left be, and once their chunk thaws out they get a roll for
+// every tick they missed, which is what they would have drawn anyway
+fn tick(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+) -> bool {
+    let now = time.now();
+    let chance = config::get(matter).fire_chance;
+    let standing: Vec<(sulphate::EntityId, Option<chunks::ChunkId>)> =
+        buildings(matter)
+            .into_iter()
+            .map(|id| (id, tile_of(space, now, id).map(chunks::chunk_of)))
+            .collect();
+    let heat = chunks::heat(space, matter, now);
+    let owed = match matter.get_mut::<Fires>(FIRES) {
+        Some(fires) => {
+            let occupied =
+                standing.iter().filter_map(|&(_, at)| at).collect();
+            fires.frozen.thaw(&heat, occupied)
+        },
+        None => Default::default(),
+    };
+    for (id, at) in standing {
+        // anything with nowhere to be is never frozen
+        let rolls = at.map_or(1, |at| owed.get(&at).cloned().unwrap_or(0));
+        for _ in 0..rolls {
+            if is_burning(matter, id) {
+                break;
+            }
+            let roll: i64 = rng::stream(matter, id).gen_range(0, 100);
+            if roll < chance {
+                ignite(space, time, matter, id);
+            }
+        }
+    }
+    true
+}
+
+/// Sets building `id` alight, giving whether it wasn't already, and sends
+/// out a fire engine from whichever fire station has the shortest drive
+/// there. Unless the engine gets there first, the building burns down once
+/// it has burned for `Config::fire_spread`, setting light to any building
+/// beside it.
+pub fn ignite(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) -> bool {
+    if matter.get::<building::Building>(id).is_none()
+        || is_burning(matter, id)
+    {
+        return false;
+    }
+    let delay = config::get(matter).fire_spread;
+    let spreading = time.schedule_relative(SpreadEvent { id }, delay);
+    if let Some(building) = matter.get_mut::<building::Building>(id) {
+        building.catch_fire(spreading);
+    }
+    if let Some((from, to)) = nearest_station(space, matter, time.now(), id) {
+        vehicle::Vehicle::dispatch(space, time, matter, from, to, id);
+    }
+    true
+}
+
+/// Puts out the fire in building `id`, giving whether it was on fire.
+pub fn put_out(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) -> bool {
+    let spreading = matter.get_mut::<building::Building>(id)
+                          .and_then(building::Building::put_out);
+    match spreading {
+        Some(spreading) => {
+            time.cancel(&spreading);
+            true
+        },
+        None => false,
+    }
+}
+
+// the road tiles beside a fire station and beside building `id` with the
+// fewest stretches of road between them, out of every station
+fn nearest_station(
+    space: &space::CollisionSpace,
+    matter: &mut sulphate::EntityHeap,
+    now: units::Time,
+    id: sulphate::EntityId,
+) -> Option<(TileId, TileId)> {
+    let roads = space.roads();
+    let scene = roads.access(tile_of(space, now, id)?);
+    let mut best: Option<(usize, TileId, TileId)> = None;
+    for station in buildings(matter) {
+        let blueprint = matter.get::<building::Building>(station)
+                              .map(building::Building::blueprint);
+        if blueprint != Some(building::Blueprint::FireStation) {
+            continue;
+        }
+        let tile = match tile_of(space, now, station) {
+            Some(tile) => tile,
+            None => continue,
+        };
+        for &from in &roads.access(tile) {
+            for &to in &scene {
+                let length = match roads.route(from, to) {
+                    Some(route) => route.len(),
+                    None => continue,
+                };
+                if best.map_or(true, |(shortest, _, _)| length < shortest) {
+                    best = Some((length, from, to));
+                }
+            }
+        }
+    }
+    best.map(|(_, from, to)| (from, to))
+}
+
+// the fire wasn't put out in time, so the building goes, and the
+// buildings beside it catch
+fn burn_down(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) {
+    if !put_out(time, matter, id) {
+        return;
+    }
+    let tile = tile_of(space, time.now(), id);
+    building::Building::remove(space, time, matter, id);
+    let tile = match tile {
+        Some(tile) => tile,
+        None => return,
+    };
+    for &next in &roads::beside(tile) {
+        let now = time.now();
+        if let Some(other) = building::standing_on(space, now, next) {
+            ignite(space, time, matter, other);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_fire {
+    use sulphate::persist;
+    use sulphate::testing::TestServer;
+    use units;
+
+    use super::*;
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    fn standing(server: &TestServer, id: sulphate::EntityId) -> bool {
+        server.matter().get::<building::Building>(id).is_some()
+    }
+
+    fn engines(server: &TestServer) -> usize {
+        let vehicle = Some(persist::EntityKind::Vehicle);
+        server.world()
+              .entities(server.now())
+              .iter()
+              .filter(|entity| entity.kind == vehicle)
+              .count()
+    }
+
+    #[test]
+    fn fires_spread_to_the_buildings_beside_them() {
+        let (mut server, (first, second, away)) =
+            TestServer::new(|space, time, matter| {
+                let (first, second, away) = {
+                    let mut place = |x, y| {
+                        building::place(
+                            space, time, matter, building::Blueprint::House,
+                            at(x, y),
+                        ).expect("no room to build")
+                    };
+                    (place(0, 0), place(40, 0), place(200, 0))
+                };
+                assert!(ignite(space, time, matter, first));
+                assert!(!ignite(space, time, matter, first));
+                (first, second, away)
+            });
+        assert!(is_burning(server.matter(), first));
+        // there is no fire station to send anyone
+        assert_eq!(engines(&server), 0);
+
+        let spread = config::Config::default().fire_spread;
+        server.advance_by(spread + units::moments(1));
+        assert!(!standing(&server, first));
+        assert!(is_burning(server.matter(), second));
+        assert!(!is_burning(server.matter(), away));
+    }
+
+    #[test]
+    fn fire_engines_put_fires_out() {
+        let (mut server, (station, house)) =
+            TestServer::new(|space, time, matter| {
+                // along the row of tiles from y = 32 to 64
+                space.build_road(at(0, 40), at(300, 40));
+                let station = building::place(
+                    space, time, matter, building::Blueprint::FireStation,
+                    at(0, 0),
+                ).expect("no room for the station");
+                let house = building::place(
+                    space, time, matter, building::Blueprint::House,
+                    at(250, 0),
+                ).expect("no room for the house");
+                ignite(space, time, matter, house);
+                (station, house)
+            });
+        assert_eq!(engines(&server), 1);
+
+        // seven tiles away, which is a few seconds' drive
+        server.advance_by(units::moments(16 * 5));
+        assert!(!is_burning(server.matter(), house));
+        assert_eq!(engines(&server), 0);
+        let spread = config::Config::default().fire_spread;
+        server.advance_by(spread);
+        assert!(standing(&server, house));
+        assert!(standing(&server, station));
+    }
+}
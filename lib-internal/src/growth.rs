@@ -0,0 +1,263 @@
+use config;
+use demand;
+use entities::building;
+use space;
+use space::chunks;
+use space::zones;
+use sulphate;
+use sulphate::tick::TickScheduler;
+use units;
+use utilities;
+
+/// What grows on tiles of each zone.
+pub fn blueprint(zone: zones::Zone) -> building::Blueprint {
+    match zone {
+        zones::Zone::Residential => building::Blueprint::House,
+        zones::Zone::Commercial => building::Blueprint::Shop,
+        zones::Zone::Industrial => building::Blueprint::Factory,
+    }
+}
+
+// a big zone fills in over a few ticks rather than all at once
+const GROWTH_PER_TICK: usize = 4;
+
+/// Marks that the city has started growing, kept in the heap as the only
+/// entity of its type, like the ledger, along with the growth that frozen
+/// chunks have missed.
+pub struct Growth {
+    frozen: chunks::Frozen,
+}
+
+const GROWTH: sulphate::EntityId = 0;
+
+/// Starts buildings growing on zoned tiles, if they aren't already.
+pub fn start(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+) {
+    if matter.get::<Growth>(GROWTH).is_none() {
+        matter.insert(GROWTH, Growth { frozen: chunks::Frozen::new() });
+        time.every_tunable(config::growth_tick, matter, tick);
+    }
+}
+
+/// Paints `zone` on the tiles between `from` and `to`, as
+/// `CollisionSpace::paint_zone` does, and gets them growing.
+pub fn zone(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    from: units::Position,
+    to: units::Position,
+    zone: Option<zones::Zone>,
+) -> usize {
+    let painted = space.paint_zone(from, to, zone);
+    start(time, matter);
+    painted
+}
+
+// tiles that are zoned, empty, and beside a road, in order
+fn vacant(space: &space::CollisionSpace) -> Vec<(space::TileId, zones::Zone)> {
+    let roads = space.roads();
+    space.zones()
+         .tiles()
+         .into_iter()
+         .filter(|&(id, _)| {
+             !space.terrain().tile(id).solid()
+                 && !roads.has_road(id)
+                 && !roads.access(id).is_empty()
+         })
+         .collect()
+}
+
+// only the tiles in hot chunks grow, and a chunk that has just thawed out
+// makes up for the ticks it missed, growing as much more as it would have
+// in the meantime
+fn thawed(
+    space: &space::CollisionSpace,
+    matter: &mut sulphate::EntityHeap,
+    now: units::Time,
+) -> (Vec<(space::TileId, zones::Zone)>, usize) {
+    let vacant = vacant(space);
+    let heat = chunks::heat(space, matter, now);
+    let owed = match matter.get_mut::<Growth>(GROWTH) {
+        Some(growth) => {
+            let zoned = vacant
+                .iter()
+                .map(|&(id, _)| chunks::chunk_of(id))
+                .collect();
+            growth.frozen.thaw(&heat, zoned)
+        },
+        None => Default::default(),
+    };
+    let missed: u64 = owed.values().map(|&ticks| ticks - 1).sum();
+    let vacant = vacant
+        .into_iter()
+        .filter(|&(id, _)| owed.contains_key(&chunks::chunk_of(id)))
+        .collect();
+    (vacant, GROWTH_PER_TICK * (1 + missed as usize))
+}
+
+// power goes along the roads and water along the pipes, so a tile counts
+// as supplied if the lines beside or under it belong to networks with
+// enough of each spare for whatever would grow there. nothing grows on a
+// zone the city doesn't want more of, and each building that grows uses
+// up as much of the demand as the people it has room for
+fn tick(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+) -> bool {
+    let now = time.now();
+    // each utility's networks, and how much each has spare
+    let mut grids = Vec::new();
+    let every = utilities::every_network(space, matter, now);
+    for (&utility, networks) in utilities::Utility::all().iter().zip(every) {
+        let spare: Vec<i64> = networks
+            .iter()
+            .map(|network| utilities::spare(matter, network, utility))
+            .collect();
+        grids.push((utility, networks, spare));
+    }
+    let mut wanted = demand::demand(space, matter, now);
+    let (vacant, most) = thawed(space, matter, now);
+    let mut grown = 0;
+    for (id, zone) in vacant {
+        if grown == most {
+            break;
+        }
+        if wanted.of(zone) <= 0 {
+            continue;
+        }
+        let blueprint = blueprint(zone);
+        let uses = config::get(matter).flow(blueprint).uses;
+        let on: Vec<Option<usize>> = grids
+            .iter()
+            .map(|&(utility, ref networks, _)| {
+                utilities::network_at(networks, utility.lines(space), id)
+            })
+            .collect();
+        let supplied = grids.iter().zip(&on).all(|(grid, &network)| {
+            let needs = grid.0.of(uses);
+            needs == 0 || network.map_or(false, |i| grid.2[i] >= needs)
+        });
+        if !supplied {
+            continue;
+        }
+        let position = space.terrain().center(id);
+        let placed = building::place(space, time, matter, blueprint, position);
+        if placed.is_some() {
+            for (grid, &network) in grids.iter_mut().zip(&on) {
+                if let Some(i) = network {
+                    grid.2[i] -= grid.0.of(uses);
+                }
+            }
+            wanted.take(zone, blueprint.occupants());
+            grown += 1;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod test_growth {
+    use std::sync::mpsc;
+    use std::time;
+
+    use sulphate::persist;
+    use sulphate::server;
+    use sulphate::testing::TestServer;
+    use units;
+
+    use super::*;
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    fn buildings(
+        upd: &mpsc::Sender<server::Interruption>,
+    ) -> Vec<units::Position> {
+        let (reply, recv) = mpsc::channel();
+        upd.send(server::Interruption::ListEntities { reply }).unwrap();
+        recv.recv_timeout(time::Duration::from_secs(1))
+            .expect("server didn't reply")
+            .into_iter()
+            .filter(|entity| {
+                entity.kind == Some(persist::EntityKind::Building)
+            })
+            .map(|entity| entity.position)
+            .collect()
+    }
+
+    #[test]
+    fn houses_grow_beside_roads_once_powered() {
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, mut clock, handle, ()) =
+            server::start_server_with_clock(|space, time, matter| {
+                // along the row of tiles from y = 0 to 32, with a pipe
+                // under it, and the plant and tower on the other side of it
+                // from the zone
+                space.build_road(at(0, 10), at(200, 10));
+                space.lay_pipe(at(0, 10), at(200, 10));
+                let plant = building::Blueprint::PowerPlant;
+                building::place(space, time, matter, plant, at(150, -20));
+                let tower = building::Blueprint::WaterTower;
+                building::place(space, time, matter, tower, at(200, -20));
+            }, clock);
+
+        // two rows of three, only the first of which is beside the road
+        let (reply, _) = mpsc::channel();
+        let paint = server::Interruption::PaintZone {
+            from: at(10, 40),
+            to: at(80, 70),
+            zone: Some(zones::Zone::Residential),
+            by: None,
+            reply,
+        };
+        upd.send(paint).unwrap();
+
+        let period = config::Config::default().growth_tick;
+        clock.advance(period + units::moments(1));
+        let grown = buildings(&upd);
+        assert_eq!(grown.len(), 2 + 3);
+        let row = at(0, 48).y;
+        let houses = grown.iter().filter(|position| position.y == row);
+        assert_eq!(houses.count(), 3);
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn shops_only_grow_once_people_live_nearby() {
+        let (mut server, ()) = TestServer::new(|space, time, matter| {
+            // the same road, pipe, plant and tower as above
+            space.build_road(at(0, 10), at(200, 10));
+            space.lay_pipe(at(0, 10), at(200, 10));
+            let plant = building::Blueprint::PowerPlant;
+            building::place(space, time, matter, plant, at(150, -20));
+            let tower = building::Blueprint::WaterTower;
+            building::place(space, time, matter, tower, at(200, -20));
+            let commercial = Some(zones::Zone::Commercial);
+            zone(space, time, matter, at(10, 40), at(80, 40), commercial);
+        });
+        let (reply, recv) = mpsc::channel();
+        server.send(server::Interruption::QueryDemand { reply });
+        let demand = recv.try_recv().expect("server didn't reply");
+        assert_eq!(demand.of(zones::Zone::Commercial), 0);
+
+        let period = config::Config::default().growth_tick;
+        server.advance_by(period + units::moments(1));
+        let building = Some(persist::EntityKind::Building);
+        let standing = server.world()
+                             .entities(server.now())
+                             .into_iter()
+                             .filter(|entity| entity.kind == building)
+                             .count();
+        // only the plant and the tower
+        assert_eq!(standing, 2);
+    }
+}
@@ -1,13 +1,35 @@
 #![feature(get_type_id)]
 
 extern crate lib_math;
+#[macro_use]
+extern crate log;
 extern crate rand;
+#[cfg(feature = "parallel")]
+extern crate rayon;
+#[cfg(feature = "scripting")]
+extern crate rhai;
 extern crate sulphate_lib;
 
+pub mod admin;
+pub mod blueprints;
+pub mod budget;
+pub mod config;
+pub mod construction;
+pub mod demand;
+pub mod economy;
 pub mod entities;
+pub mod fire;
+pub mod growth;
+pub mod logging;
+pub mod mods;
+pub mod net;
+pub mod scripting;
 pub mod space;
 // module to hide `lib_math`
 pub mod units;
+pub mod utilities;
+pub mod world;
+pub mod worldgen;
 
 // module to hide `sulphate_lib`
 pub mod sulphate;
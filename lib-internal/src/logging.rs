@@ -0,0 +1,152 @@
+use std::str::FromStr;
+use std::sync;
+use std::time;
+
+use log;
+
+/// How much gets logged, from `Off` up to `Trace`.
+pub type Level = log::LogLevelFilter;
+
+// what gets logged unless something has been said about a module
+const DEFAULT_LEVEL: Level = log::LogLevelFilter::Info;
+
+struct Filters {
+    default: Level,
+    // by module path, which also covers the modules inside it
+    modules: Vec<(String, Level)>,
+}
+
+impl Filters {
+    fn level(self: &Self, target: &str) -> Level {
+        self.modules
+            .iter()
+            .filter(|&&(ref module, _)| covers(module, target))
+            .max_by_key(|&&(ref module, _)| module.len())
+            .map_or(self.default, |&(_, level)| level)
+    }
+}
+
+fn covers(module: &str, target: &str) -> bool {
+    if !target.starts_with(module) {
+        return false;
+    }
+    let rest = &target[module.len()..];
+    rest.is_empty() || rest.starts_with("::")
+}
+
+// the filters are set from the admin console while everything else logs,
+// so they live for the whole program, starting out the first time that
+// anything needs them
+static START: sync::Once = sync::ONCE_INIT;
+static mut FILTERS: *const sync::RwLock<Filters> =
+    0 as *const sync::RwLock<Filters>;
+
+fn filters() -> &'static sync::RwLock<Filters> {
+    START.call_once(|| {
+        let filters = Filters {
+            default: DEFAULT_LEVEL,
+            modules: Vec::new(),
+        };
+        let filters = Box::new(sync::RwLock::new(filters));
+        unsafe {
+            FILTERS = Box::into_raw(filters);
+        }
+    });
+    // only ever written once, inside `call_once`, and never freed
+    unsafe { &*FILTERS }
+}
+
+fn read() -> sync::RwLockReadGuard<'static, Filters> {
+    filters().read().unwrap_or_else(|poison| poison.into_inner())
+}
+
+fn write() -> sync::RwLockWriteGuard<'static, Filters> {
+    filters().write().unwrap_or_else(|poison| poison.into_inner())
+}
+
+struct Logger;
+
+impl log::Log for Logger {
+    fn enabled(self: &Self, metadata: &log::LogMetadata) -> bool {
+        metadata.level() <= read().level(metadata.target())
+    }
+
+    fn log(self: &Self, record: &log::LogRecord) {
+        if self.enabled(record.metadata()) {
+            println!("{:<5} {}: {}", record.level(), record.target(),
+                     record.args());
+        }
+    }
+}
+
+/// Starts logging to standard output. Until this is called, nothing that
+/// is logged goes anywhere.
+pub fn init() {
+    let result = log::set_logger(|max_level| {
+        // the logger does the filtering, since the filters can change
+        max_level.set(log::LogLevelFilter::Trace);
+        Box::new(Logger)
+    });
+    if result.is_err() {
+        println!("Logging was already started");
+    }
+}
+
+/// Parses a level such as `info` or `TRACE`.
+pub fn parse_level(word: &str) -> Option<Level> {
+    Level::from_str(word).ok()
+}
+
+/// Logs `module`, and the modules inside it, at `level`, or everything
+/// that hasn't been given a level of its own if `module` is None.
+pub fn set_level(module: Option<&str>, level: Level) {
+    let mut filters = write();
+    match module {
+        Some(module) => {
+            filters.modules.retain(|&(ref each, _)| each != module);
+            filters.modules.push((module.to_string(), level));
+        },
+        None => filters.default = level,
+    }
+}
+
+/// The level that something logged from `module` would need to be at.
+pub fn level(module: &str) -> Level {
+    read().level(module)
+}
+
+/// How long `duration` is in whole microseconds, for logging how long
+/// something took.
+pub fn micros(duration: time::Duration) -> u64 {
+    let whole = duration.as_secs().saturating_mul(1_000_000);
+    whole.saturating_add(u64::from(duration.subsec_nanos() / 1_000))
+}
+
+#[cfg(test)]
+mod test_logging {
+    use super::*;
+
+    #[test]
+    fn modules_cover_what_is_inside_them() {
+        let filters = Filters {
+            default: Level::Warn,
+            modules: vec![
+                ("city_internal::net".to_string(), Level::Debug),
+                ("city_internal::net::delta".to_string(), Level::Off),
+            ],
+        };
+        assert_eq!(filters.level("city_internal::net"), Level::Debug);
+        assert_eq!(filters.level("city_internal::net::interest"),
+                   Level::Debug);
+        assert_eq!(filters.level("city_internal::net::delta"), Level::Off);
+        assert_eq!(filters.level("city_internal::network"), Level::Warn);
+        assert_eq!(filters.level("city_internal::sulphate"), Level::Warn);
+    }
+
+    #[test]
+    fn levels_parse_however_they_are_written() {
+        assert_eq!(parse_level("info"), Some(Level::Info));
+        assert_eq!(parse_level("TRACE"), Some(Level::Trace));
+        assert_eq!(parse_level("loud"), None);
+    }
+}
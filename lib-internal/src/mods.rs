@@ -0,0 +1,528 @@
+//! Content packs, which bundle changes to the game up so that they can be
+//! handed around and put in the mods directory, each in a directory of its
+//! own beside the loose scripts that `scripting` runs.
+//!
+//! A pack's directory has a `pack.txt` saying what the pack is,
+//!
+//!     # anything after a hash is ignored
+//!     name bigger_plants
+//!     version 1.2
+//!     priority 10
+//!
+//! with the priority left at 0 if it isn't given, along with whichever of
+//! these the pack needs:
+//!
+//! - a `config.txt`, written like the game's own, going over its settings
+//! - a `blueprints.txt`, written as `blueprints` describes, adding
+//!   blueprints or going over those there are
+//! - a `sprites.txt` and the art it names, written like the mapping in the
+//!   assets directory, going over the game's art
+//! - `.rhai` scripts, run as mods after the loose ones
+//!
+//! The kinds of building there are belong to the game, so a pack can't add
+//! new ones, but it can rebalance those there are through its config's
+//! costs and flows, make new things out of them through its blueprints,
+//! and redraw them through its sprites. A loose `blueprints.txt` in the
+//! mods directory goes on before any pack's.
+//!
+//! Packs go on in order of priority, lowest first, and then of name, so
+//! whatever the pack with the highest priority says about a setting, a
+//! blueprint or a sprite is what counts. Two packs with the same priority
+//! that both set something are warned about, since which of them wins is
+//! only down to their names. A pack with anything wrong with it is left
+//! out altogether rather than only partly going on, as is any pack named
+//! the same as one that goes on after it.
+//!
+//! Saves record which packs were running, so that a world picked back up
+//! without them can warn that it won't play out the way it did.
+
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path;
+
+use blueprints;
+use config;
+use scripting;
+use sulphate;
+
+/// Where mods and packs are, unless the server is told otherwise.
+pub fn mods_dir() -> path::PathBuf {
+    path::PathBuf::from("mods")
+}
+
+/// What a pack says it is, in its `pack.txt`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Manifest {
+    pub name: String,
+    pub version: String,
+    /// Packs with higher priorities go on after, and so over, the rest.
+    pub priority: i64,
+}
+
+/// Which version of a pack the world was running, as saves record it.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Stamp {
+    pub name: String,
+    pub version: String,
+}
+
+/// Reads a `pack.txt`, a field and its value to a line.
+pub fn read_manifest(text: &str) -> Result<Manifest, String> {
+    let mut name = None;
+    let mut version = None;
+    let mut priority = 0;
+    for line in text.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let field = match words.get(0) {
+            Some(&field) => field,
+            None => continue,
+        };
+        let value = match words.get(1) {
+            Some(&value) if words.len() == 2 => value,
+            _ => return Err(format!("{} needs one word after it", field)),
+        };
+        match field {
+            "name" => name = Some(value.to_string()),
+            "version" => version = Some(value.to_string()),
+            "priority" => {
+                priority = value.parse().map_err(|_| {
+                    format!("{} is not a whole number", value)
+                })?;
+            },
+            _ => return Err(format!("unknown field {}", field)),
+        }
+    }
+    Ok(Manifest {
+        name: name.ok_or("missing name")?,
+        version: version.ok_or("missing version")?,
+        priority,
+    })
+}
+
+/// A pack that was read without anything wrong with it.
+pub struct Pack {
+    pub manifest: Manifest,
+    /// Where the pack is, which its art is found relative to.
+    pub dir: path::PathBuf,
+    // lines of config, which are known to read
+    config: String,
+    // and of blueprints, which are too
+    blueprints: String,
+    scripts: Vec<scripting::Script>,
+}
+
+impl Pack {
+    pub fn stamp(self: &Self) -> Stamp {
+        Stamp {
+            name: self.manifest.name.clone(),
+            version: self.manifest.version.clone(),
+        }
+    }
+}
+
+fn read_text(path: &path::Path) -> io::Result<String> {
+    let mut text = String::new();
+    fs::File::open(path)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+// the pack in `dir`, or what is wrong with it
+fn read(dir: &path::Path) -> Result<Pack, String> {
+    let manifest = read_text(&dir.join("pack.txt"))
+        .map_err(|e| format!("couldn't read pack.txt: {}", e))?;
+    let manifest = read_manifest(&manifest)
+        .map_err(|reason| format!("pack.txt: {}", reason))?;
+
+    let config_path = dir.join("config.txt");
+    let mut config = String::new();
+    if config_path.exists() {
+        config = read_text(&config_path)
+            .map_err(|e| format!("couldn't read config.txt: {}", e))?;
+        if let Err(e) = config::Config::default().with(&config) {
+            return Err(format!("config.txt {}", e));
+        }
+    }
+
+    let blueprints_path = dir.join("blueprints.txt");
+    let mut blueprints = String::new();
+    if blueprints_path.exists() {
+        blueprints = read_text(&blueprints_path)
+            .map_err(|e| format!("couldn't read blueprints.txt: {}", e))?;
+        if let Err(e) = blueprints::Registry::parse(&blueprints) {
+            return Err(format!("blueprints.txt {}", e));
+        }
+    }
+
+    let mut scripts = scripting::load(dir)
+        .map_err(|e| format!("couldn't read its scripts: {}", e))?;
+    for script in &mut scripts {
+        script.name = format!("{}/{}", manifest.name, script.name);
+    }
+    let dir = dir.to_path_buf();
+    Ok(Pack { manifest, dir, config, blueprints, scripts })
+}
+
+/// The packs found in a mods directory.
+pub struct Found {
+    /// In the order they go on in.
+    pub packs: Vec<Pack>,
+    /// Every directory that was left out, and why.
+    pub rejected: Vec<(path::PathBuf, String)>,
+    /// Warnings about packs of the same priority setting the same thing.
+    pub clashes: Vec<String>,
+}
+
+/// Every pack in `dir`, being every directory in it, of which there are
+/// none if there is no `dir`.
+pub fn discover(dir: &path::Path) -> Found {
+    let mut dirs: Vec<path::PathBuf> = match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    dirs.sort();
+
+    let mut read_fine = Vec::new();
+    let mut rejected = Vec::new();
+    for dir in dirs {
+        match read(&dir) {
+            Ok(pack) => read_fine.push(pack),
+            Err(reason) => rejected.push((dir, reason)),
+        }
+    }
+    // the sort is stable, so packs with the same name and priority keep
+    // to the order of their directories
+    read_fine.sort_by(|a, b| {
+        let a = (a.manifest.priority, &a.manifest.name);
+        let b = (b.manifest.priority, &b.manifest.name);
+        a.cmp(&b)
+    });
+
+    let mut packs: Vec<Pack> = Vec::new();
+    for pack in read_fine.into_iter().rev() {
+        let name = pack.manifest.name.clone();
+        if packs.iter().any(|kept| kept.manifest.name == name) {
+            let reason = format!("another pack named {} goes on after", name);
+            rejected.push((pack.dir, reason));
+        } else {
+            packs.push(pack);
+        }
+    }
+    packs.reverse();
+
+    let mut clashes = Vec::new();
+    for (n, pack) in packs.iter().enumerate() {
+        let settings = sets(pack);
+        for later in &packs[n + 1..] {
+            if later.manifest.priority != pack.manifest.priority {
+                continue;
+            }
+            for setting in sets(later) {
+                let clash = format!(
+                    "{} and {} both set {}, so {} goes on last",
+                    pack.manifest.name,
+                    later.manifest.name,
+                    setting,
+                    later.manifest.name,
+                );
+                if settings.contains(&setting) && !clashes.contains(&clash) {
+                    clashes.push(clash);
+                }
+            }
+        }
+    }
+
+    Found { packs, rejected, clashes }
+}
+
+// every setting and blueprint that `pack` goes over
+fn sets(pack: &Pack) -> Vec<String> {
+    let mut sets = config::settings(&pack.config);
+    for name in blueprints::defined(&pack.blueprints) {
+        sets.push(format!("blueprint {}", name));
+    }
+    sets
+}
+
+// the built in blueprints with the loose `blueprints.txt` in `dir` gone
+// over them, or what is wrong with it
+fn loose_blueprints(dir: &path::Path) -> Result<blueprints::Registry, String> {
+    let path = dir.join("blueprints.txt");
+    if !path.exists() {
+        return Ok(blueprints::Registry::default());
+    }
+    let text = read_text(&path).map_err(|e| e.to_string())?;
+    blueprints::Registry::parse(&text).map_err(|e| e.to_string())
+}
+
+/// The blueprints that the mods in `dir` make for, being the built in ones
+/// with the loose `blueprints.txt` gone over them, unless there is
+/// something wrong with it, and then those of each of `packs` in turn.
+pub fn registry(dir: &path::Path, packs: &[Pack]) -> blueprints::Registry {
+    let loose = loose_blueprints(dir).unwrap_or_else(|reason| {
+        let path = dir.join("blueprints.txt");
+        warn!("Left out {}: {}", path.display(), reason);
+        blueprints::Registry::default()
+    });
+    packs.iter().fold(loose, |registry, pack| {
+        let before = registry.clone();
+        registry.with(&pack.blueprints).unwrap_or(before)
+    })
+}
+
+// `base` with each of `configs` gone over it in turn
+fn go_over(base: config::Config, configs: &[String]) -> config::Config {
+    configs.iter().fold(base, |config, text| {
+        config.with(text).unwrap_or(config)
+    })
+}
+
+/// Warnings for each pack in `saved` that isn't in `running`, or is but
+/// in another version.
+pub fn missing(saved: &[Stamp], running: &[Stamp]) -> Vec<String> {
+    saved.iter()
+         .filter_map(|stamp| {
+             let found = running.iter().find(|each| each.name == stamp.name);
+             match found {
+                 None => Some(format!(
+                     "The save was made with {} {}, which isn't here",
+                     stamp.name,
+                     stamp.version,
+                 )),
+                 Some(found) if found.version != stamp.version => {
+                     Some(format!(
+                         "The save was made with {} {}, not {}",
+                         stamp.name,
+                         stamp.version,
+                         found.version,
+                     ))
+                 },
+                 Some(_) => None,
+             }
+         })
+         .collect()
+}
+
+/// The packs the world is running, kept in the heap as the only entity of
+/// its type, along with what they go over.
+pub struct Active {
+    packs: Vec<Stamp>,
+    // each pack's config, in the order they go on in
+    configs: Vec<String>,
+    // the config from before the packs went over it
+    base: config::Config,
+    // what the world was running when it was saved, until packs are loaded
+    // for it to be checked against
+    saved: Option<Vec<Stamp>>,
+}
+
+const ACTIVE: sulphate::EntityId = 0;
+
+// the packs, put in the heap if they aren't there yet
+fn active_mut(matter: &mut sulphate::EntityHeap) -> &mut Active {
+    if matter.get::<Active>(ACTIVE).is_none() {
+        let active = Active {
+            packs: Vec::new(),
+            configs: Vec::new(),
+            base: config::get(matter),
+            saved: None,
+        };
+        matter.insert(ACTIVE, active);
+    }
+    matter.get_mut::<Active>(ACTIVE).expect("Active packs went missing")
+}
+
+/// Which packs the world is running, in the order they went on in.
+pub fn active(matter: &sulphate::EntityHeap) -> Vec<Stamp> {
+    matter.get::<Active>(ACTIVE).map_or(Vec::new(), |active| {
+        active.packs.clone()
+    })
+}
+
+/// Remembers that the world was running `saved` when it was saved, to be
+/// checked against whatever packs are loaded into it next.
+pub fn restored(matter: &mut sulphate::EntityHeap, saved: Vec<Stamp>) {
+    active_mut(matter).saved = Some(saved);
+}
+
+/// Has the world run with the settings in `base` from now on, with the
+/// packs it is running gone over them.
+pub fn reconfigure(matter: &mut sulphate::EntityHeap, base: config::Config) {
+    let config = match matter.get_mut::<Active>(ACTIVE) {
+        Some(active) => {
+            active.base = base;
+            go_over(base, &active.configs)
+        },
+        None => base,
+    };
+    config::set(matter, config);
+}
+
+/// Runs the world with `packs` from now on, in place of whatever packs it
+/// had before, giving a warning for each pack that it was saved with but
+/// won't be running.
+pub fn set(matter: &mut sulphate::EntityHeap, packs: &[Pack]) -> Vec<String> {
+    let (config, warnings) = {
+        let active = active_mut(matter);
+        active.packs = packs.iter().map(Pack::stamp).collect();
+        active.configs = packs.iter()
+                              .map(|pack| pack.config.clone())
+                              .collect();
+        let warnings = match active.saved.take() {
+            Some(saved) => missing(&saved, &active.packs),
+            None => Vec::new(),
+        };
+        (go_over(active.base, &active.configs), warnings)
+    };
+    config::set(matter, config);
+    warnings
+}
+
+/// Loads the loose scripts and blueprints and every pack in `dir` into the
+/// world, in place of whatever it was running before, saying what was left
+/// out and why.
+pub fn load(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    dir: &path::Path,
+) {
+    let found = discover(dir);
+    for &(ref pack, ref reason) in &found.rejected {
+        warn!("Left out the pack in {}: {}", pack.display(), reason);
+    }
+    for clash in &found.clashes {
+        warn!("{}", clash);
+    }
+
+    // the world may have been saved with packs even if there are none now
+    if dir.is_dir() {
+        let mut scripts = match scripting::load(dir) {
+            Ok(scripts) => scripts,
+            Err(e) => {
+                error!("Failed to load mods from {}: {}", dir.display(), e);
+                Vec::new()
+            },
+        };
+        for pack in &found.packs {
+            scripts.extend(pack.scripts.iter().cloned());
+        }
+        let count = scripts.len();
+        scripting::set(time, matter, scripts);
+        info!(
+            "Loaded {} mods and {} packs from {}",
+            count,
+            found.packs.len(),
+            dir.display(),
+        );
+    }
+    blueprints::set(matter, registry(dir, &found.packs));
+    for warning in set(matter, &found.packs) {
+        warn!("{}", warning);
+    }
+}
+
+#[cfg(test)]
+mod test_mods {
+    use std::env;
+    use std::io::Write;
+
+    use entities::building;
+
+    use super::*;
+
+    fn write(path: &path::Path, text: &str) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::File::create(path).unwrap().write_all(text.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn manifests_say_what_is_wrong_with_them() {
+        let manifest = read_manifest("name big\nversion 1.0 # first\n");
+        assert_eq!(manifest, Ok(Manifest {
+            name: "big".to_string(),
+            version: "1.0".to_string(),
+            priority: 0,
+        }));
+        let error = |text: &str| read_manifest(text).unwrap_err();
+        assert_eq!(error("version 1"), "missing name");
+        assert_eq!(error("name big\npriority high"),
+                   "high is not a whole number");
+        assert_eq!(error("name big pack"), "name needs one word after it");
+        assert_eq!(error("author me"), "unknown field author");
+    }
+
+    #[test]
+    fn packs_go_on_in_order_of_priority() {
+        let dir = env::temp_dir().join("lil_city_packs_test");
+        let _ = fs::remove_dir_all(&dir);
+        write(&dir.join("a/pack.txt"), "name cheap\nversion 1\npriority 5");
+        write(&dir.join("a/config.txt"), "cost shop 10\nroad_cost 1");
+        write(&dir.join("b/pack.txt"), "name dear\nversion 2");
+        write(&dir.join("b/config.txt"), "cost shop 500\npipe_cost 50");
+        write(&dir.join("b/blueprints.txt"), "blueprint hut building house");
+        write(&dir.join("c/pack.txt"), "name broken\nversion 1");
+        write(&dir.join("c/config.txt"), "cost castle 10");
+        write(&dir.join("d/pack.txt"), "name also_cheap\nversion 1\n\
+                                        priority 5");
+        write(&dir.join("d/config.txt"), "road_cost 2");
+
+        let found = discover(&dir);
+        let names: Vec<&str> = found.packs
+                                    .iter()
+                                    .map(|pack| &pack.manifest.name[..])
+                                    .collect();
+        assert_eq!(names, vec!["dear", "also_cheap", "cheap"]);
+        assert_eq!(found.rejected.len(), 1);
+        assert_eq!(found.rejected[0].0, dir.join("c"));
+        assert_eq!(found.clashes, vec![
+            "also_cheap and cheap both set road_cost, so cheap goes on last"
+                .to_string(),
+        ]);
+
+        let mut matter = sulphate::EntityHeap::new();
+        assert!(set(&mut matter, &found.packs).is_empty());
+        let config = config::get(&matter);
+        assert_eq!(config.cost(building::Blueprint::Shop), 10);
+        assert_eq!(config.road_cost, 1);
+        assert_eq!(config.pipe_cost, 50);
+        // and they still go over a config loaded after them
+        let base = config::Config::parse("pipe_cost 7\nroad_upkeep 4");
+        reconfigure(&mut matter, base.unwrap());
+        let config = config::get(&matter);
+        assert_eq!(config.pipe_cost, 50);
+        assert_eq!(config.road_upkeep, 4);
+
+        write(&dir.join("blueprints.txt"), "blueprint hut item apple");
+        let hut = blueprints::Name::new("hut").unwrap();
+        let registry = registry(&dir, &found.packs);
+        let body = registry.get(hut).map(|prefab| prefab.body());
+        let house = building::Blueprint::House;
+        assert_eq!(body, Some(blueprints::Body::Building(house)));
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn saves_warn_about_packs_that_are_missing() {
+        let stamp = |name: &str, version: &str| Stamp {
+            name: name.to_string(),
+            version: version.to_string(),
+        };
+        let saved = vec![stamp("a", "1"), stamp("b", "1"), stamp("c", "2")];
+        let running = vec![stamp("a", "1"), stamp("c", "3")];
+        assert_eq!(missing(&saved, &running), vec![
+            "The save was made with b 1, which isn't here".to_string(),
+            "The save was made with c 2, not 3".to_string(),
+        ]);
+
+        let mut matter = sulphate::EntityHeap::new();
+        restored(&mut matter, saved);
+        assert_eq!(set(&mut matter, &[]).len(), 3);
+        // and only the first packs loaded are checked
+        assert!(set(&mut matter, &[]).is_empty());
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections;
+use std::io;
+
+use net;
+use net::RemoteBody;
+use net::RemoteSnapshot;
+use net::ServerMessage;
+use sulphate;
+use sulphate::persist;
+use units;
+
+// a whole snapshot is sent this often anyway, in case a delta was applied
+// to the wrong thing without either end noticing
+const KEYFRAME_PERIOD: u32 = 64;
+
+// how many snapshots either end holds on to, waiting to hear back about
+// them or to have deltas based on them
+const HISTORY: usize = 32;
+
+/// The fields of a body that changed since the base snapshot, or all of
+/// them if it wasn't there before.
+#[derive(Clone, PartialEq, Debug)]
+pub struct BodyChange {
+    pub id: sulphate::EntityId,
+    pub kind: Option<persist::EntityKind>,
+    pub position: Option<units::Position>,
+    pub velocity: Option<units::Velocity>,
+}
+
+/// The world as of `time`, written as changes to the snapshot from `base`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Delta {
+    pub time: units::Time,
+    pub base: units::Time,
+    pub changes: Vec<BodyChange>,
+    pub removed: Vec<sulphate::EntityId>,
+}
+
+// where a body would have got to by `time` if nothing changed, worked out
+// the same way at both ends, so that it only needs sending when it's wrong
+fn expected(
+    body: &RemoteBody,
+    base: units::Time,
+    time: units::Time,
+) -> units::Position {
+    body.position + body.velocity * (time - base)
+}
+
+fn diff(base: &RemoteSnapshot, snapshot: &RemoteSnapshot) -> Delta {
+    let old: collections::HashMap<_, _> = base.bodies
+        .iter()
+        .map(|body| (body.id, body))
+        .collect();
+
+    let mut changes = Vec::new();
+    for body in &snapshot.bodies {
+        let change = match old.get(&body.id) {
+            Some(&before) => {
+                let position = expected(before, base.time, snapshot.time);
+                BodyChange {
+                    id: body.id,
+                    kind: if body.kind != before.kind {
+                        Some(body.kind)
+                    } else {
+                        None
+                    },
+                    position: if body.position != position {
+                        Some(body.position)
+                    } else {
+                        None
+                    },
+                    velocity: if body.velocity != before.velocity {
+                        Some(body.velocity)
+                    } else {
+                        None
+                    },
+                }
+            },
+            None => BodyChange {
+                id: body.id,
+                kind: Some(body.kind),
+                position: Some(body.position),
+                velocity: Some(body.velocity),
+            },
+        };
+        let changed = change.kind.is_some()
+            || change.position.is_some()
+            || change.velocity.is_some();
+        if changed {
+            changes.push(change);
+        }
+    }
+
+    let removed = base.bodies
+        .iter()
+        .map(|body| body.id)
+        .filter(|&id| snapshot.bodies.iter().all(|body| body.id != id))
+        .collect();
+
+    Delta { time: snapshot.time, base: base.time, changes, removed }
+}
+
+// None if the delta describes a new body without saying what it is
+fn apply(base: &RemoteSnapshot, delta: &Delta) -> Option<RemoteSnapshot> {
+    let mut bodies: Vec<RemoteBody> = base.bodies
+        .iter()
+        .filter(|body| !delta.removed.contains(&body.id))
+        .map(|body| RemoteBody {
+            position: expected(body, base.time, delta.time),
+            ..body.clone()
+        })
+        .collect();
+
+    for change in &delta.changes {
+        if let Some(body) = bodies.iter_mut().find(|b| b.id == change.id) {
+            if let Some(kind) = change.kind {
+                body.kind = kind;
+            }
+            if let Some(position) = change.position {
+                body.position = position;
+            }
+            if let Some(velocity) = change.velocity {
+                body.velocity = velocity;
+            }
+            continue;
+        }
+        bodies.push(RemoteBody {
+            id: change.id,
+            kind: change.kind?,
+            position: change.position?,
+            velocity: change.velocity?,
+        });
+    }
+
+    Some(RemoteSnapshot { time: delta.time, bodies })
+}
+
+/// Decides what each snapshot sent to a client should look like, given
+/// what the client has said it already has.
+pub struct Encoder {
+    // sent but not heard back about yet, oldest first
+    unacknowledged: collections::VecDeque<RemoteSnapshot>,
+    acknowledged: Option<RemoteSnapshot>,
+    since_keyframe: u32,
+}
+
+impl Encoder {
+    pub fn new() -> Encoder {
+        Encoder {
+            unacknowledged: collections::VecDeque::new(),
+            acknowledged: None,
+            since_keyframe: 0,
+        }
+    }
+
+    /// Notes that the client has the snapshot from `time`, so that deltas
+    /// can be based on it from now on.
+    pub fn acknowledge(self: &mut Self, time: units::Time) {
+        // any older snapshot would make for a worse base anyway
+        while let Some(snapshot) = self.unacknowledged.pop_front() {
+            if snapshot.time == time {
+                self.acknowledged = Some(snapshot);
+                return;
+            }
+            if snapshot.time > time {
+                self.unacknowledged.push_front(snapshot);
+                return;
+            }
+        }
+    }
+
+    /// Forgets what the client has, so that it gets the whole world next.
+    pub fn resync(self: &mut Self) {
+        self.unacknowledged.clear();
+        self.acknowledged = None;
+    }
+
+    pub fn encode(
+        self: &mut Self,
+        snapshot: RemoteSnapshot,
+    ) -> ServerMessage {
+        let message = match self.acknowledged {
+            Some(ref base) if self.since_keyframe < KEYFRAME_PERIOD => {
+                self.since_keyframe += 1;
+                ServerMessage::Delta(diff(base, &snapshot))
+            },
+            _ => {
+                self.since_keyframe = 0;
+                ServerMessage::Snapshot(snapshot.clone())
+            },
+        };
+        self.unacknowledged.push_back(snapshot);
+        while self.unacknowledged.len() > HISTORY {
+            self.unacknowledged.pop_front();
+        }
+        message
+    }
+}
+
+/// Turns deltas back into whole snapshots, on the client's end.
+pub struct Decoder {
+    // oldest first
+    received: collections::VecDeque<RemoteSnapshot>,
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        let received = collections::VecDeque::new();
+        Decoder { received }
+    }
+
+    fn remember(self: &mut Self, snapshot: RemoteSnapshot) {
+        self.received.push_back(snapshot);
+        while self.received.len() > HISTORY {
+            self.received.pop_front();
+        }
+    }
+
+    pub fn keyframe(self: &mut Self, snapshot: RemoteSnapshot) {
+        self.remember(snapshot);
+    }
+
+    /// The snapshot that `delta` describes, or None if it is based on a
+    /// snapshot that we don't have.
+    pub fn apply(self: &mut Self, delta: &Delta) -> Option<RemoteSnapshot> {
+        let snapshot = {
+            let base = self.received
+                           .iter()
+                           .find(|snapshot| snapshot.time == delta.base)?;
+            apply(base, delta)?
+        };
+        self.remember(snapshot.clone());
+        Some(snapshot)
+    }
+
+    /// Forgets everything, so that only a keyframe can be decoded next.
+    pub fn clear(self: &mut Self) {
+        self.received.clear();
+    }
+}
+
+pub fn write(delta: &Delta, out: &mut io::Write) -> io::Result<()> {
+    writeln!(
+        out, "delta {} {} {} {}",
+        delta.time.into_bits(), delta.base.into_bits(),
+        delta.changes.len(), delta.removed.len(),
+    )?;
+    for change in &delta.changes {
+        write!(out, "change {}", change.id)?;
+        if let Some(kind) = change.kind {
+            write!(out, " kind {}", kind.tag())?;
+        }
+        if let Some(position) = change.position {
+            write!(
+                out, " at {} {}",
+                position.x.into_bits(), position.y.into_bits(),
+            )?;
+        }
+        if let Some(velocity) = change.velocity {
+            write!(
+                out, " moving {} {}",
+                velocity.x.into_bits(), velocity.y.into_bits(),
+            )?;
+        }
+        writeln!(out, "")?;
+    }
+    for id in &delta.removed {
+        writeln!(out, "gone {}", id)?;
+    }
+    Ok(())
+}
+
+fn parse_change(line: &str) -> Option<BodyChange> {
+    let words = net::words(line);
+    if *words.get(0)? != "change" {
+        return None;
+    }
+    let id = words.get(1)?.parse().ok()?;
+    let mut change = BodyChange {
+        id,
+        kind: None,
+        position: None,
+        velocity: None,
+    };
+    let mut n = 2;
+    while let Some(&field) = words.get(n) {
+        match field {
+            "kind" => {
+                let tag = words.get(n + 1)?.parse().ok()?;
+                change.kind = Some(persist::EntityKind::from_tag(tag)?);
+                n += 2;
+            },
+            "at" => {
+                let x = net::coord(&words, n + 1)?;
+                let y = net::coord(&words, n + 2)?;
+                change.position = Some(units::Position { x, y });
+                n += 3;
+            },
+            "moving" => {
+                let x = net::scalar(&words, n + 1)?;
+                let y = net::scalar(&words, n + 2)?;
+                change.velocity = Some(units::Velocity { x, y });
+                n += 3;
+            },
+            _ => return None,
+        }
+    }
+    Some(change)
+}
+
+fn parse_removed(line: &str) -> Option<sulphate::EntityId> {
+    let words = net::words(line);
+    if *words.get(0)? != "gone" {
+        return None;
+    }
+    words.get(1)?.parse().ok()
+}
+
+/// Reads the rest of a delta, given the words of its first line.
+pub fn read(
+    words: &[&str],
+    line: &str,
+    input: &mut io::BufRead,
+) -> io::Result<Delta> {
+    let header = || -> Option<_> {
+        let time = net::coord(words, 1)?;
+        let base = net::coord(words, 2)?;
+        let changed = net::int(words, 3)?;
+        let removed = net::int(words, 4)?;
+        Some((time, base, changed, removed))
+    };
+    let (time, base, changed, removed) =
+        header().ok_or_else(|| net::invalid(line))?;
+
+    let mut changes = Vec::new();
+    for _ in 0..changed {
+        let mut change_line = String::new();
+        net::read_line(input, &mut change_line)?;
+        let change = parse_change(&change_line)
+            .ok_or_else(|| net::invalid(&change_line))?;
+        changes.push(change);
+    }
+    let mut gone = Vec::new();
+    for _ in 0..removed {
+        let mut removed_line = String::new();
+        net::read_line(input, &mut removed_line)?;
+        let id = parse_removed(&removed_line)
+            .ok_or_else(|| net::invalid(&removed_line))?;
+        gone.push(id);
+    }
+    Ok(Delta { time, base, changes, removed: gone })
+}
+
+#[cfg(test)]
+mod test_delta {
+    use std::io;
+
+    use net;
+    use sulphate::persist;
+    use units;
+
+    use super::*;
+
+    fn body(id: u64, x: i32, vx: i32) -> net::RemoteBody {
+        let offset = units::Displacement { x: x.into(), y: 0.into() };
+        net::RemoteBody {
+            id,
+            kind: persist::EntityKind::Player,
+            position: units::Position::default() + offset,
+            velocity: units::Velocity { x: vx.into(), y: 0.into() },
+        }
+    }
+
+    fn at(moments: i32, bodies: Vec<net::RemoteBody>) -> net::RemoteSnapshot {
+        let time = units::Time::default() + units::moments(moments);
+        net::RemoteSnapshot { time, bodies }
+    }
+
+    // everything goes through the text format, as it would over a socket
+    fn send(message: &ServerMessage) -> ServerMessage {
+        let mut bytes = Vec::new();
+        message.write(&mut bytes).unwrap();
+        let mut input = io::Cursor::new(bytes);
+        ServerMessage::read(&mut input).unwrap().expect("nothing was sent")
+    }
+
+    #[test]
+    fn only_changes_are_sent() {
+        let mut encoder = Encoder::new();
+        let mut decoder = Decoder::new();
+
+        // 16 units a second is exactly one unit a moment
+        let first = at(0, vec![body(1, 0, 16), body(2, 5, 0), body(3, 9, 0)]);
+        match send(&encoder.encode(first.clone())) {
+            ServerMessage::Snapshot(snapshot) => decoder.keyframe(snapshot),
+            _ => panic!("first snapshot wasn't a keyframe"),
+        }
+        encoder.acknowledge(first.time);
+
+        // 1 keeps going, 2 turns, 3 leaves and 4 arrives
+        let second = at(1, vec![body(1, 1, 16), body(2, 5, 8), body(4, 2, 0)]);
+        let delta = match send(&encoder.encode(second.clone())) {
+            ServerMessage::Delta(delta) => delta,
+            _ => panic!("acknowledged snapshot wasn't used as a base"),
+        };
+        let ids: Vec<_> = delta.changes.iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec![2, 4]);
+        assert_eq!(delta.changes[0].position, None);
+        assert_eq!(delta.removed, vec![3]);
+
+        let decoded = decoder.apply(&delta).expect("base was missing");
+        assert_eq!(decoded.checksum(), second.checksum());
+    }
+
+    #[test]
+    fn missing_bases_and_keyframes() {
+        let mut encoder = Encoder::new();
+        let mut decoder = Decoder::new();
+        let first = at(0, vec![body(1, 0, 0)]);
+        encoder.encode(first.clone());
+        encoder.acknowledge(first.time);
+
+        // the client never got the base
+        let delta = match encoder.encode(at(1, vec![body(1, 3, 0)])) {
+            ServerMessage::Delta(delta) => delta,
+            _ => panic!("acknowledged snapshot wasn't used as a base"),
+        };
+        assert_eq!(decoder.apply(&delta), None);
+
+        encoder.resync();
+        match encoder.encode(at(2, vec![body(1, 3, 0)])) {
+            ServerMessage::Snapshot(_) => (),
+            _ => panic!("resync didn't send a keyframe"),
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! What a client and server agree on before the client gets to join.
+//!
+//! The client says which version of the protocol it speaks, which ways of
+//! compressing messages it can do, and which packs it has. The server lets
+//! it in only if it speaks the same version and has the same packs as the
+//! world is running, since the client builds from the blueprints and draws
+//! with the art its own packs bring. Otherwise the server says why not and
+//! hangs up, rather than the two of them finding out halfway through a
+//! game that they can't read each other.
+//!
+//! A rejection reads the same in every version of the protocol, so that a
+//! client from any version can say why it was turned away.
+
+use std::fmt;
+use std::io;
+
+use mods;
+
+/// Which version of the protocol this build speaks, to be bumped whenever
+/// what is sent changes in a way that the other side can't read.
+pub const PROTOCOL_VERSION: u64 = 2;
+
+/// Ways that messages can be squeezed on the way. Each side says which it
+/// can do, so that new ones can come along without a new version of the
+/// protocol, but so far everything is sent as it is.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Compression {
+    Plain,
+}
+
+impl Compression {
+    /// Every way this build can do, in the order it would rather use them.
+    pub fn all() -> Vec<Compression> {
+        vec![Compression::Plain]
+    }
+
+    pub fn name(self: Self) -> &'static str {
+        match self {
+            Compression::Plain => "plain",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Compression> {
+        match name {
+            "plain" => Some(Compression::Plain),
+            _ => None,
+        }
+    }
+}
+
+/// Why the server wouldn't let a client in.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Rejection {
+    /// The server speaks version `server` of the protocol, and the client
+    /// `client`, or one from before there were handshakes if None.
+    Protocol {
+        server: u64,
+        client: Option<u64>,
+    },
+    /// The server can't do any of the compression the client can.
+    Compression,
+    /// The world is running a pack the client doesn't have.
+    MissingPack(mods::Stamp),
+    /// The world is running `server`, but the client has another version.
+    PackVersion {
+        server: mods::Stamp,
+        client: String,
+    },
+    /// The client has a pack the world isn't running.
+    ExtraPack(mods::Stamp),
+}
+
+// said from the point of view of the client, which is where it is shown
+impl fmt::Display for Rejection {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Rejection::Protocol { server, client: Some(client) } => write!(
+                f, "the server speaks version {} of the protocol, not {}",
+                server, client,
+            ),
+            Rejection::Protocol { server, client: None } => write!(
+                f, "the server speaks version {} of the protocol, which is \
+                    newer than this game",
+                server,
+            ),
+            Rejection::Compression => {
+                write!(f, "the server can't read anything this game sends")
+            },
+            Rejection::MissingPack(ref stamp) => write!(
+                f, "the server runs {} {}, which isn't here",
+                stamp.name, stamp.version,
+            ),
+            Rejection::PackVersion { ref server, ref client } => write!(
+                f, "the server runs {} {}, not {}",
+                server.name, server.version, client,
+            ),
+            Rejection::ExtraPack(ref stamp) => write!(
+                f, "the server doesn't run {} {}, which is here",
+                stamp.name, stamp.version,
+            ),
+        }
+    }
+}
+
+/// How the server would talk to a client that said it speaks `version`,
+/// can do `compression` and has `theirs`, when the world is running
+/// `ours`, or why it won't.
+pub fn agree(
+    version: u64,
+    compression: &[Compression],
+    theirs: &[mods::Stamp],
+    ours: &[mods::Stamp],
+) -> Result<Compression, Rejection> {
+    if version != PROTOCOL_VERSION {
+        let server = PROTOCOL_VERSION;
+        return Err(Rejection::Protocol { server, client: Some(version) });
+    }
+    for stamp in ours {
+        match theirs.iter().find(|each| each.name == stamp.name) {
+            None => return Err(Rejection::MissingPack(stamp.clone())),
+            Some(found) if found.version != stamp.version => {
+                return Err(Rejection::PackVersion {
+                    server: stamp.clone(),
+                    client: found.version.clone(),
+                });
+            },
+            Some(_) => (),
+        }
+    }
+    let extra = theirs.iter().find(|stamp| {
+        !ours.iter().any(|each| each.name == stamp.name)
+    });
+    if let Some(stamp) = extra {
+        return Err(Rejection::ExtraPack(stamp.clone()));
+    }
+    Compression::all()
+        .into_iter()
+        .find(|way| compression.contains(way))
+        .ok_or(Rejection::Compression)
+}
+
+// how stamps go in a line, being one word each for the name and version
+fn write_stamps(
+    out: &mut io::Write,
+    stamps: &[mods::Stamp],
+) -> io::Result<()> {
+    write!(out, " {}", stamps.len())?;
+    for stamp in stamps {
+        write!(out, " {} {}", stamp.name, stamp.version)?;
+    }
+    Ok(())
+}
+
+fn stamp(words: &[&str], n: usize) -> Option<mods::Stamp> {
+    Some(mods::Stamp {
+        name: words.get(n)?.to_string(),
+        version: words.get(n + 1)?.to_string(),
+    })
+}
+
+pub fn write_handshake(
+    version: u64,
+    compression: &[Compression],
+    mods: &[mods::Stamp],
+    out: &mut io::Write,
+) -> io::Result<()> {
+    write!(out, "handshake {} {}", version, compression.len())?;
+    for way in compression {
+        write!(out, " {}", way.name())?;
+    }
+    write_stamps(out, mods)?;
+    writeln!(out, "")
+}
+
+/// Reads a client's handshake, leaving out any compression this build
+/// doesn't know of. Only the version is read from a client that speaks
+/// another one, since nothing after it is known to read the same.
+pub fn read_handshake(
+    words: &[&str],
+) -> Option<(u64, Vec<Compression>, Vec<mods::Stamp>)> {
+    let version = words.get(1)?.parse().ok()?;
+    if version != PROTOCOL_VERSION {
+        return Some((version, Vec::new(), Vec::new()));
+    }
+    let ways: usize = words.get(2)?.parse().ok()?;
+    let compression = words.get(3..)?
+                           .get(..ways)?
+                           .iter()
+                           .filter_map(|name| Compression::from_name(name))
+                           .collect();
+    let count: usize = words.get(3 + ways)?.parse().ok()?;
+    let mut mods = Vec::new();
+    for n in 0..count {
+        mods.push(stamp(words, 4 + ways + 2 * n)?);
+    }
+    Some((version, compression, mods))
+}
+
+pub fn write_rejection(
+    rejection: &Rejection,
+    out: &mut io::Write,
+) -> io::Result<()> {
+    match *rejection {
+        Rejection::Protocol { server, client } => {
+            let client = client.map_or("-".to_string(), |v| v.to_string());
+            writeln!(out, "reject protocol {} {}", server, client)
+        },
+        Rejection::Compression => writeln!(out, "reject compression"),
+        Rejection::MissingPack(ref stamp) => writeln!(
+            out, "reject missing {} {}", stamp.name, stamp.version,
+        ),
+        Rejection::PackVersion { ref server, ref client } => writeln!(
+            out, "reject version {} {} {}",
+            server.name, server.version, client,
+        ),
+        Rejection::ExtraPack(ref stamp) => writeln!(
+            out, "reject extra {} {}", stamp.name, stamp.version,
+        ),
+    }
+}
+
+pub fn read_rejection(words: &[&str]) -> Option<Rejection> {
+    let rejection = match *words.get(1)? {
+        "protocol" => {
+            let server = words.get(2)?.parse().ok()?;
+            let client = match *words.get(3)? {
+                "-" => None,
+                client => Some(client.parse().ok()?),
+            };
+            Rejection::Protocol { server, client }
+        },
+        "compression" => Rejection::Compression,
+        "missing" => Rejection::MissingPack(stamp(words, 2)?),
+        "version" => Rejection::PackVersion {
+            server: stamp(words, 2)?,
+            client: words.get(4)?.to_string(),
+        },
+        "extra" => Rejection::ExtraPack(stamp(words, 2)?),
+        _ => return None,
+    };
+    Some(rejection)
+}
+
+#[cfg(test)]
+mod test_handshake {
+    use net;
+
+    use super::*;
+
+    fn stamp(name: &str, version: &str) -> mods::Stamp {
+        mods::Stamp {
+            name: name.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    #[test]
+    fn only_clients_like_the_server_get_in() {
+        let plain = Compression::all();
+        let ours = vec![stamp("huts", "1.0")];
+        let agreed = agree(PROTOCOL_VERSION, &plain, &ours, &ours);
+        assert_eq!(agreed, Ok(Compression::Plain));
+
+        let newer = PROTOCOL_VERSION + 1;
+        assert_eq!(
+            agree(newer, &plain, &ours, &ours),
+            Err(Rejection::Protocol {
+                server: PROTOCOL_VERSION,
+                client: Some(newer),
+            }),
+        );
+        assert_eq!(
+            agree(PROTOCOL_VERSION, &plain, &[], &ours),
+            Err(Rejection::MissingPack(stamp("huts", "1.0"))),
+        );
+        assert_eq!(
+            agree(PROTOCOL_VERSION, &plain, &[stamp("huts", "2.0")], &ours),
+            Err(Rejection::PackVersion {
+                server: stamp("huts", "1.0"),
+                client: "2.0".to_string(),
+            }),
+        );
+        let both = vec![stamp("huts", "1.0"), stamp("towers", "1")];
+        assert_eq!(
+            agree(PROTOCOL_VERSION, &plain, &both, &ours),
+            Err(Rejection::ExtraPack(stamp("towers", "1"))),
+        );
+        assert_eq!(
+            agree(PROTOCOL_VERSION, &[], &ours, &ours),
+            Err(Rejection::Compression),
+        );
+    }
+
+    #[test]
+    fn handshakes_read_back_as_they_were_written() {
+        let mods = vec![stamp("huts", "1.0"), stamp("towers", "1")];
+        let mut out = Vec::new();
+        write_handshake(PROTOCOL_VERSION, &Compression::all(), &mods, &mut out)
+            .unwrap();
+        let line = String::from_utf8(out).unwrap();
+        let (version, compression, read) =
+            read_handshake(&net::words(&line)).unwrap();
+        assert_eq!(version, PROTOCOL_VERSION);
+        assert_eq!(compression, Compression::all());
+        assert_eq!(read, mods);
+
+        // whatever a client of another version says after its version
+        // isn't looked at
+        let other = format!("handshake {} zstd", PROTOCOL_VERSION + 1);
+        let (version, _, read) = read_handshake(&net::words(&other)).unwrap();
+        assert_eq!(version, PROTOCOL_VERSION + 1);
+        assert!(read.is_empty());
+
+        let rejections = vec![
+            Rejection::Protocol { server: 3, client: None },
+            Rejection::Protocol { server: 3, client: Some(2) },
+            Rejection::Compression,
+            Rejection::MissingPack(stamp("huts", "1.0")),
+            Rejection::PackVersion {
+                server: stamp("huts", "1.0"),
+                client: "0.9".to_string(),
+            },
+            Rejection::ExtraPack(stamp("towers", "1")),
+        ];
+        for rejection in rejections {
+            let mut out = Vec::new();
+            write_rejection(&rejection, &mut out).unwrap();
+            let line = String::from_utf8(out).unwrap();
+            assert_eq!(read_rejection(&net::words(&line)), Some(rejection));
+        }
+    }
+}
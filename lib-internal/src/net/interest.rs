@@ -0,0 +1,96 @@
+use std::collections;
+
+use net::RemoteSnapshot;
+use net::ServerMessage;
+use sulphate;
+
+/// Which entities a client has been told are near it, so that it can be
+/// told when one comes into range or goes out of it.
+pub struct Interest {
+    visible: collections::BTreeSet<sulphate::EntityId>,
+}
+
+impl Interest {
+    pub fn new() -> Interest {
+        let visible = collections::BTreeSet::new();
+        Interest { visible }
+    }
+
+    /// An `Enter` for everything in `snapshot` that wasn't visible before,
+    /// and a `Leave` for everything that was but isn't any more.
+    pub fn update(
+        self: &mut Self,
+        snapshot: &RemoteSnapshot,
+    ) -> Vec<ServerMessage> {
+        let now_visible: collections::BTreeSet<_> = snapshot.bodies
+            .iter()
+            .map(|body| body.id)
+            .collect();
+        let mut messages: Vec<_> = self.visible
+            .difference(&now_visible)
+            .map(|&id| ServerMessage::Leave { id })
+            .collect();
+        for body in &snapshot.bodies {
+            if !self.visible.contains(&body.id) {
+                let id = body.id;
+                let kind = body.kind;
+                messages.push(ServerMessage::Enter { id, kind });
+            }
+        }
+        self.visible = now_visible;
+        messages
+    }
+
+    /// Forgets what the client was told, so that everything enters again.
+    pub fn clear(self: &mut Self) {
+        self.visible.clear();
+    }
+}
+
+#[cfg(test)]
+mod test_interest {
+    use net;
+    use sulphate::persist;
+    use units;
+
+    use super::*;
+
+    fn snapshot(ids: &[u64]) -> net::RemoteSnapshot {
+        let bodies = ids.iter()
+                        .map(|&id| net::RemoteBody {
+                            id,
+                            kind: persist::EntityKind::Player,
+                            position: Default::default(),
+                            velocity: units::Velocity {
+                                x: 0.into(),
+                                y: 0.into(),
+                            },
+                        })
+                        .collect();
+        net::RemoteSnapshot { time: units::Time::default(), bodies }
+    }
+
+    fn describe(messages: Vec<ServerMessage>) -> Vec<String> {
+        messages.into_iter()
+                .map(|message| match message {
+                    ServerMessage::Enter { id, .. } => format!("+{}", id),
+                    ServerMessage::Leave { id } => format!("-{}", id),
+                    _ => panic!("interest sent something other than news"),
+                })
+                .collect()
+    }
+
+    #[test]
+    fn entities_enter_and_leave() {
+        let mut interest = Interest::new();
+        assert_eq!(describe(interest.update(&snapshot(&[1, 2]))),
+                   vec!["+1", "+2"]);
+        assert!(interest.update(&snapshot(&[2, 1])).is_empty());
+        assert_eq!(describe(interest.update(&snapshot(&[2, 3]))),
+                   vec!["-1", "+3"]);
+
+        interest.clear();
+        assert_eq!(describe(interest.update(&snapshot(&[2, 3]))),
+                   vec!["+2", "+3"]);
+    }
+}
@@ -0,0 +1,1227 @@
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Write;
+use std::net;
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+use blueprints;
+use economy;
+use entities;
+use entities::building;
+use entities::item;
+use entities::player;
+use mods;
+use space::chunks;
+use space::zones;
+use sulphate;
+use sulphate::chat;
+use sulphate::checksum;
+use sulphate::inbox;
+use sulphate::persist;
+use sulphate::server;
+use sulphate::session;
+use units;
+
+pub use net::delta::BodyChange;
+pub use net::delta::Delta;
+pub use net::handshake::Compression;
+pub use net::handshake::PROTOCOL_VERSION;
+pub use net::handshake::Rejection;
+
+mod delta;
+mod handshake;
+mod interest;
+mod streaming;
+
+// everything is sent as lines of text, with fixed point values written as
+// their bits so that nothing gets rounded on the way
+
+pub enum ClientMessage {
+    /// Which version of the protocol the client speaks, which compression
+    /// it can do and which packs it has, which is always the first thing
+    /// sent, before the server lets it say hello.
+    Handshake {
+        version: u64,
+        compression: Vec<Compression>,
+        mods: Vec<mods::Stamp>,
+    },
+    /// How the client's player should look, which is sent once the server
+    /// has accepted the handshake, before it welcomes the client.
+    Hello {
+        appearance: player::Appearance,
+    },
+    Control(player::Control),
+    Cast {
+        target: units::Position,
+    },
+    /// Interacts with whatever is nearest the client's player.
+    Interact,
+    /// Puts down whatever the client's player is carrying in `slot`.
+    Drop {
+        slot: usize,
+    },
+    /// Asks for a building from the blueprint named `blueprint` on the
+    /// tile under `position`, which shows up in later snapshots if there
+    /// was room for it.
+    Build {
+        blueprint: blueprints::Name,
+        position: units::Position,
+    },
+    /// Asks for the tiles between `from` and `to` to be zoned, or unzoned
+    /// if `zone` is None.
+    Zone {
+        from: units::Position,
+        to: units::Position,
+        zone: Option<zones::Zone>,
+    },
+    /// Asks for road dragged from the tile under `from` to the tile under
+    /// `to`, which is laid all at once or not at all.
+    Road {
+        from: units::Position,
+        to: units::Position,
+    },
+    /// Asks for pipe to be laid between the tiles under `from` and `to`.
+    Pipe {
+        from: units::Position,
+        to: units::Position,
+    },
+    /// Takes back the last thing the client's player built.
+    Undo,
+    /// Builds again the last thing the client's player took back.
+    Redo,
+    /// Something to say to everyone else in the city.
+    Chat {
+        text: String,
+    },
+    /// Says that the snapshot from `time` arrived, so that later ones can be
+    /// sent as changes to it.
+    Acknowledge {
+        time: units::Time,
+    },
+    /// Asks for the whole world again, after the client's copy of it was
+    /// found to be wrong.
+    Resync,
+    /// Asks for whatever of the city is in each of the chunks `ids`, which
+    /// is sent back a chunk at a time.
+    Chunks {
+        ids: Vec<chunks::ChunkId>,
+    },
+    Quit,
+}
+
+pub enum ServerMessage {
+    /// Lets the client go on to say hello, with everything from here on
+    /// compressed with `compression`.
+    Accept {
+        compression: Compression,
+    },
+    /// Turns the client away, just before hanging up on it.
+    Reject(Rejection),
+    Welcome {
+        id: sulphate::EntityId,
+    },
+    Snapshot(RemoteSnapshot),
+    Delta(Delta),
+    /// Something came close enough to the client's player to be sent, from
+    /// the next snapshot on.
+    Enter {
+        id: sulphate::EntityId,
+        kind: persist::EntityKind,
+    },
+    /// Something went out of range, and won't be in snapshots any more.
+    Leave {
+        id: sulphate::EntityId,
+    },
+    /// What the world hashed to at `time`, which is always the time of a
+    /// snapshot sent just before.
+    Checksum {
+        time: units::Time,
+        checksum: u64,
+    },
+    /// How the city's books stand, sent every so often for the HUD.
+    Ledger(economy::Balances),
+    /// Something that somebody said, which every client is sent.
+    Chat(chat::ChatLine),
+    /// What came of the client's player interacting with something.
+    Interaction(entities::Interaction),
+    /// Everything the client's player is carrying, sent whenever it
+    /// changes.
+    Inventory(Vec<item::Kind>),
+    /// A chunk of the city that the client asked for.
+    Chunk(chunks::Chunk),
+}
+
+/// What a remote client gets to see of the world.
+#[derive(Clone, PartialEq, Debug)]
+pub struct RemoteSnapshot {
+    pub time: units::Time,
+    pub bodies: Vec<RemoteBody>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RemoteBody {
+    pub id: sulphate::EntityId,
+    pub kind: persist::EntityKind,
+    pub position: units::Position,
+    pub velocity: units::Velocity,
+}
+
+impl RemoteSnapshot {
+    pub fn of(snapshot: sulphate::WorldSnapshot) -> RemoteSnapshot {
+        let bodies = snapshot.bodies
+            .into_iter()
+            .flat_map(|body| {
+                persist::EntityKind::of(body.uid.ty).map(|kind| RemoteBody {
+                    id: body.uid.id,
+                    kind,
+                    position: body.position,
+                    velocity: body.velocity,
+                })
+            })
+            .collect();
+        RemoteSnapshot { time: snapshot.time, bodies }
+    }
+
+    /// The same hash as `WorldSnapshot::checksum`, if this is the snapshot
+    /// as it was taken.
+    pub fn checksum(self: &Self) -> u64 {
+        let entities: Vec<_> = self.bodies
+            .iter()
+            .map(|body| sulphate::EntitySummary {
+                id: body.id,
+                kind: Some(body.kind),
+                position: body.position,
+                velocity: body.velocity,
+            })
+            .collect();
+        checksum::of_entities(&entities)
+    }
+}
+
+// the longest line either side reads, so that a peer that never sends a
+// newline can't have the other buffer whatever it sends without end
+const MAX_LINE: u64 = 64 * 1024;
+
+// like `BufRead::read_line`, but giving up on lines longer than `MAX_LINE`
+fn read_line(
+    input: &mut io::BufRead,
+    line: &mut String,
+) -> io::Result<usize> {
+    let read = (&mut *input).take(MAX_LINE).read_line(line)?;
+    if read as u64 == MAX_LINE && !line.ends_with('\n') {
+        let msg = format!("line longer than {} bytes", MAX_LINE);
+        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+    }
+    Ok(read)
+}
+
+fn invalid(line: &str) -> io::Error {
+    let msg = format!("malformed message: {}", line);
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn words(line: &str) -> Vec<&str> {
+    line.split_whitespace().collect()
+}
+
+fn int(words: &[&str], n: usize) -> Option<i64> {
+    words.get(n)?.parse().ok()
+}
+
+fn scalar(words: &[&str], n: usize) -> Option<units::Scalar> {
+    int(words, n).map(units::Scalar::from_bits)
+}
+
+fn coord(words: &[&str], n: usize) -> Option<units::Coord> {
+    int(words, n).map(units::Coord::from_bits)
+}
+
+// everything after the first `n` words, for text that has spaces in it
+fn rest(line: &str, n: usize) -> Option<&str> {
+    line.trim_right_matches('\n').splitn(n + 1, ' ').nth(n)
+}
+
+impl ClientMessage {
+    pub fn write(self: &Self, out: &mut io::Write) -> io::Result<()> {
+        match *self {
+            ClientMessage::Handshake {
+                version,
+                ref compression,
+                ref mods,
+            } => handshake::write_handshake(version, compression, mods, out),
+            ClientMessage::Hello { ref appearance } => {
+                let color = appearance.color;
+                writeln!(
+                    out, "hello {} {} {} {}",
+                    color[0], color[1], color[2], appearance.name,
+                )
+            },
+            ClientMessage::Control(ref control) => match *control {
+                player::Control::Move { velocity, pace } => writeln!(
+                    out, "move {} {} {}",
+                    velocity.x.into_bits(), velocity.y.into_bits(),
+                    pace.tag(),
+                ),
+            },
+            ClientMessage::Cast { target } => writeln!(
+                out, "cast {} {}",
+                target.x.into_bits(), target.y.into_bits(),
+            ),
+            ClientMessage::Interact => writeln!(out, "interact"),
+            ClientMessage::Drop { slot } => writeln!(out, "drop {}", slot),
+            ClientMessage::Build { blueprint, position } => writeln!(
+                out, "build {} {} {}", blueprint,
+                position.x.into_bits(), position.y.into_bits(),
+            ),
+            ClientMessage::Zone { from, to, zone } => writeln!(
+                out, "zone {} {} {} {} {}",
+                from.x.into_bits(), from.y.into_bits(),
+                to.x.into_bits(), to.y.into_bits(),
+                zone.map_or(zones::NO_ZONE, zones::Zone::tag),
+            ),
+            ClientMessage::Road { from, to } => writeln!(
+                out, "road {} {} {} {}",
+                from.x.into_bits(), from.y.into_bits(),
+                to.x.into_bits(), to.y.into_bits(),
+            ),
+            ClientMessage::Pipe { from, to } => writeln!(
+                out, "pipe {} {} {} {}",
+                from.x.into_bits(), from.y.into_bits(),
+                to.x.into_bits(), to.y.into_bits(),
+            ),
+            ClientMessage::Undo => writeln!(out, "undo"),
+            ClientMessage::Redo => writeln!(out, "redo"),
+            ClientMessage::Chat { ref text } => {
+                writeln!(out, "chat {}", text)
+            },
+            ClientMessage::Acknowledge { time } => {
+                writeln!(out, "ack {}", time.into_bits())
+            },
+            ClientMessage::Resync => writeln!(out, "resync"),
+            ClientMessage::Chunks { ref ids } => {
+                write!(out, "chunks")?;
+                for &(x, y) in ids {
+                    write!(out, " {} {}", x, y)?;
+                }
+                writeln!(out, "")
+            },
+            ClientMessage::Quit => writeln!(out, "quit"),
+        }
+    }
+
+    fn parse(line: &str) -> Option<ClientMessage> {
+        let words = words(line);
+        let message = match *words.get(0)? {
+            "handshake" => {
+                let (version, compression, mods) =
+                    handshake::read_handshake(&words)?;
+                ClientMessage::Handshake { version, compression, mods }
+            },
+            "hello" => {
+                let red = words.get(1)?.parse().ok()?;
+                let green = words.get(2)?.parse().ok()?;
+                let blue = words.get(3)?.parse().ok()?;
+                let name = rest(line, 4)?;
+                let appearance =
+                    player::Appearance::new(name, [red, green, blue]);
+                ClientMessage::Hello { appearance }
+            },
+            "move" => {
+                let x = scalar(&words, 1)?;
+                let y = scalar(&words, 2)?;
+                let velocity = units::Velocity { x, y };
+                // clients from before there were paces only ever ran
+                let pace = match words.get(3) {
+                    Some(tag) => player::Pace::from_tag(tag.parse().ok()?)?,
+                    None => player::Pace::Run,
+                };
+                let control = player::Control::Move { velocity, pace };
+                ClientMessage::Control(control)
+            },
+            "cast" => {
+                let x = coord(&words, 1)?;
+                let y = coord(&words, 2)?;
+                let target = units::Position { x, y };
+                ClientMessage::Cast { target }
+            },
+            "interact" => ClientMessage::Interact,
+            "undo" => ClientMessage::Undo,
+            "redo" => ClientMessage::Redo,
+            "drop" => {
+                let slot = words.get(1)?.parse().ok()?;
+                ClientMessage::Drop { slot }
+            },
+            // clients from before there were blueprints send the tag of
+            // the kind of building, which reads as its blueprint
+            "build" => {
+                let blueprint = blueprints::read_name(words.get(1)?)?;
+                let x = coord(&words, 2)?;
+                let y = coord(&words, 3)?;
+                let position = units::Position { x, y };
+                ClientMessage::Build { blueprint, position }
+            },
+            "zone" => {
+                let from = units::Position {
+                    x: coord(&words, 1)?,
+                    y: coord(&words, 2)?,
+                };
+                let to = units::Position {
+                    x: coord(&words, 3)?,
+                    y: coord(&words, 4)?,
+                };
+                let zone = match words.get(5)?.parse().ok()? {
+                    zones::NO_ZONE => None,
+                    tag => Some(zones::Zone::from_tag(tag)?),
+                };
+                ClientMessage::Zone { from, to, zone }
+            },
+            "road" => {
+                let from = units::Position {
+                    x: coord(&words, 1)?,
+                    y: coord(&words, 2)?,
+                };
+                let to = units::Position {
+                    x: coord(&words, 3)?,
+                    y: coord(&words, 4)?,
+                };
+                ClientMessage::Road { from, to }
+            },
+            "pipe" => {
+                let from = units::Position {
+                    x: coord(&words, 1)?,
+                    y: coord(&words, 2)?,
+                };
+                let to = units::Position {
+                    x: coord(&words, 3)?,
+                    y: coord(&words, 4)?,
+                };
+                ClientMessage::Pipe { from, to }
+            },
+            // cleaned again by the server, but it has to fit on the line
+            "chat" => {
+                let text = chat::clean(rest(line, 1)?)?;
+                ClientMessage::Chat { text }
+            },
+            "ack" => {
+                let time = coord(&words, 1)?;
+                ClientMessage::Acknowledge { time }
+            },
+            "resync" => ClientMessage::Resync,
+            "chunks" => {
+                let numbers = words[1..]
+                    .iter()
+                    .map(|word| word.parse().ok())
+                    .collect::<Option<Vec<i64>>>()?;
+                if numbers.len() % 2 != 0 {
+                    return None;
+                }
+                let ids = numbers.chunks(2).map(|xy| (xy[0], xy[1])).collect();
+                ClientMessage::Chunks { ids }
+            },
+            "quit" => ClientMessage::Quit,
+            _ => return None,
+        };
+        Some(message)
+    }
+
+    pub fn read(input: &mut io::BufRead) -> io::Result<Option<ClientMessage>> {
+        let mut line = String::new();
+        if read_line(input, &mut line)? == 0 {
+            return Ok(None);
+        }
+        ClientMessage::parse(&line).map(Some).ok_or_else(|| invalid(&line))
+    }
+}
+
+impl ServerMessage {
+    pub fn write(self: &Self, out: &mut io::Write) -> io::Result<()> {
+        match *self {
+            ServerMessage::Accept { compression } => {
+                writeln!(out, "accept {}", compression.name())
+            },
+            ServerMessage::Reject(ref rejection) => {
+                handshake::write_rejection(rejection, out)
+            },
+            ServerMessage::Welcome { id } => writeln!(out, "welcome {}", id),
+            ServerMessage::Snapshot(ref snapshot) => {
+                writeln!(
+                    out, "snapshot {} {}",
+                    snapshot.time.into_bits(), snapshot.bodies.len(),
+                )?;
+                for body in &snapshot.bodies {
+                    writeln!(
+                        out, "body {} {} {} {} {} {}",
+                        body.id, body.kind.tag(),
+                        body.position.x.into_bits(),
+                        body.position.y.into_bits(),
+                        body.velocity.x.into_bits(),
+                        body.velocity.y.into_bits(),
+                    )?;
+                }
+                Ok(())
+            },
+            ServerMessage::Delta(ref delta) => delta::write(delta, out),
+            ServerMessage::Enter { id, kind } => {
+                writeln!(out, "enter {} {}", id, kind.tag())
+            },
+            ServerMessage::Leave { id } => writeln!(out, "leave {}", id),
+            ServerMessage::Checksum { time, checksum } => writeln!(
+                out, "checksum {} {}", time.into_bits(), checksum,
+            ),
+            ServerMessage::Ledger(ref balances) => writeln!(
+                out, "ledger {} {} {} {} {} {} {} {}",
+                balances.money,
+                balances.supply.power,
+                balances.supply.water,
+                balances.supply.money,
+                balances.demand.power,
+                balances.demand.water,
+                balances.demand.money,
+                balances.blackouts,
+            ),
+            ServerMessage::Chat(ref line) => writeln!(
+                out, "chat {} {} {}",
+                line.time.into_bits(), line.from, line.text,
+            ),
+            ServerMessage::Interaction(ref outcome) => {
+                use entities::Interaction::*;
+                match *outcome {
+                    Menu { blueprint, blackout } => writeln!(
+                        out, "interaction menu {} {}",
+                        blueprint.tag(), blackout,
+                    ),
+                    Boarded { vehicle, driving } => writeln!(
+                        out, "interaction boarded {} {}", vehicle, driving,
+                    ),
+                    Alighted => writeln!(out, "interaction alighted"),
+                    PickedUp { kind } => writeln!(
+                        out, "interaction picked {}", kind.tag(),
+                    ),
+                    Talk { ref line } => {
+                        writeln!(out, "interaction talk {}", line)
+                    },
+                }
+            },
+            ServerMessage::Inventory(ref items) => {
+                write!(out, "inventory")?;
+                for kind in items {
+                    write!(out, " {}", kind.tag())?;
+                }
+                writeln!(out, "")
+            },
+            ServerMessage::Chunk(ref chunk) => streaming::write(chunk, out),
+        }
+    }
+
+    fn parse_interaction(
+        words: &[&str],
+        line: &str,
+    ) -> Option<entities::Interaction> {
+        use entities::Interaction::*;
+        let outcome = match *words.get(1)? {
+            "menu" => {
+                let tag = words.get(2)?.parse().ok()?;
+                let blueprint = building::Blueprint::from_tag(tag)?;
+                let blackout = words.get(3)?.parse().ok()?;
+                Menu { blueprint, blackout }
+            },
+            "boarded" => {
+                let vehicle = words.get(2)?.parse().ok()?;
+                // passengers were all there was, before anyone drove
+                let driving = match words.get(3) {
+                    Some(word) => word.parse().ok()?,
+                    None => false,
+                };
+                Boarded { vehicle, driving }
+            },
+            "alighted" => Alighted,
+            "picked" => {
+                let tag = words.get(2)?.parse().ok()?;
+                PickedUp { kind: item::Kind::from_tag(tag)? }
+            },
+            "talk" => Talk { line: rest(line, 2)?.to_string() },
+            _ => return None,
+        };
+        Some(outcome)
+    }
+
+    fn parse_body(line: &str) -> Option<RemoteBody> {
+        let words = words(line);
+        if *words.get(0)? != "body" {
+            return None;
+        }
+        let id = words.get(1)?.parse().ok()?;
+        let tag = words.get(2)?.parse().ok()?;
+        let kind = persist::EntityKind::from_tag(tag)?;
+        let position = units::Position {
+            x: coord(&words, 3)?,
+            y: coord(&words, 4)?,
+        };
+        let velocity = units::Velocity {
+            x: scalar(&words, 5)?,
+            y: scalar(&words, 6)?,
+        };
+        Some(RemoteBody { id, kind, position, velocity })
+    }
+
+    pub fn read(input: &mut io::BufRead) -> io::Result<Option<ServerMessage>> {
+        let mut line = String::new();
+        if read_line(input, &mut line)? == 0 {
+            return Ok(None);
+        }
+        let words = words(&line);
+        match words.get(0).cloned() {
+            Some("accept") => {
+                let compression = words.get(1)
+                                       .and_then(|name| {
+                                           Compression::from_name(name)
+                                       })
+                                       .ok_or_else(|| invalid(&line))?;
+                Ok(Some(ServerMessage::Accept { compression }))
+            },
+            Some("reject") => {
+                let rejection = handshake::read_rejection(&words)
+                    .ok_or_else(|| invalid(&line))?;
+                Ok(Some(ServerMessage::Reject(rejection)))
+            },
+            Some("welcome") => {
+                let id = words.get(1)
+                              .and_then(|id| id.parse().ok())
+                              .ok_or_else(|| invalid(&line))?;
+                Ok(Some(ServerMessage::Welcome { id }))
+            },
+            Some("snapshot") => {
+                let time = coord(&words, 1).ok_or_else(|| invalid(&line))?;
+                let count = int(&words, 2).ok_or_else(|| invalid(&line))?;
+                let mut bodies = Vec::new();
+                for _ in 0..count {
+                    let mut body_line = String::new();
+                    read_line(input, &mut body_line)?;
+                    let body = ServerMessage::parse_body(&body_line)
+                        .ok_or_else(|| invalid(&body_line))?;
+                    bodies.push(body);
+                }
+                let snapshot = RemoteSnapshot { time, bodies };
+                Ok(Some(ServerMessage::Snapshot(snapshot)))
+            },
+            Some("delta") => {
+                let delta = delta::read(&words, &line, input)?;
+                Ok(Some(ServerMessage::Delta(delta)))
+            },
+            Some("chunk") => {
+                let chunk = streaming::read(&words, &line, input)?;
+                Ok(Some(ServerMessage::Chunk(chunk)))
+            },
+            Some("enter") => {
+                let entered = || -> Option<_> {
+                    let id = words.get(1)?.parse().ok()?;
+                    let tag = words.get(2)?.parse().ok()?;
+                    let kind = persist::EntityKind::from_tag(tag)?;
+                    Some(ServerMessage::Enter { id, kind })
+                };
+                entered().map(Some).ok_or_else(|| invalid(&line))
+            },
+            Some("leave") => {
+                let id = words.get(1)
+                              .and_then(|id| id.parse().ok())
+                              .ok_or_else(|| invalid(&line))?;
+                Ok(Some(ServerMessage::Leave { id }))
+            },
+            Some("checksum") => {
+                let time = coord(&words, 1).ok_or_else(|| invalid(&line))?;
+                let checksum = words.get(2)
+                                    .and_then(|sum| sum.parse().ok())
+                                    .ok_or_else(|| invalid(&line))?;
+                Ok(Some(ServerMessage::Checksum { time, checksum }))
+            },
+            Some("ledger") => {
+                let balances = || -> Option<_> {
+                    Some(economy::Balances {
+                        money: int(&words, 1)?,
+                        supply: economy::Resources::new(
+                            int(&words, 2)?,
+                            int(&words, 3)?,
+                            int(&words, 4)?,
+                        ),
+                        demand: economy::Resources::new(
+                            int(&words, 5)?,
+                            int(&words, 6)?,
+                            int(&words, 7)?,
+                        ),
+                        blackouts: words.get(8)?.parse().ok()?,
+                    })
+                };
+                let balances = balances().ok_or_else(|| invalid(&line))?;
+                Ok(Some(ServerMessage::Ledger(balances)))
+            },
+            Some("chat") => {
+                let said = || -> Option<_> {
+                    Some(chat::ChatLine {
+                        time: coord(&words, 1)?,
+                        from: words.get(2)?.to_string(),
+                        text: rest(&line, 3)?.to_string(),
+                    })
+                };
+                let said = said().ok_or_else(|| invalid(&line))?;
+                Ok(Some(ServerMessage::Chat(said)))
+            },
+            Some("interaction") => {
+                let outcome = ServerMessage::parse_interaction(&words, &line)
+                    .ok_or_else(|| invalid(&line))?;
+                Ok(Some(ServerMessage::Interaction(outcome)))
+            },
+            Some("inventory") => {
+                let items = words[1..]
+                    .iter()
+                    .map(|word| -> Option<_> {
+                        let tag = word.parse().ok()?;
+                        item::Kind::from_tag(tag)
+                    })
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or_else(|| invalid(&line))?;
+                Ok(Some(ServerMessage::Inventory(items)))
+            },
+            _ => Err(invalid(&line)),
+        }
+    }
+}
+
+// how often remote clients are sent the world
+fn snapshot_period() -> time::Duration {
+    time::Duration::from_millis(1000 / units::MOMENT_RATE as u64)
+}
+
+// the most chunks a client gets sent for any one request, so that asking
+// for the whole world doesn't hold up the server
+const MOST_CHUNKS: usize = 64;
+
+// how far from its player a remote client gets to see
+fn interest_radius() -> units::Distance {
+    1000.into()
+}
+
+// how often remote clients are told what the world hashes to, in game time
+fn checksum_period() -> units::Duration {
+    units::moments(2 * units::MOMENT_RATE as i32)
+}
+
+/// Accepts connections on `addr`, adding a player to the server for each one,
+/// and removing it again when the connection closes.
+pub fn listen<A: net::ToSocketAddrs>(
+    addr: A,
+    upd: mpsc::Sender<server::Interruption>,
+) -> io::Result<thread::JoinHandle<()>> {
+    let listener = net::TcpListener::bind(addr)?;
+    // what clients send once they have joined goes through the inbox, so
+    // that none of them can flood the server
+    let (reply, opened) = mpsc::channel();
+    let open = server::Interruption::OpenInbox { wake: upd.clone(), reply };
+    let disconnected = || io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "server disconnected",
+    );
+    upd.send(open).map_err(|_| disconnected())?;
+    let inbox = opened.recv().map_err(|_| disconnected())?;
+    let handle = thread::spawn(move || {
+        for stream in listener.incoming() {
+            let upd = upd.clone();
+            // a clone of its own, so that it has a budget of its own
+            let inbox = inbox.clone();
+            // each client says hello in its own time, so waiting for one
+            // mustn't hold up the next
+            thread::spawn(move || {
+                let result = stream.and_then(|stream| {
+                    serve_connection(stream, upd, inbox)
+                });
+                if let Err(e) = result {
+                    warn!("Failed to accept connection: {}", e);
+                }
+            });
+        }
+    });
+    Ok(handle)
+}
+
+fn serve_connection(
+    stream: net::TcpStream,
+    upd: mpsc::Sender<server::Interruption>,
+    inbox: inbox::InboxSender,
+) -> io::Result<()> {
+    let mut reader = io::BufReader::new(stream.try_clone()?);
+    let mut writer = io::BufWriter::new(stream.try_clone()?);
+    let disconnected = || io::Error::new(
+        io::ErrorKind::BrokenPipe,
+        "server disconnected",
+    );
+    let agreed = match ClientMessage::read(&mut reader)? {
+        Some(ClientMessage::Handshake { version, compression, mods }) => {
+            let (reply, running) = mpsc::channel();
+            let query = server::Interruption::QueryMods { reply };
+            upd.send(query).map_err(|_| disconnected())?;
+            let running = running.recv().map_err(|_| disconnected())?;
+            handshake::agree(version, &compression, &mods, &running)
+        },
+        // clients from before there were handshakes say hello first
+        Some(ClientMessage::Hello { .. }) => Err(Rejection::Protocol {
+            server: PROTOCOL_VERSION,
+            client: None,
+        }),
+        _ => return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "client didn't shake hands",
+        )),
+    };
+    let compression = match agreed {
+        Ok(compression) => compression,
+        Err(rejection) => {
+            info!("Turned a client away: {}", rejection);
+            ServerMessage::Reject(rejection).write(&mut writer)?;
+            return writer.flush();
+        },
+    };
+    ServerMessage::Accept { compression }.write(&mut writer)?;
+    writer.flush()?;
+
+    let appearance = match ClientMessage::read(&mut reader)? {
+        Some(ClientMessage::Hello { appearance }) => appearance,
+        _ => return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "client didn't say hello",
+        )),
+    };
+
+    let (update, updates) = mpsc::channel();
+    let (reply, joined) = mpsc::channel();
+    let join = server::Interruption::Join {
+        appearance,
+        position: Default::default(),
+        update,
+        reply,
+    };
+    upd.send(join).map_err(|_| disconnected())?;
+    // the session stays on this side of the connection,
+    // so the client can only ever control its own player
+    let (session, id) = joined.recv().map_err(|_| disconnected())?;
+    match stream.peer_addr() {
+        Ok(addr) => info!("Player {} joined from {}", id, addr),
+        Err(_) => info!("Player {} joined", id),
+    }
+
+    ServerMessage::Welcome { id }.write(&mut writer)?;
+    writer.flush()?;
+
+    let snapshot_upd = upd.clone();
+    let (feedback, feedbacks) = mpsc::channel();
+    thread::spawn(move || {
+        send_snapshots(writer, snapshot_upd, id, updates, feedbacks);
+    });
+
+    thread::spawn(move || {
+        receive_messages(&mut reader, &inbox, id, session, feedback);
+        info!("Player {} left", id);
+        let _ = upd.send(server::Interruption::Leave { session });
+    });
+
+    Ok(())
+}
+
+// what the client says about the snapshots it has been sent, and the
+// chunks it wants
+enum Feedback {
+    Acknowledge(units::Time),
+    Resync,
+    Chunks(Vec<chunks::ChunkId>),
+}
+
+fn send_snapshots(
+    mut writer: io::BufWriter<net::TcpStream>,
+    upd: mpsc::Sender<server::Interruption>,
+    id: sulphate::EntityId,
+    updates: mpsc::Receiver<player::Update>,
+    feedbacks: mpsc::Receiver<Feedback>,
+) {
+    let mut encoder = delta::Encoder::new();
+    let mut interest = interest::Interest::new();
+    let mut next_checksum = None;
+    let (sender, said) = mpsc::channel();
+    if upd.send(server::Interruption::ListenToChat { sender }).is_err() {
+        return;
+    }
+    loop {
+        // remote clients see the world through snapshots instead, apart
+        // from what comes of interacting with it, and what they carry
+        for update in updates.try_iter() {
+            let message = match update.what {
+                player::UpdateData::Interacted { outcome } =>
+                    ServerMessage::Interaction(outcome),
+                player::UpdateData::Inventory { items } =>
+                    ServerMessage::Inventory(items),
+                _ => continue,
+            };
+            if message.write(&mut writer).is_err() {
+                return;
+            }
+        }
+
+        let mut resync = false;
+        let mut wanted = Vec::new();
+        for feedback in feedbacks.try_iter() {
+            match feedback {
+                Feedback::Acknowledge(time) => encoder.acknowledge(time),
+                Feedback::Resync => resync = true,
+                Feedback::Chunks(ids) => wanted.extend(ids),
+            }
+        }
+        if !wanted.is_empty() {
+            let (reply, recv) = mpsc::channel();
+            let request = server::Interruption::RequestGround { reply };
+            if upd.send(request).is_err() {
+                return;
+            }
+            let ground = match recv.recv() {
+                Ok(ground) => ground,
+                Err(_) => return,
+            };
+            for id in wanted {
+                let message = ServerMessage::Chunk(ground.chunk(id));
+                if message.write(&mut writer).is_err() {
+                    return;
+                }
+            }
+        }
+        if resync {
+            debug!("Player {} asked to resync", id);
+            encoder.resync();
+            interest.clear();
+        }
+
+        let (reply, recv) = mpsc::channel();
+        let radius = interest_radius();
+        let request =
+            server::Interruption::RequestSnapshotAround { id, radius, reply };
+        if upd.send(request).is_err() {
+            return;
+        }
+        let snapshot = match recv.recv() {
+            Ok(snapshot) => snapshot,
+            Err(_) => return,
+        };
+        let time = snapshot.time;
+        let checksum = snapshot.checksum;
+        let snapshot = RemoteSnapshot::of(snapshot);
+        // the client hears about new entities before their first snapshot
+        let mut messages = interest.update(&snapshot);
+        messages.push(encoder.encode(snapshot));
+        trace!("Sending {} messages to player {}", messages.len(), id);
+        for message in messages {
+            if message.write(&mut writer).is_err() {
+                return;
+            }
+        }
+
+        // a resynced client is told straight away whether it has the world
+        // right this time, and the books go along with it, since they
+        // don't change any faster
+        if resync || next_checksum.map_or(true, |next| time >= next) {
+            let message = ServerMessage::Checksum { time, checksum };
+            if message.write(&mut writer).is_err() {
+                return;
+            }
+            next_checksum = Some(time + checksum_period());
+
+            let (reply, recv) = mpsc::channel();
+            let request = server::Interruption::QueryLedger { reply };
+            if upd.send(request).is_err() {
+                return;
+            }
+            let balances = match recv.recv() {
+                Ok(balances) => balances,
+                Err(_) => return,
+            };
+            if ServerMessage::Ledger(balances).write(&mut writer).is_err() {
+                return;
+            }
+        }
+        for line in said.try_iter() {
+            if ServerMessage::Chat(line).write(&mut writer).is_err() {
+                return;
+            }
+        }
+        if writer.flush().is_err() {
+            return;
+        }
+
+        thread::sleep(snapshot_period());
+    }
+}
+
+fn receive_messages(
+    reader: &mut io::BufRead,
+    inbox: &inbox::InboxSender,
+    id: sulphate::EntityId,
+    session: session::SessionToken,
+    feedback: mpsc::Sender<Feedback>,
+) {
+    loop {
+        let message = match ClientMessage::read(reader) {
+            Ok(Some(message)) => message,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Dropped connection for player {}: {}", id, e);
+                return;
+            },
+        };
+        let interruption = match message {
+            ClientMessage::Control(control) =>
+                server::Interruption::SessionUpdate { session, control },
+            ClientMessage::Cast { target } =>
+                server::Interruption::SessionCast { session, target },
+            ClientMessage::Interact =>
+                server::Interruption::SessionInteract { session },
+            ClientMessage::Drop { slot } =>
+                server::Interruption::SessionDrop { session, slot },
+            ClientMessage::Build { blueprint, position } => {
+                let (reply, _) = mpsc::channel();
+                server::Interruption::PlaceBuilding {
+                    blueprint,
+                    position,
+                    by: Some(id),
+                    reply,
+                }
+            },
+            ClientMessage::Zone { from, to, zone } => {
+                let (reply, _) = mpsc::channel();
+                let by = Some(id);
+                server::Interruption::PaintZone { from, to, zone, by, reply }
+            },
+            ClientMessage::Road { from, to } => {
+                let (reply, _) = mpsc::channel();
+                let by = Some(id);
+                server::Interruption::road_drag(from, to, by, &reply)
+            },
+            ClientMessage::Pipe { from, to } => {
+                let (reply, _) = mpsc::channel();
+                let by = Some(id);
+                server::Interruption::LayPipe { from, to, by, reply }
+            },
+            ClientMessage::Undo =>
+                server::Interruption::SessionUndo { session },
+            ClientMessage::Redo =>
+                server::Interruption::SessionRedo { session },
+            ClientMessage::Chat { text } =>
+                server::Interruption::SessionChat { session, text },
+            ClientMessage::Handshake { .. } => {
+                warn!("Player {} shook hands twice", id);
+                continue;
+            },
+            ClientMessage::Hello { .. } => {
+                warn!("Player {} said hello twice", id);
+                continue;
+            },
+            ClientMessage::Acknowledge { time } => {
+                if feedback.send(Feedback::Acknowledge(time)).is_err() {
+                    return;
+                }
+                continue;
+            },
+            ClientMessage::Resync => {
+                if feedback.send(Feedback::Resync).is_err() {
+                    return;
+                }
+                continue;
+            },
+            ClientMessage::Chunks { mut ids } => {
+                ids.truncate(MOST_CHUNKS);
+                if feedback.send(Feedback::Chunks(ids)).is_err() {
+                    return;
+                }
+                continue;
+            },
+            ClientMessage::Quit => return,
+        };
+        if inbox.send(interruption).is_err() {
+            return;
+        }
+    }
+}
+
+/// A client's connection to a remote server.
+pub struct Connection {
+    id: sulphate::EntityId,
+    compression: Compression,
+    writer: io::BufWriter<net::TcpStream>,
+    // everything after the welcome, in the order it was sent
+    messages: mpsc::Receiver<ServerMessage>,
+    decoder: delta::Decoder,
+    // deltas can't be decoded until the keyframe that was asked for
+    resyncing: bool,
+}
+
+impl Connection {
+    /// Joins the game at `addr` as a player that looks like `appearance`,
+    /// so long as the server is running the same packs as `mods`. If the
+    /// server turns us away, the error says why.
+    pub fn connect<A: net::ToSocketAddrs>(
+        addr: A,
+        appearance: &player::Appearance,
+        mods: &[mods::Stamp],
+    ) -> io::Result<Connection> {
+        let stream = net::TcpStream::connect(addr)?;
+        let mut writer = io::BufWriter::new(stream.try_clone()?);
+        let mut reader = io::BufReader::new(stream);
+
+        let version = PROTOCOL_VERSION;
+        let compression = Compression::all();
+        let mods = mods.to_vec();
+        ClientMessage::Handshake { version, compression, mods }
+            .write(&mut writer)?;
+        writer.flush()?;
+        let compression = match ServerMessage::read(&mut reader)? {
+            Some(ServerMessage::Accept { compression }) => compression,
+            Some(ServerMessage::Reject(rejection)) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::ConnectionRefused,
+                    rejection.to_string(),
+                ));
+            },
+            // servers from before there were handshakes hang up on them
+            None => return Err(io::Error::new(
+                io::ErrorKind::ConnectionRefused,
+                "the server hung up, and may be older than this game",
+            )),
+            _ => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "server didn't answer the handshake",
+            )),
+        };
+
+        let appearance = appearance.clone();
+        ClientMessage::Hello { appearance }.write(&mut writer)?;
+        writer.flush()?;
+
+        let id = match ServerMessage::read(&mut reader)? {
+            Some(ServerMessage::Welcome { id }) => id,
+            _ => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "server didn't welcome us",
+            )),
+        };
+
+        let (send, messages) = mpsc::channel();
+        thread::spawn(move || {
+            loop {
+                match ServerMessage::read(&mut reader) {
+                    Ok(Some(ServerMessage::Welcome { .. })) => {
+                        warn!("Server welcomed us twice");
+                    },
+                    Ok(Some(ServerMessage::Accept { .. })) |
+                    Ok(Some(ServerMessage::Reject(_))) => {
+                        warn!("Server answered the handshake twice");
+                    },
+                    Ok(Some(message)) => {
+                        if send.send(message).is_err() {
+                            return;
+                        }
+                    },
+                    Ok(None) => return,
+                    Err(e) => {
+                        warn!("Lost connection to server: {}", e);
+                        return;
+                    },
+                }
+            }
+        });
+
+        let decoder = delta::Decoder::new();
+        let resyncing = false;
+        Ok(Connection {
+            id,
+            compression,
+            writer,
+            messages,
+            decoder,
+            resyncing,
+        })
+    }
+
+    pub fn id(self: &Self) -> sulphate::EntityId {
+        self.id
+    }
+
+    /// How messages are compressed, as agreed in the handshake.
+    pub fn compression(self: &Self) -> Compression {
+        self.compression
+    }
+
+    pub fn send(self: &mut Self, message: &ClientMessage) -> io::Result<()> {
+        message.write(&mut self.writer)?;
+        self.writer.flush()
+    }
+
+    /// Everything the server has sent since last time, with any deltas
+    /// already turned back into whole snapshots.
+    pub fn receive(self: &mut Self) -> io::Result<Vec<ServerMessage>> {
+        let messages: Vec<_> = self.messages.try_iter().collect();
+        let mut received = Vec::new();
+        for message in messages {
+            let snapshot = match message {
+                ServerMessage::Snapshot(snapshot) => {
+                    self.decoder.keyframe(snapshot.clone());
+                    self.resyncing = false;
+                    snapshot
+                },
+                ServerMessage::Delta(delta) => {
+                    match self.decoder.apply(&delta) {
+                        Some(snapshot) => snapshot,
+                        None => {
+                            if !self.resyncing {
+                                debug!("Missed a snapshot, resyncing");
+                                self.resync()?;
+                            }
+                            continue;
+                        },
+                    }
+                },
+                other => {
+                    received.push(other);
+                    continue;
+                },
+            };
+            let time = snapshot.time;
+            self.send(&ClientMessage::Acknowledge { time })?;
+            received.push(ServerMessage::Snapshot(snapshot));
+        }
+        Ok(received)
+    }
+
+    /// Throws away what we have of the world, and asks for all of it again.
+    pub fn resync(self: &mut Self) -> io::Result<()> {
+        self.decoder.clear();
+        self.resyncing = true;
+        self.send(&ClientMessage::Resync)
+    }
+}
+
+#[cfg(test)]
+mod test_net {
+    use super::*;
+
+    #[test]
+    fn lines_that_never_end_are_given_up_on() {
+        let endless = vec![b'a'; MAX_LINE as usize * 2];
+        let mut input = io::BufReader::new(&endless[..]);
+        match ClientMessage::read(&mut input) {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidData),
+            Ok(_) => panic!("read a line that never ended"),
+        }
+
+        let mut input = io::BufReader::new(&b"quit\n"[..]);
+        match ClientMessage::read(&mut input) {
+            Ok(Some(ClientMessage::Quit)) => (),
+            _ => panic!("didn't read a short line"),
+        }
+    }
+}
@@ -0,0 +1,119 @@
+use std::io;
+
+use net;
+use space;
+use space::TileId;
+use space::chunks;
+use space::zones;
+
+pub fn write(chunk: &chunks::Chunk, out: &mut io::Write) -> io::Result<()> {
+    writeln!(
+        out, "chunk {} {} {} {} {} {}",
+        chunk.id.0, chunk.id.1,
+        chunk.terrain.len(), chunk.roads.len(),
+        chunk.zones.len(), chunk.pipes.len(),
+    )?;
+    for &((x, y), tile) in &chunk.terrain {
+        writeln!(out, "tile {} {} {}", x, y, tile.tag())?;
+    }
+    for &(from, to) in &chunk.roads {
+        writeln!(out, "road {} {} {} {}", from.0, from.1, to.0, to.1)?;
+    }
+    for &((x, y), zone) in &chunk.zones {
+        writeln!(out, "zoned {} {} {}", x, y, zone.tag())?;
+    }
+    for &(from, to) in &chunk.pipes {
+        writeln!(out, "pipe {} {} {} {}", from.0, from.1, to.0, to.1)?;
+    }
+    Ok(())
+}
+
+// the next `count` lines, each of which has to start with `word`
+fn lines<T, F>(
+    input: &mut io::BufRead,
+    count: i64,
+    word: &str,
+    parse: F,
+) -> io::Result<Vec<T>>
+    where F: Fn(&[&str]) -> Option<T>
+{
+    let mut parsed = Vec::new();
+    for _ in 0..count {
+        let mut line = String::new();
+        net::read_line(input, &mut line)?;
+        let words = net::words(&line);
+        let item = if words.get(0) == Some(&word) {
+            parse(&words)
+        } else {
+            None
+        };
+        parsed.push(item.ok_or_else(|| net::invalid(&line))?);
+    }
+    Ok(parsed)
+}
+
+fn tile(words: &[&str], n: usize) -> Option<TileId> {
+    Some((net::int(words, n)?, net::int(words, n + 1)?))
+}
+
+fn tag(words: &[&str], n: usize) -> Option<u8> {
+    words.get(n)?.parse().ok()
+}
+
+pub fn read(
+    words: &[&str],
+    line: &str,
+    input: &mut io::BufRead,
+) -> io::Result<chunks::Chunk> {
+    let header = || -> Option<_> {
+        let id = tile(words, 1)?;
+        let counts = [
+            net::int(words, 3)?,
+            net::int(words, 4)?,
+            net::int(words, 5)?,
+            net::int(words, 6)?,
+        ];
+        Some((id, counts))
+    };
+    let (id, counts) = header().ok_or_else(|| net::invalid(line))?;
+    let terrain = lines(input, counts[0], "tile", |words| {
+        Some((tile(words, 1)?, space::Tile::from_tag(tag(words, 3)?)?))
+    })?;
+    let segment = |words: &[&str]| Some((tile(words, 1)?, tile(words, 3)?));
+    let roads = lines(input, counts[1], "road", &segment)?;
+    let zones = lines(input, counts[2], "zoned", |words| {
+        Some((tile(words, 1)?, zones::Zone::from_tag(tag(words, 3)?)?))
+    })?;
+    let pipes = lines(input, counts[3], "pipe", &segment)?;
+    Ok(chunks::Chunk { id, terrain, roads, zones, pipes })
+}
+
+#[cfg(test)]
+mod test_streaming {
+    use std::io;
+
+    use super::*;
+
+    #[test]
+    fn chunks_read_back_as_they_were_written() {
+        let chunk = chunks::Chunk {
+            id: (-1, 2),
+            terrain: vec![((-3, 40), space::Tile::Water)],
+            roads: vec![((-16, 32), (-15, 32)), ((-16, 32), (-16, 33))],
+            zones: vec![((-2, 35), zones::Zone::Industrial)],
+            pipes: Vec::new(),
+        };
+        let parse = |text: &[u8]| {
+            let mut input = io::BufReader::new(text);
+            let mut line = String::new();
+            io::BufRead::read_line(&mut input, &mut line).unwrap();
+            read(&net::words(&line), &line, &mut input)
+        };
+        let mut out = Vec::new();
+        write(&chunk, &mut out).unwrap();
+        assert_eq!(parse(&out).unwrap(), chunk);
+
+        // one that says it has more tiles than it does
+        assert!(parse(b"chunk 0 0 2 0 0 0\ntile 1 1 3\n").is_err());
+    }
+}
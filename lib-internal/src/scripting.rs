@@ -0,0 +1,529 @@
+//! Mods, which are scripts that the city runs as things happen in it, for
+//! changing how it plays without changing the game. Scripts are written in
+//! rhai, and only run when built with the `scripting` feature.
+//!
+//! A script hears about what happens by defining functions named after
+//! the hooks it wants,
+//!
+//!     fn on_building_placed(id, kind, x, y) { ... }
+//!     fn on_day_start(day) { ... }
+//!
+//! and gives a kind of building a behaviour by defining a function named
+//! after it, such as `behave_house(id, x, y)` or `behave_power_plant`,
+//! which is run for every building of that kind once an hour. Places are
+//! given in tiles.
+//!
+//! The only way a script has of changing the city is through `build(kind,
+//! x, y)`, `road(x, y, to_x, to_y)`, `zone(x, y, to_x, to_y, kind)` and
+//! `demolish(id)`, and even those don't change it there and then. A
+//! building can be built from any of the blueprints that `blueprints`
+//! describes, not only the kinds there are. What a
+//! script asks for goes on the event queue, to be done once the script has
+//! returned in the order it was asked for, and scripts have no way to tell
+//! the time or draw random numbers, so a city with mods plays out the same
+//! however many times it is played, replays included. Whatever mods build
+//! doesn't set off `on_building_placed`, so that they can't set each other
+//! off forever.
+//!
+//! Each mod runs on a thread of its own, and one that takes longer than
+//! `patience` over anything is given up on, since rhai has no way of
+//! stopping a script partway, so that a mod stuck in a loop holds up the
+//! city for no longer than that. The other mods carry on without it.
+
+use std::any;
+#[cfg(feature = "scripting")]
+use std::cell;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path;
+#[cfg(feature = "scripting")]
+use std::rc;
+use std::sync::mpsc;
+#[cfg(feature = "scripting")]
+use std::thread;
+use std::time;
+
+#[cfg(feature = "scripting")]
+use rhai;
+
+use blueprints;
+use construction;
+use economy;
+use entities::building;
+use space;
+use space::zones;
+use sulphate;
+use sulphate::tick::TickScheduler;
+use world::calendar;
+
+/// A script from a mod, by the name of the file it came from.
+#[derive(Clone)]
+pub struct Script {
+    pub name: String,
+    pub source: String,
+}
+
+/// Reads every `.rhai` file in `dir`, in order of name, which is the order
+/// the mods are run in.
+pub fn load(dir: &path::Path) -> io::Result<Vec<Script>> {
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().map_or(false, |ext| ext == "rhai") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    let mut scripts = Vec::new();
+    for path in paths {
+        let mut source = String::new();
+        fs::File::open(&path)?.read_to_string(&mut source)?;
+        let name = path.file_name()
+                       .map_or(String::new(), |name| {
+                           name.to_string_lossy().into_owned()
+                       });
+        scripts.push(Script { name, source });
+    }
+    Ok(scripts)
+}
+
+/// How long a mod has to load, or to do whatever it was called for, before
+/// it is given up on.
+pub fn patience() -> time::Duration {
+    time::Duration::from_secs(1)
+}
+
+// a mod running on its own thread, which is sent calls and sends back
+// whatever each one asked for
+struct Loaded {
+    name: String,
+    // the functions the mod's engine has from it
+    defined: Vec<String>,
+    // None once the mod has been given up on
+    calls: Option<(mpsc::Sender<String>, mpsc::Receiver<Vec<Command>>)>,
+}
+
+impl Loaded {
+    fn defines(self: &Self, name: &str) -> bool {
+        self.calls.is_some() && self.defined.iter().any(|f| *f == name)
+    }
+}
+
+/// The mods the world is running, kept in the heap as the only entity of
+/// its type. They aren't saved, so a world picked back up from a save runs
+/// whatever mods are loaded into it then.
+pub struct Mods {
+    loaded: Vec<Loaded>,
+    // set while whatever the mods asked for is being done
+    quiet: bool,
+}
+
+impl Mods {
+    // whether any of the mods defines a function called `name`
+    fn defines(self: &Self, name: &str) -> bool {
+        self.loaded.iter().any(|loaded| loaded.defines(name))
+    }
+}
+
+const MODS: sulphate::EntityId = 0;
+
+/// Runs the world with `scripts` from now on, in place of whatever mods it
+/// had before.
+pub fn set(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    scripts: Vec<Script>,
+) {
+    if !cfg!(feature = "scripting") && !scripts.is_empty() {
+        warn!("Built without scripting, so mods won't do anything");
+    }
+    let loaded = scripts.iter().filter_map(start).collect();
+    let mods = Mods { loaded, quiet: false };
+    // overwritten in place, for the same reason as the config
+    if matter.get::<Mods>(MODS).is_some() {
+        *matter.get_mut::<Mods>(MODS).expect("Mods went missing") = mods;
+    } else {
+        matter.insert(MODS, mods);
+        time.every(calendar::hours(1), behave);
+    }
+}
+
+// kinds of building can be named the way players see them, with spaces,
+// as well as the way their blueprints are
+fn building_named(name: &str) -> Option<blueprints::Name> {
+    blueprints::Name::new(&name.replace(' ', "_"))
+}
+
+// "none" for taking the zoning off
+fn zone_named(name: &str) -> Option<Option<zones::Zone>> {
+    if name == "none" {
+        return Some(None);
+    }
+    zones::Zone::all()
+        .iter()
+        .cloned()
+        .find(|zone| zone.name() == name)
+        .map(Some)
+}
+
+fn behaviour(blueprint: building::Blueprint) -> String {
+    format!("behave_{}", blueprint.name().replace(' ', "_"))
+}
+
+// something a script asked for
+enum Command {
+    Build {
+        blueprint: blueprints::Name,
+        tile: space::TileId,
+    },
+    Road {
+        from: space::TileId,
+        to: space::TileId,
+    },
+    Zone {
+        from: space::TileId,
+        to: space::TileId,
+        zone: Option<zones::Zone>,
+    },
+    Demolish {
+        id: sulphate::EntityId,
+    },
+}
+
+// does what a script asked for, as though nobody in particular had
+struct ScriptEvent(Command);
+
+impl sulphate::Event for ScriptEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        set_quiet(matter, true);
+        match self.0 {
+            Command::Build { blueprint, tile } => {
+                if blueprints::find(matter, blueprint).is_none() {
+                    warn!("Mod tried to build a {}", blueprint);
+                }
+                let position = space.terrain().center(tile);
+                construction::place(
+                    space, time, matter, None, blueprint, position,
+                );
+            },
+            Command::Road { from, to } => {
+                let from = space.terrain().center(from);
+                let to = space.terrain().center(to);
+                construction::lay_road(space, time, matter, None, from, to);
+            },
+            Command::Zone { from, to, zone } => {
+                let from = space.terrain().center(from);
+                let to = space.terrain().center(to);
+                construction::zone(space, time, matter, None, from, to, zone);
+            },
+            Command::Demolish { id } => {
+                if matter.get::<building::Building>(id).is_some() {
+                    building::Building::remove(space, time, matter, id);
+                }
+            },
+        }
+        set_quiet(matter, false);
+    }
+}
+
+fn set_quiet(matter: &mut sulphate::EntityHeap, quiet: bool) {
+    if let Some(mods) = matter.get_mut::<Mods>(MODS) {
+        mods.quiet = quiet;
+    }
+}
+
+// calls `hook` in every mod that defines it, as `call` says to, and puts
+// whatever they ask for on the queue
+fn run(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    hook: &str,
+    call: &str,
+) {
+    let asked = match matter.get_mut::<Mods>(MODS) {
+        Some(mods) => {
+            if mods.quiet {
+                return;
+            }
+            call_mods(mods, hook, call)
+        },
+        None => return,
+    };
+    let now = time.now();
+    for command in asked {
+        time.enqueue_absolute(ScriptEvent(command), now);
+    }
+}
+
+/// Tells the mods that building `id` has been put up on `tile`, unless it
+/// was a mod that put it there.
+pub fn building_placed(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    blueprint: building::Blueprint,
+    tile: space::TileId,
+) {
+    let call = format!(
+        "on_building_placed({}, \"{}\", {}, {})",
+        id, blueprint.name(), tile.0, tile.1,
+    );
+    run(time, matter, "on_building_placed", &call);
+}
+
+/// Tells the mods that day `day` has dawned.
+pub fn day_started(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    day: i64,
+) {
+    run(time, matter, "on_day_start", &format!("on_day_start({})", day));
+}
+
+// every building of a kind that has a behaviour is run, in id order
+fn behave(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+) -> bool {
+    let defined: Vec<building::Blueprint> = match matter.get::<Mods>(MODS) {
+        Some(mods) => building::Blueprint::all()
+            .iter()
+            .cloned()
+            .filter(|&blueprint| mods.defines(&behaviour(blueprint)))
+            .collect(),
+        None => return false,
+    };
+    if defined.is_empty() {
+        return true;
+    }
+    let ids: Vec<sulphate::EntityId> = matter
+        .query::<(&economy::Flow,)>()
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    let now = time.now();
+    for id in ids {
+        let blueprint = match matter.get::<building::Building>(id) {
+            Some(building) => building.blueprint(),
+            None => continue,
+        };
+        if !defined.contains(&blueprint) {
+            continue;
+        }
+        let ty = any::TypeId::of::<building::Building>();
+        let uid = sulphate::EntityUId { id, ty };
+        let tile = match space.position(uid, now) {
+            Some(position) => space.terrain().tile_id(position),
+            None => continue,
+        };
+        let name = behaviour(blueprint);
+        let call = format!("{}({}, {}, {})", name, id, tile.0, tile.1);
+        run(time, matter, &name, &call);
+    }
+    true
+}
+
+#[cfg(feature = "scripting")]
+type Asked = rc::Rc<cell::RefCell<Vec<Command>>>;
+
+#[cfg(feature = "scripting")]
+fn register(engine: &mut rhai::Engine, asked: &Asked) {
+    let to = asked.clone();
+    engine.register_fn("build", move |kind: String, x: i64, y: i64| {
+        match building_named(&kind) {
+            Some(blueprint) => {
+                let tile = (x, y);
+                to.borrow_mut().push(Command::Build { blueprint, tile });
+            },
+            None => warn!("Mod tried to build a {}", kind),
+        }
+    });
+    let to = asked.clone();
+    engine.register_fn("road", move |x: i64, y: i64, to_x: i64, to_y: i64| {
+        let (from, to_tile) = ((x, y), (to_x, to_y));
+        to.borrow_mut().push(Command::Road { from, to: to_tile });
+    });
+    let to = asked.clone();
+    engine.register_fn(
+        "zone",
+        move |x: i64, y: i64, to_x: i64, to_y: i64, kind: String| {
+            match zone_named(&kind) {
+                Some(zone) => to.borrow_mut().push(Command::Zone {
+                    from: (x, y),
+                    to: (to_x, to_y),
+                    zone,
+                }),
+                None => warn!("Mod tried to zone for {}", kind),
+            }
+        },
+    );
+    let to = asked.clone();
+    engine.register_fn("demolish", move |id: i64| {
+        if id >= 0 {
+            let id = id as sulphate::EntityId;
+            to.borrow_mut().push(Command::Demolish { id });
+        }
+    });
+}
+
+// starts `script` on a thread of its own, and waits for it to load. anything
+// at the top level of a script is run once as it is loaded, but can't ask
+// for anything, since it happens whenever the mods are loaded
+#[cfg(feature = "scripting")]
+fn start(script: &Script) -> Option<Loaded> {
+    let (calls, called) = mpsc::channel::<String>();
+    let (answer, answers) = mpsc::channel();
+    let (loaded, loading) = mpsc::channel();
+    let name = script.name.clone();
+    let source = script.source.clone();
+    // engines can't be sent between threads, so this one is made where it
+    // is run
+    thread::spawn(move || {
+        let asked: Asked = rc::Rc::new(cell::RefCell::new(Vec::new()));
+        let mut engine = rhai::Engine::new();
+        register(&mut engine, &asked);
+        if let Err(e) = engine.eval::<()>(&source) {
+            warn!("Failed to load mod {}: {:?}", name, e);
+        }
+        asked.borrow_mut().clear();
+        let defined: Vec<String> = engine.fns
+            .keys()
+            .map(|spec| spec.ident.clone())
+            .collect();
+        if loaded.send(defined).is_err() {
+            return;
+        }
+        for call in called {
+            if let Err(e) = engine.eval::<()>(&call) {
+                warn!("{} failed in mod {}: {:?}", call, name, e);
+            }
+            let asked = asked.borrow_mut().drain(..).collect();
+            if answer.send(asked).is_err() {
+                return;
+            }
+        }
+    });
+    match loading.recv_timeout(patience()) {
+        Ok(defined) => Some(Loaded {
+            name: script.name.clone(),
+            defined,
+            calls: Some((calls, answers)),
+        }),
+        Err(_) => {
+            warn!("Gave up on mod {}, which took too long to load",
+                  script.name);
+            None
+        },
+    }
+}
+
+#[cfg(not(feature = "scripting"))]
+fn start(_script: &Script) -> Option<Loaded> {
+    None
+}
+
+// what every mod that defines `hook` asked for when it was called
+fn call_mods(mods: &mut Mods, hook: &str, call: &str) -> Vec<Command> {
+    let mut asked = Vec::new();
+    for loaded in &mut mods.loaded {
+        if !loaded.defines(hook) {
+            continue;
+        }
+        let answer = match loaded.calls {
+            Some((ref calls, ref answers)) => {
+                // a mod whose thread has gone is given up on all the same
+                let _ = calls.send(call.to_string());
+                answers.recv_timeout(patience()).ok()
+            },
+            None => continue,
+        };
+        match answer {
+            Some(answer) => asked.extend(answer),
+            None => {
+                // whatever it is doing, it is left to it, and never called
+                // again
+                warn!("Gave up on mod {}, which took too long over {}",
+                      loaded.name, hook);
+                loaded.calls = None;
+            },
+        }
+    }
+    asked
+}
+
+#[cfg(test)]
+mod test_scripting {
+    use super::*;
+
+    #[test]
+    fn buildings_go_by_the_names_players_see() {
+        assert_eq!(behaviour(building::Blueprint::PowerPlant),
+                   "behave_power_plant");
+        let fire_station = building::Blueprint::FireStation;
+        assert_eq!(building_named("fire station"),
+                   Some(blueprints::Name::from(fire_station)));
+        assert_eq!(zone_named("none"), Some(None));
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn hooks_are_whatever_the_engine_has() {
+        let source = "fn on_day_start (day) {}\nfn behave_house(id, x, y) {}";
+        let script = Script {
+            name: "hooks.rhai".to_string(),
+            source: source.to_string(),
+        };
+        let mods = Mods {
+            loaded: start(&script).into_iter().collect(),
+            quiet: false,
+        };
+        assert!(mods.defines("on_day_start"));
+        assert!(mods.defines("behave_house"));
+        assert!(!mods.defines("on_building_placed"));
+        assert!(!mods.defines("behave"));
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn mods_that_never_return_are_given_up_on() {
+        use sulphate::testing;
+
+        let stuck = "fn on_day_start(day) { while true {} }";
+        let fine = "fn on_day_start(day) { road(0, 0, 3, 0); }";
+        let (mut server, ()) = testing::TestServer::new(|_, time, matter| {
+            let scripts = vec![
+                Script { name: "stuck.rhai".into(), source: stuck.into() },
+                Script { name: "fine.rhai".into(), source: fine.into() },
+            ];
+            set(time, matter, scripts);
+        });
+        server.advance_by(calendar::hours(calendar::HOURS_PER_DAY));
+        assert!(server.space().roads().has_road((2, 0)));
+        let mods = server.matter().get::<Mods>(MODS).unwrap();
+        assert!(mods.loaded[0].calls.is_none());
+        assert!(mods.loaded[1].calls.is_some());
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn what_mods_ask_for_happens_after_them() {
+        use sulphate::testing;
+
+        let source = "fn on_day_start(day) { road(0, 0, 3, 0); }";
+        let (mut server, ()) = testing::TestServer::new(|_, time, matter| {
+            let name = "roads.rhai".to_string();
+            let source = source.to_string();
+            set(time, matter, vec![Script { name, source }]);
+        });
+        assert!(server.space().roads().is_empty());
+        server.advance_by(calendar::hours(calendar::HOURS_PER_DAY));
+        assert!(server.space().roads().has_road((2, 0)));
+    }
+}
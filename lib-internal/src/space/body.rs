@@ -3,6 +3,7 @@ use std::cmp;
 
 use entities;
 use space;
+use space::impulse;
 use sulphate;
 use units;
 
@@ -13,6 +14,12 @@ pub trait Collide: entities::Display + any::Any where Self: Sized {
     fn release(this: space::Entry<Self>, other: space::Image);
     /// called when an object teleports out of Self
     fn disappear(this: space::Entry<Self>, other: space::Image);
+
+    /// How this gets pushed around by what it hits, which by default it
+    /// doesn't.
+    fn material(self: &Self) -> Option<space::Material> {
+        None
+    }
 }
 
 /* this is quite complicated
@@ -69,9 +76,12 @@ pub fn update_physics(
                 perform_disappearance(space, time, matter, uid, other,
                                       before.clone());
             }
+            space::triggers::forget(space, time, uid);
         }
 
         space.contents.remove(n);
+        space.index.remove(uid);
+        space::terrain::forget(space, time, uid);
     }
 
     // add the new body, giving it the lowest priority:
@@ -80,6 +90,7 @@ pub fn update_physics(
         let body = image.body.clone();
         let speed = body.velocity().magnitude();
         let radius = image.inner_image.radius();
+        space.index.insert(uid, &body, radius);
         let physics_state = {
             if bounce {
                 PhysicsState::Bounce(time.now())
@@ -90,6 +101,8 @@ pub fn update_physics(
 
         let c_body = CollisionBody { body, speed, radius, physics_state };
         space.contents.push((uid, c_body));
+        space::terrain::watch(space, time, uid);
+        space::triggers::watch(space, time, uid);
 
         if bounce {
             march(space, time, uid);
@@ -518,7 +531,14 @@ fn perform_contact(
 ) {
     match contact_type {
         ContactType::Collision => {
+            if let Some(position) = space.position(first_uid, time.now()) {
+                let sound = space::sounds::Sound::Collision;
+                space::sounds::play(time, sound, position);
+            }
             space.in_contact.push((first_uid, second_uid));
+            // the push comes first, so that whatever the entities do about
+            // being hit is done knowing where they are now headed
+            respond(space, time, matter, first_uid, second_uid);
         },
         ContactType::Release | ContactType::Disappear => {
             space.release_contact(first_uid, second_uid);
@@ -529,6 +549,100 @@ fn perform_contact(
     invoke_contact(space, time, matter, second_uid, first_uid, contact_type);
 }
 
+fn material_of(
+    matter: &sulphate::EntityHeap,
+    uid: sulphate::EntityUId,
+) -> Option<space::Material> {
+    if uid.ty == any::TypeId::of::<entities::Player>() {
+        matter.get::<entities::Player>(uid.id).and_then(Collide::material)
+    } else if uid.ty == any::TypeId::of::<entities::Citizen>() {
+        matter.get::<entities::Citizen>(uid.id).and_then(Collide::material)
+    } else if uid.ty == any::TypeId::of::<entities::Building>() {
+        matter.get::<entities::Building>(uid.id).and_then(Collide::material)
+    } else if uid.ty == any::TypeId::of::<entities::Vehicle>() {
+        matter.get::<entities::Vehicle>(uid.id).and_then(Collide::material)
+    } else if uid.ty == any::TypeId::of::<entities::Item>() {
+        matter.get::<entities::Item>(uid.id).and_then(Collide::material)
+    } else {
+        None
+    }
+}
+
+fn push_as<T: Collide>(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+    velocity: units::Velocity,
+) {
+    let mut ent = space.entry::<T>(time, matter, id);
+    let now = ent.now();
+    if let Some(body) = ent.body.as_mut() {
+        body.bounce(velocity, now);
+    }
+}
+
+fn push(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    uid: sulphate::EntityUId,
+    velocity: units::Velocity,
+) {
+    let id = uid.id;
+    if uid.ty == any::TypeId::of::<entities::Player>() {
+        push_as::<entities::Player>(space, time, matter, id, velocity);
+    } else if uid.ty == any::TypeId::of::<entities::Citizen>() {
+        push_as::<entities::Citizen>(space, time, matter, id, velocity);
+    } else if uid.ty == any::TypeId::of::<entities::Building>() {
+        push_as::<entities::Building>(space, time, matter, id, velocity);
+    } else if uid.ty == any::TypeId::of::<entities::Vehicle>() {
+        push_as::<entities::Vehicle>(space, time, matter, id, velocity);
+    } else if uid.ty == any::TypeId::of::<entities::Item>() {
+        push_as::<entities::Item>(space, time, matter, id, velocity);
+    }
+}
+
+fn is_solid(matter: &sulphate::EntityHeap, uid: sulphate::EntityUId) -> bool {
+    entities::image_of(matter, uid).map_or(true, |image| image.is_solid())
+}
+
+// sends two entities that have just met off in whatever directions their
+// materials say, leaving alone any that can't be pushed, and any that met
+// something that isn't solid
+fn respond(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    first_uid: sulphate::EntityUId,
+    second_uid: sulphate::EntityUId,
+) {
+    if !is_solid(matter, first_uid) || !is_solid(matter, second_uid) {
+        return;
+    }
+    let first_material = material_of(matter, first_uid);
+    let second_material = material_of(matter, second_uid);
+    if first_material.is_none() && second_material.is_none() {
+        return;
+    }
+    let after = match (space.get_uid(first_uid), space.get_uid(second_uid)) {
+        (Some(first), Some(second)) => impulse::resolve(
+            time.now(),
+            (&first.body, first_material),
+            (&second.body, second_material),
+        ),
+        _ => None,
+    };
+    if let Some((first_velocity, second_velocity)) = after {
+        if first_material.is_some() {
+            push(space, time, matter, first_uid, first_velocity);
+        }
+        if second_material.is_some() {
+            push(space, time, matter, second_uid, second_velocity);
+        }
+    }
+}
+
 fn perform_disappearance(
     space: &mut space::CollisionSpace,
     time: &mut sulphate::EventQueue,
@@ -631,6 +745,43 @@ fn invoke_contact_with(
     if this_uid.ty == any::TypeId::of::<entities::Player>() {
         let ent = space.entry::<entities::Player>(time, matter, this_uid.id);
 
+        use self::ContactType::*;
+        match contact_type {
+            Collision => Collide::collide(ent, with),
+            Release => Collide::release(ent, with),
+            Disappear => Collide::disappear(ent, with),
+        }
+    } else if this_uid.ty == any::TypeId::of::<entities::Citizen>() {
+        let ent = space.entry::<entities::Citizen>(time, matter, this_uid.id);
+
+        use self::ContactType::*;
+        match contact_type {
+            Collision => Collide::collide(ent, with),
+            Release => Collide::release(ent, with),
+            Disappear => Collide::disappear(ent, with),
+        }
+    } else if this_uid.ty == any::TypeId::of::<entities::Building>() {
+        let ent =
+            space.entry::<entities::Building>(time, matter, this_uid.id);
+
+        use self::ContactType::*;
+        match contact_type {
+            Collision => Collide::collide(ent, with),
+            Release => Collide::release(ent, with),
+            Disappear => Collide::disappear(ent, with),
+        }
+    } else if this_uid.ty == any::TypeId::of::<entities::Vehicle>() {
+        let ent = space.entry::<entities::Vehicle>(time, matter, this_uid.id);
+
+        use self::ContactType::*;
+        match contact_type {
+            Collision => Collide::collide(ent, with),
+            Release => Collide::release(ent, with),
+            Disappear => Collide::disappear(ent, with),
+        }
+    } else if this_uid.ty == any::TypeId::of::<entities::Item>() {
+        let ent = space.entry::<entities::Item>(time, matter, this_uid.id);
+
         use self::ContactType::*;
         match contact_type {
             Collision => Collide::collide(ent, with),
@@ -693,6 +844,12 @@ pub struct CollisionBody {
     radius: units::Distance,
 }
 
+impl CollisionBody {
+    pub fn radius(self: &Self) -> units::Distance {
+        self.radius
+    }
+}
+
 #[derive(Clone, Copy)]
 enum PhysicsState {
     NoMarch,
@@ -778,6 +935,15 @@ impl Body {
         self.current_velocity
     }
 
+    /// Which way the body is going, or None if it is standing still.
+    pub fn heading(&self) -> Option<units::Angle> {
+        if self.current_velocity == units::Velocity::default() {
+            None
+        } else {
+            Some(units::Angle::of(self.current_velocity))
+        }
+    }
+
     pub fn split(
         &self,
         velocity: units::Velocity,
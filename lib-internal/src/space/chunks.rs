@@ -0,0 +1,227 @@
+use std::any;
+use std::collections;
+
+use config;
+use entities::building;
+use entities::player;
+use space;
+use space::TileId;
+use space::ground;
+use space::zones;
+use sulphate;
+use units;
+
+/// How many tiles along each side a chunk is.
+pub const CHUNK_SIZE: i64 = 16;
+
+/// Which chunk, counting chunks rather than tiles from the middle of the
+/// city.
+pub type ChunkId = (i64, i64);
+
+// rounding down on both sides of zero
+fn floor_div(n: i64, by: i64) -> i64 {
+    let div = n / by;
+    if n % by < 0 { div - 1 } else { div }
+}
+
+/// The chunk that tile `id` is in.
+pub fn chunk_of(id: TileId) -> ChunkId {
+    (floor_div(id.0, CHUNK_SIZE), floor_div(id.1, CHUNK_SIZE))
+}
+
+/// The tile at the top left of chunk `id`.
+pub fn corner(id: ChunkId) -> TileId {
+    (id.0 * CHUNK_SIZE, id.1 * CHUNK_SIZE)
+}
+
+/// Every chunk with some part of it within `distance` of `position`
+/// along either axis, row by row.
+pub fn around(
+    position: units::Position,
+    distance: units::Distance,
+) -> Vec<ChunkId> {
+    let reach = units::Displacement { x: distance, y: distance };
+    let (left, top) = chunk_of(space::tile_id(position - reach));
+    let (right, bottom) = chunk_of(space::tile_id(position + reach));
+    let mut chunks = Vec::new();
+    for y in top..bottom + 1 {
+        for x in left..right + 1 {
+            chunks.push((x, y));
+        }
+    }
+    chunks
+}
+
+/// Everything about a chunk of the city that only changes when someone
+/// builds on it, as sent to remote clients when they come to look at it.
+/// What moves about, and the buildings themselves, still come in
+/// snapshots.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Chunk {
+    pub id: ChunkId,
+    /// Every tile in it that isn't open ground.
+    pub terrain: Vec<(TileId, space::Tile)>,
+    /// Every stretch of road starting in it, from one tile to the next.
+    pub roads: Vec<(TileId, TileId)>,
+    pub zones: Vec<(TileId, zones::Zone)>,
+    /// Every stretch of pipe starting in it, from one tile to the next.
+    pub pipes: Vec<(TileId, TileId)>,
+}
+
+impl ground::Ground {
+    /// Whatever of the city is in chunk `id`.
+    pub fn chunk(self: &Self, id: ChunkId) -> Chunk {
+        let starts_here = |segments: Vec<(TileId, TileId)>| {
+            segments.into_iter()
+                    .filter(|&(from, _)| chunk_of(from) == id)
+                    .collect()
+        };
+        Chunk {
+            id,
+            terrain: self.terrain.tiles_in(corner(id), CHUNK_SIZE),
+            roads: starts_here(self.roads.segments()),
+            zones: self.zones
+                       .tiles()
+                       .into_iter()
+                       .filter(|&(tile, _)| chunk_of(tile) == id)
+                       .collect(),
+            pipes: starts_here(self.pipes.segments()),
+        }
+    }
+}
+
+/// Which chunks the systems that tick over the whole city carry on in.
+/// Everything that has already been set going, like a citizen on its way
+/// to work or a fire engine on its way to a fire, carries on wherever it
+/// is.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Heat {
+    /// Every chunk, which is how a city runs unless the config says to
+    /// freeze chunks far from anyone.
+    Everywhere,
+    /// Only these, being those near a player or with a fire in them.
+    Near(collections::BTreeSet<ChunkId>),
+}
+
+impl Heat {
+    pub fn is_hot(self: &Self, id: ChunkId) -> bool {
+        match *self {
+            Heat::Everywhere => true,
+            Heat::Near(ref hot) => hot.contains(&id),
+        }
+    }
+}
+
+/// Which chunks are hot as of `now`, going by `Config::freeze_distance`.
+pub fn heat(
+    space: &space::CollisionSpace,
+    matter: &mut sulphate::EntityHeap,
+    now: units::Time,
+) -> Heat {
+    let distance = config::get(matter).freeze_distance;
+    if distance == 0 {
+        return Heat::Everywhere;
+    }
+    let players = any::TypeId::of::<player::Player>();
+    let buildings = any::TypeId::of::<building::Building>();
+    let mut hot = collections::BTreeSet::new();
+    for body in space.snapshot(now) {
+        if body.uid.ty == players {
+            hot.extend(around(body.position, distance));
+        } else if body.uid.ty == buildings {
+            let burning = matter
+                .get::<building::Building>(body.uid.id)
+                .map_or(false, building::Building::burning);
+            if burning {
+                hot.insert(chunk_of(space::tile_id(body.position)));
+            }
+        }
+    }
+    Heat::Near(hot)
+}
+
+/// How many ticks of a system each frozen chunk has gone without, so that
+/// it can catch up once it is hot again.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Frozen {
+    missed: collections::BTreeMap<ChunkId, u64>,
+}
+
+impl Frozen {
+    pub fn new() -> Frozen {
+        let missed = collections::BTreeMap::new();
+        Frozen { missed }
+    }
+
+    /// For a system ticking with something to do in each of `chunks`, how
+    /// many ticks it should run in each one that is hot: the one it is on,
+    /// and every one it missed while the chunk was frozen. Every chunk
+    /// that isn't hot misses this tick.
+    pub fn thaw(
+        self: &mut Self,
+        heat: &Heat,
+        chunks: collections::BTreeSet<ChunkId>,
+    ) -> collections::BTreeMap<ChunkId, u64> {
+        let mut owed = collections::BTreeMap::new();
+        for id in chunks {
+            if heat.is_hot(id) {
+                let missed = self.missed.remove(&id).unwrap_or(0);
+                owed.insert(id, 1 + missed);
+            } else {
+                *self.missed.entry(id).or_insert(0) += 1;
+            }
+        }
+        // whatever there was to do in them has gone since they froze
+        self.missed.retain(|&id, _| !heat.is_hot(id));
+        owed
+    }
+}
+
+#[cfg(test)]
+mod test_chunks {
+    use units;
+
+    use super::*;
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    #[test]
+    fn chunks_round_down_either_side_of_zero() {
+        assert_eq!(chunk_of((0, 15)), (0, 0));
+        assert_eq!(chunk_of((16, -1)), (1, -1));
+        assert_eq!(chunk_of((-16, -17)), (-1, -2));
+        assert_eq!(corner((-1, 2)), (-16, 32));
+        let all = around(at(10, 10), 20.into());
+        assert_eq!(all, vec![(-1, -1), (0, -1), (-1, 0), (0, 0)]);
+    }
+
+    #[test]
+    fn chunks_hold_only_what_is_in_them() {
+        let mut space = space::CollisionSpace::new();
+        // a row of road across tiles 0 to 20, into the next chunk over
+        let tile = space::TILE_SIZE as i32;
+        space.build_road(at(0, 10), at(20 * tile, 10));
+        let ground = space.ground();
+        let first = ground.chunk((0, 0));
+        let next = ground.chunk((1, 0));
+        assert_eq!(first.roads.len(), 16);
+        assert_eq!(next.roads.len(), 4);
+        assert!(ground.chunk((0, 1)).roads.is_empty());
+    }
+
+    #[test]
+    fn frozen_chunks_catch_up_once_hot() {
+        let mut frozen = Frozen::new();
+        let chunks = || vec![(0, 0), (5, 0)].into_iter().collect();
+        let near = |ids: &[ChunkId]| Heat::Near(ids.iter().cloned().collect());
+        let owed = frozen.thaw(&near(&[(0, 0)]), chunks());
+        assert_eq!(owed.into_iter().collect::<Vec<_>>(), vec![((0, 0), 1)]);
+        frozen.thaw(&near(&[(0, 0)]), chunks());
+        let owed = frozen.thaw(&Heat::Everywhere, chunks());
+        let owed: Vec<_> = owed.into_iter().collect();
+        assert_eq!(owed, vec![((0, 0), 1), ((5, 0), 3)]);
+    }
+}
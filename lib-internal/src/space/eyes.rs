@@ -57,6 +57,10 @@ impl<'a, T> Entry<'a, T>
         self.time.now()
     }
 
+    pub fn id(self: &Self) -> sulphate::EntityId {
+        self.id
+    }
+
     pub fn image(self: &Self) -> Option<Image> {
         self.body
             .clone()
@@ -0,0 +1,59 @@
+use std::sync;
+
+use space;
+use space::roads;
+use space::zones;
+
+/// The parts of a city that only change when someone builds on it, as they
+/// were when it was taken.
+///
+/// A space keeps each part behind an `Arc` that it shares with every ground
+/// taken from it, so taking one costs no more than counting a few more
+/// references. The space only copies a part when it goes to change it
+/// while a ground still holds it, which leaves that ground as it was.
+#[derive(Clone)]
+pub struct Ground {
+    pub terrain: sync::Arc<space::Terrain>,
+    pub roads: sync::Arc<roads::RoadNetwork>,
+    pub zones: sync::Arc<zones::ZoneMap>,
+    pub pipes: sync::Arc<roads::RoadNetwork>,
+}
+
+impl space::CollisionSpace {
+    pub fn ground(self: &Self) -> Ground {
+        Ground {
+            terrain: self.terrain.clone(),
+            roads: self.roads.clone(),
+            zones: self.zones.clone(),
+            pipes: self.pipes.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_ground {
+    use units;
+
+    use super::*;
+
+    #[test]
+    fn ground_stays_as_it_was_taken() {
+        let mut space = space::CollisionSpace::new();
+        let before = space.ground();
+        let again = space.ground();
+        assert!(sync::Arc::ptr_eq(&before.roads, &again.roads));
+
+        let from = units::Position::default();
+        let offset = units::Displacement {
+            x: (space::TILE_SIZE * 3.0).into(),
+            y: 0.into(),
+        };
+        assert!(space.build_road(from, from + offset));
+        let after = space.ground();
+        assert!(before.roads.is_empty());
+        assert_eq!(after.roads.tile_count(), 4);
+        // only what changed is copied
+        assert!(sync::Arc::ptr_eq(&before.terrain, &after.terrain));
+        assert!(!sync::Arc::ptr_eq(&before.roads, &after.roads));
+    }
+}
@@ -0,0 +1,135 @@
+use space;
+use units;
+
+// two circles that meet push on each other along the line between their
+// centers, by however much it takes to stop them closing on each other,
+// plus some fraction of that again to bounce them apart
+
+/// How something moves when it is hit, for things that can be pushed
+/// around at all. Anything without one stands its ground.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Material {
+    /// How hard it is to push, relative to everything else.
+    pub mass: units::Scalar,
+    /// How much of the speed it hits things at it leaves with, from 0 to
+    /// stop dead and shove, to 1 to bounce all the way back.
+    pub restitution: units::Scalar,
+}
+
+// the reciprocal of the mass, which is 0 for anything immovable, so that
+// it takes none of the push
+fn inverse_mass(material: Option<Material>) -> units::Scalar {
+    match material {
+        Some(material) => units::Scalar::from(1) / material.mass,
+        None => Default::default(),
+    }
+}
+
+/// The velocities that two circles leave with after touching at `now`, or
+/// None if nothing changes, because they are already moving apart or
+/// neither can be pushed.
+///
+/// This stays in fixed point, so that it comes out the same on every
+/// machine.
+pub fn resolve(
+    now: units::Time,
+    one: (&space::Body, Option<Material>),
+    other: (&space::Body, Option<Material>),
+) -> Option<(units::Velocity, units::Velocity)> {
+    let (one, one_material) = one;
+    let (other, other_material) = other;
+
+    let normal = one.position(now) - other.position(now);
+    let distance_squared = normal.squared();
+    let relative = one.velocity() - other.velocity();
+    let approach = units::Vector::inner(relative, normal);
+    if distance_squared == 0 || approach >= 0 {
+        return None;
+    }
+
+    let one_inverse = inverse_mass(one_material);
+    let other_inverse = inverse_mass(other_material);
+    let total_inverse = one_inverse + other_inverse;
+    if total_inverse == 0 {
+        return None;
+    }
+
+    // the least bouncy of the two decides, with immovable things taking
+    // after whatever hits them
+    let restitution = match (one_material, other_material) {
+        (Some(a), Some(b)) => {
+            if a.restitution < b.restitution {
+                a.restitution
+            } else {
+                b.restitution
+            }
+        },
+        (Some(a), None) => a.restitution,
+        (None, Some(b)) => b.restitution,
+        (None, None) => unreachable!(),
+    };
+
+    // the push along `normal`, which isn't a unit vector, hence dividing
+    // by its length twice
+    let impulse = -(units::Scalar::from(1) + restitution) * approach
+                / (total_inverse * distance_squared);
+    let one_after = one.velocity() + normal * (impulse * one_inverse);
+    let other_after = other.velocity() - normal * (impulse * other_inverse);
+    Some((one_after, other_after))
+}
+
+#[cfg(test)]
+mod test_impulse {
+    use space;
+    use units;
+
+    use super::*;
+
+    fn body_at(x: i32, vx: i32) -> space::Body {
+        let offset = units::Displacement { x: x.into(), y: 0.into() };
+        let position = units::Position::default() + offset;
+        let velocity = units::Velocity { x: vx.into(), y: 0.into() };
+        space::Body::new(position, velocity, Default::default())
+    }
+
+    fn material(restitution: units::Scalar) -> Option<Material> {
+        let mass = 1.into();
+        Some(Material { mass, restitution })
+    }
+
+    fn x_of(velocity: units::Velocity) -> i32 {
+        assert_eq!(velocity.y, 0);
+        velocity.x.into()
+    }
+
+    #[test]
+    fn equal_masses_trade_places_or_push() {
+        let now = Default::default();
+        let one = body_at(0, 100);
+        let other = body_at(20, 0);
+
+        let bouncy = material(1.into());
+        let (a, b) = resolve(now, (&one, bouncy), (&other, bouncy)).unwrap();
+        assert_eq!((x_of(a), x_of(b)), (0, 100));
+
+        let sticky = material(0.into());
+        let (a, b) = resolve(now, (&one, sticky), (&other, sticky)).unwrap();
+        assert_eq!((x_of(a), x_of(b)), (50, 50));
+    }
+
+    #[test]
+    fn walls_send_things_back() {
+        let now = Default::default();
+        let one = body_at(0, 100);
+        let wall = body_at(20, 0);
+        let half = material(units::Scalar::from(1) / 2);
+        let (a, b) = resolve(now, (&one, half), (&wall, None)).unwrap();
+        assert_eq!((x_of(a), x_of(b)), (-50, 0));
+
+        // nothing happens to things that are already parting, or that
+        // can't be moved
+        let leaving = body_at(0, -100);
+        assert!(resolve(now, (&leaving, half), (&wall, None)).is_none());
+        assert!(resolve(now, (&one, None), (&wall, None)).is_none());
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections;
+
+use space;
+use sulphate;
+use units;
+
+// about the size of a player, so that most bodies only sit in a few cells
+const CELL_SIZE: f64 = 32.0;
+
+type Cell = (i64, i64);
+
+fn floatify_position(position: units::Position) -> [f64; 2] {
+    let origin: units::Position = Default::default();
+    let vec = position - origin;
+    [vec.x.into(), vec.y.into()]
+}
+
+fn cell_of(point: [f64; 2]) -> Cell {
+    let x = (point[0] / CELL_SIZE).floor() as i64;
+    let y = (point[1] / CELL_SIZE).floor() as i64;
+    (x, y)
+}
+
+fn cells_between(min: [f64; 2], max: [f64; 2]) -> Vec<Cell> {
+    let (min_x, min_y) = cell_of(min);
+    let (max_x, max_y) = cell_of(max);
+    let mut cells = Vec::new();
+    for x in min_x..max_x + 1 {
+        for y in min_y..max_y + 1 {
+            cells.push((x, y));
+        }
+    }
+    cells
+}
+
+fn distance_squared(a: [f64; 2], b: [f64; 2]) -> f64 {
+    let dx = a[0] - b[0];
+    let dy = a[1] - b[1];
+    dx * dx + dy * dy
+}
+
+struct Still {
+    body: space::Body,
+    center: [f64; 2],
+    radius: f64,
+    cells: Vec<Cell>,
+}
+
+/// Finds bodies by where they are, without looking at all of them.
+///
+/// Bodies that are standing still are filed under every grid cell that they
+/// overlap. Moving bodies leave their cells without anyone being told, so
+/// they are checked one by one instead; in a city most things stay put.
+pub struct SpatialIndex {
+    cells: collections::HashMap<Cell, Vec<sulphate::EntityUId>>,
+    still: collections::BTreeMap<sulphate::EntityUId, Still>,
+    moving: collections::BTreeMap<sulphate::EntityUId, (space::Body, f64)>,
+}
+
+impl SpatialIndex {
+    pub fn new() -> SpatialIndex {
+        SpatialIndex {
+            cells: collections::HashMap::new(),
+            still: collections::BTreeMap::new(),
+            moving: collections::BTreeMap::new(),
+        }
+    }
+
+    /// Files a body where it is now, replacing wherever it was before.
+    pub fn insert(
+        self: &mut Self,
+        uid: sulphate::EntityUId,
+        body: &space::Body,
+        radius: units::Distance,
+    ) {
+        self.remove(uid);
+        let radius: f64 = radius.into();
+        if body.velocity() == units::Velocity::default() {
+            let center = floatify_position(body.position(Default::default()));
+            let cells = cells_between(
+                [center[0] - radius, center[1] - radius],
+                [center[0] + radius, center[1] + radius],
+            );
+            for &cell in &cells {
+                self.cells.entry(cell).or_insert_with(Vec::new).push(uid);
+            }
+            let body = body.clone();
+            let still = Still { body, center, radius, cells };
+            self.still.insert(uid, still);
+        } else {
+            self.moving.insert(uid, (body.clone(), radius));
+        }
+    }
+
+    pub fn remove(self: &mut Self, uid: sulphate::EntityUId) {
+        if let Some(still) = self.still.remove(&uid) {
+            for cell in still.cells {
+                let emptied = match self.cells.get_mut(&cell) {
+                    Some(filed) => {
+                        filed.retain(|&other| other != uid);
+                        filed.is_empty()
+                    },
+                    None => false,
+                };
+                if emptied {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+        self.moving.remove(&uid);
+    }
+
+    /// The body filed under `uid`, if there is one.
+    pub fn body(
+        self: &Self,
+        uid: sulphate::EntityUId,
+    ) -> Option<&space::Body> {
+        match self.moving.get(&uid) {
+            Some(&(ref body, _)) => Some(body),
+            None => self.still.get(&uid).map(|still| &still.body),
+        }
+    }
+
+    // every body that might overlap the box, with where it is at `now`,
+    // in order of uid so that results don't depend on hashing
+    fn candidates(
+        self: &Self,
+        now: units::Time,
+        min: [f64; 2],
+        max: [f64; 2],
+    ) -> Vec<(sulphate::EntityUId, [f64; 2], f64)> {
+        let mut uids: Vec<sulphate::EntityUId> = cells_between(min, max)
+            .iter()
+            .flat_map(|cell| self.cells.get(cell))
+            .flat_map(|filed| filed.iter().cloned())
+            .collect();
+        uids.sort();
+        uids.dedup();
+
+        let mut result: Vec<_> = uids
+            .into_iter()
+            .map(|uid| {
+                let still = &self.still[&uid];
+                (uid, still.center, still.radius)
+            })
+            .collect();
+        for (&uid, &(ref body, radius)) in &self.moving {
+            let center = floatify_position(body.position(now));
+            result.push((uid, center, radius));
+        }
+        result.sort_by_key(|&(uid, _, _)| uid);
+        result
+    }
+
+    /// Every body that overlaps the circle at `now`.
+    pub fn query_circle(
+        self: &Self,
+        now: units::Time,
+        circle: units::Circle,
+    ) -> Vec<sulphate::EntityUId> {
+        let center = floatify_position(circle.center);
+        let radius: f64 = circle.radius.into();
+        let min = [center[0] - radius, center[1] - radius];
+        let max = [center[0] + radius, center[1] + radius];
+        self.candidates(now, min, max)
+            .into_iter()
+            .filter(|&(_, other, other_radius)| {
+                let reach = radius + other_radius;
+                distance_squared(center, other) < reach * reach
+            })
+            .map(|(uid, _, _)| uid)
+            .collect()
+    }
+
+    /// Every body that overlaps the box at `now`.
+    pub fn query_aabb(
+        self: &Self,
+        now: units::Time,
+        rect: units::Rect,
+    ) -> Vec<sulphate::EntityUId> {
+        let min = floatify_position(rect.min);
+        let max = floatify_position(rect.max);
+        self.candidates(now, min, max)
+            .into_iter()
+            .filter(|&(_, center, radius)| {
+                let closest = [
+                    center[0].max(min[0]).min(max[0]),
+                    center[1].max(min[1]).min(max[1]),
+                ];
+                distance_squared(center, closest) < radius * radius
+            })
+            .map(|(uid, _, _)| uid)
+            .collect()
+    }
+
+    /// The body whose center is closest to `point` at `now`.
+    pub fn nearest_neighbor(
+        self: &Self,
+        now: units::Time,
+        point: units::Position,
+    ) -> Option<sulphate::EntityUId> {
+        let point = floatify_position(point);
+        let mut reach = CELL_SIZE;
+        loop {
+            let min = [point[0] - reach, point[1] - reach];
+            let max = [point[0] + reach, point[1] + reach];
+            let candidates = self.candidates(now, min, max);
+
+            let mut nearest: Option<(sulphate::EntityUId, f64)> = None;
+            let mut still_seen = 0;
+            for (uid, center, _) in candidates {
+                if self.still.contains_key(&uid) {
+                    still_seen += 1;
+                }
+                let dist = distance_squared(point, center);
+                if nearest.map_or(true, |(_, best)| dist < best) {
+                    nearest = Some((uid, dist));
+                }
+            }
+
+            // any still body centered within reach overlaps the box, so it
+            // has been seen, but one further out might still be closer
+            let within_reach =
+                nearest.map_or(false, |(_, best)| best <= reach * reach);
+            if within_reach || still_seen == self.still.len() {
+                return nearest.map(|(uid, _)| uid);
+            }
+            reach *= 2.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_index {
+    use std::any;
+
+    use space;
+    use sulphate;
+    use units;
+
+    use super::*;
+
+    fn uid(id: sulphate::EntityId) -> sulphate::EntityUId {
+        let ty = any::TypeId::of::<()>();
+        sulphate::EntityUId { id, ty }
+    }
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    fn still(x: i32, y: i32) -> space::Body {
+        space::Body::new_frozen(at(x, y))
+    }
+
+    fn circle(x: i32, y: i32, radius: i32) -> units::Circle {
+        units::Circle { center: at(x, y), radius: radius.into() }
+    }
+
+    #[test]
+    fn circle_finds_overlapping_bodies() {
+        let mut index = SpatialIndex::new();
+        index.insert(uid(1), &still(0, 0), 10.into());
+        index.insert(uid(2), &still(100, 0), 10.into());
+        index.insert(uid(3), &still(1000, 0), 10.into());
+
+        let now = Default::default();
+        let found = index.query_circle(now, circle(50, 0, 45));
+        assert!(found == vec![uid(1), uid(2)]);
+        let rect = units::Rect { min: at(95, -5), max: at(2000, 5) };
+        let found = index.query_aabb(now, rect);
+        assert!(found == vec![uid(2), uid(3)]);
+    }
+
+    #[test]
+    fn moving_bodies_are_found_where_they_are_now() {
+        let mut index = SpatialIndex::new();
+        let velocity = units::Velocity { x: 100.into(), y: 0.into() };
+        let body = space::Body::new(at(0, 0), velocity, Default::default());
+        index.insert(uid(1), &body, 10.into());
+
+        let later = units::Time::default() + units::Scalar::from(10);
+        let found = index.query_circle(later, circle(1000, 0, 1));
+        assert!(found == vec![uid(1)]);
+        assert!(index.query_circle(later, circle(0, 0, 1)).is_empty());
+        assert!(index.body(uid(1)) == Some(&body));
+
+        index.insert(uid(1), &still(5, 5), 10.into());
+        assert!(index.body(uid(1)) == Some(&still(5, 5)));
+        index.remove(uid(1));
+        assert!(index.body(uid(1)).is_none());
+    }
+
+    #[test]
+    fn nearest_neighbor_searches_outwards() {
+        let mut index = SpatialIndex::new();
+        let now = Default::default();
+        assert!(index.nearest_neighbor(now, at(0, 0)).is_none());
+
+        index.insert(uid(1), &still(500, 0), 10.into());
+        index.insert(uid(2), &still(-300, 300), 10.into());
+        assert!(index.nearest_neighbor(now, at(0, 0)) == Some(uid(2)));
+
+        index.remove(uid(2));
+        assert!(index.nearest_neighbor(now, at(0, 0)) == Some(uid(1)));
+    }
+}
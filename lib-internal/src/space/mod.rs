@@ -1,29 +1,184 @@
 use std::any;
+use std::collections;
+use std::sync;
 
 use entities;
 use sulphate;
+use units;
 
 mod body;
+pub mod chunks;
 mod eyes;
+pub mod ground;
+mod impulse;
+mod index;
+pub mod nav;
+pub mod pipes;
+pub mod roads;
+pub mod sounds;
+pub mod sweep;
+mod terrain;
+pub mod traffic;
+pub mod triggers;
+pub mod zones;
 
 pub use self::body::Collide;
 pub use self::body::Body;
 pub use self::eyes::Entry;
 pub use self::eyes::Eyes;
 pub use self::eyes::Image;
+pub use self::impulse::Material;
+pub use self::terrain::Impact;
+pub use self::terrain::Terrain;
+pub use self::terrain::Tile;
+pub use self::terrain::TileId;
+pub use self::terrain::TILE_SIZE;
+pub use self::terrain::tile_center;
+pub use self::terrain::tile_id;
+
+// just the parts of a body worth drawing
+#[derive(Clone, PartialEq)]
+pub struct BodySnapshot {
+    pub uid: sulphate::EntityUId,
+    pub position: units::Position,
+    pub velocity: units::Velocity,
+}
 
 // a space is a collection of entities with some kind of location-allocation.
 // it is the medium through which entities can communicate psedunymously
 pub struct CollisionSpace {
     contents: Vec<(sulphate::EntityUId, body::CollisionBody)>,
     in_contact: Vec<(sulphate::EntityUId, sulphate::EntityUId)>,
+    index: index::SpatialIndex,
+    // the ground is shared with whatever snapshots have been taken of it,
+    // and is only copied when it changes while one still holds it
+    terrain: sync::Arc<terrain::Terrain>,
+    // each body has at most one terrain check waiting, otherwise a body
+    // watched twice would be checked twice forever
+    terrain_checks: collections::HashMap<sulphate::EntityUId,
+                                         sulphate::EventHandle>,
+    nav: nav::Navigator,
+    roads: sync::Arc<roads::RoadNetwork>,
+    pipes: sync::Arc<roads::RoadNetwork>,
+    traffic: traffic::Traffic,
+    sounds: sounds::Sounds,
+    zones: sync::Arc<zones::ZoneMap>,
+    triggers: triggers::Triggers,
 }
 
 impl CollisionSpace {
     pub fn new() -> Self {
         let contents = Vec::new();
         let in_contact = Vec::new();
-        CollisionSpace { contents, in_contact }
+        let index = index::SpatialIndex::new();
+        let terrain = sync::Arc::new(terrain::Terrain::new());
+        let terrain_checks = collections::HashMap::new();
+        let nav = nav::Navigator::new();
+        let roads = sync::Arc::new(roads::RoadNetwork::new());
+        let pipes = sync::Arc::new(roads::RoadNetwork::new());
+        let traffic = traffic::Traffic::new();
+        let sounds = sounds::Sounds::new();
+        let zones = sync::Arc::new(zones::ZoneMap::new());
+        let triggers = triggers::Triggers::new();
+        CollisionSpace {
+            contents,
+            in_contact,
+            index,
+            terrain,
+            terrain_checks,
+            nav,
+            roads,
+            pipes,
+            traffic,
+            sounds,
+            zones,
+            triggers,
+        }
+    }
+
+    /// Every body that overlaps the circle at `now`.
+    pub fn query_circle(
+        self: &Self,
+        now: units::Time,
+        circle: units::Circle,
+    ) -> Vec<sulphate::EntityUId> {
+        self.index.query_circle(now, circle)
+    }
+
+    /// Every body that overlaps the box at `now`.
+    pub fn query_aabb(
+        self: &Self,
+        now: units::Time,
+        rect: units::Rect,
+    ) -> Vec<sulphate::EntityUId> {
+        self.index.query_aabb(now, rect)
+    }
+
+    /// Every body that overlaps the circle of `radius` around the body of
+    /// `uid` at `now`, including that body, or nothing if it has no body.
+    pub fn query_around(
+        self: &Self,
+        now: units::Time,
+        uid: sulphate::EntityUId,
+        radius: units::Distance,
+    ) -> Vec<sulphate::EntityUId> {
+        match self.get_uid(uid) {
+            Some(c_body) => {
+                let center = c_body.body.position(now);
+                let circle = units::Circle { center, radius };
+                self.index.query_circle(now, circle)
+            },
+            None => Vec::new(),
+        }
+    }
+
+    /// The same as `snapshot`, but only of the bodies that `query_around`
+    /// finds, which are looked up without going through the rest.
+    pub fn snapshot_around(
+        self: &Self,
+        now: units::Time,
+        uid: sulphate::EntityUId,
+        radius: units::Distance,
+    ) -> Vec<BodySnapshot> {
+        self.query_around(now, uid, radius)
+            .into_iter()
+            .flat_map(|uid| {
+                let body = self.index.body(uid)?;
+                let position = body.position(now);
+                let velocity = body.velocity();
+                Some(BodySnapshot { uid, position, velocity })
+            })
+            .collect()
+    }
+
+    /// The body whose center is closest to `point` at `now`.
+    pub fn nearest_neighbor(
+        self: &Self,
+        now: units::Time,
+        point: units::Position,
+    ) -> Option<sulphate::EntityUId> {
+        self.index.nearest_neighbor(now, point)
+    }
+
+    /// Where the body of `uid` is at `now`, if it has one.
+    pub fn position(
+        self: &Self,
+        uid: sulphate::EntityUId,
+        now: units::Time,
+    ) -> Option<units::Position> {
+        self.get_uid(uid).map(|c_body| c_body.body.position(now))
+    }
+
+    /// The position and velocity of every body, as of `now`.
+    pub fn snapshot(self: &Self, now: units::Time) -> Vec<BodySnapshot> {
+        self.contents
+            .iter()
+            .map(|&(uid, ref c_body)| {
+                let position = c_body.body.position(now);
+                let velocity = c_body.body.velocity();
+                BodySnapshot { uid, position, velocity }
+            })
+            .collect()
     }
 
     fn find<T>(self: &Self, id: sulphate::EntityId) -> Option<usize>
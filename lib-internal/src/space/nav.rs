@@ -0,0 +1,433 @@
+use std::cmp;
+use std::collections;
+
+use space;
+use space::roads;
+use space::TileId;
+use sulphate::jobs;
+use units;
+
+// steps are measured in tenths of a tile so that costs stay whole numbers,
+// which keeps the search the same on every machine
+const STRAIGHT: u64 = 10;
+const DIAGONAL: u64 = 14;
+// going along a road is quicker than cutting across, so paths use them
+// where they can
+const ROAD: u64 = 5;
+
+// the city has no edge, so an unreachable goal would be searched for
+// forever without a limit on how many tiles to look at
+const SEARCH_LIMIT: usize = 4096;
+
+fn octile(a: TileId, b: TileId) -> u64 {
+    let dx = (a.0 - b.0).abs() as u64;
+    let dy = (a.1 - b.1).abs() as u64;
+    let (long, short) = if dx > dy { (dx, dy) } else { (dy, dx) };
+    STRAIGHT * (long - short) + DIAGONAL * short
+}
+
+// never more than the cheapest way there could cost, which is all road
+// once there are any roads at all
+fn estimate(roads: &roads::RoadNetwork, a: TileId, b: TileId) -> u64 {
+    if roads.is_empty() {
+        octile(a, b)
+    } else {
+        octile(a, b) * ROAD / STRAIGHT
+    }
+}
+
+fn is_open(terrain: &space::Terrain, id: TileId) -> bool {
+    !terrain.tile(id).solid()
+}
+
+// the tiles that can be stepped to from `id`, and what it costs,
+// not cutting corners that would scrape along something solid
+fn neighbors(
+    terrain: &space::Terrain,
+    roads: &roads::RoadNetwork,
+    id: TileId,
+) -> Vec<(TileId, u64)> {
+    let mut result = Vec::new();
+    for dx in -1..2 {
+        for dy in -1..2 {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+            let next = (id.0 + dx, id.1 + dy);
+            if !is_open(terrain, next) {
+                continue;
+            }
+            if dx != 0 && dy != 0 {
+                let beside = is_open(terrain, (id.0 + dx, id.1))
+                    && is_open(terrain, (id.0, id.1 + dy));
+                if !beside {
+                    continue;
+                }
+                result.push((next, DIAGONAL));
+            } else if roads.linked(id, next) {
+                result.push((next, ROAD));
+            } else {
+                result.push((next, STRAIGHT));
+            }
+        }
+    }
+    result
+}
+
+#[derive(PartialEq, Eq)]
+struct Frontier {
+    estimate: u64,
+    cost: u64,
+    id: TileId,
+}
+
+// BinaryHeap takes the largest first, so this is backwards,
+// and ties go by tile so that the path found doesn't depend on insertion
+impl Ord for Frontier {
+    fn cmp(self: &Self, other: &Self) -> cmp::Ordering {
+        other.estimate.cmp(&self.estimate)
+                      .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(self: &Self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// The cheapest run of tiles from `start` to `goal`, both included, and
+/// what it costs.
+pub fn search(
+    terrain: &space::Terrain,
+    roads: &roads::RoadNetwork,
+    start: TileId,
+    goal: TileId,
+) -> Option<(Vec<TileId>, u64)> {
+    if !is_open(terrain, start) || !is_open(terrain, goal) {
+        return None;
+    }
+    let mut frontier = collections::BinaryHeap::new();
+    let mut came_from = collections::HashMap::new();
+    let mut costs = collections::HashMap::new();
+    costs.insert(start, 0);
+    let first = estimate(roads, start, goal);
+    frontier.push(Frontier { estimate: first, cost: 0, id: start });
+
+    let mut expanded = 0;
+    while let Some(Frontier { cost, id, .. }) = frontier.pop() {
+        if id == goal {
+            let mut tiles = vec![goal];
+            let mut at = goal;
+            while let Some(&prev) = came_from.get(&at) {
+                tiles.push(prev);
+                at = prev;
+            }
+            tiles.reverse();
+            return Some((tiles, cost));
+        }
+        if costs.get(&id).map_or(false, |&best| best < cost) {
+            // already reached more cheaply
+            continue;
+        }
+        expanded += 1;
+        if expanded > SEARCH_LIMIT {
+            return None;
+        }
+        for (next, step) in neighbors(terrain, roads, id) {
+            let cost = cost + step;
+            if costs.get(&next).map_or(false, |&best| best <= cost) {
+                continue;
+            }
+            costs.insert(next, cost);
+            came_from.insert(next, id);
+            let estimate = cost + estimate(roads, next, goal);
+            frontier.push(Frontier { estimate, cost, id: next });
+        }
+    }
+    None
+}
+
+// only the tiles where the path turns are worth walking to
+fn corners(tiles: &[TileId]) -> Vec<TileId> {
+    let mut result: Vec<TileId> = Vec::new();
+    for (i, &id) in tiles.iter().enumerate() {
+        if i == 0 || i + 1 == tiles.len() {
+            result.push(id);
+            continue;
+        }
+        let prev = tiles[i - 1];
+        let next = tiles[i + 1];
+        let before = (id.0 - prev.0, id.1 - prev.1);
+        let after = (next.0 - id.0, next.1 - id.1);
+        if before != after {
+            result.push(id);
+        }
+    }
+    result
+}
+
+struct Route {
+    // None if there was no way there
+    tiles: Option<Vec<TileId>>,
+    cost: u64,
+}
+
+impl Route {
+    fn of(found: Option<(Vec<TileId>, u64)>) -> Route {
+        match found {
+            Some((tiles, cost)) => Route { tiles: Some(tiles), cost },
+            None => Route { tiles: None, cost: 0 },
+        }
+    }
+}
+
+/// Remembers paths that have been found, and forgets just the ones that a
+/// change to the terrain could affect.
+pub struct Navigator {
+    routes: collections::HashMap<(TileId, TileId), Route>,
+}
+
+impl Navigator {
+    pub fn new() -> Navigator {
+        let routes = collections::HashMap::new();
+        Navigator { routes }
+    }
+
+    pub fn cached(self: &Self) -> usize {
+        self.routes.len()
+    }
+
+    fn route(
+        self: &mut Self,
+        terrain: &space::Terrain,
+        roads: &roads::RoadNetwork,
+        start: TileId,
+        goal: TileId,
+    ) -> Option<Vec<TileId>> {
+        let route = self.routes.entry((start, goal)).or_insert_with(|| {
+            Route::of(search(terrain, roads, start, goal))
+        });
+        route.tiles.clone()
+    }
+
+    /// Waypoints from `from` to `to`, going around anything solid, or None
+    /// if there is no way there.
+    pub fn find_path(
+        self: &mut Self,
+        terrain: &space::Terrain,
+        roads: &roads::RoadNetwork,
+        from: units::Position,
+        to: units::Position,
+    ) -> Option<Vec<units::Position>> {
+        let start = terrain.tile_id(from);
+        let goal = terrain.tile_id(to);
+        let tiles = self.route(terrain, roads, start, goal)?;
+        let corners = corners(&tiles);
+        // the ends are where they were asked to be, not the tile centers
+        let turns = corners.len().saturating_sub(2);
+        let mut waypoints: Vec<units::Position> = corners
+            .into_iter()
+            .skip(1)
+            .take(turns)
+            .map(|id| terrain.center(id))
+            .collect();
+        waypoints.push(to);
+        Some(waypoints)
+    }
+
+    /// Waypoints for each of `trips`, as `find_path` would find them, with
+    /// whatever paths aren't remembered yet searched for as jobs, split up
+    /// over however many threads there are.
+    pub fn find_paths(
+        self: &mut Self,
+        terrain: &space::Terrain,
+        roads: &roads::RoadNetwork,
+        trips: &[(units::Position, units::Position)],
+    ) -> Vec<Option<Vec<units::Position>>> {
+        // each search only once, in tile order, however many make the trip
+        let unknown: collections::BTreeSet<(TileId, TileId)> = trips
+            .iter()
+            .map(|&(from, to)| (terrain.tile_id(from), terrain.tile_id(to)))
+            .filter(|ends| !self.routes.contains_key(ends))
+            .collect();
+        let found = jobs::run_partitioned(
+            unknown.into_iter().collect(),
+            |part| part.into_iter().map(|(start, goal)| {
+                ((start, goal), search(terrain, roads, start, goal))
+            }).collect(),
+        );
+        for (ends, found) in found {
+            self.routes.insert(ends, Route::of(found));
+        }
+        trips.iter()
+             .map(|&(from, to)| self.find_path(terrain, roads, from, to))
+             .collect()
+    }
+
+    /// Forgets any path that `tile` changing at `id` could change.
+    pub fn tile_changed(
+        self: &mut Self,
+        roads: &roads::RoadNetwork,
+        id: TileId,
+        tile: space::Tile,
+    ) {
+        if tile.solid() {
+            // only paths through the tile, or cutting its corners, are
+            // blocked by it
+            self.routes.retain(|_, route| match route.tiles {
+                Some(ref tiles) => tiles.iter().all(|&on| {
+                    (on.0 - id.0).abs() > 1 || (on.1 - id.1).abs() > 1
+                }),
+                None => true,
+            });
+        } else {
+            // a new gap only helps a path if going through it could be
+            // cheaper, and the estimate never overshoots
+            self.routes.retain(|&(start, goal), route| match route.tiles {
+                Some(_) => {
+                    let through = estimate(roads, start, id)
+                        + estimate(roads, id, goal);
+                    through >= route.cost
+                },
+                None => false,
+            });
+        }
+    }
+
+    /// Forgets every path, since any of them might now be quicker by road.
+    pub fn roads_changed(self: &mut Self) {
+        self.routes.clear();
+    }
+}
+
+impl space::CollisionSpace {
+    /// Waypoints that a body at `from` could walk along to reach `to`
+    /// without running into the terrain, the last one being `to`.
+    pub fn find_path(
+        self: &mut Self,
+        from: units::Position,
+        to: units::Position,
+    ) -> Option<Vec<units::Position>> {
+        self.nav.find_path(&self.terrain, &self.roads, from, to)
+    }
+
+    /// Waypoints for each of `trips`, searched for all at once.
+    pub fn find_paths(
+        self: &mut Self,
+        trips: &[(units::Position, units::Position)],
+    ) -> Vec<Option<Vec<units::Position>>> {
+        self.nav.find_paths(&self.terrain, &self.roads, trips)
+    }
+}
+
+#[cfg(test)]
+mod test_nav {
+    use space;
+    use units;
+
+    use super::*;
+
+    fn wall(terrain: &mut space::Terrain, x: i64, ys: ::std::ops::Range<i64>) {
+        for y in ys {
+            terrain.set((x, y), space::Tile::Wall);
+        }
+    }
+
+    fn center(terrain: &space::Terrain, x: i64, y: i64) -> units::Position {
+        terrain.center((x, y))
+    }
+
+    #[test]
+    fn open_ground_is_a_straight_line() {
+        let terrain = space::Terrain::new();
+        let roads = roads::RoadNetwork::new();
+        let (tiles, cost) = search(&terrain, &roads, (0, 0), (5, 0)).unwrap();
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(cost, 50);
+
+        let mut nav = Navigator::new();
+        let from = center(&terrain, 0, 0);
+        let to = center(&terrain, 5, 3);
+        let path = nav.find_path(&terrain, &roads, from, to).unwrap();
+        // one turn, from diagonal to straight
+        assert_eq!(path.len(), 2);
+        assert_eq!(path.last(), Some(&to));
+    }
+
+    #[test]
+    fn goes_around_walls() {
+        let mut terrain = space::Terrain::new();
+        wall(&mut terrain, 2, -3..4);
+        let roads = roads::RoadNetwork::new();
+        let (tiles, _) = search(&terrain, &roads, (0, 0), (4, 0)).unwrap();
+        assert!(tiles.iter().all(|&id| !terrain.tile(id).solid()));
+        assert!(tiles.iter().any(|&(_, y)| y.abs() >= 4));
+    }
+
+    #[test]
+    fn enclosed_goals_are_unreachable() {
+        let mut terrain = space::Terrain::new();
+        wall(&mut terrain, 9, 9..12);
+        wall(&mut terrain, 11, 9..12);
+        terrain.set((10, 9), space::Tile::Water);
+        terrain.set((10, 11), space::Tile::Water);
+        let roads = roads::RoadNetwork::new();
+        assert_eq!(search(&terrain, &roads, (0, 0), (10, 10)), None);
+        assert_eq!(search(&terrain, &roads, (0, 0), (9, 9)), None);
+    }
+
+    #[test]
+    fn changes_only_forget_affected_paths() {
+        let mut terrain = space::Terrain::new();
+        let roads = roads::RoadNetwork::new();
+        let mut nav = Navigator::new();
+        let origin = center(&terrain, 0, 0);
+        nav.find_path(&terrain, &roads, origin, center(&terrain, 5, 0));
+        nav.find_path(&terrain, &roads, origin, center(&terrain, 0, 5));
+        assert_eq!(nav.cached(), 2);
+
+        terrain.set((3, 0), space::Tile::Building);
+        nav.tile_changed(&roads, (3, 0), space::Tile::Building);
+        assert_eq!(nav.cached(), 1);
+
+        // opening a tile far off the remaining path can't shorten it
+        nav.tile_changed(&roads, (50, 50), space::Tile::Open);
+        assert_eq!(nav.cached(), 1);
+    }
+
+    #[test]
+    fn paths_found_together_are_the_same_as_one_by_one() {
+        let mut terrain = space::Terrain::new();
+        wall(&mut terrain, 2, -3..4);
+        let roads = roads::RoadNetwork::new();
+        let trips: Vec<_> = (0..6).map(|y| {
+            (center(&terrain, 0, y), center(&terrain, 4, 3 - y))
+        }).collect();
+        let mut nav = Navigator::new();
+        let together = nav.find_paths(&terrain, &roads, &trips);
+        assert_eq!(nav.cached(), trips.len());
+        let mut nav = Navigator::new();
+        let one_by_one: Vec<_> = trips.iter().map(|&(from, to)| {
+            nav.find_path(&terrain, &roads, from, to)
+        }).collect();
+        assert_eq!(together, one_by_one);
+    }
+
+    #[test]
+    fn roads_are_worth_a_detour() {
+        let terrain = space::Terrain::new();
+        let mut roads = roads::RoadNetwork::new();
+        roads.add_segment((0, 2), (10, 2));
+        let (tiles, cost) =
+            search(&terrain, &roads, (0, 0), (10, 0)).unwrap();
+        assert!(tiles.contains(&(5, 2)));
+        assert!(cost < octile((0, 0), (10, 0)));
+
+        let mut nav = Navigator::new();
+        let from = center(&terrain, 0, 0);
+        nav.find_path(&terrain, &roads, from, center(&terrain, 10, 0));
+        nav.roads_changed();
+        assert_eq!(nav.cached(), 0);
+    }
+}
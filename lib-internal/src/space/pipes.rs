@@ -0,0 +1,72 @@
+use std::sync;
+
+use space;
+use space::TileId;
+use space::roads;
+use units;
+
+impl space::CollisionSpace {
+    /// Which tiles are joined up by pipe, for carrying water.
+    pub fn pipes(self: &Self) -> &roads::RoadNetwork {
+        &self.pipes
+    }
+
+    /// Lays pipe between the tiles under `from` and `to`, which have to be
+    /// in a straight line along the grid. Pipes go under roads and
+    /// buildings, but not water or walls.
+    pub fn lay_pipe(
+        self: &mut Self,
+        from: units::Position,
+        to: units::Position,
+    ) -> bool {
+        let from = self.terrain.tile_id(from);
+        let to = self.terrain.tile_id(to);
+        let clear = match roads::run(from, to) {
+            Some(tiles) => tiles.iter().all(|&id| {
+                let tile = self.terrain.tile(id);
+                tile == space::Tile::Open || tile == space::Tile::Building
+            }),
+            None => false,
+        };
+        if !clear {
+            return false;
+        }
+        sync::Arc::make_mut(&mut self.pipes).add_segment(from, to)
+    }
+
+    /// Digs up each of `stretches`, as given by `RoadNetwork::unlaid`.
+    pub fn dig_up_pipe(self: &mut Self, stretches: &[(TileId, TileId)]) {
+        for &(a, b) in stretches {
+            sync::Arc::make_mut(&mut self.pipes).remove_segment(a, b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_pipes {
+    use sulphate;
+
+    use super::*;
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    #[test]
+    fn pipes_go_under_roads_but_not_water() {
+        let mut space = space::CollisionSpace::new();
+        let mut time = sulphate::EventQueue::new(Default::default());
+        space.build_road(at(0, 10), at(100, 10));
+        assert!(space.lay_pipe(at(0, 10), at(100, 10)));
+        assert!(space.pipes().linked((0, 0), (1, 0)));
+        // and they are a network apart from the roads
+        assert!(space.lay_pipe(at(0, 10), at(0, 100)));
+        assert!(!space.roads().has_road((0, 3)));
+
+        space.set_tile(&mut time, (5, 0), space::Tile::Water);
+        assert!(!space.lay_pipe(at(100, 10), at(200, 10)));
+        assert!(!space.pipes().has_road((4, 0)));
+        assert!(!space.lay_pipe(at(0, 0), at(100, 100)));
+    }
+}
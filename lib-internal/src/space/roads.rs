@@ -0,0 +1,446 @@
+use std::collections;
+use std::sync;
+
+use space;
+use space::TileId;
+use units;
+
+/// Which tiles are joined up by road, as a graph of tiles with a link for
+/// each stretch of road between neighbouring tiles. Pipes are kept the same
+/// way, as a network of their own.
+#[derive(Clone)]
+pub struct RoadNetwork {
+    links: collections::BTreeMap<TileId, collections::BTreeSet<TileId>>,
+}
+
+/// The tiles along a straight run of road, both ends included, or None if
+/// the ends don't share a row or a column.
+pub fn run(from: TileId, to: TileId) -> Option<Vec<TileId>> {
+    let step = if from.0 == to.0 {
+        (0, (to.1 - from.1).signum())
+    } else if from.1 == to.1 {
+        ((to.0 - from.0).signum(), 0)
+    } else {
+        return None;
+    };
+    let mut tiles = vec![from];
+    let mut at = from;
+    while at != to {
+        at = (at.0 + step.0, at.1 + step.1);
+        tiles.push(at);
+    }
+    Some(tiles)
+}
+
+/// Where a road dragged from `from` to `to` turns, going along the row
+/// first and then along the column, since each stretch has to be straight.
+pub fn corner(from: units::Position, to: units::Position) -> units::Position {
+    units::Position { x: to.x, y: from.y }
+}
+
+/// The tiles beside a tile, not counting diagonals, which is what a road
+/// has to be on to reach something.
+pub fn beside(id: TileId) -> [TileId; 4] {
+    [
+        (id.0, id.1 - 1),
+        (id.0, id.1 + 1),
+        (id.0 - 1, id.1),
+        (id.0 + 1, id.1),
+    ]
+}
+
+impl RoadNetwork {
+    pub fn new() -> RoadNetwork {
+        let links = collections::BTreeMap::new();
+        RoadNetwork { links }
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        self.links.is_empty()
+    }
+
+    pub fn has_road(self: &Self, id: TileId) -> bool {
+        self.links.contains_key(&id)
+    }
+
+    /// How many tiles have road on them.
+    pub fn tile_count(self: &Self) -> usize {
+        self.links.len()
+    }
+
+    /// Whether there is a stretch of road straight from `a` to `b`.
+    pub fn linked(self: &Self, a: TileId, b: TileId) -> bool {
+        self.links.get(&a).map_or(false, |next| next.contains(&b))
+    }
+
+    /// Every link, each once, with the smaller tile first.
+    pub fn segments(self: &Self) -> Vec<(TileId, TileId)> {
+        let mut segments = Vec::new();
+        for (&a, next) in &self.links {
+            for &b in next {
+                if a < b {
+                    segments.push((a, b));
+                }
+            }
+        }
+        segments
+    }
+
+    /// Lays road along the row or column from `from` to `to`, returning
+    /// false and laying nothing if they share neither.
+    pub fn add_segment(self: &mut Self, from: TileId, to: TileId) -> bool {
+        let tiles = match run(from, to) {
+            Some(tiles) => tiles,
+            None => return false,
+        };
+        for pair in tiles.windows(2) {
+            self.link(pair[0], pair[1]);
+        }
+        if tiles.len() == 1 {
+            // a road going nowhere still counts as a road
+            self.links.entry(from).or_insert_with(collections::BTreeSet::new);
+        }
+        true
+    }
+
+    /// The stretches of road that laying road from `from` to `to` would
+    /// add, each as the pair of tiles it joins, or a tile paired with itself
+    /// for a road going nowhere. None if they share neither a row nor a
+    /// column.
+    pub fn unlaid(
+        self: &Self,
+        from: TileId,
+        to: TileId,
+    ) -> Option<Vec<(TileId, TileId)>> {
+        let tiles = run(from, to)?;
+        if tiles.len() == 1 {
+            if self.has_road(from) {
+                return Some(Vec::new());
+            }
+            return Some(vec![(from, from)]);
+        }
+        let unlaid = tiles.windows(2)
+                          .map(|pair| (pair[0], pair[1]))
+                          .filter(|&(a, b)| !self.linked(a, b))
+                          .collect();
+        Some(unlaid)
+    }
+
+    /// Digs up the road from `from` to `to`, as `add_segment` would have
+    /// laid it.
+    pub fn remove_segment(self: &mut Self, from: TileId, to: TileId) -> bool {
+        let tiles = match run(from, to) {
+            Some(tiles) => tiles,
+            None => return false,
+        };
+        for pair in tiles.windows(2) {
+            self.unlink(pair[0], pair[1]);
+        }
+        for id in tiles {
+            let unused = self.links.get(&id).map_or(false, |next| {
+                next.is_empty()
+            });
+            if unused {
+                self.links.remove(&id);
+            }
+        }
+        true
+    }
+
+    fn link(self: &mut Self, a: TileId, b: TileId) {
+        self.links
+            .entry(a)
+            .or_insert_with(collections::BTreeSet::new)
+            .insert(b);
+        self.links
+            .entry(b)
+            .or_insert_with(collections::BTreeSet::new)
+            .insert(a);
+    }
+
+    fn unlink(self: &mut Self, a: TileId, b: TileId) {
+        if let Some(next) = self.links.get_mut(&a) {
+            next.remove(&b);
+        }
+        if let Some(next) = self.links.get_mut(&b) {
+            next.remove(&a);
+        }
+    }
+
+    /// The road tiles that `id` can be got to from, being the tile itself
+    /// if it has road on it, and otherwise any road right beside it.
+    pub fn access(self: &Self, id: TileId) -> Vec<TileId> {
+        if self.has_road(id) {
+            return vec![id];
+        }
+        beside(id).iter()
+                  .cloned()
+                  .filter(|&next| self.has_road(next))
+                  .collect()
+    }
+
+    /// Every road tile that can be driven to from the roads at `id`.
+    pub fn reachable(
+        self: &Self,
+        id: TileId,
+    ) -> collections::BTreeSet<TileId> {
+        let mut seen = collections::BTreeSet::new();
+        let mut open = self.access(id);
+        while let Some(at) = open.pop() {
+            if !seen.insert(at) {
+                continue;
+            }
+            if let Some(next) = self.links.get(&at) {
+                open.extend(next.iter().filter(|&next| !seen.contains(next)));
+            }
+        }
+        seen
+    }
+
+    /// The road tiles from `from` to `to` along the fewest stretches of
+    /// road, both ends included, or None if they aren't both on roads that
+    /// join up.
+    pub fn route(
+        self: &Self,
+        from: TileId,
+        to: TileId,
+    ) -> Option<Vec<TileId>> {
+        if !self.has_road(from) || !self.has_road(to) {
+            return None;
+        }
+        let mut came_from = collections::BTreeMap::new();
+        let mut open = collections::VecDeque::new();
+        open.push_back(from);
+        came_from.insert(from, from);
+        while let Some(at) = open.pop_front() {
+            if at == to {
+                let mut tiles = vec![to];
+                let mut at = to;
+                while at != from {
+                    at = came_from[&at];
+                    tiles.push(at);
+                }
+                tiles.reverse();
+                return Some(tiles);
+            }
+            for &next in &self.links[&at] {
+                if !came_from.contains_key(&next) {
+                    came_from.insert(next, at);
+                    open.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// The road tiles from `from` to `to` that cost the least in all, both
+    /// ends included, where going along the stretch from one tile to the
+    /// next costs `cost` of the two, which has to be positive. None if
+    /// they aren't both on roads that join up. Of routes that cost the
+    /// same, the one found first is taken, so it is the same every time.
+    pub fn route_by<F>(
+        self: &Self,
+        from: TileId,
+        to: TileId,
+        cost: F,
+    ) -> Option<Vec<TileId>>
+        where F: Fn(TileId, TileId) -> i64
+    {
+        if !self.has_road(from) || !self.has_road(to) {
+            return None;
+        }
+        let mut came_from = collections::BTreeMap::new();
+        let mut best = collections::BTreeMap::new();
+        // costs are negated, since the heap gives the largest first
+        let mut open = collections::BinaryHeap::new();
+        best.insert(from, 0);
+        open.push((0, from));
+        while let Some((spent, at)) = open.pop() {
+            let spent = -spent;
+            if best.get(&at).map_or(false, |&known| known < spent) {
+                continue;
+            }
+            if at == to {
+                let mut tiles = vec![to];
+                let mut at = to;
+                while at != from {
+                    at = came_from[&at];
+                    tiles.push(at);
+                }
+                tiles.reverse();
+                return Some(tiles);
+            }
+            for &next in &self.links[&at] {
+                let total = spent + cost(at, next);
+                if best.get(&next).map_or(true, |&known| total < known) {
+                    best.insert(next, total);
+                    came_from.insert(next, at);
+                    open.push((-total, next));
+                }
+            }
+        }
+        None
+    }
+
+    /// Whether the roads at `a` and `b` join up, such as whether a
+    /// building can be driven to from another.
+    pub fn connected(self: &Self, a: TileId, b: TileId) -> bool {
+        let ends = self.access(b);
+        if ends.is_empty() {
+            return false;
+        }
+        let reachable = self.reachable(a);
+        ends.iter().any(|end| reachable.contains(end))
+    }
+}
+
+impl space::CollisionSpace {
+    pub fn roads(self: &Self) -> &RoadNetwork {
+        &self.roads
+    }
+
+    /// Lays road between the tiles under `from` and `to`, which have to
+    /// be in a straight line along the grid, and on open ground the whole
+    /// way.
+    pub fn build_road(
+        self: &mut Self,
+        from: units::Position,
+        to: units::Position,
+    ) -> bool {
+        let from = self.terrain.tile_id(from);
+        let to = self.terrain.tile_id(to);
+        let clear = match run(from, to) {
+            Some(tiles) => {
+                tiles.iter().all(|&id| !self.terrain.tile(id).solid())
+            },
+            None => false,
+        };
+        if !clear {
+            return false;
+        }
+        sync::Arc::make_mut(&mut self.roads).add_segment(from, to);
+        self.nav.roads_changed();
+        true
+    }
+
+    /// Digs up each of `stretches`, as given by `RoadNetwork::unlaid`.
+    pub fn dig_up_road(self: &mut Self, stretches: &[(TileId, TileId)]) {
+        for &(a, b) in stretches {
+            sync::Arc::make_mut(&mut self.roads).remove_segment(a, b);
+        }
+        self.nav.roads_changed();
+    }
+
+    /// Whether the roads reaching `a` join up with those reaching `b`.
+    pub fn road_connected(
+        self: &Self,
+        a: units::Position,
+        b: units::Position,
+    ) -> bool {
+        let a = self.terrain.tile_id(a);
+        let b = self.terrain.tile_id(b);
+        self.roads.connected(a, b)
+    }
+}
+
+#[cfg(test)]
+mod test_roads {
+    use super::*;
+
+    #[test]
+    fn segments_follow_the_grid() {
+        let mut roads = RoadNetwork::new();
+        assert!(!roads.add_segment((0, 0), (2, 1)));
+        assert!(roads.is_empty());
+
+        assert!(roads.add_segment((0, 0), (3, 0)));
+        assert!(roads.linked((1, 0), (2, 0)));
+        assert!(!roads.linked((0, 0), (2, 0)));
+        assert_eq!(roads.segments().len(), 3);
+
+        assert!(roads.remove_segment((3, 0), (2, 0)));
+        assert!(!roads.has_road((3, 0)));
+        assert!(roads.has_road((2, 0)));
+    }
+
+    #[test]
+    fn only_new_road_is_unlaid() {
+        let mut roads = RoadNetwork::new();
+        roads.add_segment((1, 0), (2, 0));
+        assert_eq!(roads.unlaid((0, 1), (2, 0)), None);
+        let unlaid = roads.unlaid((0, 0), (3, 0)).unwrap();
+        assert_eq!(unlaid, vec![((0, 0), (1, 0)), ((2, 0), (3, 0))]);
+        assert_eq!(roads.unlaid((2, 0), (2, 0)), Some(vec![]));
+        assert_eq!(roads.unlaid((5, 5), (5, 5)), Some(vec![((5, 5), (5, 5))]));
+
+        // and digging it back up leaves what was there before
+        roads.add_segment((0, 0), (3, 0));
+        roads.add_segment((5, 5), (5, 5));
+        for (a, b) in unlaid {
+            roads.remove_segment(a, b);
+        }
+        roads.remove_segment((5, 5), (5, 5));
+        assert_eq!(roads.segments(), vec![((1, 0), (2, 0))]);
+        assert!(!roads.has_road((5, 5)));
+    }
+
+    #[test]
+    fn buildings_beside_roads_are_connected() {
+        let mut roads = RoadNetwork::new();
+        roads.add_segment((0, 0), (5, 0));
+        roads.add_segment((5, 0), (5, 5));
+        roads.add_segment((9, 9), (9, 12));
+
+        // off the road, but right next to it
+        assert!(roads.connected((0, 1), (6, 5)));
+        assert!(roads.connected((1, 0), (5, 3)));
+        // only diagonal to the road
+        assert!(!roads.connected((0, 1), (6, 6)));
+        // a road of its own that doesn't join up
+        assert!(!roads.connected((0, 1), (10, 10)));
+
+        roads.remove_segment((5, 1), (5, 2));
+        assert!(!roads.connected((0, 1), (6, 5)));
+    }
+
+    #[test]
+    fn routes_follow_the_roads() {
+        let mut roads = RoadNetwork::new();
+        roads.add_segment((0, 0), (3, 0));
+        roads.add_segment((3, 0), (3, 2));
+        roads.add_segment((0, 0), (0, 2));
+
+        let route = roads.route((0, 2), (3, 2)).unwrap();
+        assert_eq!(route.first(), Some(&(0, 2)));
+        assert_eq!(route.last(), Some(&(3, 2)));
+        assert_eq!(route.len(), 8);
+        assert!(route.windows(2).all(|pair| roads.linked(pair[0], pair[1])));
+
+        assert_eq!(roads.route((0, 2), (1, 1)), None);
+        assert_eq!(roads.route((0, 2), (0, 2)), Some(vec![(0, 2)]));
+    }
+
+    #[test]
+    fn costly_stretches_are_driven_around() {
+        let mut roads = RoadNetwork::new();
+        // a straight road, and a loop around the middle of it
+        roads.add_segment((0, 0), (4, 0));
+        roads.add_segment((1, 0), (1, 1));
+        roads.add_segment((1, 1), (3, 1));
+        roads.add_segment((3, 1), (3, 0));
+
+        let cheap = |_: TileId, _: TileId| -> i64 { 1 };
+        let straight = roads.route_by((0, 0), (4, 0), &cheap).unwrap();
+        assert_eq!(straight.len(), 5);
+        assert_eq!(Some(straight), roads.route((0, 0), (4, 0)));
+
+        // the stretch from (2, 0) to (3, 0)
+        let jammed = |a: TileId, b: TileId| -> i64 {
+            if a.1 == 0 && b.1 == 0 && a.0.min(b.0) == 2 { 10 } else { 1 }
+        };
+        let around = roads.route_by((0, 0), (4, 0), jammed).unwrap();
+        assert_eq!(around.len(), 7);
+        assert!(around.contains(&(2, 1)));
+        assert_eq!(roads.route_by((0, 0), (9, 9), &cheap), None);
+    }
+}
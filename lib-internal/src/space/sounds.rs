@@ -0,0 +1,165 @@
+use std::collections;
+
+use space;
+use sulphate;
+use units;
+
+/// Something that happened loud enough for anyone nearby to hear.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sound {
+    /// A building going up.
+    Construction,
+    /// A building coming down, whether knocked down or burned down.
+    Demolition,
+    /// Two bodies running into each other.
+    Collision,
+    /// A fire engine setting out.
+    Siren,
+}
+
+impl Sound {
+    pub fn all() -> [Sound; 4] {
+        [
+            Sound::Construction,
+            Sound::Demolition,
+            Sound::Collision,
+            Sound::Siren,
+        ]
+    }
+
+    pub fn name(self: Self) -> &'static str {
+        match self {
+            Sound::Construction => "construction",
+            Sound::Demolition => "demolition",
+            Sound::Collision => "collision",
+            Sound::Siren => "siren",
+        }
+    }
+}
+
+/// A sound, and when and where it was made.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Heard {
+    pub time: units::Time,
+    pub sound: Sound,
+    pub position: units::Position,
+}
+
+// long enough that a view sent any time after a sound still has it, even
+// if the one before it was dropped
+fn kept_for() -> units::Duration {
+    units::moments(16)
+}
+
+/// Every sound made lately, oldest first.
+#[derive(Clone)]
+pub struct Sounds {
+    heard: collections::VecDeque<Heard>,
+}
+
+impl Sounds {
+    pub fn new() -> Sounds {
+        Sounds { heard: collections::VecDeque::new() }
+    }
+
+    /// Makes `sound` at `position`, as of `now`, forgetting whatever has
+    /// been kept long enough.
+    pub fn make(
+        self: &mut Self,
+        now: units::Time,
+        sound: Sound,
+        position: units::Position,
+    ) {
+        while self.heard.front().map_or(false, |heard| {
+            heard.time + kept_for() < now
+        }) {
+            self.heard.pop_front();
+        }
+        self.heard.push_back(Heard { time: now, sound, position });
+    }
+
+    /// Every sound made lately as of `now`, oldest first.
+    pub fn recent(self: &Self, now: units::Time) -> Vec<Heard> {
+        self.heard
+            .iter()
+            .filter(|heard| heard.time + kept_for() >= now)
+            .cloned()
+            .collect()
+    }
+}
+
+impl space::CollisionSpace {
+    pub fn sounds(self: &Self) -> &Sounds {
+        &self.sounds
+    }
+
+    /// Makes `sound` at `position`, as of `now`.
+    pub fn make_sound(
+        self: &mut Self,
+        now: units::Time,
+        sound: Sound,
+        position: units::Position,
+    ) {
+        self.sounds.make(now, sound, position);
+    }
+}
+
+/// Makes `sound` at `position` once everything else due now has happened,
+/// unless the server is too far behind for anyone to hear it in time.
+pub fn play(
+    time: &mut sulphate::EventQueue,
+    sound: Sound,
+    position: units::Position,
+) {
+    let now = units::moments(0);
+    time.enqueue_relative(SoundEvent { sound, position }, now);
+}
+
+struct SoundEvent {
+    sound: Sound,
+    position: units::Position,
+}
+
+impl sulphate::Event for SoundEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        _matter: &mut sulphate::EntityHeap,
+    ) {
+        space.make_sound(time.now(), self.sound, self.position);
+    }
+
+    fn cosmetic(self: &Self) -> bool {
+        true
+    }
+
+    fn lane(self: &Self) -> sulphate::Lane {
+        sulphate::Lane::Late
+    }
+}
+
+#[cfg(test)]
+mod test_sounds {
+    use units;
+
+    use super::*;
+
+    #[test]
+    fn sounds_are_forgotten_once_old() {
+        let mut sounds = Sounds::new();
+        let start = units::Time::default();
+        let there = units::Position::default();
+        sounds.make(start, Sound::Siren, there);
+        let soon = start + units::moments(8);
+        sounds.make(soon, Sound::Collision, there);
+        assert_eq!(sounds.recent(soon).len(), 2);
+
+        let later = start + units::moments(20);
+        let recent = sounds.recent(later);
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0].sound, Sound::Collision);
+        sounds.make(later, Sound::Construction, there);
+        assert_eq!(sounds.heard.len(), 2);
+    }
+}
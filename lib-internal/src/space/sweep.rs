@@ -0,0 +1,190 @@
+use space;
+use units;
+
+// swept tests work out the first moment that a moving circle touches
+// something, rather than stepping it along and checking for overlap,
+// so nothing is fast enough to pass through something thin between checks
+
+/// A straight piece of wall, or anything else with no thickness.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Segment {
+    pub start: units::Position,
+    pub end: units::Position,
+}
+
+// the fixed point scalars overflow when multiplied out like this,
+// so the working is done in floats and only the answer is rounded
+fn floatify(vec: units::Vector) -> [f64; 2] {
+    [vec.x.into(), vec.y.into()]
+}
+
+fn inner(a: [f64; 2], b: [f64; 2]) -> f64 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn cross(a: [f64; 2], b: [f64; 2]) -> f64 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+// how long until a circle at `rel_pos` from a point, moving at `vel`,
+// first touches it, ignoring anything it is already moving away from
+fn sweep_point(rel_pos: [f64; 2], vel: [f64; 2], radius: f64) -> Option<f64> {
+    let speed_squared = inner(vel, vel);
+    let approach = inner(rel_pos, vel);
+    if speed_squared == 0.0 || approach >= 0.0 {
+        // still, moving away, or already past
+        return None;
+    }
+    let dist_squared = inner(rel_pos, rel_pos) - radius * radius;
+    if dist_squared <= 0.0 {
+        return Some(0.0);
+    }
+    // this comes from solving (p + vt)^2 = r^2 for the earlier root
+    let disc = approach * approach - speed_squared * dist_squared;
+    if disc < 0.0 {
+        return None;
+    }
+    Some((-approach - disc.sqrt()) / speed_squared)
+}
+
+fn earliest(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+fn after(from: units::Time, dt: f64) -> units::Time {
+    let dt: units::Duration = dt.into();
+    from + dt
+}
+
+/// The first moment from `from` onwards that a circle following `body`
+/// touches `segment`.
+pub fn circle_segment(
+    body: &space::Body,
+    radius: units::Distance,
+    segment: Segment,
+    from: units::Time,
+) -> Option<units::Time> {
+    let radius: f64 = radius.into();
+    let pos = body.position(from);
+    let vel = floatify(body.velocity());
+    let to_start = floatify(pos - segment.start);
+    let to_end = floatify(pos - segment.end);
+
+    // the rounded ends behave just like points
+    let ends = earliest(
+        sweep_point(to_start, vel, radius),
+        sweep_point(to_end, vel, radius),
+    );
+
+    let along = floatify(segment.end - segment.start);
+    let length_squared = inner(along, along);
+    if length_squared == 0.0 {
+        return ends.map(|dt| after(from, dt));
+    }
+    let length = length_squared.sqrt();
+
+    // signed distance from the line through the segment, and its rate
+    let dist = cross(along, to_start) / length;
+    let rate = cross(along, vel) / length;
+    let side = if dist < 0.0 { -radius } else { radius };
+
+    let flat = if rate == 0.0 || (rate < 0.0) == (dist < 0.0) {
+        // moving along or away from the line
+        None
+    } else if dist.abs() <= radius {
+        // already touching the line
+        Some(0.0)
+    } else {
+        Some((side - dist) / rate)
+    };
+    // the flat part only counts if the touch lands between the ends
+    let flat = flat.and_then(|dt| {
+        let touch = [to_start[0] + vel[0] * dt, to_start[1] + vel[1] * dt];
+        let fraction = inner(touch, along) / length_squared;
+        if 0.0 <= fraction && fraction <= 1.0 {
+            Some(dt)
+        } else {
+            None
+        }
+    });
+
+    earliest(flat, ends).map(|dt| after(from, dt))
+}
+
+/// The first moment from `from` onwards that two moving circles touch.
+pub fn circle_circle(
+    one: &space::Body,
+    one_radius: units::Distance,
+    other: &space::Body,
+    other_radius: units::Distance,
+    from: units::Time,
+) -> Option<units::Time> {
+    let rel_pos = floatify(one.position(from) - other.position(from));
+    let rel_vel = floatify(one.velocity() - other.velocity());
+    let reach: f64 = (one_radius + other_radius).into();
+    sweep_point(rel_pos, rel_vel, reach).map(|dt| after(from, dt))
+}
+
+#[cfg(test)]
+mod test_sweep {
+    use space;
+    use units;
+
+    use super::*;
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    fn moving(x: i32, y: i32, vx: i32, vy: i32) -> space::Body {
+        let velocity = units::Velocity { x: vx.into(), y: vy.into() };
+        space::Body::new(at(x, y), velocity, Default::default())
+    }
+
+    fn seconds(toi: Option<units::Time>) -> f64 {
+        let toi = toi.expect("no impact");
+        let dt: units::Duration = toi - units::Time::default();
+        dt.into()
+    }
+
+    #[test]
+    fn fast_circle_hits_thin_wall() {
+        // far more than its radius per second, straight at the wall
+        let body = moving(0, 0, 1000, 0);
+        let wall = Segment { start: at(500, -50), end: at(500, 50) };
+        let toi = circle_segment(&body, 10.into(), wall, Default::default());
+        assert!((seconds(toi) - 0.49).abs() < 0.001);
+    }
+
+    #[test]
+    fn passing_the_end_clips_the_corner() {
+        let body = moving(0, 55, 1000, 0);
+        let wall = Segment { start: at(500, -50), end: at(500, 50) };
+        let toi = circle_segment(&body, 10.into(), wall, Default::default());
+        assert!(seconds(toi) > 0.49 && seconds(toi) < 0.5);
+    }
+
+    #[test]
+    fn misses_are_none() {
+        let wall = Segment { start: at(500, -50), end: at(500, 50) };
+        let parallel = moving(0, 0, 0, 1000);
+        let away = moving(0, 0, -1000, 0);
+        let now = Default::default();
+        assert_eq!(circle_segment(&parallel, 10.into(), wall, now), None);
+        assert_eq!(circle_segment(&away, 10.into(), wall, now), None);
+    }
+
+    #[test]
+    fn circles_meet() {
+        let one = moving(0, 0, 100, 0);
+        let other = moving(100, 0, -100, 0);
+        let toi = circle_circle(&one, 10.into(), &other, 10.into(),
+                                Default::default());
+        assert!((seconds(toi) - 0.4).abs() < 0.001);
+    }
+}
@@ -0,0 +1,535 @@
+use std::any;
+use std::collections;
+use std::sync;
+
+use entities;
+use space;
+use space::sweep;
+use sulphate;
+use sulphate::Scheduler;
+use units;
+
+pub const TILE_SIZE: f64 = 32.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Tile {
+    Open,
+    Building,
+    Water,
+    Wall,
+}
+
+impl Tile {
+    pub fn solid(self: Self) -> bool {
+        self != Tile::Open
+    }
+
+    // for sending tiles to remote clients
+    pub fn tag(self: Self) -> u8 {
+        match self {
+            Tile::Open => 0,
+            Tile::Building => 1,
+            Tile::Water => 2,
+            Tile::Wall => 3,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Tile> {
+        match tag {
+            0 => Some(Tile::Open),
+            1 => Some(Tile::Building),
+            2 => Some(Tile::Water),
+            3 => Some(Tile::Wall),
+            _ => None,
+        }
+    }
+}
+
+pub type TileId = (i64, i64);
+
+/// Where something hits the terrain, and which way the terrain pushes back.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Impact {
+    pub time: units::Time,
+    pub normal: units::Vector,
+}
+
+// how far ahead moving bodies are checked against the terrain,
+// after which they are checked again
+fn lookahead() -> units::Duration {
+    units::moments(16)
+}
+
+// hits this close to sideways are a body sliding past a corner that it
+// has already slid along, rather than running into it
+const GRAZE: f64 = 1e-3;
+
+fn floatify_position(position: units::Position) -> [f64; 2] {
+    let origin: units::Position = Default::default();
+    let vec = position - origin;
+    [vec.x.into(), vec.y.into()]
+}
+
+fn floatify(vec: units::Vector) -> [f64; 2] {
+    [vec.x.into(), vec.y.into()]
+}
+
+fn unfloatify(vec: [f64; 2]) -> units::Vector {
+    units::Vector { x: vec[0].into(), y: vec[1].into() }
+}
+
+fn unfloatify_position(point: [f64; 2]) -> units::Position {
+    units::Position::default() + unfloatify(point)
+}
+
+fn tile_of(point: [f64; 2]) -> TileId {
+    let x = (point[0] / TILE_SIZE).floor() as i64;
+    let y = (point[1] / TILE_SIZE).floor() as i64;
+    (x, y)
+}
+
+/// Which tile `position` is on.
+pub fn tile_id(position: units::Position) -> TileId {
+    tile_of(floatify_position(position))
+}
+
+/// The middle of whichever tile `position` is on.
+pub fn tile_center(position: units::Position) -> units::Position {
+    let (x, y) = tile_id(position);
+    let x = (x as f64 + 0.5) * TILE_SIZE;
+    let y = (y as f64 + 0.5) * TILE_SIZE;
+    unfloatify_position([x, y])
+}
+
+fn closest_on(segment: &[[f64; 2]; 2], point: [f64; 2]) -> [f64; 2] {
+    let a = segment[0];
+    let b = segment[1];
+    let along = [b[0] - a[0], b[1] - a[1]];
+    let rel = [point[0] - a[0], point[1] - a[1]];
+    let length_squared = along[0] * along[0] + along[1] * along[1];
+    let fraction = (rel[0] * along[0] + rel[1] * along[1]) / length_squared;
+    let fraction = fraction.max(0.0).min(1.0);
+    [a[0] + along[0] * fraction, a[1] + along[1] * fraction]
+}
+
+/// Immovable parts of the city, as a grid of tiles that are open unless
+/// something has been put there.
+#[derive(Clone)]
+pub struct Terrain {
+    tiles: collections::BTreeMap<TileId, Tile>,
+}
+
+impl Terrain {
+    pub fn new() -> Terrain {
+        let tiles = collections::BTreeMap::new();
+        Terrain { tiles }
+    }
+
+    pub fn tile(self: &Self, id: TileId) -> Tile {
+        self.tiles.get(&id).cloned().unwrap_or(Tile::Open)
+    }
+
+    pub fn tile_at(self: &Self, position: units::Position) -> Tile {
+        self.tile(tile_of(floatify_position(position)))
+    }
+
+    pub fn tile_id(self: &Self, position: units::Position) -> TileId {
+        tile_of(floatify_position(position))
+    }
+
+    /// Every tile that isn't open, in order.
+    pub fn tiles(self: &Self) -> Vec<(TileId, Tile)> {
+        self.tiles.iter().map(|(&id, &tile)| (id, tile)).collect()
+    }
+
+    /// Every tile that isn't open in the square `size` tiles across with
+    /// `corner` at its top left, in order, without looking at any tile
+    /// outside the columns it spans.
+    pub fn tiles_in(
+        self: &Self,
+        corner: TileId,
+        size: i64,
+    ) -> Vec<(TileId, Tile)> {
+        let (left, top) = corner;
+        let first = (left, i64::min_value());
+        let past = (left + size, i64::min_value());
+        self.tiles
+            .range(first..past)
+            .filter(|&(&(_, y), _)| y >= top && y < top + size)
+            .map(|(&id, &tile)| (id, tile))
+            .collect()
+    }
+
+    /// The middle of a tile.
+    pub fn center(self: &Self, id: TileId) -> units::Position {
+        let x = (id.0 as f64 + 0.5) * TILE_SIZE;
+        let y = (id.1 as f64 + 0.5) * TILE_SIZE;
+        unfloatify_position([x, y])
+    }
+
+    /// Changes a tile without anything noticing, for building terrain up
+    /// before it is put in a space. `CollisionSpace::set_tile` rechecks
+    /// moving bodies as well.
+    pub fn set(self: &mut Self, id: TileId, tile: Tile) {
+        if tile.solid() {
+            self.tiles.insert(id, tile);
+        } else {
+            self.tiles.remove(&id);
+        }
+    }
+
+    /// Whether a circle here would overlap anything solid.
+    pub fn blocked(
+        self: &Self,
+        position: units::Position,
+        radius: units::Distance,
+    ) -> bool {
+        let center = floatify_position(position);
+        let radius: f64 = radius.into();
+        let (min_x, min_y) = tile_of([center[0] - radius, center[1] - radius]);
+        let (max_x, max_y) = tile_of([center[0] + radius, center[1] + radius]);
+        for x in min_x..max_x + 1 {
+            for y in min_y..max_y + 1 {
+                if !self.tile((x, y)).solid() {
+                    continue;
+                }
+                let closest = [
+                    center[0].max(x as f64 * TILE_SIZE)
+                             .min((x + 1) as f64 * TILE_SIZE),
+                    center[1].max(y as f64 * TILE_SIZE)
+                             .min((y + 1) as f64 * TILE_SIZE),
+                ];
+                let dx = center[0] - closest[0];
+                let dy = center[1] - closest[1];
+                if dx * dx + dy * dy < radius * radius {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    // the sides of solid tiles that face open ones, within the box
+    fn edges(
+        self: &Self,
+        min: [f64; 2],
+        max: [f64; 2],
+    ) -> Vec<[[f64; 2]; 2]> {
+        let (min_x, min_y) = tile_of(min);
+        let (max_x, max_y) = tile_of(max);
+        let mut edges = Vec::new();
+        for x in min_x..max_x + 1 {
+            for y in min_y..max_y + 1 {
+                if !self.tile((x, y)).solid() {
+                    continue;
+                }
+                let left = x as f64 * TILE_SIZE;
+                let right = left + TILE_SIZE;
+                let top = y as f64 * TILE_SIZE;
+                let bottom = top + TILE_SIZE;
+                if !self.tile((x, y - 1)).solid() {
+                    edges.push([[left, top], [right, top]]);
+                }
+                if !self.tile((x, y + 1)).solid() {
+                    edges.push([[left, bottom], [right, bottom]]);
+                }
+                if !self.tile((x - 1, y)).solid() {
+                    edges.push([[left, top], [left, bottom]]);
+                }
+                if !self.tile((x + 1, y)).solid() {
+                    edges.push([[right, top], [right, bottom]]);
+                }
+            }
+        }
+        edges
+    }
+
+    /// The first time within the lookahead that a circle following `body`
+    /// runs into the terrain.
+    pub fn first_impact(
+        self: &Self,
+        body: &space::Body,
+        radius: units::Distance,
+        now: units::Time,
+    ) -> Option<Impact> {
+        let vel = floatify(body.velocity());
+        let speed = (vel[0] * vel[0] + vel[1] * vel[1]).sqrt();
+        if speed == 0.0 {
+            return None;
+        }
+
+        let start = floatify_position(body.position(now));
+        let end = floatify_position(body.position(now + lookahead()));
+        let reach: f64 = radius.into();
+        let min = [start[0].min(end[0]) - reach, start[1].min(end[1]) - reach];
+        let max = [start[0].max(end[0]) + reach, start[1].max(end[1]) + reach];
+
+        let mut first: Option<Impact> = None;
+        for edge in self.edges(min, max) {
+            let segment = sweep::Segment {
+                start: unfloatify_position(edge[0]),
+                end: unfloatify_position(edge[1]),
+            };
+            let time = match sweep::circle_segment(body, radius, segment, now)
+            {
+                Some(time) if time <= now + lookahead() => time,
+                _ => continue,
+            };
+            if first.map_or(false, |first| first.time <= time) {
+                continue;
+            }
+
+            let center = floatify_position(body.position(time));
+            let touch = closest_on(&edge, center);
+            let away = [center[0] - touch[0], center[1] - touch[1]];
+            let dist = (away[0] * away[0] + away[1] * away[1]).sqrt();
+            if dist == 0.0 {
+                continue;
+            }
+            let normal = [away[0] / dist, away[1] / dist];
+            let closing = -(vel[0] * normal[0] + vel[1] * normal[1]);
+            if closing <= GRAZE * speed {
+                continue;
+            }
+
+            let normal = unfloatify(normal);
+            first = Some(Impact { time, normal });
+        }
+        first
+    }
+}
+
+/// What is left of `velocity` once the part going into the terrain is
+/// taken away.
+pub fn slide(
+    velocity: units::Velocity,
+    normal: units::Vector,
+) -> units::Velocity {
+    let vel = floatify(velocity);
+    let normal = floatify(normal);
+    let into = vel[0] * normal[0] + vel[1] * normal[1];
+    if into >= 0.0 {
+        return velocity;
+    }
+    unfloatify([vel[0] - into * normal[0], vel[1] - into * normal[1]])
+}
+
+impl space::CollisionSpace {
+    pub fn terrain(self: &Self) -> &Terrain {
+        &self.terrain
+    }
+
+    /// Changes a tile, and makes sure that anything on its way there
+    /// notices.
+    pub fn set_tile(
+        self: &mut Self,
+        time: &mut sulphate::EventQueue,
+        id: TileId,
+        tile: Tile,
+    ) {
+        sync::Arc::make_mut(&mut self.terrain).set(id, tile);
+        self.nav.tile_changed(&self.roads, id, tile);
+        let uids: Vec<sulphate::EntityUId> =
+            self.contents.iter().map(|&(uid, _)| uid).collect();
+        for uid in uids {
+            watch(self, time, uid);
+        }
+    }
+
+    /// Whether a circle here would overlap the terrain or any body.
+    pub fn blocked(
+        self: &Self,
+        now: units::Time,
+        position: units::Position,
+        radius: units::Distance,
+    ) -> bool {
+        let circle = units::Circle { center: position, radius };
+        self.terrain.blocked(position, radius)
+            || !self.query_circle(now, circle).is_empty()
+    }
+}
+
+// checks a moving body against the terrain when it might hit it,
+// and then either slides it or checks again later
+struct TerrainEvent {
+    uid: sulphate::EntityUId,
+    body: space::Body,
+}
+
+fn check_at(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    event: TerrainEvent,
+    check_time: units::Time,
+) {
+    let uid = event.uid;
+    let check = time.schedule_absolute(event, check_time);
+    space.terrain_checks.insert(uid, check);
+}
+
+/// Starts checking a body against the terrain, if it is moving, and stops
+/// any checks from before.
+pub fn watch(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    uid: sulphate::EntityUId,
+) {
+    let (body, radius) = match space.get_uid(uid) {
+        Some(c_body) => (c_body.body.clone(), c_body.radius()),
+        None => return,
+    };
+    if let Some(old) = space.terrain_checks.remove(&uid) {
+        time.cancel(&old);
+    }
+    if body.velocity() == units::Velocity::default() {
+        return;
+    }
+    let now = time.now();
+    let check_time = match space.terrain.first_impact(&body, radius, now) {
+        Some(impact) => impact.time,
+        None => now + lookahead(),
+    };
+    check_at(space, time, TerrainEvent { uid, body }, check_time);
+}
+
+/// Stops checking a body that has left the space.
+pub fn forget(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    uid: sulphate::EntityUId,
+) {
+    if let Some(check) = space.terrain_checks.remove(&uid) {
+        time.cancel(&check);
+    }
+}
+
+impl sulphate::Event for TerrainEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        space.terrain_checks.remove(&self.uid);
+        let radius = match space.get_uid(self.uid) {
+            Some(c_body) => c_body.radius(),
+            None => return,
+        };
+        let now = time.now();
+        let impact = space.terrain.first_impact(&self.body, radius, now);
+        match impact {
+            Some(impact) if impact.time <= now => {
+                let velocity = slide(self.body.velocity(), impact.normal);
+                // running straight into something else means it's a corner,
+                // so stop rather than bounce between the two
+                let slid = self.body.split(velocity, now);
+                let again = space.terrain.first_impact(&slid, radius, now);
+                let velocity = match again {
+                    Some(again) if again.time <= now => Default::default(),
+                    _ => velocity,
+                };
+                set_velocity(space, time, matter, self.uid, velocity);
+            },
+            Some(impact) => {
+                check_at(space, time, self, impact.time);
+            },
+            None => {
+                let later = now + lookahead();
+                check_at(space, time, self, later);
+            },
+        }
+    }
+}
+
+fn set_velocity(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+    uid: sulphate::EntityUId,
+    velocity: units::Velocity,
+) {
+    if uid.ty == any::TypeId::of::<entities::Player>() {
+        let mut this = space.entry::<entities::Player>(time, matter, uid.id);
+        let now = this.now();
+        if let Some(body) = this.body.as_mut() {
+            body.bounce(velocity, now);
+        }
+    } else if uid.ty == any::TypeId::of::<entities::Citizen>() {
+        let mut this = space.entry::<entities::Citizen>(time, matter, uid.id);
+        let now = this.now();
+        if let Some(body) = this.body.as_mut() {
+            body.bounce(velocity, now);
+        }
+    } else if uid.ty == any::TypeId::of::<entities::Vehicle>() {
+        let mut this = space.entry::<entities::Vehicle>(time, matter, uid.id);
+        let now = this.now();
+        if let Some(body) = this.body.as_mut() {
+            body.bounce(velocity, now);
+        }
+    }
+    // buildings and items never move, so they never reach the terrain
+}
+
+#[cfg(test)]
+mod test_terrain {
+    use space;
+    use units;
+
+    use super::*;
+
+    fn at(x: f64, y: f64) -> units::Position {
+        unfloatify_position([x, y])
+    }
+
+    fn wall_along_x() -> Terrain {
+        // a wall across the top, from x = 0 to x = 320
+        let mut terrain = Terrain::new();
+        for x in 0..10 {
+            terrain.set((x, 0), Tile::Wall);
+        }
+        terrain
+    }
+
+    #[test]
+    fn running_into_a_wall() {
+        let terrain = wall_along_x();
+        let velocity = units::Velocity { x: 0.into(), y: (-100).into() };
+        let body = space::Body::new(at(100.0, 100.0), velocity,
+                                    Default::default());
+        let impact = terrain.first_impact(&body, 10.into(), Default::default())
+                            .expect("should hit the wall");
+
+        // the wall's bottom edge is at y = 32, so the center stops at 42
+        let dt: f64 = (impact.time - units::Time::default()).into();
+        assert!((dt - 0.58).abs() < 0.001);
+        let normal_y: f64 = impact.normal.y.into();
+        assert!((normal_y - 1.0).abs() < 0.001);
+    }
+
+    #[test]
+    fn sliding_along_a_wall_goes_past_the_seams() {
+        let terrain = wall_along_x();
+        let velocity = units::Velocity { x: 100.into(), y: 0.into() };
+        // touching the bottom of the wall, moving along it
+        let body = space::Body::new(at(20.0, 42.0), velocity,
+                                    Default::default());
+        let now = Default::default();
+        assert_eq!(terrain.first_impact(&body, 10.into(), now), None);
+    }
+
+    #[test]
+    fn slides_keep_the_sideways_part() {
+        let velocity = units::Velocity { x: 100.into(), y: (-100).into() };
+        let normal = units::Vector { x: 0.into(), y: 1.into() };
+        let slid = slide(velocity, normal);
+        assert_eq!(slid, units::Velocity { x: 100.into(), y: 0.into() });
+    }
+
+    #[test]
+    fn blocked_by_solid_tiles() {
+        let terrain = wall_along_x();
+        assert!(terrain.blocked(at(100.0, 40.0), 10.into()));
+        assert!(!terrain.blocked(at(100.0, 50.0), 10.into()));
+        assert!(terrain.tile_at(at(100.0, 10.0)).solid());
+    }
+}
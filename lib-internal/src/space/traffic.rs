@@ -0,0 +1,158 @@
+use std::collections;
+
+use space;
+use space::TileId;
+
+// how much slower than on an empty road a vehicle can be made to go, as a
+// percentage, so that a jam still moves
+const MOST_SLOWNESS: i64 = 400;
+
+/// How busy each stretch of road has been lately, counted up as vehicles
+/// go along it and eased off every traffic tick.
+#[derive(Clone)]
+pub struct Traffic {
+    // by stretch, with the smaller tile first, leaving out any that have
+    // gone quiet
+    loads: collections::BTreeMap<(TileId, TileId), i64>,
+}
+
+fn stretch(a: TileId, b: TileId) -> (TileId, TileId) {
+    if a < b { (a, b) } else { (b, a) }
+}
+
+impl Traffic {
+    pub fn new() -> Traffic {
+        Traffic { loads: collections::BTreeMap::new() }
+    }
+
+    /// Counts a vehicle going along the stretch of road between `a` and
+    /// `b`, either way.
+    pub fn pass(self: &mut Self, a: TileId, b: TileId) {
+        *self.loads.entry(stretch(a, b)).or_insert(0) += 1;
+    }
+
+    /// How many vehicles have gone along the stretch between `a` and `b`
+    /// lately.
+    pub fn load(self: &Self, a: TileId, b: TileId) -> i64 {
+        self.loads.get(&stretch(a, b)).cloned().unwrap_or(0)
+    }
+
+    /// Halves the load on every stretch, forgetting those it takes to
+    /// nothing.
+    pub fn ease(self: &mut Self) {
+        for load in self.loads.values_mut() {
+            *load /= 2;
+        }
+        let quiet: Vec<_> = self.loads
+                                .iter()
+                                .filter(|&(_, &load)| load == 0)
+                                .map(|(&stretch, _)| stretch)
+                                .collect();
+        for stretch in quiet {
+            self.loads.remove(&stretch);
+        }
+    }
+
+    /// How long going along the stretch between `a` and `b` takes, as a
+    /// percentage of how long it takes on an empty road, where a stretch
+    /// carries `capacity` vehicles lately before it starts slowing them.
+    pub fn slowness(
+        self: &Self,
+        a: TileId,
+        b: TileId,
+        capacity: i64,
+    ) -> i64 {
+        let capacity = capacity.max(1);
+        let load = self.load(a, b).max(capacity);
+        (100 * load / capacity).min(MOST_SLOWNESS)
+    }
+
+    /// The slowness of every stretch that has had vehicles along it
+    /// lately, with the smaller tile first.
+    pub fn congestion(
+        self: &Self,
+        capacity: i64,
+    ) -> Vec<((TileId, TileId), i64)> {
+        self.loads
+            .keys()
+            .map(|&(a, b)| ((a, b), self.slowness(a, b, capacity)))
+            .collect()
+    }
+}
+
+impl space::CollisionSpace {
+    pub fn traffic(self: &Self) -> &Traffic {
+        &self.traffic
+    }
+
+    pub fn traffic_mut(self: &mut Self) -> &mut Traffic {
+        &mut self.traffic
+    }
+
+    /// The road tiles from `from` to `to` that take the least time to
+    /// drive along, as slowed by the traffic on each stretch, both ends
+    /// included.
+    pub fn quickest_route(
+        self: &Self,
+        from: TileId,
+        to: TileId,
+        capacity: i64,
+    ) -> Option<Vec<TileId>> {
+        let traffic = &self.traffic;
+        self.roads.route_by(from, to, |a, b| {
+            traffic.slowness(a, b, capacity)
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_traffic {
+    use super::*;
+
+    #[test]
+    fn busy_roads_slow_down_and_ease_off() {
+        let mut traffic = Traffic::new();
+        assert_eq!(traffic.slowness((0, 0), (1, 0), 2), 100);
+        for _ in 0..3 {
+            traffic.pass((1, 0), (0, 0));
+        }
+        assert_eq!(traffic.load((0, 0), (1, 0)), 3);
+        assert_eq!(traffic.slowness((0, 0), (1, 0), 2), 150);
+        for _ in 0..10 {
+            traffic.pass((0, 0), (1, 0));
+        }
+        assert_eq!(traffic.slowness((0, 0), (1, 0), 2), MOST_SLOWNESS);
+
+        traffic.ease();
+        assert_eq!(traffic.load((0, 0), (1, 0)), 6);
+        for _ in 0..3 {
+            traffic.ease();
+        }
+        assert!(traffic.congestion(2).is_empty());
+    }
+
+    #[test]
+    fn traffic_goes_around_jams() {
+        let mut space = space::CollisionSpace::new();
+        // a straight road, and a loop around the middle of it
+        let runs = [
+            ((0, 0), (4, 0)),
+            ((1, 0), (1, 1)),
+            ((1, 1), (3, 1)),
+            ((3, 1), (3, 0)),
+        ];
+        for &(from, to) in &runs {
+            let from = space.terrain().center(from);
+            let to = space.terrain().center(to);
+            assert!(space.build_road(from, to));
+        }
+        let route = space.quickest_route((0, 0), (4, 0), 2).unwrap();
+        assert_eq!(route.len(), 5);
+
+        for _ in 0..8 {
+            space.traffic_mut().pass((2, 0), (3, 0));
+        }
+        let route = space.quickest_route((0, 0), (4, 0), 2).unwrap();
+        assert_eq!(route.len(), 7);
+    }
+}
@@ -0,0 +1,428 @@
+use std::collections;
+
+use space;
+use sulphate;
+use sulphate::Scheduler;
+use units;
+
+pub type TriggerId = u64;
+
+/// Part of the space that notices bodies coming and going, without getting
+/// in their way.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Region {
+    Circle(units::Circle),
+    Rect(units::Rect),
+}
+
+fn floatify_position(position: units::Position) -> [f64; 2] {
+    let origin: units::Position = Default::default();
+    let vec = position - origin;
+    [vec.x.into(), vec.y.into()]
+}
+
+fn floatify(vec: units::Vector) -> [f64; 2] {
+    [vec.x.into(), vec.y.into()]
+}
+
+impl Region {
+    /// Whether a body with its center at `position` is inside, which only
+    /// goes by the center, so that a body is never half in.
+    pub fn contains(self: &Self, position: units::Position) -> bool {
+        match *self {
+            Region::Circle(circle) => circle.contains(position),
+            Region::Rect(rect) => rect.contains(position),
+        }
+    }
+
+    // how long until a center leaving `start` at `vel` might next cross the
+    // edge, in seconds, which for boxes includes reaching the line along an
+    // edge away from the edge itself
+    fn next_crossing(
+        self: &Self,
+        start: [f64; 2],
+        vel: [f64; 2],
+    ) -> Option<f64> {
+        let mut times = Vec::new();
+        match *self {
+            Region::Circle(units::Circle { center, radius }) => {
+                let center = floatify_position(center);
+                let radius: f64 = radius.into();
+                let rel = [start[0] - center[0], start[1] - center[1]];
+                // solving |rel + vel t| = radius for t
+                let a = vel[0] * vel[0] + vel[1] * vel[1];
+                let b = 2.0 * (rel[0] * vel[0] + rel[1] * vel[1]);
+                let c = rel[0] * rel[0] + rel[1] * rel[1] - radius * radius;
+                let discriminant = b * b - 4.0 * a * c;
+                if a > 0.0 && discriminant >= 0.0 {
+                    let root = discriminant.sqrt();
+                    times.push((-b - root) / (2.0 * a));
+                    times.push((-b + root) / (2.0 * a));
+                }
+            },
+            Region::Rect(units::Rect { min, max }) => {
+                let min = floatify_position(min);
+                let max = floatify_position(max);
+                for axis in 0..2 {
+                    if vel[axis] == 0.0 {
+                        continue;
+                    }
+                    times.push((min[axis] - start[axis]) / vel[axis]);
+                    times.push((max[axis] - start[axis]) / vel[axis]);
+                }
+            },
+        }
+        times.into_iter()
+             .filter(|&t| t > 0.0)
+             .fold(None, |soonest: Option<f64>, t| {
+                 Some(soonest.map_or(t, |soonest| soonest.min(t)))
+             })
+    }
+}
+
+/// A body going into or out of a trigger.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Crossing {
+    pub trigger: TriggerId,
+    pub uid: sulphate::EntityUId,
+}
+
+/// What a trigger does when something crosses it, which is handed the
+/// world the same as an event is.
+pub type Callback = fn(
+    &mut space::CollisionSpace,
+    &mut sulphate::EventQueue,
+    &mut sulphate::EntityHeap,
+    Crossing,
+);
+
+#[derive(Clone, Copy)]
+pub struct Trigger {
+    pub region: Region,
+    pub on_enter: Callback,
+    pub on_exit: Callback,
+}
+
+// how far ahead moving bodies are checked against triggers, after which they
+// are checked again, so that slow bodies aren't put off too far to count
+fn lookahead() -> units::Duration {
+    units::moments(16)
+}
+
+/// Every trigger in a space, and which bodies are inside which.
+pub struct Triggers {
+    next_id: TriggerId,
+    triggers: collections::BTreeMap<TriggerId, Trigger>,
+    inside: collections::BTreeSet<(TriggerId, sulphate::EntityUId)>,
+    // each body has at most one check waiting, the same as with the terrain
+    checks: collections::HashMap<sulphate::EntityUId, sulphate::EventHandle>,
+}
+
+impl Triggers {
+    pub fn new() -> Triggers {
+        Triggers {
+            next_id: 0,
+            triggers: collections::BTreeMap::new(),
+            inside: collections::BTreeSet::new(),
+            checks: collections::HashMap::new(),
+        }
+    }
+
+    pub fn get(self: &Self, id: TriggerId) -> Option<&Trigger> {
+        self.triggers.get(&id)
+    }
+
+    /// Whether the body of `uid` was inside the trigger as of the last time
+    /// it was checked.
+    pub fn is_inside(
+        self: &Self,
+        id: TriggerId,
+        uid: sulphate::EntityUId,
+    ) -> bool {
+        self.inside.contains(&(id, uid))
+    }
+}
+
+impl space::CollisionSpace {
+    pub fn triggers(self: &Self) -> &Triggers {
+        &self.triggers
+    }
+
+    /// Adds a trigger, which goes off straight away for anything that is
+    /// already inside it.
+    pub fn add_trigger(
+        self: &mut Self,
+        time: &mut sulphate::EventQueue,
+        trigger: Trigger,
+    ) -> TriggerId {
+        let id = self.triggers.next_id;
+        self.triggers.next_id += 1;
+        self.triggers.triggers.insert(id, trigger);
+        self.watch_all(time);
+        id
+    }
+
+    /// Takes a trigger away, without it going off for anything that was
+    /// inside it.
+    pub fn remove_trigger(
+        self: &mut Self,
+        time: &mut sulphate::EventQueue,
+        id: TriggerId,
+    ) -> Option<Trigger> {
+        let removed = self.triggers.triggers.remove(&id);
+        let left: Vec<_> = self.triggers
+                               .inside
+                               .iter()
+                               .filter(|&&(trigger, _)| trigger == id)
+                               .cloned()
+                               .collect();
+        for pair in left {
+            self.triggers.inside.remove(&pair);
+        }
+        self.watch_all(time);
+        removed
+    }
+
+    fn watch_all(self: &mut Self, time: &mut sulphate::EventQueue) {
+        let uids: Vec<sulphate::EntityUId> =
+            self.contents.iter().map(|&(uid, _)| uid).collect();
+        for uid in uids {
+            watch(self, time, uid);
+        }
+    }
+}
+
+// lets the trigger know, once the queue gets to it
+struct CrossingEvent {
+    crossing: Crossing,
+    entered: bool,
+}
+
+impl sulphate::Event for CrossingEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        let callback = match space.triggers.get(self.crossing.trigger) {
+            Some(trigger) if self.entered => trigger.on_enter,
+            Some(trigger) => trigger.on_exit,
+            // taken away since
+            None => return,
+        };
+        callback(space, time, matter, self.crossing);
+    }
+}
+
+fn cross(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    crossing: Crossing,
+    entered: bool,
+) {
+    let pair = (crossing.trigger, crossing.uid);
+    if entered {
+        space.triggers.inside.insert(pair);
+    } else {
+        space.triggers.inside.remove(&pair);
+    }
+    let now = time.now();
+    sulphate::enqueue_absolute(time, CrossingEvent { crossing, entered }, now);
+}
+
+// checks a moving body against the triggers again when it might have
+// crossed one
+struct TriggerEvent {
+    uid: sulphate::EntityUId,
+}
+
+impl sulphate::Event for TriggerEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        _matter: &mut sulphate::EntityHeap,
+    ) {
+        space.triggers.checks.remove(&self.uid);
+        watch(space, time, self.uid);
+    }
+}
+
+/// Sets off any triggers that a body has gone into or out of, and checks it
+/// again whenever it might next cross one.
+pub fn watch(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    uid: sulphate::EntityUId,
+) {
+    let body = match space.get_uid(uid) {
+        Some(c_body) => c_body.body.clone(),
+        None => return,
+    };
+    if let Some(old) = space.triggers.checks.remove(&uid) {
+        time.cancel(&old);
+    }
+
+    let now = time.now();
+    let position = body.position(now);
+    let start = floatify_position(position);
+    let vel = floatify(body.velocity());
+    let moving = vel != [0.0, 0.0];
+    let mut crossings = Vec::new();
+    let mut soonest: Option<f64> = None;
+    for (&trigger, &Trigger { region, .. }) in &space.triggers.triggers {
+        let inside = region.contains(position);
+        if inside != space.triggers.is_inside(trigger, uid) {
+            crossings.push((Crossing { trigger, uid }, inside));
+        }
+        if !moving {
+            continue;
+        }
+        if let Some(t) = region.next_crossing(start, vel) {
+            soonest = Some(soonest.map_or(t, |soonest| soonest.min(t)));
+        }
+    }
+    for (crossing, entered) in crossings {
+        cross(space, time, crossing, entered);
+    }
+
+    if let Some(t) = soonest {
+        let lookahead = lookahead();
+        let wait = if t < lookahead.into() {
+            // just past the edge, so that rounding doesn't leave it short
+            units::Scalar::from(t) + units::instants(1)
+        } else {
+            lookahead
+        };
+        let check = time.schedule_absolute(TriggerEvent { uid }, now + wait);
+        space.triggers.checks.insert(uid, check);
+    }
+}
+
+/// Stops checking a body that has left the space, letting every trigger it
+/// was in know that it's gone.
+pub fn forget(
+    space: &mut space::CollisionSpace,
+    time: &mut sulphate::EventQueue,
+    uid: sulphate::EntityUId,
+) {
+    if let Some(check) = space.triggers.checks.remove(&uid) {
+        time.cancel(&check);
+    }
+    let was_in: Vec<TriggerId> = space.triggers
+                                      .inside
+                                      .iter()
+                                      .filter(|&&(_, inside)| inside == uid)
+                                      .map(|&(trigger, _)| trigger)
+                                      .collect();
+    for trigger in was_in {
+        cross(space, time, Crossing { trigger, uid }, false);
+    }
+}
+
+#[cfg(test)]
+mod test_triggers {
+    use std::sync::atomic;
+    use std::sync::mpsc;
+
+    use entities::player;
+    use space;
+    use sulphate;
+    use sulphate::server;
+    use units;
+
+    use super::*;
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    #[test]
+    fn regions_go_by_the_center() {
+        let center = at(0, 0);
+        let radius = 10.into();
+        let circle = Region::Circle(units::Circle { center, radius });
+        assert!(circle.contains(at(5, 5)));
+        assert!(!circle.contains(at(10, 0)));
+        let rect = units::Rect { min: at(0, 0), max: at(20, 10) };
+        let aabb = Region::Rect(rect);
+        assert!(aabb.contains(at(0, 0)));
+        assert!(!aabb.contains(at(20, 5)));
+
+        let crossing = circle.next_crossing([-30.0, 0.0], [10.0, 0.0]);
+        assert_eq!(crossing, Some(2.0));
+        assert_eq!(circle.next_crossing([0.0, 0.0], [10.0, 0.0]), Some(1.0));
+        assert_eq!(circle.next_crossing([30.0, 0.0], [10.0, 0.0]), None);
+        assert_eq!(aabb.next_crossing([-10.0, 5.0], [5.0, 0.0]), Some(2.0));
+    }
+
+    // only this test sets these off
+    static ENTERED: atomic::AtomicUsize = atomic::ATOMIC_USIZE_INIT;
+    static EXITED: atomic::AtomicUsize = atomic::ATOMIC_USIZE_INIT;
+
+    fn count_enter(
+        _space: &mut space::CollisionSpace,
+        _time: &mut sulphate::EventQueue,
+        _matter: &mut sulphate::EntityHeap,
+        _crossing: Crossing,
+    ) {
+        ENTERED.fetch_add(1, atomic::Ordering::SeqCst);
+    }
+
+    fn count_exit(
+        _space: &mut space::CollisionSpace,
+        _time: &mut sulphate::EventQueue,
+        _matter: &mut sulphate::EntityHeap,
+        _crossing: Crossing,
+    ) {
+        EXITED.fetch_add(1, atomic::Ordering::SeqCst);
+    }
+
+    fn sync(upd: &mpsc::Sender<server::Interruption>) {
+        let (reply, recv) = mpsc::channel();
+        upd.send(server::Interruption::RequestSnapshot { reply }).unwrap();
+        recv.recv().expect("server didn't reply with snapshot");
+    }
+
+    #[test]
+    fn walking_through_sets_off_enter_then_exit() {
+        let clock = server::ManualClock::new(Default::default());
+        let (upd, mut clock, handle, ()) =
+            server::start_server_with_clock(|space, time, matter| {
+                let center = at(100, 0);
+                let radius = 20.into();
+                let region = Region::Circle(units::Circle { center, radius });
+                let on_enter = count_enter;
+                let on_exit = count_exit;
+                space.add_trigger(time, Trigger { region, on_enter, on_exit });
+
+                let (send, _) = mpsc::channel();
+                let appearance = Default::default();
+                let id = player::Player::new(
+                    space, time, matter, at(0, 0), appearance, send,
+                ).id();
+                let velocity = units::Velocity { x: 100.into(), y: 0.into() };
+                let pace = player::Pace::Run;
+                let control = player::Control::Move { velocity, pace };
+                player::Control::apply(space, time, matter, id, control);
+            }, clock);
+        let count = |counter: &atomic::AtomicUsize| {
+            counter.load(atomic::Ordering::SeqCst)
+        };
+
+        // in at a little after 0.8 seconds, once it has got up to speed,
+        // and out a little after 1.2
+        let second = units::moments(units::MOMENT_RATE as i32);
+        clock.advance(second);
+        sync(&upd);
+        assert_eq!((count(&ENTERED), count(&EXITED)), (1, 0));
+        clock.advance(second);
+        sync(&upd);
+        assert_eq!((count(&ENTERED), count(&EXITED)), (1, 1));
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+}
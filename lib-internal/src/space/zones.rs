@@ -0,0 +1,159 @@
+use std::collections;
+use std::sync;
+
+use space;
+use space::TileId;
+use units;
+
+/// What a tile is set aside for, which decides what grows there.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Zone {
+    Residential,
+    Commercial,
+    Industrial,
+}
+
+impl Zone {
+    pub fn all() -> [Zone; 3] {
+        [Zone::Residential, Zone::Commercial, Zone::Industrial]
+    }
+
+    pub fn name(self: Self) -> &'static str {
+        match self {
+            Zone::Residential => "residential",
+            Zone::Commercial => "commercial",
+            Zone::Industrial => "industrial",
+        }
+    }
+
+    // kept apart from the order of `all`, since it is written to saves
+    pub fn tag(self: Self) -> u8 {
+        match self {
+            Zone::Residential => 0,
+            Zone::Commercial => 1,
+            Zone::Industrial => 2,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<Zone> {
+        match tag {
+            0 => Some(Zone::Residential),
+            1 => Some(Zone::Commercial),
+            2 => Some(Zone::Industrial),
+            _ => None,
+        }
+    }
+}
+
+/// Written in place of a tag for taking the zoning off, since no zone has
+/// it.
+pub const NO_ZONE: u8 = 255;
+
+// the most tiles along either side of a rectangle painted in one go
+const MAX_SPAN: i64 = 64;
+
+/// The tiles in the rectangle with corners `from` and `to`, column by
+/// column, or None if it is too big to paint in one go.
+pub fn rectangle(from: TileId, to: TileId) -> Option<Vec<TileId>> {
+    let (left, right) = (from.0.min(to.0), from.0.max(to.0));
+    let (top, bottom) = (from.1.min(to.1), from.1.max(to.1));
+    if right - left >= MAX_SPAN || bottom - top >= MAX_SPAN {
+        return None;
+    }
+    let mut tiles = Vec::new();
+    for x in left..right + 1 {
+        for y in top..bottom + 1 {
+            tiles.push((x, y));
+        }
+    }
+    Some(tiles)
+}
+
+/// Which tiles are zoned as what, painted on as a layer over the terrain.
+#[derive(Clone)]
+pub struct ZoneMap {
+    zones: collections::BTreeMap<TileId, Zone>,
+}
+
+impl ZoneMap {
+    pub fn new() -> ZoneMap {
+        let zones = collections::BTreeMap::new();
+        ZoneMap { zones }
+    }
+
+    pub fn is_empty(self: &Self) -> bool {
+        self.zones.is_empty()
+    }
+
+    pub fn zone(self: &Self, id: TileId) -> Option<Zone> {
+        self.zones.get(&id).cloned()
+    }
+
+    /// Every zoned tile, in order.
+    pub fn tiles(self: &Self) -> Vec<(TileId, Zone)> {
+        self.zones.iter().map(|(&id, &zone)| (id, zone)).collect()
+    }
+
+    /// Zones every tile in the rectangle with corners `from` and `to`, or
+    /// takes the zoning off them if `zone` is None, returning how many
+    /// tiles were painted, which is none if the rectangle is too big.
+    pub fn paint(
+        self: &mut Self,
+        from: TileId,
+        to: TileId,
+        zone: Option<Zone>,
+    ) -> usize {
+        let tiles = match rectangle(from, to) {
+            Some(tiles) => tiles,
+            None => return 0,
+        };
+        for &id in &tiles {
+            match zone {
+                Some(zone) => self.zones.insert(id, zone),
+                None => self.zones.remove(&id),
+            };
+        }
+        tiles.len()
+    }
+}
+
+impl space::CollisionSpace {
+    pub fn zones(self: &Self) -> &ZoneMap {
+        &self.zones
+    }
+
+    /// Paints `zone` over the tiles in the rectangle between the tiles
+    /// under `from` and `to`.
+    pub fn paint_zone(
+        self: &mut Self,
+        from: units::Position,
+        to: units::Position,
+        zone: Option<Zone>,
+    ) -> usize {
+        let from = self.terrain.tile_id(from);
+        let to = self.terrain.tile_id(to);
+        sync::Arc::make_mut(&mut self.zones).paint(from, to, zone)
+    }
+}
+
+#[cfg(test)]
+mod test_zones {
+    use super::*;
+
+    #[test]
+    fn zones_are_painted_in_rectangles() {
+        let mut zones = ZoneMap::new();
+        assert_eq!(zones.paint((2, 3), (0, 1), Some(Zone::Residential)), 9);
+        assert_eq!(zones.zone((1, 2)), Some(Zone::Residential));
+        assert_eq!(zones.zone((3, 2)), None);
+
+        // painting over a zone replaces it, and None clears it
+        zones.paint((1, 1), (1, 3), Some(Zone::Industrial));
+        assert_eq!(zones.zone((1, 2)), Some(Zone::Industrial));
+        zones.paint((0, 1), (0, 3), None);
+        assert_eq!(zones.tiles().len(), 6);
+
+        assert_eq!(zones.paint((0, 0), (100, 0), Some(Zone::Commercial)), 0);
+        assert_eq!(zones.tiles().len(), 6);
+    }
+}
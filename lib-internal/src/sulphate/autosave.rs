@@ -0,0 +1,266 @@
+use std::path;
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+use space;
+use sulphate;
+use sulphate::server::Interruption;
+use sulphate::snapshot;
+use units;
+
+/// How long to wait between autosaves.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Interval {
+    /// Game time, so that a paused world isn't saved over and over.
+    Game(units::Duration),
+    /// Real time, however fast or slow the world is going.
+    Wall(time::Duration),
+}
+
+/// How often the world saves itself, and where.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Settings {
+    pub interval: Interval,
+    /// How many autosaves are kept, each one writing over the oldest.
+    pub slots: usize,
+    pub dir: path::PathBuf,
+    /// Whether to append each autosave to one journal in `dir` instead,
+    /// which only writes what has changed since the last one.
+    pub journal: bool,
+}
+
+impl Settings {
+    /// Autosaves every `interval` into three slots in the working directory.
+    pub fn every(interval: Interval) -> Settings {
+        Settings {
+            interval,
+            slots: 3,
+            dir: path::PathBuf::from("."),
+            journal: false,
+        }
+    }
+}
+
+/// Where the autosave in slot `n` goes.
+pub fn slot_path(dir: &path::Path, n: usize) -> path::PathBuf {
+    dir.join(format!("autosave_{}.lilc", n))
+}
+
+/// Where autosaves go when they are kept in a journal.
+pub fn journal_path(dir: &path::Path) -> path::PathBuf {
+    dir.join("autosave.lilj")
+}
+
+/// Takes autosaves as they come due, and hands them to the world's writer,
+/// so that the server doesn't wait on the disk.
+pub struct Autosaver {
+    settings: Settings,
+    // each autosaver gets a round of its own, so that autosaves still due
+    // from one that has been replaced can be told apart and ignored
+    round: u64,
+    next_slot: usize,
+    wake: mpsc::Sender<Interruption>,
+    // keeps real time for wall time intervals, and stops once this is
+    // dropped
+    _timer: Option<mpsc::Sender<()>>,
+}
+
+fn spawn_timer(
+    every: time::Duration,
+    wake: mpsc::Sender<Interruption>,
+    round: u64,
+) -> mpsc::Sender<()> {
+    let (stop, recv) = mpsc::channel();
+    thread::spawn(move || {
+        while let Err(mpsc::RecvTimeoutError::Timeout) =
+            recv.recv_timeout(every)
+        {
+            if wake.send(Interruption::Autosave { round }).is_err() {
+                break;
+            }
+        }
+    });
+    stop
+}
+
+// comes due once per game time interval, and asks the server for an
+// autosave, since events can't see the whole world
+struct AutosaveEvent {
+    wake: mpsc::Sender<Interruption>,
+    round: u64,
+}
+
+impl sulphate::Event for AutosaveEvent {
+    fn invoke(
+        self: Self,
+        _space: &mut space::CollisionSpace,
+        _time: &mut sulphate::EventQueue,
+        _matter: &mut sulphate::EntityHeap,
+    ) {
+        let round = self.round;
+        if self.wake.send(Interruption::Autosave { round }).is_err() {
+            warn!("Autosave due, but the server can't be interrupted");
+        }
+    }
+
+    fn lane(self: &Self) -> sulphate::Lane {
+        sulphate::Lane::Late
+    }
+}
+
+/// Starts autosaving with `settings`, in place of any autosaving already
+/// going on. `wake` should be the sender that interruptions come in on.
+pub fn start(
+    world: &mut sulphate::World,
+    settings: Settings,
+    wake: mpsc::Sender<Interruption>,
+) {
+    if settings.slots == 0 {
+        warn!("Autosaves need at least one slot");
+        return;
+    }
+    let round = world.autosave.as_ref().map_or(0, |old| old.round + 1);
+    let timer = match settings.interval {
+        Interval::Game(every) => {
+            let event = AutosaveEvent { wake: wake.clone(), round };
+            sulphate::enqueue_relative(&mut world.queue, event, every);
+            None
+        },
+        Interval::Wall(every) => Some(spawn_timer(every, wake.clone(), round)),
+    };
+    world.autosave = Some(Autosaver {
+        settings,
+        round,
+        next_slot: 0,
+        wake,
+        _timer: timer,
+    });
+}
+
+/// Takes the autosave that `round` asked for, if it is still the current
+/// round, leaving the writing to the world's writer.
+pub fn due(world: &mut sulphate::World, now: units::Time, round: u64) {
+    let snapshot = match world.autosave {
+        Some(ref saver) if saver.round == round => {
+            snapshot::Snapshot::take(world, now)
+        },
+        _ => return,
+    };
+    let (sent, next) = {
+        let saver = world.autosave.as_mut().expect("Autosaver went missing");
+        let sent = if saver.settings.journal {
+            let path = journal_path(&saver.settings.dir);
+            world.writer.append(path, snapshot)
+        } else {
+            let path = slot_path(&saver.settings.dir, saver.next_slot);
+            saver.next_slot = (saver.next_slot + 1) % saver.settings.slots;
+            world.writer.write(path, snapshot)
+        };
+        let next = match saver.settings.interval {
+            Interval::Game(every) => {
+                let wake = saver.wake.clone();
+                Some((AutosaveEvent { wake, round }, every))
+            },
+            Interval::Wall(_) => None,
+        };
+        (sent, next)
+    };
+    if !sent {
+        warn!("Save thread stopped, no longer autosaving");
+        world.autosave = None;
+    } else if let Some((event, every)) = next {
+        sulphate::enqueue_relative(&mut world.queue, event, every);
+    }
+}
+
+#[cfg(test)]
+mod test_autosave {
+    use std::env;
+    use std::fs;
+    use std::thread;
+    use std::time;
+
+    use sulphate::persist;
+    use sulphate::server;
+
+    use super::*;
+
+    // the writing happens on another thread, so give it a moment
+    fn saved_at(path: &path::Path, at: units::Time) -> bool {
+        for _ in 0..100 {
+            match persist::load(path) {
+                Ok(ref saved) if saved.time == at => return true,
+                _ => thread::sleep(time::Duration::from_millis(10)),
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn autosaves_take_turns_between_slots() {
+        let dir = env::temp_dir().join("lil_city_autosave_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, mut clock, handle, ()) = server::start_server_with_clock(
+            |_space, _time, _matter| (),
+            clock,
+        );
+        let every = units::moments(16);
+        let interval = Interval::Game(every);
+        let settings = Settings {
+            interval,
+            slots: 2,
+            dir: dir.clone(),
+            journal: false,
+        };
+        let wake = upd.clone();
+        upd.send(Interruption::StartAutosave { settings, wake }).unwrap();
+
+        clock.advance(every);
+        assert!(saved_at(&slot_path(&dir, 0), start + every));
+        clock.advance(every);
+        assert!(saved_at(&slot_path(&dir, 1), start + every * 2));
+        // the oldest gets written over
+        clock.advance(every);
+        assert!(saved_at(&slot_path(&dir, 0), start + every * 3));
+        assert!(!slot_path(&dir, 2).exists());
+
+        upd.send(Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn journal_autosaves_all_go_in_one_file() {
+        let dir = env::temp_dir().join("lil_city_autosave_journal_test");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, mut clock, handle, ()) = server::start_server_with_clock(
+            |_space, _time, _matter| (),
+            clock,
+        );
+        let every = units::moments(16);
+        let mut settings = Settings::every(Interval::Game(every));
+        settings.dir = dir.clone();
+        settings.journal = true;
+        let wake = upd.clone();
+        upd.send(Interruption::StartAutosave { settings, wake }).unwrap();
+
+        clock.advance(every);
+        assert!(saved_at(&journal_path(&dir), start + every));
+        clock.advance(every);
+        assert!(saved_at(&journal_path(&dir), start + every * 2));
+        assert!(!slot_path(&dir, 0).exists());
+
+        upd.send(Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,30 @@
+use units;
+
+/// What the server does when events take longer to process than the game
+/// time they cover, so that the simulation falls behind its clock.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CatchUp {
+    /// Drop cosmetic events until the simulation has caught up.
+    Skip,
+    /// Process at most this many events each cycle, leaving the rest of the
+    /// backlog for later cycles, so that interruptions still get handled.
+    /// Events that are put off still happen in the same order and at the
+    /// same game time as they would have, only later in real time.
+    Burst {
+        max_events_per_cycle: u64,
+    },
+    /// Slow the clock down so that it never gets too far ahead.
+    SlowClock,
+}
+
+/// How far the simulation can fall behind before the policy kicks in.
+pub fn lag_allowance() -> units::Duration {
+    units::moments(4)
+}
+
+// how many events the world has processed this cycle, for `Burst`
+#[derive(Clone, Copy, Default)]
+pub struct Burst {
+    pub cycle: u64,
+    pub events: u64,
+}
@@ -0,0 +1,127 @@
+use std::collections;
+use std::sync::mpsc;
+
+use units;
+
+/// The most characters that a line of chat can have.
+pub const MAX_LENGTH: usize = 200;
+
+// how many lines someone who starts listening gets to catch up on
+const HISTORY: usize = 50;
+
+/// Something said by a player, stamped with the game time it was said at.
+#[derive(Clone, PartialEq)]
+pub struct ChatLine {
+    pub time: units::Time,
+    /// The name of whoever said it, which is always one word.
+    pub from: String,
+    pub text: String,
+}
+
+impl ChatLine {
+    /// A line from `from` saying `text`, or None if there is nothing left
+    /// of `text` once it is cleaned up.
+    pub fn new(time: units::Time, from: &str, text: &str) -> Option<ChatLine> {
+        let text = clean(text)?;
+        let from = from.split_whitespace().collect::<Vec<_>>().join("_");
+        let from = if from.is_empty() { "someone".to_string() } else { from };
+        Some(ChatLine { time, from, text })
+    }
+}
+
+/// `text` on one line, without control characters or space at either end,
+/// and cut down to `MAX_LENGTH`, or None if that leaves nothing.
+pub fn clean(text: &str) -> Option<String> {
+    let text: String = text.chars()
+                           .map(|c| if c.is_control() { ' ' } else { c })
+                           .collect();
+    let text: String = text.trim().chars().take(MAX_LENGTH).collect();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text)
+    }
+}
+
+/// Everything said recently, and everyone listening for more.
+pub struct Chat {
+    history: collections::VecDeque<ChatLine>,
+    listeners: Vec<mpsc::Sender<ChatLine>>,
+}
+
+impl Chat {
+    pub fn new() -> Chat {
+        let history = collections::VecDeque::new();
+        let listeners = Vec::new();
+        Chat { history, listeners }
+    }
+
+    /// Sends `listener` the recent history, then every line from now on
+    /// until it is dropped.
+    pub fn listen(self: &mut Self, listener: mpsc::Sender<ChatLine>) {
+        for line in &self.history {
+            if listener.send(line.clone()).is_err() {
+                return;
+            }
+        }
+        self.listeners.push(listener);
+    }
+
+    pub fn say(self: &mut Self, line: ChatLine) {
+        self.listeners.retain(|listener| listener.send(line.clone()).is_ok());
+        self.history.push_back(line);
+        if self.history.len() > HISTORY {
+            self.history.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_chat {
+    use std::sync::mpsc;
+    use std::time;
+
+    use sulphate::server;
+    use units;
+
+    use super::*;
+
+    #[test]
+    fn lines_are_cleaned_up() {
+        let now = units::Time::default();
+        assert!(ChatLine::new(now, "a", " \n\t ").is_none());
+        let line = ChatLine::new(now, "two words", " hi\nthere ").unwrap();
+        assert_eq!(line.from, "two_words");
+        assert_eq!(line.text, "hi there");
+        let long: String = (0..MAX_LENGTH * 2).map(|_| 'a').collect();
+        assert_eq!(clean(&long).unwrap().len(), MAX_LENGTH);
+    }
+
+    #[test]
+    fn late_listeners_catch_up() {
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, _clock, handle, ()) =
+            server::start_server_with_clock(|_, _, _| {}, clock);
+
+        let (early, early_lines) = mpsc::channel();
+        upd.send(server::Interruption::ListenToChat { sender: early })
+           .unwrap();
+        let text = "hello".to_string();
+        upd.send(server::Interruption::Chat { from: 7, text }).unwrap();
+        let (late, late_lines) = mpsc::channel();
+        upd.send(server::Interruption::ListenToChat { sender: late })
+           .unwrap();
+
+        let wait = time::Duration::from_secs(1);
+        for lines in &[early_lines, late_lines] {
+            let line = lines.recv_timeout(wait).expect("nothing was said");
+            assert_eq!(line.text, "hello");
+            assert_eq!(line.from, "player_7");
+            assert!(line.time == start);
+        }
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+}
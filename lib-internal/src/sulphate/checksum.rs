@@ -0,0 +1,165 @@
+use sulphate;
+use sulphate::persist;
+
+// the standard library's hashers are free to change between versions of
+// Rust, so this is FNV-1a written out, to come out the same on every build
+const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+pub struct Checksum {
+    hash: u64,
+}
+
+impl Checksum {
+    pub fn new() -> Checksum {
+        Checksum { hash: OFFSET }
+    }
+
+    pub fn write(self: &mut Self, value: i64) {
+        let mut bytes = [0u8; 8];
+        for i in 0..8 {
+            bytes[i] = (value >> (8 * i)) as u8;
+        }
+        self.write_bytes(&bytes);
+    }
+
+    pub fn write_bytes(self: &mut Self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.hash ^= byte as u64;
+            self.hash = self.hash.wrapping_mul(PRIME);
+        }
+    }
+
+    pub fn finish(self: &Self) -> u64 {
+        self.hash
+    }
+}
+
+/// Hashes the state of some entities, in whatever order they are given.
+///
+/// Only entities with a kind are counted, since they are the only ones that
+/// remote clients get told about.
+pub fn of_entities(entities: &[sulphate::EntitySummary]) -> u64 {
+    let mut sorted: Vec<_> = entities.iter()
+                                     .flat_map(|entity| {
+                                         entity.kind.map(|kind| (entity, kind))
+                                     })
+                                     .collect();
+    sorted.sort_by_key(|&(entity, _)| entity.id);
+
+    let mut checksum = Checksum::new();
+    for (entity, kind) in sorted {
+        checksum.write(entity.id as i64);
+        checksum.write(kind.tag() as i64);
+        checksum.write(entity.position.x.into_bits());
+        checksum.write(entity.position.y.into_bits());
+        checksum.write(entity.velocity.x.into_bits());
+        checksum.write(entity.velocity.y.into_bits());
+    }
+    checksum.finish()
+}
+
+/// Hashes everything that `saved` keeps of a world, which is all the state
+/// that the rest of it follows from: every entity and what it is doing,
+/// the roads, pipes and zones, the books and the randomness. What order it
+/// was gathered in doesn't matter.
+pub fn of_saved(mut saved: persist::SavedWorld) -> u64 {
+    saved.entities.sort_by_key(|entity| entity.id);
+    saved.roads.sort();
+    saved.zones.sort_by_key(|&(tile, _)| tile);
+    saved.drawn.sort();
+    saved.pipes.sort();
+    saved.burning.sort();
+    let mut bytes = Vec::new();
+    saved.write(&mut bytes).expect("couldn't write save to memory");
+
+    let mut checksum = Checksum::new();
+    checksum.write_bytes(&bytes);
+    checksum.finish()
+}
+
+#[cfg(test)]
+mod test_checksum {
+    use entities::item;
+    use sulphate;
+    use sulphate::persist;
+    use units;
+
+    use super::*;
+
+    fn entity(id: sulphate::EntityId, vx: i32) -> sulphate::EntitySummary {
+        sulphate::EntitySummary {
+            id,
+            kind: Some(persist::EntityKind::Player),
+            position: Default::default(),
+            velocity: units::Velocity { x: vx.into(), y: 0.into() },
+        }
+    }
+
+    #[test]
+    fn order_doesnt_matter_but_state_does() {
+        let one = vec![entity(1, 0), entity(2, 5)];
+        let other = vec![entity(2, 5), entity(1, 0)];
+        assert_eq!(of_entities(&one), of_entities(&other));
+
+        let moved = vec![entity(1, 0), entity(2, 6)];
+        assert!(of_entities(&one) != of_entities(&moved));
+
+        let mut unknown = entity(3, 0);
+        unknown.kind = None;
+        let with_unknown = vec![entity(1, 0), entity(2, 5), unknown];
+        assert_eq!(of_entities(&one), of_entities(&with_unknown));
+    }
+
+    fn player(id: sulphate::EntityId) -> persist::SavedEntity {
+        persist::SavedEntity {
+            id,
+            kind: persist::EntityKind::Player,
+            position: Default::default(),
+            velocity: Default::default(),
+            plans: None,
+            blueprint: None,
+            made: None,
+            stops: None,
+            appearance: Some(Default::default()),
+            inventory: Some(Vec::new()),
+            item: None,
+            due: None,
+            waypoints: None,
+            route: None,
+        }
+    }
+
+    fn world(entities: Vec<persist::SavedEntity>) -> persist::SavedWorld {
+        persist::SavedWorld {
+            time: Default::default(),
+            entities,
+            roads: Vec::new(),
+            money: 100,
+            zones: Vec::new(),
+            seed: 1,
+            drawn: Vec::new(),
+            pipes: Vec::new(),
+            burning: Vec::new(),
+            mods: Vec::new(),
+            keys: 2,
+        }
+    }
+
+    #[test]
+    fn saves_hash_everything_they_keep() {
+        let one = of_saved(world(vec![player(1), player(2)]));
+        let other = of_saved(world(vec![player(2), player(1)]));
+        assert_eq!(one, other);
+
+        // nothing that a body would show
+        let mut carrying = player(2);
+        carrying.inventory = Some(vec![item::Kind::Apple]);
+        let carrying = of_saved(world(vec![player(1), carrying]));
+        assert!(one != carrying);
+
+        let mut richer = world(vec![player(1), player(2)]);
+        richer.money += 1;
+        assert!(one != of_saved(richer));
+    }
+}
@@ -0,0 +1,287 @@
+use std::any;
+use std::any::Any;
+use std::collections;
+
+use sulphate::entity_heap::ID;
+
+// components are kept sorted by id so that queries visit entities in the
+// same order every time, which replays depend on
+type Column<C> = collections::BTreeMap<ID, C>;
+
+pub trait Storage: Send {
+    fn remove(self: &mut Self, id: ID);
+    fn as_any(self: &Self) -> &Any;
+    fn as_any_mut(self: &mut Self) -> &mut Any;
+}
+
+impl<C: Any + Send> Storage for Column<C> {
+    fn remove(self: &mut Self, id: ID) {
+        collections::BTreeMap::remove(self, &id);
+    }
+
+    fn as_any(self: &Self) -> &Any {
+        self
+    }
+
+    fn as_any_mut(self: &mut Self) -> &mut Any {
+        self
+    }
+}
+
+static DOWNCAST_ERROR: &'static str = "\
+Component column stored under incorrect type information. \
+";
+
+fn column<C: Any>(storage: &Box<Storage>) -> &Column<C> {
+    storage.as_any()
+           .downcast_ref()
+           .expect(DOWNCAST_ERROR)
+}
+
+fn column_mut<C: Any>(storage: &mut Box<Storage>) -> &mut Column<C> {
+    storage.as_any_mut()
+           .downcast_mut()
+           .expect(DOWNCAST_ERROR)
+}
+
+/// Data attached to entities by type rather than by what kind of entity
+/// they are, so that a system can work on anything with the right parts.
+pub struct Components {
+    columns: collections::HashMap<any::TypeId, Box<Storage>>,
+}
+
+impl Components {
+    pub fn new() -> Components {
+        let columns = collections::HashMap::new();
+        Components { columns }
+    }
+
+    pub fn insert<C: Any + Send>(self: &mut Self, id: ID, component: C) {
+        let ty = any::TypeId::of::<C>();
+        let storage = self.columns.entry(ty).or_insert_with(|| {
+            Box::new(Column::<C>::new()) as Box<Storage>
+        });
+        column_mut(storage).insert(id, component);
+    }
+
+    pub fn get<C: Any + Send>(self: &Self, id: ID) -> Option<&C> {
+        let ty = any::TypeId::of::<C>();
+        self.columns
+            .get(&ty)
+            .and_then(|storage| column(storage).get(&id))
+    }
+
+    pub fn get_mut<C: Any + Send>(
+        self: &mut Self,
+        id: ID,
+    ) -> Option<&mut C> {
+        let ty = any::TypeId::of::<C>();
+        self.columns
+            .get_mut(&ty)
+            .and_then(|storage| column_mut(storage).get_mut(&id))
+    }
+
+    pub fn remove<C: Any + Send>(self: &mut Self, id: ID) -> Option<C> {
+        let ty = any::TypeId::of::<C>();
+        self.columns
+            .get_mut(&ty)
+            .and_then(|storage| column_mut(storage).remove(&id))
+    }
+
+    pub fn remove_all(self: &mut Self, id: ID) {
+        for storage in self.columns.values_mut() {
+            storage.remove(id);
+        }
+    }
+
+    // hands out each column at most once, so that they can be borrowed
+    // mutably side by side
+    fn columns(
+        self: &mut Self,
+        tys: &[any::TypeId],
+    ) -> Option<Vec<&mut Box<Storage>>> {
+        for (i, ty) in tys.iter().enumerate() {
+            assert!(!tys[..i].contains(ty), "component queried twice");
+        }
+        let mut all: collections::HashMap<_, _> =
+            self.columns.iter_mut().collect();
+        tys.iter().map(|ty| all.remove(ty)).collect()
+    }
+
+    pub fn query<'a, Q>(self: &'a mut Self) -> Vec<(ID, Q::Item)>
+        where Q: Query<'a>
+    {
+        Q::run(self)
+    }
+}
+
+pub type Fetched<'a, T> = Box<Iterator<Item = (ID, T)> + 'a>;
+
+/// One part of a query, either `&C` or `&mut C`.
+pub trait Fetch<'a> {
+    type Item;
+    fn component() -> any::TypeId;
+    fn iter(storage: &'a mut Box<Storage>) -> Fetched<'a, Self::Item>;
+}
+
+impl<'a, C: Any + Send> Fetch<'a> for &'a C {
+    type Item = &'a C;
+
+    fn component() -> any::TypeId {
+        any::TypeId::of::<C>()
+    }
+
+    fn iter(storage: &'a mut Box<Storage>) -> Fetched<'a, &'a C> {
+        let column: &'a Column<C> = column(storage);
+        Box::new(column.iter().map(|(&id, c)| (id, c)))
+    }
+}
+
+impl<'a, C: Any + Send> Fetch<'a> for &'a mut C {
+    type Item = &'a mut C;
+
+    fn component() -> any::TypeId {
+        any::TypeId::of::<C>()
+    }
+
+    fn iter(storage: &'a mut Box<Storage>) -> Fetched<'a, &'a mut C> {
+        let column: &'a mut Column<C> = column_mut(storage);
+        Box::new(column.iter_mut().map(|(&id, c)| (id, c)))
+    }
+}
+
+// steps two id-sorted lists along together, keeping the ids in both
+fn join<X, Y, I, J>(xs: I, ys: J) -> Vec<(ID, (X, Y))>
+    where I: Iterator<Item = (ID, X)>,
+          J: Iterator<Item = (ID, Y)>,
+{
+    let mut xs = xs.peekable();
+    let mut ys = ys.peekable();
+    let mut result = Vec::new();
+    loop {
+        let (x_id, y_id) = match (xs.peek(), ys.peek()) {
+            (Some(&(x_id, _)), Some(&(y_id, _))) => (x_id, y_id),
+            _ => break,
+        };
+        if x_id < y_id {
+            xs.next();
+        } else if y_id < x_id {
+            ys.next();
+        } else {
+            let (_, x) = xs.next().unwrap();
+            let (_, y) = ys.next().unwrap();
+            result.push((x_id, (x, y)));
+        }
+    }
+    result
+}
+
+/// A tuple of fetches, giving every entity that has all of the components
+/// in order of id.
+pub trait Query<'a> {
+    type Item;
+    fn run(components: &'a mut Components) -> Vec<(ID, Self::Item)>;
+}
+
+impl<'a, A> Query<'a> for (A,)
+    where A: Fetch<'a>,
+{
+    type Item = (A::Item,);
+
+    fn run(components: &'a mut Components) -> Vec<(ID, Self::Item)> {
+        let tys = [A::component()];
+        let mut columns = match components.columns(&tys) {
+            Some(columns) => columns,
+            None => return Vec::new(),
+        };
+        A::iter(columns.pop().unwrap())
+            .map(|(id, a)| (id, (a,)))
+            .collect()
+    }
+}
+
+impl<'a, A, B> Query<'a> for (A, B)
+    where A: Fetch<'a>,
+          B: Fetch<'a>,
+{
+    type Item = (A::Item, B::Item);
+
+    fn run(components: &'a mut Components) -> Vec<(ID, Self::Item)> {
+        let tys = [A::component(), B::component()];
+        let mut columns = match components.columns(&tys) {
+            Some(columns) => columns,
+            None => return Vec::new(),
+        };
+        let b = B::iter(columns.pop().unwrap());
+        let a = A::iter(columns.pop().unwrap());
+        join(a, b)
+    }
+}
+
+impl<'a, A, B, C> Query<'a> for (A, B, C)
+    where A: Fetch<'a>,
+          B: Fetch<'a>,
+          C: Fetch<'a>,
+{
+    type Item = (A::Item, B::Item, C::Item);
+
+    fn run(components: &'a mut Components) -> Vec<(ID, Self::Item)> {
+        let tys = [A::component(), B::component(), C::component()];
+        let mut columns = match components.columns(&tys) {
+            Some(columns) => columns,
+            None => return Vec::new(),
+        };
+        let c = C::iter(columns.pop().unwrap());
+        let b = B::iter(columns.pop().unwrap());
+        let a = A::iter(columns.pop().unwrap());
+        join(join(a, b).into_iter(), c)
+            .into_iter()
+            .map(|(id, ((a, b), c))| (id, (a, b, c)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test_components {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(i32);
+
+    #[test]
+    fn query_joins_by_id() {
+        let mut components = Components::new();
+        components.insert(3, Position(30));
+        components.insert(1, Position(10));
+        components.insert(2, Position(20));
+        components.insert(3, Velocity(3));
+        components.insert(1, Velocity(1));
+
+        for (_, (position, velocity)) in
+            components.query::<(&mut Position, &Velocity)>()
+        {
+            position.0 += velocity.0;
+        }
+
+        let moved: Vec<(ID, i32)> = components
+            .query::<(&Position, &Velocity)>()
+            .into_iter()
+            .map(|(id, (position, _))| (id, position.0))
+            .collect();
+        assert_eq!(moved, vec![(1, 11), (3, 33)]);
+        assert_eq!(components.get::<Position>(2), Some(&Position(20)));
+    }
+
+    #[test]
+    fn remove_all_clears_every_column() {
+        let mut components = Components::new();
+        components.insert(1, Position(10));
+        components.insert(1, Velocity(1));
+        components.remove_all(1);
+        assert!(components.get::<Position>(1).is_none());
+        assert!(components.query::<(&Position, &Velocity)>().is_empty());
+    }
+}
@@ -0,0 +1,109 @@
+use std::fs;
+use std::io;
+use std::path;
+use std::sync;
+use std::sync::mpsc;
+
+use sulphate;
+use sulphate::replay;
+use sulphate::snapshot;
+use units;
+
+/// Sent to anyone watching the server once it stops.
+#[derive(Clone, PartialEq, Debug)]
+pub enum ServerStatus {
+    Closed,
+    /// `dump` is the save file written from the last checkpoint, if it could
+    /// be written, with the interruptions since then in a replay log
+    /// alongside it.
+    Crashed {
+        dump: Option<path::PathBuf>,
+    },
+}
+
+// how much game time can pass between checkpoints,
+// as long as there are interruptions to trigger them
+fn checkpoint_period() -> units::Duration {
+    units::moments(16 * 10)
+}
+
+pub fn crash_dump_path() -> path::PathBuf {
+    path::PathBuf::from("crash.lilc")
+}
+
+// everything needed to get back to where a crashed server was, kept outside
+// of the world so that it survives the world being dropped by the panic
+struct State {
+    checkpoint: Option<snapshot::Snapshot>,
+    since: Vec<replay::Record>,
+    watchers: Vec<mpsc::Sender<ServerStatus>>,
+}
+
+#[derive(Clone)]
+pub struct Recovery(sync::Arc<sync::Mutex<State>>);
+
+impl Recovery {
+    pub fn new() -> Recovery {
+        let state = State {
+            checkpoint: None,
+            since: Vec::new(),
+            watchers: Vec::new(),
+        };
+        Recovery(sync::Arc::new(sync::Mutex::new(state)))
+    }
+
+    // a panic on the server thread can't leave this half updated,
+    // so a poisoned lock is still fine to use
+    fn state(self: &Self) -> sync::MutexGuard<State> {
+        self.0.lock().unwrap_or_else(|poison| poison.into_inner())
+    }
+
+    pub fn checkpoint_due(self: &Self, now: units::Time) -> bool {
+        match self.state().checkpoint {
+            Some(ref saved) => now - saved.time >= checkpoint_period(),
+            None => true,
+        }
+    }
+
+    pub fn checkpoint(
+        self: &Self,
+        world: &sulphate::World,
+        now: units::Time,
+    ) {
+        let snapshot = snapshot::Snapshot::take(world, now);
+        let mut state = self.state();
+        state.checkpoint = Some(snapshot);
+        state.since.clear();
+    }
+
+    pub fn note(self: &Self, record: replay::Record) {
+        self.state().since.push(record);
+    }
+
+    pub fn watch(self: &Self, watcher: mpsc::Sender<ServerStatus>) {
+        self.state().watchers.push(watcher);
+    }
+
+    /// Writes the last checkpoint to `path`, and the interruptions since
+    /// then to the same path with a `log` extension.
+    pub fn dump(self: &Self, path: &path::Path) -> io::Result<()> {
+        let state = self.state();
+        let saved = state.checkpoint.clone().ok_or_else(|| io::Error::new(
+            io::ErrorKind::NotFound,
+            "no checkpoint to dump",
+        ))?.saved();
+        let mut save = io::BufWriter::new(fs::File::create(path)?);
+        saved.write(&mut save)?;
+
+        let log_path = path.with_extension("log");
+        let mut log = io::BufWriter::new(fs::File::create(log_path)?);
+        replay::write_log(&state.since, &mut log)
+    }
+
+    pub fn announce(self: &Self, status: ServerStatus) {
+        for watcher in &self.state().watchers {
+            // watchers that have gone away don't need telling
+            let _ = watcher.send(status.clone());
+        }
+    }
+}
@@ -4,6 +4,9 @@ use std::collections;
 
 use rand;
 
+use sulphate::components;
+use sulphate::rng;
+
 pub type ID = u64;
 
 // TODO switch to macro-generated enums please?
@@ -16,26 +19,33 @@ pub struct UID {
 
 // heap in the memory sense not the queue sense
 pub struct EntityHeap {
-    content: collections::HashMap<UID, Box<Any>>,
+    content: collections::HashMap<UID, Box<Any + Send>>,
+    // how many entities of any type have each id, since an id is only
+    // given out again once nothing has it
+    taken: collections::HashMap<ID, usize>,
+    components: components::Components,
     key_seed: rand::XorShiftRng,
+    // how many ids have been drawn from `key_seed`, which is all it takes
+    // to put it back the way it was
+    keys_drawn: u64,
 }
 
 static DOWNCAST_ERROR: &'static str = "\
 Value stored under incorrect type information. \
 ";
 
-fn unwrap_box<T: Any>(box_val: Box<Any>) -> T {
+fn unwrap_box<T: Any>(box_val: Box<Any + Send>) -> T {
     *box_val.downcast()
             .ok()
             .expect(DOWNCAST_ERROR)
 }
 
-fn unwrap_box_ref<T: Any>(box_ref: &Box<Any>) -> &T {
+fn unwrap_box_ref<T: Any>(box_ref: &Box<Any + Send>) -> &T {
     box_ref.downcast_ref()
            .expect(DOWNCAST_ERROR)
 }
 
-fn unwrap_box_mut<T: Any>(box_mut: &mut Box<Any>) -> &mut T {
+fn unwrap_box_mut<T: Any>(box_mut: &mut Box<Any + Send>) -> &mut T {
     box_mut.downcast_mut()
            .expect(DOWNCAST_ERROR)
 }
@@ -43,8 +53,34 @@ fn unwrap_box_mut<T: Any>(box_mut: &mut Box<Any>) -> &mut T {
 impl EntityHeap {
     pub fn new() -> EntityHeap {
         let content = collections::HashMap::new();
-        let key_seed = rand::weak_rng();
-        EntityHeap { content, key_seed }
+        // ids only need to be unique, and a fixed sequence of them means that
+        // a session can be replayed against a fresh heap
+        let key_seed = rand::XorShiftRng::new_unseeded();
+        let components = components::Components::new();
+        EntityHeap {
+            content,
+            taken: collections::HashMap::new(),
+            components,
+            key_seed,
+            keys_drawn: 0,
+        }
+    }
+
+    /// How many ids have been given out so far, for saving.
+    pub fn keys_drawn(self: &Self) -> u64 {
+        self.keys_drawn
+    }
+
+    /// Puts the ids back to where they were once `drawn` had been given
+    /// out, so that a world loaded from a save gives out the same ids next
+    /// as it would have if it hadn't stopped.
+    pub fn restore_keys(self: &mut Self, drawn: u64) {
+        use rand::Rng;
+        self.key_seed = rand::XorShiftRng::new_unseeded();
+        for _ in 0..drawn {
+            self.key_seed.next_u64();
+        }
+        self.keys_drawn = drawn;
     }
 
     pub fn get<T: Any>(self: &Self, id: ID) -> Option<&T> {
@@ -63,21 +99,24 @@ impl EntityHeap {
             .map(unwrap_box_mut)
     }
 
-    fn new_id(self: &mut Self, ty: any::TypeId) -> ID {
+    // never one that an entity of any type already has, since plenty of
+    // things look entities up by id alone
+    fn new_id(self: &mut Self) -> ID {
         use rand::Rng;
         loop {
             let id = self.key_seed.next_u64();
-            let uid = UID { id, ty };
-            if !self.content.contains_key(&uid) {
+            self.keys_drawn += 1;
+            if !self.taken.contains_key(&id) {
                 return id;
             }
         }
     }
 
-    pub fn add<T: Any>(self: &mut Self, v: T) -> ID {
+    pub fn add<T: Any + Send>(self: &mut Self, v: T) -> ID {
         let ty = any::TypeId::of::<T>();
         let val = Box::new(v);
-        let id = self.new_id(ty);
+        let id = self.new_id();
+        *self.taken.entry(id).or_insert(0) += 1;
         let uid = UID { id, ty };
         let overflow = self.content
                            .insert(uid, val);
@@ -87,13 +126,107 @@ impl EntityHeap {
         id
     }
 
+    // for putting back entities that were saved under a known id
+    pub fn insert<T: Any + Send>(self: &mut Self, id: ID, v: T) {
+        let ty = any::TypeId::of::<T>();
+        let val = Box::new(v);
+        let uid = UID { id, ty };
+        let overflow = self.content
+                           .insert(uid, val);
+        debug_assert!(overflow.is_none(), "reused key");
+        if overflow.is_none() {
+            *self.taken.entry(id).or_insert(0) += 1;
+        }
+    }
+
+    /// Takes an entity out of the heap, along with all of its components.
     pub fn remove<T: Any>(self: &mut Self, id: ID) -> Option<T> {
         let ty = any::TypeId::of::<T>();
         let uid = UID { id, ty };
-        self.content
-            .remove(&uid)
-            .map(unwrap_box)
+        let removed = self.content
+                          .remove(&uid)
+                          .map(unwrap_box);
+        if removed.is_some() {
+            let last = match self.taken.get_mut(&id) {
+                Some(count) => {
+                    *count -= 1;
+                    *count == 0
+                },
+                None => false,
+            };
+            // the components and randomness belong to the id, so they
+            // only go once nothing else has it, like the singletons at 0
+            if last {
+                self.taken.remove(&id);
+                self.components.remove_all(id);
+                rng::forget(self, id);
+            }
+        }
+        removed
+    }
+
+    pub fn insert_component<C: Any + Send>(
+        self: &mut Self,
+        id: ID,
+        component: C,
+    ) {
+        self.components.insert(id, component);
+    }
+
+    pub fn component<C: Any + Send>(self: &Self, id: ID) -> Option<&C> {
+        self.components.get(id)
+    }
+
+    pub fn component_mut<C: Any + Send>(
+        self: &mut Self,
+        id: ID,
+    ) -> Option<&mut C> {
+        self.components.get_mut(id)
+    }
+
+    pub fn remove_component<C: Any + Send>(
+        self: &mut Self,
+        id: ID,
+    ) -> Option<C> {
+        self.components.remove(id)
+    }
+
+    /// Every entity with all of the components in `Q`, such as
+    /// `(&Position, &mut Velocity)`, in order of id.
+    pub fn query<'a, Q>(self: &'a mut Self) -> Vec<(ID, Q::Item)>
+        where Q: components::Query<'a>
+    {
+        self.components.query::<Q>()
     }
 }
 
 
+
+#[cfg(test)]
+mod test_entity_heap {
+    use super::*;
+
+    #[test]
+    fn ids_are_never_shared_between_types() {
+        let first = EntityHeap::new().add(1u8);
+        // as if a save had put it back before anything else was added
+        let mut matter = EntityHeap::new();
+        matter.insert(first, 1u8);
+        let added = matter.add("something else");
+        assert!(added != first);
+
+        matter.remove::<&str>(added);
+        assert_eq!(matter.get::<u8>(first), Some(&1));
+    }
+
+    #[test]
+    fn restored_keys_carry_on() {
+        let mut matter = EntityHeap::new();
+        for n in 0..3 {
+            matter.add(n);
+        }
+        let mut restored = EntityHeap::new();
+        restored.restore_keys(matter.keys_drawn());
+        assert_eq!(restored.add(3), matter.add(3));
+    }
+}
@@ -0,0 +1,228 @@
+use std::sync;
+
+use space;
+use sulphate;
+use sulphate::queue;
+use units;
+
+// the event itself waits here rather than in the queue, so that it can be
+// taken back out or moved; whatever is in the queue just points at it
+struct Slot {
+    event: Option<Box<queue::Queued>>,
+    // bumped whenever the event is moved, so that only the latest place it
+    // was put in the queue counts
+    version: u64,
+    due: units::Time,
+}
+
+static POISONED: &'static str = "\
+Event slot poisoned by a panic in another thread. \
+";
+
+/// A scheduled event that can still be called off or moved, until it
+/// happens.
+#[derive(Clone)]
+pub struct EventHandle {
+    slot: sync::Arc<sync::Mutex<Slot>>,
+}
+
+impl EventHandle {
+    /// Whether the event is still waiting to happen.
+    pub fn pending(self: &Self) -> bool {
+        self.slot.lock().expect(POISONED).event.is_some()
+    }
+
+    /// When the event is due to happen, if it is still waiting to.
+    pub fn due(self: &Self) -> Option<units::Time> {
+        let slot = self.slot.lock().expect(POISONED);
+        slot.event.as_ref().map(|_| slot.due)
+    }
+}
+
+struct Scheduled {
+    slot: sync::Arc<sync::Mutex<Slot>>,
+    version: u64,
+}
+
+impl sulphate::Event for Scheduled {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        let event = {
+            let mut slot = self.slot.lock().expect(POISONED);
+            if slot.version != self.version {
+                // it was moved after this was queued
+                return;
+            }
+            slot.event.take()
+        };
+        if let Some(event) = event {
+            event.invoke_boxed(space, time, matter);
+        }
+    }
+
+    fn cosmetic(self: &Self) -> bool {
+        let slot = self.slot.lock().expect(POISONED);
+        slot.event.as_ref().map_or(false, |event| event.cosmetic())
+    }
+
+    fn lane(self: &Self) -> sulphate::Lane {
+        let slot = self.slot.lock().expect(POISONED);
+        slot.event.as_ref().map_or(sulphate::Lane::Normal, |event| {
+            event.lane()
+        })
+    }
+}
+
+pub trait Scheduler {
+    /// Like `sulphate::enqueue_absolute`, but the event can be cancelled or
+    /// rescheduled through the handle.
+    fn schedule_absolute<E>(
+        self: &mut Self,
+        event: E,
+        execute_time: units::Time,
+    ) -> EventHandle where E: sulphate::Event;
+
+    fn schedule_relative<E>(
+        self: &mut Self,
+        event: E,
+        execute_delay: units::Duration,
+    ) -> EventHandle where E: sulphate::Event;
+
+    /// Stops the event from happening at all, returning false if it already
+    /// has.
+    fn cancel(self: &mut Self, handle: &EventHandle) -> bool;
+
+    /// Moves the event to `new_time`, returning false if it has already
+    /// happened, or if `new_time` has already passed.
+    fn reschedule(
+        self: &mut Self,
+        handle: &EventHandle,
+        new_time: units::Time,
+    ) -> bool;
+}
+
+impl Scheduler for sulphate::EventQueue {
+    fn schedule_absolute<E>(
+        self: &mut Self,
+        event: E,
+        execute_time: units::Time,
+    ) -> EventHandle where E: sulphate::Event {
+        let event: Box<queue::Queued> = Box::new(event);
+        let slot = Slot {
+            event: Some(event),
+            version: 0,
+            due: execute_time,
+        };
+        let slot = sync::Arc::new(sync::Mutex::new(slot));
+        let scheduled = Scheduled { slot: slot.clone(), version: 0 };
+        sulphate::enqueue_absolute(self, scheduled, execute_time);
+        EventHandle { slot }
+    }
+
+    fn schedule_relative<E>(
+        self: &mut Self,
+        event: E,
+        execute_delay: units::Duration,
+    ) -> EventHandle where E: sulphate::Event {
+        let execute_time = self.now() + execute_delay;
+        self.schedule_absolute(event, execute_time)
+    }
+
+    fn cancel(self: &mut Self, handle: &EventHandle) -> bool {
+        let mut slot = handle.slot.lock().expect(POISONED);
+        // whatever is left in the queue will find the slot empty
+        slot.event.take().is_some()
+    }
+
+    fn reschedule(
+        self: &mut Self,
+        handle: &EventHandle,
+        new_time: units::Time,
+    ) -> bool {
+        if new_time < self.now() {
+            warn!("Ignored rescheduling an event into the past");
+            return false;
+        }
+        let version = {
+            let mut slot = handle.slot.lock().expect(POISONED);
+            if slot.event.is_none() {
+                return false;
+            }
+            slot.version += 1;
+            slot.due = new_time;
+            slot.version
+        };
+        let scheduled = Scheduled { slot: handle.slot.clone(), version };
+        sulphate::enqueue_absolute(self, scheduled, new_time);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test_handle {
+    use std::sync::mpsc;
+    use std::time;
+
+    use space;
+    use sulphate;
+    use sulphate::server;
+    use units;
+
+    use super::*;
+
+    struct Ping(&'static str, mpsc::Sender<(&'static str, units::Time)>);
+
+    impl sulphate::Event for Ping {
+        fn invoke(
+            self: Self,
+            _space: &mut space::CollisionSpace,
+            time: &mut sulphate::EventQueue,
+            _matter: &mut sulphate::EntityHeap,
+        ) {
+            self.1.send((self.0, time.now())).unwrap();
+        }
+    }
+
+    #[test]
+    fn cancelled_and_moved_events() {
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, mut clock, handle, pings) =
+            server::start_server_with_clock(move |_space, time, _matter| {
+                let (send, recv) = mpsc::channel();
+                let one = units::moments(1);
+                let a = Ping("a", send.clone());
+                let b = Ping("b", send.clone());
+                let c = Ping("c", send);
+                let cancelled = time.schedule_relative(a, one);
+                let moved = time.schedule_relative(b, one);
+                let kept = time.schedule_relative(c, one);
+
+                assert!(time.cancel(&cancelled));
+                assert!(!cancelled.pending());
+                let later = time.now() + units::moments(3);
+                assert!(time.reschedule(&moved, later));
+                assert!(moved.due() == Some(later));
+                assert!(cancelled.due().is_none());
+                // moving it twice still only runs it once
+                assert!(time.reschedule(&moved, later));
+                assert!(kept.pending());
+                recv
+            }, clock);
+
+        clock.advance(units::moments(8));
+        let wait = time::Duration::from_secs(1);
+        let first = pings.recv_timeout(wait).expect("kept event never ran");
+        assert!(first == ("c", start + units::moments(1)));
+        let second = pings.recv_timeout(wait).expect("moved event never ran");
+        assert!(second == ("b", start + units::moments(3)));
+        assert!(pings.recv_timeout(wait).is_err(), "event ran twice");
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+}
@@ -0,0 +1,303 @@
+//! A bounded way in to the server for interruptions from remote clients,
+//! so that one sending far more than it should can't build up a backlog
+//! that the server has to work through before anyone else gets a turn.
+//!
+//! Each connection has a sender of its own, and anything it sends faster
+//! than the rate limit is dropped before it gets into the inbox, so that a
+//! client flooding the server only ever uses up its own share of the room.
+//! The server drains the inbox whenever it is told there is something in
+//! it, which is at most once per cycle however much has come in. Of the
+//! controls a player sent in that time only the last is kept, since each
+//! one says everything there is to say about how the player wants to
+//! move.
+
+use std::cell;
+use std::collections;
+use std::sync;
+use std::sync::atomic;
+use std::sync::mpsc;
+use std::time;
+
+use sulphate::metrics;
+use sulphate::server::Interruption;
+use sulphate::session;
+
+// how many interruptions the inbox holds before controls are dropped and
+// everything else waits for room
+const CAPACITY: usize = 256;
+
+// how many interruptions a second a connection can keep up
+const MOST_PER_SECOND: f64 = 30.0;
+
+// how many a connection can send at once after a quiet spell
+const BURST: f64 = 60.0;
+
+// shared by every sender, for what the server needs to know without
+// draining the inbox
+struct Shared {
+    // whether the server has been told to drain the inbox and hasn't yet
+    ready: atomic::AtomicBool,
+    // controls dropped for finding the inbox full
+    overflowed: atomic::AtomicUsize,
+    // interruptions dropped for coming faster than the rate limit
+    rate_limited: atomic::AtomicUsize,
+}
+
+/// The sending half of the inbox, which each connection gets a clone of.
+///
+/// Every clone is held to the rate limit on its own, starting with a full
+/// budget, so each connection should have a clone of its own.
+pub struct InboxSender {
+    sender: mpsc::SyncSender<Interruption>,
+    wake: mpsc::Sender<Interruption>,
+    shared: sync::Arc<Shared>,
+    bucket: cell::Cell<Bucket>,
+}
+
+impl Clone for InboxSender {
+    fn clone(self: &Self) -> InboxSender {
+        InboxSender {
+            sender: self.sender.clone(),
+            wake: self.wake.clone(),
+            shared: self.shared.clone(),
+            bucket: cell::Cell::new(Bucket::full(time::Instant::now())),
+        }
+    }
+}
+
+impl InboxSender {
+    /// Puts `interruption` in the inbox, unless this connection has sent
+    /// more than the rate limit allows. Controls are dropped if there is
+    /// no room, since a newer one will come along, but anything else waits
+    /// until there is. Gives Err once the server has gone.
+    pub fn send(self: &Self, interruption: Interruption) -> Result<(), ()> {
+        let mut bucket = self.bucket.get();
+        let allowed = bucket.take(time::Instant::now());
+        self.bucket.set(bucket);
+        if !allowed {
+            self.shared.rate_limited.fetch_add(1, atomic::Ordering::SeqCst);
+            return Ok(());
+        }
+        if interruption.is_control() {
+            match self.sender.try_send(interruption) {
+                Ok(()) => (),
+                Err(mpsc::TrySendError::Full(_)) => {
+                    self.shared
+                        .overflowed
+                        .fetch_add(1, atomic::Ordering::SeqCst);
+                    return Ok(());
+                },
+                Err(mpsc::TrySendError::Disconnected(_)) => return Err(()),
+            }
+        } else {
+            self.sender.send(interruption).map_err(|_| ())?;
+        }
+        if !self.shared.ready.swap(true, atomic::Ordering::SeqCst) {
+            self.wake.send(Interruption::InboxReady).map_err(|_| ())?;
+        }
+        Ok(())
+    }
+}
+
+// tokens come back at `MOST_PER_SECOND`, up to `BURST`, and each
+// interruption kept takes one
+#[derive(Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    since: time::Instant,
+}
+
+impl Bucket {
+    fn full(now: time::Instant) -> Bucket {
+        Bucket { tokens: BURST, since: now }
+    }
+
+    fn refill(self: &mut Self, now: time::Instant) {
+        let passed = now.duration_since(self.since);
+        let seconds = passed.as_secs() as f64
+                    + passed.subsec_nanos() as f64 / 1_000_000_000.0;
+        self.tokens = (self.tokens + seconds * MOST_PER_SECOND).min(BURST);
+        self.since = now;
+    }
+
+    fn take(self: &mut Self, now: time::Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// The receiving half of the inbox, kept by the world, which is only made
+/// once something asks to send to it.
+pub struct Inbox {
+    open: Option<(mpsc::Receiver<Interruption>, InboxSender)>,
+    coalesced: u64,
+}
+
+impl Inbox {
+    pub fn new() -> Inbox {
+        Inbox {
+            open: None,
+            coalesced: 0,
+        }
+    }
+
+    /// A sender for the inbox, which tells the server to drain it through
+    /// `wake`, the sender that interruptions come in on.
+    pub fn open(
+        self: &mut Self,
+        wake: mpsc::Sender<Interruption>,
+    ) -> InboxSender {
+        if let Some((_, ref sender)) = self.open {
+            return sender.clone();
+        }
+        let (sender, receiver) = mpsc::sync_channel(CAPACITY);
+        let shared = sync::Arc::new(Shared {
+            ready: atomic::AtomicBool::new(false),
+            overflowed: atomic::AtomicUsize::new(0),
+            rate_limited: atomic::AtomicUsize::new(0),
+        });
+        let bucket = cell::Cell::new(Bucket::full(time::Instant::now()));
+        let sender = InboxSender { sender, wake, shared, bucket };
+        self.open = Some((receiver, sender.clone()));
+        sender
+    }
+
+    /// Takes everything out of the inbox, giving back whatever is left
+    /// once each player's controls have been coalesced, in the order it
+    /// came in.
+    pub fn drain(
+        self: &mut Self,
+        sessions: &session::Sessions,
+    ) -> Vec<Interruption> {
+        let taken: Vec<Interruption> = match self.open {
+            Some((ref receiver, ref sender)) => {
+                // anything sent from here on needs another drain
+                sender.shared.ready.store(false, atomic::Ordering::SeqCst);
+                receiver.try_iter().collect()
+            },
+            None => return Vec::new(),
+        };
+
+        let mut last = collections::BTreeMap::new();
+        for (n, interruption) in taken.iter().enumerate() {
+            if interruption.is_control() {
+                if let Some(player) = interruption.player(sessions) {
+                    last.insert(player, n);
+                }
+            }
+        }
+
+        let mut kept = Vec::new();
+        for (n, interruption) in taken.into_iter().enumerate() {
+            let superseded = interruption.is_control()
+                && interruption.player(sessions).map_or(false, |player| {
+                    last.get(&player) != Some(&n)
+                });
+            if superseded {
+                self.coalesced += 1;
+                continue;
+            }
+            kept.push(interruption);
+        }
+        kept
+    }
+
+    pub fn drops(self: &Self) -> metrics::Drops {
+        let (overflowed, rate_limited) = match self.open {
+            Some((_, ref sender)) => {
+                let shared = &sender.shared;
+                (shared.overflowed.load(atomic::Ordering::SeqCst),
+                 shared.rate_limited.load(atomic::Ordering::SeqCst))
+            },
+            None => (0, 0),
+        };
+        metrics::Drops {
+            coalesced: self.coalesced,
+            rate_limited: rate_limited as u64,
+            overflowed: overflowed as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_inbox {
+    use entities::player;
+    use sulphate::testing;
+    use units;
+
+    use super::*;
+
+    #[test]
+    fn buckets_hold_players_to_the_rate_limit() {
+        let start = time::Instant::now();
+        let mut bucket = Bucket::full(start);
+        for _ in 0..BURST as usize {
+            assert!(bucket.take(start));
+        }
+        assert!(!bucket.take(start));
+        let later = start + time::Duration::from_millis(100);
+        for _ in 0..(MOST_PER_SECOND / 10.0) as usize {
+            assert!(bucket.take(later));
+        }
+        assert!(!bucket.take(later));
+    }
+
+    #[test]
+    fn each_connection_has_a_budget_of_its_own() {
+        let mut inbox = Inbox::new();
+        let (wake, _woken) = mpsc::channel();
+        let flood = inbox.open(wake);
+        let quiet = flood.clone();
+        let sessions = session::Sessions::new();
+        let session = 1;
+        let interact = || Interruption::SessionInteract { session };
+        for _ in 0..BURST as usize + 10 {
+            flood.send(interact()).unwrap();
+        }
+        quiet.send(interact()).unwrap();
+
+        assert_eq!(inbox.drain(&sessions).len(), BURST as usize + 1);
+        assert_eq!(inbox.drops().rate_limited, 10);
+    }
+
+    #[test]
+    fn only_the_last_control_in_a_cycle_is_kept() {
+        let (mut server, ()) = testing::TestServer::new(|_, _, _| ());
+        let (update, _updates) = mpsc::channel();
+        let (reply, joined) = mpsc::channel();
+        server.send(Interruption::Join {
+            appearance: player::Appearance::named("spam"),
+            position: Default::default(),
+            update,
+            reply,
+        });
+        let (session, _) = joined.recv().unwrap();
+
+        let wake = server.wake();
+        let (reply, opened) = mpsc::channel();
+        server.send(Interruption::OpenInbox { wake, reply });
+        let inbox = opened.recv().unwrap();
+        for n in 1..4 {
+            let x = (n as f64).into();
+            let direction = units::Vector { x, y: 0.0.into() };
+            let control = player::Control::walk(direction, player::Pace::Run);
+            let sent = inbox.send(Interruption::SessionUpdate {
+                session,
+                control,
+            });
+            assert!(sent.is_ok());
+        }
+
+        let (reply, metrics) = mpsc::channel();
+        // asked for before the inbox is drained, which happens after
+        server.send(Interruption::QueryMetrics { reply: reply.clone() });
+        assert_eq!(metrics.recv().unwrap().dropped.coalesced, 0);
+        server.send(Interruption::QueryMetrics { reply });
+        assert_eq!(metrics.recv().unwrap().dropped.coalesced, 2);
+    }
+}
@@ -0,0 +1,91 @@
+//! Work that a system can split up and run on every core at once, in the
+//! middle of a single event, when built with the `parallel` feature.
+//!
+//! Jobs only ever look at the world, never change it, so that whatever
+//! they find can be put back into the world afterwards one result at a
+//! time, in the order the jobs were given. That keeps a world playing out
+//! the same way whether or not the jobs ran at once, and however many
+//! threads there were to run them on.
+
+#[cfg(feature = "parallel")]
+use rayon;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// How many threads jobs are run on, which is one without the `parallel`
+/// feature.
+#[cfg(feature = "parallel")]
+pub fn threads() -> usize {
+    rayon::current_num_threads()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn threads() -> usize {
+    1
+}
+
+/// Runs `job` on each of `inputs`, all at once if built with the
+/// `parallel` feature and one after another otherwise, giving back what
+/// each returned in the order of `inputs` either way.
+#[cfg(feature = "parallel")]
+pub fn run<T, R, F>(inputs: Vec<T>, job: F) -> Vec<R>
+    where T: Send, R: Send, F: Fn(T) -> R + Sync + Send
+{
+    inputs.into_par_iter().map(job).collect()
+}
+
+#[cfg(not(feature = "parallel"))]
+pub fn run<T, R, F>(inputs: Vec<T>, job: F) -> Vec<R>
+    where T: Send, R: Send, F: Fn(T) -> R + Sync + Send
+{
+    inputs.into_iter().map(job).collect()
+}
+
+/// Splits `items` into at most `parts` runs of about the same length,
+/// each following on from the last, so that entities listed in id order
+/// are split into ranges of ids that don't overlap.
+pub fn partition<T>(items: Vec<T>, parts: usize) -> Vec<Vec<T>> {
+    let parts = parts.max(1);
+    let each = (items.len() + parts - 1) / parts;
+    let mut split = Vec::new();
+    let mut items = items.into_iter().peekable();
+    while items.peek().is_some() {
+        split.push(items.by_ref().take(each).collect());
+    }
+    split
+}
+
+/// Runs `job` on each run of `items`, as split by `partition` into one run
+/// per thread, and joins what they return back into a single list,
+/// following the order of `items`.
+pub fn run_partitioned<T, R, F>(items: Vec<T>, job: F) -> Vec<R>
+    where T: Send, R: Send, F: Fn(Vec<T>) -> Vec<R> + Sync + Send
+{
+    run(partition(items, threads()), job)
+        .into_iter()
+        .flat_map(|part| part)
+        .collect()
+}
+
+#[cfg(test)]
+mod test_jobs {
+    use super::*;
+
+    #[test]
+    fn partitions_follow_on_and_cover_everything() {
+        let parts = partition((0..10).collect(), 3);
+        let expected = vec![vec![0, 1, 2, 3], vec![4, 5, 6, 7], vec![8, 9]];
+        assert_eq!(parts, expected);
+        assert_eq!(partition((0..2).collect(), 4), vec![vec![0], vec![1]]);
+        assert!(partition(Vec::<u8>::new(), 4).is_empty());
+    }
+
+    #[test]
+    fn results_come_back_in_order() {
+        let squares = run_partitioned((0..100u64).collect(), |part| {
+            part.into_iter().map(|n| n * n).collect()
+        });
+        let expected: Vec<u64> = (0..100).map(|n| n * n).collect();
+        assert_eq!(squares, expected);
+    }
+}
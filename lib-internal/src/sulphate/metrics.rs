@@ -0,0 +1,112 @@
+use std::time;
+
+use units;
+
+/// How the server has been keeping up, totalled since it started.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ServerMetrics {
+    pub cycles: u64,
+    pub events: u64,
+    pub interruptions: u64,
+    /// Wall time between the end of the first cycle and the end of the last.
+    pub wall_time: time::Duration,
+    pub longest_cycle: time::Duration,
+    /// How far the simulation was behind its clock at the end of the last
+    /// cycle.
+    pub drift: units::Duration,
+    /// What was dropped on the way in from remote clients.
+    pub dropped: Drops,
+}
+
+/// Interruptions that the inbox never passed on to the server.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Drops {
+    /// Controls that a newer one from the same player came in after,
+    /// before the server got to either.
+    pub coalesced: u64,
+    /// Anything a connection sent faster than the rate limit.
+    pub rate_limited: u64,
+    /// Controls that came in while the inbox was full.
+    pub overflowed: u64,
+}
+
+impl Drops {
+    pub fn total(self: &Self) -> u64 {
+        self.coalesced + self.rate_limited + self.overflowed
+    }
+}
+
+impl ServerMetrics {
+    pub fn mean_cycle(self: &Self) -> Option<time::Duration> {
+        if self.cycles > 1 {
+            Some(self.wall_time / (self.cycles - 1) as u32)
+        } else {
+            None
+        }
+    }
+}
+
+// kept by the world, since only the world sees events and interruptions
+#[derive(Clone, Default)]
+pub struct Counts {
+    pub events: u64,
+    pub interruptions: u64,
+}
+
+// kept by the clock, since only the clock is told when a cycle finishes
+#[derive(Clone, Default)]
+pub struct Cycles {
+    count: u64,
+    first_end: Option<time::Instant>,
+    last_end: Option<time::Instant>,
+    longest: time::Duration,
+    drift: units::Duration,
+}
+
+impl Cycles {
+    pub fn count(self: &Self) -> u64 {
+        self.count
+    }
+
+    pub fn drift(self: &Self) -> units::Duration {
+        self.drift
+    }
+
+    pub fn finished(
+        self: &mut Self,
+        now: time::Instant,
+        drift: units::Duration,
+    ) {
+        if let Some(last) = self.last_end {
+            let cycle = now.duration_since(last);
+            if cycle > self.longest {
+                self.longest = cycle;
+            }
+        } else {
+            self.first_end = Some(now);
+        }
+        self.last_end = Some(now);
+        self.count += 1;
+        self.drift = drift;
+    }
+
+    pub fn metrics(
+        self: &Self,
+        counts: &Counts,
+        dropped: Drops,
+    ) -> ServerMetrics {
+        let wall_time = match (self.first_end, self.last_end) {
+            (Some(first), Some(last)) => last.duration_since(first),
+            _ => time::Duration::new(0, 0),
+        };
+        ServerMetrics {
+            cycles: self.count,
+            events: counts.events,
+            interruptions: counts.interruptions,
+            wall_time,
+            longest_cycle: self.longest,
+            drift: self.drift,
+            dropped,
+        }
+    }
+}
@@ -1,21 +1,317 @@
+use std::sync::mpsc;
+
 use sulphate_lib::event_queue;
 
+use economy;
+use entities;
 use space;
 use units;
+use world::calendar;
 
+pub use sulphate::components::Fetch;
+pub use sulphate::components::Query;
 pub use sulphate::entity_heap::EntityHeap;
+pub use sulphate::handle::EventHandle;
+pub use sulphate::handle::Scheduler;
+pub use sulphate::queue::EventQueue;
+pub use sulphate::queue::Lane;
+pub use sulphate::rng::Rng;
 
+pub mod autosave;
+pub mod catch_up;
+pub mod chat;
+pub mod checksum;
+pub mod crash;
+pub mod inbox;
+pub mod jobs;
+pub mod metrics;
+pub mod persist;
+pub mod replay;
+pub mod rng;
 pub mod server;
+pub mod session;
+pub mod snapshot;
+pub mod testing;
+pub mod tick;
 
+mod components;
 mod entity_heap;
+mod handle;
+mod queue;
 
-pub type EventQueue = event_queue::EventQueue<units::Time, World>;
+// the server's own queue, which only holds what `EventQueue` asks it to
+pub type RawQueue = event_queue::EventQueue<units::Time, World>;
 pub type EntityId = entity_heap::ID;
 pub type EntityUId = entity_heap::UID;
 
 pub struct World {
     space: space::CollisionSpace,
     matter: EntityHeap,
+    queue: EventQueue,
+    clock: Box<server::ClockControl + Send>,
+    recorder: Option<mpsc::Sender<replay::Record>>,
+    publisher: snapshot::Publisher,
+    writer: snapshot::Writer,
+    inbox: inbox::Inbox,
+    sessions: session::Sessions,
+    chat: chat::Chat,
+    counts: metrics::Counts,
+    burst: catch_up::Burst,
+    recovery: crash::Recovery,
+    autosave: Option<autosave::Autosaver>,
+}
+
+// a cheap copy of the render-relevant parts of the world,
+// so that other threads can look at it without blocking the simulation
+#[derive(Clone, PartialEq)]
+pub struct WorldSnapshot {
+    pub time: units::Time,
+    pub bodies: Vec<space::BodySnapshot>,
+    /// A hash of what `bodies` say about each entity, which is as much as
+    /// a client sent them can check its own copy of the world against.
+    pub checksum: u64,
+}
+
+/// What a spectator gets to see of the world, which is only the entities
+/// that have an image.
+#[derive(Clone, PartialEq)]
+pub struct WorldView {
+    pub time: units::Time,
+    pub entities: Vec<ViewedEntity>,
+    /// The city's books, which anyone watching can see.
+    pub balances: economy::Balances,
+    /// Every tile that isn't open ground.
+    pub terrain: Vec<(space::TileId, space::Tile)>,
+    /// Every stretch of road, from one tile to the next.
+    pub roads: Vec<(space::TileId, space::TileId)>,
+    /// Every zoned tile.
+    pub zones: Vec<(space::TileId, space::zones::Zone)>,
+    /// Every stretch of pipe, from one tile to the next.
+    pub pipes: Vec<(space::TileId, space::TileId)>,
+    /// Every stretch of road that has had traffic along it lately, with
+    /// how long it takes to go along as a percentage of how long it would
+    /// on an empty road.
+    pub traffic: Vec<((space::TileId, space::TileId), i64)>,
+    /// Every sound made lately, oldest first, so that it reaches whoever
+    /// is close enough to hear it even if a view or two goes missing.
+    pub sounds: Vec<space::sounds::Heard>,
+}
+
+#[derive(Clone, PartialEq)]
+pub struct ViewedEntity {
+    pub uid: EntityUId,
+    pub image: entities::Image,
+    pub position: units::Position,
+    pub velocity: units::Velocity,
+}
+
+/// What an admin can find out about a single entity.
+#[derive(Clone, PartialEq, Debug)]
+pub struct EntitySummary {
+    pub id: EntityId,
+    pub kind: Option<persist::EntityKind>,
+    pub position: units::Position,
+    pub velocity: units::Velocity,
+}
+
+/// Everything there is to see about a single entity, for looking at one
+/// entity at a time.
+#[derive(Clone, PartialEq, Debug)]
+pub struct EntityInfo {
+    pub summary: EntitySummary,
+    pub details: Details,
+}
+
+/// What is particular to each kind of entity.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Details {
+    Player {
+        appearance: entities::player::Appearance,
+        carrying: Vec<entities::item::Kind>,
+    },
+    Citizen {
+        plans: entities::citizen::Plans,
+    },
+    Building {
+        blueprint: entities::building::Blueprint,
+        /// Whether it has gone without power or water.
+        blackout: bool,
+        burning: bool,
+        /// What it makes and uses up every economy tick.
+        flow: Option<economy::Flow>,
+    },
+    Vehicle {
+        stops: Vec<space::TileId>,
+    },
+    Item {
+        kind: entities::item::Kind,
+    },
+    /// A kind of entity that has nothing more to it.
+    Nothing,
+}
+
+impl World {
+    // a world starting at `now`, with the calendar running and whatever `f`
+    // puts in it
+    fn new<F, R>(
+        now: units::Time,
+        clock: Box<server::ClockControl + Send>,
+        f: F,
+    ) -> (World, R)
+        where F: FnOnce(
+                     &mut space::CollisionSpace,
+                     &mut EventQueue,
+                     &mut EntityHeap,
+                 ) -> R,
+    {
+        let mut space = space::CollisionSpace::new();
+        let mut queue = EventQueue::new(now);
+        let mut matter = EntityHeap::new();
+        rng::seed(&mut matter, rng::DEFAULT_SEED);
+        calendar::start(&mut queue, &mut matter);
+
+        let r = f(&mut space, &mut queue, &mut matter);
+
+        let world = World {
+            space,
+            matter,
+            queue,
+            clock,
+            recorder: None,
+            publisher: snapshot::Publisher::new(),
+            writer: snapshot::Writer::new(),
+            inbox: inbox::Inbox::new(),
+            sessions: session::Sessions::new(),
+            chat: chat::Chat::new(),
+            counts: Default::default(),
+            burst: Default::default(),
+            recovery: crash::Recovery::new(),
+            autosave: None,
+        };
+        (world, r)
+    }
+
+    pub fn entities(self: &Self, now: units::Time) -> Vec<EntitySummary> {
+        self.space
+            .snapshot(now)
+            .into_iter()
+            .map(|body| EntitySummary {
+                id: body.uid.id,
+                kind: persist::EntityKind::of(body.uid.ty),
+                position: body.position,
+                velocity: body.velocity,
+            })
+            .collect()
+    }
+
+    /// What there is to see about the entity with a body and the id `id`,
+    /// or None if there is no such entity.
+    pub fn inspect(
+        self: &Self,
+        now: units::Time,
+        id: EntityId,
+    ) -> Option<EntityInfo> {
+        use entities::building::Building;
+        use entities::citizen::Citizen;
+        use entities::item;
+        use entities::player::Player;
+        use entities::vehicle::Vehicle;
+
+        let summary = self.entities(now)
+                          .into_iter()
+                          .find(|entity| entity.id == id)?;
+        let matter = &self.matter;
+        let details = match summary.kind {
+            Some(persist::EntityKind::Player) => matter
+                .get::<Player>(id)
+                .map(|player| Details::Player {
+                    appearance: player.appearance().clone(),
+                    carrying: matter.component::<item::Inventory>(id)
+                                    .map_or(Vec::new(), |inventory| {
+                                        inventory.items().to_vec()
+                                    }),
+                }),
+            Some(persist::EntityKind::Citizen) => matter
+                .get::<Citizen>(id)
+                .map(|citizen| Details::Citizen { plans: citizen.plans() }),
+            Some(persist::EntityKind::Building) => matter
+                .get::<Building>(id)
+                .map(|building| Details::Building {
+                    blueprint: building.blueprint(),
+                    blackout: building.blackout(),
+                    burning: building.burning(),
+                    flow: matter.component::<economy::Flow>(id).cloned(),
+                }),
+            Some(persist::EntityKind::Vehicle) => matter
+                .get::<Vehicle>(id)
+                .map(|vehicle| Details::Vehicle {
+                    stops: vehicle.stops().to_vec(),
+                }),
+            Some(persist::EntityKind::Item) => matter
+                .get::<item::Item>(id)
+                .map(|item| Details::Item { kind: item.kind() }),
+            None => None,
+        };
+        let details = details.unwrap_or(Details::Nothing);
+        Some(EntityInfo { summary, details })
+    }
+
+    pub fn snapshot(self: &Self, now: units::Time) -> WorldSnapshot {
+        let bodies = self.space.snapshot(now);
+        let checksum = checksum::of_entities(&self.entities(now));
+        WorldSnapshot { time: now, bodies, checksum }
+    }
+
+    /// Like `snapshot`, but with only the bodies within `radius` of
+    /// `center`'s body.
+    pub fn snapshot_around(
+        self: &Self,
+        now: units::Time,
+        center: EntityUId,
+        radius: units::Distance,
+    ) -> WorldSnapshot {
+        let bodies = self.space.snapshot_around(now, center, radius);
+        let entities: Vec<_> = bodies
+            .iter()
+            .map(|body| EntitySummary {
+                id: body.uid.id,
+                kind: persist::EntityKind::of(body.uid.ty),
+                position: body.position,
+                velocity: body.velocity,
+            })
+            .collect();
+        let checksum = checksum::of_entities(&entities);
+        WorldSnapshot { time: now, bodies, checksum }
+    }
+
+    /// A hash of everything a save would keep of the world as of `now`,
+    /// which comes out the same on any machine that agrees about it.
+    pub fn checksum(self: &Self, now: units::Time) -> u64 {
+        checksum::of_saved(snapshot::Snapshot::take(self, now).saved())
+    }
+
+    // starts sending views as soon as there is someone to send them to
+    fn subscribe(
+        self: &mut Self,
+        time: &mut RawQueue,
+        subscriber: mpsc::Sender<WorldView>,
+    ) {
+        if self.publisher.subscribe(subscriber) {
+            let now = time.now();
+            time.enqueue_absolute(PublishEvent, now);
+        }
+    }
+
+    fn record(self: &mut Self, time: units::Time, what: replay::Replayable) {
+        let disconnected = match self.recorder {
+            Some(ref recorder) => recorder.send((time, what)).is_err(),
+            None => false,
+        };
+        if disconnected {
+            warn!("Recording stopped, log was dropped");
+            self.recorder = None;
+        }
+    }
 }
 
 pub fn enqueue_absolute<E>(
@@ -23,7 +319,7 @@ pub fn enqueue_absolute<E>(
     event: E,
     execute_time: units::Time,
 ) where E: Event {
-    time.enqueue_absolute(AdaptEvent(event), execute_time);
+    time.enqueue_absolute(event, execute_time);
 }
 
 pub fn enqueue_relative<E>(
@@ -31,30 +327,43 @@ pub fn enqueue_relative<E>(
     event: E,
     execute_delay: units::Duration,
 ) where E: Event {
-    time.enqueue_relative(AdaptEvent(event), execute_delay);
+    time.enqueue_relative(event, execute_delay);
 }
 
-struct AdaptEvent<E>(E) where E: Event;
+// has a view sent to every subscriber once per moment, and stops once
+// there are no subscribers left
+struct PublishEvent;
 
-// TODO hide EventQueue::invoke_next() methods from Event implementors
-pub trait Event: 'static {
+impl event_queue::Event<units::Time, World> for PublishEvent {
     fn invoke(
         self: Self,
-        space: &mut space::CollisionSpace,
-        time: &mut EventQueue,
-        matter: &mut EntityHeap,
-    );
+        time: &mut RawQueue,
+        world: &mut World,
+    ) {
+        world.counts.events += 1;
+        let view = snapshot::ViewSnapshot::take(world, time.now());
+        if world.publisher.publish(view) {
+            time.enqueue_relative(PublishEvent, units::moments(1));
+        }
+    }
 }
 
-impl<E> event_queue::Event<units::Time, World> for AdaptEvent<E>
-    where E: Event
-{
+pub trait Event: 'static + Send {
     fn invoke(
         self: Self,
+        space: &mut space::CollisionSpace,
         time: &mut EventQueue,
-        world: &mut World,
-    ) {
-        self.0.invoke(&mut world.space, time, &mut world.matter);
+        matter: &mut EntityHeap,
+    );
+
+    /// Whether the event only affects how things look, so that it can be
+    /// dropped when the server is behind.
+    fn cosmetic(self: &Self) -> bool {
+        false
     }
-}
 
+    /// Where the event goes among others due at the same time.
+    fn lane(self: &Self) -> Lane {
+        Lane::Normal
+    }
+}
@@ -0,0 +1,633 @@
+//! Saves that only write what has changed since the last one, for
+//! autosaving cities too big to write out whole every time.
+//!
+//! A journal is a file of generations, each one appended after the last.
+//! Each generation holds what is small enough to write every time, like
+//! the time and the money, and then only those chunks whose ground or
+//! whose entities are different from the generation before, so that a
+//! city that is mostly standing still writes little more than the
+//! neighbourhoods where something walked about. Reading a journal goes
+//! through every generation, keeping the newest of each part, and leaves
+//! out a generation that was cut off partway through being written.
+//!
+//! Every generation is kept, so journals grow, and once one is more than
+//! `COMPACT_FACTOR` times bigger than what is still current it is written
+//! out again as a single generation, to the side first the same as saves.
+
+use std::collections;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::io::Seek;
+use std::io::Write;
+use std::path;
+
+use space;
+use space::chunks;
+use space::zones;
+use sulphate::checksum;
+use units;
+
+use super::SavedWorld;
+use super::VERSION;
+use super::invalid;
+use super::read_entity;
+use super::read_i64;
+use super::read_segments;
+use super::read_stamps;
+use super::read_u64;
+use super::read_version;
+use super::read_zones;
+use super::upgrade;
+use super::write_entity;
+use super::write_i64;
+use super::write_segments;
+use super::write_stamps;
+use super::write_u64;
+use super::write_zones;
+
+/// What journals start with, in place of what saves start with.
+pub static MAGIC: &'static [u8; 4] = b"LILJ";
+
+// how many times bigger than what is current a journal gets before it is
+// compacted
+const COMPACT_FACTOR: u64 = 3;
+
+// the magic and the version
+const PREAMBLE: u64 = 12;
+
+// what a generation can have a new copy of
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Part {
+    Chunk(chunks::ChunkId),
+    // entities by id, only in journals from before entities went by chunk
+    Page(u64),
+    // the entities in a chunk
+    Crowd(chunks::ChunkId),
+}
+
+fn write_part(out: &mut io::Write, part: Part) -> io::Result<()> {
+    match part {
+        Part::Chunk((x, y)) => {
+            out.write_all(&[0])?;
+            write_i64(out, x)?;
+            write_i64(out, y)
+        },
+        Part::Page(page) => {
+            out.write_all(&[1])?;
+            write_u64(out, page)
+        },
+        Part::Crowd((x, y)) => {
+            out.write_all(&[2])?;
+            write_i64(out, x)?;
+            write_i64(out, y)
+        },
+    }
+}
+
+fn read_part(input: &mut io::Read) -> io::Result<Part> {
+    let mut tag = [0u8; 1];
+    input.read_exact(&mut tag)?;
+    match tag[0] {
+        0 => Ok(Part::Chunk((read_i64(input)?, read_i64(input)?))),
+        1 => Ok(Part::Page(read_u64(input)?)),
+        2 => Ok(Part::Crowd((read_i64(input)?, read_i64(input)?))),
+        _ => Err(invalid("unknown part of journal")),
+    }
+}
+
+type Parts = collections::BTreeMap<Part, Vec<u8>>;
+
+// whatever of the ground is in one chunk
+#[derive(Default)]
+struct Plot {
+    roads: Vec<(space::TileId, space::TileId)>,
+    zones: Vec<(space::TileId, zones::Zone)>,
+    pipes: Vec<(space::TileId, space::TileId)>,
+}
+
+// what goes in every generation
+fn header(saved: &SavedWorld) -> io::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    write_i64(&mut out, saved.time.into_bits())?;
+    write_i64(&mut out, saved.money)?;
+    write_u64(&mut out, saved.seed)?;
+    write_u64(&mut out, saved.drawn.len() as u64)?;
+    for &(id, count) in &saved.drawn {
+        write_u64(&mut out, id)?;
+        write_u64(&mut out, count)?;
+    }
+    write_u64(&mut out, saved.burning.len() as u64)?;
+    for &id in &saved.burning {
+        write_u64(&mut out, id)?;
+    }
+    write_stamps(&mut out, &saved.mods)?;
+    write_u64(&mut out, saved.keys)?;
+    Ok(out)
+}
+
+// `saved` cut up into chunks, each sorted so that a part that hasn't
+// changed comes out the same as last time
+fn parts(saved: &SavedWorld) -> io::Result<Parts> {
+    let mut plots = collections::BTreeMap::<chunks::ChunkId, Plot>::new();
+    for &road in &saved.roads {
+        let chunk = chunks::chunk_of(road.0);
+        plots.entry(chunk).or_insert_with(Plot::default).roads.push(road);
+    }
+    for &zone in &saved.zones {
+        let chunk = chunks::chunk_of(zone.0);
+        plots.entry(chunk).or_insert_with(Plot::default).zones.push(zone);
+    }
+    for &pipe in &saved.pipes {
+        let chunk = chunks::chunk_of(pipe.0);
+        plots.entry(chunk).or_insert_with(Plot::default).pipes.push(pipe);
+    }
+    // by where they are rather than by id, since ids are drawn at random
+    // and would put every page out of date whenever anything moved
+    let mut crowds = collections::BTreeMap::new();
+    for entity in &saved.entities {
+        let chunk = chunks::chunk_of(space::tile_id(entity.position));
+        crowds.entry(chunk).or_insert_with(Vec::new).push(entity);
+    }
+
+    let mut parts = Parts::new();
+    for (id, mut plot) in plots {
+        plot.roads.sort();
+        plot.zones.sort_by_key(|&(tile, _)| tile);
+        plot.pipes.sort();
+        let mut body = Vec::new();
+        write_segments(&mut body, &plot.roads)?;
+        write_zones(&mut body, &plot.zones)?;
+        write_segments(&mut body, &plot.pipes)?;
+        parts.insert(Part::Chunk(id), body);
+    }
+    for (id, mut entities) in crowds {
+        entities.sort_by_key(|entity| entity.id);
+        let mut body = Vec::new();
+        write_u64(&mut body, entities.len() as u64)?;
+        for entity in entities {
+            write_entity(&mut body, entity)?;
+        }
+        parts.insert(Part::Crowd(id), body);
+    }
+    Ok(parts)
+}
+
+// a generation, ready to append. a part with nothing in it is one that is
+// gone since the generation before
+fn generation(header: &[u8], parts: &[(Part, &[u8])]) -> io::Result<Vec<u8>> {
+    let mut payload = Vec::new();
+    write_u64(&mut payload, header.len() as u64)?;
+    payload.write_all(header)?;
+    write_u64(&mut payload, parts.len() as u64)?;
+    for &(part, body) in parts {
+        write_part(&mut payload, part)?;
+        write_u64(&mut payload, body.len() as u64)?;
+        payload.write_all(body)?;
+    }
+    let mut sum = checksum::Checksum::new();
+    sum.write_bytes(&payload);
+
+    let mut out = Vec::new();
+    write_u64(&mut out, payload.len() as u64)?;
+    out.write_all(&payload)?;
+    write_u64(&mut out, sum.finish())?;
+    Ok(out)
+}
+
+// the next generation's payload, or None if the journal ends here, which
+// it does just the same if the last generation was cut off
+fn next_generation(input: &mut io::Read) -> io::Result<Option<Vec<u8>>> {
+    let cut_off = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => Ok(None),
+        _ => Err(e),
+    };
+    let length = match read_u64(input) {
+        Ok(length) => length,
+        Err(e) => return cut_off(e),
+    };
+    let mut payload = Vec::new();
+    (&mut *input).take(length).read_to_end(&mut payload)?;
+    if payload.len() as u64 != length {
+        return Ok(None);
+    }
+    let expected = match read_u64(input) {
+        Ok(expected) => expected,
+        Err(e) => return cut_off(e),
+    };
+    let mut sum = checksum::Checksum::new();
+    sum.write_bytes(&payload);
+    if sum.finish() != expected {
+        return Ok(None);
+    }
+    Ok(Some(payload))
+}
+
+// everything current in a journal, as of its last whole generation
+struct Scan {
+    version: u64,
+    header: Vec<u8>,
+    parts: Parts,
+    // how far into the file the last whole generation ends
+    length: u64,
+}
+
+fn scan(input: &mut io::Read) -> io::Result<Scan> {
+    let mut magic = [0u8; 4];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(invalid("not a lil-city journal"));
+    }
+    let version = read_version(input)?;
+    let mut scan = Scan {
+        version,
+        header: Vec::new(),
+        parts: Parts::new(),
+        length: PREAMBLE,
+    };
+    while let Some(payload) = next_generation(input)? {
+        scan.length += 16 + payload.len() as u64;
+        let mut rest = &payload[..];
+        let length = read_u64(&mut rest)? as usize;
+        if length > rest.len() {
+            return Err(invalid("journal header runs past its generation"));
+        }
+        scan.header = rest[..length].to_vec();
+        rest = &rest[length..];
+        let count = read_u64(&mut rest)?;
+        for _ in 0..count {
+            let part = read_part(&mut rest)?;
+            let length = read_u64(&mut rest)? as usize;
+            if length > rest.len() {
+                return Err(invalid("journal part runs past its generation"));
+            }
+            if length == 0 {
+                scan.parts.remove(&part);
+            } else {
+                scan.parts.insert(part, rest[..length].to_vec());
+            }
+            rest = &rest[length..];
+        }
+    }
+    Ok(scan)
+}
+
+// puts a save back together out of its parts
+fn assemble(scan: Scan) -> io::Result<SavedWorld> {
+    if scan.header.is_empty() {
+        return Err(invalid("journal has nothing in it"));
+    }
+    let version = scan.version;
+    let mut header = &scan.header[..];
+    let time = units::Time::from_bits(read_i64(&mut header)?);
+    let money = read_i64(&mut header)?;
+    let seed = read_u64(&mut header)?;
+    let mut drawn = Vec::new();
+    for _ in 0..read_u64(&mut header)? {
+        drawn.push((read_u64(&mut header)?, read_u64(&mut header)?));
+    }
+    let mut burning = Vec::new();
+    for _ in 0..read_u64(&mut header)? {
+        burning.push(read_u64(&mut header)?);
+    }
+    let mods = read_stamps(&mut header)?;
+    let keys = if version >= 15 { read_u64(&mut header)? } else { 0 };
+
+    let mut saved = SavedWorld {
+        time,
+        entities: Vec::new(),
+        roads: Vec::new(),
+        money,
+        zones: Vec::new(),
+        seed,
+        drawn,
+        pipes: Vec::new(),
+        burning,
+        mods,
+        keys,
+    };
+    for (part, body) in scan.parts {
+        let mut body = &body[..];
+        match part {
+            Part::Chunk(_) => {
+                saved.roads.extend(read_segments(&mut body)?);
+                saved.zones.extend(read_zones(&mut body)?);
+                saved.pipes.extend(read_segments(&mut body)?);
+            },
+            Part::Page(_) | Part::Crowd(_) => {
+                for _ in 0..read_u64(&mut body)? {
+                    saved.entities.push(read_entity(&mut body, version)?);
+                }
+            },
+        }
+    }
+    upgrade(&mut saved, version);
+    Ok(saved)
+}
+
+/// Reads a journal as the save it comes to.
+pub fn read(input: &mut io::Read) -> io::Result<SavedWorld> {
+    assemble(scan(input)?)
+}
+
+/// A journal being appended to, which remembers what it last wrote so
+/// that it can tell what has changed.
+pub struct Journal {
+    path: path::PathBuf,
+    header: Vec<u8>,
+    // what each part is as of the newest generation
+    written: Parts,
+    // how big the file is, which is nothing until the first generation
+    length: u64,
+}
+
+impl Journal {
+    /// Carries on with the journal at `path`, or starts a new one if there
+    /// isn't one there. A generation left cut off at the end is cut out of
+    /// the file, and a journal from another version of the format starts
+    /// afresh the first time it is appended to.
+    pub fn open(path: &path::Path) -> io::Result<Journal> {
+        let mut journal = Journal {
+            path: path.to_path_buf(),
+            header: Vec::new(),
+            written: Parts::new(),
+            length: 0,
+        };
+        let file = match fs::File::open(path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(journal);
+            },
+            Err(e) => return Err(e),
+        };
+        let scan = scan(&mut io::BufReader::new(file))?;
+        if scan.version != VERSION as u64 || scan.header.is_empty() {
+            return Ok(journal);
+        }
+        let file = fs::OpenOptions::new().write(true).open(path)?;
+        if file.metadata()?.len() > scan.length {
+            warn!("Journal {} was cut off, dropping the end of it",
+                  path.display());
+            file.set_len(scan.length)?;
+        }
+        journal.header = scan.header;
+        journal.written = scan.parts;
+        journal.length = scan.length;
+        Ok(journal)
+    }
+
+    /// Appends whatever of `saved` has changed since the last generation,
+    /// giving how many bytes that took, then compacts the journal if it
+    /// has grown too big.
+    pub fn append(self: &mut Self, saved: &SavedWorld) -> io::Result<u64> {
+        let header = header(saved)?;
+        let parts = parts(saved)?;
+        if self.length == 0 {
+            self.header = header;
+            self.written = parts;
+            self.compact()?;
+            return Ok(self.length);
+        }
+
+        let generation = {
+            let gone: &[u8] = &[];
+            let mut changed = Vec::new();
+            for (&part, body) in &parts {
+                if self.written.get(&part) != Some(body) {
+                    changed.push((part, &body[..]));
+                }
+            }
+            for &part in self.written.keys() {
+                if !parts.contains_key(&part) {
+                    changed.push((part, gone));
+                }
+            }
+            generation(&header, &changed)?
+        };
+        {
+            // cutting back to the last whole generation first, in case the
+            // one before this failed partway, since reading would stop at
+            // what it left and never get as far as this one
+            let mut file = fs::OpenOptions::new()
+                .write(true)
+                .open(&self.path)?;
+            file.set_len(self.length)?;
+            file.seek(io::SeekFrom::Start(self.length))?;
+            if let Err(e) = file.write_all(&generation) {
+                let _ = file.set_len(self.length);
+                return Err(e);
+            }
+        }
+        self.length += generation.len() as u64;
+        self.header = header;
+        self.written = parts;
+
+        if self.length > COMPACT_FACTOR * self.current_length() {
+            self.compact()?;
+        }
+        Ok(generation.len() as u64)
+    }
+
+    /// Writes the journal out again as a single generation holding only
+    /// what is current.
+    pub fn compact(self: &mut Self) -> io::Result<()> {
+        if self.header.is_empty() {
+            return Ok(());
+        }
+        let parts: Vec<(Part, &[u8])> = self.written
+            .iter()
+            .map(|(&part, body)| (part, &body[..]))
+            .collect();
+        let generation = generation(&self.header, &parts)?;
+        let partial = self.path.with_extension("partial");
+        {
+            let mut file = io::BufWriter::new(fs::File::create(&partial)?);
+            file.write_all(MAGIC)?;
+            write_u64(&mut file, VERSION as u64)?;
+            file.write_all(&generation)?;
+        }
+        fs::rename(&partial, &self.path)?;
+        self.length = PREAMBLE + generation.len() as u64;
+        Ok(())
+    }
+
+    // how big the journal would be compacted, counting what each part
+    // takes to say which part it is
+    fn current_length(self: &Self) -> u64 {
+        let parts = self.written
+                        .values()
+                        .map(|body| 25 + body.len() as u64)
+                        .sum::<u64>();
+        PREAMBLE + 32 + self.header.len() as u64 + parts
+    }
+
+    /// How many bytes the journal file has.
+    pub fn size(self: &Self) -> u64 {
+        self.length
+    }
+}
+
+/// Loads the journal at `path` as the save it comes to.
+pub fn load(path: &path::Path) -> io::Result<SavedWorld> {
+    let mut file = io::BufReader::new(fs::File::open(path)?);
+    read(&mut file)
+}
+
+#[cfg(test)]
+mod test_journal {
+    use std::env;
+
+    use entities::item;
+    use sulphate;
+    use sulphate::persist::EntityKind;
+    use sulphate::persist::SavedEntity;
+
+    use super::*;
+
+    // on a tile of its own, counting along from the origin by id
+    fn apple(id: sulphate::EntityId) -> SavedEntity {
+        let along = units::Displacement {
+            x: (id as i32 * space::TILE_SIZE as i32).into(),
+            y: 0.into(),
+        };
+        SavedEntity {
+            id,
+            kind: EntityKind::Item,
+            position: units::Position::default() + along,
+            velocity: Default::default(),
+            plans: None,
+            blueprint: None,
+            made: None,
+            stops: None,
+            appearance: None,
+            inventory: None,
+            item: Some(item::Kind::Apple),
+            due: None,
+            waypoints: None,
+            route: None,
+        }
+    }
+
+    // a long road, with an apple for every tile of it
+    fn city(length: i64) -> SavedWorld {
+        SavedWorld {
+            time: Default::default(),
+            entities: (0..length as u64).map(apple).collect(),
+            roads: (0..length).map(|x| ((x, 0), (x + 1, 0))).collect(),
+            money: 100,
+            zones: Vec::new(),
+            seed: 1,
+            drawn: Vec::new(),
+            pipes: Vec::new(),
+            burning: Vec::new(),
+            mods: Vec::new(),
+            keys: length as u64,
+        }
+    }
+
+    // what a save comes to whatever order its parts are in, for telling
+    // whether two have the same things in them
+    fn sorted(saved: &SavedWorld) -> Vec<u8> {
+        let mut out = Vec::new();
+        saved.write(&mut out).unwrap();
+        let mut copy = SavedWorld::read(&mut &out[..]).unwrap();
+        copy.entities.sort_by_key(|entity| entity.id);
+        copy.roads.sort();
+        copy.zones.sort_by_key(|&(tile, _)| tile);
+        copy.pipes.sort();
+        let mut out = Vec::new();
+        copy.write(&mut out).unwrap();
+        out
+    }
+
+    fn fresh(name: &str) -> path::PathBuf {
+        let path = env::temp_dir().join(name);
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn only_what_changed_is_appended() {
+        let path = fresh("lil_city_journal_deltas.lilj");
+        let mut journal = Journal::open(&path).unwrap();
+        let mut saved = city(1000);
+        let whole = journal.append(&saved).unwrap();
+
+        // one apple rolls off, one stretch of road goes, and the rest
+        // stays as it was
+        let origin = units::Position::default();
+        saved.entities[500].position.x = origin.x + units::Scalar::from(5);
+        saved.roads.pop();
+        let delta = journal.append(&saved).unwrap();
+        assert!(delta * 10 < whole);
+        assert_eq!(sorted(&load(&path).unwrap()), sorted(&saved));
+
+        // and the journal picks up from there once it is opened again
+        let mut journal = Journal::open(&path).unwrap();
+        saved.money = 5;
+        assert!(journal.append(&saved).unwrap() < delta);
+        assert_eq!(load(&path).unwrap().money, 5);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn journals_compact_once_they_grow() {
+        let path = fresh("lil_city_journal_compact.lilj");
+        let mut journal = Journal::open(&path).unwrap();
+        let mut saved = city(200);
+        journal.append(&saved).unwrap();
+        let whole = journal.size();
+        for n in 0..20 {
+            let x = units::Position::default().x + units::Scalar::from(n);
+            for entity in &mut saved.entities {
+                entity.position.x = x;
+            }
+            journal.append(&saved).unwrap();
+            assert!(journal.size() <= COMPACT_FACTOR * whole + 1024);
+        }
+        assert_eq!(fs::metadata(&path).unwrap().len(), journal.size());
+        assert_eq!(sorted(&load(&path).unwrap()), sorted(&saved));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn generations_cut_off_are_left_out() {
+        let path = fresh("lil_city_journal_cut.lilj");
+        let mut journal = Journal::open(&path).unwrap();
+        let mut saved = city(10);
+        journal.append(&saved).unwrap();
+        let before = journal.size();
+        saved.money = 5;
+        journal.append(&saved).unwrap();
+
+        let file = fs::OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(journal.size() - 4).unwrap();
+        assert_eq!(load(&path).unwrap().money, 100);
+        let journal = Journal::open(&path).unwrap();
+        assert_eq!(journal.size(), before);
+        assert_eq!(fs::metadata(&path).unwrap().len(), before);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn appends_that_fail_partway_are_written_over() {
+        let path = fresh("lil_city_journal_failed.lilj");
+        let mut journal = Journal::open(&path).unwrap();
+        let mut saved = city(10);
+        journal.append(&saved).unwrap();
+
+        // as if the disk filled up halfway through the next one
+        {
+            let mut file = fs::OpenOptions::new()
+                .append(true)
+                .open(&path)
+                .unwrap();
+            file.write_all(&[7; 20]).unwrap();
+        }
+        saved.money = 5;
+        journal.append(&saved).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().len(), journal.size());
+        assert_eq!(load(&path).unwrap().money, 5);
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,954 @@
+//! Save files, which start with which version of the format they were
+//! written in, so that saves from before the format last changed keep
+//! loading.
+//!
+//! A save is read as far as its version has anything to read, leaving out
+//! whatever was added since, and is then brought up to date by each
+//! migration newer than it in turn. Changing the format means bumping
+//! `VERSION`, reading the new parts only from saves at least that new, and,
+//! if older saves can't just go without them, adding a migration to fill
+//! them in.
+
+use std::any;
+use std::cmp;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::path;
+use std::sync::mpsc;
+
+use blueprints;
+use economy;
+use entities::building;
+use entities::citizen;
+use entities::item;
+use entities::player;
+use entities::vehicle;
+use fire;
+use growth;
+use mods;
+use space;
+use space::zones;
+use sulphate;
+use sulphate::Scheduler;
+use sulphate::rng;
+use units;
+
+pub mod journal;
+
+static MAGIC: &'static [u8; 4] = b"LILC";
+pub const VERSION: u32 = 15;
+
+/// Everything needed to rebuild a world.
+///
+/// Of the pending events, only when each entity was next due to do
+/// something is saved, since the events themselves all follow from what
+/// the entities were doing, and get scheduled again for the same time as
+/// each entity is put back. Ticks and the calendar go by the clock, and
+/// start again from the saved time. Fire engines aren't saved either,
+/// since setting light to what was burning sends them out again.
+pub struct SavedWorld {
+    pub time: units::Time,
+    pub entities: Vec<SavedEntity>,
+    /// Every stretch of road, from one tile to the next.
+    pub roads: Vec<(space::TileId, space::TileId)>,
+    /// What the city had in the bank.
+    pub money: i64,
+    /// Every zoned tile.
+    pub zones: Vec<(space::TileId, zones::Zone)>,
+    /// What the world's randomness was seeded with, and how far along each
+    /// entity's stream of it was.
+    pub seed: u64,
+    pub drawn: Vec<(sulphate::EntityId, u64)>,
+    /// Every stretch of pipe, the same as roads.
+    pub pipes: Vec<(space::TileId, space::TileId)>,
+    /// Every building that was on fire, which starts burning afresh once
+    /// it is put back.
+    pub burning: Vec<sulphate::EntityId>,
+    /// Which packs the world was running, so that picking it back up
+    /// without them can be warned about.
+    pub mods: Vec<mods::Stamp>,
+    /// How many ids the world had given out, so that the ones it gives
+    /// out next are the same as if it had never stopped.
+    pub keys: u64,
+}
+
+#[derive(Clone)]
+pub struct SavedEntity {
+    pub id: sulphate::EntityId,
+    pub kind: EntityKind,
+    pub position: units::Position,
+    pub velocity: units::Velocity,
+    /// Where a citizen lives and works, and None for everything else.
+    pub plans: Option<citizen::Plans>,
+    /// What a building was built as, and None for everything else.
+    pub blueprint: Option<building::Blueprint>,
+    /// The blueprint a building was built from, and None for everything
+    /// else.
+    pub made: Option<blueprints::Name>,
+    /// The stops a vehicle goes between, and None for everything else.
+    pub stops: Option<Vec<space::TileId>>,
+    /// How a player looks, and None for everything else.
+    pub appearance: Option<player::Appearance>,
+    /// What a player is carrying, and None for everything else.
+    pub inventory: Option<Vec<item::Kind>>,
+    /// What an item is, and None for everything else.
+    pub item: Option<item::Kind>,
+    /// When it was next due to do something: a citizen or vehicle on its
+    /// way looking at where it had got to, or a burning building burning
+    /// down. None if it was waiting on nothing.
+    pub due: Option<units::Time>,
+    /// The waypoints a citizen had left to go, the next one last, and None
+    /// for everything else.
+    pub waypoints: Option<Vec<units::Position>>,
+    /// Which of its stops a vehicle was on its way to, and the road tiles
+    /// it had left on the way there, the next one last, and None for
+    /// everything else.
+    pub route: Option<(u64, Vec<space::TileId>)>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EntityKind {
+    Player,
+    Citizen,
+    Building,
+    Vehicle,
+    Item,
+}
+
+impl EntityKind {
+    pub fn of(ty: any::TypeId) -> Option<EntityKind> {
+        if ty == any::TypeId::of::<player::Player>() {
+            Some(EntityKind::Player)
+        } else if ty == any::TypeId::of::<citizen::Citizen>() {
+            Some(EntityKind::Citizen)
+        } else if ty == any::TypeId::of::<building::Building>() {
+            Some(EntityKind::Building)
+        } else if ty == any::TypeId::of::<vehicle::Vehicle>() {
+            Some(EntityKind::Vehicle)
+        } else if ty == any::TypeId::of::<item::Item>() {
+            Some(EntityKind::Item)
+        } else {
+            None
+        }
+    }
+
+    pub fn tag(self: Self) -> u8 {
+        match self {
+            EntityKind::Player => 0,
+            EntityKind::Citizen => 1,
+            EntityKind::Building => 2,
+            EntityKind::Vehicle => 3,
+            EntityKind::Item => 4,
+        }
+    }
+
+    pub fn from_tag(tag: u8) -> Option<EntityKind> {
+        match tag {
+            0 => Some(EntityKind::Player),
+            1 => Some(EntityKind::Citizen),
+            2 => Some(EntityKind::Building),
+            3 => Some(EntityKind::Vehicle),
+            4 => Some(EntityKind::Item),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn write_u64(out: &mut io::Write, val: u64) -> io::Result<()> {
+    let mut bytes = [0u8; 8];
+    for i in 0..8 {
+        bytes[i] = (val >> (8 * i)) as u8;
+    }
+    out.write_all(&bytes)
+}
+
+fn write_i64(out: &mut io::Write, val: i64) -> io::Result<()> {
+    write_u64(out, val as u64)
+}
+
+fn read_u64(input: &mut io::Read) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    input.read_exact(&mut bytes)?;
+    let mut val = 0;
+    for i in 0..8 {
+        val |= (bytes[i] as u64) << (8 * i);
+    }
+    Ok(val)
+}
+
+fn read_i64(input: &mut io::Read) -> io::Result<i64> {
+    read_u64(input).map(|val| val as i64)
+}
+
+fn write_position(
+    out: &mut io::Write,
+    position: units::Position,
+) -> io::Result<()> {
+    write_i64(out, position.x.into_bits())?;
+    write_i64(out, position.y.into_bits())
+}
+
+fn read_position(input: &mut io::Read) -> io::Result<units::Position> {
+    Ok(units::Position {
+        x: units::Coord::from_bits(read_i64(input)?),
+        y: units::Coord::from_bits(read_i64(input)?),
+    })
+}
+
+fn write_tile(out: &mut io::Write, id: space::TileId) -> io::Result<()> {
+    write_i64(out, id.0)?;
+    write_i64(out, id.1)
+}
+
+fn read_tile(input: &mut io::Read) -> io::Result<space::TileId> {
+    Ok((read_i64(input)?, read_i64(input)?))
+}
+
+// the longest name or version of a pack, or name of a blueprint, that a
+// save can have
+const MAX_STRING_LENGTH: usize = 256;
+
+fn write_string(out: &mut io::Write, text: &str) -> io::Result<()> {
+    write_u64(out, text.len() as u64)?;
+    out.write_all(text.as_bytes())
+}
+
+fn read_string(input: &mut io::Read) -> io::Result<String> {
+    let length = read_u64(input)? as usize;
+    if length > MAX_STRING_LENGTH {
+        return Err(invalid("name or version too long in save"));
+    }
+    let mut text = vec![0u8; length];
+    input.read_exact(&mut text)?;
+    String::from_utf8(text)
+        .map_err(|_| invalid("name or version isn't UTF-8"))
+}
+
+fn read_item(input: &mut io::Read) -> io::Result<item::Kind> {
+    let mut tag = [0u8; 1];
+    input.read_exact(&mut tag)?;
+    item::Kind::from_tag(tag[0])
+        .ok_or_else(|| invalid("unknown item in save file"))
+}
+
+fn write_segments(
+    out: &mut io::Write,
+    segments: &[(space::TileId, space::TileId)],
+) -> io::Result<()> {
+    write_u64(out, segments.len() as u64)?;
+    for &(from, to) in segments {
+        write_tile(out, from)?;
+        write_tile(out, to)?;
+    }
+    Ok(())
+}
+
+fn read_segments(
+    input: &mut io::Read,
+) -> io::Result<Vec<(space::TileId, space::TileId)>> {
+    let count = read_u64(input)?;
+    let mut segments = Vec::new();
+    for _ in 0..count {
+        segments.push((read_tile(input)?, read_tile(input)?));
+    }
+    Ok(segments)
+}
+
+fn write_zones(
+    out: &mut io::Write,
+    tiles: &[(space::TileId, zones::Zone)],
+) -> io::Result<()> {
+    write_u64(out, tiles.len() as u64)?;
+    for &(id, zone) in tiles {
+        write_tile(out, id)?;
+        out.write_all(&[zone.tag()])?;
+    }
+    Ok(())
+}
+
+fn read_zones(
+    input: &mut io::Read,
+) -> io::Result<Vec<(space::TileId, zones::Zone)>> {
+    let count = read_u64(input)?;
+    let mut tiles = Vec::new();
+    for _ in 0..count {
+        let id = read_tile(input)?;
+        let mut tag = [0u8; 1];
+        input.read_exact(&mut tag)?;
+        let zone = zones::Zone::from_tag(tag[0])
+            .ok_or_else(|| invalid("unknown zone in save file"))?;
+        tiles.push((id, zone));
+    }
+    Ok(tiles)
+}
+
+fn write_stamps(
+    out: &mut io::Write,
+    stamps: &[mods::Stamp],
+) -> io::Result<()> {
+    write_u64(out, stamps.len() as u64)?;
+    for stamp in stamps {
+        write_string(out, &stamp.name)?;
+        write_string(out, &stamp.version)?;
+    }
+    Ok(())
+}
+
+fn read_stamps(input: &mut io::Read) -> io::Result<Vec<mods::Stamp>> {
+    let count = read_u64(input)?;
+    let mut stamps = Vec::new();
+    for _ in 0..count {
+        let name = read_string(input)?;
+        let version = read_string(input)?;
+        stamps.push(mods::Stamp { name, version });
+    }
+    Ok(stamps)
+}
+
+fn write_entity(out: &mut io::Write, entity: &SavedEntity) -> io::Result<()> {
+    write_u64(out, entity.id)?;
+    out.write_all(&[entity.kind.tag()])?;
+    write_i64(out, entity.position.x.into_bits())?;
+    write_i64(out, entity.position.y.into_bits())?;
+    write_i64(out, entity.velocity.x.into_bits())?;
+    write_i64(out, entity.velocity.y.into_bits())?;
+    if let Some(plans) = entity.plans {
+        write_position(out, plans.home)?;
+        write_position(out, plans.work)?;
+    }
+    if let Some(blueprint) = entity.blueprint {
+        out.write_all(&[blueprint.tag()])?;
+    }
+    if let Some(made) = entity.made {
+        write_string(out, made.as_str())?;
+    }
+    if let Some(ref stops) = entity.stops {
+        write_u64(out, stops.len() as u64)?;
+        for &stop in stops {
+            write_tile(out, stop)?;
+        }
+    }
+    if let Some(ref appearance) = entity.appearance {
+        out.write_all(&appearance.color)?;
+        write_u64(out, appearance.name.len() as u64)?;
+        out.write_all(appearance.name.as_bytes())?;
+    }
+    if let Some(ref inventory) = entity.inventory {
+        write_u64(out, inventory.len() as u64)?;
+        for &kind in inventory {
+            out.write_all(&[kind.tag()])?;
+        }
+    }
+    if let Some(item) = entity.item {
+        out.write_all(&[item.tag()])?;
+    }
+    if let Some(ref waypoints) = entity.waypoints {
+        write_u64(out, waypoints.len() as u64)?;
+        for &waypoint in waypoints {
+            write_position(out, waypoint)?;
+        }
+    }
+    if let Some((stop, ref tiles)) = entity.route {
+        write_u64(out, stop)?;
+        write_u64(out, tiles.len() as u64)?;
+        for &tile in tiles {
+            write_tile(out, tile)?;
+        }
+    }
+    match entity.due {
+        Some(due) => {
+            out.write_all(&[1])?;
+            write_i64(out, due.into_bits())
+        },
+        None => out.write_all(&[0]),
+    }
+}
+
+// reads an entity written by a save of `version`
+fn read_entity(input: &mut io::Read, version: u64) -> io::Result<SavedEntity> {
+    let id = read_u64(input)?;
+    let mut tag = [0u8; 1];
+    input.read_exact(&mut tag)?;
+    let kind = EntityKind::from_tag(tag[0])
+        .ok_or_else(|| invalid("unknown entity type in save file"))?;
+    let position = units::Position {
+        x: units::Coord::from_bits(read_i64(input)?),
+        y: units::Coord::from_bits(read_i64(input)?),
+    };
+    let velocity = units::Velocity {
+        x: units::Scalar::from_bits(read_i64(input)?),
+        y: units::Scalar::from_bits(read_i64(input)?),
+    };
+    let plans = match kind {
+        EntityKind::Citizen => Some(citizen::Plans {
+            home: read_position(input)?,
+            work: read_position(input)?,
+        }),
+        _ => None,
+    };
+    let blueprint = match kind {
+        EntityKind::Building => {
+            let mut tag = [0u8; 1];
+            input.read_exact(&mut tag)?;
+            let blueprint = building::Blueprint::from_tag(tag[0])
+                .ok_or_else(|| invalid("unknown building in save"))?;
+            Some(blueprint)
+        },
+        _ => None,
+    };
+    // saves from before there were blueprints leave buildings to
+    // have been built from their kind's
+    let made = match kind {
+        EntityKind::Building if version >= 14 => {
+            let name = read_string(input)?;
+            let made = blueprints::Name::new(&name)
+                .ok_or_else(|| invalid("bad blueprint in save"))?;
+            Some(made)
+        },
+        _ => None,
+    };
+    let stops = match kind {
+        EntityKind::Vehicle => {
+            let count = read_u64(input)?;
+            let mut stops = Vec::new();
+            for _ in 0..count {
+                stops.push(read_tile(input)?);
+            }
+            Some(stops)
+        },
+        _ => None,
+    };
+    let appearance = match kind {
+        EntityKind::Player if version >= 8 => {
+            let mut color = [0u8; 3];
+            input.read_exact(&mut color)?;
+            let length = read_u64(input)? as usize;
+            if length > 4 * player::MAX_NAME_LENGTH {
+                return Err(invalid("player name too long in save"));
+            }
+            let mut name = vec![0u8; length];
+            input.read_exact(&mut name)?;
+            let name = String::from_utf8(name)
+                .map_err(|_| invalid("player name isn't UTF-8"))?;
+            Some(player::Appearance::new(&name, color))
+        },
+        _ => None,
+    };
+    let inventory = match kind {
+        EntityKind::Player if version >= 10 => {
+            let count = read_u64(input)? as usize;
+            if count > item::CAPACITY {
+                return Err(invalid("too many items held in save"));
+            }
+            let mut inventory = Vec::new();
+            for _ in 0..count {
+                inventory.push(read_item(input)?);
+            }
+            Some(inventory)
+        },
+        _ => None,
+    };
+    let item = match kind {
+        EntityKind::Item => Some(read_item(input)?),
+        _ => None,
+    };
+    // saves from before these were saved leave citizens and vehicles to
+    // set off afresh, and fires to burn for as long as a new one would
+    let waypoints = match kind {
+        EntityKind::Citizen if version >= 15 => {
+            let mut waypoints = Vec::new();
+            for _ in 0..read_u64(input)? {
+                waypoints.push(read_position(input)?);
+            }
+            Some(waypoints)
+        },
+        _ => None,
+    };
+    let route = match kind {
+        EntityKind::Vehicle if version >= 15 => {
+            let stop = read_u64(input)?;
+            let mut tiles = Vec::new();
+            for _ in 0..read_u64(input)? {
+                tiles.push(read_tile(input)?);
+            }
+            Some((stop, tiles))
+        },
+        _ => None,
+    };
+    let mut due = None;
+    if version >= 15 {
+        let mut flag = [0u8; 1];
+        input.read_exact(&mut flag)?;
+        if flag[0] != 0 {
+            due = Some(units::Time::from_bits(read_i64(input)?));
+        }
+    }
+    Ok(SavedEntity {
+        id,
+        kind,
+        position,
+        velocity,
+        plans,
+        blueprint,
+        made,
+        stops,
+        appearance,
+        inventory,
+        item,
+        due,
+        waypoints,
+        route,
+    })
+}
+
+fn read_version(input: &mut io::Read) -> io::Result<u64> {
+    let version = read_u64(input)?;
+    // saves from before there were citizens or buildings read the same,
+    // having none
+    if version == 0 || version > VERSION as u64 {
+        return Err(invalid("unsupported save file version"));
+    }
+    Ok(version)
+}
+
+// brings a save up from the version before `to`, filling in what that
+// version added. versions that only added something a save can do
+// without, like roads or pipes, need no migration
+struct Migration {
+    to: u64,
+    upgrade: fn(&mut SavedWorld),
+}
+
+static MIGRATIONS: &'static [Migration] = &[
+    Migration { to: 6, upgrade: open_economy },
+    Migration { to: 8, upgrade: name_players },
+    Migration { to: 9, upgrade: seed_randomness },
+    Migration { to: 10, upgrade: empty_inventories },
+];
+
+// saves from before there was an economy start it afresh
+fn open_economy(saved: &mut SavedWorld) {
+    saved.money = economy::starting_money();
+}
+
+// and from before players had names give them the default
+fn name_players(saved: &mut SavedWorld) {
+    for entity in &mut saved.entities {
+        if entity.kind == EntityKind::Player {
+            entity.appearance = Some(Default::default());
+        }
+    }
+}
+
+// and from before there was randomness get what a new world would
+fn seed_randomness(saved: &mut SavedWorld) {
+    saved.seed = rng::DEFAULT_SEED;
+    saved.drawn = Vec::new();
+}
+
+// and from before there were items have players carrying nothing
+fn empty_inventories(saved: &mut SavedWorld) {
+    for entity in &mut saved.entities {
+        if entity.kind == EntityKind::Player {
+            entity.inventory = Some(Vec::new());
+        }
+    }
+}
+
+fn upgrade(saved: &mut SavedWorld, version: u64) {
+    for migration in MIGRATIONS {
+        if migration.to > version {
+            (migration.upgrade)(saved);
+        }
+    }
+}
+
+impl SavedWorld {
+    pub fn write(self: &Self, out: &mut io::Write) -> io::Result<()> {
+        out.write_all(MAGIC)?;
+        write_u64(out, VERSION as u64)?;
+        write_i64(out, self.time.into_bits())?;
+        write_u64(out, self.entities.len() as u64)?;
+        for entity in &self.entities {
+            write_entity(out, entity)?;
+        }
+        write_segments(out, &self.roads)?;
+        write_i64(out, self.money)?;
+        write_zones(out, &self.zones)?;
+        write_u64(out, self.seed)?;
+        write_u64(out, self.drawn.len() as u64)?;
+        for &(id, count) in &self.drawn {
+            write_u64(out, id)?;
+            write_u64(out, count)?;
+        }
+        write_segments(out, &self.pipes)?;
+        write_u64(out, self.burning.len() as u64)?;
+        for &id in &self.burning {
+            write_u64(out, id)?;
+        }
+        write_stamps(out, &self.mods)?;
+        write_u64(out, self.keys)
+    }
+
+    pub fn read(input: &mut io::Read) -> io::Result<SavedWorld> {
+        let mut magic = [0u8; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a lil-city save file"));
+        }
+        let version = read_version(input)?;
+        let time = units::Time::from_bits(read_i64(input)?);
+        let count = read_u64(input)?;
+        let mut entities = Vec::new();
+        for _ in 0..count {
+            entities.push(read_entity(input, version)?);
+        }
+        // saves say nothing of what was added after them, which is left
+        // for the migrations
+        let mut roads = Vec::new();
+        if version >= 4 {
+            roads = read_segments(input)?;
+        }
+        let money = if version >= 6 { read_i64(input)? } else { 0 };
+        let mut zones = Vec::new();
+        if version >= 7 {
+            zones = read_zones(input)?;
+        }
+        let mut seed = 0;
+        let mut drawn = Vec::new();
+        if version >= 9 {
+            seed = read_u64(input)?;
+            let count = read_u64(input)?;
+            for _ in 0..count {
+                drawn.push((read_u64(input)?, read_u64(input)?));
+            }
+        }
+        let mut pipes = Vec::new();
+        if version >= 11 {
+            pipes = read_segments(input)?;
+        }
+        let mut burning = Vec::new();
+        if version >= 12 {
+            let count = read_u64(input)?;
+            for _ in 0..count {
+                burning.push(read_u64(input)?);
+            }
+        }
+        let mut mods = Vec::new();
+        if version >= 13 {
+            mods = read_stamps(input)?;
+        }
+        // older saves start giving out ids from the beginning again, which
+        // only skips over the ones the save already has
+        let keys = if version >= 15 { read_u64(input)? } else { 0 };
+        let mut saved = SavedWorld {
+            time,
+            entities,
+            roads,
+            money,
+            zones,
+            seed,
+            drawn,
+            pipes,
+            burning,
+            mods,
+            keys,
+        };
+        upgrade(&mut saved, version);
+        Ok(saved)
+    }
+
+    /// Puts every saved entity back, giving each player a new device to
+    /// talk to, which will be sent a `Created` update like a new player.
+    pub fn restore(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) -> Vec<(sulphate::EntityId, mpsc::Receiver<player::Update>)> {
+        // roads go down first, since they can't be laid across the tiles
+        // that buildings make solid
+        for (from, to) in self.roads {
+            let from = space.terrain().center(from);
+            let to = space.terrain().center(to);
+            space.build_road(from, to);
+        }
+        for (from, to) in self.pipes {
+            let from = space.terrain().center(from);
+            let to = space.terrain().center(to);
+            space.lay_pipe(from, to);
+        }
+        matter.restore_keys(self.keys);
+        economy::open(time, matter).set_money(self.money);
+        rng::restore(matter, self.seed, self.drawn);
+        mods::restored(matter, self.mods);
+        if !self.zones.is_empty() {
+            for (id, zone) in self.zones {
+                let position = space.terrain().center(id);
+                space.paint_zone(position, position, Some(zone));
+            }
+            growth::start(time, matter);
+        }
+
+        let mut devices = Vec::new();
+        let mut spreading = Vec::new();
+        for entity in self.entities {
+            let body = space::Body::new(
+                entity.position,
+                entity.velocity,
+                self.time,
+            );
+            match entity.kind {
+                EntityKind::Player => {
+                    let (send, recv) = mpsc::channel();
+                    let appearance = entity.appearance.unwrap_or_default();
+                    let inventory = entity.inventory.unwrap_or_default();
+                    let inventory = item::Inventory::new(inventory);
+                    player::Player::restore(
+                        space, time, matter, entity.id, body, appearance,
+                        inventory, send,
+                    );
+                    devices.push((entity.id, recv));
+                },
+                EntityKind::Citizen => match entity.plans {
+                    Some(plans) => {
+                        let waypoints = entity.waypoints.unwrap_or_default();
+                        citizen::Citizen::restore(
+                            space, time, matter, entity.id, body, plans,
+                            waypoints, entity.due,
+                        );
+                    },
+                    None => warn!("Citizen saved without its plans"),
+                },
+                EntityKind::Building => match entity.blueprint {
+                    Some(blueprint) => {
+                        building::Building::restore(
+                            space, time, matter, entity.id,
+                            blueprint, entity.position,
+                        );
+                        if let Some(made) = entity.made {
+                            blueprints::restore(matter, entity.id, made);
+                        }
+                        if let Some(due) = entity.due {
+                            spreading.push((entity.id, due));
+                        }
+                    },
+                    None => warn!("Building saved without its blueprint"),
+                },
+                EntityKind::Vehicle => match entity.stops {
+                    Some(ref stops) if !stops.is_empty() => {
+                        let route = entity.route.clone().unwrap_or_default();
+                        vehicle::Vehicle::restore(
+                            space, time, matter, entity.id, body,
+                            stops.clone(), route, entity.due,
+                        );
+                    },
+                    _ => warn!("Vehicle saved without its stops"),
+                },
+                EntityKind::Item => match entity.item {
+                    Some(kind) => {
+                        item::Item::restore(
+                            space, time, matter, entity.id,
+                            kind, entity.position,
+                        );
+                    },
+                    None => warn!("Item saved without its kind"),
+                },
+            }
+        }
+        for id in self.burning {
+            fire::ignite(space, time, matter, id);
+        }
+        // burning down when they would have, rather than a whole fire's
+        // length after the save was loaded
+        for (id, due) in spreading {
+            let handle = matter.get::<building::Building>(id)
+                               .and_then(building::Building::spreading)
+                               .cloned();
+            if let Some(handle) = handle {
+                time.reschedule(&handle, cmp::max(due, time.now()));
+            }
+        }
+        devices
+    }
+}
+
+/// Writes `saved` to the side first, so that a crash halfway through
+/// writing leaves whatever was already at `path` as it was.
+pub fn save(path: &path::Path, saved: &SavedWorld) -> io::Result<()> {
+    let partial = path.with_extension("partial");
+    {
+        let mut file = io::BufWriter::new(fs::File::create(&partial)?);
+        saved.write(&mut file)?;
+    }
+    fs::rename(&partial, path)
+}
+
+/// Loads whatever is at `path`, whether a save or a journal.
+pub fn load(path: &path::Path) -> io::Result<SavedWorld> {
+    let mut file = io::BufReader::new(fs::File::open(path)?);
+    if file.fill_buf()?.starts_with(journal::MAGIC) {
+        journal::read(&mut file)
+    } else {
+        SavedWorld::read(&mut file)
+    }
+}
+
+#[cfg(test)]
+mod test_persist {
+    use super::*;
+
+    fn entity(id: sulphate::EntityId, kind: EntityKind) -> SavedEntity {
+        SavedEntity {
+            id,
+            kind,
+            position: Default::default(),
+            velocity: Default::default(),
+            plans: None,
+            blueprint: None,
+            made: None,
+            stops: None,
+            appearance: None,
+            inventory: None,
+            item: None,
+            due: None,
+            waypoints: None,
+            route: None,
+        }
+    }
+
+    fn bytes(saved: &SavedWorld) -> Vec<u8> {
+        let mut out = Vec::new();
+        saved.write(&mut out).expect("failed to write to memory");
+        out
+    }
+
+    #[test]
+    fn saves_read_back_as_they_were_written() {
+        let house = building::Blueprint::House;
+        let mut ada = entity(1, EntityKind::Player);
+        ada.appearance = Some(player::Appearance::new("ada", [1, 2, 3]));
+        ada.inventory = Some(vec![item::Kind::Brick]);
+        let mut resident = entity(2, EntityKind::Citizen);
+        resident.plans = Some(citizen::Plans {
+            home: Default::default(),
+            work: Default::default(),
+        });
+        resident.waypoints = Some(vec![Default::default()]);
+        resident.due = Some(units::Time::default() + units::moments(3));
+        let mut home = entity(3, EntityKind::Building);
+        home.blueprint = Some(house);
+        home.made = Some(blueprints::Name::from(house));
+        let mut bus = entity(4, EntityKind::Vehicle);
+        bus.stops = Some(vec![(0, 0), (2, 0)]);
+        bus.route = Some((1, vec![(2, 0), (1, 0)]));
+        let mut apple = entity(5, EntityKind::Item);
+        apple.item = Some(item::Kind::Apple);
+        let saved = SavedWorld {
+            time: Default::default(),
+            entities: vec![ada, resident, home, bus, apple],
+            roads: vec![((0, 0), (1, 0))],
+            money: 7,
+            zones: vec![((1, 1), zones::Zone::all()[0])],
+            seed: 99,
+            drawn: vec![(1, 4)],
+            pipes: vec![((0, 1), (1, 1))],
+            burning: vec![3],
+            mods: vec![mods::Stamp {
+                name: "huts".to_string(),
+                version: "1.0".to_string(),
+            }],
+            keys: 12,
+        };
+
+        let written = bytes(&saved);
+        let read = SavedWorld::read(&mut &written[..]).expect("save unread");
+        assert_eq!(bytes(&read), written);
+        assert_eq!(read.money, 7);
+        assert_eq!(read.entities[3].stops, Some(vec![(0, 0), (2, 0)]));
+        assert_eq!(read.entities[3].route, saved.entities[3].route);
+        assert_eq!(read.entities[1].due, saved.entities[1].due);
+        assert_eq!(read.mods, saved.mods);
+        assert_eq!(read.keys, 12);
+    }
+
+    #[test]
+    fn restored_worlds_give_out_new_ids() {
+        let mut space = space::CollisionSpace::new();
+        let mut time = sulphate::EventQueue::new(Default::default());
+        let mut matter = sulphate::EntityHeap::new();
+        // the first id a fresh heap gives out, taken by an item
+        let first = sulphate::EntityHeap::new().add(());
+        let mut apple = entity(first, EntityKind::Item);
+        apple.item = Some(item::Kind::Apple);
+        let saved = SavedWorld {
+            time: Default::default(),
+            entities: vec![apple],
+            roads: Vec::new(),
+            money: 0,
+            zones: Vec::new(),
+            seed: rng::DEFAULT_SEED,
+            drawn: Vec::new(),
+            pipes: Vec::new(),
+            burning: Vec::new(),
+            mods: Vec::new(),
+            keys: 0,
+        };
+        saved.restore(&mut space, &mut time, &mut matter);
+
+        let added = matter.add(());
+        assert!(added != first);
+        assert!(matter.get::<item::Item>(first).is_some());
+    }
+
+    #[test]
+    fn old_saves_are_brought_up_to_date() {
+        // a save from version 5, with a player and a road but no economy
+        let mut old = Vec::new();
+        old.extend_from_slice(MAGIC);
+        write_u64(&mut old, 5).unwrap();
+        write_i64(&mut old, 0).unwrap();
+        write_u64(&mut old, 1).unwrap();
+        write_u64(&mut old, 1).unwrap();
+        old.push(EntityKind::Player.tag());
+        for _ in 0..4 {
+            write_i64(&mut old, 0).unwrap();
+        }
+        write_u64(&mut old, 1).unwrap();
+        write_tile(&mut old, (0, 0)).unwrap();
+        write_tile(&mut old, (1, 0)).unwrap();
+
+        let read = SavedWorld::read(&mut &old[..]).expect("old save unread");
+        assert_eq!(read.roads, vec![((0, 0), (1, 0))]);
+        assert_eq!(read.money, economy::starting_money());
+        assert_eq!(read.seed, rng::DEFAULT_SEED);
+        let first = &read.entities[0];
+        assert_eq!(first.appearance, Some(player::Appearance::default()));
+        assert_eq!(first.inventory, Some(Vec::new()));
+
+        // and once it is written again it is a save like any other
+        let again = bytes(&read);
+        let reread = SavedWorld::read(&mut &again[..]).expect("save unread");
+        assert_eq!(bytes(&reread), again);
+    }
+
+    #[test]
+    fn migrations_go_in_order_up_to_the_current_version() {
+        let mut last = 1;
+        for migration in MIGRATIONS {
+            assert!(migration.to > last);
+            assert!(migration.to <= VERSION as u64);
+            last = migration.to;
+        }
+
+        let mut newer = Vec::new();
+        newer.extend_from_slice(MAGIC);
+        write_u64(&mut newer, VERSION as u64 + 1).unwrap();
+        assert!(SavedWorld::read(&mut &newer[..]).is_err());
+    }
+}
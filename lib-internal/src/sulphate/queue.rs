@@ -0,0 +1,498 @@
+use std::cmp;
+use std::collections;
+use std::time;
+
+use sulphate_lib::event_queue;
+
+use logging;
+use space;
+use sulphate;
+use sulphate::catch_up;
+use units;
+
+/// Which of the events due at the same time go first.
+///
+/// Events due at the same time happen lane by lane in the order below, and
+/// within a lane in the order they were enqueued, so that a world plays out
+/// the same way on every run and every machine.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Lane {
+    /// Settling where things are, before anything acts on where they are.
+    Physics,
+    Normal,
+    /// Looking at the world once everything else has happened.
+    Late,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct Key {
+    time: units::Time,
+    lane: Lane,
+    // counts up forever, so the values mean nothing but their order
+    sequence: u64,
+}
+
+pub trait Queued: Send {
+    fn invoke_boxed(
+        self: Box<Self>,
+        space: &mut space::CollisionSpace,
+        time: &mut EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    );
+
+    fn cosmetic(self: &Self) -> bool;
+
+    fn lane(self: &Self) -> Lane;
+}
+
+impl<E: sulphate::Event> Queued for E {
+    fn invoke_boxed(
+        self: Box<Self>,
+        space: &mut space::CollisionSpace,
+        time: &mut EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        (*self).invoke(space, time, matter);
+    }
+
+    fn cosmetic(self: &Self) -> bool {
+        sulphate::Event::cosmetic(self)
+    }
+
+    fn lane(self: &Self) -> Lane {
+        sulphate::Event::lane(self)
+    }
+}
+
+/// Events waiting to happen, in a fixed order.
+///
+/// The events themselves are kept here, and the server's own queue is only
+/// told when to wake up for them, since it makes no promises about what
+/// order it runs things in when they are due at the same time.
+pub struct EventQueue {
+    now: units::Time,
+    next_sequence: u64,
+    pending: collections::BTreeMap<Key, Box<Queued>>,
+    // times with nothing waiting for them in the server's queue yet
+    wakes: Vec<units::Time>,
+}
+
+impl EventQueue {
+    pub fn new(now: units::Time) -> EventQueue {
+        EventQueue {
+            now,
+            next_sequence: 0,
+            pending: collections::BTreeMap::new(),
+            wakes: Vec::new(),
+        }
+    }
+
+    pub fn now(self: &Self) -> units::Time {
+        self.now
+    }
+
+    pub fn enqueue_absolute<E>(
+        self: &mut Self,
+        event: E,
+        execute_time: units::Time,
+    ) where E: sulphate::Event {
+        self.enqueue_boxed(Box::new(event), execute_time);
+    }
+
+    pub fn enqueue_relative<E>(
+        self: &mut Self,
+        event: E,
+        execute_delay: units::Duration,
+    ) where E: sulphate::Event {
+        let execute_time = self.now + execute_delay;
+        self.enqueue_boxed(Box::new(event), execute_time);
+    }
+
+    fn enqueue_boxed(
+        self: &mut Self,
+        event: Box<Queued>,
+        execute_time: units::Time,
+    ) {
+        let earliest = Key {
+            time: execute_time,
+            lane: Lane::Physics,
+            sequence: 0,
+        };
+        let waiting = self.pending
+                          .range(earliest..)
+                          .next()
+                          .map_or(false, |(key, _)| key.time == execute_time);
+        if !waiting {
+            self.wakes.push(execute_time);
+        }
+
+        let key = Key {
+            time: execute_time,
+            lane: event.lane(),
+            sequence: self.next_sequence,
+        };
+        self.next_sequence += 1;
+        self.pending.insert(key, event);
+    }
+
+    // the first event that is due by `until`, if any, making it the time
+    // that it was due, since events put off by `Burst` are still late
+    fn pop_due(self: &mut Self, until: units::Time) -> Option<Box<Queued>> {
+        let key = match self.pending.keys().next() {
+            Some(&key) if key.time <= until => key,
+            _ => return None,
+        };
+        self.now = key.time;
+        self.pending.remove(&key)
+    }
+}
+
+/// Brings the queue up to the server's time, before anything is enqueued
+/// from outside of an event.
+pub fn catch_up_to(queue: &mut EventQueue, raw: &sulphate::RawQueue) {
+    queue.now = raw.now();
+}
+
+/// Tells the server when to come back for anything enqueued since last
+/// time.
+pub fn hand_over(queue: &mut EventQueue, raw: &mut sulphate::RawQueue) {
+    for time in queue.wakes.drain(..) {
+        raw.enqueue_absolute(Wake, time);
+    }
+}
+
+// what the server's queue actually holds, one for each time that events are
+// due, which runs everything due at that time in order
+struct Wake;
+
+impl event_queue::Event<units::Time, sulphate::World> for Wake {
+    fn invoke(
+        self: Self,
+        raw: &mut sulphate::RawQueue,
+        world: &mut sulphate::World,
+    ) {
+        catch_up_to(&mut world.queue, raw);
+        let started = time::Instant::now();
+        let before = world.counts.events;
+        run_due(world, true);
+        let taken = logging::micros(started.elapsed());
+        let ran = world.counts.events - before;
+        trace!("ran {} events due at {} in {}us", ran, world.queue.now(),
+               taken);
+        hand_over(&mut world.queue, raw);
+    }
+}
+
+fn run_due(world: &mut sulphate::World, bursting: bool) {
+    let until = world.queue.now;
+    // anything enqueued for now by these is run by this loop too
+    loop {
+        if bursting && !burst_left(world) {
+            // the rest stay where they are, so that they still happen in
+            // the same order and at the same times, but the server is
+            // woken just past this cycle to come back for them
+            let behind = next_due(&world.queue)
+                .map_or(false, |time| time <= until);
+            if behind {
+                let target = world.clock.cycle_target();
+                let later = cmp::max(target, until) + units::instants(1);
+                world.queue.wakes.push(later);
+            }
+            break;
+        }
+        match world.queue.pop_due(until) {
+            Some(event) => run(event, world),
+            None => break,
+        }
+    }
+    world.queue.now = until;
+}
+
+/// When the next event is due, if there is one.
+pub fn next_due(queue: &EventQueue) -> Option<units::Time> {
+    queue.pending.keys().next().map(|key| key.time)
+}
+
+/// Brings the queue up to `time` and runs everything due by then, without
+/// the server's queue, for worlds that are driven by hand.
+pub fn run_due_by(world: &mut sulphate::World, time: units::Time) {
+    world.queue.now = cmp::max(world.queue.now, time);
+    // without cycles there is nothing to spread a backlog over, so `Burst`
+    // would only leave it where it is
+    run_due(world, false);
+    // there is no server queue to wake, since whoever drives the world
+    // asks `next_due` instead
+    world.queue.wakes.clear();
+}
+
+// whether `Burst` leaves room for another event this cycle
+fn burst_left(world: &mut sulphate::World) -> bool {
+    use sulphate::catch_up::CatchUp;
+    let max = match world.clock.catch_up() {
+        Some(CatchUp::Burst { max_events_per_cycle }) => max_events_per_cycle,
+        _ => return true,
+    };
+    let cycle = world.clock.cycles().count();
+    if world.burst.cycle != cycle {
+        world.burst = catch_up::Burst { cycle, events: 0 };
+    }
+    world.burst.events < max
+}
+
+fn run(event: Box<Queued>, world: &mut sulphate::World) {
+    use sulphate::catch_up::CatchUp;
+    match world.clock.catch_up() {
+        Some(CatchUp::Skip) => {
+            let drift = world.clock.cycles().drift();
+            if event.cosmetic() && drift > catch_up::lag_allowance() {
+                return;
+            }
+        },
+        Some(CatchUp::Burst { .. }) => world.burst.events += 1,
+        Some(CatchUp::SlowClock) | None => (),
+    }
+    world.counts.events += 1;
+    event.invoke_boxed(&mut world.space, &mut world.queue, &mut world.matter);
+}
+
+#[cfg(test)]
+mod test_queue {
+    use std::collections::hash_map;
+    use std::hash::Hash;
+    use std::hash::Hasher;
+    use std::sync::mpsc;
+    use std::time;
+
+    use rand;
+    use rand::Rng;
+    use rand::SeedableRng;
+
+    use entities::player;
+    use space;
+    use sulphate;
+    use sulphate::catch_up;
+    use sulphate::server;
+    use units;
+
+    use super::*;
+
+    struct Ping {
+        name: &'static str,
+        lane: Lane,
+        cosmetic: bool,
+        pings: mpsc::Sender<&'static str>,
+    }
+
+    impl sulphate::Event for Ping {
+        fn invoke(
+            self: Self,
+            _space: &mut space::CollisionSpace,
+            _time: &mut sulphate::EventQueue,
+            _matter: &mut sulphate::EntityHeap,
+        ) {
+            self.pings.send(self.name).unwrap();
+        }
+
+        fn cosmetic(self: &Self) -> bool {
+            self.cosmetic
+        }
+
+        fn lane(self: &Self) -> Lane {
+            self.lane
+        }
+    }
+
+    struct Stamp(mpsc::Sender<units::Time>);
+
+    impl sulphate::Event for Stamp {
+        fn invoke(
+            self: Self,
+            _space: &mut space::CollisionSpace,
+            time: &mut sulphate::EventQueue,
+            _matter: &mut sulphate::EntityHeap,
+        ) {
+            self.0.send(time.now()).unwrap();
+        }
+    }
+
+    // a world run by hand, with the clock the world itself looks at
+    fn by_hand<F, R>(
+        policy: catch_up::CatchUp,
+        f: F,
+    ) -> (sulphate::World, server::ManualClock, R)
+        where F: FnOnce(&mut sulphate::EventQueue) -> R,
+    {
+        let start = units::Time::default();
+        let mut clock = server::ManualClock::new(start);
+        server::ClockControl::set_catch_up(&mut clock, Some(policy));
+        let control = Box::new(clock.clone());
+        let (world, r) = sulphate::World::new(start, control, |_, time, _| {
+            f(time)
+        });
+        (world, clock, r)
+    }
+
+    // tells the clock that a cycle has finished, `behind` the clock
+    fn finish_cycle(clock: &mut server::ManualClock, behind: units::Duration) {
+        let in_game = clock.now() - behind;
+        let now = time::Instant::now();
+        server::ClockMethods::finished_cycle(clock, now, in_game);
+    }
+
+    #[test]
+    fn cosmetic_events_are_skipped_only_when_behind() {
+        let (pings, recv) = mpsc::channel();
+        let at = units::Time::default() + units::moments(1);
+        let ping = |name: &'static str, cosmetic: bool| Ping {
+            name,
+            lane: Lane::Normal,
+            cosmetic,
+            pings: pings.clone(),
+        };
+        let policy = catch_up::CatchUp::Skip;
+        let (mut world, mut clock, ()) = by_hand(policy, |time| {
+            time.enqueue_absolute(ping("looks", true), at);
+            time.enqueue_absolute(ping("acts", false), at);
+        });
+
+        clock.advance(units::moments(1));
+        let far = catch_up::lag_allowance() + units::moments(1);
+        finish_cycle(&mut clock, far);
+        run_due_by(&mut world, at);
+        assert_eq!(recv.try_iter().collect::<Vec<_>>(), vec!["acts"]);
+
+        let later = at + units::moments(1);
+        world.queue.enqueue_absolute(ping("looks", true), later);
+        world.queue.enqueue_absolute(ping("acts", false), later);
+        clock.advance(units::moments(1));
+        finish_cycle(&mut clock, catch_up::lag_allowance());
+        run_due_by(&mut world, later);
+        let names: Vec<_> = recv.try_iter().collect();
+        assert_eq!(names, vec!["looks", "acts"]);
+    }
+
+    #[test]
+    fn bursts_put_events_off_without_moving_them() {
+        let (stamps, recv) = mpsc::channel();
+        let start = units::Time::default();
+        let due: Vec<_> = (1..6).map(|i| start + units::moments(i)).collect();
+        let policy = catch_up::CatchUp::Burst { max_events_per_cycle: 2 };
+        let (mut world, mut clock, ()) = by_hand(policy, |time| {
+            for &at in due.iter() {
+                time.enqueue_absolute(Stamp(stamps.clone()), at);
+            }
+        });
+
+        clock.advance(units::moments(5));
+        let until = clock.now();
+        world.queue.wakes.clear();
+        let mut ran = Vec::new();
+        for _ in 0..3 {
+            world.queue.now = until;
+            run_due(&mut world, true);
+            assert_eq!(world.queue.now(), until);
+            ran.push(recv.try_iter().collect::<Vec<_>>());
+            finish_cycle(&mut clock, units::moments(0));
+        }
+        assert_eq!(ran, vec![
+            due[0..2].to_vec(),
+            due[2..4].to_vec(),
+            due[4..5].to_vec(),
+        ]);
+        // the server is only woken again while there's more to come
+        let woken = until + units::instants(1);
+        assert_eq!(world.queue.wakes, vec![woken, woken]);
+    }
+
+    #[test]
+    fn simultaneous_events_go_by_lane_then_order() {
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, mut clock, handle, pings) =
+            server::start_server_with_clock(move |_space, time, _matter| {
+                let (pings, recv) = mpsc::channel();
+                let at = time.now() + units::moments(1);
+                let order = [
+                    ("a", Lane::Normal),
+                    ("b", Lane::Late),
+                    ("c", Lane::Physics),
+                    ("d", Lane::Normal),
+                    ("e", Lane::Physics),
+                ];
+                for &(name, lane) in order.iter() {
+                    let pings = pings.clone();
+                    let cosmetic = false;
+                    let ping = Ping { name, lane, cosmetic, pings };
+                    time.enqueue_absolute(ping, at);
+                }
+                recv
+            }, clock);
+
+        clock.advance(units::moments(2));
+        let wait = time::Duration::from_secs(1);
+        let mut names = Vec::new();
+        for _ in 0..5 {
+            names.push(pings.recv_timeout(wait).expect("event never ran"));
+        }
+        assert_eq!(names, vec!["c", "e", "a", "d", "b"]);
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    // players scattered at random, far enough apart not to start out
+    // touching, and sent running at each other, so that plenty of contacts
+    // happen at the same time as each other
+    fn scenario(seed: u32) -> u64 {
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, mut clock, handle, ()) =
+            server::start_server_with_clock(move |space, time, matter| {
+                let mut rng = rand::XorShiftRng::from_seed([seed, 1, 2, 3]);
+                let mut cells: Vec<(i32, i32)> = (-8..8)
+                    .flat_map(|x| (-8..8).map(move |y| (x, y)))
+                    .collect();
+                rng.shuffle(&mut cells);
+                for &(x, y) in &cells[..20] {
+                    // twice as far apart as a player is wide
+                    let (x, y) = (x * 40, y * 40);
+                    let offset = units::Displacement {
+                        x: x.into(),
+                        y: y.into(),
+                    };
+                    let (send, _) = mpsc::channel();
+                    let position = units::Position::default() + offset;
+                    let appearance = Default::default();
+                    let id = player::Player::new(
+                        space, time, matter, position, appearance, send,
+                    ).id();
+                    let velocity = units::Velocity {
+                        x: (-x / 4).into(),
+                        y: (-y / 4).into(),
+                    };
+                    let pace = player::Pace::Run;
+                    let control = player::Control::Move { velocity, pace };
+                    player::Control::apply(space, time, matter, id, control);
+                }
+            }, clock);
+
+        clock.advance(units::moments(64));
+        let (reply, recv) = mpsc::channel();
+        upd.send(server::Interruption::ListEntities { reply }).unwrap();
+        let entities = recv.recv().expect("server didn't list entities");
+
+        let mut hasher = hash_map::DefaultHasher::new();
+        format!("{:?}", entities).hash(&mut hasher);
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+        hasher.finish()
+    }
+
+    #[test]
+    fn seeded_scenarios_replay_identically() {
+        for seed in 1..4 {
+            assert_eq!(scenario(seed), scenario(seed));
+        }
+    }
+}
@@ -0,0 +1,849 @@
+use std::io;
+use std::io::BufRead;
+use std::sync::mpsc;
+use std::thread;
+
+use blueprints;
+use construction;
+use entities;
+use entities::player;
+use space;
+use space::zones;
+use sulphate;
+use sulphate::server;
+use units;
+use worldgen;
+
+/// The parts of an interruption that can be written down and replayed.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Replayable {
+    PlayerUpdate {
+        id: sulphate::EntityId,
+        control: player::Control,
+    },
+    PlayerCast {
+        id: sulphate::EntityId,
+        target: units::Position,
+    },
+    PlayerInteract {
+        id: sulphate::EntityId,
+    },
+    PlayerDrop {
+        id: sulphate::EntityId,
+        slot: usize,
+    },
+    /// A player that joined, made again without anyone to control it.
+    Spawn {
+        appearance: player::Appearance,
+        position: units::Position,
+    },
+    /// Whatever a blueprint made, and whether a player it made was given
+    /// someone to send its updates to.
+    SpawnBlueprint {
+        blueprint: blueprints::Name,
+        position: units::Position,
+        controlled: bool,
+    },
+    RemovePlayer {
+        id: sulphate::EntityId,
+    },
+    Despawn {
+        id: sulphate::EntityId,
+    },
+    PlaceBuilding {
+        blueprint: blueprints::Name,
+        position: units::Position,
+        by: Option<sulphate::EntityId>,
+    },
+    BuildRoad {
+        from: units::Position,
+        to: units::Position,
+        by: Option<sulphate::EntityId>,
+    },
+    LayPipe {
+        from: units::Position,
+        to: units::Position,
+        by: Option<sulphate::EntityId>,
+    },
+    PaintZone {
+        from: units::Position,
+        to: units::Position,
+        zone: Option<zones::Zone>,
+        by: Option<sulphate::EntityId>,
+    },
+    Undo {
+        id: sulphate::EntityId,
+    },
+    Redo {
+        id: sulphate::EntityId,
+    },
+    SetTimeScale {
+        factor: units::Scalar,
+    },
+    PauseWorld {
+        paused: bool,
+    },
+    /// Construction done all at once, as `construction::batch` does it.
+    Batch(Vec<Replayable>),
+    KillServer,
+}
+
+/// An interruption along with the game time that it was applied.
+pub type Record = (units::Time, Replayable);
+
+impl Replayable {
+    pub fn into_interruption(self: Self) -> server::Interruption {
+        use self::Replayable::*;
+        match self {
+            PlayerUpdate { id, control } =>
+                server::Interruption::PlayerUpdate { id, control },
+            PlayerCast { id, target } =>
+                server::Interruption::PlayerCast { id, target },
+            PlayerInteract { id } =>
+                server::Interruption::PlayerInteract { id },
+            PlayerDrop { id, slot } =>
+                server::Interruption::PlayerDrop { id, slot },
+            Spawn { appearance, position } => {
+                // nobody is there to hear from the player,
+                // or to take over its session
+                let (update, _) = mpsc::channel();
+                let (reply, _) = mpsc::channel();
+                server::Interruption::Join {
+                    appearance,
+                    position,
+                    update,
+                    reply,
+                }
+            },
+            SpawnBlueprint { blueprint, position, controlled } => {
+                let update = if controlled {
+                    Some(mpsc::channel().0)
+                } else {
+                    None
+                };
+                let (reply, _) = mpsc::channel();
+                server::Interruption::Spawn {
+                    blueprint,
+                    position,
+                    update,
+                    reply,
+                }
+            },
+            RemovePlayer { id } =>
+                server::Interruption::RemovePlayer { id },
+            Despawn { id } => server::Interruption::Despawn { id },
+            PlaceBuilding { blueprint, position, by } => {
+                // whether there was room comes out the same as before, so
+                // there is nothing to hear back
+                let (reply, _) = mpsc::channel();
+                server::Interruption::PlaceBuilding {
+                    blueprint,
+                    position,
+                    by,
+                    reply,
+                }
+            },
+            BuildRoad { from, to, by } => {
+                let (reply, _) = mpsc::channel();
+                server::Interruption::BuildRoad { from, to, by, reply }
+            },
+            LayPipe { from, to, by } => {
+                let (reply, _) = mpsc::channel();
+                server::Interruption::LayPipe { from, to, by, reply }
+            },
+            PaintZone { from, to, zone, by } => {
+                let (reply, _) = mpsc::channel();
+                server::Interruption::PaintZone { from, to, zone, by, reply }
+            },
+            Undo { id } => server::Interruption::Undo { id },
+            Redo { id } => server::Interruption::Redo { id },
+            SetTimeScale { factor } =>
+                server::Interruption::SetTimeScale { factor },
+            PauseWorld { paused } =>
+                server::Interruption::PauseWorld { paused },
+            Batch(batch) => server::Interruption::Batch(
+                batch.into_iter().map(Replayable::into_interruption).collect()
+            ),
+            KillServer => server::Interruption::KillServer,
+        }
+    }
+}
+
+/// The steps that `batch` takes, along with who they are all put down to,
+/// or None if any of them isn't construction or they aren't all put down
+/// to the same player.
+pub fn batch_steps(
+    batch: &[Replayable],
+) -> Option<(Option<sulphate::EntityId>, Vec<construction::Step>)> {
+    use self::Replayable::*;
+    let mut put_to = None;
+    let mut steps = Vec::new();
+    for (n, what) in batch.iter().enumerate() {
+        let (by, step) = match *what {
+            PlaceBuilding { blueprint, position, by } => {
+                (by, construction::Step::Place { blueprint, position })
+            },
+            BuildRoad { from, to, by } => {
+                (by, construction::Step::Road { from, to })
+            },
+            LayPipe { from, to, by } => {
+                (by, construction::Step::Pipe { from, to })
+            },
+            PaintZone { from, to, zone, by } => {
+                (by, construction::Step::Zone { from, to, zone })
+            },
+            _ => return None,
+        };
+        if n > 0 && by != put_to {
+            return None;
+        }
+        put_to = by;
+        steps.push(step);
+    }
+    Some((put_to, steps))
+}
+
+// construction is followed by whoever it is put down to, if anyone
+fn write_by(
+    by: Option<sulphate::EntityId>,
+    out: &mut io::Write,
+) -> io::Result<()> {
+    match by {
+        Some(id) => write!(out, " by {}", id),
+        None => Ok(()),
+    }
+}
+
+// fixed point values are written as their bits so that nothing is rounded
+pub fn write_log(log: &[Record], out: &mut io::Write) -> io::Result<()> {
+    for &(time, ref what) in log {
+        write_record(time, what, out)?;
+    }
+    Ok(())
+}
+
+fn write_record(
+    time: units::Time,
+    what: &Replayable,
+    out: &mut io::Write,
+) -> io::Result<()> {
+    write!(out, "{}", time.into_bits())?;
+    use self::Replayable::*;
+    match *what {
+        PlayerUpdate { id, ref control } => match *control {
+            player::Control::Move { velocity, pace } => write!(
+                out, " update {} move {} {} {}", id,
+                velocity.x.into_bits(), velocity.y.into_bits(),
+                pace.tag(),
+            )?,
+        },
+        PlayerCast { id, target } => write!(
+            out, " cast {} {} {}", id,
+            target.x.into_bits(), target.y.into_bits(),
+        )?,
+        PlayerInteract { id } => write!(out, " interact {}", id)?,
+        PlayerDrop { id, slot } => write!(out, " drop {} {}", id, slot)?,
+        // names are always one word, so it can go last
+        Spawn { ref appearance, position } => {
+            let color = appearance.color;
+            write!(
+                out, " spawn {} {} {} {} {} {}",
+                position.x.into_bits(), position.y.into_bits(),
+                color[0], color[1], color[2], appearance.name,
+            )?
+        },
+        SpawnBlueprint { blueprint, position, controlled } => write!(
+            out, " make {} {} {} {}", blueprint,
+            position.x.into_bits(), position.y.into_bits(), controlled,
+        )?,
+        RemovePlayer { id } => write!(out, " remove {}", id)?,
+        Despawn { id } => write!(out, " despawn {}", id)?,
+        PlaceBuilding { blueprint, position, by } => {
+            write!(
+                out, " build {} {} {}", blueprint,
+                position.x.into_bits(), position.y.into_bits(),
+            )?;
+            write_by(by, out)?;
+        },
+        BuildRoad { from, to, by } => {
+            write!(
+                out, " road {} {} {} {}",
+                from.x.into_bits(), from.y.into_bits(),
+                to.x.into_bits(), to.y.into_bits(),
+            )?;
+            write_by(by, out)?;
+        },
+        LayPipe { from, to, by } => {
+            write!(
+                out, " pipe {} {} {} {}",
+                from.x.into_bits(), from.y.into_bits(),
+                to.x.into_bits(), to.y.into_bits(),
+            )?;
+            write_by(by, out)?;
+        },
+        PaintZone { from, to, zone, by } => {
+            write!(
+                out, " zone {} {} {} {} {}",
+                from.x.into_bits(), from.y.into_bits(),
+                to.x.into_bits(), to.y.into_bits(),
+                zone.map_or(zones::NO_ZONE, zones::Zone::tag),
+            )?;
+            write_by(by, out)?;
+        },
+        Undo { id } => write!(out, " undo {}", id)?,
+        Redo { id } => write!(out, " redo {}", id)?,
+        SetTimeScale { factor } =>
+            write!(out, " scale {}", factor.into_bits())?,
+        PauseWorld { paused } => write!(out, " pause {}", paused)?,
+        Batch(ref batch) => {
+            // followed by each of its steps, one to a line
+            writeln!(out, " batch {}", batch.len())?;
+            for what in batch {
+                write_record(time, what, out)?;
+            }
+            return Ok(());
+        },
+        KillServer => write!(out, " kill")?,
+    }
+    writeln!(out, "")
+}
+
+fn bad_line(line: &str) -> io::Error {
+    let msg = format!("malformed replay entry: {}", line);
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn parse_record(line: &str) -> Option<Record> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let time = units::Time::from_bits(words.get(0)?.parse().ok()?);
+    let int = |n: usize| -> Option<i64> { words.get(n)?.parse().ok() };
+    let id = || -> Option<sulphate::EntityId> { words.get(2)?.parse().ok() };
+    // logs from before there was undoing put construction down to nobody
+    let by = |n: usize| -> Option<Option<sulphate::EntityId>> {
+        match words.get(n) {
+            Some(&"by") => Some(Some(words.get(n + 1)?.parse().ok()?)),
+            Some(_) => None,
+            None => Some(None),
+        }
+    };
+
+    let what = match *words.get(1)? {
+        "update" if words.get(3) == Some(&"move") => {
+            let x = units::Scalar::from_bits(int(4)?);
+            let y = units::Scalar::from_bits(int(5)?);
+            let velocity = units::Velocity { x, y };
+            // logs from before there were paces only ever ran
+            let pace = match words.get(6) {
+                Some(tag) => player::Pace::from_tag(tag.parse().ok()?)?,
+                None => player::Pace::Run,
+            };
+            let control = player::Control::Move { velocity, pace };
+            Replayable::PlayerUpdate { id: id()?, control }
+        },
+        "cast" => {
+            let x = units::Coord::from_bits(int(3)?);
+            let y = units::Coord::from_bits(int(4)?);
+            let target = units::Position { x, y };
+            Replayable::PlayerCast { id: id()?, target }
+        },
+        "interact" => Replayable::PlayerInteract { id: id()? },
+        "drop" => {
+            let slot = words.get(3)?.parse().ok()?;
+            Replayable::PlayerDrop { id: id()?, slot }
+        },
+        "spawn" => {
+            let x = units::Coord::from_bits(int(2)?);
+            let y = units::Coord::from_bits(int(3)?);
+            let position = units::Position { x, y };
+            let byte = |n: usize| -> Option<u8> { words.get(n)?.parse().ok() };
+            let color = [byte(4)?, byte(5)?, byte(6)?];
+            let appearance = player::Appearance::new(words.get(7)?, color);
+            Replayable::Spawn { appearance, position }
+        },
+        "make" => {
+            let blueprint = blueprints::read_name(words.get(2)?)?;
+            let x = units::Coord::from_bits(int(3)?);
+            let y = units::Coord::from_bits(int(4)?);
+            let position = units::Position { x, y };
+            let controlled = words.get(5)?.parse().ok()?;
+            Replayable::SpawnBlueprint { blueprint, position, controlled }
+        },
+        "remove" => Replayable::RemovePlayer { id: id()? },
+        "despawn" => Replayable::Despawn { id: id()? },
+        // logs from before there were blueprints wrote the tag of each
+        // kind of building, which reads as its blueprint
+        "build" => {
+            let blueprint = blueprints::read_name(words.get(2)?)?;
+            let x = units::Coord::from_bits(int(3)?);
+            let y = units::Coord::from_bits(int(4)?);
+            let position = units::Position { x, y };
+            Replayable::PlaceBuilding { blueprint, position, by: by(5)? }
+        },
+        "road" => {
+            let from = units::Position {
+                x: units::Coord::from_bits(int(2)?),
+                y: units::Coord::from_bits(int(3)?),
+            };
+            let to = units::Position {
+                x: units::Coord::from_bits(int(4)?),
+                y: units::Coord::from_bits(int(5)?),
+            };
+            Replayable::BuildRoad { from, to, by: by(6)? }
+        },
+        "pipe" => {
+            let from = units::Position {
+                x: units::Coord::from_bits(int(2)?),
+                y: units::Coord::from_bits(int(3)?),
+            };
+            let to = units::Position {
+                x: units::Coord::from_bits(int(4)?),
+                y: units::Coord::from_bits(int(5)?),
+            };
+            Replayable::LayPipe { from, to, by: by(6)? }
+        },
+        "zone" => {
+            let from = units::Position {
+                x: units::Coord::from_bits(int(2)?),
+                y: units::Coord::from_bits(int(3)?),
+            };
+            let to = units::Position {
+                x: units::Coord::from_bits(int(4)?),
+                y: units::Coord::from_bits(int(5)?),
+            };
+            let zone = match words.get(6)?.parse().ok()? {
+                zones::NO_ZONE => None,
+                tag => Some(zones::Zone::from_tag(tag)?),
+            };
+            Replayable::PaintZone { from, to, zone, by: by(7)? }
+        },
+        "undo" => Replayable::Undo { id: id()? },
+        "redo" => Replayable::Redo { id: id()? },
+        "scale" => {
+            let factor = units::Scalar::from_bits(int(2)?);
+            Replayable::SetTimeScale { factor }
+        },
+        "pause" => {
+            let paused = words.get(2)?.parse().ok()?;
+            Replayable::PauseWorld { paused }
+        },
+        "kill" => Replayable::KillServer,
+        _ => return None,
+    };
+    Some((time, what))
+}
+
+pub fn read_log(input: &mut io::BufRead) -> io::Result<Vec<Record>> {
+    let mut log = Vec::new();
+    let mut lines = input.lines();
+    while let Some(record) = read_record(&mut lines)? {
+        log.push(record);
+    }
+    Ok(log)
+}
+
+// the next record in `lines`, or None once there are none left
+fn read_record(
+    lines: &mut Iterator<Item = io::Result<String>>,
+) -> io::Result<Option<Record>> {
+    let mut line = String::new();
+    while line.trim().is_empty() {
+        line = match lines.next() {
+            Some(line) => line?,
+            None => return Ok(None),
+        };
+    }
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if words.get(1) != Some(&"batch") {
+        let record = parse_record(&line).ok_or_else(|| bad_line(&line))?;
+        return Ok(Some(record));
+    }
+    // followed by as many records as it says, one for each step
+    let header = || -> Option<(units::Time, usize)> {
+        let time = units::Time::from_bits(words.get(0)?.parse().ok()?);
+        Some((time, words.get(2)?.parse().ok()?))
+    };
+    let (time, count) = header().ok_or_else(|| bad_line(&line))?;
+    let mut batch = Vec::new();
+    for _ in 0..count {
+        match read_record(lines)? {
+            Some((_, what)) => batch.push(what),
+            None => return Err(bad_line(&line)),
+        }
+    }
+    Ok(Some((time, Replayable::Batch(batch))))
+}
+
+// replays an interruption from inside the event queue,
+// only the ones that change the world can be applied this way
+struct ReplayEvent(Replayable);
+
+impl sulphate::Event for ReplayEvent {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        use self::Replayable::*;
+        match self.0 {
+            PlayerUpdate { id, control } => {
+                player::Control::apply(space, time, matter, id, control);
+            },
+            PlayerCast { id, target } => {
+                player::cast(space, time, matter, id, target);
+            },
+            PlayerInteract { id } => {
+                player::interact(space, time, matter, id);
+            },
+            PlayerDrop { id, slot } => {
+                player::drop_item(space, time, matter, id, slot);
+            },
+            Spawn { appearance, position } => {
+                // there are no sessions to open out here
+                let position = worldgen::spawn_point(matter, position);
+                let (update, _) = mpsc::channel();
+                player::Player::new(
+                    space, time, matter, position, appearance, update,
+                );
+            },
+            SpawnBlueprint { blueprint, position, controlled } => {
+                let update = if controlled {
+                    Some(mpsc::channel().0)
+                } else {
+                    None
+                };
+                let _ = blueprints::spawn(
+                    space, time, matter, blueprint, position, update,
+                );
+            },
+            RemovePlayer { id } => {
+                player::Player::remove(space, time, matter, id);
+            },
+            Despawn { id } => {
+                entities::despawn(space, time, matter, id);
+            },
+            PlaceBuilding { blueprint, position, by } => {
+                construction::place(
+                    space, time, matter, by, blueprint, position,
+                );
+            },
+            BuildRoad { from, to, by } => {
+                construction::lay_road(space, time, matter, by, from, to);
+            },
+            LayPipe { from, to, by } => {
+                construction::lay_pipe(space, time, matter, by, from, to);
+            },
+            PaintZone { from, to, zone, by } => {
+                construction::zone(space, time, matter, by, from, to, zone);
+            },
+            Undo { id } => {
+                construction::undo(space, time, matter, id);
+            },
+            Redo { id } => {
+                construction::redo(space, time, matter, id);
+            },
+            Batch(batch) => {
+                if let Some((by, steps)) = batch_steps(&batch) {
+                    construction::batch(space, time, matter, by, &steps);
+                }
+            },
+            // these only change how fast the replay is watched,
+            // and stopping is up to whoever is watching
+            SetTimeScale { .. } | PauseWorld { .. } | KillServer => (),
+        }
+    }
+}
+
+/// Starts a server from `f` on the real clock, with every recorded
+/// interruption scheduled as an event at the time it was originally applied,
+/// so that the session plays out again as it is watched.
+pub fn start_replay_server<F, R>(f: F, log: Vec<Record>) -> (
+    mpsc::Sender<server::Interruption>,
+    server::Clock,
+    thread::JoinHandle<()>,
+    R,
+)
+    where F: Send + 'static
+           + FnOnce(
+                 &mut space::CollisionSpace,
+                 &mut sulphate::EventQueue,
+                 &mut sulphate::EntityHeap,
+             ) -> R,
+          R: Send + 'static,
+{
+    server::start_server(move |space, time, matter| {
+        let r = f(space, time, matter);
+        for (when, what) in log {
+            sulphate::enqueue_absolute(time, ReplayEvent(what), when);
+        }
+        r
+    })
+}
+
+// round trip through the server so that we know it is done with everything
+// sent so far, before the clock moves on
+fn sync(upd: &mpsc::Sender<server::Interruption>) -> bool {
+    let (reply, recv) = mpsc::channel();
+    let request = server::Interruption::RequestSnapshot { reply };
+    upd.send(request).is_ok() && recv.recv().is_ok()
+}
+
+/// Starts a server from `f` on a manual clock, and applies each recorded
+/// interruption at exactly the time it was originally applied.
+///
+/// The server is left running at the time of the last record, unless the
+/// log ends by killing it.
+pub fn replay<F, R>(f: F, log: Vec<Record>) -> (
+    mpsc::Sender<server::Interruption>,
+    server::ManualClock,
+    thread::JoinHandle<()>,
+    R,
+)
+    where F: Send + 'static
+           + FnOnce(
+                 &mut space::CollisionSpace,
+                 &mut sulphate::EventQueue,
+                 &mut sulphate::EntityHeap,
+             ) -> R,
+          R: Send + 'static,
+{
+    let clock = server::ManualClock::new(Default::default());
+    let (upd, mut clock, handle, r) = server::start_server_with_clock(f, clock);
+
+    for (time, what) in log {
+        // the records are in game time already, so scale and pauses
+        // that were replayed mustn't apply to them again
+        clock.set_to(time);
+
+        let kill = what == Replayable::KillServer;
+        let sent = upd.send(what.into_interruption()).is_ok();
+        if kill || !sent || !sync(&upd) {
+            break;
+        }
+    }
+
+    (upd, clock, handle, r)
+}
+
+#[cfg(test)]
+mod test_replay {
+    use std::sync::mpsc;
+
+    use entities::building;
+    use entities::player;
+    use sulphate;
+    use sulphate::server;
+    use units;
+
+    use super::*;
+
+    fn init(
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) -> sulphate::EntityId {
+        let (send, recv) = mpsc::channel();
+        let position = Default::default();
+        let appearance = Default::default();
+        player::Player::new(space, time, matter, position, appearance, send);
+        match recv.recv().expect("player never sent update").what {
+            player::UpdateData::Created { id, .. } => id,
+            _ => panic!("player didn't send Created update first"),
+        }
+    }
+
+    fn snapshot(
+        upd: &mpsc::Sender<server::Interruption>,
+    ) -> sulphate::WorldSnapshot {
+        let (reply, recv) = mpsc::channel();
+        upd.send(server::Interruption::RequestSnapshot { reply }).unwrap();
+        recv.recv().expect("server didn't reply with snapshot")
+    }
+
+    fn walk(
+        upd: &mpsc::Sender<server::Interruption>,
+        id: sulphate::EntityId,
+        x: i32,
+        y: i32,
+    ) {
+        let velocity = units::Velocity { x: x.into(), y: y.into() };
+        let pace = player::Pace::Run;
+        let control = player::Control::Move { velocity, pace };
+        upd.send(server::Interruption::PlayerUpdate { id, control }).unwrap();
+        // the snapshot is only there to wait for the update to be applied
+        snapshot(upd);
+    }
+
+    #[test]
+    fn replay_matches_original() {
+        let clock = server::ManualClock::new(Default::default());
+        let (upd, mut clock, handle, id) =
+            server::start_server_with_clock(init, clock);
+
+        let (log_send, log_recv) = mpsc::channel();
+        let log = server::Interruption::StartRecording { log: log_send };
+        upd.send(log).unwrap();
+
+        let mut originals = Vec::new();
+        walk(&upd, id, 30, 0);
+        clock.advance(units::moments(5));
+        originals.push(snapshot(&upd));
+        walk(&upd, id, 0, -20);
+        clock.advance(units::moments(7));
+        originals.push(snapshot(&upd));
+        walk(&upd, id, -10, 10);
+        clock.advance(units::moments(3));
+        originals.push(snapshot(&upd));
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+
+        let log: Vec<Record> = log_recv.iter().collect();
+        assert_eq!(log.len(), 4);
+
+        // make sure the log survives being written down
+        let mut text = Vec::new();
+        write_log(&log, &mut text).unwrap();
+        let reread = read_log(&mut &text[..]).unwrap();
+        assert_eq!(reread, log);
+
+        // stop short of the kill and the final advance
+        let mut partial = log.clone();
+        partial.pop();
+        let (upd, mut clock, handle, replay_id) = replay(init, partial);
+        assert!(replay_id == id);
+
+        clock.advance(units::moments(3));
+        let replayed = snapshot(&upd);
+        assert!(replayed == *originals.last().unwrap());
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn replay_keeps_time_through_scale_and_pauses() {
+        let clock = server::ManualClock::new(Default::default());
+        let (upd, mut clock, handle, id) =
+            server::start_server_with_clock(init, clock);
+
+        let (log_send, log_recv) = mpsc::channel();
+        let log = server::Interruption::StartRecording { log: log_send };
+        upd.send(log).unwrap();
+
+        walk(&upd, id, 30, 0);
+        let factor = 2.into();
+        upd.send(server::Interruption::SetTimeScale { factor }).unwrap();
+        snapshot(&upd);
+        clock.advance(units::moments(5));
+        let paused = true;
+        upd.send(server::Interruption::PauseWorld { paused }).unwrap();
+        snapshot(&upd);
+        clock.advance(units::moments(3));
+        walk(&upd, id, 0, -20);
+        let paused = false;
+        upd.send(server::Interruption::PauseWorld { paused }).unwrap();
+        snapshot(&upd);
+        clock.advance(units::moments(4));
+        walk(&upd, id, -10, 10);
+        clock.advance(units::moments(2));
+        let original = snapshot(&upd);
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+
+        let mut log: Vec<Record> = log_recv.iter().collect();
+        // stop short of the kill and the final advance
+        log.pop();
+        let (upd, mut clock, handle, _) = replay(init, log);
+
+        clock.advance(units::moments(2));
+        assert!(snapshot(&upd) == original);
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn replay_makes_players_that_joined() {
+        let clock = server::ManualClock::new(Default::default());
+        let (upd, mut clock, handle, id) =
+            server::start_server_with_clock(init, clock);
+
+        let (log_send, log_recv) = mpsc::channel();
+        let log = server::Interruption::StartRecording { log: log_send };
+        upd.send(log).unwrap();
+
+        walk(&upd, id, 30, 0);
+        clock.advance(units::moments(5));
+        // far enough away not to start out touching the first player
+        let position = units::Position::default()
+            + units::Displacement { x: 100.into(), y: 0.into() };
+        let (update, _updates) = mpsc::channel();
+        let (reply, joined) = mpsc::channel();
+        upd.send(server::Interruption::Join {
+            appearance: player::Appearance::named("visitor"),
+            position,
+            update,
+            reply,
+        }).unwrap();
+        let (_, visitor) = joined.recv().expect("server didn't reply");
+        clock.advance(units::moments(3));
+        walk(&upd, visitor, 0, 20);
+        clock.advance(units::moments(4));
+        upd.send(server::Interruption::RemovePlayer { id }).unwrap();
+        snapshot(&upd);
+        clock.advance(units::moments(2));
+        let original = snapshot(&upd);
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+
+        let mut log: Vec<Record> = log_recv.iter().collect();
+        let mut text = Vec::new();
+        write_log(&log, &mut text).unwrap();
+        assert_eq!(read_log(&mut &text[..]).unwrap(), log);
+
+        // stop short of the kill and the final advance
+        log.pop();
+        let (upd, mut clock, handle, _) = replay(init, log);
+
+        clock.advance(units::moments(2));
+        assert!(snapshot(&upd) == original);
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn batches_read_back_as_they_were_written() {
+        let from = units::Position::default();
+        let to = from + units::Displacement { x: 0.into(), y: 100.into() };
+        let batch = Replayable::Batch(vec![
+            Replayable::BuildRoad { from, to, by: Some(3) },
+            Replayable::PlaceBuilding {
+                blueprint: building::Blueprint::House.into(),
+                position: to,
+                by: Some(3),
+            },
+        ]);
+        let log = vec![
+            (Default::default(), batch),
+            (Default::default(), Replayable::Undo { id: 3 }),
+        ];
+        let mut text = Vec::new();
+        write_log(&log, &mut text).unwrap();
+        assert_eq!(read_log(&mut &text[..]).unwrap(), log);
+
+        let steps = match log[0].1 {
+            Replayable::Batch(ref batch) => batch_steps(batch),
+            _ => unreachable!(),
+        };
+        let (by, steps) = steps.expect("batch wasn't all construction");
+        assert_eq!(by, Some(3));
+        assert_eq!(steps[0], construction::Step::Road { from, to });
+
+        // one that says it has more steps than it does
+        assert!(read_log(&mut &b"0 batch 2\n0 undo 3\n"[..]).is_err());
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections;
+
+use rand;
+
+use sulphate;
+
+/// What a world is seeded with unless it is given something else, so that
+/// every fresh world plays out the same given the same interruptions.
+pub const DEFAULT_SEED: u64 = 0x6c69_6c63;
+
+/// Randomness for gameplay that comes out the same every time the world is
+/// replayed, kept in the heap as the only entity of its type, like the
+/// ledger.
+///
+/// Each entity draws from a stream of its own, worked out from the seed,
+/// its id, and how many numbers it has drawn so far, so that the order
+/// events happen in can't change what any other entity gets.
+pub struct Rng {
+    seed: u64,
+    drawn: collections::BTreeMap<sulphate::EntityId, u64>,
+}
+
+const RNG: sulphate::EntityId = 0;
+
+impl Rng {
+    pub fn seed(self: &Self) -> u64 {
+        self.seed
+    }
+
+    /// How many numbers each entity has drawn, for saving.
+    pub fn drawn(self: &Self) -> Vec<(sulphate::EntityId, u64)> {
+        self.drawn.iter().map(|(&id, &count)| (id, count)).collect()
+    }
+}
+
+/// Seeds the world's randomness, starting every stream over.
+pub fn seed(matter: &mut sulphate::EntityHeap, seed: u64) {
+    restore(matter, seed, Vec::new());
+}
+
+/// Puts back randomness that was saved, so that each stream carries on
+/// from where it was.
+pub fn restore(
+    matter: &mut sulphate::EntityHeap,
+    seed: u64,
+    drawn: Vec<(sulphate::EntityId, u64)>,
+) {
+    matter.remove::<Rng>(RNG);
+    let drawn = drawn.into_iter().collect();
+    matter.insert(RNG, Rng { seed, drawn });
+}
+
+fn open(matter: &mut sulphate::EntityHeap) -> &mut Rng {
+    if matter.get::<Rng>(RNG).is_none() {
+        seed(matter, DEFAULT_SEED);
+    }
+    matter.get_mut::<Rng>(RNG).expect("Rng wasn't seeded")
+}
+
+/// The world's randomness, or None if nothing has drawn from it or seeded
+/// it yet.
+pub fn get(matter: &sulphate::EntityHeap) -> Option<&Rng> {
+    matter.get::<Rng>(RNG)
+}
+
+/// The stream of random numbers belonging to `id`.
+pub fn stream(
+    matter: &mut sulphate::EntityHeap,
+    id: sulphate::EntityId,
+) -> Stream {
+    let rng = open(matter);
+    let seed = rng.seed;
+    let drawn = rng.drawn.entry(id).or_insert(0);
+    Stream { seed, id, drawn }
+}
+
+/// Drops the stream of an entity that is gone.
+pub fn forget(matter: &mut sulphate::EntityHeap, id: sulphate::EntityId) {
+    if let Some(rng) = matter.get_mut::<Rng>(RNG) {
+        rng.drawn.remove(&id);
+    }
+}
+
+/// Scrambles consecutive numbers into unrelated ones, using splitmix64, for
+/// anything else that needs randomness worked out from a seed.
+pub fn mix(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// One entity's share of the world's randomness.
+pub struct Stream<'a> {
+    seed: u64,
+    id: sulphate::EntityId,
+    drawn: &'a mut u64,
+}
+
+impl<'a> rand::Rng for Stream<'a> {
+    fn next_u32(self: &mut Self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(self: &mut Self) -> u64 {
+        let count = *self.drawn;
+        *self.drawn += 1;
+        mix(mix(self.seed ^ mix(self.id)) ^ count)
+    }
+}
+
+#[cfg(test)]
+mod test_rng {
+    use rand::Rng;
+
+    use sulphate;
+
+    use super::*;
+
+    fn draws(
+        matter: &mut sulphate::EntityHeap,
+        id: sulphate::EntityId,
+    ) -> Vec<u32> {
+        let mut stream = stream(matter, id);
+        (0..4).map(|_| stream.gen_range(0, 1000)).collect()
+    }
+
+    #[test]
+    fn streams_ignore_each_other() {
+        let mut matter = sulphate::EntityHeap::new();
+        seed(&mut matter, 7);
+        let first = draws(&mut matter, 1);
+        let second = draws(&mut matter, 2);
+        let then = draws(&mut matter, 1);
+
+        // drawing for 2 first makes no difference to what 1 gets
+        let mut other = sulphate::EntityHeap::new();
+        seed(&mut other, 7);
+        assert_eq!(draws(&mut other, 2), second);
+        assert_eq!(draws(&mut other, 1), first);
+        assert_eq!(draws(&mut other, 1), then);
+        assert!(first != then);
+
+        seed(&mut other, 8);
+        assert!(draws(&mut other, 1) != first);
+    }
+
+    #[test]
+    fn restored_streams_carry_on() {
+        let mut matter = sulphate::EntityHeap::new();
+        seed(&mut matter, 7);
+        draws(&mut matter, 1);
+        let (seed, drawn) = {
+            let rng = get(&matter).unwrap();
+            (rng.seed(), rng.drawn())
+        };
+        let next = draws(&mut matter, 1);
+
+        let mut restored = sulphate::EntityHeap::new();
+        restore(&mut restored, seed, drawn);
+        assert_eq!(draws(&mut restored, 1), next);
+    }
+}
@@ -1,65 +1,1136 @@
+use std::any;
+use std::cmp;
+use std::i64;
+use std::io;
+use std::mem;
+use std::panic;
+use std::path;
+use std::sync;
 use std::sync::mpsc;
 use std::time;
 use std::thread;
 
 use sulphate_lib::server;
 
+use blueprints;
+use budget;
+use demand;
+use config;
+use construction;
+use economy;
+use entities;
 use entities::player;
+use logging;
+use mods;
 use space;
+use space::ground;
+use space::roads;
+use space::zones;
 use sulphate;
+use sulphate::autosave;
+use sulphate::catch_up;
+use sulphate::chat;
+use sulphate::crash;
+use sulphate::inbox;
+use sulphate::metrics;
+use sulphate::persist;
+use sulphate::replay;
+use sulphate::session;
+use sulphate::snapshot;
 use units;
+use world::calendar;
+use worldgen;
 
 pub enum Interruption {
     PlayerUpdate {
         id: sulphate::EntityId,
         control: player::Control
     },
+    PlayerCast {
+        id: sulphate::EntityId,
+        target: units::Position,
+    },
+    /// Interact with whatever is nearest to player `id`, or get out of
+    /// whatever it is riding.
+    PlayerInteract {
+        id: sulphate::EntityId,
+    },
+    /// Put down whatever player `id` is carrying in `slot` of its
+    /// inventory.
+    PlayerDrop {
+        id: sulphate::EntityId,
+        slot: usize,
+    },
+    /// Add a player that looks like `appearance`, and open a session under
+    /// its name that can control it.
+    Join {
+        appearance: player::Appearance,
+        position: units::Position,
+        update: mpsc::Sender<player::Update>,
+        reply: mpsc::Sender<(session::SessionToken, sulphate::EntityId)>,
+    },
+    // like the player variants, but only applied to the player that the
+    // session owns, for when whoever is sending them isn't trusted
+    SessionUpdate {
+        session: session::SessionToken,
+        control: player::Control,
+    },
+    SessionCast {
+        session: session::SessionToken,
+        target: units::Position,
+    },
+    SessionInteract {
+        session: session::SessionToken,
+    },
+    SessionDrop {
+        session: session::SessionToken,
+        slot: usize,
+    },
+    SessionUndo {
+        session: session::SessionToken,
+    },
+    SessionRedo {
+        session: session::SessionToken,
+    },
+    SessionChat {
+        session: session::SessionToken,
+        text: String,
+    },
+    Leave {
+        session: session::SessionToken,
+    },
+    RemovePlayer {
+        id: sulphate::EntityId,
+    },
+    /// Make whatever the blueprint named `blueprint` makes at `position`,
+    /// replying with the new entity or why it couldn't be made. A player
+    /// is sent its updates through `update`.
+    Spawn {
+        blueprint: blueprints::Name,
+        position: units::Position,
+        update: Option<mpsc::Sender<player::Update>>,
+        reply: mpsc::Sender<Result<sulphate::EntityId, String>>,
+    },
+    Despawn {
+        id: sulphate::EntityId,
+    },
+    /// Build from the blueprint named `blueprint` on the tile under
+    /// `position`, replying with the new building, or None if something
+    /// was in the way or it isn't a building's blueprint. Paid for by
+    /// player `by`, who can undo it, or free if `by` is None.
+    PlaceBuilding {
+        blueprint: blueprints::Name,
+        position: units::Position,
+        by: Option<sulphate::EntityId>,
+        reply: mpsc::Sender<Option<sulphate::EntityId>>,
+    },
+    /// Lay road in a straight line along the grid between the tiles under
+    /// `from` and `to`, replying with whether it could be laid. Paid for
+    /// the same way as buildings.
+    BuildRoad {
+        from: units::Position,
+        to: units::Position,
+        by: Option<sulphate::EntityId>,
+        reply: mpsc::Sender<bool>,
+    },
+    /// Lay pipe for water between the tiles under `from` and `to`, the
+    /// same way as road.
+    LayPipe {
+        from: units::Position,
+        to: units::Position,
+        by: Option<sulphate::EntityId>,
+        reply: mpsc::Sender<bool>,
+    },
+    /// Zone the rectangle of tiles between the tiles under `from` and `to`,
+    /// or take the zoning off if `zone` is None, replying with how many
+    /// tiles were painted. Zoning is free, but player `by` can undo it.
+    PaintZone {
+        from: units::Position,
+        to: units::Position,
+        zone: Option<zones::Zone>,
+        by: Option<sulphate::EntityId>,
+        reply: mpsc::Sender<usize>,
+    },
+    /// Do every building, road, pipe and zoning in the batch as one, all
+    /// put down to the same player, so that either all of it goes ahead or
+    /// none of it does, and it is undone all at once. Each replies as it
+    /// would on its own, and the city has to be able to afford all of it
+    /// before any of it is done.
+    Batch(Vec<Interruption>),
+    /// Take back the last building, road, pipe or zoning that player `id`
+    /// did, refunding what it cost, unless something has been built that
+    /// depends on it since.
+    Undo {
+        id: sulphate::EntityId,
+    },
+    /// Do again whatever player `id` last undid, if nothing is in the way
+    /// of it now.
+    Redo {
+        id: sulphate::EntityId,
+    },
+    /// Whether something at `from` could be driven to from `to` by road.
+    RoadConnected {
+        from: units::Position,
+        to: units::Position,
+        reply: mpsc::Sender<bool>,
+    },
+    SetTimeScale {
+        factor: units::Scalar,
+    },
+    PauseWorld {
+        paused: bool,
+    },
+    /// Change what happens when the server falls behind, `None` to just let
+    /// it run late.
+    SetCatchUp {
+        policy: Option<catch_up::CatchUp>,
+    },
+    RequestSnapshot {
+        reply: mpsc::Sender<sulphate::WorldSnapshot>,
+    },
+    /// A snapshot of only what is within `radius` of player `id`, for a
+    /// client that has no need to know about the rest.
+    RequestSnapshotAround {
+        id: sulphate::EntityId,
+        radius: units::Distance,
+        reply: mpsc::Sender<sulphate::WorldSnapshot>,
+    },
+    /// The city's ground as it stands, for cutting into chunks for a
+    /// client that only has what it has looked at, away from the server's
+    /// own thread.
+    RequestGround {
+        reply: mpsc::Sender<ground::Ground>,
+    },
+    QueryMetrics {
+        reply: mpsc::Sender<metrics::ServerMetrics>,
+    },
+    /// What `World::checksum` comes to now.
+    QueryChecksum {
+        reply: mpsc::Sender<u64>,
+    },
+    /// A bounded way in for a remote client's interruptions, which tells
+    /// the server there is something in it through `wake`, the sender that
+    /// this is sent on.
+    OpenInbox {
+        wake: mpsc::Sender<Interruption>,
+        reply: mpsc::Sender<inbox::InboxSender>,
+    },
+    /// Sent through `wake` when something has been put in the inbox.
+    InboxReady,
+    /// How the city's books stood after the last economy tick.
+    QueryLedger {
+        reply: mpsc::Sender<economy::Balances>,
+    },
+    /// What the city took in and spent on each thing over the last `days`
+    /// game days, or as many as are kept.
+    QueryBudget {
+        days: usize,
+        reply: mpsc::Sender<budget::Report>,
+    },
+    /// How much the city wants more of each zone, and how happy its
+    /// citizens are, as of now.
+    QueryDemand {
+        reply: mpsc::Sender<demand::Demand>,
+    },
+    /// Which packs the world is running, for a client that wants to join.
+    QueryMods {
+        reply: mpsc::Sender<Vec<mods::Stamp>>,
+    },
+    /// Say `text` to everyone listening to the chat, as player `from`.
+    Chat {
+        from: sulphate::EntityId,
+        text: String,
+    },
+    /// Receive the last few lines of chat, then every line said from now
+    /// on, until `sender` is dropped.
+    ListenToChat {
+        sender: mpsc::Sender<chat::ChatLine>,
+    },
+    /// Sends the time and which it was at every dawn and dusk from now on.
+    WatchSky {
+        sender: mpsc::Sender<(units::Time, calendar::Sun)>,
+    },
+    ListEntities {
+        reply: mpsc::Sender<Vec<sulphate::EntitySummary>>,
+    },
+    /// Everything about the entity `id`, or None if there is no such
+    /// entity.
+    Inspect {
+        id: sulphate::EntityId,
+        reply: mpsc::Sender<Option<sulphate::EntityInfo>>,
+    },
+    /// Find a way around the terrain, for debugging what entities would
+    /// walk along.
+    FindPath {
+        from: units::Position,
+        to: units::Position,
+        reply: mpsc::Sender<Option<Vec<units::Position>>>,
+    },
+    /// Receive a view of the world once per moment, until `sender` is
+    /// dropped.
+    Subscribe {
+        sender: mpsc::Sender<sulphate::WorldView>,
+    },
+    StartRecording {
+        log: mpsc::Sender<replay::Record>,
+    },
+    StopRecording,
+    SaveTo(path::PathBuf),
+    /// Save the world every so often, in place of any autosaving already
+    /// going on. `wake` should be the sender that this is sent on.
+    StartAutosave {
+        settings: autosave::Settings,
+        wake: mpsc::Sender<Interruption>,
+    },
+    StopAutosave,
+    /// Read gameplay settings from the file at the path, and play by them
+    /// from now on.
+    ReloadConfig(path::PathBuf),
+    /// Run the mods and packs in the directory at the path from now on, in
+    /// place of whatever was running before, warning about any packs that
+    /// the world was saved with but won't be running.
+    LoadMods(path::PathBuf),
+    /// Sent by the server to itself when an autosave is due.
+    Autosave {
+        round: u64,
+    },
+    /// Be told when the server stops, including if it crashes.
+    WatchStatus {
+        sender: mpsc::Sender<crash::ServerStatus>,
+    },
+    /// Keep running until `deadline`, then stop and send back the world.
+    /// `wake` should be the sender that this is sent on.
+    Shutdown {
+        deadline: units::Time,
+        wake: mpsc::Sender<Interruption>,
+        reply: mpsc::Sender<sulphate::World>,
+    },
+    /// Sent by the server to itself once a shutdown's deadline has passed.
+    Drained {
+        reply: mpsc::Sender<sulphate::World>,
+    },
     KillServer,
 }
 
+impl Interruption {
+    /// Lays road dragged from the tile under `from` to the tile under `to`,
+    /// turning at `roads::corner` if it has to, as one batch so that the
+    /// whole drag is paid for, built and undone together. Each stretch
+    /// replies on `reply`, and they all succeed or fail as one.
+    pub fn road_drag(
+        from: units::Position,
+        to: units::Position,
+        by: Option<sulphate::EntityId>,
+        reply: &mpsc::Sender<bool>,
+    ) -> Interruption {
+        let corner = roads::corner(from, to);
+        let mut stretches = [(from, corner), (corner, to)]
+            .iter()
+            .cloned()
+            .filter(|&(a, b)| space::tile_id(a) != space::tile_id(b))
+            .collect::<Vec<_>>();
+        // a click without a drag still lays the one tile
+        if stretches.is_empty() {
+            stretches.push((from, to));
+        }
+        let batch = stretches
+            .into_iter()
+            .map(|(from, to)| {
+                let reply = reply.clone();
+                Interruption::BuildRoad { from, to, by, reply }
+            })
+            .collect();
+        Interruption::Batch(batch)
+    }
+
+    // turns a session's interruptions into interruptions on its own player,
+    // or None if there is no such session
+    fn authorize(
+        self: Self,
+        sessions: &session::Sessions,
+    ) -> Option<Interruption> {
+        use self::Interruption::*;
+        match self {
+            SessionUpdate { session, control } => sessions
+                .player(session)
+                .map(|id| PlayerUpdate { id, control }),
+            SessionCast { session, target } => sessions
+                .player(session)
+                .map(|id| PlayerCast { id, target }),
+            SessionInteract { session } => sessions
+                .player(session)
+                .map(|id| PlayerInteract { id }),
+            SessionDrop { session, slot } => sessions
+                .player(session)
+                .map(|id| PlayerDrop { id, slot }),
+            SessionUndo { session } => sessions
+                .player(session)
+                .map(|id| Undo { id }),
+            SessionRedo { session } => sessions
+                .player(session)
+                .map(|id| Redo { id }),
+            SessionChat { session, text } => sessions
+                .player(session)
+                .map(|from| Chat { from, text }),
+            Leave { session } => sessions
+                .player(session)
+                .map(|id| RemovePlayer { id }),
+            other => Some(other),
+        }
+    }
+
+    /// Whether this says how a player wants to move, which a newer one
+    /// from the same player says everything about.
+    pub fn is_control(self: &Self) -> bool {
+        use self::Interruption::*;
+        match *self {
+            PlayerUpdate { .. } | SessionUpdate { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Which player this comes from, or None if it doesn't come from a
+    /// player, or from a session that isn't open.
+    pub fn player(
+        self: &Self,
+        sessions: &session::Sessions,
+    ) -> Option<sulphate::EntityId> {
+        use self::Interruption::*;
+        match *self {
+            PlayerUpdate { id, .. } | PlayerCast { id, .. } => Some(id),
+            PlayerInteract { id } | PlayerDrop { id, .. } => Some(id),
+            Undo { id } | Redo { id } => Some(id),
+            Chat { from, .. } => Some(from),
+            PlaceBuilding { by, .. } | BuildRoad { by, .. } => by,
+            LayPipe { by, .. } | PaintZone { by, .. } => by,
+            Batch(ref batch) => {
+                batch.first().and_then(|first| first.player(sessions))
+            },
+            SessionUpdate { session, .. } | SessionCast { session, .. } => {
+                sessions.player(session)
+            },
+            SessionInteract { session } | SessionDrop { session, .. } => {
+                sessions.player(session)
+            },
+            SessionUndo { session } | SessionRedo { session } => {
+                sessions.player(session)
+            },
+            SessionChat { session, .. } | Leave { session } => {
+                sessions.player(session)
+            },
+            _ => None,
+        }
+    }
+
+    // the interruptions that change the world, in a form that can be kept
+    fn replayable(self: &Self) -> Option<replay::Replayable> {
+        use self::Interruption::*;
+        use sulphate::replay::Replayable;
+        match *self {
+            PlayerUpdate { id, ref control } => {
+                let control = control.clone();
+                Some(Replayable::PlayerUpdate { id, control })
+            },
+            PlayerCast { id, target } => {
+                Some(Replayable::PlayerCast { id, target })
+            },
+            PlayerInteract { id } => Some(Replayable::PlayerInteract { id }),
+            PlayerDrop { id, slot } => {
+                Some(Replayable::PlayerDrop { id, slot })
+            },
+            RemovePlayer { id } => Some(Replayable::RemovePlayer { id }),
+            Despawn { id } => Some(Replayable::Despawn { id }),
+            PlaceBuilding { blueprint, position, by, .. } => {
+                Some(Replayable::PlaceBuilding { blueprint, position, by })
+            },
+            BuildRoad { from, to, by, .. } => {
+                Some(Replayable::BuildRoad { from, to, by })
+            },
+            LayPipe { from, to, by, .. } => {
+                Some(Replayable::LayPipe { from, to, by })
+            },
+            PaintZone { from, to, zone, by, .. } => {
+                Some(Replayable::PaintZone { from, to, zone, by })
+            },
+            // anything in it that can't be kept can't be in a batch anyway
+            Batch(ref batch) => batch
+                .iter()
+                .map(Interruption::replayable)
+                .collect::<Option<Vec<Replayable>>>()
+                .map(Replayable::Batch),
+            Undo { id } => Some(Replayable::Undo { id }),
+            Redo { id } => Some(Replayable::Redo { id }),
+            SetTimeScale { factor } => {
+                Some(Replayable::SetTimeScale { factor })
+            },
+            PauseWorld { paused } => {
+                Some(Replayable::PauseWorld { paused })
+            },
+            KillServer => Some(Replayable::KillServer),
+            // the device a player talks to can't be recorded, so it is
+            // made again without one
+            Join { ref appearance, position, .. } => {
+                let appearance = appearance.clone();
+                Some(Replayable::Spawn { appearance, position })
+            },
+            Spawn { blueprint, position, ref update, .. } => {
+                let controlled = update.is_some();
+                Some(Replayable::SpawnBlueprint {
+                    blueprint,
+                    position,
+                    controlled,
+                })
+            },
+            // these have already been authorized into the ones above
+            SessionUpdate { .. } | SessionCast { .. } | Leave { .. } => None,
+            SessionChat { .. } | SessionInteract { .. } => None,
+            SessionDrop { .. } | SessionUndo { .. } => None,
+            SessionRedo { .. } => None,
+            // what is said doesn't change the world
+            Chat { .. } | ListenToChat { .. } => None,
+            RequestSnapshot { .. } | QueryMetrics { .. } => None,
+            QueryChecksum { .. } => None,
+            // whatever is in the inbox is recorded as it is applied
+            OpenInbox { .. } | InboxReady => None,
+            QueryLedger { .. } | WatchSky { .. } => None,
+            QueryBudget { .. } | QueryDemand { .. } => None,
+            QueryMods { .. } => None,
+            RequestSnapshotAround { .. } | RequestGround { .. } => None,
+            ListEntities { .. } | Inspect { .. } => None,
+            FindPath { .. } | RoadConnected { .. } => None,
+            Subscribe { .. } => None,
+            StartRecording { .. } | StopRecording => None,
+            SetCatchUp { .. } => None,
+            SaveTo(_) | WatchStatus { .. } => None,
+            StartAutosave { .. } | StopAutosave => None,
+            // reloads aren't recorded, so a replay plays by whatever
+            // settings were loaded when it started
+            ReloadConfig(_) => None,
+            // and the same goes for mods
+            LoadMods(_) => None,
+            Autosave { .. } => None,
+            Shutdown { .. } | Drained { .. } => None,
+        }
+    }
+
+    // what kind of interruption this is, for logging
+    fn kind(self: &Self) -> &'static str {
+        use self::Interruption::*;
+        match *self {
+            PlayerUpdate { .. } => "PlayerUpdate",
+            PlayerCast { .. } => "PlayerCast",
+            PlayerInteract { .. } => "PlayerInteract",
+            PlayerDrop { .. } => "PlayerDrop",
+            Join { .. } => "Join",
+            SessionUpdate { .. } => "SessionUpdate",
+            SessionCast { .. } => "SessionCast",
+            SessionInteract { .. } => "SessionInteract",
+            SessionDrop { .. } => "SessionDrop",
+            SessionUndo { .. } => "SessionUndo",
+            SessionRedo { .. } => "SessionRedo",
+            SessionChat { .. } => "SessionChat",
+            Leave { .. } => "Leave",
+            RemovePlayer { .. } => "RemovePlayer",
+            Spawn { .. } => "Spawn",
+            Despawn { .. } => "Despawn",
+            PlaceBuilding { .. } => "PlaceBuilding",
+            BuildRoad { .. } => "BuildRoad",
+            LayPipe { .. } => "LayPipe",
+            PaintZone { .. } => "PaintZone",
+            Batch(_) => "Batch",
+            Undo { .. } => "Undo",
+            Redo { .. } => "Redo",
+            RoadConnected { .. } => "RoadConnected",
+            SetTimeScale { .. } => "SetTimeScale",
+            PauseWorld { .. } => "PauseWorld",
+            SetCatchUp { .. } => "SetCatchUp",
+            RequestSnapshot { .. } => "RequestSnapshot",
+            RequestSnapshotAround { .. } => "RequestSnapshotAround",
+            RequestGround { .. } => "RequestGround",
+            QueryMetrics { .. } => "QueryMetrics",
+            QueryChecksum { .. } => "QueryChecksum",
+            OpenInbox { .. } => "OpenInbox",
+            InboxReady => "InboxReady",
+            QueryLedger { .. } => "QueryLedger",
+            QueryBudget { .. } => "QueryBudget",
+            QueryDemand { .. } => "QueryDemand",
+            QueryMods { .. } => "QueryMods",
+            Chat { .. } => "Chat",
+            ListenToChat { .. } => "ListenToChat",
+            WatchSky { .. } => "WatchSky",
+            ListEntities { .. } => "ListEntities",
+            Inspect { .. } => "Inspect",
+            FindPath { .. } => "FindPath",
+            Subscribe { .. } => "Subscribe",
+            StartRecording { .. } => "StartRecording",
+            StopRecording => "StopRecording",
+            SaveTo(_) => "SaveTo",
+            StartAutosave { .. } => "StartAutosave",
+            StopAutosave => "StopAutosave",
+            ReloadConfig(_) => "ReloadConfig",
+            LoadMods(_) => "LoadMods",
+            Autosave { .. } => "Autosave",
+            WatchStatus { .. } => "WatchStatus",
+            Shutdown { .. } => "Shutdown",
+            Drained { .. } => "Drained",
+            KillServer => "KillServer",
+        }
+    }
+}
+
 impl server::Interruption<units::Time, sulphate::World> for Interruption {
     fn update(
         self: Self,
-        time: &mut sulphate::EventQueue,
+        raw: &mut sulphate::RawQueue,
+        world: &mut sulphate::World,
+    ) -> bool {
+        sulphate::queue::catch_up_to(&mut world.queue, raw);
+        let kind = self.kind();
+        let started = time::Instant::now();
+        let stop = self.apply(raw, world);
+        let taken = logging::micros(started.elapsed());
+        trace!("applied {} in {}us", kind, taken);
+        sulphate::queue::hand_over(&mut world.queue, raw);
+        stop
+    }
+}
+
+impl Interruption {
+    fn apply(
+        self: Self,
+        raw: &mut sulphate::RawQueue,
         world: &mut sulphate::World,
     ) -> bool {
+        let this = match self.authorize(&world.sessions) {
+            Some(this) => this,
+            None => {
+                warn!("Ignored interruption from unknown session");
+                return false;
+            },
+        };
+
+        world.counts.interruptions += 1;
+
+        let now = raw.now();
+        if world.recovery.checkpoint_due(now) {
+            world.recovery.checkpoint(world, now);
+        }
+        if let Some(replayable) = this.replayable() {
+            world.recovery.note((now, replayable.clone()));
+            if world.recorder.is_some() {
+                world.record(now, replayable);
+            }
+        }
+
         use self::Interruption::*;
-        match self {
+        match this {
             PlayerUpdate { id, control } => {
                 player::Control::apply(
                     &mut world.space,
-                    time,
+                    &mut world.queue,
                     &mut world.matter,
                     id,
                     control,
                 );
             },
+            PlayerCast { id, target } => {
+                player::cast(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    id,
+                    target,
+                );
+            },
+            PlayerInteract { id } => {
+                player::interact(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    id,
+                );
+            },
+            PlayerDrop { id, slot } => {
+                player::drop_item(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    id,
+                    slot,
+                );
+            },
+            Join { appearance, position, update, reply } => {
+                let name = appearance.name.clone();
+                let position =
+                    worldgen::spawn_point(&mut world.matter, position);
+                let id = player::Player::new(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    position,
+                    appearance,
+                    update,
+                ).id();
+                let token = world.sessions.open(name, id);
+                if reply.send((token, id)).is_err() {
+                    warn!("Player joined for disconnected requester");
+                }
+            },
+            SessionUpdate { .. } | SessionCast { .. } | Leave { .. }
+            | SessionChat { .. } | SessionInteract { .. }
+            | SessionDrop { .. } | SessionUndo { .. }
+            | SessionRedo { .. } => {
+                unreachable!("session interruption wasn't authorized");
+            },
+            RemovePlayer { id } => {
+                world.sessions.forget_player(id);
+                let removed = player::Player::remove(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    id,
+                );
+                if removed.is_none() {
+                    warn!("Tried to remove nonexistent player");
+                }
+            },
+            Spawn { blueprint, position, update, reply } => {
+                let id = blueprints::spawn(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    blueprint,
+                    position,
+                    update,
+                );
+                if reply.send(id).is_err() {
+                    warn!("Entity spawned by disconnected requester");
+                }
+            },
+            Despawn { id } => {
+                world.sessions.forget_player(id);
+                let removed = entities::despawn(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    id,
+                );
+                if !removed {
+                    warn!("Tried to despawn nonexistent entity");
+                }
+            },
+            PlaceBuilding { blueprint, position, by, reply } => {
+                let id = construction::place(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    by,
+                    blueprint,
+                    position,
+                );
+                // replays and remote players find out from what gets built
+                // instead, so nobody listening is fine
+                let _ = reply.send(id);
+            },
+            BuildRoad { from, to, by, reply } => {
+                let built = construction::lay_road(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    by,
+                    from,
+                    to,
+                );
+                // the same goes for roads
+                let _ = reply.send(built);
+            },
+            LayPipe { from, to, by, reply } => {
+                let laid = construction::lay_pipe(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    by,
+                    from,
+                    to,
+                );
+                // and pipes
+                let _ = reply.send(laid);
+            },
+            PaintZone { from, to, zone, by, reply } => {
+                let painted = construction::zone(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    by,
+                    from,
+                    to,
+                    zone,
+                );
+                // and for zones
+                let _ = reply.send(painted);
+            },
+            Batch(batch) => {
+                let steps = batch
+                    .iter()
+                    .map(Interruption::replayable)
+                    .collect::<Option<Vec<replay::Replayable>>>()
+                    .and_then(|batch| replay::batch_steps(&batch));
+                let done = match steps {
+                    Some((by, steps)) => construction::batch(
+                        &mut world.space,
+                        &mut world.queue,
+                        &mut world.matter,
+                        by,
+                        &steps,
+                    ),
+                    None => {
+                        warn!("Ignored batch that wasn't all construction \
+                               by one player");
+                        None
+                    },
+                };
+                // each step hears back as it would have on its own
+                for (n, interruption) in batch.into_iter().enumerate() {
+                    let outcome = done.as_ref().map(|done| done[n]);
+                    match interruption {
+                        PlaceBuilding { reply, .. } => {
+                            let id = match outcome {
+                                Some(construction::Done::Placed(id)) => {
+                                    Some(id)
+                                },
+                                _ => None,
+                            };
+                            let _ = reply.send(id);
+                        },
+                        BuildRoad { reply, .. } | LayPipe { reply, .. } => {
+                            let _ = reply.send(outcome.is_some());
+                        },
+                        PaintZone { reply, .. } => {
+                            let painted = match outcome {
+                                Some(construction::Done::Zoned(painted)) => {
+                                    painted
+                                },
+                                _ => 0,
+                            };
+                            let _ = reply.send(painted);
+                        },
+                        _ => (),
+                    }
+                }
+            },
+            Undo { id } => {
+                let undone = construction::undo(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    id,
+                );
+                if !undone {
+                    debug!("Player {} had nothing it could undo", id);
+                }
+            },
+            Redo { id } => {
+                let redone = construction::redo(
+                    &mut world.space,
+                    &mut world.queue,
+                    &mut world.matter,
+                    id,
+                );
+                if !redone {
+                    debug!("Player {} had nothing it could redo", id);
+                }
+            },
+            RoadConnected { from, to, reply } => {
+                let connected = world.space.road_connected(from, to);
+                if reply.send(connected).is_err() {
+                    warn!("Roads asked about by disconnected receiver");
+                }
+            },
+            SetTimeScale { factor } => {
+                world.clock.set_scale(time::Instant::now(), factor);
+            },
+            PauseWorld { paused } => {
+                world.clock.set_paused(time::Instant::now(), paused);
+            },
+            SetCatchUp { policy } => {
+                world.clock.set_catch_up(policy);
+            },
+            RequestSnapshot { reply } => {
+                let snapshot = world.snapshot(raw.now());
+                if reply.send(snapshot).is_err() {
+                    warn!("Snapshot requested by disconnected receiver");
+                }
+            },
+            RequestSnapshotAround { id, radius, reply } => {
+                let ty = any::TypeId::of::<player::Player>();
+                let center = sulphate::EntityUId { id, ty };
+                let now = raw.now();
+                let snapshot = world.snapshot_around(now, center, radius);
+                if reply.send(snapshot).is_err() {
+                    warn!("Snapshot requested by disconnected receiver");
+                }
+            },
+            RequestGround { reply } => {
+                if reply.send(world.space.ground()).is_err() {
+                    warn!("Ground requested by disconnected receiver");
+                }
+            },
+            QueryMetrics { reply } => {
+                let dropped = world.inbox.drops();
+                let metrics =
+                    world.clock.cycles().metrics(&world.counts, dropped);
+                if reply.send(metrics).is_err() {
+                    warn!("Metrics requested by disconnected receiver");
+                }
+            },
+            QueryChecksum { reply } => {
+                let checksum = world.checksum(raw.now());
+                if reply.send(checksum).is_err() {
+                    warn!("Checksum requested by disconnected receiver");
+                }
+            },
+            OpenInbox { wake, reply } => {
+                if reply.send(world.inbox.open(wake)).is_err() {
+                    warn!("Inbox opened by disconnected receiver");
+                }
+            },
+            InboxReady => {
+                let drained = world.inbox.drain(&world.sessions);
+                for interruption in drained {
+                    if interruption.apply(raw, world) {
+                        return true;
+                    }
+                }
+            },
+            QueryLedger { reply } => {
+                let balances = economy::balances(&world.matter);
+                if reply.send(balances).is_err() {
+                    warn!("Ledger requested by disconnected receiver");
+                }
+            },
+            QueryBudget { days, reply } => {
+                let now = raw.now();
+                let report = budget::report(now, &world.matter, days);
+                if reply.send(report).is_err() {
+                    warn!("Budget requested by disconnected receiver");
+                }
+            },
+            QueryDemand { reply } => {
+                let now = raw.now();
+                let demand = demand::demand(&world.space, &world.matter, now);
+                if reply.send(demand).is_err() {
+                    warn!("Demand requested by disconnected receiver");
+                }
+            },
+            QueryMods { reply } => {
+                if reply.send(mods::active(&world.matter)).is_err() {
+                    warn!("Mods requested by disconnected receiver");
+                }
+            },
+            Chat { from, text } => {
+                let name = world.sessions
+                                .name(from)
+                                .map(str::to_string)
+                                .unwrap_or_else(|| format!("player {}", from));
+                let line = chat::ChatLine::new(raw.now(), &name, &text);
+                if let Some(line) = line {
+                    world.chat.say(line);
+                }
+            },
+            ListenToChat { sender } => {
+                world.chat.listen(sender);
+            },
+            WatchSky { sender } => {
+                calendar::watch(&mut world.matter, sender);
+            },
+            ListEntities { reply } => {
+                let entities = world.entities(raw.now());
+                if reply.send(entities).is_err() {
+                    warn!("Entities requested by disconnected receiver");
+                }
+            },
+            Inspect { id, reply } => {
+                let entity = world.inspect(raw.now(), id);
+                if reply.send(entity).is_err() {
+                    warn!("Entity requested by disconnected receiver");
+                }
+            },
+            FindPath { from, to, reply } => {
+                let path = world.space.find_path(from, to);
+                if reply.send(path).is_err() {
+                    warn!("Path requested by disconnected receiver");
+                }
+            },
+            Subscribe { sender } => {
+                world.subscribe(raw, sender);
+            },
+            StartRecording { log } => {
+                world.recorder = Some(log);
+            },
+            StopRecording => {
+                world.recorder = None;
+            },
+            SaveTo(path) => {
+                let snapshot = snapshot::Snapshot::take(world, raw.now());
+                if !world.writer.write(path, snapshot) {
+                    error!("Save thread stopped, nothing saved");
+                }
+            },
+            StartAutosave { settings, wake } => {
+                autosave::start(world, settings, wake);
+            },
+            StopAutosave => {
+                world.autosave = None;
+            },
+            ReloadConfig(path) => match config::load(&path) {
+                Ok(loaded) => mods::reconfigure(&mut world.matter, loaded),
+                Err(e) => {
+                    error!("Failed to reload {}: {}", path.display(), e);
+                },
+            },
+            LoadMods(path) => {
+                mods::load(&mut world.queue, &mut world.matter, &path);
+            },
+            Autosave { round } => {
+                autosave::due(world, raw.now(), round);
+            },
+            WatchStatus { sender } => {
+                world.recovery.watch(sender);
+            },
+            Shutdown { deadline, wake, reply } => {
+                if deadline <= raw.now() {
+                    return finish_shutdown(raw.now(), world, reply);
+                }
+                let drain = DrainEvent { wake, reply };
+                sulphate::enqueue_absolute(&mut world.queue, drain, deadline);
+            },
+            Drained { reply } => {
+                return finish_shutdown(raw.now(), world, reply);
+            },
             KillServer => return true,
         }
         false
     }
 }
 
-fn duration_in_game(duration: time::Duration) -> units::Duration {
-    let seconds = duration.as_secs();
-    let nanos = duration.subsec_nanos();
-    let time_s: units::Duration = (seconds as i32).into();
-    let time_n_num: units::Scalar = (nanos as i32).into();
-    let time_n = time_n_num / 1_000_000_000;
-    time_s + time_n
+// events are invoked in order, and this goes last among those due at the
+// deadline, so once it is invoked everything due by then has happened
+struct DrainEvent {
+    wake: mpsc::Sender<Interruption>,
+    reply: mpsc::Sender<sulphate::World>,
+}
+
+impl sulphate::Event for DrainEvent {
+    fn invoke(
+        self: Self,
+        _space: &mut space::CollisionSpace,
+        _time: &mut sulphate::EventQueue,
+        _matter: &mut sulphate::EntityHeap,
+    ) {
+        let reply = self.reply;
+        if self.wake.send(Interruption::Drained { reply }).is_err() {
+            warn!("Server drained, but can no longer be interrupted");
+        }
+    }
+
+    fn lane(self: &Self) -> sulphate::Lane {
+        sulphate::Lane::Late
+    }
+}
+
+fn finish_shutdown(
+    now: units::Time,
+    world: &mut sulphate::World,
+    reply: mpsc::Sender<sulphate::World>,
+) -> bool {
+    // the server is about to stop, so it can have an empty world to drop
+    let replacement = sulphate::World {
+        space: space::CollisionSpace::new(),
+        matter: sulphate::EntityHeap::new(),
+        queue: sulphate::EventQueue::new(now),
+        clock: Box::new(ManualClock::new(now)),
+        recorder: None,
+        publisher: snapshot::Publisher::new(),
+        writer: snapshot::Writer::new(),
+        inbox: inbox::Inbox::new(),
+        sessions: session::Sessions::new(),
+        chat: chat::Chat::new(),
+        counts: Default::default(),
+        burst: Default::default(),
+        recovery: crash::Recovery::new(),
+        autosave: None,
+    };
+    let last = mem::replace(world, replacement);
+    if reply.send(last).is_err() {
+        warn!("Final world state dropped by requester");
+    }
+    true
+}
+
+const NANOS_PER_SECOND: i64 = 1_000_000_000;
+
+// `scale` is the game time that passes per real second
+//
+// these work on the bits of the scalars, so that whole seconds convert
+// exactly and only what's left of a second gets rounded, and they saturate
+// rather than overflow, however long the server has been up
+fn duration_in_game(
+    duration: time::Duration,
+    scale: units::Scalar,
+) -> units::Duration {
+    let scale_bits = scale.into_bits();
+    let seconds = cmp::min(duration.as_secs(), i64::MAX as u64) as i64;
+    let nanos = duration.subsec_nanos() as i64;
+    let whole = seconds.saturating_mul(scale_bits);
+    // rounding down, so that the game never gets ahead of the real time
+    let part = match nanos.checked_mul(scale_bits) {
+        Some(prod) => prod / NANOS_PER_SECOND,
+        None => scale_bits,
+    };
+    units::Scalar::from_bits(whole.saturating_add(part))
+}
+
+fn duration_real_time(
+    duration: units::Duration,
+    scale: units::Scalar,
+) -> time::Duration {
+    let bits = duration.into_bits();
+    let scale_bits = scale.into_bits();
+    if bits <= 0 {
+        return time::Duration::new(0, 0);
+    }
+    if scale_bits <= 0 {
+        // a clock that isn't moving forward will never get there
+        return indefinite_wait();
+    }
+    let seconds = bits / scale_bits;
+    let rest = bits % scale_bits;
+    // rounding up, so that waiting this long always gets to `duration`
+    let nanos = match rest.checked_mul(NANOS_PER_SECOND) {
+        Some(prod) if prod % scale_bits == 0 => prod / scale_bits,
+        Some(prod) => prod / scale_bits + 1,
+        None => NANOS_PER_SECOND,
+    };
+    time::Duration::new(seconds as u64, 0)
+        + time::Duration::new(0, nanos as u32)
 }
 
-fn duration_real_time(duration: units::Duration) -> time::Duration {
-    let time_s: i32 = duration.into();
-    let time_frac = duration - time_s.into();
-    let time_n: i32 = (time_frac * 1_000_000_000).into();
-    time::Duration::new(time_s as u64, time_n as u32)
+// long enough that the server thread is effectively asleep until the next
+// interruption wakes it up
+fn indefinite_wait() -> time::Duration {
+    time::Duration::from_secs(60 * 60)
 }
 
 #[derive(Clone)]
 struct Simple {
     start_instant: Option<time::Instant>,
     last_time: units::Time,
+    // game time elapsed per real second
+    scale: units::Scalar,
+    cycles: metrics::Cycles,
+    // the time the server is currently processing up to
+    cycle_target: units::Time,
+    catch_up: Option<catch_up::CatchUp>,
 }
 
 impl Simple {
@@ -67,9 +1138,17 @@ impl Simple {
         Simple {
             start_instant: None,
             last_time: start_time,
+            scale: 1.into(),
+            cycles: Default::default(),
+            cycle_target: start_time,
+            catch_up: None,
         }
     }
 
+    fn is_running(&self) -> bool {
+        self.start_instant.is_some()
+    }
+
     fn elapsed_as_of(&self, now: time::Instant) -> time::Duration {
         if let Some(start) = self.start_instant {
             now.duration_since(start)
@@ -81,7 +1160,17 @@ impl Simple {
 
     fn time(&self, now: time::Instant) -> units::Time {
         let elapsed = self.elapsed_as_of(now);
-        self.last_time + duration_in_game(elapsed)
+        let elapsed = duration_in_game(elapsed, self.scale);
+        self.last_time.saturating_add(elapsed)
+    }
+
+    fn wait(&self, now: units::Time, until: units::Time) -> time::Duration {
+        if self.is_running() {
+            duration_real_time(until - now, self.scale)
+        } else {
+            // paused clocks will never reach `until` on their own
+            indefinite_wait()
+        }
     }
 
     fn stop(&mut self, now: time::Instant) {
@@ -93,10 +1182,106 @@ impl Simple {
         self.stop(now);
         self.start_instant = Some(now);
     }
+
+    // lose the game time between `to` and now, keeping the clock running
+    fn fall_back(&mut self, now: time::Instant, to: units::Time) {
+        self.last_time = to;
+        if self.is_running() {
+            self.start_instant = Some(now);
+        }
+    }
+
+    fn set_scale(&mut self, now: time::Instant, scale: units::Scalar) {
+        // fold the time elapsed so far into last_time at the old scale,
+        // so that changing speed never makes the game time jump
+        let running = self.is_running();
+        self.stop(now);
+        self.scale = scale;
+        if running {
+            self.start(now);
+        }
+    }
 }
 
+// the state is shared so that the client's copy of the clock pauses and
+// changes speed along with the server's
 #[derive(Clone)]
-pub struct Clock(Simple);
+pub struct Clock(sync::Arc<sync::Mutex<Simple>>);
+
+impl Clock {
+    fn new(start_time: units::Time) -> Clock {
+        let simple = Simple::new(start_time);
+        Clock(sync::Arc::new(sync::Mutex::new(simple)))
+    }
+
+    fn simple(self: &Self) -> sync::MutexGuard<Simple> {
+        self.0.lock().expect("clock was poisoned")
+    }
+
+    /// The current game time, without affecting the server.
+    pub fn now(self: &Self) -> units::Time {
+        self.simple().time(time::Instant::now())
+    }
+
+    pub fn scale(self: &Self) -> units::Scalar {
+        self.simple().scale
+    }
+
+    pub fn is_paused(self: &Self) -> bool {
+        !self.simple().is_running()
+    }
+
+    pub fn set_scale(self: &mut Self, factor: units::Scalar) {
+        ClockControl::set_scale(self, time::Instant::now(), factor);
+    }
+}
+
+/// Anything that interruptions can pause or speed up.
+pub trait ClockControl {
+    fn set_paused(self: &mut Self, now: time::Instant, paused: bool);
+    fn set_scale(self: &mut Self, now: time::Instant, factor: units::Scalar);
+    /// How the cycles that the clock has been told about have gone.
+    fn cycles(self: &Self) -> metrics::Cycles;
+    /// The game time that the current cycle is processing up to.
+    fn cycle_target(self: &Self) -> units::Time;
+    fn catch_up(self: &Self) -> Option<catch_up::CatchUp>;
+    fn set_catch_up(self: &mut Self, policy: Option<catch_up::CatchUp>);
+}
+
+impl ClockControl for Clock {
+    fn set_paused(self: &mut Self, now: time::Instant, paused: bool) {
+        let mut simple = self.simple();
+        if paused {
+            simple.stop(now);
+        } else if !simple.is_running() {
+            simple.start(now);
+        }
+    }
+
+    fn set_scale(self: &mut Self, now: time::Instant, factor: units::Scalar) {
+        if factor <= 0 {
+            warn!("Ignored non-positive time scale {}", factor);
+            return;
+        }
+        self.simple().set_scale(now, factor);
+    }
+
+    fn cycles(self: &Self) -> metrics::Cycles {
+        self.simple().cycles.clone()
+    }
+
+    fn cycle_target(self: &Self) -> units::Time {
+        self.simple().cycle_target
+    }
+
+    fn catch_up(self: &Self) -> Option<catch_up::CatchUp> {
+        self.simple().catch_up
+    }
+
+    fn set_catch_up(self: &mut Self, policy: Option<catch_up::CatchUp>) {
+        self.simple().catch_up = policy;
+    }
+}
 
 pub trait ClockMethods {
     fn in_game(self: &mut Self, now: time::Instant) -> units::Time;
@@ -141,56 +1326,175 @@ impl<C> ClockMethods for C where C: server::Clock<units::Time> {
 
 impl server::Clock<units::Time> for Clock {
     fn in_game(self: &mut Self, now: time::Instant) -> units::Time {
-        self.0.time(now)
+        let mut simple = self.simple();
+        let target = simple.time(now);
+        simple.cycle_target = target;
+        target
     }
     fn minimum_wait(
         self: &mut Self,
         now: units::Time,
         until: units::Time,
     ) -> time::Duration {
-        duration_real_time(until - now)
+        self.simple().wait(now, until)
     }
     fn finished_cycle(
         self: &mut Self,
-        _now: time::Instant,
-        _in_game: units::Time
-    ) {}
+        now: time::Instant,
+        in_game: units::Time
+    ) {
+        let mut simple = self.simple();
+        let drift = simple.time(now) - in_game;
+        simple.cycles.finished(now, drift);
+        let allowance = catch_up::lag_allowance();
+        if simple.catch_up == Some(catch_up::CatchUp::SlowClock)
+            && drift > allowance
+        {
+            simple.fall_back(now, in_game + allowance);
+        }
+    }
     fn end_cycles(self: &mut Self) {}
 }
 
-type Server = server::Server<
-    Clock,
+#[derive(Clone)]
+struct Manual {
+    now: units::Time,
+    paused: bool,
+    scale: units::Scalar,
+    cycles: metrics::Cycles,
+    catch_up: Option<catch_up::CatchUp>,
+}
+
+/// A clock that only moves when told to, so that the simulation can be run
+/// deterministically, or headless as fast as possible.
+#[derive(Clone)]
+pub struct ManualClock(sync::Arc<sync::Mutex<Manual>>);
+
+impl ManualClock {
+    pub fn new(start_time: units::Time) -> ManualClock {
+        let manual = Manual {
+            now: start_time,
+            paused: false,
+            scale: 1.into(),
+            cycles: Default::default(),
+            catch_up: None,
+        };
+        ManualClock(sync::Arc::new(sync::Mutex::new(manual)))
+    }
+
+    fn manual(self: &Self) -> sync::MutexGuard<Manual> {
+        self.0.lock().expect("clock was poisoned")
+    }
+
+    pub fn now(self: &Self) -> units::Time {
+        self.manual().now
+    }
+
+    pub fn advance(self: &mut Self, by: units::Duration) {
+        let mut manual = self.manual();
+        if !manual.paused {
+            manual.now = manual.now + by * manual.scale;
+        }
+    }
+
+    /// Moves straight to `time`, whatever the time scale or pause, for when
+    /// the time is already known in game terms, as in a replay.
+    pub fn set_to(self: &mut Self, time: units::Time) {
+        self.manual().now = time;
+    }
+}
+
+impl ClockControl for ManualClock {
+    fn set_paused(self: &mut Self, _now: time::Instant, paused: bool) {
+        self.manual().paused = paused;
+    }
+
+    fn set_scale(self: &mut Self, _now: time::Instant, factor: units::Scalar) {
+        if factor <= 0 {
+            warn!("Ignored non-positive time scale {}", factor);
+            return;
+        }
+        self.manual().scale = factor;
+    }
+
+    fn cycles(self: &Self) -> metrics::Cycles {
+        self.manual().cycles.clone()
+    }
+
+    fn cycle_target(self: &Self) -> units::Time {
+        self.now()
+    }
+
+    // a manual clock never runs ahead of the server, so only `Burst` does
+    // anything here
+    fn catch_up(self: &Self) -> Option<catch_up::CatchUp> {
+        self.manual().catch_up
+    }
+
+    fn set_catch_up(self: &mut Self, policy: Option<catch_up::CatchUp>) {
+        self.manual().catch_up = policy;
+    }
+}
+
+impl server::Clock<units::Time> for ManualClock {
+    fn in_game(self: &mut Self, _now: time::Instant) -> units::Time {
+        self.now()
+    }
+    fn minimum_wait(
+        self: &mut Self,
+        _now: units::Time,
+        _until: units::Time,
+    ) -> time::Duration {
+        // nothing to wait for, time only passes when someone says so
+        time::Duration::new(0, 0)
+    }
+    fn finished_cycle(
+        self: &mut Self,
+        now: time::Instant,
+        in_game: units::Time
+    ) {
+        let mut manual = self.manual();
+        let drift = manual.now - in_game;
+        manual.cycles.finished(now, drift);
+    }
+    fn end_cycles(self: &mut Self) {}
+}
+
+type Server<C> = server::Server<
+    C,
     Interruption,
     units::Time,
     sulphate::World
 >;
 
-fn create_server_local<F, R>(
+fn create_server_local<C, F, R>(
     f: F,
     upd: mpsc::Receiver<Interruption>,
-) -> (Server, Clock, R)
-    where F: FnOnce(
+    mut clock: C,
+) -> (Server<C>, C, R, crash::Recovery)
+    where C: server::Clock<units::Time> + ClockControl
+           + Clone + Send + 'static,
+          F: FnOnce(
                  &mut space::CollisionSpace,
                  &mut sulphate::EventQueue,
                  &mut sulphate::EntityHeap,
              ) -> R,
           R: Send + 'static,
 {
-    let initial_time = Default::default();
-    let mut clock = Clock(Simple::new(initial_time));
-    clock.0.start(time::Instant::now());
+    let initial_time = server::Clock::in_game(&mut clock, time::Instant::now());
 
-    let mut space = space::CollisionSpace::new();
-    let mut time = sulphate::EventQueue::new(initial_time);
-    let mut matter = sulphate::EntityHeap::new();
+    let control = Box::new(clock.clone());
+    let (mut world, r) = sulphate::World::new(initial_time, control, f);
 
-    let r = f(&mut space, &mut time, &mut matter);
+    let mut time = sulphate::RawQueue::new(initial_time);
+    sulphate::queue::hand_over(&mut world.queue, &mut time);
 
-    let world = sulphate::World { space, matter };
+    world.recovery.checkpoint(&world, initial_time);
+    let recovery = world.recovery.clone();
 
     let server = Server::new(time, world, upd, clock.clone());
 
-    (server, clock, r)
+    (server, clock, r, recovery)
 }
 
 struct ServerWatcher {
@@ -200,17 +1504,21 @@ struct ServerWatcher {
 impl Drop for ServerWatcher {
     fn drop(self: &mut Self) {
         if self.natural {
-            println!("Server closed without panicking");
+            info!("Server closed without panicking");
         } else {
-            println!("Server panicked!");
+            error!("Server panicked!");
         }
     }
 }
 
 
+/// Spawns the server thread. Once `KillServer` has been sent, joining the
+/// returned handle waits for the thread to finish, and gives `Err` if the
+/// server panicked instead.
 pub fn start_server<F, R>(f: F) -> (
     mpsc::Sender<Interruption>,
     Clock,
+    thread::JoinHandle<()>,
     R,
 )
     where F: Send + 'static
@@ -220,22 +1528,577 @@ pub fn start_server<F, R>(f: F) -> (
                  &mut sulphate::EntityHeap,
              ) -> R,
           R: Send + 'static,
+{
+    let clock = Clock::new(Default::default());
+    clock.simple().start(time::Instant::now());
+    start_server_with_clock(f, clock)
+}
+
+/// Spawns the server thread with the world that the map at `path`
+/// describes, or gives why the map couldn't be read or built, in which case
+/// no server is left running.
+pub fn start_server_from_map(path: &path::Path) -> Result<(
+    mpsc::Sender<Interruption>,
+    Clock,
+    thread::JoinHandle<()>,
+), worldgen::MapError> {
+    start_server_with_map(worldgen::load(path)?)
+}
+
+/// Spawns the server thread with the world that `map` describes, such as
+/// one that was generated, in the same way as `start_server_from_map`.
+pub fn start_server_with_map(map: worldgen::Map) -> Result<(
+    mpsc::Sender<Interruption>,
+    Clock,
+    thread::JoinHandle<()>,
+), worldgen::MapError> {
+    let (upd, clock, handle, built) =
+        start_server(move |space, time, matter| {
+            map.build(space, time, matter)
+        });
+    match built {
+        Ok(()) => Ok((upd, clock, handle)),
+        Err(err) => {
+            let _ = upd.send(Interruption::KillServer);
+            if handle.join().is_err() {
+                error!("Server panicked while shutting down");
+            }
+            Err(err)
+        },
+    }
+}
+
+pub fn start_server_with_clock<C, F, R>(f: F, clock: C) -> (
+    mpsc::Sender<Interruption>,
+    C,
+    thread::JoinHandle<()>,
+    R,
+)
+    where C: server::Clock<units::Time> + ClockControl
+           + Clone + Send + 'static,
+          F: Send + 'static
+           + FnOnce(
+                 &mut space::CollisionSpace,
+                 &mut sulphate::EventQueue,
+                 &mut sulphate::EntityHeap,
+             ) -> R,
+          R: Send + 'static,
 {
     let (upd, upd_recv) = mpsc::channel();
     let (send, recv) = mpsc::channel();
 
-    thread::spawn(move || {
+    let handle = thread::spawn(move || {
         let mut announce_shutdown = ServerWatcher { natural: false };
 
-        let (mut server, clock, r) =
-            create_server_local(f, upd_recv);
+        let (mut server, clock, r, recovery) =
+            create_server_local(f, upd_recv, clock);
         send.send((clock, r)).expect("failed to send server result");
-        server.run();
+        // the world is lost with the server, but the recovery state is not
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+            server.run();
+        }));
+
+        if let Err(payload) = result {
+            let path = crash::crash_dump_path();
+            let dump = match recovery.dump(&path) {
+                Ok(()) => Some(path),
+                Err(e) => {
+                    error!("Failed to write crash dump: {}", e);
+                    None
+                },
+            };
+            recovery.announce(crash::ServerStatus::Crashed { dump });
+            // still panic, so that joining the thread gives `Err`
+            panic::resume_unwind(payload);
+        }
+        recovery.announce(crash::ServerStatus::Closed);
 
         announce_shutdown.natural = true;
     });
 
     let (clock, r) = recv.recv().expect("failed to receive server result");
 
-    (upd, clock, r)
+    (upd, clock, handle, r)
+}
+
+/// Resumes a saved game, with the clock continuing from when it was saved.
+/// Each player in the save is given a new device to send updates to.
+pub fn create_server_from_snapshot(path: &path::Path) -> io::Result<(
+    mpsc::Sender<Interruption>,
+    Clock,
+    thread::JoinHandle<()>,
+    Vec<(sulphate::EntityId, mpsc::Receiver<player::Update>)>,
+)> {
+    let saved = persist::load(path)?;
+
+    let clock = Clock::new(saved.time);
+    clock.simple().start(time::Instant::now());
+    let result = start_server_with_clock(move |space, time, matter| {
+        saved.restore(space, time, matter)
+    }, clock);
+
+    Ok(result)
+}
+
+/// Owns a running server, so that it can be stopped cleanly.
+pub struct ServerHandle {
+    upd: mpsc::Sender<Interruption>,
+    thread: thread::JoinHandle<()>,
+}
+
+impl ServerHandle {
+    pub fn new(
+        upd: mpsc::Sender<Interruption>,
+        thread: thread::JoinHandle<()>,
+    ) -> ServerHandle {
+        ServerHandle { upd, thread }
+    }
+
+    pub fn sender(self: &Self) -> &mpsc::Sender<Interruption> {
+        &self.upd
+    }
+
+    /// Blocks until every event due before `deadline` has been processed,
+    /// then joins the server thread and returns the world as it was left.
+    pub fn shutdown(
+        self: Self,
+        deadline: units::Time,
+    ) -> thread::Result<sulphate::World> {
+        let (reply, recv) = mpsc::channel();
+        let wake = self.upd.clone();
+        let shutdown = Interruption::Shutdown { deadline, wake, reply };
+        // if this fails then so will recv, and join will say why
+        let _ = self.upd.send(shutdown);
+        let world = recv.recv();
+
+        self.thread.join()?;
+        world.map_err(|_| {
+            let msg = "server stopped before draining";
+            Box::new(msg) as Box<::std::any::Any + Send>
+        })
+    }
+}
+
+#[cfg(test)]
+mod test_snapshot {
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time;
+
+    use entities::player;
+    use sulphate;
+    use units;
+
+    use super::*;
+
+    fn with_player(
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) -> mpsc::Receiver<player::Update> {
+        let (send, recv) = mpsc::channel();
+        let position = Default::default();
+        let appearance = Default::default();
+        player::Player::new(space, time, matter, position, appearance, send);
+        recv
+    }
+
+    fn created(recv: &mpsc::Receiver<player::Update>) -> sulphate::EntityId {
+        match recv.recv().expect("player never sent update").what {
+            player::UpdateData::Created { id, .. } => id,
+            _ => panic!("player didn't send Created update first"),
+        }
+    }
+
+    fn start_with_player() -> (
+        mpsc::Sender<Interruption>,
+        thread::JoinHandle<()>,
+        sulphate::EntityId,
+    ) {
+        let (upd, _clock, handle, recv) = start_server(with_player);
+        (upd, handle, created(&recv))
+    }
+
+    fn snapshot(
+        upd: &mpsc::Sender<Interruption>,
+    ) -> sulphate::WorldSnapshot {
+        let (reply, recv) = mpsc::channel();
+        upd.send(Interruption::RequestSnapshot { reply }).unwrap();
+        recv.recv().expect("server didn't reply with snapshot")
+    }
+
+    #[test]
+    fn snapshot_sees_movement() {
+        let clock = ManualClock::new(Default::default());
+        let (upd, mut clock, handle, recv) =
+            start_server_with_clock(with_player, clock);
+        let id = created(&recv);
+
+        let before = snapshot(&upd);
+        assert_eq!(before.bodies.len(), 1);
+        assert!(before.bodies[0].uid.id == id);
+        assert_eq!(before.bodies[0].position, Default::default());
+
+        let velocity = units::Velocity { x: 100.into(), y: 0.into() };
+        let pace = player::Pace::Run;
+        let control = player::Control::Move { velocity, pace };
+        upd.send(Interruption::PlayerUpdate { id, control }).unwrap();
+        // long enough for the player to get up to speed
+        clock.advance(units::moments(units::MOMENT_RATE as i32));
+
+        let after = snapshot(&upd);
+        assert_eq!(after.bodies[0].velocity, velocity);
+        assert!(after.bodies[0].position.x > units::Coord::default());
+
+        upd.send(Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn snapshot_around_leaves_out_the_distance() {
+        let (upd, _clock, handle, ids) = start_server(|space, time, matter| {
+            let mut ids = Vec::new();
+            for &x in [0, 50, 5000].iter() {
+                let (send, _) = mpsc::channel();
+                let offset = units::Displacement { x: x.into(), y: 0.into() };
+                let position = units::Position::default() + offset;
+                let player = player::Player::new(
+                    space, time, matter, position, Default::default(), send,
+                );
+                ids.push(player.id());
+            }
+            ids
+        });
+
+        let (reply, recv) = mpsc::channel();
+        let radius = 100.into();
+        let request = Interruption::RequestSnapshotAround {
+            id: ids[0], radius, reply,
+        };
+        upd.send(request).unwrap();
+        let around = recv.recv().expect("server didn't reply with snapshot");
+        let mut seen: Vec<_> = around.bodies
+                                     .iter()
+                                     .map(|body| body.uid.id)
+                                     .collect();
+        seen.sort();
+        assert_eq!(seen, vec![ids[0], ids[1]]);
+
+        upd.send(Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn subscriber_sees_player() {
+        let (upd, handle, id) = start_with_player();
+
+        let (sender, views) = mpsc::channel();
+        upd.send(Interruption::Subscribe { sender }).unwrap();
+        let first = views.recv_timeout(time::Duration::from_secs(1))
+                         .expect("subscriber never sent a view");
+        assert_eq!(first.entities.len(), 1);
+        assert!(first.entities[0].uid.id == id);
+
+        let second = views.recv_timeout(time::Duration::from_secs(1))
+                          .expect("subscriber only sent one view");
+        assert!(second.time > first.time);
+
+        upd.send(Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+}
+
+#[cfg(test)]
+mod test_conversions {
+    use std::time;
+
+    use rand;
+    use rand::Rng;
+    use rand::SeedableRng;
+
+    use units;
+
+    use super::*;
+
+    fn scales() -> Vec<units::Scalar> {
+        let half = units::Scalar::from(1) / 2;
+        vec![1.into(), 2.into(), half, units::Scalar::from(1) / 3, 60.into()]
+    }
+
+    #[test]
+    fn game_time_survives_a_round_trip() {
+        let mut rng = rand::XorShiftRng::from_seed([1, 2, 3, 4]);
+        for scale in scales() {
+            for _ in 0..10_000 {
+                // up to about a year, down to the smallest instant
+                let bits: i64 = rng.gen_range(1, 1 << 41);
+                let duration = units::Scalar::from_bits(bits);
+                let real = duration_real_time(duration, scale);
+                let back = duration_in_game(real, scale);
+                // to within a nanosecond, which is less than an instant, so
+                // exactly
+                assert_eq!(back, duration, "at scale {}", scale);
+            }
+        }
+    }
+
+    #[test]
+    fn real_time_never_comes_back_later() {
+        let mut rng = rand::XorShiftRng::from_seed([5, 6, 7, 8]);
+        for scale in scales() {
+            for _ in 0..10_000 {
+                let seconds = rng.gen_range(0, 60 * 60 * 24 * 365);
+                let nanos = rng.gen_range(0, NANOS_PER_SECOND as u32);
+                let real = time::Duration::new(seconds, nanos);
+                let game = duration_in_game(real, scale);
+                let back = duration_real_time(game, scale);
+                // any further and the server would sleep past events
+                assert!(back <= real);
+                // and the rest is lost to rounding into game time
+                let instant = duration_real_time(units::instants(1), scale);
+                assert!(real - back <= instant);
+            }
+        }
+    }
+
+    #[test]
+    fn long_sessions_saturate() {
+        let forever = time::Duration::new(u64::max_value(), 999_999_999);
+        let scale = 1000.into();
+        let game = duration_in_game(forever, scale);
+        assert_eq!(game, units::Scalar::max_value());
+
+        let late = units::Time::from_bits(1 << 62);
+        let end = units::Time::from_bits(i64::max_value());
+        assert_eq!(late.saturating_add(game), end);
+        let wait = duration_real_time(game, 0.into());
+        assert_eq!(wait, indefinite_wait());
+    }
+}
+
+#[cfg(test)]
+mod test_catch_up {
+    use std::time;
+
+    use sulphate::catch_up;
+    use units;
+
+    use super::*;
+
+    // a clock that has been running for a minute of real time, with the
+    // server having only got through the first moment of it
+    fn behind(policy: Option<catch_up::CatchUp>) -> (Clock, time::Instant) {
+        let mut clock = Clock::new(Default::default());
+        clock.set_catch_up(policy);
+        let started = time::Instant::now();
+        ClockControl::set_paused(&mut clock, started, false);
+        let now = started + time::Duration::from_secs(60);
+        let in_game = units::Time::default() + units::moments(1);
+        ClockMethods::finished_cycle(&mut clock, now, in_game);
+        (clock, now)
+    }
+
+    #[test]
+    fn slow_clocks_fall_back_to_the_allowance() {
+        let (clock, now) = behind(Some(catch_up::CatchUp::SlowClock));
+        let allowed = units::Time::default() + units::moments(1)
+                    + catch_up::lag_allowance();
+        assert_eq!(clock.simple().time(now), allowed);
+        assert!(!clock.is_paused());
+    }
+
+    #[test]
+    fn other_clocks_stay_ahead() {
+        let policies = [None, Some(catch_up::CatchUp::Skip)];
+        for &policy in policies.iter() {
+            let (clock, now) = behind(policy);
+            let minute = duration_in_game(
+                time::Duration::from_secs(60),
+                1.into(),
+            );
+            let ahead = units::Time::default() + minute;
+            assert_eq!(clock.simple().time(now), ahead);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_manual_clock {
+    use std::sync::mpsc;
+    use std::time;
+
+    use space;
+    use sulphate;
+    use units;
+
+    use super::*;
+
+    struct Ping(mpsc::Sender<units::Time>);
+
+    impl sulphate::Event for Ping {
+        fn invoke(
+            self: Self,
+            _space: &mut space::CollisionSpace,
+            time: &mut sulphate::EventQueue,
+            _matter: &mut sulphate::EntityHeap,
+        ) {
+            self.0.send(time.now()).unwrap();
+        }
+    }
+
+    // round trip through the server so that it has caught up with the clock
+    fn sync(upd: &mpsc::Sender<Interruption>) {
+        let (reply, recv) = mpsc::channel();
+        upd.send(Interruption::RequestSnapshot { reply }).unwrap();
+        recv.recv().expect("server didn't reply with snapshot");
+    }
+
+    #[test]
+    fn event_fires_once_after_advance() {
+        let start = units::Time::default();
+        let at = start + units::moments(16);
+        let clock = ManualClock::new(start);
+        let (upd, mut clock, handle, pings) =
+            start_server_with_clock(move |_space, time, _matter| {
+                let (send, recv) = mpsc::channel();
+                sulphate::enqueue_absolute(time, Ping(send), at);
+                recv
+            }, clock);
+
+        clock.advance(units::moments(8));
+        sync(&upd);
+        assert!(pings.try_recv().is_err(), "event fired early");
+
+        clock.advance(units::moments(16));
+        let when = pings.recv_timeout(time::Duration::from_secs(1))
+                        .expect("event never fired");
+        assert!(when == at);
+
+        clock.advance(units::moments(16));
+        sync(&upd);
+        assert!(pings.try_recv().is_err(), "event fired twice");
+
+        upd.send(Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn metrics_count_events() {
+        let start = units::Time::default();
+        let clock = ManualClock::new(start);
+        let (upd, mut clock, handle, _pings) =
+            start_server_with_clock(move |_space, time, _matter| {
+                let (send, recv) = mpsc::channel();
+                sulphate::enqueue_relative(time, Ping(send), 1.into());
+                recv
+            }, clock);
+
+        clock.advance(units::moments(32));
+        sync(&upd);
+
+        let (reply, recv) = mpsc::channel();
+        upd.send(Interruption::QueryMetrics { reply }).unwrap();
+        let metrics = recv.recv().expect("server didn't reply with metrics");
+        assert_eq!(metrics.events, 1);
+        // the sync and the query itself
+        assert_eq!(metrics.interruptions, 2);
+        assert!(metrics.cycles > 0);
+
+        upd.send(Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+}
+
+#[cfg(test)]
+mod test_session {
+    use std::sync::mpsc;
+
+    use entities;
+    use entities::player;
+    use sulphate;
+    use sulphate::session;
+    use units;
+
+    use super::*;
+
+    fn join(
+        upd: &mpsc::Sender<Interruption>,
+        name: &str,
+    ) -> (session::SessionToken, sulphate::EntityId) {
+        let (update, _updates) = mpsc::channel();
+        let (reply, joined) = mpsc::channel();
+        let appearance = player::Appearance::named(name);
+        let position = Default::default();
+        upd.send(Interruption::Join { appearance, position, update, reply })
+           .unwrap();
+        joined.recv().expect("server didn't reply to join")
+    }
+
+    fn velocity_of(
+        upd: &mpsc::Sender<Interruption>,
+        id: sulphate::EntityId,
+    ) -> units::Velocity {
+        let (reply, recv) = mpsc::channel();
+        upd.send(Interruption::RequestSnapshot { reply }).unwrap();
+        let snapshot = recv.recv().expect("server didn't reply with snapshot");
+        snapshot.bodies
+                .iter()
+                .find(|body| body.uid.id == id)
+                .expect("player missing from snapshot")
+                .velocity
+    }
+
+    #[test]
+    fn sessions_only_control_their_own_player() {
+        let clock = ManualClock::new(Default::default());
+        let (upd, mut clock, handle, ()) =
+            start_server_with_clock(|_, _, _| (), clock);
+        let (session, mine) = join(&upd, "mine");
+        let (_, theirs) = join(&upd, "theirs");
+
+        let velocity = units::Velocity { x: 100.into(), y: 0.into() };
+        let pace = player::Pace::Run;
+        let control = player::Control::Move { velocity, pace };
+        upd.send(Interruption::SessionUpdate { session, control }).unwrap();
+
+        let pace = player::Pace::Run;
+        let control = player::Control::Move { velocity, pace };
+        let forged = session.wrapping_add(1);
+        upd.send(Interruption::SessionUpdate { session: forged, control })
+           .unwrap();
+        // the snapshots below wait for both updates, and a second is long
+        // enough for the player to get up to speed
+        clock.advance(units::moments(units::MOMENT_RATE as i32));
+
+        assert_eq!(velocity_of(&upd, mine), velocity);
+        assert_eq!(velocity_of(&upd, theirs), units::Velocity::default());
+
+        upd.send(Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    #[test]
+    fn views_show_how_players_look() {
+        let (upd, _clock, handle, ()) = start_server(|_, _, _| ());
+        let (_, id) = join(&upd, "mine");
+
+        let (sender, views) = mpsc::channel();
+        upd.send(Interruption::Subscribe { sender }).unwrap();
+        let view = views.recv().expect("server didn't send a view");
+        let image = view.entities
+                        .into_iter()
+                        .find(|entity| entity.uid.id == id)
+                        .expect("player missing from view")
+                        .image;
+        match image {
+            entities::Image::Player(image) => {
+                let asked = player::Appearance::named("mine");
+                assert_eq!(image.appearance, asked);
+            },
+            _ => panic!("player viewed as something else"),
+        }
+
+        upd.send(Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
 }
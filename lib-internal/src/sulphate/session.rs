@@ -0,0 +1,74 @@
+use std::collections;
+
+use rand;
+
+use sulphate;
+
+/// Handed to whoever joined as a player, and needed to control that player.
+pub type SessionToken = u64;
+
+pub struct PlayerSession {
+    pub name: String,
+    pub player: sulphate::EntityId,
+}
+
+// tokens are random rather than from a fixed sequence like entity ids,
+// so that one connection can't guess the token of another
+pub struct Sessions {
+    sessions: collections::HashMap<SessionToken, PlayerSession>,
+}
+
+impl Sessions {
+    pub fn new() -> Sessions {
+        let sessions = collections::HashMap::new();
+        Sessions { sessions }
+    }
+
+    pub fn open(
+        self: &mut Self,
+        name: String,
+        player: sulphate::EntityId,
+    ) -> SessionToken {
+        loop {
+            let token = rand::random();
+            if !self.sessions.contains_key(&token) {
+                let session = PlayerSession { name, player };
+                self.sessions.insert(token, session);
+                return token;
+            }
+        }
+    }
+
+    pub fn get(self: &Self, token: SessionToken) -> Option<&PlayerSession> {
+        self.sessions.get(&token)
+    }
+
+    /// The player that `token` is allowed to control, if any.
+    pub fn player(
+        self: &Self,
+        token: SessionToken,
+    ) -> Option<sulphate::EntityId> {
+        self.get(token).map(|session| session.player)
+    }
+
+    /// What the player was called when they joined, if they joined through
+    /// a session.
+    pub fn name(self: &Self, player: sulphate::EntityId) -> Option<&str> {
+        self.sessions
+            .values()
+            .find(|session| session.player == player)
+            .map(|session| &session.name[..])
+    }
+
+    pub fn close(
+        self: &mut Self,
+        token: SessionToken,
+    ) -> Option<PlayerSession> {
+        self.sessions.remove(&token)
+    }
+
+    // for when a player is removed some other way than by leaving
+    pub fn forget_player(self: &mut Self, player: sulphate::EntityId) {
+        self.sessions.retain(|_, session| session.player != player);
+    }
+}
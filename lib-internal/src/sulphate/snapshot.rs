@@ -0,0 +1,466 @@
+//! Copies of the world cheap enough to take between events, for saving it
+//! and showing it to subscribers on threads of their own while the world
+//! carries on.
+//!
+//! A snapshot shares the city's ground with the world, as
+//! `space::ground::Ground` explains, and only copies out what there is to
+//! know about each entity. Whatever is slow about turning that into a save
+//! file or a view, such as walking every road, happens on the thread the
+//! snapshot is sent to.
+
+use std::collections;
+use std::path;
+use std::sync;
+use std::sync::atomic;
+use std::sync::mpsc;
+use std::thread;
+
+use blueprints;
+use config;
+use economy;
+use entities;
+use entities::building;
+use entities::citizen;
+use entities::item;
+use entities::player;
+use entities::vehicle;
+use fire;
+use mods;
+use space;
+use space::ground;
+use space::sounds;
+use sulphate;
+use sulphate::WorldView;
+use sulphate::persist;
+use sulphate::persist::journal;
+use sulphate::rng;
+use units;
+
+/// Everything a save file needs, as of `time`.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub time: units::Time,
+    ground: ground::Ground,
+    entities: Vec<persist::SavedEntity>,
+    money: i64,
+    seed: u64,
+    drawn: Vec<(sulphate::EntityId, u64)>,
+    burning: Vec<sulphate::EntityId>,
+    mods: Vec<mods::Stamp>,
+    keys: u64,
+}
+
+impl Snapshot {
+    pub fn take(world: &sulphate::World, now: units::Time) -> Snapshot {
+        let entities = world.space
+            .snapshot(now)
+            .into_iter()
+            .flat_map(|body| {
+                let responding = world.matter
+                                      .get::<vehicle::Vehicle>(body.uid.id)
+                                      .and_then(vehicle::Vehicle::responding);
+                if responding.is_some() {
+                    return None;
+                }
+                let kind = persist::EntityKind::of(body.uid.ty);
+                if kind.is_none() {
+                    warn!("Entity of unknown type not saved");
+                }
+                let plans = world.matter
+                                 .get::<citizen::Citizen>(body.uid.id)
+                                 .map(citizen::Citizen::plans);
+                let blueprint = world.matter
+                                     .get::<building::Building>(body.uid.id)
+                                     .map(building::Building::blueprint);
+                let made = blueprints::made_as(&world.matter, body.uid.id);
+                let stops = world.matter
+                                 .get::<vehicle::Vehicle>(body.uid.id)
+                                 .map(|vehicle| vehicle.stops().to_vec());
+                let appearance = world.matter
+                                      .get::<player::Player>(body.uid.id)
+                                      .map(player::Player::appearance)
+                                      .cloned();
+                let inventory = appearance.as_ref().map(|_| {
+                    world.matter
+                         .component::<item::Inventory>(body.uid.id)
+                         .map_or(Vec::new(), |inv| inv.items().to_vec())
+                });
+                let item = world.matter
+                                .get::<item::Item>(body.uid.id)
+                                .map(item::Item::kind);
+                let citizen = world.matter
+                                   .get::<citizen::Citizen>(body.uid.id);
+                let waypoints = citizen.map(|citizen| {
+                    citizen.route().to_vec()
+                });
+                let vehicle = world.matter
+                                   .get::<vehicle::Vehicle>(body.uid.id);
+                let route = vehicle.map(|vehicle| {
+                    let (stop, tiles) = vehicle.route();
+                    (stop as u64, tiles.to_vec())
+                });
+                let spreading = world.matter
+                                     .get::<building::Building>(body.uid.id)
+                                     .and_then(building::Building::spreading);
+                let due = citizen.and_then(citizen::Citizen::due)
+                                 .or_else(|| {
+                                     vehicle.and_then(vehicle::Vehicle::due)
+                                 })
+                                 .or_else(|| {
+                                     spreading.and_then(
+                                         sulphate::EventHandle::due,
+                                     )
+                                 });
+                kind.map(|kind| persist::SavedEntity {
+                    id: body.uid.id,
+                    kind,
+                    position: body.position,
+                    velocity: body.velocity,
+                    plans,
+                    blueprint,
+                    made,
+                    stops,
+                    appearance,
+                    inventory,
+                    item,
+                    due,
+                    waypoints,
+                    route,
+                })
+            })
+            .collect::<Vec<persist::SavedEntity>>();
+        let burning = entities
+            .iter()
+            .filter(|entity| entity.kind == persist::EntityKind::Building)
+            .filter(|entity| fire::is_burning(&world.matter, entity.id))
+            .map(|entity| entity.id)
+            .collect();
+        let money = economy::balances(&world.matter).money;
+        let (seed, drawn) = match rng::get(&world.matter) {
+            Some(state) => (state.seed(), state.drawn()),
+            None => (rng::DEFAULT_SEED, Vec::new()),
+        };
+        Snapshot {
+            time: now,
+            ground: world.space.ground(),
+            entities,
+            money,
+            seed,
+            drawn,
+            burning,
+            mods: mods::active(&world.matter),
+            keys: world.matter.keys_drawn(),
+        }
+    }
+
+    /// What `persist::SavedWorld::write` writes out, which takes as long as
+    /// the city is big.
+    pub fn saved(self: Self) -> persist::SavedWorld {
+        persist::SavedWorld {
+            time: self.time,
+            entities: self.entities,
+            roads: self.ground.roads.segments(),
+            money: self.money,
+            zones: self.ground.zones.tiles(),
+            seed: self.seed,
+            drawn: self.drawn,
+            pipes: self.ground.pipes.segments(),
+            burning: self.burning,
+            mods: self.mods,
+            keys: self.keys,
+        }
+    }
+}
+
+enum Job {
+    Save(path::PathBuf, Snapshot),
+    Append(path::PathBuf, Snapshot),
+}
+
+// keeps each journal open between appends, so that it remembers what it
+// last wrote, and opens it again after something goes wrong, so that a
+// generation left half written gets cut off
+fn append(
+    journals: &mut collections::BTreeMap<path::PathBuf, journal::Journal>,
+    path: path::PathBuf,
+    snapshot: Snapshot,
+) {
+    if !journals.contains_key(&path) {
+        match journal::Journal::open(&path) {
+            Ok(journal) => {
+                journals.insert(path.clone(), journal);
+            },
+            Err(e) => {
+                error!("Failed to open {}: {}", path.display(), e);
+                return;
+            },
+        }
+    }
+    let appended = journals
+        .get_mut(&path)
+        .expect("journal went missing")
+        .append(&snapshot.saved());
+    if let Err(e) = appended {
+        error!("Failed to append to {}: {}", path.display(), e);
+        journals.remove(&path);
+    }
+}
+
+/// Writes snapshots to disk on a thread of its own, one after another in
+/// the order they were taken, which it starts the first time it is given
+/// one. Dropping it waits for everything it was given to be written.
+pub struct Writer {
+    jobs: Option<mpsc::Sender<Job>>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl Writer {
+    pub fn new() -> Writer {
+        Writer { jobs: None, thread: None }
+    }
+
+    /// Hands `snapshot` over to be saved to `path`, giving whether the
+    /// writing thread is still there to do it.
+    pub fn write(
+        self: &mut Self,
+        path: path::PathBuf,
+        snapshot: Snapshot,
+    ) -> bool {
+        self.send(Job::Save(path, snapshot))
+    }
+
+    /// Hands `snapshot` over to be appended to the journal at `path`, the
+    /// same as `write`.
+    pub fn append(
+        self: &mut Self,
+        path: path::PathBuf,
+        snapshot: Snapshot,
+    ) -> bool {
+        self.send(Job::Append(path, snapshot))
+    }
+
+    fn send(self: &mut Self, job: Job) -> bool {
+        if self.jobs.is_none() {
+            let (jobs, recv) = mpsc::channel::<Job>();
+            let thread = thread::spawn(move || {
+                let mut journals = collections::BTreeMap::new();
+                for job in recv {
+                    match job {
+                        Job::Save(path, snapshot) => {
+                            let saved = snapshot.saved();
+                            if let Err(e) = persist::save(&path, &saved) {
+                                error!(
+                                    "Failed to save to {}: {}",
+                                    path.display(), e,
+                                );
+                            }
+                        },
+                        Job::Append(path, snapshot) => {
+                            append(&mut journals, path, snapshot);
+                        },
+                    }
+                }
+            });
+            self.jobs = Some(jobs);
+            self.thread = Some(thread);
+        }
+        let jobs = self.jobs.as_ref().expect("Writer started without jobs");
+        jobs.send(job).is_ok()
+    }
+}
+
+impl Drop for Writer {
+    fn drop(self: &mut Self) {
+        // the thread finishes once it runs out of jobs
+        self.jobs = None;
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                error!("Save thread panicked");
+            }
+        }
+    }
+}
+
+/// Everything a `WorldView` shows, as of `time`.
+pub struct ViewSnapshot {
+    pub time: units::Time,
+    entities: Vec<sulphate::ViewedEntity>,
+    balances: economy::Balances,
+    ground: ground::Ground,
+    traffic: Vec<((space::TileId, space::TileId), i64)>,
+    sounds: Vec<sounds::Heard>,
+}
+
+impl ViewSnapshot {
+    pub fn take(world: &sulphate::World, now: units::Time) -> ViewSnapshot {
+        let entities = world.space
+            .snapshot(now)
+            .into_iter()
+            .flat_map(|body| {
+                let image = entities::image_of(&world.matter, body.uid);
+                image.map(|image| sulphate::ViewedEntity {
+                    uid: body.uid,
+                    image,
+                    position: body.position,
+                    velocity: body.velocity,
+                })
+            })
+            .collect();
+        let capacity = config::get(&world.matter).road_capacity;
+        ViewSnapshot {
+            time: now,
+            entities,
+            balances: economy::balances(&world.matter),
+            ground: world.space.ground(),
+            traffic: world.space.traffic().congestion(capacity),
+            sounds: world.space.sounds().recent(now),
+        }
+    }
+
+    pub fn view(self: Self) -> WorldView {
+        WorldView {
+            time: self.time,
+            entities: self.entities,
+            balances: self.balances,
+            terrain: self.ground.terrain.tiles(),
+            roads: self.ground.roads.segments(),
+            zones: self.ground.zones.tiles(),
+            pipes: self.ground.pipes.segments(),
+            traffic: self.traffic,
+            sounds: self.sounds,
+        }
+    }
+}
+
+// who is subscribed, along with how many of them there are, for checking
+// without waiting on the publishing thread to let go of the list
+struct Subscribers {
+    list: sync::Mutex<Vec<mpsc::Sender<WorldView>>>,
+    count: atomic::AtomicUsize,
+}
+
+impl Subscribers {
+    // a panic while sending views can't leave the list half updated,
+    // so a poisoned lock is still fine to use
+    fn lock(self: &Self) -> sync::MutexGuard<Vec<mpsc::Sender<WorldView>>> {
+        self.list.lock().unwrap_or_else(|poison| poison.into_inner())
+    }
+}
+
+/// Turns view snapshots into views for every subscriber on a thread of its
+/// own, which it starts the first time it is given one, and drops
+/// subscribers as they go away.
+pub struct Publisher {
+    subscribers: sync::Arc<Subscribers>,
+    // only ever holds the snapshot waiting to be published, so that a
+    // publisher that has fallen behind skips views rather than piling
+    // them up
+    snapshots: Option<mpsc::SyncSender<ViewSnapshot>>,
+    publishing: bool,
+}
+
+impl Publisher {
+    pub fn new() -> Publisher {
+        let subscribers = sync::Arc::new(Subscribers {
+            list: sync::Mutex::new(Vec::new()),
+            count: atomic::AtomicUsize::new(0),
+        });
+        Publisher { subscribers, snapshots: None, publishing: false }
+    }
+
+    /// Adds `subscriber`, giving whether views need to start being
+    /// published, since nothing was being published before.
+    pub fn subscribe(
+        self: &mut Self,
+        subscriber: mpsc::Sender<WorldView>,
+    ) -> bool {
+        let mut list = self.subscribers.lock();
+        list.push(subscriber);
+        self.subscribers.count.store(list.len(), atomic::Ordering::SeqCst);
+        let start = !self.publishing;
+        self.publishing = true;
+        start
+    }
+
+    /// Hands `snapshot` over to be sent to every subscriber, unless the
+    /// last one still hasn't been sent, giving whether there is still
+    /// anyone to publish to.
+    pub fn publish(self: &mut Self, snapshot: ViewSnapshot) -> bool {
+        if self.subscribers.count.load(atomic::Ordering::SeqCst) == 0 {
+            self.publishing = false;
+            return false;
+        }
+        if self.snapshots.is_none() {
+            let (snapshots, recv) = mpsc::sync_channel::<ViewSnapshot>(1);
+            let subscribers = self.subscribers.clone();
+            thread::spawn(move || {
+                for snapshot in recv {
+                    let view = snapshot.view();
+                    let mut list = subscribers.lock();
+                    list.retain(|sub| sub.send(view.clone()).is_ok());
+                    let count = list.len();
+                    subscribers.count.store(count, atomic::Ordering::SeqCst);
+                }
+            });
+            self.snapshots = Some(snapshots);
+        }
+        let sent = self.snapshots.as_ref().map(|s| s.try_send(snapshot));
+        if let Some(Err(mpsc::TrySendError::Disconnected(_))) = sent {
+            warn!("Publishing thread stopped, no longer publishing");
+            self.snapshots = None;
+            self.subscribers.lock().clear();
+            self.subscribers.count.store(0, atomic::Ordering::SeqCst);
+            self.publishing = false;
+            return false;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod test_snapshot {
+    use std::time;
+
+    use super::*;
+
+    #[test]
+    fn snapshots_keep_to_when_they_were_taken() {
+        let (mut world, ()) = sulphate::World::new(
+            Default::default(),
+            Box::new(sulphate::server::ManualClock::new(Default::default())),
+            |_, _, _| (),
+        );
+        let now = Default::default();
+        let before = Snapshot::take(&world, now);
+        let from = units::Position::default();
+        let offset = units::Displacement {
+            x: (space::TILE_SIZE * 2.0).into(),
+            y: 0.into(),
+        };
+        assert!(world.space.build_road(from, from + offset));
+        let after = Snapshot::take(&world, now);
+        assert!(before.saved().roads.is_empty());
+        assert_eq!(after.saved().roads.len(), 2);
+    }
+
+    #[test]
+    fn views_reach_every_subscriber() {
+        let (world, ()) = sulphate::World::new(
+            Default::default(),
+            Box::new(sulphate::server::ManualClock::new(Default::default())),
+            |_, _, _| (),
+        );
+        let mut publisher = Publisher::new();
+        let now = Default::default();
+        assert!(!publisher.publish(ViewSnapshot::take(&world, now)));
+
+        let (first, first_views) = mpsc::channel();
+        let (second, second_views) = mpsc::channel();
+        assert!(publisher.subscribe(first));
+        assert!(!publisher.subscribe(second));
+        assert!(publisher.publish(ViewSnapshot::take(&world, now)));
+        let wait = time::Duration::from_secs(1);
+        assert!(first_views.recv_timeout(wait).is_ok());
+        assert!(second_views.recv_timeout(wait).is_ok());
+    }
+}
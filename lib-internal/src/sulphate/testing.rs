@@ -0,0 +1,200 @@
+use std::sync::mpsc;
+
+use sulphate_lib;
+
+use space;
+use sulphate;
+use sulphate::server;
+use units;
+
+/// Runs a world the way the server would, but on the calling thread and by
+/// a clock that only moves when told to, so that tests of gameplay come out
+/// the same on every run, however slow the machine is.
+///
+/// Interruptions are applied as soon as they are sent, and events happen
+/// as the clock is advanced past them. Views for subscribers aren't
+/// published, since they go through the server's own queue.
+pub struct TestServer {
+    world: sulphate::World,
+    clock: server::ManualClock,
+    // for what the world sends itself, such as autosaves and shutdowns
+    wake: mpsc::Sender<server::Interruption>,
+    woken: mpsc::Receiver<server::Interruption>,
+}
+
+impl TestServer {
+    /// Starts a world at the start of time, set up by `f` in the same way
+    /// that `server::start_server` sets one up.
+    pub fn new<F, R>(f: F) -> (TestServer, R)
+        where F: FnOnce(
+                     &mut space::CollisionSpace,
+                     &mut sulphate::EventQueue,
+                     &mut sulphate::EntityHeap,
+                 ) -> R,
+    {
+        TestServer::starting_at(Default::default(), f)
+    }
+
+    pub fn starting_at<F, R>(start: units::Time, f: F) -> (TestServer, R)
+        where F: FnOnce(
+                     &mut space::CollisionSpace,
+                     &mut sulphate::EventQueue,
+                     &mut sulphate::EntityHeap,
+                 ) -> R,
+    {
+        let clock = server::ManualClock::new(start);
+        let control = Box::new(clock.clone());
+        let (world, r) = sulphate::World::new(start, control, f);
+        let (wake, woken) = mpsc::channel();
+        (TestServer { world, clock, wake, woken }, r)
+    }
+
+    pub fn now(self: &Self) -> units::Time {
+        self.world.queue.now()
+    }
+
+    /// The sender to give interruptions that need one to be woken by, such
+    /// as `StartAutosave` and `Shutdown`.
+    pub fn wake(self: &Self) -> mpsc::Sender<server::Interruption> {
+        self.wake.clone()
+    }
+
+    /// Applies `interruption` straight away, giving whether it would have
+    /// stopped the server.
+    pub fn send(self: &mut Self, interruption: server::Interruption) -> bool {
+        let mut stop = self.apply(interruption);
+        stop |= self.deliver();
+        stop
+    }
+
+    /// Moves the clock on by `by`, as it is scaled or paused, and runs
+    /// every event that comes due on the way, in order.
+    pub fn advance_by(self: &mut Self, by: units::Duration) {
+        self.clock.advance(by);
+        let until = self.clock.now();
+        // stopping at each time that something is due, so that whatever
+        // the world sends itself is applied before anything later happens
+        loop {
+            let next = match sulphate::queue::next_due(&self.world.queue) {
+                Some(next) if next <= until => next,
+                _ => break,
+            };
+            sulphate::queue::run_due_by(&mut self.world, next);
+            self.deliver();
+        }
+        sulphate::queue::run_due_by(&mut self.world, until);
+    }
+
+    pub fn world(self: &Self) -> &sulphate::World {
+        &self.world
+    }
+
+    pub fn world_mut(self: &mut Self) -> &mut sulphate::World {
+        &mut self.world
+    }
+
+    pub fn space(self: &Self) -> &space::CollisionSpace {
+        &self.world.space
+    }
+
+    pub fn matter(self: &Self) -> &sulphate::EntityHeap {
+        &self.world.matter
+    }
+
+    pub fn matter_mut(self: &mut Self) -> &mut sulphate::EntityHeap {
+        &mut self.world.matter
+    }
+
+    fn apply(self: &mut Self, interruption: server::Interruption) -> bool {
+        // only there for the interruption to read the time from, since
+        // nothing here waits on the server's queue
+        let mut raw = sulphate::RawQueue::new(self.now());
+        sulphate_lib::server::Interruption::update(
+            interruption,
+            &mut raw,
+            &mut self.world,
+        )
+    }
+
+    // applies what the world has sent itself, giving whether any of it
+    // would have stopped the server
+    fn deliver(self: &mut Self) -> bool {
+        let mut stop = false;
+        while let Ok(interruption) = self.woken.try_recv() {
+            stop |= self.apply(interruption);
+        }
+        stop
+    }
+}
+
+#[cfg(test)]
+mod test_testing {
+    use std::sync::mpsc;
+
+    use space;
+    use sulphate;
+    use sulphate::server::Interruption;
+    use sulphate::tick;
+    use sulphate::tick::TickScheduler;
+    use units;
+
+    use super::*;
+
+    struct Counter {
+        ticks: mpsc::Sender<units::Time>,
+    }
+
+    impl tick::System for Counter {
+        fn tick(
+            self: &mut Self,
+            _space: &mut space::CollisionSpace,
+            time: &mut sulphate::EventQueue,
+            _matter: &mut sulphate::EntityHeap,
+        ) -> bool {
+            self.ticks.send(time.now()).unwrap();
+            true
+        }
+    }
+
+    #[test]
+    fn events_happen_as_the_clock_passes_them() {
+        let (mut server, ticks) = TestServer::new(|_space, time, _matter| {
+            let (send, recv) = mpsc::channel();
+            time.every(units::moments(2), Counter { ticks: send });
+            recv
+        });
+        let start = server.now();
+
+        server.advance_by(units::moments(1));
+        assert!(ticks.try_recv().is_err());
+        server.advance_by(units::moments(4));
+        assert!(server.now() == start + units::moments(5));
+        let seen: Vec<_> = ticks.try_iter().collect();
+        assert_eq!(seen.len(), 2);
+        assert!(seen[0] == start + units::moments(2));
+        assert!(seen[1] == start + units::moments(4));
+
+        // paused, the clock doesn't move, so neither does anything else
+        server.send(Interruption::PauseWorld { paused: true });
+        server.advance_by(units::moments(4));
+        assert!(ticks.try_recv().is_err());
+    }
+
+    #[test]
+    fn shutdowns_come_back_on_their_own() {
+        let (mut server, ()) = TestServer::new(|_space, _time, _matter| ());
+        let deadline = server.now() + units::moments(3);
+        let (reply, world) = mpsc::channel();
+        let wake = server.wake();
+        let stop = server.send(Interruption::Shutdown {
+            deadline,
+            wake,
+            reply,
+        });
+        assert!(!stop);
+        server.advance_by(units::moments(2));
+        assert!(world.try_recv().is_err());
+        server.advance_by(units::moments(2));
+        assert!(world.try_recv().is_ok());
+    }
+}
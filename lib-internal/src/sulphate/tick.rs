@@ -0,0 +1,196 @@
+use space;
+use sulphate;
+use units;
+
+/// Something that is run at a fixed rate instead of in response to events,
+/// like traffic or the economy.
+pub trait System: 'static + Send {
+    /// Returns false once the system should stop being run.
+    fn tick(
+        self: &mut Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) -> bool;
+}
+
+impl<F> System for F
+    where F: 'static + Send
+           + FnMut(
+                 &mut space::CollisionSpace,
+                 &mut sulphate::EventQueue,
+                 &mut sulphate::EntityHeap,
+             ) -> bool
+{
+    fn tick(
+        self: &mut Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) -> bool {
+        self(space, time, matter)
+    }
+}
+
+/// Looks up how long a system waits between ticks, for systems whose rate
+/// can be changed while they run.
+pub type Tunable = fn(&sulphate::EntityHeap) -> units::Duration;
+
+pub trait TickScheduler {
+    /// Runs `system` every `period` of game time, starting one period from
+    /// now.
+    fn every<S: System>(self: &mut Self, period: units::Duration, system: S);
+
+    /// Runs `system` every so often, as `period` says, looking it up again
+    /// after every tick.
+    fn every_tunable<S: System>(
+        self: &mut Self,
+        period: Tunable,
+        matter: &sulphate::EntityHeap,
+        system: S,
+    );
+}
+
+fn schedule<S: System>(
+    time: &mut sulphate::EventQueue,
+    period: Period,
+    delay: units::Duration,
+    system: S,
+) {
+    if delay <= 0 {
+        warn!("Ignored system with non-positive period {}", delay);
+        return;
+    }
+    sulphate::enqueue_relative(time, TickEvent { period, system }, delay);
+}
+
+impl TickScheduler for sulphate::EventQueue {
+    fn every<S: System>(self: &mut Self, period: units::Duration, system: S) {
+        schedule(self, Period::Fixed(period), period, system);
+    }
+
+    fn every_tunable<S: System>(
+        self: &mut Self,
+        period: Tunable,
+        matter: &sulphate::EntityHeap,
+        system: S,
+    ) {
+        let delay = period(matter);
+        schedule(self, Period::Tunable(period), delay, system);
+    }
+}
+
+enum Period {
+    Fixed(units::Duration),
+    Tunable(Tunable),
+}
+
+// each tick schedules the next, so ticks stay exactly a period apart
+struct TickEvent<S: System> {
+    period: Period,
+    system: S,
+}
+
+impl<S: System> sulphate::Event for TickEvent<S> {
+    fn invoke(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        let TickEvent { period, mut system } = self;
+        if system.tick(space, time, matter) {
+            let delay = match period {
+                Period::Fixed(period) => period,
+                Period::Tunable(ref period) => period(matter),
+            };
+            schedule(time, period, delay, system);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_tick {
+    use std::sync::mpsc;
+    use std::time;
+
+    use space;
+    use sulphate;
+    use sulphate::server;
+    use units;
+
+    use super::*;
+
+    struct Counter {
+        left: u32,
+        ticks: mpsc::Sender<units::Time>,
+    }
+
+    impl System for Counter {
+        fn tick(
+            self: &mut Self,
+            _space: &mut space::CollisionSpace,
+            time: &mut sulphate::EventQueue,
+            _matter: &mut sulphate::EntityHeap,
+        ) -> bool {
+            self.ticks.send(time.now()).unwrap();
+            self.left -= 1;
+            self.left > 0
+        }
+    }
+
+    #[test]
+    fn ticks_at_fixed_rate_until_stopped() {
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, mut clock, handle, ticks) =
+            server::start_server_with_clock(move |_space, time, _matter| {
+                let (send, recv) = mpsc::channel();
+                let counter = Counter { left: 3, ticks: send };
+                time.every(units::moments(1), counter);
+                recv
+            }, clock);
+
+        clock.advance(units::moments(8));
+        let wait = time::Duration::from_secs(1);
+        for n in 1..4 {
+            let when = ticks.recv_timeout(wait).expect("system never ticked");
+            assert!(when == start + units::moments(n));
+        }
+        assert!(ticks.recv_timeout(wait).is_err(), "system didn't stop");
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+
+    struct Gap(units::Duration);
+
+    fn gap(matter: &sulphate::EntityHeap) -> units::Duration {
+        matter.get::<Gap>(0).map_or(units::moments(1), |gap| gap.0)
+    }
+
+    #[test]
+    fn tunable_periods_are_looked_up_each_tick() {
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, mut clock, handle, ticks) =
+            server::start_server_with_clock(move |_space, time, matter| {
+                let (send, recv) = mpsc::channel();
+                let counter = Counter { left: 3, ticks: send };
+                time.every_tunable(gap, matter, counter);
+                // takes effect once the first tick is done
+                matter.insert(0, Gap(units::moments(2)));
+                recv
+            }, clock);
+
+        clock.advance(units::moments(8));
+        let wait = time::Duration::from_secs(1);
+        for &n in &[1, 3, 5] {
+            let when = ticks.recv_timeout(wait).expect("system never ticked");
+            assert!(when == start + units::moments(n));
+        }
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+}
@@ -1,3 +1,5 @@
+use std::cmp;
+
 use lib_math;
 
 pub type Time = lib_math::Coord;
@@ -9,6 +11,10 @@ pub type Distance = lib_math::Scalar;
 pub type Scalar = lib_math::Scalar;
 pub type Coord = lib_math::Coord;
 
+/// A direction, with sin and cos looked up in a table rather than worked
+/// out with floats, so that they come out the same on every machine.
+pub type Angle = lib_math::Angle;
+
 // meant to be a minimal unit of time for rendering
 // things that ought to be seen, will last at least this long
 // so by keeping the framerate above 16, these things will be seen!
@@ -32,3 +38,98 @@ pub type Position = lib_math::Position;
 // velocity, and don't want something misleading such as
 // units::Displacement::inner(velocity, displacement)
 pub type Vector = lib_math::Vector;
+
+/// A disc, for asking what is near a point.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Circle {
+    pub center: Position,
+    pub radius: Distance,
+}
+
+impl Circle {
+    /// Whether `point` is strictly inside.
+    pub fn contains(self: &Self, point: Position) -> bool {
+        point.distance_squared(self.center) < self.radius.squared()
+    }
+
+    /// Whether this overlaps `other` by any amount.
+    pub fn overlaps(self: &Self, other: &Circle) -> bool {
+        let reach = self.radius + other.radius;
+        self.center.distance_squared(other.center) < reach.squared()
+    }
+
+    /// The smallest box around the circle.
+    pub fn bounds(self: &Self) -> Rect {
+        let corner = Vector { x: self.radius, y: self.radius };
+        Rect { min: self.center - corner, max: self.center + corner }
+    }
+}
+
+/// The box with corners `min` and `max`, including its bottom and left
+/// edges but not its top and right, so that boxes side by side don't
+/// overlap.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Rect {
+    pub min: Position,
+    pub max: Position,
+}
+
+impl Rect {
+    /// The box with `a` and `b` at opposite corners, whichever way round.
+    pub fn from_corners(a: Position, b: Position) -> Rect {
+        let min = Position { x: cmp::min(a.x, b.x), y: cmp::min(a.y, b.y) };
+        let max = Position { x: cmp::max(a.x, b.x), y: cmp::max(a.y, b.y) };
+        Rect { min, max }
+    }
+
+    pub fn contains(self: &Self, point: Position) -> bool {
+        point.x >= self.min.x && point.x < self.max.x
+            && point.y >= self.min.y && point.y < self.max.y
+    }
+
+    /// The point in the box closest to `point`.
+    pub fn clamp(self: &Self, point: Position) -> Position {
+        let x = cmp::max(self.min.x, cmp::min(point.x, self.max.x));
+        let y = cmp::max(self.min.y, cmp::min(point.y, self.max.y));
+        Position { x, y }
+    }
+
+    /// Whether `circle` reaches into the box.
+    pub fn overlaps_circle(self: &Self, circle: &Circle) -> bool {
+        let closest = self.clamp(circle.center);
+        closest.distance_squared(circle.center) < circle.radius.squared()
+    }
+
+    pub fn center(self: &Self) -> Position {
+        self.min.lerp(self.max, Scalar::from(1) / 2)
+    }
+}
+
+#[cfg(test)]
+mod test_shapes {
+    use super::*;
+
+    fn at(x: i32, y: i32) -> Position {
+        Position::default() + Displacement { x: x.into(), y: y.into() }
+    }
+
+    #[test]
+    fn circles_and_boxes_agree() {
+        let circle = Circle { center: at(0, 0), radius: 10.into() };
+        assert!(circle.contains(at(6, 7)));
+        assert!(!circle.contains(at(10, 0)));
+        let other = Circle { center: at(15, 0), radius: 6.into() };
+        assert!(circle.overlaps(&other));
+
+        let rect = Rect::from_corners(at(20, 10), at(0, 0));
+        assert_eq!(rect, Rect { min: at(0, 0), max: at(20, 10) });
+        assert!(rect.contains(at(0, 0)) && !rect.contains(at(20, 5)));
+        assert_eq!(rect.clamp(at(30, -5)), at(20, 0));
+        assert_eq!(rect.center(), at(10, 5));
+        let near = Circle { center: at(25, 5), radius: 6.into() };
+        assert!(rect.overlaps_circle(&near));
+        assert!(!rect.overlaps_circle(&Circle { radius: 5.into(), ..near }));
+        let bounds = Rect::from_corners(at(-10, -10), at(10, 10));
+        assert_eq!(circle.bounds(), bounds);
+    }
+}
@@ -0,0 +1,296 @@
+use std::any;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use economy;
+use entities::building;
+use space;
+use space::TileId;
+use space::roads::RoadNetwork;
+use sulphate;
+use sulphate::jobs;
+use units;
+
+/// Something that buildings share out over a grid, as carried along lines
+/// of its own.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Utility {
+    /// Carried along the roads.
+    Power,
+    /// Carried along pipes.
+    Water,
+}
+
+impl Utility {
+    pub fn all() -> [Utility; 2] {
+        [Utility::Power, Utility::Water]
+    }
+
+    pub fn name(self: Self) -> &'static str {
+        match self {
+            Utility::Power => "power",
+            Utility::Water => "water",
+        }
+    }
+
+    /// The lines that the utility is carried along.
+    pub fn lines(self: Self, space: &space::CollisionSpace) -> &RoadNetwork {
+        match self {
+            Utility::Power => space.roads(),
+            Utility::Water => space.pipes(),
+        }
+    }
+
+    /// How much of the utility is in `resources`.
+    pub fn of(self: Self, resources: economy::Resources) -> i64 {
+        match self {
+            Utility::Power => resources.power,
+            Utility::Water => resources.water,
+        }
+    }
+
+    /// Whether `coverage` says this utility was got.
+    pub fn covered(self: Self, coverage: building::Coverage) -> bool {
+        match self {
+            Utility::Power => coverage.power,
+            Utility::Water => coverage.water,
+        }
+    }
+}
+
+/// Buildings that share whatever of a utility any of them make, being
+/// those whose lines join up, along with the lines that join them. A
+/// building with no line beside it is on a network of its own.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Network {
+    /// In id order.
+    pub buildings: Vec<sulphate::EntityId>,
+    pub lines: BTreeSet<TileId>,
+}
+
+// every building that takes part in the economy as of `now`, in id order,
+// and the tile it stands on
+fn buildings(
+    space: &space::CollisionSpace,
+    matter: &mut sulphate::EntityHeap,
+    now: units::Time,
+) -> Vec<(sulphate::EntityId, TileId)> {
+    let ty = any::TypeId::of::<building::Building>();
+    matter
+        .query::<(&economy::Flow,)>()
+        .into_iter()
+        .filter_map(|(id, _)| -> Option<_> {
+            let uid = sulphate::EntityUId { id, ty };
+            let position = space.position(uid, now)?;
+            Some((id, space.terrain().tile_id(position)))
+        })
+        .collect()
+}
+
+/// Every network of `utility` in the city as of `now`, found by flooding
+/// out from each building in turn along the lines beside it, and on
+/// through every other building those lines reach, which joins up any
+/// lines beside that one.
+pub fn networks(
+    space: &space::CollisionSpace,
+    matter: &mut sulphate::EntityHeap,
+    now: units::Time,
+    utility: Utility,
+) -> Vec<Network> {
+    flood(utility.lines(space), &buildings(space, matter, now))
+}
+
+/// The networks of each of `Utility::all`, in that order, as `networks`
+/// finds them, with each utility flooded as a job of its own.
+pub fn every_network(
+    space: &space::CollisionSpace,
+    matter: &mut sulphate::EntityHeap,
+    now: units::Time,
+) -> Vec<Vec<Network>> {
+    let buildings = buildings(space, matter, now);
+    let lines: Vec<&RoadNetwork> = Utility::all()
+        .iter()
+        .map(|&utility| utility.lines(space))
+        .collect();
+    jobs::run(lines, |lines| flood(lines, &buildings))
+}
+
+// only looks at the lines and where the buildings are, so that it can be
+// run as a job
+fn flood(
+    lines: &RoadNetwork,
+    buildings: &[(sulphate::EntityId, TileId)],
+) -> Vec<Network> {
+    // which of the buildings each line tile runs beside, or under
+    let mut beside: BTreeMap<TileId, Vec<usize>> = BTreeMap::new();
+    for (i, &(_, tile)) in buildings.iter().enumerate() {
+        for line in lines.access(tile) {
+            beside.entry(line).or_insert_with(Vec::new).push(i);
+        }
+    }
+
+    let mut joined = vec![false; buildings.len()];
+    let mut networks = Vec::new();
+    for start in 0..buildings.len() {
+        if joined[start] {
+            continue;
+        }
+        joined[start] = true;
+        let mut network = Network {
+            buildings: Vec::new(),
+            lines: BTreeSet::new(),
+        };
+        let mut open = vec![start];
+        while let Some(i) = open.pop() {
+            let (id, tile) = buildings[i];
+            network.buildings.push(id);
+            for at in lines.reachable(tile) {
+                if !network.lines.insert(at) {
+                    continue;
+                }
+                for &next in beside.get(&at).map_or(&[][..], |v| &v[..]) {
+                    if !joined[next] {
+                        joined[next] = true;
+                        open.push(next);
+                    }
+                }
+            }
+        }
+        network.buildings.sort();
+        networks.push(network);
+    }
+    networks
+}
+
+/// Which of `networks` each building is on.
+pub fn membership(
+    networks: &[Network],
+) -> BTreeMap<sulphate::EntityId, usize> {
+    let mut on = BTreeMap::new();
+    for (i, network) in networks.iter().enumerate() {
+        for &id in &network.buildings {
+            on.insert(id, i);
+        }
+    }
+    on
+}
+
+/// The network that the lines beside or under tile `id` belong to, if any
+/// of them belong to one.
+pub fn network_at(networks: &[Network], lines: &RoadNetwork, id: TileId)
+    -> Option<usize>
+{
+    let access = lines.access(id);
+    networks.iter().position(|network| {
+        access.iter().any(|tile| network.lines.contains(tile))
+    })
+}
+
+/// How much of `utility` the buildings on `network` had over after it was
+/// last shared out, which is what more buildings on it could use.
+pub fn spare(
+    matter: &sulphate::EntityHeap,
+    network: &Network,
+    utility: Utility,
+) -> i64 {
+    let mut spare = 0;
+    for &id in &network.buildings {
+        let supplied = matter.get::<building::Building>(id)
+                             .map_or(false, |building| !building.blackout());
+        let flow = match matter.component::<economy::Flow>(id) {
+            Some(flow) if supplied => flow,
+            _ => continue,
+        };
+        spare += utility.of(flow.makes) - utility.of(flow.uses);
+    }
+    spare
+}
+
+#[cfg(test)]
+mod test_utilities {
+    use entities::building::Blueprint;
+    use units;
+
+    use super::*;
+
+    fn at(x: i32, y: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: y.into() };
+        units::Position::default() + offset
+    }
+
+    // ids are drawn at random, so buildings come in no order that the test
+    // can know ahead
+    fn sorted(mut ids: Vec<sulphate::EntityId>) -> Vec<sulphate::EntityId> {
+        ids.sort();
+        ids
+    }
+
+    // which of `networks` has `id` in it
+    fn grid_of(networks: &[Network], id: sulphate::EntityId) -> usize {
+        networks.iter()
+                .position(|network| network.buildings.contains(&id))
+                .expect("building in no network")
+    }
+
+    #[test]
+    fn each_utility_goes_only_along_its_own_lines() {
+        let mut space = space::CollisionSpace::new();
+        let mut time = sulphate::EventQueue::new(Default::default());
+        let mut matter = sulphate::EntityHeap::new();
+        // along the rows of tiles from y = 0 and from y = 96
+        space.build_road(at(0, 10), at(130, 10));
+        space.build_road(at(0, 100), at(130, 100));
+        let (house, plant, tower, alone) = {
+            let mut place = |blueprint, x, y| {
+                let position = at(x, y);
+                building::place(
+                    &mut space, &mut time, &mut matter, blueprint, position,
+                ).expect("no room to build")
+            };
+            (
+                place(Blueprint::House, 0, 40),
+                place(Blueprint::PowerPlant, 100, 40),
+                place(Blueprint::WaterTower, 0, 130),
+                place(Blueprint::House, 300, 300),
+            )
+        };
+
+        let now = time.now();
+        let power = networks(&space, &mut matter, now, Utility::Power);
+        let mut buildings: Vec<_> =
+            power.iter().map(|network| network.buildings.clone()).collect();
+        buildings.sort();
+        let mut expected =
+            vec![sorted(vec![house, plant]), vec![tower], vec![alone]];
+        expected.sort();
+        assert_eq!(buildings, expected);
+        assert!(power[grid_of(&power, alone)].lines.is_empty());
+        let grid = &power[grid_of(&power, plant)];
+        assert_eq!(spare(&matter, grid, Utility::Power), 12);
+        // there is water in the city, but no pipe to the house
+        let water = networks(&space, &mut matter, now, Utility::Water);
+        assert_eq!(water.len(), 4);
+        let building = |matter: &sulphate::EntityHeap, id| {
+            let building = matter.get::<building::Building>(id).unwrap();
+            (building.blackout(), building.coverage())
+        };
+        let dry = building::Coverage { power: true, water: false };
+        assert_eq!(building(&matter, house), (true, dry));
+
+        // a pipe under both of them gets water to the house
+        space.lay_pipe(at(0, 130), at(0, 40));
+        economy::resupply(&space, &time, &mut matter);
+        let covered = building::Coverage::full();
+        assert_eq!(building(&matter, house), (false, covered));
+        let water = networks(&space, &mut matter, now, Utility::Water);
+        let piped = grid_of(&water, house);
+        assert_eq!(water[piped].buildings, sorted(vec![house, tower]));
+        assert_eq!(membership(&water)[&plant], grid_of(&water, plant));
+        assert!(grid_of(&water, plant) != piped);
+        let pipes = space.pipes();
+        assert_eq!(network_at(&water, pipes, (1, 2)), Some(piped));
+        assert_eq!(network_at(&power, space.roads(), (9, 8)), None);
+        let every = every_network(&space, &mut matter, now);
+        assert_eq!(every, vec![power, water]);
+    }
+}
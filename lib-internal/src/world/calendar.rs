@@ -0,0 +1,299 @@
+use std::sync::mpsc;
+
+use scripting;
+use space;
+use sulphate;
+use units;
+
+// the city's clock runs well ahead of a real one,
+// so that a whole day goes by in twelve minutes
+pub const HOURS_PER_DAY: i32 = 24;
+
+pub const DAYS_PER_SEASON: i64 = 7;
+
+pub fn hours(num: i32) -> units::Duration {
+    units::moments(num * 30 * units::MOMENT_RATE as i32)
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Season {
+    Spring,
+    Summer,
+    Autumn,
+    Winter,
+}
+
+impl Season {
+    pub fn name(self: Self) -> &'static str {
+        match self {
+            Season::Spring => "spring",
+            Season::Summer => "summer",
+            Season::Autumn => "autumn",
+            Season::Winter => "winter",
+        }
+    }
+
+    /// The hour that the sun comes up.
+    pub fn dawn(self: Self) -> i32 {
+        match self {
+            Season::Spring => 6,
+            Season::Summer => 5,
+            Season::Autumn => 7,
+            Season::Winter => 8,
+        }
+    }
+
+    /// The hour that the sun goes down.
+    pub fn dusk(self: Self) -> i32 {
+        match self {
+            Season::Spring => 19,
+            Season::Summer => 21,
+            Season::Autumn => 18,
+            Season::Winter => 17,
+        }
+    }
+}
+
+/// What the city's clock reads at some time, taking time zero to be
+/// midnight on the first day of spring.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Date {
+    /// Days since the first, which is day 0.
+    pub day: i64,
+    pub hour: i32,
+    pub minute: i32,
+    pub season: Season,
+}
+
+// whole bits, so that this rounds the same way everywhere
+fn day_bits() -> i64 {
+    hours(HOURS_PER_DAY).into_bits()
+}
+
+fn elapsed_bits(time: units::Time) -> i64 {
+    (time - units::Time::default()).into_bits()
+}
+
+fn modulo(a: i64, b: i64) -> i64 {
+    ((a % b) + b) % b
+}
+
+fn season_of_day(day: i64) -> Season {
+    match modulo(day / DAYS_PER_SEASON, 4) {
+        0 => Season::Spring,
+        1 => Season::Summer,
+        2 => Season::Autumn,
+        _ => Season::Winter,
+    }
+}
+
+fn day_of(time: units::Time) -> i64 {
+    let elapsed = elapsed_bits(time);
+    (elapsed - modulo(elapsed, day_bits())) / day_bits()
+}
+
+// midnight at the start of `day`
+fn midnight(day: i64) -> units::Time {
+    units::Time::default() + units::instants(day * day_bits())
+}
+
+pub fn date(time: units::Time) -> Date {
+    let day = day_of(time);
+    let since_midnight = modulo(elapsed_bits(time), day_bits());
+    let minute_bits = hours(1).into_bits() / 60;
+    let minutes = (since_midnight / minute_bits) as i32;
+    Date {
+        day,
+        hour: minutes / 60,
+        minute: minutes % 60,
+        season: season_of_day(day),
+    }
+}
+
+/// How far through the day it is, in hours.
+pub fn hour_of_day(time: units::Time) -> f64 {
+    let since_midnight = modulo(elapsed_bits(time), day_bits());
+    since_midnight as f64 / hours(1).into_bits() as f64
+}
+
+/// The first time after `now` that the city's clock reads `of_day` past
+/// midnight.
+pub fn next_time_of_day(
+    now: units::Time,
+    of_day: units::Duration,
+) -> units::Time {
+    let day = day_bits();
+    let elapsed = elapsed_bits(now);
+    let mut at = elapsed - modulo(elapsed, day) + of_day.into_bits();
+    if at <= elapsed {
+        at += day;
+    }
+    units::Time::default() + units::instants(at)
+}
+
+/// How light it is at `time`, from 0 in the dead of night to 1 in the day,
+/// getting lighter over the hour after dawn and darker over the hour
+/// before dusk.
+pub fn daylight(time: units::Time) -> f64 {
+    let season = date(time).season;
+    let hour = hour_of_day(time);
+    let dawn = season.dawn() as f64;
+    let dusk = season.dusk() as f64;
+    (hour - dawn).min(dusk - hour).max(0.0).min(1.0)
+}
+
+/// The sun coming up or going down.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sun {
+    Dawn,
+    Dusk,
+}
+
+/// Whichever of dawn or dusk comes next after `now`, and when.
+pub fn next_sun(now: units::Time) -> (Sun, units::Time) {
+    let today = day_of(now);
+    let mut changes = Vec::new();
+    for day in today..today + 2 {
+        let season = season_of_day(day);
+        changes.push((Sun::Dawn, midnight(day) + hours(season.dawn())));
+        changes.push((Sun::Dusk, midnight(day) + hours(season.dusk())));
+    }
+    changes.into_iter()
+           .filter(|&(_, at)| elapsed_bits(at) > elapsed_bits(now))
+           .min_by_key(|&(_, at)| elapsed_bits(at))
+           .expect("a day without a dawn or a dusk")
+}
+
+/// Whether the sun is up, and who wants to hear when that changes, kept in
+/// the heap as the only entity of its type.
+pub struct Sky {
+    daylight: bool,
+    watchers: Vec<mpsc::Sender<(units::Time, Sun)>>,
+}
+
+const SKY: sulphate::EntityId = 0;
+
+impl Sky {
+    pub fn is_day(self: &Self) -> bool {
+        self.daylight
+    }
+}
+
+/// Starts firing events at every dawn and dusk, if they aren't already.
+pub fn start(
+    time: &mut sulphate::EventQueue,
+    matter: &mut sulphate::EntityHeap,
+) {
+    if matter.get::<Sky>(SKY).is_some() {
+        return;
+    }
+    let (sun, at) = next_sun(time.now());
+    // whatever comes next, it is currently the other
+    let daylight = sun == Sun::Dusk;
+    let watchers = Vec::new();
+    matter.insert(SKY, Sky { daylight, watchers });
+    time.enqueue_absolute(SunEvent { sun }, at);
+}
+
+/// Sends `(time, sun)` to `watcher` at every dawn and dusk from now on,
+/// until it is dropped.
+pub fn watch(
+    matter: &mut sulphate::EntityHeap,
+    watcher: mpsc::Sender<(units::Time, Sun)>,
+) {
+    match matter.get_mut::<Sky>(SKY) {
+        Some(sky) => sky.watchers.push(watcher),
+        None => warn!("Watched a sky that was never started"),
+    }
+}
+
+pub fn is_day(matter: &sulphate::EntityHeap) -> bool {
+    matter.get::<Sky>(SKY).map_or(true, Sky::is_day)
+}
+
+struct SunEvent {
+    sun: Sun,
+}
+
+impl sulphate::Event for SunEvent {
+    fn invoke(
+        self: Self,
+        _space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) {
+        let now = time.now();
+        if let Some(sky) = matter.get_mut::<Sky>(SKY) {
+            sky.daylight = self.sun == Sun::Dawn;
+            let sun = self.sun;
+            sky.watchers.retain(|watcher| watcher.send((now, sun)).is_ok());
+        }
+        if self.sun == Sun::Dawn {
+            scripting::day_started(time, matter, date(now).day);
+        }
+        let (sun, at) = next_sun(now);
+        time.enqueue_absolute(SunEvent { sun }, at);
+    }
+}
+
+#[cfg(test)]
+mod test_calendar {
+    use std::sync::mpsc;
+    use std::time;
+
+    use sulphate::server;
+    use units;
+
+    use super::*;
+
+    #[test]
+    fn dates_count_days_and_seasons() {
+        let start = units::Time::default();
+        let first = date(start);
+        assert_eq!(first.day, 0);
+        assert_eq!(first.season, Season::Spring);
+
+        let later = start + hours(HOURS_PER_DAY * 8 + 13) + hours(1) / 2;
+        let later = date(later);
+        assert_eq!((later.day, later.hour, later.minute), (8, 13, 30));
+        assert_eq!(later.season, Season::Summer);
+
+        let year = HOURS_PER_DAY * 4 * DAYS_PER_SEASON as i32;
+        assert_eq!(date(start + hours(year)).season, Season::Spring);
+    }
+
+    #[test]
+    fn daylight_follows_the_sun() {
+        let start = units::Time::default();
+        assert_eq!(daylight(start), 0.0);
+        assert_eq!(daylight(start + hours(12)), 1.0);
+        let dawn = start + hours(Season::Spring.dawn());
+        assert_eq!(daylight(dawn + hours(1) / 2), 0.5);
+
+        assert!(next_sun(start) == (Sun::Dawn, dawn));
+        let dusk = start + hours(Season::Spring.dusk());
+        assert!(next_sun(dawn) == (Sun::Dusk, dusk));
+        let tomorrow = start + hours(HOURS_PER_DAY);
+        let dawn = tomorrow + hours(Season::Spring.dawn());
+        assert!(next_sun(dusk) == (Sun::Dawn, dawn));
+    }
+
+    #[test]
+    fn watchers_are_told_at_dawn() {
+        let start = units::Time::default();
+        let clock = server::ManualClock::new(start);
+        let (upd, mut clock, handle, ()) =
+            server::start_server_with_clock(|_, _, _| {}, clock);
+
+        let (sender, suns) = mpsc::channel();
+        upd.send(server::Interruption::WatchSky { sender }).unwrap();
+        clock.advance(hours(Season::Spring.dawn()) + units::moments(1));
+        let wait = time::Duration::from_secs(1);
+        let (when, sun) = suns.recv_timeout(wait).expect("sun never rose");
+        assert_eq!(sun, Sun::Dawn);
+        assert!(when == start + hours(Season::Spring.dawn()));
+
+        upd.send(server::Interruption::KillServer).unwrap();
+        handle.join().expect("server panicked");
+    }
+}
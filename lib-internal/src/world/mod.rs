@@ -0,0 +1,4 @@
+//! The things that happen to the whole city at once, rather than to the
+//! entities in it.
+
+pub mod calendar;
@@ -0,0 +1,590 @@
+use std::cmp;
+use std::collections;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Read;
+use std::path;
+
+use blueprints;
+use economy;
+use entities::citizen;
+use growth;
+use space;
+use space::zones;
+use sulphate;
+use sulphate::rng;
+use units;
+
+// maps are written by people, so each line is a word saying what it is
+// followed by its numbers, written normally, with tiles counted from the
+// middle of the city:
+//
+//     # anything after a hash is ignored
+//     money 20000
+//     seed 42
+//     tiles water -10 -10 10 -8
+//     tile wall 3 3
+//     road -5 0 5 0
+//     pipe -5 0 5 0
+//     zone residential -5 1 -1 3
+//     building power_plant 2 1
+//     building cottage 4 1
+//     citizens 12 1
+//     spawn 0 -1
+//     deposits 6 6 8 7
+//
+// boxes of tiles include both corners, and buildings are named by their
+// blueprints, which for each kind of building is its name with underscores
+// in place of spaces
+
+// the most tiles along either side of a box set in one line
+const MAX_SPAN: i64 = 256;
+
+/// Why a map couldn't be read or built, and which line of it was to blame.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MapError {
+    /// Counted from 1, or None if it wasn't down to any one line.
+    pub line: Option<usize>,
+    pub reason: String,
+}
+
+impl fmt::Display for MapError {
+    fn fmt(self: &Self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "line {}: {}", line, self.reason),
+            None => write!(f, "{}", self.reason),
+        }
+    }
+}
+
+impl From<io::Error> for MapError {
+    fn from(err: io::Error) -> MapError {
+        MapError { line: None, reason: err.to_string() }
+    }
+}
+
+/// The world a server starts with, as a map file describes it.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Map {
+    /// The city's money to start with, or None for the usual.
+    pub money: Option<i64>,
+    /// What the world's randomness is seeded with, or None for the usual.
+    pub seed: Option<u64>,
+    pub tiles: Vec<(space::TileId, space::Tile)>,
+    // the rest keep which line they came from, since whether they fit
+    // isn't known until they are built
+    pub roads: Vec<(usize, space::TileId, space::TileId)>,
+    pub pipes: Vec<(usize, space::TileId, space::TileId)>,
+    pub zones: Vec<(space::TileId, space::TileId, zones::Zone)>,
+    pub buildings: Vec<(usize, blueprints::Name, space::TileId)>,
+    /// How many citizens move in, and what decides where they live.
+    pub citizens: Option<(usize, u32)>,
+    /// Where players turn up, taken in turn.
+    pub spawns: Vec<space::TileId>,
+    /// Tiles with raw materials in the ground under them.
+    pub deposits: Vec<space::TileId>,
+    /// What the buildings are built from, which the map was read against.
+    pub blueprints: blueprints::Registry,
+}
+
+fn number<T: ::std::str::FromStr>(
+    words: &[&str],
+    n: usize,
+) -> Result<T, String> {
+    let word = words.get(n).ok_or("missing number")?;
+    word.parse().map_err(|_| format!("{} is not a number", word))
+}
+
+fn tile_at(words: &[&str], n: usize) -> Result<space::TileId, String> {
+    Ok((number(words, n)?, number(words, n + 1)?))
+}
+
+fn span(
+    words: &[&str],
+    n: usize,
+) -> Result<(space::TileId, space::TileId), String> {
+    let from = tile_at(words, n)?;
+    let to = tile_at(words, n + 2)?;
+    let across = cmp::max((from.0 - to.0).abs(), (from.1 - to.1).abs());
+    if across >= MAX_SPAN {
+        return Err(format!("boxes can be at most {} tiles across", MAX_SPAN));
+    }
+    Ok((from, to))
+}
+
+fn tiles_between(
+    from: space::TileId,
+    to: space::TileId,
+) -> Vec<space::TileId> {
+    let (min_x, max_x) = (from.0.min(to.0), from.0.max(to.0));
+    let (min_y, max_y) = (from.1.min(to.1), from.1.max(to.1));
+    let mut tiles = Vec::new();
+    for x in min_x..max_x + 1 {
+        for y in min_y..max_y + 1 {
+            tiles.push((x, y));
+        }
+    }
+    tiles
+}
+
+fn tile_kind(word: Option<&&str>) -> Result<space::Tile, String> {
+    match word.cloned() {
+        Some("open") => Ok(space::Tile::Open),
+        Some("water") => Ok(space::Tile::Water),
+        Some("wall") => Ok(space::Tile::Wall),
+        Some(other) => Err(format!("unknown tile {}", other)),
+        None => Err("missing tile".to_string()),
+    }
+}
+
+fn blueprint(
+    word: Option<&&str>,
+    registry: &blueprints::Registry,
+) -> Result<blueprints::Name, String> {
+    let word = word.ok_or("missing building")?;
+    registry.buildings()
+            .into_iter()
+            .map(|(name, _)| name)
+            .find(|name| name.as_str() == *word)
+            .ok_or_else(|| format!("unknown building {}", word))
+}
+
+fn zone(word: Option<&&str>) -> Result<zones::Zone, String> {
+    let word = word.ok_or("missing zone")?;
+    zones::Zone::all()
+        .iter()
+        .cloned()
+        .find(|zone| zone.name() == *word)
+        .ok_or_else(|| format!("unknown zone {}", word))
+}
+
+fn once<T>(
+    field: &mut Option<T>,
+    value: T,
+    what: &str,
+) -> Result<(), String> {
+    if field.is_some() {
+        return Err(format!("{} is given twice", what));
+    }
+    *field = Some(value);
+    Ok(())
+}
+
+impl Map {
+    pub fn parse(text: &str) -> Result<Map, MapError> {
+        Map::parse_with(text, blueprints::Registry::default())
+    }
+
+    /// Reads a map whose buildings are built from `blueprints`, rather
+    /// than only the ones built in.
+    pub fn parse_with(
+        text: &str,
+        blueprints: blueprints::Registry,
+    ) -> Result<Map, MapError> {
+        let mut map = Map { blueprints, ..Map::default() };
+        for (i, line) in text.lines().enumerate() {
+            let line_number = i + 1;
+            map.parse_line(line_number, line).map_err(|reason| MapError {
+                line: Some(line_number),
+                reason,
+            })?;
+        }
+        Ok(map)
+    }
+
+    fn parse_line(
+        self: &mut Self,
+        line_number: usize,
+        line: &str,
+    ) -> Result<(), String> {
+        let line = line.split('#').next().unwrap_or("");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let expected = match words.get(0).cloned() {
+            None => return Ok(()),
+            Some("money") => {
+                once(&mut self.money, number(&words, 1)?, "money")?;
+                2
+            },
+            Some("seed") => {
+                once(&mut self.seed, number(&words, 1)?, "seed")?;
+                2
+            },
+            Some("tile") => {
+                let tile = tile_kind(words.get(1))?;
+                self.tiles.push((tile_at(&words, 2)?, tile));
+                4
+            },
+            Some("tiles") => {
+                let tile = tile_kind(words.get(1))?;
+                let (from, to) = span(&words, 2)?;
+                for id in tiles_between(from, to) {
+                    self.tiles.push((id, tile));
+                }
+                6
+            },
+            Some("road") => {
+                let (from, to) = span(&words, 1)?;
+                if from.0 != to.0 && from.1 != to.1 {
+                    return Err("roads have to be straight".to_string());
+                }
+                self.roads.push((line_number, from, to));
+                5
+            },
+            Some("pipe") => {
+                let (from, to) = span(&words, 1)?;
+                if from.0 != to.0 && from.1 != to.1 {
+                    return Err("pipes have to be straight".to_string());
+                }
+                self.pipes.push((line_number, from, to));
+                5
+            },
+            Some("zone") => {
+                let zone = zone(words.get(1))?;
+                let (from, to) = span(&words, 2)?;
+                self.zones.push((from, to, zone));
+                6
+            },
+            Some("building") => {
+                let blueprint = blueprint(words.get(1), &self.blueprints)?;
+                let at = tile_at(&words, 2)?;
+                self.buildings.push((line_number, blueprint, at));
+                4
+            },
+            Some("citizens") => {
+                let citizens = (number(&words, 1)?, number(&words, 2)?);
+                once(&mut self.citizens, citizens, "citizens")?;
+                3
+            },
+            Some("spawn") => {
+                self.spawns.push(tile_at(&words, 1)?);
+                3
+            },
+            Some("deposit") => {
+                self.deposits.push(tile_at(&words, 1)?);
+                3
+            },
+            Some("deposits") => {
+                let (from, to) = span(&words, 1)?;
+                self.deposits.extend(tiles_between(from, to));
+                5
+            },
+            Some(other) => return Err(format!("unknown entry {}", other)),
+        };
+        if words.len() > expected {
+            return Err(format!("unexpected {}", words[expected]));
+        }
+        Ok(())
+    }
+
+    /// Sets the world up as the map describes, giving the line of anything
+    /// that didn't fit, such as a building with no road to it.
+    pub fn build(
+        self: Self,
+        space: &mut space::CollisionSpace,
+        time: &mut sulphate::EventQueue,
+        matter: &mut sulphate::EntityHeap,
+    ) -> Result<(), MapError> {
+        let failed = |line: usize, reason: &str| MapError {
+            line: Some(line),
+            reason: reason.to_string(),
+        };
+
+        if let Some(seed) = self.seed {
+            rng::seed(matter, seed);
+        }
+        blueprints::set(matter, self.blueprints);
+        if let Some(money) = self.money {
+            economy::open(time, matter).set_money(money);
+        }
+        for (id, tile) in self.tiles {
+            space.set_tile(time, id, tile);
+        }
+        for (line, from, to) in self.roads {
+            let from = space.terrain().center(from);
+            let to = space.terrain().center(to);
+            if !space.build_road(from, to) {
+                return Err(failed(line, "road runs into something"));
+            }
+        }
+        for (line, from, to) in self.pipes {
+            let from = space.terrain().center(from);
+            let to = space.terrain().center(to);
+            if !space.lay_pipe(from, to) {
+                return Err(failed(line, "pipe runs into something"));
+            }
+        }
+        for (from, to, zone) in self.zones {
+            let from = space.terrain().center(from);
+            let to = space.terrain().center(to);
+            growth::zone(space, time, matter, from, to, Some(zone));
+        }
+        for (line, blueprint, at) in self.buildings {
+            let position = space.terrain().center(at);
+            let placed =
+                blueprints::place(space, time, matter, blueprint, position);
+            if placed.is_none() {
+                return Err(failed(line, "no room for the building"));
+            }
+        }
+        if let Some((count, seed)) = self.citizens {
+            citizen::populate(space, time, matter, count, seed);
+        }
+        if !self.spawns.is_empty() {
+            let points = self.spawns
+                             .iter()
+                             .map(|&id| space.terrain().center(id))
+                             .collect();
+            matter.remove::<Spawns>(SPAWNS);
+            matter.insert(SPAWNS, Spawns { points, next: 0 });
+        }
+        if !self.deposits.is_empty() {
+            let tiles = self.deposits.into_iter().collect();
+            matter.remove::<Deposits>(DEPOSITS);
+            matter.insert(DEPOSITS, Deposits { tiles });
+        }
+        Ok(())
+    }
+}
+
+/// Reads the map at `path`.
+pub fn load(path: &path::Path) -> Result<Map, MapError> {
+    load_with(path, blueprints::Registry::default())
+}
+
+/// Reads the map at `path`, as `Map::parse_with` reads one.
+pub fn load_with(
+    path: &path::Path,
+    blueprints: blueprints::Registry,
+) -> Result<Map, MapError> {
+    let mut text = String::new();
+    fs::File::open(path)?.read_to_string(&mut text)?;
+    Map::parse_with(&text, blueprints)
+}
+
+/// Where the map says players turn up, kept in the heap as the only entity
+/// of its type, like the ledger.
+pub struct Spawns {
+    points: Vec<units::Position>,
+    next: usize,
+}
+
+const SPAWNS: sulphate::EntityId = 0;
+
+/// The next of the map's spawn points, or `requested` if it has none.
+pub fn spawn_point(
+    matter: &mut sulphate::EntityHeap,
+    requested: units::Position,
+) -> units::Position {
+    match matter.get_mut::<Spawns>(SPAWNS) {
+        Some(spawns) => {
+            let point = spawns.points[spawns.next];
+            spawns.next = (spawns.next + 1) % spawns.points.len();
+            point
+        },
+        None => requested,
+    }
+}
+
+/// Which tiles have raw materials under them, kept in the heap as the only
+/// entity of its type.
+pub struct Deposits {
+    tiles: collections::BTreeSet<space::TileId>,
+}
+
+const DEPOSITS: sulphate::EntityId = 0;
+
+/// Whether the tile has raw materials under it.
+pub fn has_deposit(
+    matter: &sulphate::EntityHeap,
+    id: space::TileId,
+) -> bool {
+    match matter.get::<Deposits>(DEPOSITS) {
+        Some(deposits) => deposits.tiles.contains(&id),
+        None => false,
+    }
+}
+
+// how many tiles apart the corners of each layer of noise are, from broad
+// lakes down to the bumps along their shores, and how much each counts
+const LAYERS: [(i64, i64); 3] = [(16, 4), (8, 2), (4, 1)];
+// and for the layer deciding where deposits lie, which is patchier
+const DEPOSIT_SCALE: i64 = 6;
+// the tiles this close to the middle are always dry land, since that is
+// where players turn up when there aren't any spawn points
+const DRY_RADIUS: i64 = 8;
+
+fn floor_div(a: i64, b: i64) -> i64 {
+    if a >= 0 {
+        a / b
+    } else {
+        -((-a - 1) / b) - 1
+    }
+}
+
+// a number in [0, 1) for each corner of the noise, the same every time for
+// the same seed, layer and corner
+fn corner(seed: u64, layer: u64, x: i64, y: i64) -> units::Scalar {
+    let hash = rng::mix(rng::mix(seed ^ rng::mix(layer)) ^ x as u64);
+    let hash = rng::mix(hash ^ y as u64);
+    units::Scalar::from_bits((hash >> 48) as i64)
+}
+
+// eases from one corner to the next, so that slopes meet without creases
+fn smooth(t: units::Scalar) -> units::Scalar {
+    t * t * (units::Scalar::from(3) - t * 2)
+}
+
+fn lerp(
+    a: units::Scalar,
+    b: units::Scalar,
+    t: units::Scalar,
+) -> units::Scalar {
+    a + (b - a) * t
+}
+
+// value noise: numbers picked at the corners of a grid `scale` tiles wide,
+// blended smoothly across the tiles in between
+fn noise(
+    seed: u64,
+    layer: u64,
+    scale: i64,
+    id: space::TileId,
+) -> units::Scalar {
+    let (x, y) = (floor_div(id.0, scale), floor_div(id.1, scale));
+    let between = |along: i64, cell: i64| {
+        smooth(units::Scalar::from((along - cell * scale) as i32) / scale)
+    };
+    let (tx, ty) = (between(id.0, x), between(id.1, y));
+    let top = lerp(
+        corner(seed, layer, x, y),
+        corner(seed, layer, x + 1, y),
+        tx,
+    );
+    let bottom = lerp(
+        corner(seed, layer, x, y + 1),
+        corner(seed, layer, x + 1, y + 1),
+        tx,
+    );
+    lerp(top, bottom, ty)
+}
+
+fn elevation(seed: u64, id: space::TileId) -> units::Scalar {
+    let mut total = units::Scalar::default();
+    let mut weights = 0;
+    for (layer, &(scale, weight)) in LAYERS.iter().enumerate() {
+        total = total + noise(seed, layer as u64, scale, id) * weight;
+        weights += weight;
+    }
+    total / weights
+}
+
+/// Makes up a world `size` tiles across, centered on the middle of the
+/// city, with lakes, dry land to build on, and patches of deposits, the
+/// same every time for the same seed.
+pub fn generate(seed: u64, size: i64) -> Map {
+    let sea_level = units::Scalar::from(2) / 5;
+    let rich = units::Scalar::from(3) / 4;
+    let deposit_layer = LAYERS.len() as u64;
+
+    let mut map = Map::default();
+    map.seed = Some(seed);
+    let start = -size / 2;
+    for x in start..start + size {
+        for y in start..start + size {
+            let id = (x, y);
+            let dry = x.abs() < DRY_RADIUS && y.abs() < DRY_RADIUS;
+            if !dry && elevation(seed, id) < sea_level {
+                map.tiles.push((id, space::Tile::Water));
+            } else if noise(seed, deposit_layer, DEPOSIT_SCALE, id) > rich {
+                map.deposits.push(id);
+            }
+        }
+    }
+    map
+}
+
+#[cfg(test)]
+mod test_worldgen {
+    use entities::building;
+
+    use super::*;
+
+    #[test]
+    fn maps_read_line_by_line() {
+        let map = Map::parse("\
+            # a small town\n\
+            money 500\n\
+            tiles water 0 0 1 2\n\
+            \n\
+            road -5 0 5 0  # main street\n\
+            pipe 0 -3 0 3\n\
+            building power_plant 2 1\n\
+            spawn 0 -1\n\
+        ").unwrap();
+        assert_eq!(map.money, Some(500));
+        assert_eq!(map.tiles.len(), 6);
+        assert_eq!(map.roads, vec![(5, (-5, 0), (5, 0))]);
+        assert_eq!(map.pipes, vec![(6, (0, -3), (0, 3))]);
+        let plant: blueprints::Name = building::Blueprint::PowerPlant.into();
+        assert_eq!(map.buildings, vec![(7, plant, (2, 1))]);
+        assert_eq!(map.spawns, vec![(0, -1)]);
+    }
+
+    #[test]
+    fn mistakes_say_where_they_are() {
+        let error = |text: &str| Map::parse(text).unwrap_err();
+        assert_eq!(error("money 5\nbuilding castle 0 0").to_string(),
+                   "line 2: unknown building castle");
+        assert_eq!(error("tile wall 0").line, Some(1));
+        assert_eq!(error("money 5\nmoney 6").reason, "money is given twice");
+        assert_eq!(error("road 0 0 3 3").reason, "roads have to be straight");
+        assert_eq!(error("spawn 0 0 0").reason, "unexpected 0");
+    }
+
+    #[test]
+    fn maps_build_from_blueprints() {
+        let text = "building cottage 2 1";
+        assert!(Map::parse(text).is_err());
+        let registry = "blueprint cottage building house";
+        let registry = blueprints::Registry::parse(registry).unwrap();
+        let map = Map::parse_with(text, registry).unwrap();
+        let cottage = blueprints::Name::new("cottage").unwrap();
+        assert_eq!(map.buildings, vec![(1, cottage, (2, 1))]);
+    }
+
+    #[test]
+    fn generated_worlds_follow_the_seed() {
+        let map = generate(42, 64);
+        assert!(map == generate(42, 64));
+        assert!(map != generate(43, 64));
+        assert_eq!(map.seed, Some(42));
+
+        // there is some of everything, but dry land around the middle
+        let water = map.tiles.len();
+        let land = 64 * 64 - water;
+        assert!(water > 0 && land > water);
+        assert!(!map.deposits.is_empty());
+        for &((x, y), tile) in &map.tiles {
+            assert_eq!(tile, space::Tile::Water);
+            assert!(x.abs() >= DRY_RADIUS || y.abs() >= DRY_RADIUS);
+        }
+        for &(x, y) in &map.deposits {
+            assert!(x >= -32 && x < 32 && y >= -32 && y < 32);
+            assert!(!map.tiles.iter().any(|&(id, _)| id == (x, y)));
+        }
+    }
+
+    #[test]
+    fn noise_is_smooth() {
+        for x in -20..20 {
+            let here = noise(7, 0, 8, (x, 3));
+            let next = noise(7, 0, 8, (x + 1, 3));
+            assert!(here >= 0 && here < 1);
+            // a corner is at most a whole step from the next, eased over
+            // eight tiles
+            let step = units::Scalar::from(1) / 4;
+            assert!(next - here < step && here - next < step);
+        }
+    }
+}
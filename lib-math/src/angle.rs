@@ -0,0 +1,264 @@
+use std::ops;
+
+use {Angle, Scalar, Vector};
+
+// the bits of a whole turn, which an angle's bits are kept under
+const TURN_BITS: i64 = 1 << 16;
+const QUARTER_BITS: i64 = TURN_BITS / 4;
+
+// 2 pi, to the nearest bit
+const TAU_BITS: i64 = 411_775;
+
+impl Angle {
+    /// `turns` whole turns anticlockwise of the x axis, wrapped to less
+    /// than one turn.
+    pub fn from_turns(turns: Scalar) -> Angle {
+        let bits = turns.into_bits() & (TURN_BITS - 1);
+        Angle(Scalar::from_bits(bits))
+    }
+
+    pub fn from_radians(radians: Scalar) -> Angle {
+        Angle::from_turns(radians / Scalar::from_bits(TAU_BITS))
+    }
+
+    pub fn from_degrees(degrees: i32) -> Angle {
+        Angle::from_turns(Scalar::from(degrees) / 360)
+    }
+
+    /// Which way `vector` is pointing, or no angle at all for zero.
+    pub fn of(vector: Vector) -> Angle {
+        let (x, y) = (vector.x.into_bits(), vector.y.into_bits());
+        if x == 0 && y == 0 {
+            return Default::default();
+        }
+        let (mut ax, mut ay) = (x.abs(), y.abs());
+        // keep the ratio from overflowing when it is shifted up
+        while ax >= 1 << 46 || ay >= 1 << 46 {
+            ax >>= 1;
+            ay >>= 1;
+        }
+        // within the first octant, then reflected into the right one
+        let base = if ax >= ay {
+            atan_bits((ay << 16) / ax)
+        } else {
+            QUARTER_BITS - atan_bits((ax << 16) / ay)
+        };
+        let bits = match (x >= 0, y >= 0) {
+            (true, true) => base,
+            (false, true) => 2 * QUARTER_BITS - base,
+            (false, false) => 2 * QUARTER_BITS + base,
+            (true, false) => TURN_BITS - base,
+        };
+        Angle::from_turns(Scalar::from_bits(bits))
+    }
+
+    /// How much of a turn this is, from 0 up to but not including 1.
+    pub fn turns(self: Angle) -> Scalar {
+        self.0
+    }
+
+    pub fn radians(self: Angle) -> Scalar {
+        self.0 * Scalar::from_bits(TAU_BITS)
+    }
+
+    pub fn sin(self: Angle) -> Scalar {
+        let bits = self.0.into_bits();
+        let within = bits % QUARTER_BITS;
+        // sin rises over the first quarter, falls back over the second,
+        // and then does the same again below zero
+        let magnitude = match bits / QUARTER_BITS {
+            0 | 2 => sin_bits(within),
+            _ => sin_bits(QUARTER_BITS - within),
+        };
+        if bits < 2 * QUARTER_BITS {
+            Scalar::from_bits(magnitude)
+        } else {
+            Scalar::from_bits(-magnitude)
+        }
+    }
+
+    pub fn cos(self: Angle) -> Scalar {
+        (self + Angle::from_degrees(90)).sin()
+    }
+
+    /// How far to turn from here to face `other`, whichever way is shorter,
+    /// in turns from -1/2 up to 1/2, anticlockwise being positive.
+    pub fn to(self: Angle, other: Angle) -> Scalar {
+        let bits = (other - self).0.into_bits();
+        if bits >= 2 * QUARTER_BITS {
+            Scalar::from_bits(bits - TURN_BITS)
+        } else {
+            Scalar::from_bits(bits)
+        }
+    }
+
+    /// This angle turned towards `target` by no more than `step` turns,
+    /// for things that can only turn so fast.
+    pub fn turn_towards(self: Angle, target: Angle, step: Scalar) -> Angle {
+        let left = self.to(target);
+        if left > step {
+            self + Angle::from_turns(step)
+        } else if left < -step {
+            self - Angle::from_turns(step)
+        } else {
+            target
+        }
+    }
+}
+
+impl ops::Add for Angle {
+    type Output = Angle;
+    fn add(self: Angle, other: Angle) -> Angle {
+        Angle::from_turns(self.0 + other.0)
+    }
+}
+
+impl ops::Sub for Angle {
+    type Output = Angle;
+    fn sub(self: Angle, other: Angle) -> Angle {
+        Angle::from_turns(self.0 - other.0)
+    }
+}
+
+impl ops::Neg for Angle {
+    type Output = Angle;
+    fn neg(self: Angle) -> Angle {
+        Angle::from_turns(-self.0)
+    }
+}
+
+impl ops::Mul<Scalar> for Angle {
+    type Output = Angle;
+    fn mul(self: Angle, other: Scalar) -> Angle {
+        Angle::from_turns(self.0 * other)
+    }
+}
+
+// values between the entries of a table are taken along the straight line
+// between them, which is good to within a bit either way
+fn lookup(table: &[i64; 257], position: i64, shift: u32) -> i64 {
+    let i = (position >> shift) as usize;
+    let along = position & ((1 << shift) - 1);
+    if i + 1 >= table.len() {
+        return table[table.len() - 1];
+    }
+    table[i] + (((table[i + 1] - table[i]) * along) >> shift)
+}
+
+// sin of the bits of an angle within the first quarter turn
+fn sin_bits(bits: i64) -> i64 {
+    lookup(&SIN_TABLE, bits, 6)
+}
+
+// atan of the bits of a ratio from 0 to 1, as the bits of an angle
+fn atan_bits(ratio: i64) -> i64 {
+    lookup(&ATAN_TABLE, ratio, 8)
+}
+
+const SIN_TABLE: [i64; 257] = [
+    0, 402, 804, 1206, 1608, 2010, 2412, 2814, 3216, 3617, 4019, 4420, 4821,
+    5222, 5623, 6023, 6424, 6824, 7224, 7623, 8022, 8421, 8820, 9218, 9616,
+    10014, 10411, 10808, 11204, 11600, 11996, 12391, 12785, 13180, 13573,
+    13966, 14359, 14751, 15143, 15534, 15924, 16314, 16703, 17091, 17479,
+    17867, 18253, 18639, 19024, 19409, 19792, 20175, 20557, 20939, 21320,
+    21699, 22078, 22457, 22834, 23210, 23586, 23961, 24335, 24708, 25080,
+    25451, 25821, 26190, 26558, 26925, 27291, 27656, 28020, 28383, 28745,
+    29106, 29466, 29824, 30182, 30538, 30893, 31248, 31600, 31952, 32303,
+    32652, 33000, 33347, 33692, 34037, 34380, 34721, 35062, 35401, 35738,
+    36075, 36410, 36744, 37076, 37407, 37736, 38064, 38391, 38716, 39040,
+    39362, 39683, 40002, 40320, 40636, 40951, 41264, 41576, 41886, 42194,
+    42501, 42806, 43110, 43412, 43713, 44011, 44308, 44604, 44898, 45190,
+    45480, 45769, 46056, 46341, 46624, 46906, 47186, 47464, 47741, 48015,
+    48288, 48559, 48828, 49095, 49361, 49624, 49886, 50146, 50404, 50660,
+    50914, 51166, 51417, 51665, 51911, 52156, 52398, 52639, 52878, 53114,
+    53349, 53581, 53812, 54040, 54267, 54491, 54714, 54934, 55152, 55368,
+    55582, 55794, 56004, 56212, 56418, 56621, 56823, 57022, 57219, 57414,
+    57607, 57798, 57986, 58172, 58356, 58538, 58718, 58896, 59071, 59244,
+    59415, 59583, 59750, 59914, 60075, 60235, 60392, 60547, 60700, 60851,
+    60999, 61145, 61288, 61429, 61568, 61705, 61839, 61971, 62101, 62228,
+    62353, 62476, 62596, 62714, 62830, 62943, 63054, 63162, 63268, 63372,
+    63473, 63572, 63668, 63763, 63854, 63944, 64031, 64115, 64197, 64277,
+    64354, 64429, 64501, 64571, 64639, 64704, 64766, 64827, 64884, 64940,
+    64993, 65043, 65091, 65137, 65180, 65220, 65259, 65294, 65328, 65358,
+    65387, 65413, 65436, 65457, 65476, 65492, 65505, 65516, 65525, 65531,
+    65535, 65536,
+];
+
+const ATAN_TABLE: [i64; 257] = [
+    0, 41, 81, 122, 163, 204, 244, 285, 326, 367, 407, 448, 489, 529, 570, 610,
+    651, 692, 732, 773, 813, 854, 894, 935, 975, 1015, 1056, 1096, 1136, 1177,
+    1217, 1257, 1297, 1337, 1377, 1417, 1457, 1497, 1537, 1577, 1617, 1656,
+    1696, 1736, 1775, 1815, 1854, 1894, 1933, 1973, 2012, 2051, 2090, 2129,
+    2168, 2207, 2246, 2285, 2324, 2363, 2401, 2440, 2478, 2517, 2555, 2594,
+    2632, 2670, 2708, 2746, 2784, 2822, 2860, 2897, 2935, 2973, 3010, 3047,
+    3085, 3122, 3159, 3196, 3233, 3270, 3307, 3344, 3380, 3417, 3453, 3490,
+    3526, 3562, 3599, 3635, 3670, 3706, 3742, 3778, 3813, 3849, 3884, 3920,
+    3955, 3990, 4025, 4060, 4095, 4129, 4164, 4199, 4233, 4267, 4302, 4336,
+    4370, 4404, 4438, 4471, 4505, 4539, 4572, 4605, 4639, 4672, 4705, 4738,
+    4771, 4803, 4836, 4869, 4901, 4933, 4966, 4998, 5030, 5062, 5094, 5125,
+    5157, 5188, 5220, 5251, 5282, 5313, 5344, 5375, 5406, 5437, 5467, 5498,
+    5528, 5559, 5589, 5619, 5649, 5679, 5708, 5738, 5768, 5797, 5826, 5856,
+    5885, 5914, 5943, 5972, 6000, 6029, 6058, 6086, 6114, 6142, 6171, 6199,
+    6227, 6254, 6282, 6310, 6337, 6365, 6392, 6419, 6446, 6473, 6500, 6527,
+    6554, 6580, 6607, 6633, 6660, 6686, 6712, 6738, 6764, 6790, 6815, 6841,
+    6867, 6892, 6917, 6943, 6968, 6993, 7018, 7043, 7068, 7092, 7117, 7141,
+    7166, 7190, 7214, 7238, 7262, 7286, 7310, 7334, 7358, 7381, 7405, 7428,
+    7451, 7475, 7498, 7521, 7544, 7566, 7589, 7612, 7635, 7657, 7679, 7702,
+    7724, 7746, 7768, 7790, 7812, 7834, 7856, 7877, 7899, 7920, 7942, 7963,
+    7984, 8005, 8026, 8047, 8068, 8089, 8110, 8131, 8151, 8172, 8192,
+];
+
+#[cfg(test)]
+mod test_angle {
+    use {Angle, Scalar, Vector};
+
+    fn near(a: Scalar, b: Scalar) -> bool {
+        let difference = (a - b).into_bits();
+        (-4..=4).contains(&difference)
+    }
+
+    #[test]
+    fn test_trig() {
+        let half: Scalar = Scalar::from(1) / 2;
+        for &(degrees, sin, cos) in [
+            (0, 0.into(), 1.into()),
+            (30, half, Scalar::from(3).sqrt() / 2),
+            (90, 1.into(), 0.into()),
+            (180, 0.into(), (-1).into()),
+            (270, (-1).into(), 0.into()),
+            (-30, -half, Scalar::from(3).sqrt() / 2),
+        ].iter() {
+            let angle = Angle::from_degrees(degrees);
+            assert!(near(angle.sin(), sin), "sin {}", degrees);
+            assert!(near(angle.cos(), cos), "cos {}", degrees);
+        }
+    }
+
+    #[test]
+    fn test_of() {
+        for degrees in (-180..180).map(|d| d * 7) {
+            let angle = Angle::from_degrees(degrees);
+            let direction = Vector::from_angle(angle) * 100;
+            assert!(near(angle.to(Angle::of(direction)), 0.into()));
+        }
+        assert_eq!(Angle::of(Vector::default()), Angle::default());
+    }
+
+    #[test]
+    fn test_turning() {
+        let north = Angle::from_degrees(90);
+        let east = Angle::from_degrees(0);
+        let quarter = Scalar::from(1) / 4;
+        assert_eq!(east.to(north), quarter);
+        assert_eq!(north.to(east), -quarter);
+        // the short way round, past the x axis
+        let slightly_south = Angle::from_degrees(-10);
+        assert!(Angle::from_degrees(10).to(slightly_south) < 0);
+
+        let step = Scalar::from(1) / 8;
+        let halfway = east.turn_towards(north, step);
+        assert_eq!(halfway, Angle::from_degrees(45));
+        assert_eq!(halfway.turn_towards(north, step), north);
+        assert_eq!(north.turn_towards(north, step), north);
+    }
+}
@@ -2,12 +2,15 @@ extern crate fix;
 extern crate typenum;
 
 // bunch of trait implementations
+mod angle;
 mod scalar_assigns;
 mod scalar_ops;
 mod scalar_misc;
+mod scalar_checked;
 mod vector_assigns;
 mod vector_ops;
 mod vector_misc;
+mod vector_checked;
 
 use fix::aliases::binary::IFix64;
 use typenum::N16;
@@ -18,6 +21,9 @@ type Inner = IFix64<N16>;
 pub struct Scalar(Inner);
 #[derive(Clone, Copy, Hash, Default, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Coord(Inner);
+/// A direction, as how far it is anticlockwise of the x axis in turns.
+#[derive(Clone, Copy, Hash, Default, PartialEq, Eq, Debug)]
+pub struct Angle(Scalar);
 
 #[derive(Clone, Copy, Hash, Default, PartialEq, Eq, Debug)]
 pub struct Vector {
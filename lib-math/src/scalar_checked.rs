@@ -0,0 +1,185 @@
+use {Scalar, Coord};
+
+// the full 128 bit product of two magnitudes, as (high, low) halves
+fn wide_mul(a: u64, b: u64) -> (u64, u64) {
+    let mask = (1 << 32) - 1;
+    let (a_hi, a_lo) = (a >> 32, a & mask);
+    let (b_hi, b_lo) = (b >> 32, b & mask);
+
+    let low = a_lo * b_lo;
+    let cross_1 = a_lo * b_hi;
+    let cross_2 = a_hi * b_lo;
+    let high = a_hi * b_hi;
+
+    let middle = (low >> 32) + (cross_1 & mask) + (cross_2 & mask);
+    let lo = (low & mask) | (middle << 32);
+    let hi = high + (cross_1 >> 32) + (cross_2 >> 32) + (middle >> 32);
+    (hi, lo)
+}
+
+// multiplies the bits of two scalars, keeping the whole product until it has
+// been shifted back down, so that only an answer too big to hold overflows,
+// rather than anything with a product of bits too big to hold
+fn overflowing_mul_bits(a: i64, b: i64) -> (i64, bool) {
+    let negative = (a < 0) != (b < 0);
+    // wrapping so that i64::MIN comes out as 2^63, which fits in a u64
+    let (hi, lo) = wide_mul(a.wrapping_abs() as u64, b.wrapping_abs() as u64);
+    let shifted_hi = hi >> 16;
+    let shifted_lo = (lo >> 16) | (hi << 48);
+
+    // rounding toward zero, the same as the narrowing conversion did
+    let limit = if negative {
+        i64::MAX as u64 + 1
+    } else {
+        i64::MAX as u64
+    };
+    let overflowed = shifted_hi != 0 || shifted_lo > limit;
+    let bits = if negative {
+        (shifted_lo as i64).wrapping_neg()
+    } else {
+        shifted_lo as i64
+    };
+    (bits, overflowed)
+}
+
+impl Scalar {
+    /// The product, and whether it was too big to hold, in which case it
+    /// wraps around.
+    pub fn overflowing_mul(self: Scalar, other: Scalar) -> (Scalar, bool) {
+        let (a, b) = (self.0.bits, other.0.bits);
+        let (bits, overflowed) = overflowing_mul_bits(a, b);
+        (Scalar::from_bits(bits), overflowed)
+    }
+
+    pub fn checked_add(self: Scalar, other: Scalar) -> Option<Scalar> {
+        self.0.bits.checked_add(other.0.bits).map(Scalar::from_bits)
+    }
+
+    pub fn checked_sub(self: Scalar, other: Scalar) -> Option<Scalar> {
+        self.0.bits.checked_sub(other.0.bits).map(Scalar::from_bits)
+    }
+
+    pub fn checked_mul(self: Scalar, other: Scalar) -> Option<Scalar> {
+        match self.overflowing_mul(other) {
+            (prod, false) => Some(prod),
+            (_, true) => None,
+        }
+    }
+
+    /// None when dividing by zero, or when the answer, or the dividend
+    /// widened to twice the precision on the way, is too big to hold.
+    pub fn checked_div(self: Scalar, other: Scalar) -> Option<Scalar> {
+        let nume = self.0.bits.checked_mul(1 << 16)?;
+        nume.checked_div(other.0.bits).map(Scalar::from_bits)
+    }
+
+    pub fn saturating_add(self: Scalar, other: Scalar) -> Scalar {
+        Scalar::from_bits(self.0.bits.saturating_add(other.0.bits))
+    }
+
+    pub fn saturating_sub(self: Scalar, other: Scalar) -> Scalar {
+        Scalar::from_bits(self.0.bits.saturating_sub(other.0.bits))
+    }
+
+    pub fn saturating_mul(self: Scalar, other: Scalar) -> Scalar {
+        match self.checked_mul(other) {
+            Some(prod) => prod,
+            None if (self < 0) != (other < 0) => Scalar::min_value(),
+            None => Scalar::max_value(),
+        }
+    }
+
+    pub fn wrapping_add(self: Scalar, other: Scalar) -> Scalar {
+        Scalar::from_bits(self.0.bits.wrapping_add(other.0.bits))
+    }
+
+    pub fn wrapping_sub(self: Scalar, other: Scalar) -> Scalar {
+        Scalar::from_bits(self.0.bits.wrapping_sub(other.0.bits))
+    }
+
+    pub fn wrapping_mul(self: Scalar, other: Scalar) -> Scalar {
+        self.overflowing_mul(other).0
+    }
+
+    pub fn min_value() -> Scalar {
+        Scalar::from_bits(i64::MIN)
+    }
+
+    pub fn max_value() -> Scalar {
+        Scalar::from_bits(i64::MAX)
+    }
+}
+
+impl Coord {
+    pub fn checked_add(self: Coord, other: Scalar) -> Option<Coord> {
+        self.0.bits.checked_add(other.0.bits).map(Coord::from_bits)
+    }
+
+    pub fn checked_sub(self: Coord, other: Scalar) -> Option<Coord> {
+        self.0.bits.checked_sub(other.0.bits).map(Coord::from_bits)
+    }
+
+    /// How far apart two coordinates are, if that can be held.
+    pub fn checked_offset(self: Coord, other: Coord) -> Option<Scalar> {
+        self.0.bits.checked_sub(other.0.bits).map(Scalar::from_bits)
+    }
+
+    pub fn saturating_add(self: Coord, other: Scalar) -> Coord {
+        Coord::from_bits(self.0.bits.saturating_add(other.0.bits))
+    }
+
+    pub fn saturating_sub(self: Coord, other: Scalar) -> Coord {
+        Coord::from_bits(self.0.bits.saturating_sub(other.0.bits))
+    }
+
+    pub fn wrapping_add(self: Coord, other: Scalar) -> Coord {
+        Coord::from_bits(self.0.bits.wrapping_add(other.0.bits))
+    }
+
+    pub fn wrapping_sub(self: Coord, other: Scalar) -> Coord {
+        Coord::from_bits(self.0.bits.wrapping_sub(other.0.bits))
+    }
+}
+
+#[cfg(test)]
+mod test_checked {
+    use super::*;
+
+    #[test]
+    fn products_widen_before_narrowing() {
+        // the bits of these multiply to more than 2^63, but the answer fits
+        let big: Scalar = 2_000_000_000.into();
+        let four: Scalar = 4.into();
+        assert_eq!(big * four, big + big + big + big);
+        assert_eq!(-big * four, -(big * four));
+        assert_eq!(big.checked_mul(four), Some(big * four));
+
+        // rounding toward zero either way
+        let third = Scalar::from(1) / 3;
+        assert_eq!(Scalar::from(-7) * third, -(Scalar::from(7) * third));
+    }
+
+    #[test]
+    fn overflow_is_caught_or_clamped() {
+        let big: Scalar = 1_000_000_000.into();
+        assert_eq!(big.checked_mul(big), None);
+        assert_eq!(big.saturating_mul(big), Scalar::max_value());
+        assert_eq!(big.saturating_mul(-big), Scalar::min_value());
+        assert!(big.overflowing_mul(big).1);
+
+        let max = Scalar::max_value();
+        assert_eq!(max.checked_add(Scalar::from_bits(1)), None);
+        assert_eq!(max.saturating_add(1.into()), max);
+        let min = Scalar::min_value();
+        assert_eq!(max.wrapping_add(Scalar::from_bits(1)), min);
+
+        let half = Scalar::from(1) / 2;
+        assert_eq!(Scalar::from(3).checked_div(2.into()), Some(3 * half));
+        assert_eq!(Scalar::from(1).checked_div(0.into()), None);
+
+        let far = Coord::from_bits(i64::MAX);
+        assert_eq!(far.checked_add(1.into()), None);
+        assert_eq!(far.saturating_add(1.into()), far);
+        assert_eq!(far.checked_offset(Coord::from_bits(-1)), None);
+    }
+}
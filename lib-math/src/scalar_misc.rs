@@ -24,8 +24,24 @@ impl Scalar {
         Scalar::from_bits((result as i64) << 8)
     }
 
+    /// The square root, rounded down, for anything a scalar can hold,
+    /// where `rough_sqrt` only works for small numbers.
     pub fn sqrt(self: Scalar) -> Scalar {
-        self.rough_sqrt()
+        let bits = self.0.bits;
+        debug_assert!(bits >= 0, "Square root of negative number");
+        if bits <= 0 {
+            return Default::default();
+        }
+        let bits = bits as u64;
+        // the root of x * 2 ^ 16 is root_x * 2 ^ 8, so get the bits of the
+        // root as the root of the bits shifted up by another 16, if they
+        // fit, and otherwise lose the last 8 bits of the root
+        let root = if bits < 1 << 47 {
+            exact_sqrt(bits << 16)
+        } else {
+            exact_sqrt(bits) << 8
+        };
+        Scalar::from_bits(root as i64)
     }
 
     pub fn squared(self: Scalar) -> Scalar {
@@ -33,6 +49,36 @@ impl Scalar {
     }
 }
 
+impl Coord {
+    pub fn from_bits(bits: i64) -> Coord {
+        Coord(Inner::new(bits))
+    }
+
+    pub fn into_bits(self: Coord) -> i64 {
+        self.0.bits
+    }
+}
+
+// the integer square root, rounded down, a digit at a time
+fn exact_sqrt(val: u64) -> u64 {
+    let mut rem = val;
+    let mut root = 0;
+    let mut bit = 1 << 62;
+    while bit > val {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if rem >= root + bit {
+            rem -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
 // note there is a nice approximation algorithm at
 // https://users.rust-lang.org/t/integer-square-root-algorithm/13529/5
 // but this is fine for now
@@ -83,6 +129,16 @@ mod test_rough_sqrt {
         );
     }
 
+    #[test]
+    fn test_big_sqrts() {
+        for &num in [256, 1000, 46_340, 1_000_000, 10_000_000].iter() {
+            let val: ::Scalar = num.into();
+            assert_eq!(val.squared().sqrt(), val);
+        }
+        // 92681 ^ 2 <= 2 * 2 ^ 32 < 92682 ^ 2
+        assert_eq!(::Scalar::from(2).sqrt(), ::Scalar::from_bits(92681));
+    }
+
     #[test]
     fn test_small_sqrts_exhaustive() {
         println!("Testing");
@@ -121,6 +177,13 @@ impl From<Scalar> for f64 {
     }
 }
 
+// rounds toward zero, so keep floats out of anything that must be repeatable
+impl From<f64> for Scalar {
+    fn from(val: f64) -> Scalar {
+        Scalar::from_bits((val * (1 << 16) as f64) as i64)
+    }
+}
+
 
 impl PartialEq<i32> for Scalar {
     fn eq(self: &Scalar, other: &i32) -> bool {
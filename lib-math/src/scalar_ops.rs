@@ -62,8 +62,9 @@ impl ops::Sub for Coord {
 impl ops::Mul for Scalar {
     type Output = Scalar;
     fn mul(self: Scalar, other: Scalar) -> Scalar {
-        let prod = self.0 * other.0;
-        Scalar(prod.convert())
+        let (prod, overflowed) = self.overflowing_mul(other);
+        debug_assert!(!overflowed, "Scalar multiplication overflowed");
+        prod
     }
 }
 
@@ -0,0 +1,102 @@
+use {Vector, Position, Scalar};
+
+impl Vector {
+    pub fn checked_add(self: Vector, other: Vector) -> Option<Vector> {
+        let x = self.x.checked_add(other.x)?;
+        let y = self.y.checked_add(other.y)?;
+        Some(Vector { x, y })
+    }
+
+    pub fn checked_sub(self: Vector, other: Vector) -> Option<Vector> {
+        let x = self.x.checked_sub(other.x)?;
+        let y = self.y.checked_sub(other.y)?;
+        Some(Vector { x, y })
+    }
+
+    pub fn checked_mul(self: Vector, other: Scalar) -> Option<Vector> {
+        let x = self.x.checked_mul(other)?;
+        let y = self.y.checked_mul(other)?;
+        Some(Vector { x, y })
+    }
+
+    pub fn saturating_add(self: Vector, other: Vector) -> Vector {
+        let x = self.x.saturating_add(other.x);
+        let y = self.y.saturating_add(other.y);
+        Vector { x, y }
+    }
+
+    pub fn saturating_sub(self: Vector, other: Vector) -> Vector {
+        let x = self.x.saturating_sub(other.x);
+        let y = self.y.saturating_sub(other.y);
+        Vector { x, y }
+    }
+
+    pub fn saturating_mul(self: Vector, other: Scalar) -> Vector {
+        let x = self.x.saturating_mul(other);
+        let y = self.y.saturating_mul(other);
+        Vector { x, y }
+    }
+
+    pub fn wrapping_add(self: Vector, other: Vector) -> Vector {
+        let x = self.x.wrapping_add(other.x);
+        let y = self.y.wrapping_add(other.y);
+        Vector { x, y }
+    }
+
+    pub fn wrapping_sub(self: Vector, other: Vector) -> Vector {
+        let x = self.x.wrapping_sub(other.x);
+        let y = self.y.wrapping_sub(other.y);
+        Vector { x, y }
+    }
+
+    pub fn wrapping_mul(self: Vector, other: Scalar) -> Vector {
+        let x = self.x.wrapping_mul(other);
+        let y = self.y.wrapping_mul(other);
+        Vector { x, y }
+    }
+}
+
+impl Position {
+    pub fn checked_add(self: Position, other: Vector) -> Option<Position> {
+        let x = self.x.checked_add(other.x)?;
+        let y = self.y.checked_add(other.y)?;
+        Some(Position { x, y })
+    }
+
+    pub fn checked_sub(self: Position, other: Vector) -> Option<Position> {
+        let x = self.x.checked_sub(other.x)?;
+        let y = self.y.checked_sub(other.y)?;
+        Some(Position { x, y })
+    }
+
+    /// The displacement from `other` to here, if that can be held.
+    pub fn checked_offset(self: Position, other: Position) -> Option<Vector> {
+        let x = self.x.checked_offset(other.x)?;
+        let y = self.y.checked_offset(other.y)?;
+        Some(Vector { x, y })
+    }
+
+    pub fn saturating_add(self: Position, other: Vector) -> Position {
+        let x = self.x.saturating_add(other.x);
+        let y = self.y.saturating_add(other.y);
+        Position { x, y }
+    }
+
+    pub fn saturating_sub(self: Position, other: Vector) -> Position {
+        let x = self.x.saturating_sub(other.x);
+        let y = self.y.saturating_sub(other.y);
+        Position { x, y }
+    }
+
+    pub fn wrapping_add(self: Position, other: Vector) -> Position {
+        let x = self.x.wrapping_add(other.x);
+        let y = self.y.wrapping_add(other.y);
+        Position { x, y }
+    }
+
+    pub fn wrapping_sub(self: Position, other: Vector) -> Position {
+        let x = self.x.wrapping_sub(other.x);
+        let y = self.y.wrapping_sub(other.y);
+        Position { x, y }
+    }
+}
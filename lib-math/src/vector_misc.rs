@@ -1,4 +1,4 @@
-use {Vector, Scalar};
+use {Angle, Vector, Position, Scalar};
 
 impl Vector {
     pub fn rough_magnitude(self: Vector) -> Scalar {
@@ -16,4 +16,104 @@ impl Vector {
     pub fn inner(self: Vector, other: Vector) -> Scalar {
         self.x * other.x + self.y * other.y
     }
+
+    /// The vector of length one going the same way, or zero for zero.
+    pub fn normalized(self: Vector) -> Vector {
+        let magnitude = self.magnitude();
+        if magnitude == 0 {
+            Default::default()
+        } else {
+            self / magnitude
+        }
+    }
+
+    /// The vector of length one pointing at `angle`.
+    pub fn from_angle(angle: Angle) -> Vector {
+        Vector { x: angle.cos(), y: angle.sin() }
+    }
+
+    /// This vector turned anticlockwise by `angle`.
+    pub fn rotated(self: Vector, angle: Angle) -> Vector {
+        let turn = Vector::from_angle(angle);
+        Vector {
+            x: self.x * turn.x - self.y * turn.y,
+            y: self.x * turn.y + self.y * turn.x,
+        }
+    }
+
+    /// The vector `t` of the way from this one to `other`.
+    pub fn lerp(self: Vector, other: Vector, t: Scalar) -> Vector {
+        self + (other - self) * t
+    }
+}
+
+impl Position {
+    pub fn distance(self: Position, other: Position) -> Scalar {
+        (other - self).magnitude()
+    }
+
+    pub fn distance_squared(self: Position, other: Position) -> Scalar {
+        (other - self).squared()
+    }
+
+    /// The position `t` of the way from here to `other`.
+    pub fn lerp(self: Position, other: Position, t: Scalar) -> Position {
+        self + (other - self) * t
+    }
+}
+
+#[cfg(test)]
+mod test_vector {
+    use {Angle, Vector, Position, Scalar};
+
+    fn vector(x: i32, y: i32) -> Vector {
+        Vector { x: x.into(), y: y.into() }
+    }
+
+    // within a hundredth
+    fn close(a: Vector, b: Vector) -> bool {
+        (a - b).squared() < Scalar::from(1) / 10_000
+    }
+
+    #[test]
+    fn test_normalized() {
+        assert_eq!(vector(0, -40).normalized(), vector(0, -1));
+        assert_eq!(vector(0, 0).normalized(), vector(0, 0));
+        let tenth = Scalar::from(1) / 10;
+        let tenths = Vector { x: tenth * 6, y: tenth * -8 };
+        assert!(close(vector(30, -40).normalized(), tenths));
+        let long = vector(100_000, 100_000).normalized();
+        let half_root_2 = Scalar::from(2).sqrt() / 2;
+        assert!(close(long, Vector { x: half_root_2, y: half_root_2 }));
+    }
+
+    #[test]
+    fn test_rotated() {
+        let east = vector(10, 0);
+        let quarter = Angle::from_degrees(90);
+        assert!(close(east.rotated(quarter), vector(0, 10)));
+        assert!(close(east.rotated(quarter + quarter), vector(-10, 0)));
+        assert!(close(east.rotated(-quarter), vector(0, -10)));
+        // all the way round, several times over
+        assert!(close(east.rotated(Angle::from_degrees(360 * 5)), east));
+
+        let diagonal = Vector::from_angle(Angle::from_degrees(45));
+        let half_root_2 = Scalar::from(2).sqrt() / 2;
+        assert!(close(diagonal, Vector { x: half_root_2, y: half_root_2 }));
+    }
+
+    #[test]
+    fn test_lerp_and_distance() {
+        let origin = Position::default();
+        let a = origin + vector(10, 0);
+        let b = origin + vector(10, 40);
+        assert_eq!(a.lerp(b, Scalar::from(1) / 4), origin + vector(10, 10));
+        assert_eq!(vector(0, 8).lerp(vector(8, 0), Scalar::from(1) / 2),
+                   vector(4, 4));
+        assert_eq!(a.distance(b), Scalar::from(40));
+        assert_eq!(a.distance_squared(b), Scalar::from(1600));
+        // further than rough_sqrt could manage
+        let far = origin + vector(3000, 4000);
+        assert_eq!(origin.distance(far), Scalar::from(5000));
+    }
 }
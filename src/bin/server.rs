@@ -0,0 +1,155 @@
+extern crate city_internal;
+
+use city_internal::admin;
+use city_internal::entities::citizen;
+use city_internal::logging;
+use city_internal::mods;
+use city_internal::net;
+use city_internal::sulphate::autosave;
+use city_internal::sulphate::server;
+use city_internal::units;
+use city_internal::worldgen;
+
+// how many tiles across a generated world is, unless asked for otherwise
+const DEFAULT_SIZE: i64 = 128;
+
+// a city with nobody but its citizens in it until players connect,
+// for running somewhere that doesn't have a screen
+fn main() {
+    logging::init();
+    let mut args = ::std::env::args().skip(1);
+    let mut host = "0.0.0.0:7878".to_string();
+    let mut admin_addr = None;
+    let mut map = None;
+    let mut seed = None;
+    let mut size = DEFAULT_SIZE;
+    let mut autosave_every = None;
+    let mut journal = false;
+    let mut config = None;
+    let mut mods = mods::mods_dir();
+    while let Some(arg) = args.next() {
+        if arg == "--host" {
+            host = args.next().unwrap_or(host);
+        } else if arg == "--admin" {
+            admin_addr = args.next();
+        } else if arg == "--map" {
+            map = args.next();
+        } else if arg == "--seed" {
+            match args.next().map(|seed| seed.parse()) {
+                Some(Ok(given)) => seed = Some(given),
+                _ => println!("--seed needs a number"),
+            }
+        } else if arg == "--size" {
+            match args.next().map(|size| size.parse()) {
+                Some(Ok(given)) if given > 0 => size = given,
+                _ => println!("--size needs a positive number"),
+            }
+        } else if arg == "--config" {
+            config = args.next();
+        } else if arg == "--mods" {
+            mods = args.next().map_or(mods, From::from);
+        } else if arg == "--autosave" {
+            // real seconds
+            match args.next().map(|secs| secs.parse()) {
+                Some(Ok(secs)) if secs > 0 => {
+                    let every = ::std::time::Duration::from_secs(secs);
+                    autosave_every = Some(autosave::Interval::Wall(every));
+                },
+                _ => println!("--autosave needs a number of seconds"),
+            }
+        } else if arg == "--autosave-game" {
+            // seconds of game time
+            match args.next().map(|secs| secs.parse::<i32>()) {
+                Some(Ok(secs)) if secs > 0 => {
+                    let rate = units::MOMENT_RATE as i32;
+                    let every = units::moments(secs * rate);
+                    autosave_every = Some(autosave::Interval::Game(every));
+                },
+                _ => println!("--autosave-game needs a number of seconds"),
+            }
+        } else if arg == "--journal" {
+            // autosave into a journal, writing only what has changed
+            journal = true;
+        } else {
+            println!("Unrecognized argument {}", arg);
+        }
+    }
+
+    let (upd, server) = match (map, seed) {
+        (Some(path), _) => {
+            // maps can build from whatever blueprints the mods bring
+            let packs = mods::discover(&mods).packs;
+            let blueprints = mods::registry(&mods, &packs);
+            let started = worldgen::load_with(path.as_ref(), blueprints)
+                .and_then(server::start_server_with_map);
+            match started {
+                Ok((upd, _clock, server)) => (upd, server),
+                Err(e) => {
+                    println!("Couldn't start from map {}: {}", path, e);
+                    ::std::process::exit(1);
+                },
+            }
+        },
+        (None, Some(seed)) => {
+            let mut map = worldgen::generate(seed, size);
+            map.citizens = Some((12, seed as u32));
+            match server::start_server_with_map(map) {
+                Ok((upd, _clock, server)) => (upd, server),
+                Err(e) => {
+                    println!("Couldn't generate a world: {}", e);
+                    ::std::process::exit(1);
+                },
+            }
+        },
+        (None, None) => {
+            let (upd, _clock, server, ()) =
+                server::start_server(|space, time, matter| {
+                    citizen::populate(space, time, matter, 12, 1);
+                });
+            (upd, server)
+        },
+    };
+
+    if let Some(path) = config {
+        let reload = server::Interruption::ReloadConfig(path.into());
+        if upd.send(reload).is_err() {
+            println!("Server stopped before reading the config");
+        }
+    }
+
+    // there is nothing to load if the game didn't come with any mods
+    if mods.is_dir() {
+        let load = server::Interruption::LoadMods(mods);
+        if upd.send(load).is_err() {
+            println!("Server stopped before loading mods");
+        }
+    }
+
+    if let Some(interval) = autosave_every {
+        let mut settings = autosave::Settings::every(interval);
+        settings.journal = journal;
+        let wake = upd.clone();
+        let start = server::Interruption::StartAutosave { settings, wake };
+        if upd.send(start).is_err() {
+            println!("Server stopped before autosaving could start");
+        }
+    }
+
+    if let Err(e) = net::listen(&host[..], upd.clone()) {
+        panic!("Failed to host on {}: {}", host, e);
+    }
+    println!("Hosting on {}", host);
+
+    if let Some(addr) = admin_addr {
+        if let Err(e) = admin::listen(&addr[..], upd.clone()) {
+            panic!("Failed to take admin connections on {}: {}", addr, e);
+        }
+    }
+    // the terminal is a console too, if there is one
+    admin::serve_stdin(upd);
+
+    if server.join().is_err() {
+        println!("Server thread panicked");
+        ::std::process::exit(1);
+    }
+}
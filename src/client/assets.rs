@@ -0,0 +1,453 @@
+use std::cell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::path;
+use std::time;
+
+use image;
+use piston_window as app;
+use piston_window::CreateTexture;
+
+use city_internal::entities::item;
+use city_internal::space;
+
+use client::locale;
+use client::render;
+use client::render::animation;
+
+// as wide as the atlas gets, past which sprites go on the next row down
+const ATLAS_WIDTH: u32 = 1024;
+
+// seconds between looking for art that has changed, in debug builds
+#[cfg(debug_assertions)]
+const RELOAD_EVERY: u64 = 1;
+
+/// Where the art and the file mapping sprites to it are.
+pub fn assets_dir() -> path::PathBuf {
+    path::PathBuf::from("assets")
+}
+
+fn invalid(line: &str) -> io::Error {
+    let msg = format!("malformed sprite mapping: {}", line);
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Reads which image each sprite is drawn from, one per line as
+///
+///     building.house = "buildings/house.png"
+///
+/// with the paths relative to the assets directory.
+pub fn read_mapping(
+    input: &mut io::BufRead,
+) -> io::Result<Vec<(String, path::PathBuf)>> {
+    let mut mapping = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let mut halves = trimmed.splitn(2, '=');
+        let key = halves.next().unwrap_or("").trim();
+        let value = halves.next().map_or("", str::trim);
+        let quoted = value.len() >= 2
+            && value.starts_with('"')
+            && value.ends_with('"');
+        if key.is_empty() || !quoted {
+            return Err(invalid(&line));
+        }
+        let file = path::PathBuf::from(&value[1..value.len() - 1]);
+        mapping.push((key.to_string(), file));
+    }
+    Ok(mapping)
+}
+
+/// Where in an atlas each image of `sizes` goes, in rows from the top
+/// left, and how big that makes the atlas.
+pub fn pack(sizes: &[[u32; 2]]) -> (Vec<[u32; 2]>, [u32; 2]) {
+    let mut places = Vec::new();
+    let (mut x, mut y, mut row) = (0, 0, 0);
+    let mut width = 0;
+    for size in sizes {
+        if x > 0 && x + size[0] > ATLAS_WIDTH {
+            x = 0;
+            y += row;
+            row = 0;
+        }
+        places.push([x, y]);
+        x += size[0];
+        row = row.max(size[1]);
+        width = width.max(x);
+    }
+    (places, [width, y + row])
+}
+
+/// The name `sprite` is mapped to art by, if it has any art.
+pub fn key(sprite: render::Sprite) -> Option<String> {
+    let key = match sprite {
+        render::Sprite::Player { .. } => "player".to_string(),
+        render::Sprite::Citizen { .. } => "citizen".to_string(),
+        render::Sprite::Building { blueprint, .. } => {
+            let name = blueprint.map_or("unknown", |b| b.name());
+            format!("building.{}", name.replace(' ', "_"))
+        },
+        render::Sprite::Vehicle(_) => "vehicle".to_string(),
+        render::Sprite::Item(kind) => {
+            format!("item.{}", kind.map_or("unknown", item::Kind::name))
+        },
+        render::Sprite::Terrain(tile) => match tile {
+            space::Tile::Water => "terrain.water".to_string(),
+            space::Tile::Wall => "terrain.wall".to_string(),
+            // drawn over by whatever is on it
+            space::Tile::Open | space::Tile::Building => return None,
+        },
+    };
+    Some(key)
+}
+
+/// The name the frame `sprite` is on is mapped to art by, as in
+/// `citizen.walk.2`, if it is animated at all. Its frames are drawn in
+/// place of its still art wherever there is art for them.
+pub fn frame_key(sprite: render::Sprite) -> Option<String> {
+    let frame = match sprite {
+        render::Sprite::Citizen { frame } => frame,
+        render::Sprite::Building { frame, .. } => frame,
+        _ => return None,
+    };
+    if frame.cycle == animation::Cycle::Idle {
+        return None;
+    }
+    key(sprite).map(|key| {
+        format!("{}.{}.{}", key, frame.cycle.name(), frame.frame)
+    })
+}
+
+// the box a sprite's art is stretched over, around where it is drawn, the
+// same size as the shape drawn without art
+fn bounds(sprite: render::Sprite) -> [f64; 4] {
+    match sprite {
+        render::Sprite::Player { .. } => [-10.0, -10.0, 20.0, 20.0],
+        render::Sprite::Citizen { .. } => [-8.0, -8.0, 16.0, 16.0],
+        render::Sprite::Building { .. } => [-15.0, -15.0, 30.0, 30.0],
+        render::Sprite::Vehicle(_) => [-10.0, -6.0, 20.0, 12.0],
+        render::Sprite::Item(_) => [-4.0, -4.0, 8.0, 8.0],
+        render::Sprite::Terrain(_) => {
+            let tile = space::TILE_SIZE;
+            [0.0, 0.0, tile, tile]
+        },
+    }
+}
+
+// which file each sprite is drawn from, as mapped in `dir` and then gone
+// over by the mapping in each of `packs` in turn, along with every
+// mapping that was read
+fn merged_mapping(
+    dir: &path::Path,
+    packs: &[path::PathBuf],
+) -> io::Result<(Vec<(String, path::PathBuf)>, Vec<path::PathBuf>)> {
+    let mapping_path = dir.join("sprites.txt");
+    let file = fs::File::open(&mapping_path)?;
+    let mut merged = BTreeMap::new();
+    for (key, file) in read_mapping(&mut io::BufReader::new(file))? {
+        merged.insert(key, dir.join(file));
+    }
+    let mut read = vec![mapping_path];
+    for pack in packs {
+        // packs don't have to redraw anything
+        let mapping_path = pack.join("sprites.txt");
+        if !mapping_path.exists() {
+            continue;
+        }
+        let mapping = fs::File::open(&mapping_path)
+            .and_then(|file| read_mapping(&mut io::BufReader::new(file)));
+        match mapping {
+            Ok(mapping) => {
+                for (key, file) in mapping {
+                    merged.insert(key, pack.join(file));
+                }
+                read.push(mapping_path);
+            },
+            Err(e) => {
+                warn!("Left out {}: {}", mapping_path.display(), e);
+            },
+        }
+    }
+    Ok((merged.into_iter().collect(), read))
+}
+
+// every file the art comes from, the mappings first
+fn sources(
+    dir: &path::Path,
+    packs: &[path::PathBuf],
+) -> io::Result<Vec<path::PathBuf>> {
+    let (mapping, mut sources) = merged_mapping(dir, packs)?;
+    sources.extend(mapping.into_iter().map(|(_, file)| file));
+    Ok(sources)
+}
+
+// the newest time any of `files` was changed
+fn last_changed(files: &[path::PathBuf]) -> Option<time::SystemTime> {
+    files.iter()
+         .filter_map(|file| fs::metadata(file).ok())
+         .filter_map(|meta| meta.modified().ok())
+         .max()
+}
+
+/// The art for every sprite, packed into one texture so that it all draws
+/// without switching textures, and the font that text is drawn in.
+pub struct Assets {
+    dir: path::PathBuf,
+    // the packs whose art goes over it, in the order they go on in
+    packs: Vec<path::PathBuf>,
+    texture: Option<app::G2dTexture>,
+    // where in the texture each sprite's art is
+    rects: BTreeMap<String, [f64; 4]>,
+    changed: Option<time::SystemTime>,
+    // glyphs are cached as they are first drawn, which happens while
+    // the art is only being looked at
+    glyphs: cell::RefCell<Option<app::Glyphs>>,
+    #[cfg(debug_assertions)]
+    checked: time::Instant,
+}
+
+impl Assets {
+    /// Assets with no art at all, so that everything is drawn as shapes.
+    pub fn none() -> Assets {
+        Assets {
+            dir: assets_dir(),
+            packs: Vec::new(),
+            texture: None,
+            rects: BTreeMap::new(),
+            changed: None,
+            glyphs: cell::RefCell::new(None),
+            #[cfg(debug_assertions)]
+            checked: time::Instant::now(),
+        }
+    }
+
+    /// Loads whatever art the mapping in `dir` names, with whatever the
+    /// mappings in the directories of `packs` name going over it, leaving
+    /// out whatever can't be loaded, which is then drawn as shapes, and the
+    /// font that the language in use says to draw text in, without which
+    /// text isn't drawn at all.
+    pub fn load(
+        factory: &mut app::GfxFactory,
+        dir: &path::Path,
+        packs: &[path::PathBuf],
+    ) -> Assets {
+        let mut assets = Assets::none();
+        assets.dir = dir.to_path_buf();
+        assets.packs = packs.to_vec();
+        assets.reload(factory);
+        let font = dir.join(locale::text("font"));
+        let settings = app::TextureSettings::new();
+        match app::Glyphs::new(&font, factory.clone(), settings) {
+            Ok(glyphs) => assets.glyphs = cell::RefCell::new(Some(glyphs)),
+            Err(e) => warn!("Couldn't load {}: {}", font.display(), e),
+        }
+        assets
+    }
+
+    /// The font to draw text in, if it loaded.
+    pub fn glyphs(self: &Self) -> cell::RefMut<Option<app::Glyphs>> {
+        self.glyphs.borrow_mut()
+    }
+
+    fn reload(self: &mut Self, factory: &mut app::GfxFactory) {
+        let mapping = match merged_mapping(&self.dir, &self.packs) {
+            Ok((mapping, _)) => mapping,
+            Err(e) => {
+                warn!("No sprites, couldn't load the mapping: {}", e);
+                return;
+            },
+        };
+
+        let mut keys = Vec::new();
+        let mut images = Vec::new();
+        for (key, path) in mapping {
+            match image::open(&path) {
+                Ok(image) => {
+                    keys.push(key);
+                    images.push(image.to_rgba());
+                },
+                Err(e) => warn!("Couldn't load {}: {}", path.display(), e),
+            }
+        }
+        let sizes: Vec<[u32; 2]> = images
+            .iter()
+            .map(|image| [image.width(), image.height()])
+            .collect();
+        let (places, size) = pack(&sizes);
+        if size[0] == 0 || size[1] == 0 {
+            return;
+        }
+
+        // every image copied into its place, a row at a time
+        let stride = size[0] as usize * 4;
+        let mut pixels = vec![0; stride * size[1] as usize];
+        for (image, place) in images.iter().zip(&places) {
+            let row = image.width() as usize * 4;
+            let raw: &[u8] = &**image;
+            for y in 0..image.height() as usize {
+                let to = (place[1] as usize + y) * stride
+                    + place[0] as usize * 4;
+                pixels[to..to + row]
+                    .copy_from_slice(&raw[y * row..(y + 1) * row]);
+            }
+        }
+        let settings = app::TextureSettings::new();
+        let texture = app::Texture::create(
+            factory, app::Format::Rgba8, &pixels, size, &settings,
+        );
+        match texture {
+            Ok(texture) => self.texture = Some(texture),
+            Err(e) => {
+                warn!("Couldn't make the sprite atlas: {:?}", e);
+                return;
+            },
+        }
+        self.rects = keys.into_iter().zip(places.iter().zip(&sizes)).map(
+            |(key, (place, size))| {
+                let rect = [
+                    place[0] as f64,
+                    place[1] as f64,
+                    size[0] as f64,
+                    size[1] as f64,
+                ];
+                (key, rect)
+            },
+        ).collect();
+        self.changed = sources(&self.dir, &self.packs)
+            .ok()
+            .and_then(|files| last_changed(&files));
+    }
+
+    /// Loads the art again if any of it has changed since it was loaded,
+    /// looking every so often, so that art can be redrawn without starting
+    /// over. Release builds don't look.
+    #[cfg(debug_assertions)]
+    pub fn refresh(self: &mut Self, factory: &mut app::GfxFactory) {
+        if self.checked.elapsed() < time::Duration::from_secs(RELOAD_EVERY) {
+            return;
+        }
+        self.checked = time::Instant::now();
+        let changed = sources(&self.dir, &self.packs)
+            .ok()
+            .and_then(|files| last_changed(&files));
+        if changed.is_some() && changed != self.changed {
+            info!("Art changed, reloading it");
+            self.reload(factory);
+        }
+    }
+
+    #[cfg(not(debug_assertions))]
+    pub fn refresh(self: &mut Self, _factory: &mut app::GfxFactory) {
+    }
+
+    /// Draws the art for `sprite` in `color`, giving whether there was any
+    /// art to draw.
+    pub fn draw(
+        self: &Self,
+        sprite: render::Sprite,
+        color: render::Color,
+        draw_state: &app::DrawState,
+        trans: app::math::Matrix2d,
+        graphics: &mut app::G2d,
+    ) -> bool {
+        let texture = match self.texture {
+            Some(ref texture) => texture,
+            None => return false,
+        };
+        let src = frame_key(sprite)
+            .into_iter()
+            .chain(key(sprite))
+            .filter_map(|key| self.rects.get(&key).cloned())
+            .next();
+        let src = match src {
+            Some(src) => src,
+            None => return false,
+        };
+        app::Image::new_color(color)
+            .src_rect(src)
+            .rect(bounds(sprite))
+            .draw(texture, draw_state, trans, graphics);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test_assets {
+    use city_internal::entities::building;
+
+    use super::*;
+
+    #[test]
+    fn mappings_are_read_a_line_each() {
+        let text = b"# the art\nplayer = \"people/player.png\"\n\n\
+                     terrain.water = \"water.png\"\n";
+        let mapping = read_mapping(&mut &text[..]).unwrap();
+        assert_eq!(mapping, vec![
+            ("player".to_string(), path::PathBuf::from("people/player.png")),
+            ("terrain.water".to_string(), path::PathBuf::from("water.png")),
+        ]);
+        assert!(read_mapping(&mut &b"player = player.png\n"[..]).is_err());
+    }
+
+    #[test]
+    fn packs_go_over_the_art() {
+        use std::env;
+        use std::io::Write;
+
+        let dir = env::temp_dir().join("lil_city_pack_art_test");
+        let _ = fs::remove_dir_all(&dir);
+        let write = |path: &path::Path, text: &str| {
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            let mut file = fs::File::create(path).unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        };
+        let assets = dir.join("assets");
+        let pack = dir.join("pack");
+        write(&assets.join("sprites.txt"),
+              "player = \"player.png\"\nvehicle = \"car.png\"\n");
+        write(&pack.join("sprites.txt"), "vehicle = \"bus.png\"\n");
+
+        let packs = vec![pack.clone(), dir.join("nothing_redrawn")];
+        let (mapping, read) = merged_mapping(&assets, &packs).unwrap();
+        assert_eq!(mapping, vec![
+            ("player".to_string(), assets.join("player.png")),
+            ("vehicle".to_string(), pack.join("bus.png")),
+        ]);
+        assert_eq!(read, vec![
+            assets.join("sprites.txt"),
+            pack.join("sprites.txt"),
+        ]);
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn images_are_packed_in_rows() {
+        let sizes = [[600, 20], [400, 30], [100, 10], [50, 50]];
+        let (places, size) = pack(&sizes);
+        assert_eq!(places, vec![[0, 0], [600, 0], [0, 30], [100, 30]]);
+        assert_eq!(size, [1000, 80]);
+    }
+
+    #[test]
+    fn sprites_are_keyed_by_what_they_are() {
+        let plant = render::Sprite::Building {
+            blueprint: Some(building::Blueprint::PowerPlant),
+            blackout: true,
+            burning: false,
+            frame: animation::Cycle::Unlit.at(Default::default(), 0),
+        };
+        assert_eq!(key(plant), Some("building.power_plant".to_string()));
+        let frame = frame_key(plant);
+        assert_eq!(frame, Some("building.power_plant.unlit.0".to_string()));
+        let unknown = render::Sprite::Item(None);
+        assert_eq!(key(unknown), Some("item.unknown".to_string()));
+        let open = render::Sprite::Terrain(space::Tile::Open);
+        assert_eq!(key(open), None);
+    }
+}
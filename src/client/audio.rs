@@ -0,0 +1,345 @@
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path;
+
+use city_internal::space::sounds;
+use city_internal::sulphate;
+use city_internal::units;
+
+use client::settings_file::SettingsFile;
+
+// how far from where the camera is looking a sound can still be heard
+const HEARING: f64 = 640.0;
+
+/// How loud each kind of audio plays, from 0 for silent to 1 for as loud
+/// as it was recorded.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Volumes {
+    pub effects: f32,
+    pub ambient: f32,
+    pub music: f32,
+}
+
+impl Default for Volumes {
+    fn default() -> Volumes {
+        Volumes { effects: 0.8, ambient: 0.4, music: 0.5 }
+    }
+}
+
+fn invalid(line: &str) -> io::Error {
+    let msg = format!("malformed volume: {}", line);
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+impl SettingsFile for Volumes {
+    fn path() -> path::PathBuf {
+        path::PathBuf::from("audio.toml")
+    }
+
+    fn called() -> &'static str {
+        "volumes"
+    }
+
+    /// Reads volumes written as `write` writes them, leaving any that
+    /// aren't mentioned as they are by default.
+    fn read(input: &mut io::BufRead) -> io::Result<Volumes> {
+        let mut volumes = Volumes::default();
+        for line in input.lines() {
+            let line = line?;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let mut halves = trimmed.splitn(2, '=');
+            let name = halves.next().unwrap_or("").trim();
+            let volume: f32 = halves.next()
+                .and_then(|value| value.trim().parse().ok())
+                .ok_or_else(|| invalid(&line))?;
+            let volume = volume.max(0.0).min(1.0);
+            match name {
+                "effects" => volumes.effects = volume,
+                "ambient" => volumes.ambient = volume,
+                "music" => volumes.music = volume,
+                _ => return Err(invalid(&line)),
+            }
+        }
+        Ok(volumes)
+    }
+
+    fn write(self: &Self, out: &mut io::Write) -> io::Result<()> {
+        writeln!(out, "effects = {}", self.effects)?;
+        writeln!(out, "ambient = {}", self.ambient)?;
+        writeln!(out, "music = {}", self.music)
+    }
+}
+
+/// What plays over and over for as long as the client is open.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Track {
+    /// The hum of the city.
+    Ambient,
+    Music,
+}
+
+impl Track {
+    pub fn all() -> [Track; 2] {
+        [Track::Ambient, Track::Music]
+    }
+
+    pub fn name(self: Self) -> &'static str {
+        match self {
+            Track::Ambient => "ambient",
+            Track::Music => "music",
+        }
+    }
+}
+
+/// How much of a sound made at `at` is heard by a camera looking at
+/// `focus`, from 1 right where it is looking to 0 out past hearing.
+pub fn falloff(focus: units::Position, at: units::Position) -> f32 {
+    let offset = at - focus;
+    let x: f64 = offset.x.into();
+    let y: f64 = offset.y.into();
+    let distance = (x * x + y * y).sqrt();
+    (1.0 - distance / HEARING).max(0.0) as f32
+}
+
+/// Plays the sounds the server says were made nearby, and the tracks that
+/// loop under them.
+pub struct Audio {
+    volumes: Volumes,
+    // every sound in the last view, so that the next one, which still has
+    // most of them, doesn't play them again
+    played: Vec<sounds::Heard>,
+    output: output::Output,
+}
+
+impl Audio {
+    pub fn new(volumes: Volumes) -> Audio {
+        let output = output::Output::new();
+        Audio { volumes, played: Vec::new(), output }
+    }
+
+    /// Starts every track looping, at the volume set for it.
+    pub fn start_tracks(self: &mut Self) {
+        for &track in &Track::all() {
+            let volume = match track {
+                Track::Ambient => self.volumes.ambient,
+                Track::Music => self.volumes.music,
+            };
+            self.output.loop_track(track, volume);
+        }
+    }
+
+    /// Plays every sound in `view` that no view before it had, as loud as
+    /// it is for a camera looking at `focus`, giving each sound played and
+    /// how loud it was.
+    pub fn hear(
+        self: &mut Self,
+        view: &sulphate::WorldView,
+        focus: units::Position,
+    ) -> Vec<(sounds::Sound, f32)> {
+        let mut heard = Vec::new();
+        for each in &view.sounds {
+            if self.played.contains(each) {
+                continue;
+            }
+            let volume = self.volumes.effects * falloff(focus, each.position);
+            if volume > 0.0 {
+                self.output.play(each.sound, volume);
+                heard.push((each.sound, volume));
+            }
+        }
+        self.played = view.sounds.clone();
+        heard
+    }
+}
+
+#[cfg(feature = "audio")]
+mod output {
+    use std::fs;
+    use std::io;
+    use std::io::Read;
+    use std::path;
+
+    use rodio;
+    use rodio::Source;
+
+    use city_internal::space::sounds;
+
+    use super::Track;
+
+    // the whole file, read up front so that playing a sound doesn't wait
+    // on the disk
+    fn read(path: &path::Path) -> Option<Vec<u8>> {
+        let mut bytes = Vec::new();
+        let read = fs::File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut bytes));
+        match read {
+            Ok(_) => Some(bytes),
+            Err(e) => {
+                warn!("Couldn't load {}: {}", path.display(), e);
+                None
+            },
+        }
+    }
+
+    fn sound_path(sound: sounds::Sound) -> path::PathBuf {
+        let name = format!("{}.ogg", sound.name());
+        path::Path::new("assets").join("sounds").join(name)
+    }
+
+    fn track_path(track: Track) -> path::PathBuf {
+        let name = format!("{}.ogg", track.name());
+        path::Path::new("assets").join("music").join(name)
+    }
+
+    pub struct Output {
+        endpoint: Option<rodio::Endpoint>,
+        sounds: Vec<(sounds::Sound, Vec<u8>)>,
+        // kept so that the tracks keep playing
+        tracks: Vec<rodio::Sink>,
+    }
+
+    impl Output {
+        pub fn new() -> Output {
+            let endpoint = rodio::default_endpoint();
+            if endpoint.is_none() {
+                warn!("No audio device, playing nothing");
+            }
+            let sounds = sounds::Sound::all()
+                .iter()
+                .filter_map(|&sound| {
+                    read(&sound_path(sound)).map(|bytes| (sound, bytes))
+                })
+                .collect();
+            Output { endpoint, sounds, tracks: Vec::new() }
+        }
+
+        pub fn play(self: &mut Self, sound: sounds::Sound, volume: f32) {
+            let endpoint = match self.endpoint {
+                Some(ref endpoint) => endpoint,
+                None => return,
+            };
+            let bytes = match self.sounds.iter().find(|&&(s, _)| s == sound) {
+                Some(&(_, ref bytes)) => bytes.clone(),
+                None => return,
+            };
+            match rodio::Decoder::new(io::Cursor::new(bytes)) {
+                Ok(source) => {
+                    let sink = rodio::Sink::new(endpoint);
+                    sink.set_volume(volume);
+                    sink.append(source);
+                    sink.detach();
+                },
+                Err(e) => warn!("Couldn't play {}: {:?}", sound.name(), e),
+            }
+        }
+
+        pub fn loop_track(self: &mut Self, track: Track, volume: f32) {
+            let endpoint = match self.endpoint {
+                Some(ref endpoint) => endpoint,
+                None => return,
+            };
+            let bytes = match read(&track_path(track)) {
+                Some(bytes) => bytes,
+                None => return,
+            };
+            match rodio::Decoder::new(io::Cursor::new(bytes)) {
+                Ok(source) => {
+                    let sink = rodio::Sink::new(endpoint);
+                    sink.set_volume(volume);
+                    sink.append(source.buffered().repeat_infinite());
+                    self.tracks.push(sink);
+                },
+                Err(e) => warn!("Couldn't play {}: {:?}", track.name(), e),
+            }
+        }
+    }
+}
+
+// without a way to play anything, everything is played to nobody
+#[cfg(not(feature = "audio"))]
+mod output {
+    use city_internal::space::sounds;
+
+    use super::Track;
+
+    pub struct Output;
+
+    impl Output {
+        pub fn new() -> Output {
+            Output
+        }
+
+        pub fn play(self: &mut Self, _sound: sounds::Sound, _volume: f32) {
+        }
+
+        pub fn loop_track(self: &mut Self, _track: Track, _volume: f32) {
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_audio {
+    use city_internal::space::sounds;
+    use city_internal::units;
+
+    use super::*;
+
+    fn at(x: i32) -> units::Position {
+        let offset = units::Displacement { x: x.into(), y: 0.into() };
+        units::Position::default() + offset
+    }
+
+    fn view(sounds: Vec<sounds::Heard>) -> sulphate::WorldView {
+        sulphate::WorldView {
+            time: Default::default(),
+            entities: Vec::new(),
+            balances: Default::default(),
+            terrain: Vec::new(),
+            roads: Vec::new(),
+            zones: Vec::new(),
+            pipes: Vec::new(),
+            traffic: Vec::new(),
+            sounds,
+        }
+    }
+
+    #[test]
+    fn volumes_read_back_as_they_were_written() {
+        let volumes = Volumes { effects: 0.25, ambient: 0.0, music: 1.0 };
+        let mut out = Vec::new();
+        volumes.write(&mut out).unwrap();
+        assert_eq!(Volumes::read(&mut &out[..]).unwrap(), volumes);
+        assert!(Volumes::read(&mut &b"cowbell = 1\n"[..]).is_err());
+    }
+
+    #[test]
+    fn sounds_play_once_and_quieter_further_away() {
+        let mut audio = Audio::new(Volumes::default());
+        let time = units::Time::default();
+        let siren = sounds::Heard {
+            time,
+            sound: sounds::Sound::Siren,
+            position: at(320),
+        };
+        let crash = sounds::Heard {
+            time,
+            sound: sounds::Sound::Collision,
+            position: at(HEARING as i32 * 2),
+        };
+        let heard = audio.hear(&view(vec![siren, crash]), at(0));
+        let effects = Volumes::default().effects;
+        assert_eq!(heard, vec![(sounds::Sound::Siren, effects * 0.5)]);
+        // the next view still has it, but it has already been played
+        let building = sounds::Heard {
+            sound: sounds::Sound::Construction,
+            position: at(0),
+            ..siren
+        };
+        let heard = audio.hear(&view(vec![siren, building]), at(0));
+        assert_eq!(heard, vec![(sounds::Sound::Construction, effects)]);
+    }
+}
@@ -0,0 +1,373 @@
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path;
+
+use piston_window as app;
+
+use client::settings_file::SettingsFile;
+use client::user_input::Dir;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Move(Dir),
+    Fire,
+    Pause,
+    Faster,
+    Slower,
+    Pan(Dir),
+    Drag,
+    Follow,
+    ShowPath,
+    // picks the next blueprint to build, or stops building after the last
+    Build,
+    // the same for zones, with taking zoning off after the last one
+    Zone,
+    // starts typing a line of chat, which keeps the other keys to itself
+    Chat,
+    // shows or hides the debug overlay
+    Debug,
+    // goes faster for as long as it is held, and stamina lasts
+    Sprint,
+    // switches between walking and running
+    Walk,
+    // does whatever the nearest thing does when walked up to
+    Interact,
+    // takes back the last thing built, or builds again what was taken back
+    Undo,
+    Redo,
+    // shows or hides what the city has taken in and spent lately
+    Budget,
+    // starts or stops laying pipe, which is dragged out like zones
+    Pipe,
+    // starts or stops laying road, which is dragged out the same way
+    Road,
+    // shows where the next utility reaches, or the traffic after the last
+    // of them, and then nothing again
+    Overlay,
+}
+
+impl Action {
+    pub fn all() -> [Action; 28] {
+        [
+            Action::Move(Dir::Up),
+            Action::Move(Dir::Down),
+            Action::Move(Dir::Left),
+            Action::Move(Dir::Right),
+            Action::Fire,
+            Action::Pause,
+            Action::Faster,
+            Action::Slower,
+            Action::Pan(Dir::Up),
+            Action::Pan(Dir::Down),
+            Action::Pan(Dir::Left),
+            Action::Pan(Dir::Right),
+            Action::Drag,
+            Action::Follow,
+            Action::ShowPath,
+            Action::Build,
+            Action::Zone,
+            Action::Chat,
+            Action::Debug,
+            Action::Sprint,
+            Action::Walk,
+            Action::Interact,
+            Action::Undo,
+            Action::Redo,
+            Action::Budget,
+            Action::Pipe,
+            Action::Road,
+            Action::Overlay,
+        ]
+    }
+
+    pub fn name(self: Self) -> &'static str {
+        match self {
+            Action::Move(Dir::Up) => "move_up",
+            Action::Move(Dir::Down) => "move_down",
+            Action::Move(Dir::Left) => "move_left",
+            Action::Move(Dir::Right) => "move_right",
+            Action::Fire => "fire",
+            Action::Pause => "pause",
+            Action::Faster => "faster",
+            Action::Slower => "slower",
+            Action::Pan(Dir::Up) => "pan_up",
+            Action::Pan(Dir::Down) => "pan_down",
+            Action::Pan(Dir::Left) => "pan_left",
+            Action::Pan(Dir::Right) => "pan_right",
+            Action::Drag => "drag",
+            Action::Follow => "follow",
+            Action::ShowPath => "show_path",
+            Action::Build => "build",
+            Action::Zone => "zone",
+            Action::Chat => "chat",
+            Action::Debug => "debug",
+            Action::Sprint => "sprint",
+            Action::Walk => "walk",
+            Action::Interact => "interact",
+            Action::Undo => "undo",
+            Action::Redo => "redo",
+            Action::Budget => "budget",
+            Action::Pipe => "pipe",
+            Action::Road => "road",
+            Action::Overlay => "overlay",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::all().iter().cloned().find(|action| action.name() == name)
+    }
+}
+
+// buttons on different devices can be bound to the same action,
+// but rebinding only replaces the ones on the same device
+fn same_device(a: app::Button, b: app::Button) -> bool {
+    use piston_window::Button::*;
+    match (a, b) {
+        (Keyboard(_), Keyboard(_)) => true,
+        (Mouse(_), Mouse(_)) => true,
+        (Controller(a), Controller(b)) => a.id == b.id,
+        _ => false,
+    }
+}
+
+fn write_button(button: app::Button) -> String {
+    match button {
+        app::Button::Keyboard(key) =>
+            format!("keyboard {}", u32::from(key)),
+        app::Button::Mouse(mouse) => format!("mouse {}", u32::from(mouse)),
+        app::Button::Controller(pad) =>
+            format!("controller {} {}", pad.id, pad.button),
+    }
+}
+
+fn parse_button(text: &str) -> Option<app::Button> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let button = match *words.get(0)? {
+        "keyboard" => {
+            let code: u32 = words.get(1)?.parse().ok()?;
+            app::Button::Keyboard(app::Key::from(code))
+        },
+        "mouse" => {
+            let code: u32 = words.get(1)?.parse().ok()?;
+            app::Button::Mouse(app::MouseButton::from(code))
+        },
+        "controller" => {
+            let id = words.get(1)?.parse().ok()?;
+            let button = words.get(2)?.parse().ok()?;
+            app::Button::Controller(app::ControllerButton { id, button })
+        },
+        _ => return None,
+    };
+    Some(button)
+}
+
+fn pad_button(button: u8) -> app::Button {
+    app::Button::Controller(app::ControllerButton { id: 0, button })
+}
+
+fn key(key: app::Key) -> app::Button {
+    app::Button::Keyboard(key)
+}
+
+/// Which buttons do what.
+///
+/// Saved as a TOML table of action names to lists of buttons, such as
+/// `move_up = ["keyboard 119", "controller 0 11"]`.
+#[derive(Clone)]
+pub struct Bindings {
+    bound: Vec<(Action, app::Button)>,
+}
+
+impl Default for Bindings {
+    fn default() -> Bindings {
+        let bound = vec![
+            (Action::Move(Dir::Up), key(app::Key::W)),
+            (Action::Move(Dir::Down), key(app::Key::S)),
+            (Action::Move(Dir::Left), key(app::Key::A)),
+            (Action::Move(Dir::Right), key(app::Key::D)),
+            // d-pad, as numbered by SDL game controllers
+            (Action::Move(Dir::Up), pad_button(11)),
+            (Action::Move(Dir::Down), pad_button(12)),
+            (Action::Move(Dir::Left), pad_button(13)),
+            (Action::Move(Dir::Right), pad_button(14)),
+            (Action::Fire, app::Button::Mouse(app::MouseButton::Left)),
+            (Action::Pause, key(app::Key::P)),
+            (Action::Faster, key(app::Key::RightBracket)),
+            (Action::Slower, key(app::Key::LeftBracket)),
+            (Action::Pan(Dir::Up), key(app::Key::Up)),
+            (Action::Pan(Dir::Down), key(app::Key::Down)),
+            (Action::Pan(Dir::Left), key(app::Key::Left)),
+            (Action::Pan(Dir::Right), key(app::Key::Right)),
+            (Action::Drag, app::Button::Mouse(app::MouseButton::Middle)),
+            (Action::Follow, key(app::Key::F)),
+            (Action::ShowPath, app::Button::Mouse(app::MouseButton::Right)),
+            (Action::Build, key(app::Key::B)),
+            (Action::Zone, key(app::Key::Z)),
+            (Action::Chat, key(app::Key::Return)),
+            (Action::Debug, key(app::Key::F3)),
+            (Action::Sprint, key(app::Key::LShift)),
+            (Action::Walk, key(app::Key::CapsLock)),
+            (Action::Interact, key(app::Key::E)),
+            (Action::Undo, key(app::Key::U)),
+            (Action::Redo, key(app::Key::R)),
+            (Action::Budget, key(app::Key::M)),
+            (Action::Pipe, key(app::Key::L)),
+            (Action::Road, key(app::Key::T)),
+            (Action::Overlay, key(app::Key::O)),
+        ];
+        Bindings { bound }
+    }
+}
+
+fn invalid(line: &str) -> io::Error {
+    let msg = format!("malformed binding: {}", line);
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// just enough of TOML to read back what `write` writes
+fn parse_line(line: &str) -> Option<(Action, Vec<app::Button>)> {
+    let mut halves = line.splitn(2, '=');
+    let action = Action::from_name(halves.next()?.trim())?;
+    let list = halves.next()?.trim();
+    if !list.starts_with('[') || !list.ends_with(']') {
+        return None;
+    }
+    let mut buttons = Vec::new();
+    for item in list[1..list.len() - 1].split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+        if !item.starts_with('"') || !item.ends_with('"') || item.len() < 2 {
+            return None;
+        }
+        buttons.push(parse_button(&item[1..item.len() - 1])?);
+    }
+    Some((action, buttons))
+}
+
+impl Bindings {
+    pub fn action(self: &Self, button: app::Button) -> Option<Action> {
+        self.bound
+            .iter()
+            .find(|&&(_, bound)| bound == button)
+            .map(|&(action, _)| action)
+    }
+
+    pub fn buttons(self: &Self, action: Action) -> Vec<app::Button> {
+        self.bound
+            .iter()
+            .filter(|&&(bound_action, _)| bound_action == action)
+            .map(|&(_, button)| button)
+            .collect()
+    }
+
+    /// Makes `button` the only button on its device for `action`, and takes
+    /// it away from whatever it did before.
+    pub fn rebind(self: &mut Self, action: Action, button: app::Button) {
+        self.bound.retain(|&(bound_action, bound)| {
+            let replaced = bound_action == action
+                        && same_device(bound, button);
+            !replaced && bound != button
+        });
+        self.bound.push((action, button));
+    }
+}
+
+impl SettingsFile for Bindings {
+    fn path() -> path::PathBuf {
+        path::PathBuf::from("bindings.toml")
+    }
+
+    fn called() -> &'static str {
+        "bindings"
+    }
+
+    /// Reads the actions that `input` lists over the defaults, so that an
+    /// action left out, such as one added since the file was written,
+    /// keeps its default buttons. A button that `input` gives an action is
+    /// taken away from whatever it did by default.
+    fn read(input: &mut io::BufRead) -> io::Result<Bindings> {
+        let mut bindings = Bindings::default();
+        for line in input.lines() {
+            let line = line?;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let (action, buttons) = parse_line(trimmed)
+                .ok_or_else(|| invalid(&line))?;
+            bindings.bound.retain(|&(bound_action, bound)| {
+                bound_action != action && !buttons.contains(&bound)
+            });
+            for button in buttons {
+                bindings.bound.push((action, button));
+            }
+        }
+        Ok(bindings)
+    }
+
+    fn write(self: &Self, out: &mut io::Write) -> io::Result<()> {
+        for &action in Action::all().iter() {
+            let buttons: Vec<String> = self.buttons(action)
+                .into_iter()
+                .map(|button| format!("\"{}\"", write_button(button)))
+                .collect();
+            writeln!(out, "{} = [{}]", action.name(), buttons.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test_bindings {
+    use piston_window as app;
+
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut bindings = Bindings::default();
+        let up = app::Button::Keyboard(app::Key::Up);
+        bindings.rebind(Action::Move(Dir::Up), up);
+
+        let mut written = Vec::new();
+        bindings.write(&mut written).unwrap();
+        let read = Bindings::read(&mut &written[..]).unwrap();
+
+        for &action in Action::all().iter() {
+            assert_eq!(read.buttons(action), bindings.buttons(action));
+        }
+    }
+
+    #[test]
+    fn unlisted_actions_keep_their_defaults() {
+        let up = app::Button::Keyboard(app::Key::W);
+        let written = format!("pan_up = [\"{}\"]\nfire = []\n",
+                              write_button(up));
+        let read = Bindings::read(&mut written.as_bytes()).unwrap();
+
+        assert_eq!(read.buttons(Action::Pan(Dir::Up)), vec![up]);
+        assert!(read.buttons(Action::Fire).is_empty());
+        // W went to panning, but moving up still has the d-pad
+        let buttons = read.buttons(Action::Move(Dir::Up));
+        assert_eq!(buttons, vec![pad_button(11)]);
+        let defaults = Bindings::default();
+        for &action in &[Action::Pause, Action::Overlay, Action::Build] {
+            assert_eq!(read.buttons(action), defaults.buttons(action));
+        }
+    }
+
+    #[test]
+    fn rebind_keeps_other_devices() {
+        let mut bindings = Bindings::default();
+        let up = app::Button::Keyboard(app::Key::Up);
+        bindings.rebind(Action::Move(Dir::Up), up);
+
+        let buttons = bindings.buttons(Action::Move(Dir::Up));
+        assert!(buttons.contains(&up));
+        assert!(buttons.contains(&pad_button(11)));
+        assert!(!buttons.contains(&key(app::Key::W)));
+    }
+}
@@ -0,0 +1,125 @@
+use std::sync::mpsc;
+
+use city_internal::budget;
+use city_internal::sulphate::server;
+
+use client::locale;
+use client::render;
+
+// seconds between asking the server for the books again
+const REFRESH: f64 = 1.0;
+
+/// The panel that shows what the city took in and spent on each thing over
+/// the last few game days.
+pub struct BudgetPanel {
+    shown: bool,
+    // seconds since the server was last asked for the books
+    since_asked: f64,
+    report: Option<budget::Report>,
+    send_report: mpsc::Sender<budget::Report>,
+    recv_report: mpsc::Receiver<budget::Report>,
+}
+
+impl BudgetPanel {
+    pub fn new() -> BudgetPanel {
+        let (send_report, recv_report) = mpsc::channel();
+        BudgetPanel {
+            shown: false,
+            since_asked: REFRESH,
+            report: None,
+            send_report,
+            recv_report,
+        }
+    }
+
+    pub fn toggle(self: &mut Self) {
+        self.shown = !self.shown;
+    }
+
+    /// Asks the server for the books through `upd` every so often while
+    /// shown, `dt` seconds after the last update.
+    pub fn update(
+        self: &mut Self,
+        dt: f64,
+        upd: &mpsc::Sender<server::Interruption>,
+    ) {
+        if let Some(report) = self.recv_report.try_iter().last() {
+            self.report = Some(report);
+        }
+        self.since_asked += dt;
+        if self.shown && self.since_asked >= REFRESH {
+            self.since_asked = 0.0;
+            let reply = self.send_report.clone();
+            let days = budget::DAYS;
+            let request = server::Interruption::QueryBudget { days, reply };
+            if upd.send(request).is_err() {
+                warn!("Budget wanted from a disconnected server");
+            }
+        }
+    }
+
+    /// The panel's text, a line at a time, or nothing while hidden.
+    pub fn lines(self: &Self) -> Vec<String> {
+        let report = match self.report {
+            Some(ref report) if self.shown => report,
+            _ => return Vec::new(),
+        };
+        let title = locale::format("budget.title", &[&report.days]);
+        let mut lines = vec![title];
+        for line in &report.lines {
+            let name = locale::name("budget", line.category.name());
+            lines.push(locale::format(
+                "budget.line",
+                &[&name, &line.income, &line.expense],
+            ));
+        }
+        let net = format!("{:+}", report.net());
+        lines.push(locale::format("budget.overall", &[&net]));
+        lines
+    }
+
+    /// Draws the panel's text down the screen from `pixel`.
+    pub fn draw(self: &Self, list: &mut render::DrawList, pixel: [f64; 2]) {
+        let mut at = pixel;
+        for line in self.lines() {
+            list.label(at, &line);
+            at[1] += 20.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_budget {
+    use city_internal::budget;
+
+    use super::*;
+
+    #[test]
+    fn the_books_are_only_asked_for_while_shown() {
+        let (upd, requests) = mpsc::channel();
+        let mut panel = BudgetPanel::new();
+        panel.update(2.0, &upd);
+        assert!(requests.try_recv().is_err());
+        assert!(panel.lines().is_empty());
+
+        panel.toggle();
+        panel.update(0.0, &upd);
+        let reply = match requests.try_recv() {
+            Ok(server::Interruption::QueryBudget { reply, .. }) => reply,
+            _ => panic!("the budget wasn't asked for"),
+        };
+        let lines = budget::Category::all()
+            .iter()
+            .map(|&category| {
+                budget::Line { category, income: 10, expense: 4 }
+            })
+            .collect();
+        reply.send(budget::Report { days: 7, lines }).unwrap();
+        panel.update(0.0, &upd);
+
+        let lines = panel.lines();
+        assert_eq!(lines[0], "budget, last 7 days");
+        assert_eq!(lines[1], "taxes +10 -4");
+        assert_eq!(lines.last().unwrap(), "overall +24");
+    }
+}
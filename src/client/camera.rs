@@ -0,0 +1,215 @@
+use city_internal::units;
+
+use piston_window as app;
+
+use client::user_input::Dir;
+use client::user_input::DirPad;
+use client::user_input::DeviceUpdate;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    Follow,
+    Free,
+}
+
+/// Where the screen is looking, and how closely.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct View {
+    // the world position drawn at the middle of the screen
+    pub focus: units::Position,
+    // pixels per unit of world distance
+    pub zoom: units::Scalar,
+    // the middle of the screen, in pixels
+    pub screen_center: [f64; 2],
+}
+
+impl View {
+    /// Turns a transform for drawing in pixels into one for drawing in world
+    /// coordinates.
+    pub fn transform(
+        self: &Self,
+        corner: app::math::Matrix2d,
+    ) -> app::math::Matrix2d {
+        use piston_window::Transformed;
+        let focus = self.focus - units::Position::default();
+        let focus_x: f64 = focus.x.into();
+        let focus_y: f64 = focus.y.into();
+        let zoom: f64 = self.zoom.into();
+        corner.trans(self.screen_center[0], self.screen_center[1])
+              .zoom(zoom)
+              .trans(-focus_x, -focus_y)
+    }
+
+    pub fn to_world(self: &Self, pixel: [f64; 2]) -> units::Position {
+        let zoom: f64 = self.zoom.into();
+        let x = (pixel[0] - self.screen_center[0]) / zoom;
+        let y = (pixel[1] - self.screen_center[1]) / zoom;
+        self.focus + units::Displacement { x: x.into(), y: y.into() }
+    }
+}
+
+fn min_zoom() -> units::Scalar {
+    units::Scalar::from(1) / 4
+}
+
+fn max_zoom() -> units::Scalar {
+    4.into()
+}
+
+// how much one click of the scroll wheel zooms by
+const ZOOM_STEP: f64 = 1.25;
+
+// how fast the arrow keys move the view, in pixels per second,
+// so that panning feels the same at any zoom
+const PAN_SPEED: f64 = 400.0;
+
+pub struct Camera {
+    view: View,
+    mode: Mode,
+    panning: DirPad<bool>,
+    dragging: bool,
+    mouse: [f64; 2],
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        let view = View {
+            focus: Default::default(),
+            zoom: 1.into(),
+            screen_center: [0.0, 0.0],
+        };
+        Camera {
+            view,
+            mode: Mode::Follow,
+            panning: Default::default(),
+            dragging: false,
+            mouse: [0.0, 0.0],
+        }
+    }
+
+    pub fn view(self: &Self) -> View {
+        self.view
+    }
+
+    pub fn mode(self: &Self) -> Mode {
+        self.mode
+    }
+
+    pub fn set_screen_size(self: &mut Self, width: u32, height: u32) {
+        self.view.screen_center = [width as f64 / 2.0, height as f64 / 2.0];
+    }
+
+    /// Keeps `target` in the middle of the screen, unless the camera has
+    /// been moved away from it.
+    pub fn follow(self: &mut Self, target: Option<units::Position>) {
+        if let (Mode::Follow, Some(target)) = (self.mode, target) {
+            self.view.focus = target;
+        }
+    }
+
+    /// Looks at `focus` instead, no longer following anything.
+    pub fn jump_to(self: &mut Self, focus: units::Position) {
+        self.view.focus = focus;
+        self.mode = Mode::Free;
+    }
+
+    fn pan_by(self: &mut Self, pixels: [f64; 2]) {
+        let zoom: f64 = self.view.zoom.into();
+        let x = (pixels[0] / zoom).into();
+        let y = (pixels[1] / zoom).into();
+        self.view.focus = self.view.focus + units::Displacement { x, y };
+        self.mode = Mode::Free;
+    }
+
+    pub fn zoom_by(self: &mut Self, steps: f64) {
+        let zoom: f64 = self.view.zoom.into();
+        let zoom: units::Scalar = (zoom * ZOOM_STEP.powf(steps)).into();
+        self.view.zoom = zoom.max(min_zoom()).min(max_zoom());
+    }
+
+    pub fn on_action(self: &mut Self, action: &DeviceUpdate) {
+        match *action {
+            DeviceUpdate::Pan { dir, held } => {
+                self.panning[dir] = held;
+            },
+            DeviceUpdate::Drag { held } => {
+                self.dragging = held;
+            },
+            DeviceUpdate::ToggleFollow => {
+                self.mode = match self.mode {
+                    Mode::Follow => Mode::Free,
+                    Mode::Free => Mode::Follow,
+                };
+            },
+            DeviceUpdate::Zoom { steps } => {
+                self.zoom_by(steps);
+            },
+            _ => (),
+        }
+    }
+
+    pub fn on_mouse_move(self: &mut Self, mouse: [f64; 2]) {
+        if self.dragging {
+            // the world moves with the mouse, so the view moves against it
+            let dx = self.mouse[0] - mouse[0];
+            let dy = self.mouse[1] - mouse[1];
+            self.pan_by([dx, dy]);
+        }
+        self.mouse = mouse;
+    }
+
+    pub fn update(self: &mut Self, dt: f64) {
+        let mut pixels = [0.0, 0.0];
+        let step = PAN_SPEED * dt;
+        if self.panning[Dir::Up] { pixels[1] -= step; }
+        if self.panning[Dir::Down] { pixels[1] += step; }
+        if self.panning[Dir::Left] { pixels[0] -= step; }
+        if self.panning[Dir::Right] { pixels[0] += step; }
+        if pixels != [0.0, 0.0] {
+            self.pan_by(pixels);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_camera {
+    use city_internal::units;
+
+    use super::*;
+
+    #[test]
+    fn to_world_undoes_zoom() {
+        let mut camera = Camera::new();
+        camera.set_screen_size(600, 400);
+        camera.zoom_by(2.0);
+        let view = camera.view();
+        let zoom: f64 = view.zoom.into();
+
+        let world = view.to_world([300.0 + 10.0 * zoom, 200.0]);
+        let offset = world - units::Position::default();
+        assert!((offset.x - 10.into()).squared() < 1);
+        assert!(offset.y == 0);
+    }
+
+    #[test]
+    fn zoom_is_clamped() {
+        let mut camera = Camera::new();
+        camera.zoom_by(100.0);
+        assert!(camera.view().zoom == max_zoom());
+        camera.zoom_by(-100.0);
+        assert!(camera.view().zoom == min_zoom());
+    }
+
+    #[test]
+    fn dragging_stops_following() {
+        let mut camera = Camera::new();
+        camera.on_mouse_move([0.0, 0.0]);
+        camera.on_action(&DeviceUpdate::Drag { held: true });
+        camera.on_mouse_move([10.0, 0.0]);
+        assert_eq!(camera.mode(), Mode::Free);
+
+        let target = units::Position::default();
+        camera.follow(Some(target));
+        assert!(camera.view().focus != target);
+    }
+}
@@ -0,0 +1,124 @@
+use std::collections;
+
+use city_internal::sulphate::chat;
+
+use piston_window as app;
+
+// how many lines are kept on screen, newest last
+const SHOWN: usize = 8;
+
+/// What a button did to the line being typed.
+pub enum Typed {
+    Still,
+    Sent(String),
+    /// Given up on, or sent with nothing in it.
+    Closed,
+}
+
+/// The last few lines said, and whatever the player is in the middle of
+/// saying.
+pub struct ChatBox {
+    history: collections::VecDeque<chat::ChatLine>,
+    // None unless the player is typing
+    draft: Option<String>,
+}
+
+impl ChatBox {
+    pub fn new() -> ChatBox {
+        let history = collections::VecDeque::new();
+        let draft = None;
+        ChatBox { history, draft }
+    }
+
+    pub fn open(self: &mut Self) {
+        self.draft = Some(String::new());
+    }
+
+    pub fn draft(self: &Self) -> Option<&str> {
+        self.draft.as_ref().map(|draft| &draft[..])
+    }
+
+    pub fn history(self: &Self) -> &collections::VecDeque<chat::ChatLine> {
+        &self.history
+    }
+
+    pub fn receive(self: &mut Self, line: chat::ChatLine) {
+        self.history.push_back(line);
+        if self.history.len() > SHOWN {
+            self.history.pop_front();
+        }
+    }
+
+    /// Adds what was typed to the draft, leaving out anything that can't go
+    /// in a line of chat.
+    pub fn on_text(self: &mut Self, text: &str) {
+        if let Some(ref mut draft) = self.draft {
+            let room = chat::MAX_LENGTH - draft.chars().count();
+            draft.extend(text.chars().filter(|c| !c.is_control()).take(room));
+        }
+    }
+
+    /// Return sends the draft and backspace takes a character off it.
+    /// Letters come through `on_text` instead, since which one a key is
+    /// depends on the keyboard.
+    pub fn on_button(self: &mut Self, bin: app::ButtonArgs) -> Typed {
+        if bin.state != app::ButtonState::Press {
+            return Typed::Still;
+        }
+        match bin.button {
+            app::Button::Keyboard(app::Key::Return) => {
+                let draft = self.draft.take().unwrap_or_default();
+                match chat::clean(&draft) {
+                    Some(text) => Typed::Sent(text),
+                    None => Typed::Closed,
+                }
+            },
+            app::Button::Keyboard(app::Key::Backspace) => {
+                if let Some(ref mut draft) = self.draft {
+                    draft.pop();
+                }
+                Typed::Still
+            },
+            _ => Typed::Still,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_chat_box {
+    use piston_window as app;
+
+    use super::*;
+
+    fn press(key: app::Key) -> app::ButtonArgs {
+        let button = app::Button::Keyboard(key);
+        let state = app::ButtonState::Press;
+        app::ButtonArgs { state, button, scancode: None }
+    }
+
+    #[test]
+    fn typing_builds_up_a_line() {
+        let mut chat = ChatBox::new();
+        chat.on_text("ignored");
+        assert!(chat.draft().is_none());
+
+        chat.open();
+        chat.on_text("hello\r");
+        chat.on_text(" wor");
+        chat.on_button(press(app::Key::Backspace));
+        chat.on_text("rld");
+        assert_eq!(chat.draft(), Some("hello world"));
+        match chat.on_button(press(app::Key::Return)) {
+            Typed::Sent(text) => assert_eq!(text, "hello world"),
+            _ => panic!("return didn't send"),
+        }
+        assert!(chat.draft().is_none());
+
+        chat.open();
+        chat.on_text("   ");
+        match chat.on_button(press(app::Key::Return)) {
+            Typed::Closed => (),
+            _ => panic!("sent a line with nothing in it"),
+        }
+    }
+}
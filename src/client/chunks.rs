@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+
+use city_internal::space;
+use city_internal::space::chunks;
+use city_internal::units;
+
+use client::camera;
+
+// seconds before a chunk still in view is asked for again, in case
+// something was built on it since
+const REFRESH: f64 = 5.0;
+
+// chunks past the edge of the screen that are asked for anyway, so that
+// they are there by the time the camera gets to them
+const MARGIN: f64 = 1.0;
+
+// chunks this far past the edge of the screen are forgotten
+const FORGET: f64 = 4.0;
+
+// the most chunks asked for at once, leaving the rest for later updates
+const MOST_AT_ONCE: usize = 16;
+
+struct Held {
+    // None until it arrives
+    chunk: Option<chunks::Chunk>,
+    // seconds since it was last asked for
+    age: f64,
+}
+
+/// The chunks of the city that a remote client has been sent, which it
+/// asks for as the camera comes to them, and forgets once the camera has
+/// left them far behind.
+pub struct Chunks {
+    held: BTreeMap<chunks::ChunkId, Held>,
+}
+
+// every chunk within `margin` chunks of what `view` shows
+fn in_view(view: &camera::View, margin: f64) -> Vec<chunks::ChunkId> {
+    let zoom: f64 = view.zoom.into();
+    let half = view.screen_center[0].max(view.screen_center[1]) / zoom;
+    let size = chunks::CHUNK_SIZE as f64 * space::TILE_SIZE;
+    let reach: units::Distance = (half + margin * size).into();
+    chunks::around(view.focus, reach)
+}
+
+impl Chunks {
+    pub fn new() -> Chunks {
+        Chunks { held: BTreeMap::new() }
+    }
+
+    /// Ages every chunk by `dt` seconds, forgets those far out of `view`,
+    /// and gives those that should be asked for now, being those in view
+    /// that haven't been asked for lately.
+    pub fn update(
+        self: &mut Self,
+        dt: f64,
+        view: &camera::View,
+    ) -> Vec<chunks::ChunkId> {
+        let near = in_view(view, FORGET);
+        self.held.retain(|id, _| near.contains(id));
+        for held in self.held.values_mut() {
+            held.age += dt;
+        }
+
+        let mut wanted = Vec::new();
+        for id in in_view(view, MARGIN) {
+            if wanted.len() == MOST_AT_ONCE {
+                break;
+            }
+            let held = self.held.entry(id).or_insert(Held {
+                chunk: None,
+                age: REFRESH,
+            });
+            if held.age >= REFRESH {
+                held.age = 0.0;
+                wanted.push(id);
+            }
+        }
+        wanted
+    }
+
+    /// Keeps `chunk`, unless the camera left it behind while it was on its
+    /// way.
+    pub fn receive(self: &mut Self, chunk: chunks::Chunk) {
+        if let Some(held) = self.held.get_mut(&chunk.id) {
+            held.chunk = Some(chunk);
+        }
+    }
+
+    /// Every chunk that has arrived, in order.
+    pub fn arrived(self: &Self) -> Vec<&chunks::Chunk> {
+        self.held
+            .values()
+            .filter_map(|held| held.chunk.as_ref())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test_chunks {
+    use super::*;
+
+    fn chunk(id: chunks::ChunkId) -> chunks::Chunk {
+        chunks::Chunk {
+            id,
+            terrain: Vec::new(),
+            roads: Vec::new(),
+            zones: Vec::new(),
+            pipes: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn chunks_are_asked_for_once_until_they_go_stale() {
+        let mut chunks = Chunks::new();
+        let mut camera = camera::Camera::new();
+        camera.set_screen_size(200, 200);
+        let view = camera.view();
+        let first = chunks.update(0.1, &view);
+        assert!(!first.is_empty());
+        assert!(first.len() <= MOST_AT_ONCE);
+        for &id in &first {
+            chunks.receive(chunk(id));
+        }
+        assert_eq!(chunks.arrived().len(), first.len());
+        // nothing is asked for twice while it's fresh
+        let again = chunks.update(0.1, &view);
+        assert!(again.iter().all(|id| !first.contains(id)));
+        let stale = chunks.update(REFRESH, &view);
+        assert!(first.iter().all(|id| stale.contains(id)));
+
+        // and anything that was never asked for is turned away
+        chunks.receive(chunk((1000, 1000)));
+        assert_eq!(chunks.arrived().len(), first.len());
+    }
+}
@@ -0,0 +1,247 @@
+use std::sync::mpsc;
+
+use city_internal::entities;
+use city_internal::sulphate;
+use city_internal::sulphate::metrics;
+use city_internal::sulphate::server;
+use city_internal::units;
+
+use client::render;
+
+// seconds between asking the server how it is doing, and between working
+// out the frame rate again
+const REFRESH: f64 = 1.0;
+
+/// What the F3 screen shows: how fast the client draws, how the server is
+/// keeping up, and where things are as the simulation sees them.
+pub struct DebugOverlay {
+    shown: bool,
+    // frames drawn, and seconds gone by, since the rate was last worked out
+    frames: u32,
+    counting: f64,
+    fps: Option<f64>,
+    // seconds since the server was last asked for its metrics
+    since_asked: f64,
+    metrics: Option<metrics::ServerMetrics>,
+    send_metrics: mpsc::Sender<metrics::ServerMetrics>,
+    recv_metrics: mpsc::Receiver<metrics::ServerMetrics>,
+}
+
+impl DebugOverlay {
+    pub fn new() -> DebugOverlay {
+        let (send_metrics, recv_metrics) = mpsc::channel();
+        DebugOverlay {
+            shown: false,
+            frames: 0,
+            counting: 0.0,
+            fps: None,
+            since_asked: REFRESH,
+            metrics: None,
+            send_metrics,
+            recv_metrics,
+        }
+    }
+
+    pub fn is_shown(self: &Self) -> bool {
+        self.shown
+    }
+
+    pub fn toggle(self: &mut Self) {
+        self.shown = !self.shown;
+    }
+
+    /// Counts `dt` seconds towards the frame rate, and asks the server for
+    /// its metrics through `upd` every so often while shown.
+    pub fn update(
+        self: &mut Self,
+        dt: f64,
+        upd: &mpsc::Sender<server::Interruption>,
+    ) {
+        self.counting += dt;
+        if self.counting >= REFRESH {
+            self.fps = Some(self.frames as f64 / self.counting);
+            self.frames = 0;
+            self.counting = 0.0;
+        }
+
+        if let Some(metrics) = self.recv_metrics.try_iter().last() {
+            self.metrics = Some(metrics);
+        }
+        self.since_asked += dt;
+        if self.shown && self.since_asked >= REFRESH {
+            self.since_asked = 0.0;
+            let reply = self.send_metrics.clone();
+            let request = server::Interruption::QueryMetrics { reply };
+            if upd.send(request).is_err() {
+                warn!("Metrics wanted from a disconnected server");
+            }
+        }
+    }
+
+    /// Counts a frame towards the frame rate, whether or not the overlay is
+    /// drawn in it.
+    pub fn frame(self: &mut Self) {
+        self.frames += 1;
+    }
+
+    /// The overlay's text, a line at a time, with player `own`'s body
+    /// looked up in `view`.
+    pub fn lines(
+        self: &Self,
+        view: Option<&sulphate::WorldView>,
+        own: sulphate::EntityId,
+        now: units::Time,
+    ) -> Vec<String> {
+        let mut lines = Vec::new();
+        match self.fps {
+            Some(fps) => lines.push(format!("{:.0} fps", fps)),
+            None => lines.push("? fps".to_string()),
+        }
+        if let Some(ref metrics) = self.metrics {
+            lines.push(format!(
+                "{} cycles, {} events, {} interruptions",
+                metrics.cycles, metrics.events, metrics.interruptions,
+            ));
+            let longest = metrics.longest_cycle;
+            let millis = longest.as_secs() as f64 * 1000.0
+                       + longest.subsec_nanos() as f64 / 1_000_000.0;
+            lines.push(format!(
+                "longest cycle {:.1}ms, {} behind",
+                millis, metrics.drift,
+            ));
+            let dropped = metrics.dropped;
+            lines.push(format!(
+                "dropped {} coalesced, {} limited, {} overflowed",
+                dropped.coalesced, dropped.rate_limited, dropped.overflowed,
+            ));
+        }
+        let view = match view {
+            Some(view) => view,
+            None => return lines,
+        };
+        lines.push(format!("{} entities", view.entities.len()));
+        let player = view.entities.iter().find(|entity| {
+            let is_player = match entity.image {
+                entities::Image::Player(_) => true,
+                _ => false,
+            };
+            is_player && entity.uid.id == own
+        });
+        if let Some(player) = player {
+            let dt = now - view.time;
+            let position = player.position + player.velocity * dt;
+            lines.push(format!("at ({}, {})", position.x, position.y));
+            lines.push(format!(
+                "moving ({}, {})",
+                player.velocity.x, player.velocity.y,
+            ));
+        }
+        lines
+    }
+
+    /// Draws the overlay's text down the screen from `pixel`, and the shape
+    /// that everything in `view` collides as.
+    pub fn draw(
+        self: &Self,
+        list: &mut render::DrawList,
+        pixel: [f64; 2],
+        view: Option<&sulphate::WorldView>,
+        own: sulphate::EntityId,
+        now: units::Time,
+    ) {
+        if let Some(view) = view {
+            list.collision_shapes(view, now);
+        }
+        let mut at = pixel;
+        for line in self.lines(view, own, now) {
+            list.label(at, &line);
+            at[1] += 20.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_debug {
+    use std::any;
+    use std::time;
+
+    use city_internal::entities;
+    use city_internal::sulphate;
+    use city_internal::units;
+
+    use super::*;
+
+    #[test]
+    fn frames_are_counted_over_a_second() {
+        let (upd, _requests) = mpsc::channel();
+        let mut overlay = DebugOverlay::new();
+        let now = units::Time::default();
+        assert_eq!(overlay.lines(None, 0, now)[0], "? fps");
+        for _ in 0..30 {
+            overlay.frame();
+            overlay.update(0.05, &upd);
+        }
+        assert_eq!(overlay.lines(None, 0, now)[0], "20 fps");
+    }
+
+    #[test]
+    fn metrics_are_only_asked_for_while_shown() {
+        let (upd, requests) = mpsc::channel();
+        let mut overlay = DebugOverlay::new();
+        overlay.update(2.0, &upd);
+        assert!(requests.try_recv().is_err());
+
+        overlay.toggle();
+        overlay.update(0.0, &upd);
+        let reply = match requests.try_recv() {
+            Ok(server::Interruption::QueryMetrics { reply }) => reply,
+            _ => panic!("metrics weren't asked for"),
+        };
+        let metrics = metrics::ServerMetrics {
+            cycles: 3,
+            events: 4,
+            interruptions: 5,
+            wall_time: time::Duration::from_secs(1),
+            longest_cycle: time::Duration::from_millis(2),
+            drift: Default::default(),
+            dropped: Default::default(),
+        };
+        reply.send(metrics).unwrap();
+        overlay.update(0.0, &upd);
+        // not again until a second has gone by
+        assert!(requests.try_recv().is_err());
+
+        let lines = overlay.lines(None, 0, Default::default());
+        assert_eq!(lines[1], "3 cycles, 4 events, 5 interruptions");
+    }
+
+    #[test]
+    fn the_player_is_found_in_the_view() {
+        let ty = any::TypeId::of::<entities::Player>();
+        let entity = sulphate::ViewedEntity {
+            uid: sulphate::EntityUId { id: 7, ty },
+            image: entities::Image::Player(entities::player::Image {
+                appearance: Default::default(),
+            }),
+            position: units::Position::default(),
+            velocity: units::Velocity { x: 2.into(), y: 0.into() },
+        };
+        let view = sulphate::WorldView {
+            time: Default::default(),
+            entities: vec![entity],
+            balances: Default::default(),
+            terrain: Vec::new(),
+            roads: Vec::new(),
+            zones: Vec::new(),
+            pipes: Vec::new(),
+            traffic: Vec::new(),
+            sounds: Vec::new(),
+        };
+        let overlay = DebugOverlay::new();
+        let later = units::Time::default() + units::moments(16);
+        let lines = overlay.lines(Some(&view), 7, later);
+        assert_eq!(lines[1], "1 entities");
+        assert_eq!(lines[2], "at (2, 0)");
+        assert_eq!(lines[3], "moving (+2, 0)");
+    }
+}
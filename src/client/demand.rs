@@ -0,0 +1,110 @@
+use std::sync::mpsc;
+
+use city_internal::demand;
+use city_internal::sulphate::server;
+
+use client::locale;
+use client::render;
+
+// seconds between asking the server how much the city wants again
+const REFRESH: f64 = 1.0;
+
+/// The bars that show how much the city wants more of each zone, always
+/// on screen, since they are what says where to zone next.
+pub struct DemandBars {
+    // seconds since the server was last asked
+    since_asked: f64,
+    demand: Option<demand::Demand>,
+    send_demand: mpsc::Sender<demand::Demand>,
+    recv_demand: mpsc::Receiver<demand::Demand>,
+}
+
+impl DemandBars {
+    pub fn new() -> DemandBars {
+        let (send_demand, recv_demand) = mpsc::channel();
+        DemandBars {
+            since_asked: REFRESH,
+            demand: None,
+            send_demand,
+            recv_demand,
+        }
+    }
+
+    /// Asks the server how much the city wants through `upd` every so
+    /// often, `dt` seconds after the last update.
+    pub fn update(
+        self: &mut Self,
+        dt: f64,
+        upd: &mpsc::Sender<server::Interruption>,
+    ) {
+        if let Some(demand) = self.recv_demand.try_iter().last() {
+            self.demand = Some(demand);
+        }
+        self.since_asked += dt;
+        if self.since_asked >= REFRESH {
+            self.since_asked = 0.0;
+            let reply = self.send_demand.clone();
+            let request = server::Interruption::QueryDemand { reply };
+            if upd.send(request).is_err() {
+                warn!("Demand wanted from a disconnected server");
+            }
+        }
+    }
+
+    /// Draws the bars along from `pixel`, with how happy the citizens are
+    /// above them, or nothing until the server has said.
+    pub fn draw(self: &Self, list: &mut render::DrawList, pixel: [f64; 2]) {
+        let demand = match self.demand {
+            Some(ref demand) => demand,
+            None => return,
+        };
+        if let Some(happiness) = demand.happiness {
+            let line = locale::format("demand.happiness", &[&happiness]);
+            list.label([pixel[0], pixel[1] - 60.0], &line);
+        }
+        list.demand_bars(pixel, demand);
+    }
+}
+
+#[cfg(test)]
+mod test_demand {
+    use city_internal::demand;
+
+    use super::*;
+
+    #[test]
+    fn the_bars_wait_for_the_server() {
+        let (upd, requests) = mpsc::channel();
+        let mut bars = DemandBars::new();
+        let mut list = render::DrawList::new();
+        bars.draw(&mut list, [0.0, 0.0]);
+        assert!(list.commands().is_empty());
+
+        bars.update(0.0, &upd);
+        let reply = match requests.try_recv() {
+            Ok(server::Interruption::QueryDemand { reply }) => reply,
+            _ => panic!("the demand wasn't asked for"),
+        };
+        let demand = demand::Demand {
+            happiness: Some(70),
+            residential: 20,
+            commercial: -10,
+            industrial: 0,
+        };
+        reply.send(demand).unwrap();
+        bars.update(0.5, &upd);
+        // not due to ask again yet
+        assert!(requests.try_recv().is_err());
+
+        bars.draw(&mut list, [0.0, 0.0]);
+        let commands = list.commands();
+        // the happiness, then a bar and an initial for each zone
+        assert_eq!(commands.len(), 1 + 3 * 2);
+        match commands[0].item {
+            render::Item::Text { ref text, .. } => {
+                assert_eq!(text, "happiness 70%");
+            },
+            _ => panic!("the happiness wasn't shown"),
+        }
+    }
+}
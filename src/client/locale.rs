@@ -0,0 +1,280 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::path;
+use std::sync;
+
+// what everything says when the language in use doesn't say otherwise,
+// with each {} filled in by whatever goes there, in order
+const ENGLISH: &'static [(&'static str, &'static str)] = &[
+    ("font", "fonts/DejaVuSans.ttf"),
+    ("title", "lil-city"),
+    ("paused", "paused"),
+    ("nameplate", "{}"),
+
+    ("menu.new_game", "new game"),
+    ("menu.load", "load"),
+    ("menu.join", "join {}"),
+    ("menu.quit", "quit"),
+    ("menu.resume", "resume"),
+    ("menu.save", "save"),
+    ("menu.leave", "quit to menu"),
+    ("menu.settings", "settings"),
+    ("menu.back", "back"),
+    ("menu.seed", "new world from seed {}"),
+    ("menu.no_seed", "type a seed for a new world"),
+    ("menu.connecting", "Connecting to {}"),
+    ("settings.resolution", "resolution {}x{}"),
+    ("settings.fullscreen", "fullscreen {}"),
+    ("settings.vsync", "vsync {}"),
+    ("settings.ui_scale", "ui scale {}%"),
+    ("settings.on", "on"),
+    ("settings.off", "off"),
+    ("notice.load_failed", "Failed to load {}: {}"),
+    ("notice.saved", "Saved to {}"),
+    ("notice.stopped", "The server has stopped"),
+    ("notice.connect_failed", "Failed to connect to {}: {}"),
+
+    ("clock", "day {} of {}, {}:{}"),
+    ("season.spring", "spring"),
+    ("season.summer", "summer"),
+    ("season.autumn", "autumn"),
+    ("season.winter", "winter"),
+
+    ("chat.line", "[{}:{}] {}: {}"),
+    ("chat.draft", "> {}_"),
+
+    ("ledger.money", "money {} ({})"),
+    ("ledger.power", "power {}/{}"),
+    ("ledger.water", "water {}/{}"),
+    ("ledger.blackouts", "{} buildings without"),
+    ("money.change", "{}${}"),
+
+    ("budget.title", "budget, last {} days"),
+    ("budget.line", "{} +{} -{}"),
+    ("budget.overall", "overall {}"),
+    ("budget.taxes", "taxes"),
+    ("budget.running", "running"),
+    ("budget.construction", "construction"),
+    ("budget.road_upkeep", "road upkeep"),
+    ("demand.happiness", "happiness {}%"),
+
+    ("interaction.supplied", "supplied"),
+    ("interaction.blacked_out", "blacked out"),
+    ("interaction.driving", "driving, interact to get out"),
+    ("interaction.riding", "riding, interact to get out"),
+    ("interaction.picked_up", "picked up the {}"),
+    ("interaction.talk", "\"{}\""),
+
+    ("tool.pause", "pause"),
+    ("tool.build", "build"),
+    ("tool.zone", "zone"),
+    ("tool.pipe", "pipe"),
+    ("tool.road", "road"),
+    ("tool.budget", "budget"),
+    ("tool.chat", "chat"),
+    ("tool.unzone", "unzone"),
+
+    ("overlay.shown", "[{}]"),
+    ("overlay.zoning", "zoning"),
+    ("overlay.power", "power"),
+    ("overlay.water", "water"),
+    ("overlay.value", "value"),
+    ("overlay.traffic", "traffic"),
+
+    ("building.house", "house"),
+    ("building.shop", "shop"),
+    ("building.power_plant", "power plant"),
+    ("building.water_tower", "water tower"),
+    ("building.factory", "factory"),
+    ("building.fire_station", "fire station"),
+    ("zone.residential", "residential"),
+    ("zone.commercial", "commercial"),
+    ("zone.industrial", "industrial"),
+    ("item.apple", "apple"),
+    ("item.brick", "brick"),
+    ("item.coin", "coin"),
+];
+
+fn invalid(line: &str) -> io::Error {
+    let msg = format!("malformed string: {}", line);
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+/// Everything the client says, in one language.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Table {
+    strings: BTreeMap<String, String>,
+}
+
+impl Table {
+    pub fn english() -> Table {
+        let strings = ENGLISH
+            .iter()
+            .map(|&(key, text)| (key.to_string(), text.to_string()))
+            .collect();
+        Table { strings }
+    }
+
+    /// Reads a language one string per line, as
+    ///
+    ///     menu.quit = "beenden"
+    ///
+    /// saying in English whatever it leaves out.
+    pub fn read(input: &mut io::BufRead) -> io::Result<Table> {
+        let mut table = Table::english();
+        for line in input.lines() {
+            let line = line?;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let mut halves = trimmed.splitn(2, '=');
+            let key = halves.next().unwrap_or("").trim();
+            let value = halves.next().map_or("", str::trim);
+            let quoted = value.len() >= 2
+                && value.starts_with('"')
+                && value.ends_with('"');
+            if key.is_empty() || !quoted {
+                return Err(invalid(&line));
+            }
+            let text = value[1..value.len() - 1].to_string();
+            table.strings.insert(key.to_string(), text);
+        }
+        Ok(table)
+    }
+
+    pub fn load(path: &path::Path) -> io::Result<Table> {
+        let mut file = io::BufReader::new(fs::File::open(path)?);
+        Table::read(&mut file)
+    }
+
+    /// What `key` says, with each {} in it filled in by the next of
+    /// `args`, or the key itself if nothing says what it says.
+    pub fn format(self: &Self, key: &str, args: &[&fmt::Display]) -> String {
+        let pattern = match self.strings.get(key) {
+            Some(pattern) => pattern,
+            None => return key.to_string(),
+        };
+        let mut out = String::new();
+        let mut args = args.iter();
+        let mut pieces = pattern.split("{}");
+        if let Some(first) = pieces.next() {
+            out.push_str(first);
+        }
+        for piece in pieces {
+            if let Some(arg) = args.next() {
+                out.push_str(&arg.to_string());
+            }
+            out.push_str(piece);
+        }
+        out
+    }
+}
+
+/// Where the table for `language`, such as `de`, is loaded from.
+pub fn table_path(language: &str) -> path::PathBuf {
+    let name = format!("{}.txt", language);
+    path::Path::new("assets").join("locale").join(name)
+}
+
+// what LANG says, as in `de` from `de_DE.UTF-8`
+fn system_language() -> Option<String> {
+    let lang = env::var("LANG").ok()?;
+    let language = lang.split(|c| c == '_' || c == '.').next()?;
+    if language.is_empty() || language == "C" || language == "POSIX" {
+        return None;
+    }
+    Some(language.to_lowercase())
+}
+
+// set once at startup and read by whatever draws text, so it lives for
+// the whole program, in English until something says otherwise
+static START: sync::Once = sync::ONCE_INIT;
+static mut TABLE: *const sync::RwLock<Table> =
+    0 as *const sync::RwLock<Table>;
+
+fn table() -> &'static sync::RwLock<Table> {
+    START.call_once(|| {
+        let table = Box::new(sync::RwLock::new(Table::english()));
+        unsafe {
+            TABLE = Box::into_raw(table);
+        }
+    });
+    // only ever written once, inside `call_once`, and never freed
+    unsafe { &*TABLE }
+}
+
+/// Says everything in `language`, or in whatever language LANG says if
+/// that is None, keeping to English if there is no table for it.
+pub fn set_language(language: Option<&str>) {
+    let language = match language {
+        Some(language) => language.to_string(),
+        None => match system_language() {
+            Some(language) => language,
+            None => return,
+        },
+    };
+    let loaded = if language == "en" {
+        Ok(Table::english())
+    } else {
+        Table::load(&table_path(&language))
+    };
+    match loaded {
+        Ok(loaded) => {
+            let mut table = table()
+                .write()
+                .unwrap_or_else(|poison| poison.into_inner());
+            *table = loaded;
+        },
+        Err(e) => warn!("Keeping to English, no {}: {}", language, e),
+    }
+}
+
+/// What `key` says in the language in use.
+pub fn text(key: &str) -> String {
+    format(key, &[])
+}
+
+/// What `key` says in the language in use, with each {} in it filled in
+/// by the next of `args`.
+pub fn format(key: &str, args: &[&fmt::Display]) -> String {
+    let table = table().read().unwrap_or_else(|poison| poison.into_inner());
+    table.format(key, args)
+}
+
+/// What something called `name` in code is called in the language in use,
+/// as with `name("building", "power plant")`.
+pub fn name(kind: &str, name: &str) -> String {
+    text(&format!("{}.{}", kind, name.replace(' ', "_")))
+}
+
+#[cfg(test)]
+mod test_locale {
+    use super::*;
+
+    #[test]
+    fn tables_fill_in_what_they_leave_out_in_english() {
+        let text = b"# deutsch\nmenu.quit = \"beenden\"\n\n\
+                     menu.join = \"{} beitreten\"\n";
+        let table = Table::read(&mut &text[..]).unwrap();
+        assert_eq!(table.format("menu.quit", &[]), "beenden");
+        assert_eq!(table.format("menu.save", &[]), "save");
+        let join = table.format("menu.join", &[&"127.0.0.1:7878"]);
+        assert_eq!(join, "127.0.0.1:7878 beitreten");
+        assert_eq!(table.format("nonsense", &[]), "nonsense");
+        assert!(Table::read(&mut &b"menu.quit = beenden\n"[..]).is_err());
+    }
+
+    #[test]
+    fn every_pattern_is_filled_in_order() {
+        let table = Table::english();
+        let line = table.format("chat.line", &[&"09", &"05", &"bo", &"hi"]);
+        assert_eq!(line, "[09:05] bo: hi");
+        // missing arguments are left out rather than panicking
+        assert_eq!(table.format("ledger.power", &[&3]), "power 3/");
+    }
+}
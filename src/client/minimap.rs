@@ -0,0 +1,271 @@
+use city_internal::entities;
+use city_internal::space;
+use city_internal::sulphate;
+use city_internal::units;
+
+use client::camera;
+use client::render;
+use client::ui;
+
+const SIZE: [f64; 2] = [160.0, 160.0];
+
+// room left around everything shown, as a share of how far it spans
+const MARGIN: f64 = 0.1;
+
+const ROAD_COLOR: render::Color = [0.7, 0.7, 0.7, 1.0];
+const VIEWPORT_COLOR: render::Color = [1.0, 1.0, 1.0, 0.9];
+
+fn floatify(position: units::Position) -> [f64; 2] {
+    let offset = position - units::Position::default();
+    [offset.x.into(), offset.y.into()]
+}
+
+fn tile_color(tile: space::Tile) -> Option<render::Color> {
+    match tile {
+        space::Tile::Water => Some([0.2, 0.4, 0.8, 1.0]),
+        space::Tile::Wall => Some([0.4, 0.35, 0.3, 1.0]),
+        // buildings get a dot of their own
+        space::Tile::Building | space::Tile::Open => None,
+    }
+}
+
+fn dot_color(image: &entities::Image) -> Option<render::Color> {
+    match *image {
+        entities::Image::Player(ref image) => {
+            Some(render::color_of(image.appearance.color))
+        },
+        entities::Image::Citizen(_) => Some([0.9, 0.9, 0.9, 1.0]),
+        entities::Image::Building(_) => Some([0.5, 0.5, 0.6, 1.0]),
+        entities::Image::Vehicle(_) => Some([1.0, 0.8, 0.2, 1.0]),
+        // too small to make out
+        entities::Image::Item(_) => None,
+    }
+}
+
+/// How the world is fitted onto the minimap, with the same scale across
+/// as down.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Scale {
+    rect: ui::Rect,
+    // the world point at the top left of the minimap
+    origin: [f64; 2],
+    // minimap pixels per unit of world distance
+    zoom: f64,
+}
+
+impl Scale {
+    /// Fits everything at `points` onto `rect`, with a little room around
+    /// it, and centred on it.
+    pub fn fit(rect: ui::Rect, points: &[[f64; 2]]) -> Scale {
+        let mut least = [0.0f64, 0.0f64];
+        let mut most = [0.0f64, 0.0f64];
+        if let Some(first) = points.first() {
+            least = *first;
+            most = *first;
+        }
+        for point in points {
+            for i in 0..2 {
+                least[i] = least[i].min(point[i]);
+                most[i] = most[i].max(point[i]);
+            }
+        }
+        let span = (most[0] - least[0]).max(most[1] - least[1]).max(1.0);
+        let span = span * (1.0 + 2.0 * MARGIN);
+        let zoom = rect.size[0].min(rect.size[1]) / span;
+        let middle = [(least[0] + most[0]) / 2.0, (least[1] + most[1]) / 2.0];
+        let origin = [
+            middle[0] - rect.size[0] / zoom / 2.0,
+            middle[1] - rect.size[1] / zoom / 2.0,
+        ];
+        Scale { rect, origin, zoom }
+    }
+
+    /// Where on the screen the world point `world` is drawn.
+    pub fn to_map(self: &Self, world: [f64; 2]) -> [f64; 2] {
+        [
+            self.rect.position[0] + (world[0] - self.origin[0]) * self.zoom,
+            self.rect.position[1] + (world[1] - self.origin[1]) * self.zoom,
+        ]
+    }
+
+    /// The world position drawn at `pixel` on the screen.
+    pub fn to_world(self: &Self, pixel: [f64; 2]) -> units::Position {
+        let off = [
+            (pixel[0] - self.rect.position[0]) / self.zoom,
+            (pixel[1] - self.rect.position[1]) / self.zoom,
+        ];
+        let (x, y) = (self.origin[0] + off[0], self.origin[1] + off[1]);
+        units::Position::default() + units::Displacement {
+            x: x.into(),
+            y: y.into(),
+        }
+    }
+}
+
+// the world corners of what the camera shows on a screen of `screen`
+// pixels, top left first
+fn viewport(camera: &camera::View, screen: [f64; 2]) -> [[f64; 2]; 2] {
+    [
+        floatify(camera.to_world([0.0, 0.0])),
+        floatify(camera.to_world(screen)),
+    ]
+}
+
+// every world point the minimap has to fit in
+fn extent(
+    view: Option<&sulphate::WorldView>,
+    viewport: [[f64; 2]; 2],
+) -> Vec<[f64; 2]> {
+    let mut points = viewport.to_vec();
+    if let Some(view) = view {
+        let tile = space::TILE_SIZE;
+        for &((x, y), _) in &view.terrain {
+            points.push([x as f64 * tile, y as f64 * tile]);
+            points.push([(x + 1) as f64 * tile, (y + 1) as f64 * tile]);
+        }
+        for &(a, b) in &view.roads {
+            for &(x, y) in &[a, b] {
+                let (x, y) = (x as f64 + 0.5, y as f64 + 0.5);
+                points.push([x * tile, y * tile]);
+            }
+        }
+        for entity in &view.entities {
+            points.push(floatify(entity.position));
+        }
+    }
+    points
+}
+
+/// A small map of everything in `view` in the top right of the screen of
+/// `screen` pixels, under the toolbar, with the edges of what `camera`
+/// shows drawn on it. Gives the world position under wherever it was
+/// clicked, for the camera to jump to.
+pub fn minimap(
+    ui: &mut ui::Ui,
+    list: &mut render::DrawList,
+    view: Option<&sulphate::WorldView>,
+    camera: &camera::View,
+    screen: [f64; 2],
+) -> Option<units::Position> {
+    let anchor = ui::Anchor::TopRight;
+    let (rect, click) = ui.clickable_panel(list, anchor, [0.0, 40.0], SIZE);
+    let viewport = viewport(camera, screen);
+    let scale = Scale::fit(rect, &extent(view, viewport));
+    let layer = render::Layer::Overlay;
+
+    if let Some(view) = view {
+        let tile = space::TILE_SIZE;
+        // never too small to see
+        let side = (tile * scale.zoom).max(1.0);
+        for &((x, y), kind) in &view.terrain {
+            let color = match tile_color(kind) {
+                Some(color) => color,
+                None => continue,
+            };
+            let corner = scale.to_map([x as f64 * tile, y as f64 * tile]);
+            let shape = render::Shape::Rectangle { size: [side, side], color };
+            list.push(layer, corner, render::Item::Shape(shape));
+        }
+        let centre = |(x, y): space::TileId| {
+            scale.to_map([(x as f64 + 0.5) * tile, (y as f64 + 0.5) * tile])
+        };
+        for &(a, b) in &view.roads {
+            let (start, end) = (centre(a), centre(b));
+            let by = [end[0] - start[0], end[1] - start[1]];
+            let shape = render::Shape::Line {
+                by,
+                width: 1.0,
+                color: ROAD_COLOR,
+            };
+            list.push(layer, start, render::Item::Shape(shape));
+        }
+        for entity in &view.entities {
+            let color = match dot_color(&entity.image) {
+                Some(color) => color,
+                None => continue,
+            };
+            let at = scale.to_map(floatify(entity.position));
+            let shape = render::Shape::Circle { radius: 1.5, color };
+            list.push(layer, at, render::Item::Shape(shape));
+        }
+    }
+
+    // the viewport's outline, a side at a time
+    let from = scale.to_map(viewport[0]);
+    let to = scale.to_map(viewport[1]);
+    let sides = [
+        (from, [to[0] - from[0], 0.0]),
+        (from, [0.0, to[1] - from[1]]),
+        (to, [from[0] - to[0], 0.0]),
+        (to, [0.0, from[1] - to[1]]),
+    ];
+    for &(start, by) in &sides {
+        let shape = render::Shape::Line {
+            by,
+            width: 1.0,
+            color: VIEWPORT_COLOR,
+        };
+        list.push(layer, start, render::Item::Shape(shape));
+    }
+
+    click.map(|pixel| scale.to_world(pixel))
+}
+
+#[cfg(test)]
+mod test_minimap {
+    use piston_window as app;
+
+    use city_internal::units;
+
+    use super::*;
+
+    #[test]
+    fn the_map_fits_and_undoes_itself() {
+        let rect = ui::Rect { position: [100.0, 50.0], size: SIZE };
+        let points = [[0.0, 0.0], [1000.0, 500.0]];
+        let scale = Scale::fit(rect, &points);
+        // the wider way fills the map, less the margin
+        let left = scale.to_map([0.0, 0.0]);
+        let right = scale.to_map([1000.0, 0.0]);
+        let across = SIZE[0] / (1.0 + 2.0 * MARGIN);
+        assert!((right[0] - left[0] - across).abs() < 1e-9);
+        // and the middle of it all is the middle of the map
+        let middle = scale.to_map([500.0, 250.0]);
+        assert!((middle[0] - 180.0).abs() < 1e-9);
+        assert!((middle[1] - 130.0).abs() < 1e-9);
+
+        let world = scale.to_world([180.0, 130.0]);
+        let offset = world - units::Position::default();
+        assert!((offset.x - 500.into()).squared() < 1);
+        assert!((offset.y - 250.into()).squared() < 1);
+    }
+
+    #[test]
+    fn clicking_the_map_gives_where_to_look() {
+        let mut ui = ui::Ui::new();
+        let mut list = render::DrawList::new();
+        let mut camera = camera::Camera::new();
+        let screen = [800.0, 600.0];
+        camera.set_screen_size(800, 600);
+        let draw = |ui: &mut ui::Ui, list: &mut render::DrawList| {
+            ui.begin(screen);
+            let view = camera.view();
+            let clicked = minimap(ui, list, None, &view, screen);
+            ui.end();
+            clicked
+        };
+        assert_eq!(draw(&mut ui, &mut list), None);
+        // a panel and the four sides of the viewport
+        assert_eq!(list.commands().len(), 1 + 4);
+
+        // the middle of the map, which is the middle of the viewport
+        let middle = [800.0 - SIZE[0] / 2.0, 40.0 + SIZE[1] / 2.0];
+        ui.on_mouse_move(middle);
+        let button = app::Button::Mouse(app::MouseButton::Left);
+        let state = app::ButtonState::Press;
+        let args = app::ButtonArgs { state, button, scancode: None };
+        assert!(ui.on_input(args));
+        let jump = draw(&mut ui, &mut list).expect("the map wasn't clicked");
+        assert!((jump - camera.view().focus).squared() < 1);
+    }
+}
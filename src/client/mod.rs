@@ -1,17 +1,82 @@
+use std::io;
+use std::net;
+use std::path;
 use std::sync::mpsc;
+use std::thread;
 
+use city_internal::admin;
+use city_internal::blueprints;
+use city_internal::entities;
+use city_internal::entities::building;
+use city_internal::entities::citizen;
+use city_internal::entities::item;
 use city_internal::entities::player;
+use city_internal::mods;
+use city_internal::net as city_net;
 use city_internal::units;
 use city_internal::space;
+use city_internal::space::zones;
 use city_internal::sulphate;
+use city_internal::sulphate::chat as city_chat;
+use city_internal::sulphate::crash;
 use city_internal::sulphate::server;
+use city_internal::worldgen;
 
 use piston_window as app;
 
-mod draw;
+use client::bindings::Action;
+use client::settings_file::SettingsFile;
+
+pub use self::assets::Assets;
+pub use self::locale::set_language;
+pub use self::remote::RemoteClient;
+pub use self::screens::Options;
+pub use self::screens::Screens;
+pub use self::settings::Graphics;
+
+mod assets;
+mod audio;
+mod bindings;
+mod budget;
+mod camera;
+mod chat;
+mod chunks;
+mod debug;
+mod demand;
+mod locale;
+mod minimap;
+mod particles;
+mod playback;
+mod prediction;
+mod remote;
+mod render;
+mod screens;
+mod settings;
+mod settings_file;
 mod trackers;
+mod ui;
 mod user_input;
 
+/// Anything the window can drive, local or remote.
+pub trait Game {
+    fn on_update(self: &mut Self, upd: app::UpdateArgs);
+    fn on_input(self: &mut Self, bin: app::ButtonArgs);
+    fn on_controller_axis(self: &mut Self, args: app::ControllerAxisArgs);
+    fn on_mouse_move(self: &mut Self, mouse: [f64; 2]);
+    fn on_mouse_scroll(self: &mut Self, scroll: [f64; 2]);
+    /// Characters typed, as opposed to the keys that typed them.
+    fn on_text(self: &mut Self, text: &str);
+    fn on_draw(
+        self: &mut Self,
+        context: app::Context,
+        graphics: &mut app::G2d,
+        ren: app::RenderArgs,
+        assets: &assets::Assets,
+    );
+    /// Draws the UI `scale` times bigger than it would be.
+    fn set_ui_scale(self: &mut Self, scale: f64);
+}
+
 struct ClientData {
     recv_upd: mpsc::Receiver<player::Update>,
     recv_other: mpsc::Receiver<player::Update>,
@@ -19,36 +84,127 @@ struct ClientData {
 
 pub struct Client {
     vision: trackers::Perception,
+    casts: trackers::Casts,
+    money: trackers::MoneyChanges,
+    // what came of the player last interacting with something
+    interaction: Option<entities::Interaction>,
+    // everything the player is carrying, as the server last said
+    inventory: Vec<item::Kind>,
+    // the latest snapshot of the world, which is what gets drawn
+    view: Option<sulphate::WorldView>,
+    views: mpsc::Receiver<sulphate::WorldView>,
+    // the last path asked for, drawn from the player to show what the
+    // pathfinder would do
+    path: Option<Vec<units::Position>>,
+    send_path: mpsc::Sender<Option<Vec<units::Position>>>,
+    recv_path: mpsc::Receiver<Option<Vec<units::Position>>>,
+    // whether each building asked for had room
+    send_built: mpsc::Sender<Option<sulphate::EntityId>>,
+    recv_built: mpsc::Receiver<Option<sulphate::EntityId>>,
+    // and how many tiles each zoning painted
+    send_painted: mpsc::Sender<usize>,
+    recv_painted: mpsc::Receiver<usize>,
+    // and whether each run of pipe went in
+    send_laid: mpsc::Sender<bool>,
+    recv_laid: mpsc::Receiver<bool>,
+    // and whether each stretch of road went in
+    send_paved: mpsc::Sender<bool>,
+    recv_paved: mpsc::Receiver<bool>,
+    // everything said in the city, as the server hears it
+    said: mpsc::Receiver<city_chat::ChatLine>,
+    chat: chat::ChatBox,
+    debug: debug::DebugOverlay,
+    budget: budget::BudgetPanel,
+    demand: demand::DemandBars,
+    audio: audio::Audio,
+    particles: particles::Particles,
+    // what is being shown over the city, if anything
+    overlay: Option<render::Overlay>,
+    tape: playback::Tape,
     clock: server::Clock,
+    ui: ui::Ui,
     input: user_input::Input,
+    camera: camera::Camera,
     send_upd: mpsc::Sender<server::Interruption>,
     recv_upd: mpsc::Receiver<player::Update>,
+    server: Option<thread::JoinHandle<()>>,
+    status: mpsc::Receiver<crash::ServerStatus>,
+    crashed: bool,
+    paused: bool,
 }
 
 fn server_init(
     space: &mut space::CollisionSpace,
     time: &mut sulphate::EventQueue,
     matter: &mut sulphate::EntityHeap,
+    appearance: player::Appearance,
+    seed: Option<u64>,
 ) -> ClientData {
+    if let Some(seed) = seed {
+        let map = worldgen::generate(seed, GENERATED_SIZE);
+        if let Err(e) = map.build(space, time, matter) {
+            warn!("Failed to generate a world: {}", e);
+        }
+    }
+
     let (player_send_upd, recv_upd) = mpsc::channel();
     {
         let position = Default::default();
-        player::Player::new(space, time, matter, position, player_send_upd);
+        player::Player::new(
+            space, time, matter, position, appearance, player_send_upd,
+        );
     }
 
     let (other_send_upd, recv_other) = mpsc::channel();
     {
         let displacement = units::Displacement { x: 200.into(), y: 0.into() };
         let position = units::Position::default() + displacement;
-        player::Player::new(space, time, matter, position, other_send_upd);
+        let appearance = player::Appearance::named("visitor");
+        player::Player::new(
+            space, time, matter, position, appearance, other_send_upd,
+        );
     }
 
+    citizen::populate(space, time, matter, 12, 1);
+
     ClientData { recv_upd, recv_other }
 }
 
-pub fn start_game() -> Client {
-    let (send_upd, clock, client_data) = server::start_server(server_init);
-    Client::new(send_upd, clock, client_data)
+// how many tiles across a generated world is
+const GENERATED_SIZE: i64 = 128;
+
+/// Starts a new game, playing as a player that looks like `appearance`, in
+/// a world generated from `seed` if there is one.
+pub fn start_game(
+    appearance: player::Appearance,
+    seed: Option<u64>,
+) -> Client {
+    let (send_upd, clock, server, data) =
+        server::start_server(move |space, time, matter| {
+            server_init(space, time, matter, appearance, seed)
+        });
+    let ClientData { recv_upd, recv_other } = data;
+    start_other(&send_upd, &recv_other);
+    Client::new(send_upd, clock, server, recv_upd, vec![recv_other])
+}
+
+/// Picks a saved game back up, playing as the first player in it.
+pub fn resume_game(path: &path::Path) -> io::Result<Client> {
+    let (send_upd, clock, server, devices) =
+        server::create_server_from_snapshot(path)?;
+    let mut devices = devices.into_iter().map(|(_, device)| device);
+    let own = match devices.next() {
+        Some(own) => own,
+        None => {
+            let _ = send_upd.send(server::Interruption::KillServer);
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "nobody to play as in the save",
+            ));
+        },
+    };
+    let others = devices.collect();
+    Ok(Client::new(send_upd, clock, server, own, others))
 }
 
 fn start_other(
@@ -57,7 +213,9 @@ fn start_other(
 ) {
     let id = recv_id(&recv, "Other");
     let velocity = units::Velocity { x: (-50).into(), y: 0.into() };
-    send_velocity(send_upd, id, velocity, "Other");
+    let pace = player::Pace::Run;
+    let control = player::Control::Move { velocity, pace };
+    send_control(send_upd, id, control, "Other");
 }
 
 fn recv_id(
@@ -73,13 +231,12 @@ fn recv_id(
     }
 }
 
-fn send_velocity(
+fn send_control(
     send_upd: &mpsc::Sender<server::Interruption>,
     id: sulphate::EntityId,
-    velocity: units::Velocity,
+    control: player::Control,
     name: &str,
 ) {
-    let control = player::Control::Move { velocity };
     let interruption = server::Interruption::PlayerUpdate { id, control };
     let result = send_upd.send(interruption);
     if result.is_err() {
@@ -91,91 +248,651 @@ impl Client {
     fn new(
         send_upd: mpsc::Sender<server::Interruption>,
         clock: server::Clock,
-        data: ClientData,
+        server: thread::JoinHandle<()>,
+        recv_upd: mpsc::Receiver<player::Update>,
+        // the devices of the players that nobody here is playing as
+        others: Vec<mpsc::Receiver<player::Update>>,
     ) -> Client {
-        let ClientData { recv_upd, recv_other } = data;
-
         let id = recv_id(&recv_upd, "Player");
         let vision = trackers::Perception::new(id);
-        let input = user_input::Input::new();
+        let bindings = bindings::Bindings::load_or_default();
+        let mut input = user_input::Input::with_bindings(bindings);
+        let mut audio = audio::Audio::new(audio::Volumes::load_or_default());
+        audio.start_tracks();
 
-        start_other(&send_upd, &recv_other);
-        ::std::thread::spawn(move || {
-            for _ in recv_other {
-            }
-        });
+        for other in others {
+            thread::spawn(move || {
+                for _ in other {
+                }
+            });
+        }
 
-        Client { vision, clock, input, send_upd, recv_upd }
-    }
+        let (sender, status) = mpsc::channel();
+        let watch = server::Interruption::WatchStatus { sender };
+        send_upd.send(watch).expect("Server disconnected");
 
-    pub fn on_update(self: &mut Self, _upd: app::UpdateArgs) {
-        for upd in self.recv_upd.try_iter() {
-            use city_internal::entities::player::UpdateData::*;
-            match upd.what {
-                Created { .. } => unreachable!(),
-                Vision { before, after } => {
-                    self.vision.apply_update(before, after);
-                },
-            }
+        let (sender, views) = mpsc::channel();
+        let subscribe = server::Interruption::Subscribe { sender };
+        send_upd.send(subscribe).expect("Server disconnected");
+
+        let (sender, said) = mpsc::channel();
+        let listen = server::Interruption::ListenToChat { sender };
+        send_upd.send(listen).expect("Server disconnected");
+
+        // whatever a dedicated server would run, which is checked against
+        // the save if the game was picked back up from one
+        input.set_blueprints(&mods_blueprints());
+        let load = server::Interruption::LoadMods(mods::mods_dir());
+        send_upd.send(load).expect("Server disconnected");
+
+        let (send_path, recv_path) = mpsc::channel();
+        let (send_built, recv_built) = mpsc::channel();
+        let (send_painted, recv_painted) = mpsc::channel();
+        let (send_laid, recv_laid) = mpsc::channel();
+        let (send_paved, recv_paved) = mpsc::channel();
+
+        Client {
+            vision,
+            casts: trackers::Casts::new(),
+            money: trackers::MoneyChanges::new(),
+            interaction: None,
+            inventory: Vec::new(),
+            view: None,
+            views,
+            path: None,
+            send_path,
+            recv_path,
+            send_built,
+            recv_built,
+            send_painted,
+            recv_painted,
+            send_laid,
+            recv_laid,
+            send_paved,
+            recv_paved,
+            said,
+            chat: chat::ChatBox::new(),
+            debug: debug::DebugOverlay::new(),
+            budget: budget::BudgetPanel::new(),
+            demand: demand::DemandBars::new(),
+            audio,
+            particles: particles::Particles::new(),
+            overlay: None,
+            tape: playback::Tape::new(),
+            clock,
+            ui: ui::Ui::new(),
+            input,
+            camera: camera::Camera::new(),
+            send_upd,
+            recv_upd,
+            server: Some(server),
+            status,
+            crashed: false,
+            paused: false,
         }
     }
 
-    pub fn on_input(self: &mut Self, bin: app::ButtonArgs) {
-        let action = self.input.interpret(bin);
+    /// Lets players on other machines join this game.
+    pub fn host<A: net::ToSocketAddrs>(self: &Self, addr: A) -> io::Result<()> {
+        city_net::listen(addr, self.send_upd.clone()).map(|_| ())
+    }
 
+    /// Takes admin commands from the terminal this was started in.
+    pub fn serve_admin(self: &Self) {
+        admin::serve_stdin(self.send_upd.clone());
+    }
+
+    /// Where input is recorded to and played back from.
+    pub fn tape(self: &mut Self) -> &mut playback::Tape {
+        &mut self.tape
+    }
+
+    fn on_action(self: &mut Self, action: user_input::DeviceUpdate) {
+        if self.crashed {
+            // nothing left to send these to
+            return;
+        }
+        self.tape.record(&action);
+        self.camera.on_action(&action);
         use client::user_input::DeviceUpdate::*;
         match action {
             Nop => (),
-            ChangeMovement { dirs } => {
-                self.change_movement(dirs);
+            ChangeMovement { direction, pace } => {
+                walked_off(&mut self.interaction);
+                self.change_movement(direction, pace);
+            },
+            Cast { target } => {
+                self.cast(target);
+            },
+            TogglePause => {
+                let paused = !self.paused;
+                self.set_paused(paused);
+            },
+            ChangeTimeScale { factor } => {
+                let scale = self.clock.scale() * factor;
+                self.set_time_scale(scale);
+            },
+            Pan { .. } | Drag { .. } | ToggleFollow | Zoom { .. } => (),
+            ShowPath { target } => {
+                self.show_path(target);
+            },
+            PlaceBuilding { blueprint, position } => {
+                self.place_building(blueprint, position);
+            },
+            PaintZone { from, to, zone } => {
+                self.paint_zone(from, to, zone);
+            },
+            LayPipe { from, to } => {
+                self.lay_pipe(from, to);
+            },
+            LayRoad { from, to } => {
+                self.lay_road(from, to);
+            },
+            StartChat => {
+                self.chat.open();
+                let stop = self.input.let_go();
+                self.on_action(stop);
             },
+            ToggleDebug => self.debug.toggle(),
+            ToggleBudget => self.budget.toggle(),
+            CycleOverlay => self.overlay = next_overlay(self.overlay),
+            Interact => self.interact(),
+            DropItem { slot } => self.drop_item(slot),
+            Undo => self.undo(),
+            Redo => self.redo(),
         }
     }
 
-    fn change_movement(self: &Self, dirs: user_input::DirPad<bool>) {
-        let mut velocity: units::Velocity = Default::default();
-        let speed: units::Scalar = 150.into();
+    fn undo(self: &Self) {
+        let id = self.vision.player_id();
+        let interruption = server::Interruption::Undo { id };
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Server disconnected");
+        }
+    }
+
+    fn redo(self: &Self) {
+        let id = self.vision.player_id();
+        let interruption = server::Interruption::Redo { id };
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Server disconnected");
+        }
+    }
+
+    fn drop_item(self: &Self, slot: usize) {
+        let id = self.vision.player_id();
+        let interruption = server::Interruption::PlayerDrop { id, slot };
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Server disconnected");
+        }
+    }
+
+    fn interact(self: &Self) {
+        let id = self.vision.player_id();
+        let interruption = server::Interruption::PlayerInteract { id };
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Server disconnected");
+        }
+    }
+
+    fn say(self: &Self, text: String) {
+        let from = self.vision.player_id();
+        let interruption = server::Interruption::Chat { from, text };
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Server disconnected");
+        }
+    }
+
+    fn paint_zone(
+        self: &Self,
+        from: units::Position,
+        to: units::Position,
+        zone: Option<zones::Zone>,
+    ) {
+        let reply = self.send_painted.clone();
+        let by = Some(self.vision.player_id());
+        let interruption =
+            server::Interruption::PaintZone { from, to, zone, by, reply };
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Server disconnected");
+        }
+    }
+
+    fn lay_pipe(self: &Self, from: units::Position, to: units::Position) {
+        let reply = self.send_laid.clone();
+        let by = Some(self.vision.player_id());
+        let interruption =
+            server::Interruption::LayPipe { from, to, by, reply };
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Server disconnected");
+        }
+    }
+
+    fn lay_road(self: &Self, from: units::Position, to: units::Position) {
+        let by = Some(self.vision.player_id());
+        let interruption =
+            server::Interruption::road_drag(from, to, by, &self.send_paved);
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Server disconnected");
+        }
+    }
+
+    fn place_building(
+        self: &Self,
+        blueprint: blueprints::Name,
+        position: units::Position,
+    ) {
+        let reply = self.send_built.clone();
+        let interruption = server::Interruption::PlaceBuilding {
+            blueprint,
+            position,
+            by: Some(self.vision.player_id()),
+            reply,
+        };
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Server disconnected");
+        }
+    }
 
-        if dirs.up    { velocity.y -= speed; }
-        if dirs.down  { velocity.y += speed; }
-        if dirs.left  { velocity.x -= speed; }
-        if dirs.right { velocity.x += speed; }
+    fn show_path(self: &Self, to: units::Position) {
+        let from = match self.vision.own_position(self.clock.now()) {
+            Some(from) => from,
+            None => return,
+        };
+        let reply = self.send_path.clone();
+        let interruption = server::Interruption::FindPath { from, to, reply };
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Server disconnected");
+        }
+    }
 
-        if velocity.x != 0 && velocity.y != 0 {
-            velocity *= 5;
-            velocity /= 7;
+    fn set_time_scale(self: &Self, factor: units::Scalar) {
+        let interruption = server::Interruption::SetTimeScale { factor };
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Server disconnected");
         }
+    }
 
+    fn set_paused(self: &mut Self, paused: bool) {
+        let interruption = server::Interruption::PauseWorld { paused };
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Server disconnected");
+        }
+        self.paused = paused;
+    }
+
+    fn cast(self: &Self, target: units::Position) {
         let id = self.vision.player_id();
-        send_velocity(&self.send_upd, id, velocity, "Player");
+        let interruption = server::Interruption::PlayerCast { id, target };
+        if self.send_upd.send(interruption).is_err() {
+            panic!("Player entity disconnected");
+        }
     }
 
-    pub fn on_mouse_move(&mut self, _mouse: [f64; 2]) {
-        // self.input.on_mouse_move(mouse);
+    fn change_movement(
+        self: &Self,
+        direction: units::Vector,
+        pace: player::Pace,
+    ) {
+        let control = player::Control::walk(direction, pace);
+
+        let id = self.vision.player_id();
+        send_control(&self.send_upd, id, control, "Player");
     }
+}
 
-    pub fn on_draw(
-        &mut self,
+// the blueprints that loading the mods gives the server, which the
+// player goes through when building
+fn mods_blueprints() -> blueprints::Registry {
+    let dir = mods::mods_dir();
+    mods::registry(&dir, &mods::discover(&dir).packs)
+}
+
+// the game's own blueprints go by what the language in use calls their
+// kinds, and the rest by the names their mods gave them
+fn blueprint_label(
+    blueprint: blueprints::Name,
+    kind: building::Blueprint,
+) -> String {
+    if blueprint == blueprints::Name::from(kind) {
+        locale::name("building", kind.name())
+    } else {
+        blueprint.to_string()
+    }
+}
+
+// what came of interacting stays up until the player walks away, unless
+// it got the player riding, which lasts until it gets out
+fn walked_off(interaction: &mut Option<entities::Interaction>) {
+    let riding = match *interaction {
+        Some(entities::Interaction::Boarded { .. }) => true,
+        _ => false,
+    };
+    if !riding {
+        *interaction = None;
+    }
+}
+
+fn interacted(
+    interaction: &mut Option<entities::Interaction>,
+    outcome: entities::Interaction,
+) {
+    *interaction = match outcome {
+        entities::Interaction::Alighted => None,
+        outcome => Some(outcome),
+    };
+}
+
+// each overlay in turn, then back to showing none of them
+fn next_overlay(
+    current: Option<render::Overlay>,
+) -> Option<render::Overlay> {
+    let all = render::Overlay::all();
+    match current {
+        None => Some(all[0]),
+        Some(current) => all.iter()
+                            .skip_while(|&&overlay| overlay != current)
+                            .nth(1)
+                            .cloned(),
+    }
+}
+
+// the actions that get a button on the toolbar
+const TOOLS: [Action; 7] = [
+    Action::Pause,
+    Action::Build,
+    Action::Zone,
+    Action::Pipe,
+    Action::Road,
+    Action::Budget,
+    Action::Chat,
+];
+
+impl Game for Client {
+    fn on_update(self: &mut Self, upd: app::UpdateArgs) {
+        for status in self.status.try_iter() {
+            if let crash::ServerStatus::Crashed { dump } = status {
+                match dump {
+                    Some(path) => warn!(
+                        "Server crashed, dumped to {}", path.display(),
+                    ),
+                    None => warn!("Server crashed, nothing was dumped"),
+                }
+                self.crashed = true;
+            }
+        }
+
+        for upd in self.recv_upd.try_iter() {
+            use city_internal::entities::player::UpdateData::*;
+            match upd.what {
+                Created { .. } => unreachable!(),
+                Vision { before, after, own } => {
+                    self.vision.apply_update(before, after, own);
+                },
+                Cast { target, .. } => {
+                    self.casts.push(upd.when, target);
+                },
+                Interacted { outcome } => {
+                    interacted(&mut self.interaction, outcome);
+                },
+                Inventory { items } => {
+                    self.inventory = items;
+                },
+            }
+        }
+
+        if let Some(view) = self.views.try_iter().last() {
+            self.money.observe(view.balances.money);
+            self.audio.hear(&view, self.camera.view().focus);
+            self.particles.observe(&view);
+            self.view = Some(view);
+        }
+        self.money.update(upd.dt);
+        let camera = self.camera.view();
+        let now = self.clock.now();
+        self.particles.update(upd.dt, self.view.as_ref(), now, &camera);
+
+        for path in self.recv_path.try_iter() {
+            if path.is_none() {
+                info!("No way there");
+            }
+            self.path = path;
+        }
+
+        for built in self.recv_built.try_iter() {
+            if built.is_none() {
+                info!("No room to build there, or not the money");
+            }
+        }
+
+        for painted in self.recv_painted.try_iter() {
+            if painted == 0 {
+                info!("Too much to zone at once");
+            }
+        }
+
+        for laid in self.recv_laid.try_iter() {
+            if !laid {
+                info!("Pipe can't go there, or not the money");
+            }
+        }
+
+        // every stretch of a drag answers, but they go in or not together
+        let blocked = self.recv_paved
+                          .try_iter()
+                          .fold(false, |blocked, paved| blocked || !paved);
+        if blocked {
+            info!("Road can't go there, or not the money");
+        }
+
+        for line in self.said.try_iter() {
+            self.chat.receive(line);
+        }
+
+        self.debug.update(upd.dt, &self.send_upd);
+        self.budget.update(upd.dt, &self.send_upd);
+        self.demand.update(upd.dt, &self.send_upd);
+
+        for action in self.tape.advance(upd.dt) {
+            self.on_action(action);
+        }
+
+        self.camera.update(upd.dt);
+        let player = self.vision.own_position(self.clock.now());
+        self.camera.follow(player);
+    }
+
+    fn on_input(self: &mut Self, bin: app::ButtonArgs) {
+        if self.input.is_typing() {
+            match self.chat.on_button(bin) {
+                chat::Typed::Still => (),
+                chat::Typed::Sent(text) => {
+                    self.say(text);
+                    self.input.stop_typing();
+                },
+                chat::Typed::Closed => self.input.stop_typing(),
+            }
+            return;
+        }
+        if self.ui.on_input(bin) {
+            return;
+        }
+        let action = self.input.interpret(bin);
+        self.on_action(action);
+        if let Some(bindings) = self.input.take_rebound() {
+            if let Err(e) = bindings.save() {
+                warn!("Failed to save bindings: {}", e);
+            }
+        }
+    }
+
+    fn on_controller_axis(self: &mut Self, args: app::ControllerAxisArgs) {
+        let action = self.input.on_controller_axis(args);
+        self.on_action(action);
+    }
+
+    fn on_mouse_move(self: &mut Self, mouse: [f64; 2]) {
+        self.camera.on_mouse_move(mouse);
+        self.ui.on_mouse_move(mouse);
+        let action = self.input.on_mouse_move(mouse);
+        self.on_action(action);
+    }
+
+    fn on_mouse_scroll(self: &mut Self, scroll: [f64; 2]) {
+        let action = self.input.on_mouse_scroll(scroll);
+        self.on_action(action);
+    }
+
+    fn on_text(self: &mut Self, text: &str) {
+        self.chat.on_text(text);
+    }
+
+    fn on_draw(
+        self: &mut Self,
         context: app::Context,
         graphics: &mut app::G2d,
-        ren: app::RenderArgs
+        ren: app::RenderArgs,
+        assets: &assets::Assets,
     ) {
-        let now_rt = ::std::time::Instant::now();
-        let clock: &mut server::ClockMethods = &mut self.clock;
-        let now = clock.in_game(now_rt);
-        clock.finished_cycle(now_rt, now);
+        if self.crashed {
+            app::clear([0.4, 0.0, 0.0, 1.0], graphics);
+            return;
+        }
 
+        // only read the clock, the server's cycles are its own business
+        let now = self.clock.now();
 
         app::clear([0.0, 0.0, 0.0, 1.0], graphics);
 
-        let corner = context.transform;
-        let center_x = (ren.width / 2) as f64;
-        let center_y = (ren.height / 2) as f64;
-        let center = app::Transformed::trans(corner, center_x, center_y);
+        self.camera.set_screen_size(ren.width, ren.height);
+        let view = self.camera.view();
+        self.input.set_view(view);
+        let world = view.transform(context.transform);
+        // the UI's pixels, which the window's are a multiple of
+        let size = self.ui.screen_of([ren.width as f64, ren.height as f64]);
 
-        for image in &self.vision {
-            draw::draw(image, now, center, graphics);
+        let mut list = match self.view {
+            Some(ref view) => render::DrawList::of_view(view, now),
+            None => render::DrawList::new(),
+        };
+        list.motes(&self.particles.motes());
+        list.night(size, now);
+        list.clock([10.0, 60.0], now);
+        if let Some(ref view) = self.view {
+            if let Some(from) = self.vision.own_position(now) {
+                let own = self.vision.player_id();
+                list.nameplates(view, now, own, from);
+            }
+        }
+        for &(when, target) in self.casts.visible(now) {
+            list.cast(target, now - when);
         }
+        if let (Some(from), Some(path)) =
+            (self.vision.own_position(now), self.path.as_ref())
+        {
+            list.path(from, path);
+        }
+        if let Some((blueprint, kind, position)) = self.input.ghost() {
+            list.ghost(kind, position);
+            list.label([10.0, 40.0], &blueprint_label(blueprint, kind));
+        }
+        if let Some((zone, from, to)) = self.input.zone_ghost() {
+            list.zone_ghost(zone, from, to);
+            let name = match zone {
+                Some(zone) => locale::name("zone", zone.name()),
+                None => locale::text("tool.unzone"),
+            };
+            list.label([10.0, 40.0], &name);
+        }
+        if let Some((from, to)) = self.input.pipe_ghost() {
+            list.pipe_ghost(from, to);
+            list.label([10.0, 40.0], &locale::text("tool.pipe"));
+        }
+        if let Some((from, to)) = self.input.road_ghost() {
+            list.road_ghost(from, to);
+            list.label([10.0, 40.0], &locale::text("tool.road"));
+        }
+        let shown = (self.overlay, self.view.as_ref());
+        if let (Some(overlay), Some(view)) = shown {
+            list.overlay(view, overlay);
+            let name = locale::name("overlay", overlay.name());
+            list.label([160.0, 20.0], &name);
+        }
+        if self.paused {
+            list.label([10.0, 20.0], &locale::text("paused"));
+        }
+        if let Some(ref view) = self.view {
+            list.ledger([10.0, 80.0], &view.balances);
+        }
+        list.money_changes([160.0, 80.0], &self.money);
+        if let Some(ref outcome) = self.interaction {
+            list.interaction([10.0, 160.0], outcome);
+        }
+        self.budget.draw(&mut list, [10.0, 220.0]);
+        let corner = [size[0] - 130.0, size[1] - 90.0];
+        self.demand.draw(&mut list, corner);
+        list.chat([10.0, size[1] - 200.0], &self.chat);
+        self.debug.frame();
+        if self.debug.is_shown() {
+            let own = self.vision.player_id();
+            let pixel = [size[0] - 300.0, 60.0];
+            self.debug.draw(&mut list, pixel, self.view.as_ref(), own, now);
+        }
+
+        self.ui.begin(size);
+        let tool = ui::toolbar(&mut self.ui, &mut list, &TOOLS);
+        let dropped = ui::inventory(&mut self.ui, &mut list, &self.inventory);
+        let jump = minimap::minimap(
+            &mut self.ui, &mut list, self.view.as_ref(), &view, size,
+        );
+        // under the minimap
+        let picked = ui::overlay_bar(
+            &mut self.ui, &mut list, [0.0, 204.0], &render::Overlay::all(),
+            self.overlay,
+        );
+        self.ui.end();
+        if let Some(focus) = jump {
+            self.camera.jump_to(focus);
+        }
+        if let Some(picked) = picked {
+            // showing it again hides it
+            self.overlay = if self.overlay == Some(picked) {
+                None
+            } else {
+                Some(picked)
+            };
+        }
+        if let Some(tool) = tool {
+            let update = self.input.trigger(tool);
+            self.on_action(update);
+        }
+        if let Some(slot) = dropped {
+            self.on_action(user_input::DeviceUpdate::DropItem { slot });
+        }
+
+        let screen = self.ui.transform(context.transform);
+        let mut font = assets.glyphs();
+        let glyphs = font.as_mut();
+        let assets = Some(assets);
+        let mut backend = render::piston::Backend {
+            world, screen, glyphs, assets,
+        };
+        backend.execute(&list, &context, graphics);
+    }
+
+    fn set_ui_scale(self: &mut Self, scale: f64) {
+        self.ui.set_scale(scale);
     }
 }
 
+impl Drop for Client {
+    fn drop(self: &mut Self) {
+        // the server might have already died, in which case join will say so
+        let _ = self.send_upd.send(server::Interruption::KillServer);
+        if let Some(server) = self.server.take() {
+            if server.join().is_err() {
+                warn!("Server thread panicked before shutdown");
+            }
+        }
+    }
+}
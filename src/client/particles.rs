@@ -0,0 +1,322 @@
+use std::f64::consts;
+
+use city_internal::entities;
+use city_internal::entities::building;
+use city_internal::space::sounds;
+use city_internal::sulphate;
+use city_internal::units;
+use city_internal::world::calendar;
+
+use client::camera;
+
+// the most particles there can be at once, past which new ones are left
+// out rather than the pool growing
+const MOST_PARTICLES: usize = 2048;
+
+// seconds between puffs of smoke from each factory
+const SMOKE_EVERY: f64 = 0.25;
+
+// drops of rain started each second over the screen, while it rains
+const RAIN_RATE: f64 = 240.0;
+
+// specks of dust thrown up by a building coming down
+const DUST_BURST: usize = 16;
+
+/// What a particle is, which decides how it moves and how it looks.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    Smoke,
+    Dust,
+    Rain,
+}
+
+impl Kind {
+    // seconds from when a particle starts to when it has faded out
+    fn life(self: Self) -> f64 {
+        match self {
+            Kind::Smoke => 3.0,
+            Kind::Dust => 1.0,
+            Kind::Rain => 0.75,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Particle {
+    kind: Kind,
+    // in world coordinates
+    position: [f64; 2],
+    velocity: [f64; 2],
+    age: f64,
+}
+
+/// A particle as it should be drawn.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Mote {
+    pub kind: Kind,
+    pub position: [f64; 2],
+    /// How far through its life it is, from 0 when it starts to 1 when it
+    /// has faded out.
+    pub age: f32,
+}
+
+/// Whether it is raining at `now`, which is the same for everyone
+/// watching, since it only goes by the date: some afternoons rain, more
+/// often in autumn and winter.
+pub fn raining(now: units::Time) -> bool {
+    let date = calendar::date(now);
+    let often = match date.season {
+        calendar::Season::Autumn | calendar::Season::Winter => 2,
+        _ => 4,
+    };
+    let afternoon = date.hour >= 14 && date.hour < 20;
+    afternoon && scramble(date.day as u64) % often == 0
+}
+
+// a cheap hash, for turning a count into something that looks random
+fn scramble(n: u64) -> u64 {
+    let mut x = n.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Every particle in the air, kept in slots that are reused once their
+/// particle fades, so that nothing is allocated once the pool is as big
+/// as it gets.
+pub struct Particles {
+    pool: Vec<Option<Particle>>,
+    // slots in the pool with nothing in them
+    free: Vec<usize>,
+    // counts up with every number drawn, so that the same updates always
+    // give the same particles
+    draws: u64,
+    // seconds until the factories next puff, and rain carried over from
+    // one update to the next
+    smoke_in: f64,
+    rain_owed: f64,
+    // every sound in the last view, so that the next doesn't start dust
+    // twice for the same building
+    seen: Vec<sounds::Heard>,
+}
+
+impl Particles {
+    pub fn new() -> Particles {
+        Particles {
+            pool: Vec::new(),
+            free: Vec::new(),
+            draws: 0,
+            smoke_in: 0.0,
+            rain_owed: 0.0,
+            seen: Vec::new(),
+        }
+    }
+
+    // between 0 and 1
+    fn random(self: &mut Self) -> f64 {
+        self.draws += 1;
+        (scramble(self.draws) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn spawn(
+        self: &mut Self,
+        kind: Kind,
+        position: [f64; 2],
+        velocity: [f64; 2],
+    ) {
+        let particle = Particle { kind, position, velocity, age: 0.0 };
+        if let Some(slot) = self.free.pop() {
+            self.pool[slot] = Some(particle);
+        } else if self.pool.len() < MOST_PARTICLES {
+            self.pool.push(Some(particle));
+        }
+    }
+
+    /// Throws up dust wherever a building came down in `view` that no view
+    /// before it had.
+    pub fn observe(self: &mut Self, view: &sulphate::WorldView) {
+        for each in &view.sounds {
+            if each.sound != sounds::Sound::Demolition
+                || self.seen.contains(each)
+            {
+                continue;
+            }
+            let at = floatify(each.position);
+            for _ in 0..DUST_BURST {
+                let angle = self.random() * 2.0 * consts::PI;
+                let speed = 20.0 + 30.0 * self.random();
+                let velocity = [angle.cos() * speed, angle.sin() * speed];
+                self.spawn(Kind::Dust, at, velocity);
+            }
+        }
+        self.seen = view.sounds.clone();
+    }
+
+    /// Moves every particle on by `dt` seconds, then puffs smoke from each
+    /// factory in `view` and rains over whatever `camera` shows, if it is
+    /// raining at `now`.
+    pub fn update(
+        self: &mut Self,
+        dt: f64,
+        view: Option<&sulphate::WorldView>,
+        now: units::Time,
+        camera: &camera::View,
+    ) {
+        for slot in 0..self.pool.len() {
+            let faded = match self.pool[slot] {
+                Some(ref mut particle) => {
+                    particle.age += dt;
+                    particle.position[0] += particle.velocity[0] * dt;
+                    particle.position[1] += particle.velocity[1] * dt;
+                    particle.age >= particle.kind.life()
+                },
+                None => false,
+            };
+            if faded {
+                self.pool[slot] = None;
+                self.free.push(slot);
+            }
+        }
+
+        self.smoke_in -= dt;
+        if self.smoke_in <= 0.0 {
+            self.smoke_in += SMOKE_EVERY;
+            if let Some(view) = view {
+                for at in factories(view) {
+                    let drift = 10.0 * (self.random() - 0.5);
+                    self.spawn(Kind::Smoke, at, [drift, -15.0]);
+                }
+            }
+        }
+
+        if !raining(now) {
+            self.rain_owed = 0.0;
+            return;
+        }
+        let top_left = floatify(camera.to_world([0.0, 0.0]));
+        let corner = [
+            2.0 * camera.screen_center[0],
+            2.0 * camera.screen_center[1],
+        ];
+        let bottom_right = floatify(camera.to_world(corner));
+        let width = bottom_right[0] - top_left[0];
+        let height = bottom_right[1] - top_left[1];
+        let fall = height / Kind::Rain.life();
+        self.rain_owed += RAIN_RATE * dt;
+        while self.rain_owed >= 1.0 {
+            self.rain_owed -= 1.0;
+            // started anywhere over the screen, so it is already there
+            // when the rain starts
+            let x = top_left[0] + width * self.random();
+            let y = top_left[1] + height * (self.random() - 0.5);
+            self.spawn(Kind::Rain, [x, y], [-0.2 * fall, fall]);
+        }
+    }
+
+    /// How many particles there are.
+    pub fn count(self: &Self) -> usize {
+        self.pool.len() - self.free.len()
+    }
+
+    /// Every particle as it should be drawn now.
+    pub fn motes(self: &Self) -> Vec<Mote> {
+        self.pool
+            .iter()
+            .filter_map(|slot| slot.as_ref())
+            .map(|particle| Mote {
+                kind: particle.kind,
+                position: particle.position,
+                age: (particle.age / particle.kind.life()) as f32,
+            })
+            .collect()
+    }
+}
+
+fn floatify(position: units::Position) -> [f64; 2] {
+    let offset = position - units::Position::default();
+    [offset.x.into(), offset.y.into()]
+}
+
+// each factory in `view`, by where its chimney is
+fn factories(view: &sulphate::WorldView) -> Vec<[f64; 2]> {
+    view.entities.iter().filter_map(|entity| {
+        let blueprint = match entity.image {
+            entities::Image::Building(ref image) => image.blueprint,
+            _ => return None,
+        };
+        if blueprint != building::Blueprint::Factory {
+            return None;
+        }
+        let at = floatify(entity.position);
+        Some([at[0] + 8.0, at[1] - 12.0])
+    }).collect()
+}
+
+#[cfg(test)]
+mod test_particles {
+    use city_internal::space::sounds;
+    use city_internal::units;
+
+    use super::*;
+
+    fn view(sounds: Vec<sounds::Heard>) -> sulphate::WorldView {
+        sulphate::WorldView {
+            time: Default::default(),
+            entities: Vec::new(),
+            balances: Default::default(),
+            terrain: Vec::new(),
+            roads: Vec::new(),
+            zones: Vec::new(),
+            pipes: Vec::new(),
+            traffic: Vec::new(),
+            sounds,
+        }
+    }
+
+    // the first morning, which is never rainy
+    fn morning() -> units::Time {
+        units::Time::default() + calendar::hours(8)
+    }
+
+    #[test]
+    fn dust_settles_and_its_slots_are_reused() {
+        let mut particles = Particles::new();
+        let camera = camera::Camera::new().view();
+        let fall = sounds::Heard {
+            time: Default::default(),
+            sound: sounds::Sound::Demolition,
+            position: Default::default(),
+        };
+        let view = view(vec![fall]);
+        particles.observe(&view);
+        assert_eq!(particles.count(), DUST_BURST);
+        // still in the next view, but already thrown up
+        particles.observe(&view);
+        assert_eq!(particles.count(), DUST_BURST);
+
+        particles.update(2.0, None, morning(), &camera);
+        assert_eq!(particles.count(), 0);
+        let pool = particles.pool.len();
+        particles.seen.clear();
+        particles.observe(&view);
+        assert_eq!(particles.pool.len(), pool);
+        assert_eq!(particles.count(), DUST_BURST);
+    }
+
+    #[test]
+    fn the_same_updates_give_the_same_particles() {
+        let run = || {
+            let mut particles = Particles::new();
+            let camera = camera::Camera::new().view();
+            let fall = sounds::Heard {
+                time: Default::default(),
+                sound: sounds::Sound::Demolition,
+                position: Default::default(),
+            };
+            particles.observe(&view(vec![fall]));
+            particles.update(0.5, None, morning(), &camera);
+            particles.motes()
+        };
+        assert_eq!(run(), run());
+    }
+}
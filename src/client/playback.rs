@@ -0,0 +1,353 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::path;
+
+use city_internal::blueprints;
+use city_internal::entities::player;
+use city_internal::space::zones;
+use city_internal::units;
+
+use client::user_input::Dir;
+use client::user_input::DeviceUpdate;
+
+// each line is the seconds since recording started, as counted by the
+// updates the client was given, then what the player did:
+//
+//     0.5 move 4096 0 0
+//     1.25 cast 81920 -40960
+//     2 pan up true
+//
+// fixed point values are written as their bits, the same as in replays,
+// so that nothing is rounded. what is typed into the chat box isn't a
+// device update, so conversations don't come back
+
+fn dir_name(dir: Dir) -> &'static str {
+    match dir {
+        Dir::Up => "up",
+        Dir::Down => "down",
+        Dir::Left => "left",
+        Dir::Right => "right",
+    }
+}
+
+fn dir_from_name(name: &str) -> Option<Dir> {
+    match name {
+        "up" => Some(Dir::Up),
+        "down" => Some(Dir::Down),
+        "left" => Some(Dir::Left),
+        "right" => Some(Dir::Right),
+        _ => None,
+    }
+}
+
+/// Writes `action` down as having happened `seconds` in, giving whether it
+/// was worth writing down at all.
+pub fn write_update(
+    seconds: f64,
+    action: &DeviceUpdate,
+    out: &mut io::Write,
+) -> io::Result<bool> {
+    use client::user_input::DeviceUpdate::*;
+    let what = match *action {
+        // the chat box would take the keyboard for good, since nothing
+        // that closes it again is recorded
+        Nop | StartChat => return Ok(false),
+        ChangeMovement { direction, pace } => format!(
+            "move {} {} {}",
+            direction.x.into_bits(), direction.y.into_bits(), pace.tag(),
+        ),
+        Cast { target } => format!(
+            "cast {} {}", target.x.into_bits(), target.y.into_bits(),
+        ),
+        TogglePause => "pause".to_string(),
+        ChangeTimeScale { factor } =>
+            format!("scale {}", factor.into_bits()),
+        Pan { dir, held } => format!("pan {} {}", dir_name(dir), held),
+        Drag { held } => format!("drag {}", held),
+        ToggleFollow => "follow".to_string(),
+        Zoom { steps } => format!("zoom {}", steps),
+        ShowPath { target } => format!(
+            "path {} {}", target.x.into_bits(), target.y.into_bits(),
+        ),
+        PlaceBuilding { blueprint, position } => format!(
+            "build {} {} {}", blueprint,
+            position.x.into_bits(), position.y.into_bits(),
+        ),
+        PaintZone { from, to, zone } => format!(
+            "zone {} {} {} {} {}",
+            from.x.into_bits(), from.y.into_bits(),
+            to.x.into_bits(), to.y.into_bits(),
+            zone.map_or(zones::NO_ZONE, zones::Zone::tag),
+        ),
+        LayPipe { from, to } => format!(
+            "pipe {} {} {} {}",
+            from.x.into_bits(), from.y.into_bits(),
+            to.x.into_bits(), to.y.into_bits(),
+        ),
+        LayRoad { from, to } => format!(
+            "road {} {} {} {}",
+            from.x.into_bits(), from.y.into_bits(),
+            to.x.into_bits(), to.y.into_bits(),
+        ),
+        ToggleDebug => "debug".to_string(),
+        ToggleBudget => "budget".to_string(),
+        CycleOverlay => "overlay".to_string(),
+        Interact => "interact".to_string(),
+        DropItem { slot } => format!("drop {}", slot),
+        Undo => "undo".to_string(),
+        Redo => "redo".to_string(),
+    };
+    writeln!(out, "{} {}", seconds, what)?;
+    Ok(true)
+}
+
+fn bad_line(line: &str) -> io::Error {
+    let msg = format!("malformed input entry: {}", line);
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn parse_update(line: &str) -> Option<(f64, DeviceUpdate)> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let seconds = words.get(0)?.parse().ok()?;
+    let int = |n: usize| -> Option<i64> { words.get(n)?.parse().ok() };
+    let position = |n: usize| -> Option<units::Position> {
+        let x = units::Coord::from_bits(int(n)?);
+        let y = units::Coord::from_bits(int(n + 1)?);
+        Some(units::Position { x, y })
+    };
+
+    let action = match *words.get(1)? {
+        "move" => {
+            let x = units::Scalar::from_bits(int(2)?);
+            let y = units::Scalar::from_bits(int(3)?);
+            let direction = units::Vector { x, y };
+            let pace = player::Pace::from_tag(words.get(4)?.parse().ok()?)?;
+            DeviceUpdate::ChangeMovement { direction, pace }
+        },
+        "cast" => DeviceUpdate::Cast { target: position(2)? },
+        "pause" => DeviceUpdate::TogglePause,
+        "scale" => {
+            let factor = units::Scalar::from_bits(int(2)?);
+            DeviceUpdate::ChangeTimeScale { factor }
+        },
+        "pan" => {
+            let dir = dir_from_name(words.get(2)?)?;
+            let held = words.get(3)?.parse().ok()?;
+            DeviceUpdate::Pan { dir, held }
+        },
+        "drag" => DeviceUpdate::Drag { held: words.get(2)?.parse().ok()? },
+        "follow" => DeviceUpdate::ToggleFollow,
+        "zoom" => DeviceUpdate::Zoom { steps: words.get(2)?.parse().ok()? },
+        "path" => DeviceUpdate::ShowPath { target: position(2)? },
+        "build" => {
+            // recordings from before blueprints had names give the tag
+            let blueprint = blueprints::read_name(words.get(2)?)?;
+            DeviceUpdate::PlaceBuilding { blueprint, position: position(3)? }
+        },
+        "zone" => {
+            let zone = match words.get(6)?.parse().ok()? {
+                zones::NO_ZONE => None,
+                tag => Some(zones::Zone::from_tag(tag)?),
+            };
+            let from = position(2)?;
+            let to = position(4)?;
+            DeviceUpdate::PaintZone { from, to, zone }
+        },
+        "pipe" => {
+            let from = position(2)?;
+            let to = position(4)?;
+            DeviceUpdate::LayPipe { from, to }
+        },
+        "road" => {
+            let from = position(2)?;
+            let to = position(4)?;
+            DeviceUpdate::LayRoad { from, to }
+        },
+        "debug" => DeviceUpdate::ToggleDebug,
+        "budget" => DeviceUpdate::ToggleBudget,
+        "overlay" => DeviceUpdate::CycleOverlay,
+        "interact" => DeviceUpdate::Interact,
+        "undo" => DeviceUpdate::Undo,
+        "redo" => DeviceUpdate::Redo,
+        "drop" => {
+            let slot = words.get(2)?.parse().ok()?;
+            DeviceUpdate::DropItem { slot }
+        },
+        _ => return None,
+    };
+    Some((seconds, action))
+}
+
+/// Reads back everything that `write_update` wrote, in order.
+pub fn read_updates(
+    input: &mut io::BufRead,
+) -> io::Result<Vec<(f64, DeviceUpdate)>> {
+    let mut updates = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let update = parse_update(&line).ok_or_else(|| bad_line(&line))?;
+        updates.push(update);
+    }
+    Ok(updates)
+}
+
+/// Where a client's input is being written down as it happens, and where
+/// input written down before is being played back from.
+pub struct Tape {
+    // seconds since the tape started, counted out by updates rather than
+    // read off a clock, so that playback lines up with the frames
+    elapsed: f64,
+    recording: Option<io::LineWriter<fs::File>>,
+    playing: Option<VecDeque<(f64, DeviceUpdate)>>,
+}
+
+impl Tape {
+    /// A tape that neither records nor plays anything.
+    pub fn new() -> Tape {
+        Tape { elapsed: 0.0, recording: None, playing: None }
+    }
+
+    /// Starts writing input down to a new file at `path`.
+    pub fn record_to(self: &mut Self, path: &path::Path) -> io::Result<()> {
+        let file = fs::File::create(path)?;
+        self.recording = Some(io::LineWriter::new(file));
+        Ok(())
+    }
+
+    /// Starts playing back the input written down at `path`, timed from
+    /// now.
+    pub fn play_from(self: &mut Self, path: &path::Path) -> io::Result<()> {
+        let file = fs::File::open(path)?;
+        let updates = read_updates(&mut io::BufReader::new(file))?;
+        self.play(updates);
+        Ok(())
+    }
+
+    pub fn play(self: &mut Self, updates: Vec<(f64, DeviceUpdate)>) {
+        let start = self.elapsed;
+        let updates = updates
+            .into_iter()
+            .map(|(seconds, action)| (start + seconds, action))
+            .collect();
+        self.playing = Some(updates);
+    }
+
+    /// Moves the tape on by `dt` seconds, giving whatever was played back
+    /// on the way.
+    pub fn advance(self: &mut Self, dt: f64) -> Vec<DeviceUpdate> {
+        self.elapsed += dt;
+        let now = self.elapsed;
+        let mut due = Vec::new();
+        if let Some(ref mut playing) = self.playing {
+            while playing.front().map_or(false, |&(at, _)| at <= now) {
+                let (_, action) = playing.pop_front().expect("tape ran out");
+                due.push(action);
+            }
+        }
+        due
+    }
+
+    /// Writes `action` down if recording, as having just happened.
+    pub fn record(self: &mut Self, action: &DeviceUpdate) {
+        let result = match self.recording {
+            Some(ref mut out) => write_update(self.elapsed, action, out),
+            None => return,
+        };
+        if let Err(e) = result {
+            warn!("Failed to record input, stopping: {}", e);
+            self.recording = None;
+        }
+    }
+
+    /// Whether everything that was being played back has been.
+    pub fn is_played_out(self: &Self) -> bool {
+        self.playing.as_ref().map_or(false, VecDeque::is_empty)
+    }
+}
+
+#[cfg(test)]
+mod test_playback {
+    use city_internal::entities::building;
+    use city_internal::entities::player;
+    use city_internal::space::zones;
+    use city_internal::units;
+
+    use super::*;
+
+    #[test]
+    fn updates_read_back_as_they_were_written() {
+        let target = units::Position {
+            x: units::Coord::from_bits(81920),
+            y: units::Coord::from_bits(-40960),
+        };
+        let updates = vec![
+            (0.0, DeviceUpdate::ChangeMovement {
+                direction: units::Vector { x: 1.into(), y: (-1).into() },
+                pace: player::Pace::Sprint,
+            }),
+            (0.25, DeviceUpdate::Cast { target }),
+            (0.5, DeviceUpdate::Pan { dir: Dir::Left, held: true }),
+            (1.0 / 3.0, DeviceUpdate::Zoom { steps: -2.0 }),
+            (2.0, DeviceUpdate::PlaceBuilding {
+                blueprint: building::Blueprint::WaterTower.into(),
+                position: target,
+            }),
+            (2.5, DeviceUpdate::PaintZone {
+                from: units::Position::default(),
+                to: target,
+                zone: Some(zones::Zone::all()[0]),
+            }),
+            (3.0, DeviceUpdate::PaintZone {
+                from: target,
+                to: target,
+                zone: None,
+            }),
+            (3.5, DeviceUpdate::LayPipe { from: target, to: target }),
+            (3.75, DeviceUpdate::LayRoad { from: target, to: target }),
+            (4.0, DeviceUpdate::ToggleDebug),
+            (4.25, DeviceUpdate::ToggleBudget),
+            (4.375, DeviceUpdate::CycleOverlay),
+            (4.5, DeviceUpdate::Interact),
+            (5.0, DeviceUpdate::DropItem { slot: 3 }),
+            (5.5, DeviceUpdate::Undo),
+            (6.0, DeviceUpdate::Redo),
+        ];
+        let mut out = Vec::new();
+        for &(seconds, ref action) in &updates {
+            assert!(write_update(seconds, action, &mut out).unwrap());
+        }
+        let read = read_updates(&mut &out[..]).unwrap();
+        assert_eq!(read, updates);
+    }
+
+    #[test]
+    fn nothing_is_written_for_nothing() {
+        let mut out = Vec::new();
+        let nop = DeviceUpdate::Nop;
+        assert!(!write_update(1.0, &nop, &mut out).unwrap());
+        assert!(out.is_empty());
+        assert!(read_updates(&mut &b"1 teleport 0 0\n"[..]).is_err());
+    }
+
+    #[test]
+    fn updates_come_back_once_their_time_has_passed() {
+        let mut tape = Tape::new();
+        assert!(!tape.is_played_out());
+        tape.advance(5.0);
+        // timed from when playing started, not when the tape did
+        tape.play(vec![
+            (0.5, DeviceUpdate::TogglePause),
+            (1.0, DeviceUpdate::ToggleFollow),
+        ]);
+        assert_eq!(tape.advance(0.25), vec![]);
+        assert_eq!(tape.advance(0.25), vec![DeviceUpdate::TogglePause]);
+        assert!(!tape.is_played_out());
+        assert_eq!(tape.advance(2.0), vec![DeviceUpdate::ToggleFollow]);
+        assert!(tape.is_played_out());
+    }
+}
@@ -0,0 +1,231 @@
+use std::cmp;
+use std::collections;
+
+use city_internal::config;
+use city_internal::entities::player;
+use city_internal::net;
+use city_internal::sulphate;
+use city_internal::sulphate::checksum;
+use city_internal::sulphate::persist;
+use city_internal::units;
+
+// enough to interpolate over a short hiccup in the connection
+const MAX_SNAPSHOTS: usize = 8;
+
+// the local player heading for the velocity it last asked for, since the
+// server may not have heard about it yet
+struct Predicted {
+    since: units::Time,
+    velocity: units::Velocity,
+}
+
+pub struct Prediction {
+    player: sulphate::EntityId,
+    // oldest first
+    snapshots: collections::VecDeque<net::RemoteSnapshot>,
+    predicted: Option<Predicted>,
+    // what the server has said is in range, which is all that gets drawn
+    proxies: collections::HashMap<sulphate::EntityId, persist::EntityKind>,
+}
+
+pub struct Seen {
+    pub id: sulphate::EntityId,
+    pub kind: persist::EntityKind,
+    pub position: units::Position,
+    pub velocity: units::Velocity,
+}
+
+fn find(
+    snapshot: &net::RemoteSnapshot,
+    id: sulphate::EntityId,
+) -> Option<&net::RemoteBody> {
+    snapshot.bodies.iter().find(|body| body.id == id)
+}
+
+fn extrapolate(
+    body: &net::RemoteBody,
+    from: units::Time,
+    to: units::Time,
+) -> units::Position {
+    body.position + body.velocity * (to - from)
+}
+
+impl Prediction {
+    pub fn new(player: sulphate::EntityId) -> Prediction {
+        let snapshots = collections::VecDeque::new();
+        let predicted = None;
+        let proxies = collections::HashMap::new();
+        Prediction { player, snapshots, predicted, proxies }
+    }
+
+    pub fn latest_time(self: &Self) -> Option<units::Time> {
+        self.snapshots.back().map(|snapshot| snapshot.time)
+    }
+
+    pub fn push(self: &mut Self, snapshot: net::RemoteSnapshot) {
+        if self.latest_time().map_or(false, |t| snapshot.time <= t) {
+            return;  // arrived out of order, and we already know better
+        }
+
+        // the server has seen the prediction once it has caught up to it
+        let acknowledged = self.predicted
+                               .as_ref()
+                               .map_or(false, |p| p.since <= snapshot.time);
+        if acknowledged {
+            self.predicted = None;
+        }
+
+        self.snapshots.push_back(snapshot);
+        while self.snapshots.len() > MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Whether the world as we have it at `time`, which is what `seen`
+    /// draws of it, hashes to what the server says it does. Only the
+    /// latest snapshot can be checked, since the local player is worked
+    /// out from that, so anything older is assumed to be fine.
+    pub fn verify(self: &Self, time: units::Time, checksum: u64) -> bool {
+        if self.latest_time() != Some(time) {
+            return true;
+        }
+        // only what was said to come into range is seen, so a lost `Enter`
+        // shows up here too
+        let entities: Vec<_> = self.seen(time, time)
+            .into_iter()
+            .map(|seen| sulphate::EntitySummary {
+                id: seen.id,
+                kind: Some(seen.kind),
+                position: seen.position,
+                velocity: seen.velocity,
+            })
+            .collect();
+        checksum::of_entities(&entities) == checksum
+    }
+
+    /// Forgets everything the server has sent, to start again from its next
+    /// snapshot.
+    pub fn resync(self: &mut Self) {
+        self.snapshots.clear();
+        self.proxies.clear();
+    }
+
+    pub fn enter(
+        self: &mut Self,
+        id: sulphate::EntityId,
+        kind: persist::EntityKind,
+    ) {
+        self.proxies.insert(id, kind);
+    }
+
+    pub fn leave(self: &mut Self, id: sulphate::EntityId) {
+        self.proxies.remove(&id);
+    }
+
+    pub fn control_sent(
+        self: &mut Self,
+        control: &player::Control,
+        now: units::Time,
+    ) {
+        match *control {
+            player::Control::Move { velocity, pace } => {
+                let since = now;
+                // stamina isn't known here, so sprinting is predicted to
+                // last, and put right by the server when it doesn't
+                let velocity = pace.scale(velocity);
+                self.predicted = Some(Predicted { since, velocity });
+            },
+        }
+    }
+
+    /// Where everything ought to be drawn at `time`.
+    ///
+    /// Other entities are interpolated between the snapshots either side of
+    /// `time`, so it should be a little in the past. The local player is
+    /// extrapolated from the latest snapshot instead.
+    pub fn seen(
+        self: &Self,
+        time: units::Time,
+        player_time: units::Time,
+    ) -> Vec<Seen> {
+        let latest = match self.snapshots.back() {
+            Some(latest) => latest,
+            None => return Vec::new(),
+        };
+
+        let before = self.snapshots
+                         .iter()
+                         .rev()
+                         .find(|snapshot| snapshot.time <= time)
+                         .unwrap_or(&self.snapshots[0]);
+        // earlier than every snapshot, such as just after connecting or
+        // resyncing, there is nothing to go between, so the first is drawn
+        // as it is
+        let after = if time < before.time {
+            None
+        } else {
+            self.snapshots.iter().find(|snapshot| snapshot.time > time)
+        };
+        let drawn_at = cmp::max(time, before.time);
+
+        let mut seen = Vec::new();
+        for body in &before.bodies {
+            if body.id == self.player || !self.proxies.contains_key(&body.id) {
+                continue;
+            }
+            let position = match after.and_then(|a| find(a, body.id)) {
+                Some(next) => {
+                    // later than `time`, which is no earlier than `before`,
+                    // so the two are never at the same time
+                    let after = after.unwrap();
+                    let frac =
+                        (time - before.time) / (after.time - before.time);
+                    body.position + (next.position - body.position) * frac
+                },
+                None => extrapolate(body, before.time, drawn_at),
+            };
+            let id = body.id;
+            let kind = body.kind;
+            let velocity = body.velocity;
+            seen.push(Seen { id, kind, position, velocity });
+        }
+
+        let player = find(latest, self.player).and_then(|body| {
+            if self.proxies.contains_key(&body.id) {
+                Some(body)
+            } else {
+                None
+            }
+        });
+        if let Some(body) = player {
+            let (position, velocity) = match self.predicted {
+                Some(ref predicted)
+                    if latest.time < predicted.since
+                        && predicted.since <= player_time =>
+                {
+                    let turn = extrapolate(body, latest.time, predicted.since);
+                    // speeding up as the server would, at the usual rates,
+                    // since how the server is set up isn't sent
+                    let motion = player::Motion::walking(
+                        turn,
+                        body.velocity,
+                        predicted.since,
+                        predicted.velocity,
+                        &config::Config::default(),
+                    );
+                    let position = motion.position(player_time);
+                    (position, motion.velocity(player_time))
+                },
+                _ => {
+                    let position = extrapolate(body, latest.time, player_time);
+                    (position, body.velocity)
+                },
+            };
+            let id = body.id;
+            let kind = body.kind;
+            seen.push(Seen { id, kind, position, velocity });
+        }
+
+        seen
+    }
+}
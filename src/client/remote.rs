@@ -0,0 +1,363 @@
+use std::time;
+
+use city_internal::economy;
+use city_internal::entities;
+use city_internal::entities::item;
+use city_internal::entities::player;
+use city_internal::net as city_net;
+use city_internal::units;
+
+use piston_window as app;
+
+use client::assets;
+use client::bindings;
+use client::bindings::Action;
+use client::camera;
+use client::chat;
+use client::chunks;
+use client::playback;
+use client::prediction;
+use client::render;
+use client::settings_file::SettingsFile;
+use client::trackers;
+use client::ui;
+use client::user_input;
+
+// how far behind the latest snapshot to draw other entities,
+// so that there is usually a snapshot either side to interpolate between
+fn interpolation_delay() -> units::Duration {
+    units::moments(2)
+}
+
+fn elapsed_in_game(since: time::Instant) -> units::Duration {
+    let elapsed = since.elapsed();
+    let seconds: units::Scalar = (elapsed.as_secs() as i32).into();
+    let nanos: units::Scalar = (elapsed.subsec_nanos() as i32).into();
+    seconds + nanos / 1_000_000_000
+}
+
+/// Plays in a city hosted on another machine.
+pub struct RemoteClient {
+    connection: city_net::Connection,
+    prediction: prediction::Prediction,
+    input: user_input::Input,
+    ui: ui::Ui,
+    camera: camera::Camera,
+    // the server's time as of the latest snapshot, and when it arrived
+    synced: Option<(units::Time, time::Instant)>,
+    // the books as the server last told them
+    ledger: Option<economy::Balances>,
+    money: trackers::MoneyChanges,
+    interaction: Option<entities::Interaction>,
+    inventory: Vec<item::Kind>,
+    chat: chat::ChatBox,
+    tape: playback::Tape,
+    // the ground, as far as the client has been sent it
+    chunks: chunks::Chunks,
+}
+
+impl RemoteClient {
+    /// Plays over a connection that has already been welcomed.
+    pub fn new(connection: city_net::Connection) -> RemoteClient {
+        let prediction = prediction::Prediction::new(connection.id());
+        let bindings = bindings::Bindings::load_or_default();
+        let mut input = user_input::Input::with_bindings(bindings);
+        // the host builds from its own blueprints, which are most likely
+        // the ones from the same mods
+        input.set_blueprints(&::client::mods_blueprints());
+        let camera = camera::Camera::new();
+        let synced = None;
+        let ledger = None;
+        let interaction = None;
+        let chat = chat::ChatBox::new();
+        RemoteClient {
+            connection,
+            prediction,
+            input,
+            ui: ui::Ui::new(),
+            camera,
+            synced,
+            ledger,
+            money: trackers::MoneyChanges::new(),
+            interaction,
+            inventory: Vec::new(),
+            chat,
+            tape: playback::Tape::new(),
+            chunks: chunks::Chunks::new(),
+        }
+    }
+
+    fn now(self: &Self) -> Option<units::Time> {
+        self.synced.map(|(time, at)| time + elapsed_in_game(at))
+    }
+
+    fn send(self: &mut Self, message: city_net::ClientMessage) {
+        if let Err(e) = self.connection.send(&message) {
+            panic!("Lost connection to server: {}", e);
+        }
+    }
+
+    /// Where input is recorded to and played back from.
+    pub fn tape(self: &mut Self) -> &mut playback::Tape {
+        &mut self.tape
+    }
+
+    fn on_action(self: &mut Self, action: user_input::DeviceUpdate) {
+        self.tape.record(&action);
+        self.camera.on_action(&action);
+        use client::user_input::DeviceUpdate::*;
+        match action {
+            ChangeMovement { direction, pace } => {
+                ::client::walked_off(&mut self.interaction);
+                let control = player::Control::walk(direction, pace);
+                if let Some(now) = self.now() {
+                    self.prediction.control_sent(&control, now);
+                }
+                self.send(city_net::ClientMessage::Control(control));
+            },
+            Cast { target } => {
+                self.send(city_net::ClientMessage::Cast { target });
+            },
+            // the host decides how fast time passes
+            Nop | TogglePause | ChangeTimeScale { .. } => (),
+            Pan { .. } | Drag { .. } | ToggleFollow | Zoom { .. } => (),
+            // paths are only worked out by the host
+            ShowPath { .. } => (),
+            // and only the host can see how the server is doing, what the
+            // city has been spending, or where its utilities reach
+            ToggleDebug | ToggleBudget | CycleOverlay => (),
+            PlaceBuilding { blueprint, position } => {
+                let message =
+                    city_net::ClientMessage::Build { blueprint, position };
+                self.send(message);
+            },
+            PaintZone { from, to, zone } => {
+                let message = city_net::ClientMessage::Zone { from, to, zone };
+                self.send(message);
+            },
+            LayPipe { from, to } => {
+                self.send(city_net::ClientMessage::Pipe { from, to });
+            },
+            LayRoad { from, to } => {
+                self.send(city_net::ClientMessage::Road { from, to });
+            },
+            StartChat => {
+                self.chat.open();
+                let stop = self.input.let_go();
+                self.on_action(stop);
+            },
+            Interact => self.send(city_net::ClientMessage::Interact),
+            DropItem { slot } => {
+                self.send(city_net::ClientMessage::Drop { slot });
+            },
+            Undo => self.send(city_net::ClientMessage::Undo),
+            Redo => self.send(city_net::ClientMessage::Redo),
+        }
+    }
+}
+
+impl Drop for RemoteClient {
+    fn drop(self: &mut Self) {
+        let _ = self.connection.send(&city_net::ClientMessage::Quit);
+    }
+}
+
+// the actions that get a button on the toolbar
+const TOOLS: [Action; 5] = [
+    Action::Build,
+    Action::Zone,
+    Action::Pipe,
+    Action::Road,
+    Action::Chat,
+];
+
+impl ::client::Game for RemoteClient {
+    fn on_update(self: &mut Self, upd: app::UpdateArgs) {
+        let messages = match self.connection.receive() {
+            Ok(messages) => messages,
+            Err(e) => panic!("Lost connection to server: {}", e),
+        };
+        for message in messages {
+            match message {
+                city_net::ServerMessage::Snapshot(snapshot) => {
+                    self.synced = Some((snapshot.time, time::Instant::now()));
+                    self.prediction.push(snapshot);
+                },
+                city_net::ServerMessage::Enter { id, kind } => {
+                    self.prediction.enter(id, kind);
+                },
+                city_net::ServerMessage::Leave { id } => {
+                    self.prediction.leave(id);
+                },
+                city_net::ServerMessage::Checksum { time, checksum } => {
+                    if !self.prediction.verify(time, checksum) {
+                        info!("Out of sync with server, resyncing");
+                        self.prediction.resync();
+                        if let Err(e) = self.connection.resync() {
+                            panic!("Lost connection to server: {}", e);
+                        }
+                    }
+                },
+                city_net::ServerMessage::Ledger(balances) => {
+                    self.money.observe(balances.money);
+                    self.ledger = Some(balances);
+                },
+                city_net::ServerMessage::Chat(line) => {
+                    self.chat.receive(line);
+                },
+                city_net::ServerMessage::Interaction(outcome) => {
+                    ::client::interacted(&mut self.interaction, outcome);
+                },
+                city_net::ServerMessage::Inventory(items) => {
+                    self.inventory = items;
+                },
+                city_net::ServerMessage::Chunk(chunk) => {
+                    self.chunks.receive(chunk);
+                },
+                city_net::ServerMessage::Welcome { .. } => (),
+                city_net::ServerMessage::Accept { .. } => (),
+                city_net::ServerMessage::Reject(_) => (),
+                // already turned into snapshots by the connection
+                city_net::ServerMessage::Delta(_) => (),
+            }
+        }
+
+        for action in self.tape.advance(upd.dt) {
+            self.on_action(action);
+        }
+
+        self.money.update(upd.dt);
+        self.camera.update(upd.dt);
+        let wanted = self.chunks.update(upd.dt, &self.camera.view());
+        if !wanted.is_empty() {
+            self.send(city_net::ClientMessage::Chunks { ids: wanted });
+        }
+        if let Some(now) = self.now() {
+            let player = self.connection.id();
+            let own = self.prediction
+                          .seen(now - interpolation_delay(), now)
+                          .into_iter()
+                          .find(|seen| seen.id == player)
+                          .map(|seen| seen.position);
+            self.camera.follow(own);
+        }
+    }
+
+    fn on_input(self: &mut Self, bin: app::ButtonArgs) {
+        if self.input.is_typing() {
+            match self.chat.on_button(bin) {
+                chat::Typed::Still => (),
+                chat::Typed::Sent(text) => {
+                    self.send(city_net::ClientMessage::Chat { text });
+                    self.input.stop_typing();
+                },
+                chat::Typed::Closed => self.input.stop_typing(),
+            }
+            return;
+        }
+        if self.ui.on_input(bin) {
+            return;
+        }
+        let action = self.input.interpret(bin);
+        self.on_action(action);
+    }
+
+    fn on_controller_axis(self: &mut Self, args: app::ControllerAxisArgs) {
+        let action = self.input.on_controller_axis(args);
+        self.on_action(action);
+    }
+
+    fn on_mouse_move(self: &mut Self, mouse: [f64; 2]) {
+        self.camera.on_mouse_move(mouse);
+        self.ui.on_mouse_move(mouse);
+        let action = self.input.on_mouse_move(mouse);
+        self.on_action(action);
+    }
+
+    fn on_mouse_scroll(self: &mut Self, scroll: [f64; 2]) {
+        let action = self.input.on_mouse_scroll(scroll);
+        self.on_action(action);
+    }
+
+    fn on_text(self: &mut Self, text: &str) {
+        self.chat.on_text(text);
+    }
+
+    fn on_draw(
+        self: &mut Self,
+        context: app::Context,
+        graphics: &mut app::G2d,
+        ren: app::RenderArgs,
+        assets: &assets::Assets,
+    ) {
+        app::clear([0.0, 0.0, 0.0, 1.0], graphics);
+
+        self.camera.set_screen_size(ren.width, ren.height);
+        let view = self.camera.view();
+        self.input.set_view(view);
+        let world = view.transform(context.transform);
+        // the UI's pixels, which the window's are a multiple of
+        let size = self.ui.screen_of([ren.width as f64, ren.height as f64]);
+
+        let mut list = render::DrawList::new();
+        for chunk in self.chunks.arrived() {
+            list.chunk(chunk);
+        }
+        if let Some(now) = self.now() {
+            let others_time = now - interpolation_delay();
+            for seen in self.prediction.seen(others_time, now) {
+                let sprite = render::Sprite::of_kind(
+                    seen.kind, seen.velocity, seen.id, now,
+                );
+                list.sprite(seen.position, sprite);
+            }
+            list.night(size, now);
+            list.clock([10.0, 60.0], now);
+        }
+        if let Some((_, kind, position)) = self.input.ghost() {
+            list.ghost(kind, position);
+        }
+        if let Some((zone, from, to)) = self.input.zone_ghost() {
+            list.zone_ghost(zone, from, to);
+        }
+        if let Some((from, to)) = self.input.pipe_ghost() {
+            list.pipe_ghost(from, to);
+        }
+        if let Some((from, to)) = self.input.road_ghost() {
+            list.road_ghost(from, to);
+        }
+        if let Some(ref balances) = self.ledger {
+            list.ledger([10.0, 80.0], balances);
+        }
+        list.money_changes([160.0, 80.0], &self.money);
+        if let Some(ref outcome) = self.interaction {
+            list.interaction([10.0, 160.0], outcome);
+        }
+        list.chat([10.0, size[1] - 200.0], &self.chat);
+
+        self.ui.begin(size);
+        let tool = ui::toolbar(&mut self.ui, &mut list, &TOOLS);
+        let dropped = ui::inventory(&mut self.ui, &mut list, &self.inventory);
+        self.ui.end();
+        if let Some(tool) = tool {
+            let update = self.input.trigger(tool);
+            self.on_action(update);
+        }
+        if let Some(slot) = dropped {
+            self.on_action(user_input::DeviceUpdate::DropItem { slot });
+        }
+
+        let screen = self.ui.transform(context.transform);
+        let mut font = assets.glyphs();
+        let glyphs = font.as_mut();
+        let assets = Some(assets);
+        let mut backend = render::piston::Backend {
+            world, screen, glyphs, assets,
+        };
+        backend.execute(&list, &context, graphics);
+    }
+
+    fn set_ui_scale(self: &mut Self, scale: f64) {
+        self.ui.set_scale(scale);
+    }
+}
@@ -0,0 +1,117 @@
+use city_internal::sulphate;
+use city_internal::units;
+
+/// Which animation a sprite is going through, picked by what the server
+/// says about its entity.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Cycle {
+    /// Standing still, and so not animated.
+    Idle,
+    Walking,
+    /// A building's lights blinking, while it has all it needs.
+    Lit,
+    /// A building's lights out, while it is going without.
+    Unlit,
+}
+
+impl Cycle {
+    pub fn name(self: Self) -> &'static str {
+        match self {
+            Cycle::Idle => "idle",
+            Cycle::Walking => "walk",
+            Cycle::Lit => "lit",
+            Cycle::Unlit => "unlit",
+        }
+    }
+
+    /// How many frames it goes through before starting over.
+    pub fn frames(self: Self) -> u32 {
+        match self {
+            Cycle::Idle | Cycle::Unlit => 1,
+            Cycle::Walking => 4,
+            Cycle::Lit => 2,
+        }
+    }
+
+    // how long each frame is shown for
+    fn frame_length(self: Self) -> units::Duration {
+        match self {
+            Cycle::Idle | Cycle::Unlit => units::moments(1),
+            Cycle::Walking => units::moments(3),
+            Cycle::Lit => units::moments(12),
+        }
+    }
+
+    /// The frame it is on at `now`, for entity `id`, going by game time so
+    /// that it speeds up and slows down along with the game. Each entity
+    /// starts at a different point, so that they don't all step or blink
+    /// at once.
+    pub fn at(
+        self: Self,
+        now: units::Time,
+        id: sulphate::EntityId,
+    ) -> Frame {
+        let elapsed = (now - units::Time::default()).into_bits();
+        let length = self.frame_length().into_bits();
+        let frames = self.frames() as i64;
+        let offset = (id % frames as u64) as i64;
+        let frame = ((elapsed / length + offset) % frames + frames) % frames;
+        Frame { cycle: self, frame: frame as u32 }
+    }
+}
+
+/// Walking while it is moving, idle otherwise.
+pub fn of_motion(velocity: units::Velocity) -> Cycle {
+    if velocity == units::Velocity::default() {
+        Cycle::Idle
+    } else {
+        Cycle::Walking
+    }
+}
+
+/// Blinking while it has what it needs, dark while it goes without.
+pub fn of_power(blackout: bool) -> Cycle {
+    if blackout {
+        Cycle::Unlit
+    } else {
+        Cycle::Lit
+    }
+}
+
+/// One frame of an animation.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Frame {
+    pub cycle: Cycle,
+    pub frame: u32,
+}
+
+impl Frame {
+    /// Not animated at all.
+    pub fn still() -> Frame {
+        Frame { cycle: Cycle::Idle, frame: 0 }
+    }
+}
+
+#[cfg(test)]
+mod test_animation {
+    use super::*;
+
+    #[test]
+    fn frames_go_by_game_time_and_start_over() {
+        let start = units::Time::default();
+        let walking = |moments, id| {
+            Cycle::Walking.at(start + units::moments(moments), id).frame
+        };
+        assert_eq!(walking(0, 0), 0);
+        assert_eq!(walking(2, 0), 0);
+        assert_eq!(walking(3, 0), 1);
+        assert_eq!(walking(9, 0), 3);
+        assert_eq!(walking(12, 0), 0);
+        // a different entity, at a different point in its cycle
+        assert_eq!(walking(0, 1), 1);
+        assert_eq!(walking(9, 1), 0);
+
+        let still = Cycle::Unlit.at(start + units::moments(100), 7);
+        assert_eq!(still.frame, 0);
+    }
+}
@@ -0,0 +1,1028 @@
+use city_internal::demand;
+use city_internal::economy;
+use city_internal::entities;
+use city_internal::entities::building;
+use city_internal::entities::item;
+use city_internal::entities::player;
+use city_internal::entities::vehicle;
+use city_internal::space;
+use city_internal::space::chunks;
+use city_internal::space::roads;
+use city_internal::space::zones;
+use city_internal::sulphate;
+use city_internal::sulphate::persist;
+use city_internal::units;
+use city_internal::utilities;
+use city_internal::world::calendar;
+
+use client::chat;
+use client::locale;
+use client::particles;
+use client::trackers;
+
+pub mod animation;
+pub mod overlay;
+pub mod piston;
+
+pub use self::overlay::Overlay;
+
+/// What gets drawn on top of what, bottom first.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Layer {
+    /// Painted on the ground, under everything that stands on it.
+    Ground,
+    /// Shaded over the ground by an overlay, still under everything that
+    /// stands on it.
+    Tint,
+    Entities,
+    /// Smoke, dust and rain, over what stands on the ground.
+    Particles,
+    Effects,
+    /// Drawn in pixels rather than in world coordinates, so that it stays
+    /// put when the camera moves.
+    Overlay,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Sprite {
+    Player {
+        color: player::Color,
+    },
+    /// Stepping along while it walks.
+    Citizen {
+        frame: animation::Frame,
+    },
+    /// Drawn darker while it is going without, and aflame while on fire,
+    /// as whatever it was built as, if that is known. Its lights blink
+    /// while it has what it needs.
+    Building {
+        blueprint: Option<building::Blueprint>,
+        blackout: bool,
+        burning: bool,
+        frame: animation::Frame,
+    },
+    Vehicle(vehicle::Heading),
+    /// Whatever it is, if that is known.
+    Item(Option<item::Kind>),
+    /// A whole tile of the ground, drawn from its top left corner.
+    Terrain(space::Tile),
+}
+
+impl Sprite {
+    /// What to draw for `entity`, on whatever frame it is on at `now`.
+    pub fn of_entity(
+        entity: &sulphate::ViewedEntity,
+        now: units::Time,
+    ) -> Sprite {
+        let id = entity.uid.id;
+        match entity.image {
+            entities::Image::Player(ref image) => {
+                Sprite::Player { color: image.appearance.color }
+            },
+            entities::Image::Citizen(_) => {
+                let cycle = animation::of_motion(entity.velocity);
+                Sprite::Citizen { frame: cycle.at(now, id) }
+            },
+            entities::Image::Building(ref image) => {
+                let cycle = animation::of_power(image.blackout);
+                Sprite::Building {
+                    blueprint: Some(image.blueprint),
+                    blackout: image.blackout,
+                    burning: image.burning,
+                    frame: cycle.at(now, id),
+                }
+            },
+            entities::Image::Vehicle(ref image) => {
+                Sprite::Vehicle(image.heading)
+            },
+            entities::Image::Item(ref image) => Sprite::Item(Some(image.kind)),
+        }
+    }
+
+    /// What to draw for something that only its kind and velocity are
+    /// known about, as for remote clients.
+    pub fn of_kind(
+        kind: persist::EntityKind,
+        velocity: units::Velocity,
+        id: sulphate::EntityId,
+        now: units::Time,
+    ) -> Sprite {
+        match kind {
+            // nor how a player looks
+            persist::EntityKind::Player => {
+                Sprite::Player { color: player::Appearance::default().color }
+            },
+            persist::EntityKind::Citizen => {
+                let cycle = animation::of_motion(velocity);
+                Sprite::Citizen { frame: cycle.at(now, id) }
+            },
+            // nor what a building is, whether it has what it needs, or
+            // whether it is on fire, so its lights are left off
+            persist::EntityKind::Building => Sprite::Building {
+                blueprint: None,
+                blackout: false,
+                burning: false,
+                frame: animation::Frame::still(),
+            },
+            // a vehicle that has stopped could be facing any way
+            persist::EntityKind::Vehicle => {
+                let heading = vehicle::Heading::of(velocity)
+                                  .unwrap_or(vehicle::Heading::East);
+                Sprite::Vehicle(heading)
+            },
+            // nor what an item is
+            persist::EntityKind::Item => Sprite::Item(None),
+        }
+    }
+}
+
+pub type Color = [f32; 4];
+
+/// A player's color, as drawn.
+pub fn color_of(rgb: player::Color) -> Color {
+    let channel = |c: u8| c as f32 / 255.0;
+    [channel(rgb[0]), channel(rgb[1]), channel(rgb[2]), 1.0]
+}
+
+// nameplates are clear out to the first distance and gone by the second
+const NAMEPLATE_NEAR: f64 = 200.0;
+const NAMEPLATE_FAR: f64 = 500.0;
+
+fn nameplate_alpha(distance: f64) -> f32 {
+    let fade = (NAMEPLATE_FAR - distance) / (NAMEPLATE_FAR - NAMEPLATE_NEAR);
+    fade.max(0.0).min(1.0) as f32
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Shape {
+    Circle {
+        radius: f64,
+        color: Color,
+    },
+    /// From the command's position to `by` away from it, so that shapes
+    /// are all drawn relative to where they are.
+    Line {
+        by: [f64; 2],
+        width: f64,
+        color: Color,
+    },
+    /// With its top left corner at the command's position.
+    Rectangle {
+        size: [f64; 2],
+        color: Color,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Item {
+    Sprite(Sprite),
+    Shape(Shape),
+    Text {
+        text: String,
+        size: u32,
+        color: Color,
+    },
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Command {
+    pub layer: Layer,
+    // world coordinates, or pixels on the overlay
+    pub position: [f64; 2],
+    pub item: Item,
+}
+
+/// Everything to draw in one frame, without saying how to draw it.
+#[derive(Clone, Default, Debug)]
+pub struct DrawList {
+    commands: Vec<Command>,
+}
+
+fn floatify_position(position: units::Position) -> [f64; 2] {
+    let origin: units::Position = Default::default();
+    let vec = position - origin;
+    [vec.x.into(), vec.y.into()]
+}
+
+// see-through, so that what is built there still shows
+fn zone_color(zone: Option<zones::Zone>) -> Color {
+    match zone {
+        Some(zones::Zone::Residential) => [0.2, 0.8, 0.2, 0.25],
+        Some(zones::Zone::Commercial) => [0.2, 0.4, 0.9, 0.25],
+        Some(zones::Zone::Industrial) => [0.9, 0.8, 0.2, 0.25],
+        None => [0.6, 0.6, 0.6, 0.25],
+    }
+}
+
+fn tile_centre(id: space::TileId) -> [f64; 2] {
+    let tile = space::TILE_SIZE;
+    [(id.0 as f64 + 0.5) * tile, (id.1 as f64 + 0.5) * tile]
+}
+
+const PIPE_COLOR: Color = [0.3, 0.6, 1.0, 0.8];
+
+impl DrawList {
+    pub fn new() -> DrawList {
+        Default::default()
+    }
+
+    /// Draws everything in `view` where it will be at `now`.
+    pub fn of_view(
+        view: &sulphate::WorldView,
+        now: units::Time,
+    ) -> DrawList {
+        let mut list = DrawList::new();
+        list.ground(&view.terrain, &view.zones);
+        let dt = now - view.time;
+        for entity in &view.entities {
+            let position = entity.position + entity.velocity * dt;
+            list.sprite(position, Sprite::of_entity(entity, now));
+        }
+        list
+    }
+
+    // the terrain, with zoning over it
+    fn ground(
+        self: &mut Self,
+        terrain: &[(space::TileId, space::Tile)],
+        zones: &[(space::TileId, space::zones::Zone)],
+    ) {
+        let tile = space::TILE_SIZE;
+        for &(id, kind) in terrain {
+            let corner = [id.0 as f64 * tile, id.1 as f64 * tile];
+            let sprite = Item::Sprite(Sprite::Terrain(kind));
+            self.push(Layer::Ground, corner, sprite);
+        }
+        for &(id, zone) in zones {
+            self.zone_tiles(id, id, Some(zone), Layer::Ground);
+        }
+    }
+
+    /// Draws the ground of a chunk that a remote client was sent.
+    pub fn chunk(self: &mut Self, chunk: &chunks::Chunk) {
+        self.ground(&chunk.terrain, &chunk.zones);
+    }
+
+    /// The name of every player in `view` but `own` over their heads,
+    /// fading out the further they are from `from`.
+    pub fn nameplates(
+        self: &mut Self,
+        view: &sulphate::WorldView,
+        now: units::Time,
+        own: sulphate::EntityId,
+        from: units::Position,
+    ) {
+        let dt = now - view.time;
+        let from = floatify_position(from);
+        for entity in &view.entities {
+            let appearance = match entity.image {
+                entities::Image::Player(ref image) => &image.appearance,
+                _ => continue,
+            };
+            if entity.uid.id == own {
+                continue;
+            }
+            let position = entity.position + entity.velocity * dt;
+            let position = floatify_position(position);
+            let dx = position[0] - from[0];
+            let dy = position[1] - from[1];
+            let alpha = nameplate_alpha((dx * dx + dy * dy).sqrt());
+            if alpha == 0.0 {
+                continue;
+            }
+            let text = locale::format("nameplate", &[&appearance.name]);
+            // roughly centred, since text can't be measured without a font
+            let width = 4.0 * text.chars().count() as f64;
+            let radius: f64 = entity.image.radius().into();
+            let above = [position[0] - width, position[1] - radius - 6.0];
+            let size = 12;
+            let color = [1.0, 1.0, 1.0, alpha];
+            self.push(Layer::Effects, above, Item::Text { text, size, color });
+        }
+    }
+
+    // the rectangle of tiles from `a` to `b`, both included
+    fn zone_tiles(
+        self: &mut Self,
+        a: space::TileId,
+        b: space::TileId,
+        zone: Option<zones::Zone>,
+        layer: Layer,
+    ) {
+        let tile = space::TILE_SIZE;
+        let left = a.0.min(b.0) as f64 * tile;
+        let top = a.1.min(b.1) as f64 * tile;
+        let size = [
+            ((a.0 - b.0).abs() + 1) as f64 * tile,
+            ((a.1 - b.1).abs() + 1) as f64 * tile,
+        ];
+        let color = zone_color(zone);
+        let shape = Shape::Rectangle { size, color };
+        self.push(layer, [left, top], Item::Shape(shape));
+    }
+
+    /// The tiles that dragging from `from` to `to` would zone.
+    pub fn zone_ghost(
+        self: &mut Self,
+        zone: Option<zones::Zone>,
+        from: units::Position,
+        to: units::Position,
+    ) {
+        let from = space::tile_id(from);
+        let to = space::tile_id(to);
+        self.zone_tiles(from, to, zone, Layer::Effects);
+    }
+
+    // a line through the middle of each tile from `a` to `b`
+    fn tile_line(
+        self: &mut Self,
+        a: space::TileId,
+        b: space::TileId,
+        width: f64,
+        color: Color,
+    ) {
+        let start = tile_centre(a);
+        let end = tile_centre(b);
+        let by = [end[0] - start[0], end[1] - start[1]];
+        let shape = Shape::Line { by, width, color };
+        self.push(Layer::Effects, start, Item::Shape(shape));
+    }
+
+    fn pipe_run(
+        self: &mut Self,
+        a: space::TileId,
+        b: space::TileId,
+        color: Color,
+    ) {
+        self.tile_line(a, b, 3.0, color);
+    }
+
+    /// The pipe that dragging from `from` to `to` would lay.
+    pub fn pipe_ghost(
+        self: &mut Self,
+        from: units::Position,
+        to: units::Position,
+    ) {
+        let color = [0.6, 0.6, 0.6, 0.6];
+        self.pipe_run(space::tile_id(from), space::tile_id(to), color);
+    }
+
+    /// The road that dragging from `from` to `to` would lay, turning where
+    /// `roads::corner` says it will.
+    pub fn road_ghost(
+        self: &mut Self,
+        from: units::Position,
+        to: units::Position,
+    ) {
+        let color = [0.7, 0.7, 0.7, 0.6];
+        let corner = space::tile_id(roads::corner(from, to));
+        self.tile_line(space::tile_id(from), corner, 6.0, color);
+        self.tile_line(corner, space::tile_id(to), 6.0, color);
+    }
+
+    /// Whatever `overlay` shows of the city in `view`, tinting the tiles
+    /// it has something to say about, along with whatever water is carried
+    /// along if it is the water overlay.
+    pub fn overlay(
+        self: &mut Self,
+        view: &sulphate::WorldView,
+        overlay: Overlay,
+    ) {
+        let tile = space::TILE_SIZE;
+        for (id, color) in overlay.tints(view) {
+            let corner = [id.0 as f64 * tile, id.1 as f64 * tile];
+            let shape = Shape::Rectangle { size: [tile, tile], color };
+            self.push(Layer::Tint, corner, Item::Shape(shape));
+        }
+        if overlay == Overlay::Utility(utilities::Utility::Water) {
+            for &(a, b) in &view.pipes {
+                self.pipe_run(a, b, PIPE_COLOR);
+            }
+        }
+    }
+
+    pub fn push(
+        self: &mut Self,
+        layer: Layer,
+        position: [f64; 2],
+        item: Item,
+    ) {
+        self.commands.push(Command { layer, position, item });
+    }
+
+    pub fn sprite(self: &mut Self, position: units::Position, sprite: Sprite) {
+        let position = floatify_position(position);
+        self.push(Layer::Entities, position, Item::Sprite(sprite));
+    }
+
+    pub fn cast(
+        self: &mut Self,
+        target: units::Position,
+        age: units::Duration,
+    ) {
+        // shrinks away over the first half second
+        let age: f64 = age.into();
+        let radius = 5.0 * (1.0 - 2.0 * age).max(0.2);
+        let color = [1.0, 1.0, 0.0, 1.0];
+        let shape = Shape::Circle { radius, color };
+        let position = floatify_position(target);
+        self.push(Layer::Effects, position, Item::Shape(shape));
+    }
+
+    /// Lines joining up the waypoints of a path, starting from `from`.
+    pub fn path(
+        self: &mut Self,
+        from: units::Position,
+        waypoints: &[units::Position],
+    ) {
+        let color = [0.0, 1.0, 0.5, 1.0];
+        let width = 1.0;
+        let mut start = floatify_position(from);
+        for &waypoint in waypoints {
+            let to = floatify_position(waypoint);
+            let by = [to[0] - start[0], to[1] - start[1]];
+            let shape = Shape::Line { by, width, color };
+            self.push(Layer::Effects, start, Item::Shape(shape));
+            start = to;
+        }
+    }
+
+    /// The circle that each entity in `view` collides as, where it will be
+    /// at `now`, over the top of how it looks.
+    pub fn collision_shapes(
+        self: &mut Self,
+        view: &sulphate::WorldView,
+        now: units::Time,
+    ) {
+        let dt = now - view.time;
+        let color = [1.0, 0.0, 1.0, 0.3];
+        for entity in &view.entities {
+            let position = entity.position + entity.velocity * dt;
+            let radius = entity.image.radius().into();
+            let shape = Shape::Circle { radius, color };
+            let position = floatify_position(position);
+            self.push(Layer::Effects, position, Item::Shape(shape));
+        }
+    }
+
+    /// A see-through outline of where a building would go.
+    pub fn ghost(
+        self: &mut Self,
+        blueprint: building::Blueprint,
+        position: units::Position,
+    ) {
+        let radius = blueprint.radius().into();
+        let color = [0.6, 0.6, 0.6, 0.4];
+        let shape = Shape::Circle { radius, color };
+        let position = floatify_position(position);
+        self.push(Layer::Effects, position, Item::Shape(shape));
+    }
+
+    pub fn label(self: &mut Self, pixel: [f64; 2], text: &str) {
+        let text = text.to_string();
+        let size = 16;
+        let color = [1.0, 1.0, 1.0, 1.0];
+        self.push(Layer::Overlay, pixel, Item::Text { text, size, color });
+    }
+
+    /// Darkens the whole screen of `size` pixels by however dark it is at
+    /// `now`, so this goes before anything else on the overlay.
+    pub fn night(self: &mut Self, size: [f64; 2], now: units::Time) {
+        let dark = 1.0 - calendar::daylight(now);
+        if dark == 0.0 {
+            return;
+        }
+        let color = [0.0, 0.0, 0.1, 0.6 * dark as f32];
+        let shape = Shape::Rectangle { size, color };
+        self.push(Layer::Overlay, [0.0, 0.0], Item::Shape(shape));
+    }
+
+    /// Every particle in `motes`, fading out as it ages.
+    pub fn motes(self: &mut Self, motes: &[particles::Mote]) {
+        for mote in motes {
+            let fade = 1.0 - mote.age;
+            let shape = match mote.kind {
+                particles::Kind::Smoke => Shape::Circle {
+                    radius: 3.0 + 6.0 * mote.age as f64,
+                    color: [0.5, 0.5, 0.5, 0.5 * fade],
+                },
+                particles::Kind::Dust => Shape::Circle {
+                    radius: 2.0,
+                    color: [0.7, 0.6, 0.4, 0.8 * fade],
+                },
+                particles::Kind::Rain => Shape::Line {
+                    by: [-2.0, 8.0],
+                    width: 1.0,
+                    color: [0.6, 0.7, 1.0, 0.5],
+                },
+            };
+            self.push(Layer::Particles, mote.position, Item::Shape(shape));
+        }
+    }
+
+    /// What the city's clock reads at `now`.
+    pub fn clock(self: &mut Self, pixel: [f64; 2], now: units::Time) {
+        let date = calendar::date(now);
+        let day = date.day + 1;
+        let season = locale::name("season", date.season.name());
+        let hour = format!("{:02}", date.hour);
+        let minute = format!("{:02}", date.minute);
+        let text = locale::format("clock", &[&day, &season, &hour, &minute]);
+        self.label(pixel, &text);
+    }
+
+    /// The chat box's lines down the screen from `pixel`, each with the
+    /// time it was said, then the line being typed if there is one.
+    pub fn chat(self: &mut Self, pixel: [f64; 2], chat: &chat::ChatBox) {
+        let mut at = pixel;
+        for line in chat.history() {
+            let date = calendar::date(line.time);
+            let hour = format!("{:02}", date.hour);
+            let minute = format!("{:02}", date.minute);
+            let text = locale::format(
+                "chat.line",
+                &[&hour, &minute, &line.from, &line.text],
+            );
+            self.label(at, &text);
+            at[1] += 20.0;
+        }
+        if let Some(draft) = chat.draft() {
+            self.label(at, &locale::format("chat.draft", &[&draft]));
+        }
+    }
+
+    /// A readout of the city's books, down the left of the screen from
+    /// `pixel`.
+    pub fn ledger(
+        self: &mut Self,
+        pixel: [f64; 2],
+        balances: &economy::Balances,
+    ) {
+        let change = balances.supply.money - balances.demand.money;
+        let lines = [
+            locale::format(
+                "ledger.money",
+                &[&balances.money, &format!("{:+}", change)],
+            ),
+            locale::format(
+                "ledger.power",
+                &[&balances.demand.power, &balances.supply.power],
+            ),
+            locale::format(
+                "ledger.water",
+                &[&balances.demand.water, &balances.supply.water],
+            ),
+        ];
+        let mut at = pixel;
+        for line in &lines {
+            self.label(at, line);
+            at[1] += 20.0;
+        }
+        if balances.blackouts > 0 {
+            let line =
+                locale::format("ledger.blackouts", &[&balances.blackouts]);
+            self.label(at, &line);
+        }
+    }
+
+    /// Recent changes to the city's money, rising up from `pixel`.
+    pub fn money_changes(
+        self: &mut Self,
+        pixel: [f64; 2],
+        changes: &trackers::MoneyChanges,
+    ) {
+        for (text, risen) in changes.visible() {
+            self.label([pixel[0], pixel[1] - risen], &text);
+        }
+    }
+
+    /// A bar for how much the city wants more of each zone, in the zone's
+    /// color, rising from the line through `pixel` when it wants more and
+    /// hanging below it when it wants less, each with its zone's initial
+    /// underneath.
+    pub fn demand_bars(
+        self: &mut Self,
+        pixel: [f64; 2],
+        demand: &demand::Demand,
+    ) {
+        // pixels tall at the most demand either way
+        let most = 40.0;
+        let width = 12.0;
+        for (i, &zone) in zones::Zone::all().iter().enumerate() {
+            let x = pixel[0] + i as f64 * 2.0 * width;
+            let height = most * demand.of(zone) as f64 / 100.0;
+            let top = pixel[1] - height.max(0.0);
+            let mut color = zone_color(Some(zone));
+            color[3] = 1.0;
+            let size = [width, height.abs()];
+            let shape = Shape::Rectangle { size, color };
+            self.push(Layer::Overlay, [x, top], Item::Shape(shape));
+            let name = locale::name("zone", zone.name());
+            let initial: String = name.chars().take(1).collect();
+            self.label([x, pixel[1] + most + 20.0], &initial);
+        }
+    }
+
+    /// What came of the player last interacting with something, down the
+    /// screen from `pixel`.
+    pub fn interaction(
+        self: &mut Self,
+        pixel: [f64; 2],
+        outcome: &entities::Interaction,
+    ) {
+        use city_internal::entities::Interaction::*;
+        let lines = match *outcome {
+            Menu { blueprint, blackout } => {
+                let state = if blackout {
+                    "interaction.blacked_out"
+                } else {
+                    "interaction.supplied"
+                };
+                let name = locale::name("building", blueprint.name());
+                vec![name, locale::text(state)]
+            },
+            Boarded { driving, .. } => {
+                let doing = if driving {
+                    "interaction.driving"
+                } else {
+                    "interaction.riding"
+                };
+                vec![locale::text(doing)]
+            },
+            Alighted => Vec::new(),
+            PickedUp { kind } => {
+                let name = locale::name("item", kind.name());
+                vec![locale::format("interaction.picked_up", &[&name])]
+            },
+            Talk { ref line } => {
+                vec![locale::format("interaction.talk", &[line])]
+            },
+        };
+        let mut at = pixel;
+        for line in &lines {
+            self.label(at, line);
+            at[1] += 20.0;
+        }
+    }
+
+    /// The commands bottom layer first, and in the order they were pushed
+    /// within each layer.
+    pub fn commands(self: &Self) -> Vec<&Command> {
+        let mut commands: Vec<&Command> = self.commands.iter().collect();
+        // sort_by_key is stable, so pushes keep their order
+        commands.sort_by_key(|command| command.layer);
+        commands
+    }
+}
+
+#[cfg(test)]
+mod test_draw_list {
+    use std::any;
+
+    use city_internal::entities;
+    use city_internal::sulphate;
+    use city_internal::units;
+
+    use super::*;
+
+    // how players look if they don't choose
+    fn red() -> Sprite {
+        Sprite::Player { color: [255, 0, 0] }
+    }
+
+    fn player_at(x: i32, vx: i32) -> sulphate::ViewedEntity {
+        let id = 0;
+        let ty = any::TypeId::of::<entities::Player>();
+        let offset = units::Displacement { x: x.into(), y: 0.into() };
+        sulphate::ViewedEntity {
+            uid: sulphate::EntityUId { id, ty },
+            image: entities::Image::Player(entities::player::Image {
+                appearance: Default::default(),
+            }),
+            position: units::Position::default() + offset,
+            velocity: units::Velocity { x: vx.into(), y: 0.into() },
+        }
+    }
+
+    #[test]
+    fn entities_move_to_now() {
+        let view = sulphate::WorldView {
+            time: Default::default(),
+            entities: vec![player_at(10, 20)],
+            balances: Default::default(),
+            terrain: Vec::new(),
+            roads: Vec::new(),
+            zones: Vec::new(),
+            pipes: Vec::new(),
+            traffic: Vec::new(),
+            sounds: Vec::new(),
+        };
+        let half = units::Scalar::from(1) / 2;
+        let now = units::Time::default() + half;
+
+        let list = DrawList::of_view(&view, now);
+        let commands = list.commands();
+        assert_eq!(commands.len(), 1);
+        assert_eq!(commands[0].item, Item::Sprite(red()));
+        assert_eq!(commands[0].position, [20.0, 0.0]);
+    }
+
+    #[test]
+    fn layers_are_ordered() {
+        let mut list = DrawList::new();
+        list.label([0.0, 0.0], "paused");
+        list.cast(Default::default(), Default::default());
+        list.sprite(Default::default(), red());
+
+        let layers: Vec<Layer> =
+            list.commands().iter().map(|command| command.layer).collect();
+        let expected = vec![Layer::Entities, Layer::Effects, Layer::Overlay];
+        assert_eq!(layers, expected);
+    }
+
+    #[test]
+    fn particles_go_over_what_stands_on_the_ground() {
+        let mut list = DrawList::new();
+        let smoke = particles::Mote {
+            kind: particles::Kind::Smoke,
+            position: [5.0, 5.0],
+            age: 0.5,
+        };
+        list.motes(&[smoke]);
+        list.sprite(Default::default(), red());
+        let commands = list.commands();
+        assert_eq!(commands[1].layer, Layer::Particles);
+        match commands[1].item {
+            Item::Shape(Shape::Circle { radius, color }) => {
+                assert_eq!(radius, 6.0);
+                assert_eq!(color[3], 0.25);
+            },
+            _ => panic!("smoke wasn't drawn as a puff"),
+        }
+    }
+
+    #[test]
+    fn paths_are_joined_up() {
+        let at = |x: i32| {
+            let offset = units::Displacement { x: x.into(), y: 0.into() };
+            units::Position::default() + offset
+        };
+        let mut list = DrawList::new();
+        list.path(at(0), &[at(10), at(30)]);
+
+        let lines: Vec<([f64; 2], [f64; 2])> = list
+            .commands()
+            .iter()
+            .map(|command| match command.item {
+                Item::Shape(Shape::Line { by, .. }) => (command.position, by),
+                _ => panic!("path drew something other than lines"),
+            })
+            .collect();
+        let expected = vec![
+            ([0.0, 0.0], [10.0, 0.0]),
+            ([10.0, 0.0], [20.0, 0.0]),
+        ];
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    fn collision_shapes_go_over_entities() {
+        let view = sulphate::WorldView {
+            time: Default::default(),
+            entities: vec![player_at(10, 0)],
+            balances: Default::default(),
+            terrain: Vec::new(),
+            roads: Vec::new(),
+            zones: Vec::new(),
+            pipes: Vec::new(),
+            traffic: Vec::new(),
+            sounds: Vec::new(),
+        };
+        let mut list = DrawList::of_view(&view, Default::default());
+        list.collision_shapes(&view, Default::default());
+        let commands = list.commands();
+        assert_eq!(commands[1].layer, Layer::Effects);
+        assert_eq!(commands[1].position, [10.0, 0.0]);
+        match commands[1].item {
+            Item::Shape(Shape::Circle { radius, .. }) => {
+                assert_eq!(radius, 10.0);
+            },
+            _ => panic!("collision shape wasn't drawn as a circle"),
+        }
+    }
+
+    #[test]
+    fn zones_go_under_entities() {
+        let view = sulphate::WorldView {
+            time: Default::default(),
+            entities: vec![player_at(10, 0)],
+            balances: Default::default(),
+            terrain: Vec::new(),
+            roads: Vec::new(),
+            zones: vec![((1, -1), zones::Zone::Commercial)],
+            pipes: Vec::new(),
+            traffic: Vec::new(),
+            sounds: Vec::new(),
+        };
+        let list = DrawList::of_view(&view, Default::default());
+        let commands = list.commands();
+        assert_eq!(commands[0].layer, Layer::Ground);
+        assert_eq!(commands[0].position, [32.0, -32.0]);
+        match commands[0].item {
+            Item::Shape(Shape::Rectangle { size, .. }) => {
+                assert_eq!(size, [32.0, 32.0]);
+            },
+            _ => panic!("zone wasn't drawn as a rectangle"),
+        }
+        assert_eq!(commands[1].item, Item::Sprite(red()));
+    }
+
+    #[test]
+    fn the_water_overlay_tints_dry_buildings() {
+        use city_internal::entities::building::Coverage;
+
+        let building = |x: i32, coverage| {
+            let id = 1;
+            let ty = any::TypeId::of::<building::Building>();
+            let offset = units::Displacement { x: x.into(), y: 0.into() };
+            let blueprint = building::Blueprint::House;
+            let blackout = false;
+            sulphate::ViewedEntity {
+                uid: sulphate::EntityUId { id, ty },
+                image: entities::Image::Building(building::Image {
+                    blueprint, blackout, coverage, burning: false,
+                }),
+                position: units::Position::default() + offset,
+                velocity: Default::default(),
+            }
+        };
+        let dry = Coverage { power: true, water: false };
+        let view = sulphate::WorldView {
+            time: Default::default(),
+            entities: vec![building(0, Coverage::full()), building(64, dry)],
+            balances: Default::default(),
+            terrain: Vec::new(),
+            roads: Vec::new(),
+            zones: Vec::new(),
+            pipes: vec![((0, 0), (1, 0))],
+            traffic: Vec::new(),
+            sounds: Vec::new(),
+        };
+        let tint = |list: &DrawList, i: usize| -> Color {
+            match list.commands()[i].item {
+                Item::Shape(Shape::Rectangle { color, .. }) => color,
+                _ => panic!("tile wasn't tinted"),
+            }
+        };
+        let mut list = DrawList::new();
+        let overlay = |utility| Overlay::Utility(utility);
+        list.overlay(&view, overlay(utilities::Utility::Power));
+        assert_eq!(list.commands().len(), 2);
+        assert_eq!(tint(&list, 0), tint(&list, 1));
+
+        let mut list = DrawList::new();
+        list.overlay(&view, overlay(utilities::Utility::Water));
+        let commands = list.commands();
+        assert_eq!(commands.len(), 3);
+        assert_eq!(commands[1].layer, Layer::Tint);
+        assert_eq!(commands[1].position, [64.0, 0.0]);
+        assert!(tint(&list, 0) != tint(&list, 1));
+        // the pipe goes on top
+        assert_eq!(commands[2].position, [16.0, 16.0]);
+        match commands[2].item {
+            Item::Shape(Shape::Line { by, .. }) => {
+                assert_eq!(by, [32.0, 0.0]);
+            },
+            _ => panic!("pipe wasn't drawn as a line"),
+        }
+    }
+
+    #[test]
+    fn overlays_tint_whole_tiles_under_entities() {
+        let view = sulphate::WorldView {
+            time: Default::default(),
+            entities: Vec::new(),
+            balances: Default::default(),
+            terrain: Vec::new(),
+            roads: Vec::new(),
+            zones: Vec::new(),
+            pipes: Vec::new(),
+            traffic: vec![(((0, 0), (1, 0)), 100), (((0, 0), (0, 1)), 400)],
+            sounds: Vec::new(),
+        };
+        let mut list = DrawList::new();
+        list.sprite(Default::default(), red());
+        list.overlay(&view, Overlay::Traffic);
+        let commands = list.commands();
+        assert_eq!(commands.len(), 1 + 3);
+        for command in &commands[..3] {
+            assert_eq!(command.layer, Layer::Tint);
+            match command.item {
+                Item::Shape(Shape::Rectangle { size, .. }) => {
+                    assert_eq!(size, [32.0, 32.0]);
+                },
+                _ => panic!("traffic wasn't drawn as tiles"),
+            }
+        }
+        assert_eq!(commands[2].position, [32.0, 0.0]);
+        assert_eq!(commands[3].item, Item::Sprite(red()));
+    }
+
+    #[test]
+    fn chat_is_stamped_with_the_hour() {
+        use city_internal::sulphate::chat::ChatLine;
+
+        let evening = units::Time::default() + calendar::hours(18);
+        let mut chat = chat::ChatBox::new();
+        chat.receive(ChatLine::new(evening, "someone", "hi").unwrap());
+        chat.open();
+        let mut list = DrawList::new();
+        list.chat([0.0, 0.0], &chat);
+
+        let commands = list.commands();
+        let texts: Vec<&str> = commands
+            .iter()
+            .map(|command| match command.item {
+                Item::Text { ref text, .. } => &text[..],
+                _ => panic!("chat drew something other than text"),
+            })
+            .collect();
+        assert_eq!(texts, vec!["[18:00] someone: hi", "> _"]);
+    }
+
+    #[test]
+    fn building_menus_say_how_the_building_is() {
+        let mut list = DrawList::new();
+        let outcome = entities::Interaction::Menu {
+            blueprint: building::Blueprint::Shop,
+            blackout: true,
+        };
+        list.interaction([0.0, 0.0], &outcome);
+
+        let commands = list.commands();
+        let texts: Vec<&str> = commands
+            .iter()
+            .map(|command| match command.item {
+                Item::Text { ref text, .. } => &text[..],
+                _ => panic!("the menu drew something other than text"),
+            })
+            .collect();
+        assert_eq!(texts, vec!["shop", "blacked out"]);
+    }
+
+    #[test]
+    fn nights_are_tinted_under_labels() {
+        let midnight = units::Time::default();
+        let noon = midnight + calendar::hours(12);
+        let mut list = DrawList::new();
+        list.night([640.0, 480.0], noon);
+        assert!(list.commands().is_empty());
+
+        list.night([640.0, 480.0], midnight);
+        list.label([0.0, 0.0], "paused");
+        let commands = list.commands();
+        match commands[0].item {
+            Item::Shape(Shape::Rectangle { size, color }) => {
+                assert_eq!(size, [640.0, 480.0]);
+                assert!(color[3] > 0.0);
+            },
+            _ => panic!("night wasn't drawn as a rectangle"),
+        }
+        assert_eq!(commands[1].layer, Layer::Overlay);
+    }
+
+    #[test]
+    fn nameplates_fade_with_distance() {
+        let mut entities = vec![player_at(0, 0), player_at(300, 0)];
+        entities.push(player_at(1000, 0));
+        for (id, entity) in entities.iter_mut().enumerate() {
+            entity.uid.id = id as sulphate::EntityId;
+        }
+        let view = sulphate::WorldView {
+            time: Default::default(),
+            entities,
+            balances: Default::default(),
+            terrain: Vec::new(),
+            roads: Vec::new(),
+            zones: Vec::new(),
+            pipes: Vec::new(),
+            traffic: Vec::new(),
+            sounds: Vec::new(),
+        };
+        let mut list = DrawList::new();
+        let from = units::Position::default();
+        list.nameplates(&view, Default::default(), 0, from);
+
+        // not our own, and not the one too far off to read
+        let commands = list.commands();
+        assert_eq!(commands.len(), 1);
+        match commands[0].item {
+            Item::Text { ref text, color, .. } => {
+                assert_eq!(text, "player");
+                assert!(color[3] > 0.0 && color[3] < 1.0);
+            },
+            _ => panic!("nameplate wasn't drawn as text"),
+        }
+        assert!(commands[0].position[0] > 200.0);
+    }
+}
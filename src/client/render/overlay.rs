@@ -0,0 +1,245 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use city_internal::entities;
+use city_internal::space;
+use city_internal::space::zones;
+use city_internal::sulphate;
+use city_internal::utilities;
+
+use super::Color;
+
+// see-through, so that whatever is on the tile still shows
+const TINT_ALPHA: f32 = 0.4;
+
+// how many tiles around a tile what is there counts towards its value
+const NEARBY: i64 = 2;
+
+/// Something drawn over the city to show how it is doing, as a tint over
+/// every tile it has something to say about.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Overlay {
+    /// What each tile is zoned for.
+    Zoning,
+    /// Which buildings get the utility and which go without it.
+    Utility(utilities::Utility),
+    /// What each zoned tile is worth.
+    LandValue,
+    /// How slow the traffic is on each road tile.
+    Traffic,
+}
+
+impl Overlay {
+    /// Zoning, every utility's, land value, then the traffic.
+    pub fn all() -> Vec<Overlay> {
+        let mut all = vec![Overlay::Zoning];
+        for &utility in &utilities::Utility::all() {
+            all.push(Overlay::Utility(utility));
+        }
+        all.push(Overlay::LandValue);
+        all.push(Overlay::Traffic);
+        all
+    }
+
+    pub fn name(self: Self) -> &'static str {
+        match self {
+            Overlay::Zoning => "zoning",
+            Overlay::Utility(utility) => utility.name(),
+            Overlay::LandValue => "value",
+            Overlay::Traffic => "traffic",
+        }
+    }
+
+    /// The tint of every tile in `view` that the overlay has something to
+    /// say about, in no particular order.
+    pub fn tints(
+        self: Self,
+        view: &sulphate::WorldView,
+    ) -> Vec<(space::TileId, Color)> {
+        match self {
+            Overlay::Zoning => view.zones.iter().map(|&(id, zone)| {
+                let mut color = super::zone_color(Some(zone));
+                color[3] = TINT_ALPHA;
+                (id, color)
+            }).collect(),
+            Overlay::Utility(utility) => {
+                buildings(view).into_iter().map(|(id, coverage)| {
+                    let got = utility.covered(coverage);
+                    (id, scale(if got { 1.0 } else { 0.0 }))
+                }).collect()
+            },
+            Overlay::LandValue => {
+                let land = Land::of_view(view);
+                view.zones.iter().map(|&(id, _)| {
+                    (id, scale(land.value(id) as f32 / 100.0))
+                }).collect()
+            },
+            Overlay::Traffic => jams(view).into_iter().map(|(id, jam)| {
+                (id, scale(1.0 - jam as f32 / 300.0))
+            }).collect(),
+        }
+    }
+}
+
+// from red for the worst to green for the best, where `score` is between
+// 0 and 1
+fn scale(score: f32) -> Color {
+    let score = score.max(0.0).min(1.0);
+    [1.0 - score, score, 0.1, TINT_ALPHA]
+}
+
+// the tile and coverage of every building in `view`
+fn buildings(
+    view: &sulphate::WorldView,
+) -> Vec<(space::TileId, entities::building::Coverage)> {
+    view.entities.iter().filter_map(|entity| match entity.image {
+        entities::Image::Building(ref image) => {
+            Some((space::tile_id(entity.position), image.coverage))
+        },
+        _ => None,
+    }).collect()
+}
+
+// how much slower than it could be the traffic on each road tile is, out
+// of 300, going by the worst stretch of road from it
+fn jams(view: &sulphate::WorldView) -> BTreeMap<space::TileId, i64> {
+    let mut jams = BTreeMap::new();
+    for &((a, b), slowness) in &view.traffic {
+        let jam = (slowness - 100).max(0);
+        for &id in &[a, b] {
+            let worst = jams.entry(id).or_insert(0);
+            *worst = jam.max(*worst);
+        }
+    }
+    jams
+}
+
+// what is around each tile, as far as its value goes
+struct Land {
+    roads: BTreeSet<space::TileId>,
+    water: BTreeSet<space::TileId>,
+    // buildings that get every utility
+    served: BTreeSet<space::TileId>,
+    industry: BTreeSet<space::TileId>,
+    jams: BTreeMap<space::TileId, i64>,
+}
+
+impl Land {
+    fn of_view(view: &sulphate::WorldView) -> Land {
+        let mut roads = BTreeSet::new();
+        for &(a, b) in &view.roads {
+            roads.insert(a);
+            roads.insert(b);
+        }
+        let water = view.terrain
+            .iter()
+            .filter(|&&(_, tile)| tile == space::Tile::Water)
+            .map(|&(id, _)| id)
+            .collect();
+        let all = utilities::Utility::all();
+        let served = buildings(view)
+            .into_iter()
+            .filter(|&(_, coverage)| {
+                all.iter().all(|utility| utility.covered(coverage))
+            })
+            .map(|(id, _)| id)
+            .collect();
+        let industry = view.zones
+            .iter()
+            .filter(|&&(_, zone)| zone == zones::Zone::Industrial)
+            .map(|&(id, _)| id)
+            .collect();
+        let jams = jams(view);
+        Land { roads, water, served, industry, jams }
+    }
+
+    // out of 100. a road beside it, water nearby and buildings nearby that
+    // get every utility add to it, while industry nearby and jams on the
+    // roads nearby take away from it
+    fn value(self: &Self, id: space::TileId) -> i64 {
+        let mut value = 40;
+        let by_road = self.roads.contains(&id)
+            || space::roads::beside(id)
+                   .iter()
+                   .any(|next| self.roads.contains(next));
+        if by_road {
+            value += 20;
+        }
+        let (mut water, mut served, mut industry, mut jam) = (0, 0, 0, 0);
+        for x in id.0 - NEARBY..id.0 + NEARBY + 1 {
+            for y in id.1 - NEARBY..id.1 + NEARBY + 1 {
+                let near = (x, y);
+                if self.water.contains(&near) {
+                    water += 1;
+                }
+                if self.served.contains(&near) {
+                    served += 1;
+                }
+                if near != id && self.industry.contains(&near) {
+                    industry += 1;
+                }
+                if let Some(&each) = self.jams.get(&near) {
+                    jam = jam.max(each);
+                }
+            }
+        }
+        value += (10 * water).min(20);
+        value += (10 * served).min(30);
+        value -= (10 * industry).min(30);
+        value -= jam / 10;
+        value.max(0).min(100)
+    }
+}
+
+#[cfg(test)]
+mod test_overlay {
+    use super::*;
+
+    fn view() -> sulphate::WorldView {
+        sulphate::WorldView {
+            time: Default::default(),
+            entities: Vec::new(),
+            balances: Default::default(),
+            terrain: Vec::new(),
+            roads: Vec::new(),
+            zones: Vec::new(),
+            pipes: Vec::new(),
+            traffic: Vec::new(),
+            sounds: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn roads_raise_land_value_and_industry_lowers_it() {
+        let mut view = view();
+        view.roads = vec![((0, 1), (1, 1))];
+        view.zones = vec![
+            ((0, 0), zones::Zone::Residential),
+            ((10, 0), zones::Zone::Residential),
+            ((20, 0), zones::Zone::Residential),
+            ((21, 0), zones::Zone::Industrial),
+            ((22, 0), zones::Zone::Industrial),
+        ];
+        let land = Land::of_view(&view);
+        assert_eq!(land.value((0, 0)), 60);
+        assert_eq!(land.value((10, 0)), 40);
+        assert_eq!(land.value((20, 0)), 20);
+
+        let tints = Overlay::LandValue.tints(&view);
+        assert_eq!(tints.len(), view.zones.len());
+        assert_eq!(tints[0], ((0, 0), scale(0.6)));
+    }
+
+    #[test]
+    fn jams_tint_their_road_tiles() {
+        let mut view = view();
+        view.traffic = vec![(((0, 0), (1, 0)), 100), (((0, 0), (0, 1)), 400)];
+        let tints = Overlay::Traffic.tints(&view);
+        // the worst stretch from a tile is what counts
+        assert_eq!(tints, vec![
+            ((0, 0), scale(0.0)),
+            ((0, 1), scale(0.0)),
+            ((1, 0), scale(1.0)),
+        ]);
+    }
+}
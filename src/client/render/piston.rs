@@ -0,0 +1,189 @@
+use piston_window as app;
+use piston_window::Transformed;
+
+use city_internal::entities::item;
+use city_internal::space;
+
+use client::assets;
+use client::render;
+use client::render::animation;
+
+/// Carries out a draw list with piston's 2d graphics.
+pub struct Backend<'a> {
+    /// Transform for drawing in world coordinates, e.g. from the camera.
+    pub world: app::math::Matrix2d,
+    /// Transform for drawing in pixels.
+    pub screen: app::math::Matrix2d,
+    /// Text is skipped if there is no font to draw it with.
+    pub glyphs: Option<&'a mut app::Glyphs>,
+    /// Sprites without art, or without any assets, are drawn as shapes.
+    pub assets: Option<&'a assets::Assets>,
+}
+
+impl<'a> Backend<'a> {
+    pub fn execute(
+        self: &mut Self,
+        list: &render::DrawList,
+        context: &app::Context,
+        graphics: &mut app::G2d,
+    ) {
+        for command in list.commands() {
+            let base = match command.layer {
+                render::Layer::Overlay => self.screen,
+                _ => self.world,
+            };
+            let trans = base.trans(command.position[0], command.position[1]);
+            match command.item {
+                render::Item::Sprite(sprite) => {
+                    let state = &context.draw_state;
+                    draw_sprite(sprite, self.assets, state, trans, graphics);
+                },
+                render::Item::Shape(ref shape) => {
+                    draw_shape(shape, trans, graphics);
+                },
+                render::Item::Text { ref text, size, color } => {
+                    if let Some(ref mut glyphs) = self.glyphs {
+                        let text_drawer = app::Text::new_color(color, size);
+                        // a glyph that fails to render isn't worth stopping
+                        // the frame for
+                        let _ = text_drawer.draw(
+                            text,
+                            &mut **glyphs,
+                            &context.draw_state,
+                            trans,
+                            graphics,
+                        );
+                    }
+                },
+            }
+        }
+    }
+}
+
+const WHITE: render::Color = [1.0, 1.0, 1.0, 1.0];
+
+fn draw_sprite(
+    sprite: render::Sprite,
+    assets: Option<&assets::Assets>,
+    draw_state: &app::DrawState,
+    trans: app::math::Matrix2d,
+    graphics: &mut app::G2d,
+) {
+    // art is drawn white, so tinting it is what colors it in
+    let (tint, art_trans) = match sprite {
+        render::Sprite::Player { color } => (render::color_of(color), trans),
+        render::Sprite::Building { blackout: true, .. } => {
+            ([0.4, 0.4, 0.5, 1.0], trans)
+        },
+        render::Sprite::Vehicle(heading) => {
+            (WHITE, trans.rot_rad(heading.angle()))
+        },
+        _ => (WHITE, trans),
+    };
+    let drawn = assets.map_or(false, |assets| {
+        assets.draw(sprite, tint, draw_state, art_trans, graphics)
+    });
+    if !drawn {
+        draw_shapes(sprite, trans, graphics);
+    }
+    // flames over whatever is left showing of it
+    if let render::Sprite::Building { burning: true, .. } = sprite {
+        let flames = [0.95, 0.45, 0.1, 0.8];
+        let half = 10.0;
+        let rect = [-half, -half, 2.0 * half, 2.0 * half];
+        app::ellipse(flames, rect, trans, graphics);
+    }
+}
+
+// what a sprite that has no art is drawn as
+fn draw_shapes(
+    sprite: render::Sprite,
+    trans: app::math::Matrix2d,
+    graphics: &mut app::G2d,
+) {
+    match sprite {
+        render::Sprite::Player { color } => {
+            let color = render::color_of(color);
+            let radius = 10.0;
+            let circle = render::Shape::Circle { color, radius };
+            draw_shape(&circle, trans, graphics);
+        },
+        render::Sprite::Citizen { frame } => {
+            // bobbing up and down with each step
+            let bob = match frame.frame {
+                1 => -1.0,
+                3 => 1.0,
+                _ => 0.0,
+            };
+            let color = [0.9, 0.8, 0.3, 1.0];
+            let radius = 8.0;
+            let circle = render::Shape::Circle { color, radius };
+            draw_shape(&circle, trans.trans(0.0, bob), graphics);
+        },
+        render::Sprite::Building { blackout, frame, .. } => {
+            let color = if blackout {
+                [0.2, 0.2, 0.25, 1.0]
+            } else {
+                [0.5, 0.5, 0.6, 1.0]
+            };
+            let half = 15.0;
+            let rect = [-half, -half, 2.0 * half, 2.0 * half];
+            app::rectangle(color, rect, trans, graphics);
+            // a light on the roof, on every other frame while lit
+            if frame.cycle == animation::Cycle::Lit && frame.frame == 0 {
+                let light = [1.0, 0.9, 0.4, 1.0];
+                let rect = [half - 7.0, -half + 3.0, 4.0, 4.0];
+                app::rectangle(light, rect, trans, graphics);
+            }
+        },
+        render::Sprite::Vehicle(heading) => {
+            // drawn facing east, then turned to face the way it is going
+            let color = [0.2, 0.6, 0.9, 1.0];
+            let rect = [-10.0, -6.0, 20.0, 12.0];
+            let trans = trans.rot_rad(heading.angle());
+            app::rectangle(color, rect, trans, graphics);
+        },
+        render::Sprite::Item(kind) => {
+            let color = match kind {
+                Some(item::Kind::Apple) => [0.8, 0.1, 0.1, 1.0],
+                Some(item::Kind::Brick) => [0.6, 0.3, 0.2, 1.0],
+                Some(item::Kind::Coin) => [0.9, 0.8, 0.1, 1.0],
+                None => [0.8, 0.8, 0.8, 1.0],
+            };
+            let rect = [-4.0, -4.0, 8.0, 8.0];
+            app::rectangle(color, rect, trans, graphics);
+        },
+        render::Sprite::Terrain(tile) => {
+            let color = match tile {
+                space::Tile::Water => [0.15, 0.3, 0.6, 1.0],
+                space::Tile::Wall => [0.35, 0.3, 0.25, 1.0],
+                // buildings are drawn over their own tiles
+                space::Tile::Open | space::Tile::Building => return,
+            };
+            let size = space::TILE_SIZE;
+            app::rectangle(color, [0.0, 0.0, size, size], trans, graphics);
+        },
+    }
+}
+
+fn draw_shape(
+    shape: &render::Shape,
+    trans: app::math::Matrix2d,
+    graphics: &mut app::G2d,
+) {
+    match *shape {
+        render::Shape::Circle { color, radius } => {
+            // we could transform but this seems clearer
+            let rect = [-radius, -radius, 2.0 * radius, 2.0 * radius];
+            app::ellipse(color, rect, trans, graphics);
+        },
+        render::Shape::Line { by, width, color } => {
+            let line = [0.0, 0.0, by[0], by[1]];
+            app::line(color, width / 2.0, line, trans, graphics);
+        },
+        render::Shape::Rectangle { size, color } => {
+            let rect = [0.0, 0.0, size[0], size[1]];
+            app::rectangle(color, rect, trans, graphics);
+        },
+    }
+}
@@ -0,0 +1,713 @@
+use std::io;
+use std::mem;
+use std::path;
+use std::sync::mpsc;
+use std::thread;
+
+use city_internal::entities::player;
+use city_internal::mods;
+use city_internal::net as city_net;
+use city_internal::sulphate::server;
+
+use piston_window as app;
+
+use client;
+use client::Game;
+use client::assets;
+use client::locale;
+use client::playback;
+use client::render;
+use client::settings;
+use client::settings_file::SettingsFile;
+use client::ui;
+
+pub fn save_path() -> path::PathBuf {
+    path::PathBuf::from("save.lilc")
+}
+
+/// What the command line asked for.
+pub struct Options {
+    /// Where to let other players join local games, if anywhere.
+    pub host: Option<String>,
+    /// Whether to take admin commands from the terminal.
+    pub admin: bool,
+    /// Where joining a game connects to.
+    pub join: String,
+    /// How the player wants to look, in whichever game they play.
+    pub appearance: player::Appearance,
+    /// Where to write down the player's input, for playing back later.
+    pub record_input: Option<path::PathBuf>,
+    /// Where to play input back from, in place of the player's own.
+    pub play_input: Option<path::PathBuf>,
+    /// Whether to quit once the input played back has run out, so that
+    /// it can be run as a smoke test.
+    pub quit_after_playback: bool,
+}
+
+enum Playing {
+    Local(client::Client),
+    Remote(client::RemoteClient),
+}
+
+impl Playing {
+    fn game(self: &mut Self) -> &mut Game {
+        match *self {
+            Playing::Local(ref mut client) => client as &mut Game,
+            Playing::Remote(ref mut client) => client as &mut Game,
+        }
+    }
+
+    fn tape(self: &mut Self) -> &mut playback::Tape {
+        match *self {
+            Playing::Local(ref mut client) => client.tape(),
+            Playing::Remote(ref mut client) => client.tape(),
+        }
+    }
+}
+
+enum Screen {
+    MainMenu,
+    Connecting(mpsc::Receiver<io::Result<city_net::Connection>>),
+    InGame(Playing),
+    /// With whether the world was already paused before the menu paused
+    /// it, so that resuming leaves it how it was.
+    PauseMenu(Playing, bool),
+    /// Over the menu it was opened from, which it goes back to.
+    Settings(Box<Screen>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Choice {
+    NewGame,
+    Load,
+    Join,
+    Quit,
+    Resume,
+    Save,
+    Leave,
+    Settings,
+    Resolution,
+    Fullscreen,
+    Vsync,
+    UiScale,
+    Back,
+}
+
+impl Choice {
+    // the key of what its button says
+    fn key(self: Self) -> &'static str {
+        match self {
+            Choice::NewGame => "menu.new_game",
+            Choice::Load => "menu.load",
+            Choice::Join => "menu.join",
+            Choice::Quit => "menu.quit",
+            Choice::Resume => "menu.resume",
+            Choice::Save => "menu.save",
+            Choice::Leave => "menu.leave",
+            Choice::Settings => "menu.settings",
+            Choice::Resolution => "settings.resolution",
+            Choice::Fullscreen => "settings.fullscreen",
+            Choice::Vsync => "settings.vsync",
+            Choice::UiScale => "settings.ui_scale",
+            Choice::Back => "menu.back",
+        }
+    }
+}
+
+const BUTTON_SIZE: [f64; 2] = [160.0, 28.0];
+
+// as many digits as always fit in a u64
+const MAX_SEED_DIGITS: usize = 19;
+
+/// Which screen the player is looking at, from the main menu through to
+/// playing, and the menu that pauses it.
+pub struct Screens {
+    screen: Screen,
+    options: Options,
+    ui: ui::Ui,
+    // said under the menu, for when something didn't work
+    notice: Option<String>,
+    // typed on the main menu, for a new game to generate its world from
+    seed: String,
+    // hosting and the admin console only go to the first local game, since
+    // they hold on to the server they were started with
+    served: bool,
+    finished: bool,
+    graphics: settings::Graphics,
+    // whether the graphics have changed in a way that takes a new window
+    new_window: bool,
+}
+
+impl Screens {
+    pub fn new(options: Options) -> Screens {
+        let graphics = settings::Graphics::load_or_default();
+        let mut ui = ui::Ui::new();
+        ui.set_scale(graphics.ui_scale);
+        Screens {
+            screen: Screen::MainMenu,
+            options,
+            ui,
+            notice: None,
+            seed: String::new(),
+            served: false,
+            finished: false,
+            graphics,
+            new_window: false,
+        }
+    }
+
+    /// Whether the player has quit, and the window can close.
+    pub fn is_finished(self: &Self) -> bool {
+        self.finished
+    }
+
+    /// What the window should be made with.
+    pub fn graphics(self: &Self) -> settings::Graphics {
+        self.graphics
+    }
+
+    /// The graphics to make the window again with, if they have changed in
+    /// a way that the window can't just be drawn differently for, since
+    /// this was last asked.
+    pub fn take_new_window(self: &mut Self) -> Option<settings::Graphics> {
+        if self.new_window {
+            self.new_window = false;
+            Some(self.graphics)
+        } else {
+            None
+        }
+    }
+
+    // whatever is being played, if anything is, even behind a menu
+    fn playing(self: &mut Self) -> Option<&mut Playing> {
+        match self.screen {
+            Screen::InGame(ref mut playing)
+            | Screen::PauseMenu(ref mut playing, _) => Some(playing),
+            Screen::Settings(ref mut behind) => match **behind {
+                Screen::PauseMenu(ref mut playing, _) => Some(playing),
+                _ => None,
+            },
+            Screen::MainMenu | Screen::Connecting(_) => None,
+        }
+    }
+
+    // applies `change` to the graphics straight away, and keeps it for
+    // next time
+    fn change_graphics<F>(self: &mut Self, change: F)
+    where
+        F: FnOnce(&mut settings::Graphics),
+    {
+        let mut graphics = self.graphics;
+        change(&mut graphics);
+        if self.graphics.needs_new_window(&graphics) {
+            self.new_window = true;
+        }
+        self.graphics = graphics;
+        self.set_ui_scale(graphics.ui_scale);
+        if let Err(e) = graphics.save() {
+            warn!("Couldn't save the graphics settings: {}", e);
+        }
+    }
+
+    /// Starts connecting to `options.join`, without waiting to see whether
+    /// it works.
+    pub fn join(self: &mut Self) {
+        let addr = self.options.join.clone();
+        let appearance = self.options.appearance.clone();
+        // the server only lets us in with the same packs as it runs
+        let packs = mods::discover(&mods::mods_dir()).packs;
+        let stamps: Vec<_> = packs.iter().map(|pack| pack.stamp()).collect();
+        let (send, attempt) = mpsc::channel();
+        thread::spawn(move || {
+            let connection = city_net::Connection::connect(
+                &addr[..],
+                &appearance,
+                &stamps,
+            );
+            let _ = send.send(connection);
+        });
+        self.screen = Screen::Connecting(attempt);
+    }
+
+    fn play_local(self: &mut Self, client: client::Client) {
+        if !self.served {
+            if let Some(ref addr) = self.options.host {
+                if let Err(e) = client.host(&addr[..]) {
+                    warn!("Failed to host on {}: {}", addr, e);
+                }
+            }
+            if self.options.admin {
+                client.serve_admin();
+            }
+            self.served = true;
+        }
+        let mut playing = Playing::Local(client);
+        playing.game().set_ui_scale(self.graphics.ui_scale);
+        self.start_tape(&mut playing);
+        self.screen = Screen::InGame(playing);
+    }
+
+    // like hosting, recording and playing back only go to the first game,
+    // since input is only worth anything from the start of a game
+    fn start_tape(self: &mut Self, playing: &mut Playing) {
+        if let Some(path) = self.options.record_input.take() {
+            if let Err(e) = playing.tape().record_to(&path) {
+                warn!("Failed to record to {}: {}", path.display(), e);
+            }
+        }
+        if let Some(path) = self.options.play_input.take() {
+            if let Err(e) = playing.tape().play_from(&path) {
+                warn!("Failed to play back {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    // the seed typed in, or None to start in the usual world
+    fn new_seed(self: &Self) -> Option<u64> {
+        self.seed.parse().ok()
+    }
+
+    fn type_seed(self: &mut Self, text: &str) {
+        let room = MAX_SEED_DIGITS - self.seed.len();
+        let digits = text.chars().filter(|c| c.is_digit(10)).take(room);
+        self.seed.extend(digits);
+    }
+
+    fn choices(self: &Self) -> Vec<Choice> {
+        use self::Choice::*;
+        match self.screen {
+            Screen::MainMenu => vec![NewGame, Load, Join, Settings, Quit],
+            Screen::PauseMenu(Playing::Local(_), _) => {
+                vec![Resume, Save, Load, Settings, Leave]
+            },
+            Screen::PauseMenu(Playing::Remote(_), _) => {
+                vec![Resume, Settings, Leave]
+            },
+            Screen::Settings(_) => {
+                vec![Resolution, Fullscreen, Vsync, UiScale, Back]
+            },
+            Screen::Connecting(_) | Screen::InGame(_) => Vec::new(),
+        }
+    }
+
+    fn pause(self: &mut Self) {
+        let screen = mem::replace(&mut self.screen, Screen::MainMenu);
+        self.screen = match screen {
+            Screen::InGame(Playing::Local(mut client)) => {
+                // the host stops the world, but players elsewhere can't
+                let was_paused = client.paused;
+                if !client.crashed {
+                    client.set_paused(true);
+                }
+                Screen::PauseMenu(Playing::Local(client), was_paused)
+            },
+            Screen::InGame(playing) => Screen::PauseMenu(playing, false),
+            other => other,
+        };
+    }
+
+    fn resume(self: &mut Self) {
+        let screen = mem::replace(&mut self.screen, Screen::MainMenu);
+        self.screen = match screen {
+            Screen::PauseMenu(Playing::Local(mut client), was_paused) => {
+                if !client.crashed {
+                    client.set_paused(was_paused);
+                }
+                Screen::InGame(Playing::Local(client))
+            },
+            Screen::PauseMenu(playing, _) => Screen::InGame(playing),
+            other => other,
+        };
+    }
+
+    fn choose(self: &mut Self, choice: Choice) {
+        self.notice = None;
+        match choice {
+            Choice::NewGame => {
+                let appearance = self.options.appearance.clone();
+                let client = client::start_game(appearance, self.new_seed());
+                self.play_local(client);
+            },
+            // the game being played keeps going until the save is known to
+            // load, so that a bad save doesn't lose it
+            Choice::Load => match client::resume_game(&save_path()) {
+                Ok(client) => {
+                    self.screen = Screen::MainMenu;
+                    self.play_local(client);
+                },
+                Err(e) => {
+                    let path = save_path();
+                    let notice = locale::format(
+                        "notice.load_failed",
+                        &[&path.display(), &e],
+                    );
+                    self.notice = Some(notice);
+                },
+            },
+            Choice::Join => self.join(),
+            Choice::Quit => self.finished = true,
+            Choice::Resume => self.resume(),
+            Choice::Save => {
+                if let Screen::PauseMenu(Playing::Local(ref client), _) =
+                    self.screen
+                {
+                    let save = server::Interruption::SaveTo(save_path());
+                    let notice = if client.send_upd.send(save).is_ok() {
+                        let path = save_path();
+                        locale::format("notice.saved", &[&path.display()])
+                    } else {
+                        locale::text("notice.stopped")
+                    };
+                    self.notice = Some(notice);
+                }
+            },
+            // dropping the game stops its server, or leaves the remote one
+            Choice::Leave => self.screen = Screen::MainMenu,
+            Choice::Settings => {
+                let behind = mem::replace(&mut self.screen, Screen::MainMenu);
+                self.screen = Screen::Settings(Box::new(behind));
+            },
+            Choice::Resolution => {
+                self.change_graphics(|graphics| graphics.next_resolution());
+            },
+            Choice::Fullscreen => self.change_graphics(|graphics| {
+                graphics.fullscreen = !graphics.fullscreen;
+            }),
+            Choice::Vsync => self.change_graphics(|graphics| {
+                graphics.vsync = !graphics.vsync;
+            }),
+            Choice::UiScale => {
+                self.change_graphics(|graphics| graphics.next_ui_scale());
+            },
+            Choice::Back => self.close_settings(),
+        }
+    }
+
+    fn close_settings(self: &mut Self) {
+        let screen = mem::replace(&mut self.screen, Screen::MainMenu);
+        self.screen = match screen {
+            Screen::Settings(behind) => *behind,
+            other => other,
+        };
+    }
+
+    // what `choice`'s button says
+    fn label(self: &Self, choice: Choice) -> String {
+        let on_off = |on| {
+            locale::text(if on { "settings.on" } else { "settings.off" })
+        };
+        let graphics = &self.graphics;
+        match choice {
+            Choice::Join => {
+                locale::format(choice.key(), &[&self.options.join])
+            },
+            Choice::Resolution => {
+                let size = graphics.resolution;
+                locale::format(choice.key(), &[&size[0], &size[1]])
+            },
+            Choice::Fullscreen => {
+                locale::format(choice.key(), &[&on_off(graphics.fullscreen)])
+            },
+            Choice::Vsync => {
+                locale::format(choice.key(), &[&on_off(graphics.vsync)])
+            },
+            Choice::UiScale => {
+                let percent = (graphics.ui_scale * 100.0).round();
+                locale::format(choice.key(), &[&percent])
+            },
+            _ => locale::text(choice.key()),
+        }
+    }
+
+    // a column of buttons down the middle of the screen
+    fn menu(
+        self: &mut Self,
+        list: &mut render::DrawList,
+        screen: [f64; 2],
+    ) -> Option<Choice> {
+        let choices = self.choices();
+        self.ui.begin(screen);
+        if choices.is_empty() {
+            self.ui.end();
+            return None;
+        }
+        let gap = 8.0;
+        let height = choices.len() as f64 * (BUTTON_SIZE[1] + gap) + gap;
+        let size = [BUTTON_SIZE[0] + 2.0 * gap, height];
+        let corner = [
+            (screen[0] - size[0]) / 2.0,
+            (screen[1] - size[1]) / 2.0,
+        ];
+
+        let anchor = ui::Anchor::TopLeft;
+        self.ui.panel(list, anchor, corner, size);
+        let mut chosen = None;
+        for (i, &choice) in choices.iter().enumerate() {
+            let offset = [
+                corner[0] + gap,
+                corner[1] + gap + i as f64 * (BUTTON_SIZE[1] + gap),
+            ];
+            let name = self.label(choice);
+            if self.ui.button(list, anchor, offset, BUTTON_SIZE, &name) {
+                chosen = Some(choice);
+            }
+        }
+        if let Some(ref notice) = self.notice {
+            let under = [corner[0], corner[1] + height + 20.0];
+            self.ui.label(list, anchor, under, notice);
+        }
+        self.ui.end();
+        chosen
+    }
+}
+
+fn is_escape(bin: &app::ButtonArgs) -> bool {
+    bin.state == app::ButtonState::Press
+        && bin.button == app::Button::Keyboard(app::Key::Escape)
+}
+
+fn is_backspace(bin: &app::ButtonArgs) -> bool {
+    bin.state == app::ButtonState::Press
+        && bin.button == app::Button::Keyboard(app::Key::Backspace)
+}
+
+impl Game for Screens {
+    fn on_update(self: &mut Self, upd: app::UpdateArgs) {
+        let attempt = match self.screen {
+            Screen::Connecting(ref attempt) => attempt.try_recv(),
+            Screen::InGame(ref mut playing) => {
+                playing.game().on_update(upd);
+                if self.options.quit_after_playback
+                    && playing.tape().is_played_out()
+                {
+                    self.finished = true;
+                }
+                return;
+            },
+            // players elsewhere keep going while this one is in the menu
+            Screen::PauseMenu(ref mut playing, _) => {
+                playing.game().on_update(upd);
+                return;
+            },
+            Screen::Settings(ref mut behind) => {
+                if let Screen::PauseMenu(ref mut playing, _) = **behind {
+                    playing.game().on_update(upd);
+                }
+                return;
+            },
+            Screen::MainMenu => return,
+        };
+        match attempt {
+            Ok(Ok(connection)) => {
+                let client = client::RemoteClient::new(connection);
+                let mut playing = Playing::Remote(client);
+                playing.game().set_ui_scale(self.graphics.ui_scale);
+                self.start_tape(&mut playing);
+                self.screen = Screen::InGame(playing);
+            },
+            Ok(Err(e)) => {
+                let addr = &self.options.join;
+                let notice =
+                    locale::format("notice.connect_failed", &[addr, &e]);
+                self.notice = Some(notice);
+                self.screen = Screen::MainMenu;
+            },
+            Err(mpsc::TryRecvError::Empty) => (),
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.screen = Screen::MainMenu;
+            },
+        }
+    }
+
+    fn on_input(self: &mut Self, bin: app::ButtonArgs) {
+        let escape = is_escape(&bin);
+        if !escape {
+            if let Screen::InGame(ref mut playing) = self.screen {
+                playing.game().on_input(bin);
+                return;
+            }
+        }
+        match self.screen {
+            Screen::InGame(_) => self.pause(),
+            Screen::PauseMenu(..) if escape => self.resume(),
+            Screen::Settings(_) if escape => self.close_settings(),
+            Screen::MainMenu if is_backspace(&bin) => {
+                self.seed.pop();
+            },
+            _ => {
+                self.ui.on_input(bin);
+            },
+        }
+    }
+
+    fn on_controller_axis(self: &mut Self, args: app::ControllerAxisArgs) {
+        if let Screen::InGame(ref mut playing) = self.screen {
+            playing.game().on_controller_axis(args);
+        }
+    }
+
+    fn on_mouse_move(self: &mut Self, mouse: [f64; 2]) {
+        self.ui.on_mouse_move(mouse);
+        match self.screen {
+            Screen::InGame(ref mut playing)
+            | Screen::PauseMenu(ref mut playing, _) => {
+                playing.game().on_mouse_move(mouse);
+            },
+            _ => (),
+        }
+    }
+
+    fn on_mouse_scroll(self: &mut Self, scroll: [f64; 2]) {
+        if let Screen::InGame(ref mut playing) = self.screen {
+            playing.game().on_mouse_scroll(scroll);
+        }
+    }
+
+    fn on_text(self: &mut Self, text: &str) {
+        match self.screen {
+            Screen::InGame(ref mut playing) => playing.game().on_text(text),
+            Screen::MainMenu => self.type_seed(text),
+            _ => (),
+        }
+    }
+
+    fn on_draw(
+        self: &mut Self,
+        context: app::Context,
+        graphics: &mut app::G2d,
+        ren: app::RenderArgs,
+        assets: &assets::Assets,
+    ) {
+        let size = self.ui.screen_of([ren.width as f64, ren.height as f64]);
+        let mut list = render::DrawList::new();
+        match self.screen {
+            Screen::InGame(ref mut playing) => {
+                playing.game().on_draw(context, graphics, ren, assets);
+                return;
+            },
+            Screen::PauseMenu(ref mut playing, _) => {
+                playing.game().on_draw(context, graphics, ren, assets);
+            },
+            Screen::Settings(ref mut behind) => match **behind {
+                Screen::PauseMenu(ref mut playing, _) => {
+                    playing.game().on_draw(context, graphics, ren, assets);
+                },
+                _ => app::clear([0.0, 0.0, 0.0, 1.0], graphics),
+            },
+            Screen::Connecting(_) => {
+                app::clear([0.0, 0.0, 0.0, 1.0], graphics);
+                let join = &self.options.join;
+                let text = locale::format("menu.connecting", &[join]);
+                list.label([size[0] / 2.0 - 80.0, size[1] / 2.0], &text);
+            },
+            Screen::MainMenu => {
+                app::clear([0.0, 0.0, 0.0, 1.0], graphics);
+                let title = locale::text("title");
+                list.label([size[0] / 2.0 - 30.0, 60.0], &title);
+                let seed = match self.new_seed() {
+                    Some(seed) => locale::format("menu.seed", &[&seed]),
+                    None => locale::text("menu.no_seed"),
+                };
+                list.label([size[0] / 2.0 - 80.0, 90.0], &seed);
+            },
+        }
+        let chosen = self.menu(&mut list, size);
+
+        let world = context.transform;
+        let screen = self.ui.transform(context.transform);
+        let mut font = assets.glyphs();
+        let glyphs = font.as_mut();
+        let assets = Some(assets);
+        let mut backend = render::piston::Backend {
+            world, screen, glyphs, assets,
+        };
+        backend.execute(&list, &context, graphics);
+
+        if let Some(choice) = chosen {
+            self.choose(choice);
+        }
+    }
+
+    fn set_ui_scale(self: &mut Self, scale: f64) {
+        self.graphics.ui_scale = scale;
+        self.ui.set_scale(scale);
+        if let Some(playing) = self.playing() {
+            playing.game().set_ui_scale(scale);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_screens {
+    use std::thread;
+    use std::time;
+
+    use piston_window as app;
+
+    use client::Game;
+
+    use super::*;
+
+    fn options() -> Options {
+        Options {
+            host: None,
+            admin: false,
+            // nothing listens on the discard port, so joining is refused
+            join: "127.0.0.1:9".to_string(),
+            appearance: Default::default(),
+            record_input: None,
+            play_input: None,
+            quit_after_playback: false,
+        }
+    }
+
+    #[test]
+    fn failed_joins_go_back_to_the_menu() {
+        let mut screens = Screens::new(options());
+        assert_eq!(screens.choices()[0], Choice::NewGame);
+
+        screens.join();
+        assert!(screens.choices().is_empty());
+        for _ in 0..100 {
+            screens.on_update(app::UpdateArgs { dt: 0.01 });
+            if let Screen::MainMenu = screens.screen {
+                break;
+            }
+            thread::sleep(time::Duration::from_millis(10));
+        }
+        assert_eq!(screens.choices()[0], Choice::NewGame);
+        assert!(screens.notice.is_some());
+    }
+
+    #[test]
+    fn seeds_are_typed_on_the_main_menu() {
+        let mut screens = Screens::new(options());
+        assert_eq!(screens.new_seed(), None);
+
+        screens.on_text("4x2");
+        assert_eq!(screens.new_seed(), Some(42));
+        let button = app::Button::Keyboard(app::Key::Backspace);
+        let state = app::ButtonState::Press;
+        screens.on_input(app::ButtonArgs { state, button, scancode: None });
+        assert_eq!(screens.new_seed(), Some(4));
+
+        screens.on_text(&"9".repeat(30));
+        assert_eq!(screens.seed.len(), MAX_SEED_DIGITS);
+        assert!(screens.new_seed().is_some());
+    }
+
+    #[test]
+    fn settings_go_back_to_the_menu_they_were_opened_from() {
+        let mut screens = Screens::new(options());
+        screens.choose(Choice::Settings);
+        assert_eq!(screens.choices()[0], Choice::Resolution);
+        let label = screens.label(Choice::Fullscreen);
+        assert!(label.starts_with("fullscreen"));
+
+        let button = app::Button::Keyboard(app::Key::Escape);
+        let state = app::ButtonState::Press;
+        screens.on_input(app::ButtonArgs { state, button, scancode: None });
+        assert_eq!(screens.choices()[0], Choice::NewGame);
+        assert!(screens.take_new_window().is_none());
+    }
+}
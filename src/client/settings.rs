@@ -0,0 +1,174 @@
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path;
+
+use client::settings_file::SettingsFile;
+
+/// The window sizes the settings screen steps through.
+pub const RESOLUTIONS: [[u32; 2]; 5] = [
+    [600, 600],
+    [800, 600],
+    [1024, 768],
+    [1280, 720],
+    [1920, 1080],
+];
+
+/// How much bigger the UI can be drawn, stepped through in turn.
+pub const UI_SCALES: [f64; 4] = [1.0, 1.25, 1.5, 2.0];
+
+/// How the window is made, and how big the UI is drawn in it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Graphics {
+    pub resolution: [u32; 2],
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// How many pixels each pixel of the UI covers.
+    pub ui_scale: f64,
+}
+
+impl Default for Graphics {
+    fn default() -> Graphics {
+        Graphics {
+            resolution: RESOLUTIONS[0],
+            fullscreen: false,
+            vsync: true,
+            ui_scale: 1.0,
+        }
+    }
+}
+
+fn invalid(line: &str) -> io::Error {
+    let msg = format!("malformed graphics setting: {}", line);
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+// whatever comes after the first of `values` that `current` is, going
+// back to the start after the last
+fn next_of<T: Copy + PartialEq>(values: &[T], current: T) -> T {
+    let at = values.iter().position(|&value| value == current);
+    match at {
+        Some(at) => values[(at + 1) % values.len()],
+        None => values[0],
+    }
+}
+
+impl SettingsFile for Graphics {
+    // kept beside the key bindings
+    fn path() -> path::PathBuf {
+        path::PathBuf::from("graphics.toml")
+    }
+
+    fn called() -> &'static str {
+        "graphics"
+    }
+
+    /// Reads settings written as `write` writes them, leaving any that
+    /// aren't mentioned as they are by default.
+    fn read(input: &mut io::BufRead) -> io::Result<Graphics> {
+        let mut graphics = Graphics::default();
+        for line in input.lines() {
+            let line = line?;
+            let trimmed = line.trim();
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            let mut halves = trimmed.splitn(2, '=');
+            let name = halves.next().unwrap_or("").trim();
+            let value = halves.next().map_or("", str::trim);
+            match name {
+                "resolution" => {
+                    let sizes: Vec<u32> = value
+                        .split('x')
+                        .filter_map(|size| size.trim().parse().ok())
+                        .collect();
+                    if sizes.len() != 2 || sizes[0] == 0 || sizes[1] == 0 {
+                        return Err(invalid(&line));
+                    }
+                    graphics.resolution = [sizes[0], sizes[1]];
+                },
+                "fullscreen" => {
+                    graphics.fullscreen = value
+                        .parse()
+                        .map_err(|_| invalid(&line))?;
+                },
+                "vsync" => {
+                    graphics.vsync = value
+                        .parse()
+                        .map_err(|_| invalid(&line))?;
+                },
+                "ui_scale" => {
+                    let scale: f64 =
+                        value.parse().map_err(|_| invalid(&line))?;
+                    graphics.ui_scale = scale.max(0.5).min(4.0);
+                },
+                _ => return Err(invalid(&line)),
+            }
+        }
+        Ok(graphics)
+    }
+
+    fn write(self: &Self, out: &mut io::Write) -> io::Result<()> {
+        let size = self.resolution;
+        writeln!(out, "resolution = {}x{}", size[0], size[1])?;
+        writeln!(out, "fullscreen = {}", self.fullscreen)?;
+        writeln!(out, "vsync = {}", self.vsync)?;
+        writeln!(out, "ui_scale = {}", self.ui_scale)
+    }
+}
+
+impl Graphics {
+    /// The next bigger resolution, back to the smallest after the biggest.
+    pub fn next_resolution(self: &mut Self) {
+        self.resolution = next_of(&RESOLUTIONS, self.resolution);
+    }
+
+    /// The next bigger UI scale, back to the smallest after the biggest.
+    pub fn next_ui_scale(self: &mut Self) {
+        self.ui_scale = next_of(&UI_SCALES, self.ui_scale);
+    }
+
+    /// Whether going from `self` to `other` takes a new window, rather than
+    /// just drawing the same window differently.
+    pub fn needs_new_window(self: &Self, other: &Graphics) -> bool {
+        self.resolution != other.resolution
+            || self.fullscreen != other.fullscreen
+            || self.vsync != other.vsync
+    }
+}
+
+#[cfg(test)]
+mod test_settings {
+    use super::*;
+
+    #[test]
+    fn settings_read_back_as_they_were_written() {
+        let graphics = Graphics {
+            resolution: [1280, 720],
+            fullscreen: true,
+            vsync: false,
+            ui_scale: 1.5,
+        };
+        let mut out = Vec::new();
+        graphics.write(&mut out).unwrap();
+        assert_eq!(Graphics::read(&mut &out[..]).unwrap(), graphics);
+        assert!(Graphics::read(&mut &b"resolution = 800\n"[..]).is_err());
+    }
+
+    #[test]
+    fn only_the_window_itself_takes_a_new_window() {
+        let before = Graphics::default();
+        let mut after = before;
+        after.next_ui_scale();
+        assert_eq!(after.ui_scale, 1.25);
+        assert!(!before.needs_new_window(&after));
+        after.next_resolution();
+        assert_eq!(after.resolution, [800, 600]);
+        assert!(before.needs_new_window(&after));
+
+        // a resolution that isn't one of the steps starts them over
+        after.resolution = [640, 480];
+        after.next_resolution();
+        assert_eq!(after.resolution, RESOLUTIONS[0]);
+    }
+}
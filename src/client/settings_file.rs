@@ -0,0 +1,44 @@
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path;
+
+/// Settings that the client keeps in a file of their own beside it, written
+/// as lines of `name = value` in the manner of TOML.
+pub trait SettingsFile: Default {
+    /// The file these settings are kept in.
+    fn path() -> path::PathBuf;
+
+    /// What these settings are called, for saying they couldn't be loaded.
+    fn called() -> &'static str;
+
+    fn read(input: &mut io::BufRead) -> io::Result<Self>;
+
+    fn write(self: &Self, out: &mut io::Write) -> io::Result<()>;
+
+    fn load() -> io::Result<Self> {
+        let mut file = io::BufReader::new(fs::File::open(Self::path())?);
+        Self::read(&mut file)
+    }
+
+    fn save(self: &Self) -> io::Result<()> {
+        let mut file = io::BufWriter::new(fs::File::create(Self::path())?);
+        self.write(&mut file)?;
+        file.flush()
+    }
+
+    /// The saved settings, or the defaults if there aren't any yet.
+    fn load_or_default() -> Self {
+        match Self::load() {
+            Ok(settings) => settings,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                Default::default()
+            },
+            Err(e) => {
+                warn!("Using default {}, couldn't load them: {}",
+                      Self::called(), e);
+                Default::default()
+            },
+        }
+    }
+}
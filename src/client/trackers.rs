@@ -2,10 +2,15 @@ use std::slice;
 
 use city_internal::space;
 use city_internal::sulphate;
+use city_internal::units;
+
+use client::locale;
 
 pub struct Perception {
     player: sulphate::EntityId,
     others: Vec<space::Image>,
+    // how the player last saw itself
+    own: Option<space::Image>,
 }
 
 impl Perception {
@@ -13,7 +18,11 @@ impl Perception {
         self: &mut Self,
         before: Option<space::Image>,
         after: Option<space::Image>,
+        own: bool,
     ) {
+        if own {
+            self.own = after.clone();
+        }
         if let Some(before) = before {
             let mut it = None;
             for (i, each) in self.others.iter().enumerate() {
@@ -23,7 +32,7 @@ impl Perception {
                 }
             }
             if it.is_none() {
-                println!("Unknown entity updated");
+                warn!("Unknown entity updated");
                 return;
             }
             let it = it.unwrap();
@@ -41,12 +50,20 @@ impl Perception {
 
     pub fn new(player: sulphate::EntityId) -> Self {
         let others = Vec::new();
-        Perception { player, others }
+        let own = None;
+        Perception { player, others, own }
     }
 
     pub fn player_id(self: &Self) -> sulphate::EntityId {
         self.player
     }
+
+    pub fn own_position(
+        self: &Self,
+        now: units::Time,
+    ) -> Option<units::Position> {
+        self.own.as_ref().map(|image| image.body.position(now))
+    }
 }
 
 
@@ -57,3 +74,110 @@ impl<'a> IntoIterator for &'a Perception {
         self.others.iter()
     }
 }
+
+
+// how long a cast stays on screen
+fn cast_duration() -> units::Duration {
+    units::moments(8)
+}
+
+/// Where the player has recently cast, so that it can be shown.
+pub struct Casts {
+    recent: Vec<(units::Time, units::Position)>,
+}
+
+impl Casts {
+    pub fn new() -> Self {
+        let recent = Vec::new();
+        Casts { recent }
+    }
+
+    pub fn push(self: &mut Self, when: units::Time, target: units::Position) {
+        self.recent.push((when, target));
+    }
+
+    /// Forgets casts that have been shown for long enough, and gives the
+    /// rest.
+    pub fn visible(
+        self: &mut Self,
+        now: units::Time,
+    ) -> &[(units::Time, units::Position)] {
+        self.recent.retain(|&(when, _)| now - when < cast_duration());
+        &self.recent
+    }
+}
+
+// seconds that a change in money floats up beside the ledger for, and how
+// many pixels it rises meanwhile
+const FLOAT_SECONDS: f64 = 2.0;
+const FLOAT_HEIGHT: f64 = 30.0;
+
+/// How the city's money has changed lately, so that each change can float
+/// up beside the ledger for a while.
+pub struct MoneyChanges {
+    last: Option<i64>,
+    // each change, and how many seconds it has been floating
+    floating: Vec<(i64, f64)>,
+}
+
+impl MoneyChanges {
+    pub fn new() -> Self {
+        let floating = Vec::new();
+        MoneyChanges { last: None, floating }
+    }
+
+    /// Notes how much money the city has now, floating however much that
+    /// is different from last time.
+    pub fn observe(self: &mut Self, money: i64) {
+        if let Some(last) = self.last {
+            if money != last {
+                self.floating.push((money - last, 0.0));
+            }
+        }
+        self.last = Some(money);
+    }
+
+    /// Floats everything on by `dt` seconds, forgetting the changes that
+    /// have been shown for long enough.
+    pub fn update(self: &mut Self, dt: f64) {
+        for change in &mut self.floating {
+            change.1 += dt;
+        }
+        self.floating.retain(|&(_, age)| age < FLOAT_SECONDS);
+    }
+
+    /// Each change as it should read, such as "-$500", with how many
+    /// pixels it has risen so far, oldest first.
+    pub fn visible(self: &Self) -> Vec<(String, f64)> {
+        self.floating.iter().map(|&(change, age)| {
+            let sign = if change < 0 { "-" } else { "+" };
+            let text =
+                locale::format("money.change", &[&sign, &change.abs()]);
+            (text, FLOAT_HEIGHT * age / FLOAT_SECONDS)
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod test_trackers {
+    use super::*;
+
+    #[test]
+    fn money_changes_float_away() {
+        let mut changes = MoneyChanges::new();
+        // the first look at the books isn't a change
+        changes.observe(1000);
+        assert!(changes.visible().is_empty());
+        changes.observe(500);
+        changes.update(1.0);
+        changes.observe(512);
+        assert_eq!(changes.visible(), vec![
+            ("-$500".to_string(), FLOAT_HEIGHT / 2.0),
+            ("+$12".to_string(), 0.0),
+        ]);
+        changes.update(1.5);
+        assert_eq!(changes.visible().len(), 1);
+        changes.update(1.0);
+        assert!(changes.visible().is_empty());
+    }
+}
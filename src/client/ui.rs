@@ -0,0 +1,427 @@
+use piston_window as app;
+use piston_window::Transformed;
+
+use city_internal::entities::item;
+
+use client::bindings::Action;
+use client::locale;
+use client::render;
+
+/// Which corner of the screen a widget is placed from, with offsets
+/// measured inwards from it.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+/// A box on the screen, in pixels.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rect {
+    pub position: [f64; 2],
+    pub size: [f64; 2],
+}
+
+impl Rect {
+    /// The box of `size` that is `offset` in from the `anchor` corner of a
+    /// screen of `screen` pixels.
+    pub fn anchored(
+        anchor: Anchor,
+        offset: [f64; 2],
+        size: [f64; 2],
+        screen: [f64; 2],
+    ) -> Rect {
+        let x = match anchor {
+            Anchor::TopLeft | Anchor::BottomLeft => offset[0],
+            _ => screen[0] - offset[0] - size[0],
+        };
+        let y = match anchor {
+            Anchor::TopLeft | Anchor::TopRight => offset[1],
+            _ => screen[1] - offset[1] - size[1],
+        };
+        Rect { position: [x, y], size }
+    }
+
+    pub fn contains(self: &Self, point: [f64; 2]) -> bool {
+        let (x, y) = (self.position[0], self.position[1]);
+        let (w, h) = (self.size[0], self.size[1]);
+        point[0] >= x && point[0] < x + w && point[1] >= y && point[1] < y + h
+    }
+}
+
+const PANEL_COLOR: render::Color = [0.1, 0.1, 0.1, 0.7];
+const BUTTON_COLOR: render::Color = [0.3, 0.3, 0.3, 0.9];
+const HOVER_COLOR: render::Color = [0.45, 0.45, 0.45, 0.9];
+
+/// Widgets that are laid out afresh every frame, drawing as they go and
+/// answering whether they were clicked.
+///
+/// A click is taken from the widgets drawn the frame before, since those
+/// are the ones that were on screen when it happened, and is handed to
+/// whichever widget in the next frame is under it.
+pub struct Ui {
+    screen: [f64; 2],
+    // how many pixels of the window each pixel of the UI covers
+    scale: f64,
+    cursor: [f64; 2],
+    // where the last frame's widgets were, and this one's so far
+    shown: Vec<Rect>,
+    drawing: Vec<Rect>,
+    // a click on a widget that hasn't been handed out yet
+    click: Option<[f64; 2]>,
+    // whether the mouse went down over a widget, so that letting go of it
+    // is the UI's business too
+    holding: bool,
+}
+
+impl Ui {
+    pub fn new() -> Ui {
+        Ui {
+            screen: [0.0, 0.0],
+            scale: 1.0,
+            cursor: [0.0, 0.0],
+            shown: Vec::new(),
+            drawing: Vec::new(),
+            click: None,
+            holding: false,
+        }
+    }
+
+    /// Draws the UI `scale` times bigger, which is up to whatever carries
+    /// out the draw list, given `transform` to do it with.
+    pub fn set_scale(self: &mut Self, scale: f64) {
+        self.scale = scale;
+    }
+
+    pub fn transform(
+        self: &Self,
+        window: app::math::Matrix2d,
+    ) -> app::math::Matrix2d {
+        window.scale(self.scale, self.scale)
+    }
+
+    /// How big a window of `window` pixels is in the UI's own pixels.
+    pub fn screen_of(self: &Self, window: [f64; 2]) -> [f64; 2] {
+        [window[0] / self.scale, window[1] / self.scale]
+    }
+
+    /// With `mouse` in the window's pixels.
+    pub fn on_mouse_move(self: &mut Self, mouse: [f64; 2]) {
+        self.cursor = [mouse[0] / self.scale, mouse[1] / self.scale];
+    }
+
+    fn over_widget(self: &Self) -> bool {
+        self.shown.iter().any(|rect| rect.contains(self.cursor))
+    }
+
+    /// Whether the UI took `bin` for itself, in which case nothing else
+    /// should do anything with it.
+    pub fn on_input(self: &mut Self, bin: app::ButtonArgs) -> bool {
+        if let app::Button::Mouse(_) = bin.button {
+            match bin.state {
+                app::ButtonState::Press if self.over_widget() => {
+                    self.click = Some(self.cursor);
+                    self.holding = true;
+                    true
+                },
+                app::ButtonState::Release if self.holding => {
+                    self.holding = false;
+                    true
+                },
+                _ => false,
+            }
+        } else {
+            false
+        }
+    }
+
+    /// Starts laying out a frame on a screen of `screen` pixels.
+    pub fn begin(self: &mut Self, screen: [f64; 2]) {
+        self.screen = screen;
+        self.drawing.clear();
+    }
+
+    /// Finishes the frame, keeping where everything went for the clicks to
+    /// come, and dropping any click that nothing was drawn under.
+    pub fn end(self: &mut Self) {
+        ::std::mem::swap(&mut self.shown, &mut self.drawing);
+        self.click = None;
+    }
+
+    fn place(
+        self: &mut Self,
+        anchor: Anchor,
+        offset: [f64; 2],
+        size: [f64; 2],
+    ) -> Rect {
+        let rect = Rect::anchored(anchor, offset, size, self.screen);
+        self.drawing.push(rect);
+        rect
+    }
+
+    /// A backdrop for other widgets to go on, which keeps clicks from
+    /// reaching the world behind it.
+    pub fn panel(
+        self: &mut Self,
+        list: &mut render::DrawList,
+        anchor: Anchor,
+        offset: [f64; 2],
+        size: [f64; 2],
+    ) -> Rect {
+        let rect = self.place(anchor, offset, size);
+        let shape = render::Shape::Rectangle { size, color: PANEL_COLOR };
+        let item = render::Item::Shape(shape);
+        list.push(render::Layer::Overlay, rect.position, item);
+        rect
+    }
+
+    /// A panel that also gives wherever on it was clicked since the last
+    /// frame, if anywhere was.
+    pub fn clickable_panel(
+        self: &mut Self,
+        list: &mut render::DrawList,
+        anchor: Anchor,
+        offset: [f64; 2],
+        size: [f64; 2],
+    ) -> (Rect, Option<[f64; 2]>) {
+        let rect = self.panel(list, anchor, offset, size);
+        match self.click {
+            Some(click) if rect.contains(click) => {
+                self.click = None;
+                (rect, Some(click))
+            },
+            _ => (rect, None),
+        }
+    }
+
+    pub fn label(
+        self: &mut Self,
+        list: &mut render::DrawList,
+        anchor: Anchor,
+        offset: [f64; 2],
+        text: &str,
+    ) {
+        // just the baseline, since text can't be measured without a font
+        let rect = Rect::anchored(anchor, offset, [0.0, 0.0], self.screen);
+        list.label(rect.position, text);
+    }
+
+    /// Whether the button was clicked since the last frame.
+    pub fn button(
+        self: &mut Self,
+        list: &mut render::DrawList,
+        anchor: Anchor,
+        offset: [f64; 2],
+        size: [f64; 2],
+        text: &str,
+    ) -> bool {
+        let rect = self.place(anchor, offset, size);
+        let color = if rect.contains(self.cursor) {
+            HOVER_COLOR
+        } else {
+            BUTTON_COLOR
+        };
+        let shape = render::Shape::Rectangle { size, color };
+        let item = render::Item::Shape(shape);
+        list.push(render::Layer::Overlay, rect.position, item);
+        let baseline = [
+            rect.position[0] + 6.0,
+            rect.position[1] + size[1] - 6.0,
+        ];
+        list.label(baseline, text);
+
+        match self.click {
+            Some(click) if rect.contains(click) => {
+                self.click = None;
+                true
+            },
+            _ => false,
+        }
+    }
+}
+
+const TOOL_SIZE: [f64; 2] = [64.0, 24.0];
+
+/// A row of buttons along the top right of the screen, one for each of
+/// `tools`, giving the action of whichever was clicked.
+pub fn toolbar(
+    ui: &mut Ui,
+    list: &mut render::DrawList,
+    tools: &[Action],
+) -> Option<Action> {
+    let gap = 4.0;
+    let width = tools.len() as f64 * (TOOL_SIZE[0] + gap) + gap;
+    let height = TOOL_SIZE[1] + 2.0 * gap;
+    ui.panel(list, Anchor::TopRight, [0.0, 0.0], [width, height]);
+    let mut clicked = None;
+    // laid out from the right, so the first tool is furthest left
+    for (i, &tool) in tools.iter().rev().enumerate() {
+        let offset = [gap + i as f64 * (TOOL_SIZE[0] + gap), gap];
+        let name = locale::name("tool", tool.name());
+        if ui.button(list, Anchor::TopRight, offset, TOOL_SIZE, &name) {
+            clicked = Some(tool);
+        }
+    }
+    clicked
+}
+
+/// A column of buttons on the right of the screen, `offset` in from the
+/// top right corner, one for each of `overlays`, giving whichever was
+/// clicked. The one `shown`, if any, is marked.
+pub fn overlay_bar(
+    ui: &mut Ui,
+    list: &mut render::DrawList,
+    offset: [f64; 2],
+    overlays: &[render::Overlay],
+    shown: Option<render::Overlay>,
+) -> Option<render::Overlay> {
+    let gap = 4.0;
+    let width = TOOL_SIZE[0] + 2.0 * gap;
+    let height = overlays.len() as f64 * (TOOL_SIZE[1] + gap) + gap;
+    ui.panel(list, Anchor::TopRight, offset, [width, height]);
+    let mut clicked = None;
+    for (i, &overlay) in overlays.iter().enumerate() {
+        let at = [
+            offset[0] + gap,
+            offset[1] + gap + i as f64 * (TOOL_SIZE[1] + gap),
+        ];
+        let mut name = locale::name("overlay", overlay.name());
+        if shown == Some(overlay) {
+            name = locale::format("overlay.shown", &[&name]);
+        }
+        if ui.button(list, Anchor::TopRight, at, TOOL_SIZE, &name) {
+            clicked = Some(overlay);
+        }
+    }
+    clicked
+}
+
+const SLOT_SIZE: [f64; 2] = [56.0, 24.0];
+
+/// A row of buttons along the bottom right of the screen, one for each of
+/// `items` that the player is carrying, giving the slot of whichever was
+/// clicked to be dropped.
+pub fn inventory(
+    ui: &mut Ui,
+    list: &mut render::DrawList,
+    items: &[item::Kind],
+) -> Option<usize> {
+    if items.is_empty() {
+        return None;
+    }
+    let gap = 4.0;
+    let width = items.len() as f64 * (SLOT_SIZE[0] + gap) + gap;
+    let height = SLOT_SIZE[1] + 2.0 * gap;
+    ui.panel(list, Anchor::BottomRight, [0.0, 0.0], [width, height]);
+    let mut clicked = None;
+    // laid out from the right, so the first picked up is furthest left
+    for (i, kind) in items.iter().rev().enumerate() {
+        let offset = [gap + i as f64 * (SLOT_SIZE[0] + gap), gap];
+        let name = locale::name("item", kind.name());
+        if ui.button(list, Anchor::BottomRight, offset, SLOT_SIZE, &name) {
+            clicked = Some(items.len() - 1 - i);
+        }
+    }
+    clicked
+}
+
+#[cfg(test)]
+mod test_ui {
+    use piston_window as app;
+
+    use city_internal::entities::item;
+
+    use super::*;
+
+    fn mouse(state: app::ButtonState) -> app::ButtonArgs {
+        let button = app::Button::Mouse(app::MouseButton::Left);
+        app::ButtonArgs { state, button, scancode: None }
+    }
+
+    #[test]
+    fn anchors_measure_in_from_their_corner() {
+        let screen = [200.0, 100.0];
+        let size = [20.0, 10.0];
+        let corner = Anchor::BottomRight;
+        let rect = Rect::anchored(corner, [5.0, 5.0], size, screen);
+        assert_eq!(rect.position, [175.0, 85.0]);
+        assert!(rect.contains([180.0, 90.0]));
+        assert!(!rect.contains([170.0, 90.0]));
+    }
+
+    #[test]
+    fn clicks_on_buttons_are_kept_from_the_world() {
+        let mut ui = Ui::new();
+        let mut list = render::DrawList::new();
+        let size = [50.0, 20.0];
+        let draw = |ui: &mut Ui, list: &mut render::DrawList| {
+            ui.begin([200.0, 200.0]);
+            let clicked =
+                ui.button(list, Anchor::TopLeft, [10.0, 10.0], size, "ok");
+            ui.end();
+            clicked
+        };
+        assert!(!draw(&mut ui, &mut list));
+
+        // off the button, so the world gets it
+        ui.on_mouse_move([100.0, 100.0]);
+        assert!(!ui.on_input(mouse(app::ButtonState::Press)));
+        assert!(!ui.on_input(mouse(app::ButtonState::Release)));
+        assert!(!draw(&mut ui, &mut list));
+
+        ui.on_mouse_move([20.0, 20.0]);
+        assert!(ui.on_input(mouse(app::ButtonState::Press)));
+        assert!(ui.on_input(mouse(app::ButtonState::Release)));
+        assert!(draw(&mut ui, &mut list));
+        // and only the once
+        assert!(!draw(&mut ui, &mut list));
+    }
+
+    #[test]
+    fn inventory_slots_are_counted_from_the_left() {
+        let mut ui = Ui::new();
+        let mut list = render::DrawList::new();
+        let items = [item::Kind::Apple, item::Kind::Brick];
+        let draw = |ui: &mut Ui, list: &mut render::DrawList| {
+            ui.begin([200.0, 100.0]);
+            let clicked = inventory(ui, list, &items);
+            ui.end();
+            clicked
+        };
+        assert_eq!(draw(&mut ui, &mut list), None);
+
+        // the apple's button, two slots in from the right edge
+        ui.on_mouse_move([200.0 - 2.0 * (SLOT_SIZE[0] + 4.0), 80.0]);
+        assert!(ui.on_input(mouse(app::ButtonState::Press)));
+        assert_eq!(draw(&mut ui, &mut list), Some(0));
+
+        // and nothing at all to click with empty hands
+        ui.begin([200.0, 100.0]);
+        assert_eq!(inventory(&mut ui, &mut list, &[]), None);
+        ui.end();
+        assert!(!ui.on_input(mouse(app::ButtonState::Press)));
+    }
+
+    #[test]
+    fn overlays_are_listed_from_the_top() {
+        let mut ui = Ui::new();
+        let mut list = render::DrawList::new();
+        let overlays = render::Overlay::all();
+        let draw = |ui: &mut Ui, list: &mut render::DrawList| {
+            ui.begin([200.0, 200.0]);
+            let clicked =
+                overlay_bar(ui, list, [0.0, 10.0], &overlays, None);
+            ui.end();
+            clicked
+        };
+        assert_eq!(draw(&mut ui, &mut list), None);
+
+        // the second button down
+        let y = 10.0 + 4.0 + TOOL_SIZE[1] + 4.0 + 1.0;
+        ui.on_mouse_move([190.0, y]);
+        assert!(ui.on_input(mouse(app::ButtonState::Press)));
+        assert_eq!(draw(&mut ui, &mut list), Some(overlays[1]));
+    }
+}
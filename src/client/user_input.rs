@@ -1,10 +1,20 @@
 use std::ops;
 
+use city_internal::blueprints;
+use city_internal::entities::building;
+use city_internal::entities::player;
+use city_internal::space::zones;
+use city_internal::units;
+
 use piston_window as app;
 
+use client::bindings;
+use client::bindings::Action;
+use client::camera;
+
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum Dir {
+pub enum Dir {
     Up,
     Down,
     Left,
@@ -42,53 +52,313 @@ impl<T> ops::IndexMut<Dir> for DirPad<T> {
     }
 }
 
-impl<T> DirPad<T>
-    where T: PartialEq
-{
-    fn dir(&self, item: T) -> Option<Dir> {
-        if      item == self.up    { Some( Dir::Up    ) }
-        else if item == self.down  { Some( Dir::Down  ) }
-        else if item == self.left  { Some( Dir::Left  ) }
-        else if item == self.right { Some( Dir::Right ) }
-        else { None }
+
+
+// the direction keys currently held, diagonals are normalized by the player
+fn dirs_direction(dirs: &DirPad<bool>) -> units::Vector {
+    let mut direction: units::Vector = Default::default();
+    let one: units::Scalar = 1.into();
+
+    if dirs.up    { direction.y -= one; }
+    if dirs.down  { direction.y += one; }
+    if dirs.left  { direction.x -= one; }
+    if dirs.right { direction.x += one; }
+
+    direction
+}
+
+
+// the blueprints in order, then back to not building at all
+fn next_blueprint(
+    current: Option<blueprints::Name>,
+    all: &[(blueprints::Name, building::Blueprint)],
+) -> Option<blueprints::Name> {
+    let mut names = all.iter().map(|&(name, _)| name);
+    match current {
+        None => names.next(),
+        Some(current) => names.skip_while(|&name| name != current).nth(1),
+    }
+}
+
+// the zones in order, then taking zoning off, then back to not zoning
+fn next_zone(
+    current: Option<Option<zones::Zone>>,
+) -> Option<Option<zones::Zone>> {
+    let all = zones::Zone::all();
+    match current {
+        None => Some(Some(all[0])),
+        Some(Some(current)) => {
+            let next = all.iter()
+                          .skip_while(|&&zone| zone != current)
+                          .nth(1)
+                          .cloned();
+            Some(next)
+        },
+        Some(None) => None,
     }
 }
 
+fn magnitude(x: units::Scalar) -> units::Scalar {
+    if x < 0 { units::Scalar::from(0) - x } else { x }
+}
 
+// pipes only go in straight lines, so a drag is laid along whichever way
+// it went furthest
+fn straighten(from: units::Position, to: units::Position) -> units::Position {
+    let offset = to - from;
+    if magnitude(offset.x) >= magnitude(offset.y) {
+        units::Position { x: to.x, y: from.y }
+    } else {
+        units::Position { x: from.x, y: to.y }
+    }
+}
 
+#[derive(Clone, PartialEq, Debug)]
 pub enum DeviceUpdate {
     Nop,
     ChangeMovement {
-        dirs: DirPad<bool>
+        // magnitude 1 or more is full speed
+        direction: units::Vector,
+        pace: player::Pace,
+    },
+    Cast {
+        target: units::Position
+    },
+    TogglePause,
+    // multiply the speed of the world by this much
+    ChangeTimeScale {
+        factor: units::Scalar
+    },
+    Pan {
+        dir: Dir,
+        held: bool,
+    },
+    Drag {
+        held: bool,
+    },
+    ToggleFollow,
+    // scroll wheel clicks, positive to zoom in
+    Zoom {
+        steps: f64,
+    },
+    // ask how the player would walk to here, for seeing what the
+    // pathfinder does
+    ShowPath {
+        target: units::Position
+    },
+    // already snapped to the tile that it would stand on
+    PlaceBuilding {
+        blueprint: blueprints::Name,
+        position: units::Position,
+    },
+    // the corners of a rectangle dragged out, and None to take zoning off
+    PaintZone {
+        from: units::Position,
+        to: units::Position,
+        zone: Option<zones::Zone>,
+    },
+    // the ends of a straight run of pipe dragged out
+    LayPipe {
+        from: units::Position,
+        to: units::Position,
     },
+    // the ends of a road dragged out, which turns at most once on the way
+    LayRoad {
+        from: units::Position,
+        to: units::Position,
+    },
+    // the chat box has taken the keyboard until it says otherwise
+    StartChat,
+    ToggleDebug,
+    ToggleBudget,
+    // show the next overlay, after the last one showing nothing
+    CycleOverlay,
+    // use whatever is nearest the player
+    Interact,
+    // put down whatever the player is carrying in this slot, counting from
+    // the first thing picked up
+    DropItem {
+        slot: usize,
+    },
+    // take back the last thing the player built, or build it again
+    Undo,
+    Redo,
 }
 
 pub struct Input {
-    move_controls: DirPad<app::Button>,
+    bindings: bindings::Bindings,
+    // the action that the next button pressed will be bound to
+    capturing: Option<Action>,
+    // whether the bindings have changed since they were last saved
+    rebound: bool,
+    // controller axes read as the x and y of the movement stick
+    stick_axes: [u8; 2],
+    // stick deflections smaller than this are treated as resting
+    dead_zone: f64,
 
     dirs: DirPad<bool>,
-    //cursor_pos: units::Position,
+    stick: [f64; 2],
+    movement: units::Vector,
+    pace: player::Pace,
+    // sprinting is held, and overrides walking, which is toggled
+    sprinting: bool,
+    walking: bool,
+    // kept in pixels, so that it stays under the mouse if the view moves
+    cursor_screen: [f64; 2],
+    // what there is to build, and the kind of building each makes
+    buildable: Vec<(blueprints::Name, building::Blueprint)>,
+    // what clicking will build, if the player is building instead of casting
+    constructing: Option<blueprints::Name>,
+    // what dragging paints, if the player is zoning, with Some(None) for
+    // taking zoning off
+    zoning: Option<Option<zones::Zone>>,
+    // where the drag being painted started
+    zone_start: Option<units::Position>,
+    // whether dragging lays pipe, and where the drag being laid started
+    piping: bool,
+    pipe_start: Option<units::Position>,
+    // and the same for road
+    roading: bool,
+    road_start: Option<units::Position>,
+    // whether buttons are going to the chat box instead
+    typing: bool,
+
+    // how the screen was last drawn, for working out where the cursor is
+    view: camera::View,
 }
 
 impl Input {
     pub fn new() -> Input {
-        let move_controls = DirPad {
-            up:    app::Button::Keyboard(app::Key::W),
-            down:  app::Button::Keyboard(app::Key::S),
-            left:  app::Button::Keyboard(app::Key::A),
-            right: app::Button::Keyboard(app::Key::D),
-        };
+        Input::with_bindings(Default::default())
+    }
 
-        // let fire_button = app::Button::Mouse(app::MouseButton::Left);
+    pub fn with_bindings(bindings: bindings::Bindings) -> Input {
+        let capturing = None;
+        let rebound = false;
+        let stick_axes = [0, 1];
+        let dead_zone = 0.2;
 
         let dirs = Default::default();
-        // let cursor_pos = Default::default();
+        let stick = [0.0, 0.0];
+        let movement = Default::default();
+        let pace = Default::default();
+        let sprinting = false;
+        let walking = false;
+        let cursor_screen = [0.0, 0.0];
+        let buildable = blueprints::Registry::default().buildings();
+        let constructing = None;
+        let zoning = None;
+        let zone_start = None;
+        let piping = false;
+        let pipe_start = None;
+        let roading = false;
+        let road_start = None;
+        let typing = false;
+
+        let view = camera::Camera::new().view();
 
         Input {
-            move_controls,
+            bindings,
+            capturing,
+            rebound,
+            stick_axes,
+            dead_zone,
 
             dirs,
-            // cursor_pos,
+            stick,
+            movement,
+            pace,
+            sprinting,
+            walking,
+            cursor_screen,
+            buildable,
+            constructing,
+            zoning,
+            zone_start,
+            piping,
+            pipe_start,
+            roading,
+            road_start,
+            typing,
+
+            view,
+        }
+    }
+
+    pub fn set_view(&mut self, view: camera::View) {
+        self.view = view;
+    }
+
+    pub fn set_dead_zone(&mut self, dead_zone: f64) {
+        self.dead_zone = dead_zone;
+    }
+
+    pub fn bindings(&self) -> &bindings::Bindings {
+        &self.bindings
+    }
+
+    /// Binds the next button pressed to `action`, instead of doing anything
+    /// else with it.
+    pub fn capture_next(&mut self, action: Action) {
+        self.capturing = Some(action);
+    }
+
+    /// The bindings, if they have changed since this was last called.
+    pub fn take_rebound(&mut self) -> Option<&bindings::Bindings> {
+        if self.rebound {
+            self.rebound = false;
+            Some(&self.bindings)
+        } else {
+            None
+        }
+    }
+
+    pub fn rebind(
+        &mut self,
+        action: Action,
+        button: app::Button,
+    ) -> DeviceUpdate {
+        self.bindings.rebind(action, button);
+        self.rebound = true;
+        // the old button's release will no longer be recognized, and the
+        // button might have been taken from another direction
+        self.let_go()
+    }
+
+    /// Stops moving, as if every direction had been released, for when
+    /// their releases might never come.
+    pub fn let_go(&mut self) -> DeviceUpdate {
+        self.dirs = Default::default();
+        self.sprinting = false;
+        let direction = dirs_direction(&self.dirs);
+        self.change_movement(direction)
+    }
+
+    pub fn is_typing(&self) -> bool {
+        self.typing
+    }
+
+    /// Gives the keyboard back after `StartChat`.
+    pub fn stop_typing(&mut self) {
+        self.typing = false;
+    }
+
+    // short circuit to avoid unnecessary updates/rounding
+    // esp since holding keys create repeated keypresses
+    fn change_movement(&mut self, direction: units::Vector) -> DeviceUpdate {
+        let pace = if self.sprinting {
+            player::Pace::Sprint
+        } else if self.walking {
+            player::Pace::Walk
+        } else {
+            player::Pace::Run
+        };
+        if self.movement != direction || self.pace != pace {
+            self.movement = direction;
+            self.pace = pace;
+            DeviceUpdate::ChangeMovement { direction, pace }
+        } else {
+            DeviceUpdate::Nop
         }
     }
 
@@ -99,29 +369,563 @@ impl Input {
         let app::ButtonArgs { button, state, .. } = bin;
         let butt_pressed = state == app::ButtonState::Press;
 
-        if let Some(dir) = self.move_controls.dir(button) {
-            // short circuit to avoid unnecessary updates/rounding
-            // esp since holding keys create repeated keypresses
-            if self.dirs[dir] != butt_pressed {
-                self.dirs[dir] = butt_pressed;
-                DeviceUpdate::ChangeMovement { dirs: self.dirs.clone() }
-            } else {
-                DeviceUpdate::Nop
+        if butt_pressed {
+            if let Some(action) = self.capturing.take() {
+                return self.rebind(action, button);
             }
-        // } else if butt_pressed && button == self.fire_button {
-        //     DeviceUpdate::Cast { target: self.cursor_pos }
+        }
+        if self.typing {
+            return DeviceUpdate::Nop;
+        }
+
+        match self.bindings.action(button) {
+            Some(action) => self.act(action, butt_pressed),
+            None => DeviceUpdate::Nop,
+        }
+    }
+
+    /// Does what a press of a button bound to `action` would, for things
+    /// like toolbars that stand in for a button, and which have no way of
+    /// letting go of it.
+    pub fn trigger(&mut self, action: Action) -> DeviceUpdate {
+        self.act(action, true)
+    }
+
+    fn act(&mut self, action: Action, butt_pressed: bool) -> DeviceUpdate {
+        match action {
+            Action::Move(dir) => {
+                if self.dirs[dir] != butt_pressed {
+                    self.dirs[dir] = butt_pressed;
+                    let direction = dirs_direction(&self.dirs);
+                    self.change_movement(direction)
+                } else {
+                    DeviceUpdate::Nop
+                }
+            },
+            Action::Pan(dir) => {
+                DeviceUpdate::Pan { dir, held: butt_pressed }
+            },
+            Action::Drag => DeviceUpdate::Drag { held: butt_pressed },
+            Action::Sprint => {
+                self.sprinting = butt_pressed;
+                let direction = self.movement;
+                self.change_movement(direction)
+            },
+            Action::Walk if butt_pressed => {
+                self.walking = !self.walking;
+                let direction = self.movement;
+                self.change_movement(direction)
+            },
+            // zones, pipes and roads are dragged out, so they go on when
+            // let go of
+            Action::Fire if !butt_pressed => {
+                let to = self.cursor();
+                let zone_start = self.zone_start.take();
+                let pipe_start = self.pipe_start.take();
+                let road_start = self.road_start.take();
+                match (self.zoning, zone_start, pipe_start, road_start) {
+                    (Some(zone), Some(from), _, _) => {
+                        DeviceUpdate::PaintZone { from, to, zone }
+                    },
+                    (None, _, Some(from), _) => {
+                        let to = straighten(from, to);
+                        DeviceUpdate::LayPipe { from, to }
+                    },
+                    (None, _, None, Some(from)) => {
+                        DeviceUpdate::LayRoad { from, to }
+                    },
+                    _ => DeviceUpdate::Nop,
+                }
+            },
+            _ if !butt_pressed => DeviceUpdate::Nop,
+            Action::Fire if self.zoning.is_some() => {
+                self.zone_start = Some(self.cursor());
+                DeviceUpdate::Nop
+            },
+            Action::Fire if self.piping => {
+                self.pipe_start = Some(self.cursor());
+                DeviceUpdate::Nop
+            },
+            Action::Fire if self.roading => {
+                self.road_start = Some(self.cursor());
+                DeviceUpdate::Nop
+            },
+            Action::Fire => match self.constructing {
+                Some(blueprint) => {
+                    let position = building::snap(self.cursor());
+                    DeviceUpdate::PlaceBuilding { blueprint, position }
+                },
+                None => DeviceUpdate::Cast { target: self.cursor() },
+            },
+            Action::Pause => DeviceUpdate::TogglePause,
+            Action::Faster => {
+                DeviceUpdate::ChangeTimeScale { factor: 2.into() }
+            },
+            Action::Slower => {
+                let half = units::Scalar::from(1) / 2;
+                DeviceUpdate::ChangeTimeScale { factor: half }
+            },
+            Action::Follow => DeviceUpdate::ToggleFollow,
+            Action::ShowPath => {
+                DeviceUpdate::ShowPath { target: self.cursor() }
+            },
+            Action::Build => {
+                self.constructing =
+                    next_blueprint(self.constructing, &self.buildable);
+                self.zoning = None;
+                self.zone_start = None;
+                self.piping = false;
+                self.pipe_start = None;
+                self.roading = false;
+                self.road_start = None;
+                DeviceUpdate::Nop
+            },
+            Action::Zone => {
+                self.zoning = next_zone(self.zoning);
+                self.zone_start = None;
+                self.constructing = None;
+                self.piping = false;
+                self.pipe_start = None;
+                self.roading = false;
+                self.road_start = None;
+                DeviceUpdate::Nop
+            },
+            Action::Pipe => {
+                self.piping = !self.piping;
+                self.pipe_start = None;
+                self.constructing = None;
+                self.zoning = None;
+                self.zone_start = None;
+                self.roading = false;
+                self.road_start = None;
+                DeviceUpdate::Nop
+            },
+            Action::Road => {
+                self.roading = !self.roading;
+                self.road_start = None;
+                self.constructing = None;
+                self.zoning = None;
+                self.zone_start = None;
+                self.piping = false;
+                self.pipe_start = None;
+                DeviceUpdate::Nop
+            },
+            Action::Overlay => DeviceUpdate::CycleOverlay,
+            Action::Chat => {
+                self.typing = true;
+                DeviceUpdate::StartChat
+            },
+            Action::Debug => DeviceUpdate::ToggleDebug,
+            Action::Budget => DeviceUpdate::ToggleBudget,
+            Action::Interact => DeviceUpdate::Interact,
+            Action::Undo => DeviceUpdate::Undo,
+            Action::Redo => DeviceUpdate::Redo,
+        }
+    }
+
+    pub fn on_controller_axis(
+        &mut self,
+        args: app::ControllerAxisArgs
+    ) -> DeviceUpdate {
+        let app::ControllerAxisArgs { axis, position, .. } = args;
+        if axis == self.stick_axes[0] {
+            self.stick[0] = position;
+        } else if axis == self.stick_axes[1] {
+            self.stick[1] = position;
         } else {
-            DeviceUpdate::Nop
+            return DeviceUpdate::Nop;
         }
+
+        // a resting stick shouldn't stop the keys from moving the player
+        let direction = self.stick_direction()
+                            .unwrap_or_else(|| dirs_direction(&self.dirs));
+        self.change_movement(direction)
+    }
+
+    // the stick's deflection scaled to be at most a unit vector,
+    // or None if the stick is resting in the dead zone
+    fn stick_direction(&self) -> Option<units::Vector> {
+        let x = self.stick[0];
+        let y = self.stick[1];
+        let len = (x * x + y * y).sqrt();
+        if len <= self.dead_zone {
+            return None;
+        }
+        // start from zero at the edge of the dead zone, rather than jumping
+        let throttle = (len - self.dead_zone) / (1.0 - self.dead_zone);
+        let scale = throttle.min(1.0) / len;
+        Some(units::Vector { x: (x * scale).into(), y: (y * scale).into() })
+    }
+
+    /// Has building go through the blueprints in `registry` that make
+    /// buildings, starting again from the first.
+    pub fn set_blueprints(&mut self, registry: &blueprints::Registry) {
+        self.buildable = registry.buildings();
+        self.constructing = None;
     }
 
-    // pub fn on_mouse_move(&mut self, mouse: [f64; 2]) {
-    //     let x = (mouse[0] - 300.0) * units::DOT as f64;
-    //     let y = (mouse[1] - 300.0) * units::DOT as f64;
-    //     self.cursor_pos = units::Vec2 {
-    //         x: x as units::Scalar,
-    //         y: y as units::Scalar,
-    //     };
-    // }
+    /// The blueprint being built, the kind of building it makes, and where
+    /// it would go, for showing a ghost of it under the cursor.
+    pub fn ghost(
+        &self,
+    ) -> Option<(blueprints::Name, building::Blueprint, units::Position)> {
+        let constructing = self.constructing?;
+        let &(name, kind) = self.buildable
+                                .iter()
+                                .find(|&&(name, _)| name == constructing)?;
+        Some((name, kind, building::snap(self.cursor())))
+    }
+
+    /// What is being zoned and the corners of the rectangle it would be
+    /// painted over, which is just the tile under the cursor until the
+    /// player starts dragging.
+    pub fn zone_ghost(
+        &self,
+    ) -> Option<(Option<zones::Zone>, units::Position, units::Position)> {
+        let to = self.cursor();
+        let from = self.zone_start.unwrap_or(to);
+        self.zoning.map(|zone| (zone, from, to))
+    }
+
+    /// The ends of the pipe that would be laid, straightened out the same
+    /// way it will be, and just the tile under the cursor until the player
+    /// starts dragging.
+    pub fn pipe_ghost(&self) -> Option<(units::Position, units::Position)> {
+        if !self.piping {
+            return None;
+        }
+        let cursor = self.cursor();
+        let from = self.pipe_start.unwrap_or(cursor);
+        Some((from, straighten(from, cursor)))
+    }
+
+    /// The ends of the road that would be laid, which turns where
+    /// `roads::corner` says it will, and just the tile under the cursor
+    /// until the player starts dragging.
+    pub fn road_ghost(&self) -> Option<(units::Position, units::Position)> {
+        if !self.roading {
+            return None;
+        }
+        let cursor = self.cursor();
+        Some((self.road_start.unwrap_or(cursor), cursor))
+    }
+
+    /// Where in the world the mouse is pointing.
+    pub fn cursor(&self) -> units::Position {
+        self.view.to_world(self.cursor_screen)
+    }
+
+    pub fn on_mouse_move(&mut self, mouse: [f64; 2]) -> DeviceUpdate {
+        self.cursor_screen = mouse;
+        // nothing happens until the cursor is actually used
+        DeviceUpdate::Nop
+    }
+
+    pub fn on_mouse_scroll(&mut self, scroll: [f64; 2]) -> DeviceUpdate {
+        DeviceUpdate::Zoom { steps: scroll[1] }
+    }
+}
+
+#[cfg(test)]
+mod test_rebind {
+    use piston_window as app;
+
+    use super::*;
+
+    fn key(key: app::Key, state: app::ButtonState) -> app::ButtonArgs {
+        let button = app::Button::Keyboard(key);
+        app::ButtonArgs { state, button, scancode: None }
+    }
+
+    #[test]
+    fn rebind_while_held() {
+        let mut input = Input::new();
+
+        input.interpret(key(app::Key::W, app::ButtonState::Press));
+        assert!(input.dirs.up);
+
+        let new_up = app::Button::Keyboard(app::Key::Up);
+        match input.rebind(Action::Move(Dir::Up), new_up) {
+            DeviceUpdate::ChangeMovement { direction, .. } =>
+                assert_eq!(direction, units::Vector::default()),
+            _ => panic!("rebinding a held direction didn't release it"),
+        }
+        let up = input.bindings().buttons(Action::Move(Dir::Up));
+        assert!(up.contains(&new_up));
+        assert!(!up.contains(&app::Button::Keyboard(app::Key::W)));
+
+        // releasing the old key shouldn't affect anything now
+        match input.interpret(key(app::Key::W, app::ButtonState::Release)) {
+            DeviceUpdate::Nop => (),
+            _ => panic!("old binding still recognized"),
+        }
+        assert!(!input.dirs.up);
+
+        match input.interpret(key(app::Key::Up, app::ButtonState::Press)) {
+            DeviceUpdate::ChangeMovement { direction, .. } =>
+                assert!(direction.y < 0),
+            _ => panic!("new binding not recognized"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_pace {
+    use piston_window as app;
+
+    use city_internal::entities::player::Pace;
+
+    use super::*;
+
+    fn key(key: app::Key, state: app::ButtonState) -> app::ButtonArgs {
+        let button = app::Button::Keyboard(key);
+        app::ButtonArgs { state, button, scancode: None }
+    }
+
+    fn pace_of(update: DeviceUpdate) -> Option<Pace> {
+        match update {
+            DeviceUpdate::ChangeMovement { pace, .. } => Some(pace),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn sprinting_is_held_and_walking_toggled() {
+        use piston_window::ButtonState::*;
+        let mut input = Input::new();
+        let walk = input.interpret(key(app::Key::CapsLock, Press));
+        assert_eq!(pace_of(walk), Some(Pace::Walk));
+        let walk = input.interpret(key(app::Key::CapsLock, Release));
+        assert_eq!(pace_of(walk), None);
+
+        // sprinting wins out while it is held
+        let sprint = input.interpret(key(app::Key::LShift, Press));
+        assert_eq!(pace_of(sprint), Some(Pace::Sprint));
+        let sprint = input.interpret(key(app::Key::LShift, Release));
+        assert_eq!(pace_of(sprint), Some(Pace::Walk));
+
+        let run = input.interpret(key(app::Key::CapsLock, Press));
+        assert_eq!(pace_of(run), Some(Pace::Run));
+    }
+}
+
+#[cfg(test)]
+mod test_stick {
+    use piston_window as app;
+
+    use super::*;
+
+    fn axis(axis: u8, position: f64) -> app::ControllerAxisArgs {
+        app::ControllerAxisArgs { id: 0, axis, position }
+    }
+
+    fn movement(update: DeviceUpdate) -> Option<units::Vector> {
+        match update {
+            DeviceUpdate::ChangeMovement { direction, .. } => Some(direction),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn partial_deflection_is_slower() {
+        let mut input = Input::new();
+        let direction = movement(input.on_controller_axis(axis(0, 0.6)))
+            .expect("stick outside dead zone didn't move");
+        assert!(direction.x > 0 && direction.x < 1);
+        assert!(direction.y == 0);
+    }
+
+    #[test]
+    fn dead_zone_falls_back_to_keys() {
+        let mut input = Input::new();
+        let button = app::Button::Keyboard(app::Key::D);
+        let state = app::ButtonState::Press;
+        input.interpret(app::ButtonArgs { state, button, scancode: None });
+
+        // jitter inside the dead zone leaves the keys in charge
+        match input.on_controller_axis(axis(1, 0.1)) {
+            DeviceUpdate::Nop => (),
+            _ => panic!("resting stick changed movement"),
+        }
+        assert!(input.movement.x > 0);
+    }
+}
+
+#[cfg(test)]
+mod test_cursor {
+    use piston_window as app;
+
+    use super::*;
+
+    #[test]
+    fn click_casts_at_cursor() {
+        let mut input = Input::new();
+        let mut camera = camera::Camera::new();
+        camera.set_screen_size(200, 200);
+        input.set_view(camera.view());
+        input.on_mouse_move([110.0, 80.0]);
+
+        let button = app::Button::Mouse(app::MouseButton::Left);
+        let state = app::ButtonState::Press;
+        let click = app::ButtonArgs { state, button, scancode: None };
+        match input.interpret(click) {
+            DeviceUpdate::Cast { target } => {
+                let offset = target - units::Position::default();
+                assert!(offset.x == 10 && offset.y == -20);
+            },
+            _ => panic!("click didn't cast"),
+        }
+    }
+
+    #[test]
+    fn building_mode_places_on_tiles() {
+        let mut input = Input::new();
+        let mut camera = camera::Camera::new();
+        camera.set_screen_size(200, 200);
+        input.set_view(camera.view());
+        input.on_mouse_move([110.0, 80.0]);
+
+        let press = |button| {
+            let state = app::ButtonState::Press;
+            app::ButtonArgs { state, button, scancode: None }
+        };
+        let build = app::Button::Keyboard(app::Key::B);
+        let click = app::Button::Mouse(app::MouseButton::Left);
+        input.interpret(press(build));
+        let (_, _, ghost) = input.ghost().expect("no ghost while building");
+        match input.interpret(press(click)) {
+            DeviceUpdate::PlaceBuilding { blueprint, position } => {
+                let first = building::Blueprint::all()[0];
+                assert_eq!(blueprint, blueprints::Name::from(first));
+                assert!(position == ghost);
+                let offset = position - units::Position::default();
+                assert!(offset.x == 16 && offset.y == -16);
+            },
+            _ => panic!("click didn't build"),
+        }
+
+        // cycling past the last blueprint goes back to casting
+        for _ in 0..building::Blueprint::all().len() {
+            input.interpret(press(build));
+        }
+        assert!(input.ghost().is_none());
+        match input.interpret(press(click)) {
+            DeviceUpdate::Cast { .. } => (),
+            _ => panic!("click didn't cast after building"),
+        }
+    }
+
+    #[test]
+    fn zoning_paints_what_is_dragged_over() {
+        let mut input = Input::new();
+        let mut camera = camera::Camera::new();
+        camera.set_screen_size(200, 200);
+        input.set_view(camera.view());
+        input.on_mouse_move([110.0, 80.0]);
+
+        let button = |button, state| {
+            app::ButtonArgs { state, button, scancode: None }
+        };
+        let zone = app::Button::Keyboard(app::Key::Z);
+        let click = app::Button::Mouse(app::MouseButton::Left);
+        input.interpret(button(zone, app::ButtonState::Press));
+        match input.interpret(button(click, app::ButtonState::Press)) {
+            DeviceUpdate::Nop => (),
+            _ => panic!("pressing down painted straight away"),
+        }
+        input.on_mouse_move([150.0, 120.0]);
+        let (_, from, to) = input.zone_ghost().expect("no ghost while zoning");
+        match input.interpret(button(click, app::ButtonState::Release)) {
+            DeviceUpdate::PaintZone { from: start, to: end, zone } => {
+                assert_eq!(zone, Some(zones::Zone::all()[0]));
+                assert!(start == from && end == to);
+                let offset = end - start;
+                assert!(offset.x == 40 && offset.y == 40);
+            },
+            _ => panic!("letting go didn't paint"),
+        }
+
+        // after the zones comes taking zoning off, then back to casting
+        for _ in 0..zones::Zone::all().len() {
+            input.interpret(button(zone, app::ButtonState::Press));
+        }
+        let (zone_ghost, _, _) = input.zone_ghost().unwrap();
+        assert_eq!(zone_ghost, None);
+        input.interpret(button(zone, app::ButtonState::Press));
+        assert!(input.zone_ghost().is_none());
+    }
+
+    #[test]
+    fn pipes_are_laid_straight() {
+        let mut input = Input::new();
+        let mut camera = camera::Camera::new();
+        camera.set_screen_size(200, 200);
+        input.set_view(camera.view());
+        input.on_mouse_move([110.0, 80.0]);
+
+        let button = |button, state| {
+            app::ButtonArgs { state, button, scancode: None }
+        };
+        let pipe = app::Button::Keyboard(app::Key::L);
+        let click = app::Button::Mouse(app::MouseButton::Left);
+        input.interpret(button(pipe, app::ButtonState::Press));
+        input.interpret(button(click, app::ButtonState::Press));
+        // further down than across, so it goes straight down
+        input.on_mouse_move([130.0, 180.0]);
+        let (from, to) = input.pipe_ghost().expect("no ghost while piping");
+        match input.interpret(button(click, app::ButtonState::Release)) {
+            DeviceUpdate::LayPipe { from: start, to: end } => {
+                assert!(start == from && end == to);
+                let offset = end - start;
+                assert!(offset.x == 0 && offset.y == 100);
+            },
+            _ => panic!("letting go didn't lay pipe"),
+        }
+
+        input.interpret(button(pipe, app::ButtonState::Press));
+        assert!(input.pipe_ghost().is_none());
+        match input.interpret(button(click, app::ButtonState::Press)) {
+            DeviceUpdate::Cast { .. } => (),
+            _ => panic!("click didn't cast after piping"),
+        }
+    }
 }
 
+#[cfg(test)]
+mod test_typing {
+    use piston_window as app;
+
+    use super::*;
+
+    fn press(key: app::Key) -> app::ButtonArgs {
+        let button = app::Button::Keyboard(key);
+        let state = app::ButtonState::Press;
+        app::ButtonArgs { state, button, scancode: None }
+    }
+
+    #[test]
+    fn typing_keeps_keys_from_moving() {
+        let mut input = Input::new();
+        input.interpret(press(app::Key::W));
+        match input.interpret(press(app::Key::Return)) {
+            DeviceUpdate::StartChat => (),
+            _ => panic!("return didn't start chatting"),
+        }
+        match input.let_go() {
+            DeviceUpdate::ChangeMovement { direction, .. } =>
+                assert_eq!(direction, units::Vector::default()),
+            _ => panic!("starting to chat didn't stop the player"),
+        }
+        match input.interpret(press(app::Key::D)) {
+            DeviceUpdate::Nop => (),
+            _ => panic!("typed letters moved the player"),
+        }
+
+        input.stop_typing();
+        match input.interpret(press(app::Key::D)) {
+            DeviceUpdate::ChangeMovement { direction, .. } =>
+                assert!(direction.x > 0),
+            _ => panic!("keys didn't come back after chatting"),
+        }
+    }
+}
@@ -1,25 +1,40 @@
+extern crate image;
+#[macro_use]
+extern crate log;
 extern crate piston_window;
+#[cfg(feature = "audio")]
+extern crate rodio;
 
 use piston_window::*;
 
 extern crate city_internal;
 
+use std::path;
+
+use city_internal::entities::player;
+use city_internal::logging;
+use city_internal::mods;
+
 mod client;
 
 
 // as seen in https://github.com/PistonDevelopers/piston-examples/issues/336
-fn build_window() -> PistonWindow {
+fn build_window(graphics: client::Graphics) -> PistonWindow {
     let title = "lil-city";
-    let resolution = [600, 600];
+    let resolution = graphics.resolution;
     let opengl = OpenGL::V3_2;
     let mut window_result = WindowSettings::new(title, resolution)
-        .exit_on_esc(true)
+        .exit_on_esc(false)
+        .fullscreen(graphics.fullscreen)
+        .vsync(graphics.vsync)
         .srgb(true)                      // try to init windowbuilder with srgb enabled
         .opengl(opengl)
         .build();
     if window_result.is_err() {   //if srgb=true fails, retry as srgb=false
         window_result = WindowSettings::new(title, resolution)
-            .exit_on_esc(true)
+            .exit_on_esc(false)
+            .fullscreen(graphics.fullscreen)
+            .vsync(graphics.vsync)
             .srgb(false)                 // !!!
             .opengl(opengl)
             .build();
@@ -34,13 +49,107 @@ fn build_window() -> PistonWindow {
 
 
 fn main() {
-    let mut client = client::start_game();
+    logging::init();
+    let mut args = ::std::env::args().skip(1);
+    let mut host = None;
+    let mut connect = None;
+    let mut admin = false;
+    let mut name = None;
+    let mut color = None;
+    let mut record_input = None;
+    let mut play_input = None;
+    let mut quit_after_playback = false;
+    let mut language = None;
+    while let Some(arg) = args.next() {
+        if arg == "--host" {
+            host = Some(args.next().unwrap_or("0.0.0.0:7878".to_string()));
+        } else if arg == "--connect" {
+            connect = args.next();
+        } else if arg == "--admin" {
+            admin = true;
+        } else if arg == "--name" {
+            name = args.next();
+        } else if arg == "--color" {
+            // as red,green,blue out of 255
+            let arg = args.next().unwrap_or_default();
+            let channels: Vec<u8> = arg.split(',')
+                                       .flat_map(|c| c.trim().parse())
+                                       .collect();
+            if channels.len() == 3 {
+                color = Some([channels[0], channels[1], channels[2]]);
+            } else {
+                println!("Unrecognized color {}", arg);
+            }
+        } else if arg == "--record-input" {
+            record_input = args.next().map(path::PathBuf::from);
+        } else if arg == "--play-input" {
+            play_input = args.next().map(path::PathBuf::from);
+        } else if arg == "--quit-after-playback" {
+            quit_after_playback = true;
+        } else if arg == "--lang" {
+            // such as de, for assets/locale/de.txt
+            language = args.next();
+        } else {
+            println!("Unrecognized argument {}", arg);
+        }
+    }
 
-    let mut window: PistonWindow = build_window();
+    // before anything is drawn, or the font for it is loaded
+    client::set_language(language.as_ref().map(String::as_str));
+
+    // the menu's join button goes to wherever --connect said, if anywhere
+    let join = connect.clone().unwrap_or("127.0.0.1:7878".to_string());
+    let mut appearance = match name {
+        Some(name) => player::Appearance::named(&name),
+        None => player::Appearance::default(),
+    };
+    if let Some(color) = color {
+        appearance.color = color;
+    }
+    let options = client::Options {
+        host,
+        admin,
+        join,
+        appearance,
+        record_input,
+        play_input,
+        quit_after_playback,
+    };
+    let mut screens = client::Screens::new(options);
+    if connect.is_some() {
+        screens.join();
+    }
+    run(screens);
+}
+
+fn run(mut client: client::Screens) {
+    use client::Game;
+
+    let mut window: PistonWindow = build_window(client.graphics());
+    let dir = path::Path::new("assets");
+    // the server says what is wrong with any packs that are left out
+    let packs: Vec<path::PathBuf> = mods::discover(&mods::mods_dir())
+        .packs
+        .into_iter()
+        .map(|pack| pack.dir)
+        .collect();
+    let mut assets = client::Assets::load(&mut window.factory, dir, &packs);
 
     while let Some(e) = window.next() {
+        if client.is_finished() {
+            break;
+        }
+        if let Some(graphics) = client.take_new_window() {
+            // the art's textures belong to the old window, so they go with
+            // it and are loaded again
+            window = build_window(graphics);
+            assets = client::Assets::load(&mut window.factory, dir, &packs);
+            continue;
+        }
         if let Some(ren) = e.render_args() {
-            window.draw_2d(&e, |c, g| client.on_draw(c, g, ren));
+            assets.refresh(&mut window.factory);
+            let assets = &assets;
+            window.draw_2d(&e, |c, g| client.on_draw(c, g, ren, assets));
         }
         if let Some(upd) = e.update_args() {
             client.on_update(upd);
@@ -51,5 +160,14 @@ fn main() {
         if let Some(mouse) = e.mouse_cursor_args() {
             client.on_mouse_move(mouse);
         }
+        if let Some(scroll) = e.mouse_scroll_args() {
+            client.on_mouse_scroll(scroll);
+        }
+        if let Some(axis) = e.controller_axis_args() {
+            client.on_controller_axis(axis);
+        }
+        if let Some(text) = e.text_args() {
+            client.on_text(&text);
+        }
     }
 }